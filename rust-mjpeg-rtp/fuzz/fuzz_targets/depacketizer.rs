@@ -0,0 +1,20 @@
+//! Hostile network input into the RTP/JPEG depacketizer
+//!
+//! The input is a sequence of datagrams, each prefixed with its length as a
+//! big-endian u16, so one run can exercise reassembly across packets.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rust_mjpeg_rtp::receiver::{Depacketizer, ReceiverConfig};
+
+fuzz_target!(|data: &[u8]| {
+    let mut depacketizer = Depacketizer::new(ReceiverConfig::default());
+
+    let mut rest = data;
+    while rest.len() >= 2 {
+        let len = (u16::from_be_bytes([rest[0], rest[1]]) as usize).min(rest.len() - 2);
+        let _ = depacketizer.push(&rest[2..2 + len]);
+        rest = &rest[2 + len..];
+    }
+});
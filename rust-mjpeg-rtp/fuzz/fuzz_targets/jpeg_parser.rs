@@ -0,0 +1,15 @@
+//! Camera output straight into the JPEG parsers and the packetizer
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rust_mjpeg_rtp::rtp::{parse_jpeg_for_rtp, JpegProbe, RtpPacketizer};
+
+fuzz_target!(|data: &[u8]| {
+    let _ = parse_jpeg_for_rtp(data);
+    let _ = JpegProbe::new(data);
+
+    // A small MTU so even short inputs are split into several packets
+    let packetizer = RtpPacketizer::new(0x1234_5678, 200);
+    let _ = packetizer.packetize_jpeg(data, 640, 480, 0);
+});
@@ -0,0 +1,27 @@
+//! CPU affinity for hot-path threads
+//!
+//! On 4-core Pis, pinning the capture, encoder and sender threads away from
+//! busy cores (e.g. isolating core 3 for the sender) removes jitter spikes
+//! caused by other work preempting them.
+
+use tracing::{debug, warn};
+
+/// Pins the calling thread to `core`
+///
+/// Failures are logged and otherwise ignored: affinity is an optimisation,
+/// never a reason to stop streaming.
+pub fn pin_current_thread(core: usize, role: &str) -> bool {
+    let cores = core_affinity::get_core_ids().unwrap_or_default();
+    let Some(core_id) = cores.into_iter().find(|c| c.id == core) else {
+        warn!(role, core, "CPU core not available, thread left unpinned");
+        return false;
+    };
+
+    if core_affinity::set_for_current(core_id) {
+        debug!(role, core, "Pinned thread to CPU core");
+        true
+    } else {
+        warn!(role, core, "Failed to set thread affinity");
+        false
+    }
+}
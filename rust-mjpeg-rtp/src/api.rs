@@ -0,0 +1,741 @@
+//! HTTP control API for the `stream` command
+//!
+//! Off unless `api_listen` is set under `[mjpeg-rtp]`. It lets a ground
+//! station subscribe to a camera's raw RTP feed on demand, without
+//! restarting the pipeline, and read the streamer's counters:
+//!
+//! - `GET /cameras/{name}/stats`: [`StreamerStats`](crate::StreamerStats), extra
+//!   destinations included
+//! - `GET /cameras/{name}/identity`: the stream's persistent UUID, SSRC and
+//!   RTCP CNAME, for correlating it across reboots
+//! - `GET /cameras/{name}/destinations`: extra destinations and their counters
+//! - `POST /cameras/{name}/destinations/{addr}`: also send to `addr` (`ip:port`)
+//! - `DELETE /cameras/{name}/destinations/{addr}`: stop sending to `addr`
+//! - `GET /cameras/{name}/events`: the most recent
+//!   [`AnalyticsEvent`](crate::events::AnalyticsEvent)s frame processors
+//!   reported about the camera
+//! - `GET /cameras/{name}/recordings`: the camera's closed event clips, as
+//!   [`ClipMetadata`]
+//! - `GET /recordings/{id}/thumb`: a clip's thumbnail JPEG, where `id` is the
+//!   clip's file name without `.frames`
+//! - `GET /recordings/{id}/frames/{frame}`: the clip's JPEG nearest frame id
+//!   `frame`, such as the `frame_id` of one of its events
+//! - `GET /timeline?from=&to=&camera=&offset=&limit=`: recordings, events and
+//!   alerts merged in time order, paged; see [`crate::timeline`]. `from` and
+//!   `to` are ms since the Unix epoch, every parameter is optional
+//! - `GET /status`: the state of each of the runtime's components (cameras,
+//!   relay, transcoder, ...) as [`ComponentStatus`](crate::supervisor::ComponentStatus)es
+//! - `GET /metrics`: every camera's counters as OpenMetrics text, labelled
+//!   by camera, codec, resolution and session; see [`crate::metrics`]
+//!
+//! Failures are answered with `{"error": ..., "code": ...}` and the status of
+//! the error's [`ErrorCode`](crate::ErrorCode). There is no authentication;
+//! bind it to a trusted interface.
+
+use crate::clips::{self, ClipMetadata};
+use crate::events::{AnalyticsEvent, EventBus};
+use crate::identity::CameraIdentity;
+use crate::metrics::{self, MetricLabels};
+use crate::streamer::{DestinationStats, Destinations, StreamerStatsHandle};
+use crate::supervisor::ComponentStates;
+use crate::task::CancellationToken;
+use crate::timeline::{AlertLog, Timeline, TimelineQuery, MAX_LIMIT};
+use crate::{Streamer, StreamerStats};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::{debug, info, warn};
+
+/// Largest request accepted; requests have no body
+const MAX_REQUEST_BYTES: usize = 8192;
+
+#[derive(Clone)]
+struct CameraHandle {
+    destinations: Destinations,
+    stats: StreamerStatsHandle,
+    labels: MetricLabels,
+    identity: StreamIdentity,
+}
+
+/// Streamers reachable through the API, by camera name
+#[derive(Clone, Default)]
+pub struct ApiRegistry {
+    cameras: Arc<Mutex<HashMap<String, CameraHandle>>>,
+    /// Where each camera's event clips are written
+    clip_dirs: Arc<Mutex<HashMap<String, PathBuf>>>,
+    events: EventBus,
+    alerts: AlertLog,
+    components: ComponentStates,
+}
+
+impl ApiRegistry {
+    /// Makes a running camera's streamer controllable as `name`, with its
+    /// persistent identity if it has one
+    pub fn register(&self, name: &str, streamer: &Streamer, identity: Option<&CameraIdentity>) {
+        let (width, height) = streamer.frame_size();
+        let handle = CameraHandle {
+            destinations: streamer.destinations(),
+            stats: streamer.stats_handle(),
+            labels: MetricLabels::new(name, width, height),
+            identity: StreamIdentity {
+                name: name.to_string(),
+                uuid: identity.map(|identity| identity.uuid),
+                ssrc: streamer.ssrc(),
+                cname: streamer.cname().map(str::to_string),
+            },
+        };
+        self.cameras
+            .lock()
+            .unwrap()
+            .insert(name.to_string(), handle);
+    }
+
+    pub fn unregister(&self, name: &str) {
+        self.cameras.lock().unwrap().remove(name);
+    }
+
+    /// Serves the event clips `name` writes to `dir`; they stay reachable
+    /// after the camera stops
+    pub fn register_clips(&self, name: &str, dir: &str) {
+        self.clip_dirs
+            .lock()
+            .unwrap()
+            .insert(name.to_string(), PathBuf::from(dir));
+    }
+
+    /// Where the cameras' processors publish analytics events
+    pub fn events(&self) -> &EventBus {
+        &self.events
+    }
+
+    /// Where the cameras' streamers' alerts are kept for the timeline
+    pub fn alerts(&self) -> &AlertLog {
+        &self.alerts
+    }
+
+    /// States of the runtime's components, filled in by its supervisor
+    pub fn components(&self) -> &ComponentStates {
+        &self.components
+    }
+
+    /// Current stats of every streaming camera, by name
+    pub fn stats(&self) -> BTreeMap<String, StreamerStats> {
+        self.cameras
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(name, camera)| (name.clone(), camera.stats.get()))
+            .collect()
+    }
+
+    /// Current stats of every streaming camera with their metric labels,
+    /// in name order
+    fn labelled_stats(&self) -> Vec<(MetricLabels, StreamerStats)> {
+        let cameras = self.cameras.lock().unwrap();
+        let mut stats: Vec<_> = cameras
+            .values()
+            .map(|camera| (camera.labels.clone(), camera.stats.get()))
+            .collect();
+        stats.sort_by(|a, b| a.0.camera.cmp(&b.0.camera));
+        stats
+    }
+
+    fn get(&self, name: &str) -> Option<CameraHandle> {
+        self.cameras.lock().unwrap().get(name).cloned()
+    }
+
+    /// The `.frames` file of clip `id`, in whichever camera's directory.
+    /// Once transcoded, only the clip's other files are left.
+    fn clip(&self, id: &str) -> Option<PathBuf> {
+        if id.is_empty() || id.starts_with('.') || id.contains('\\') {
+            return None;
+        }
+        let file = format!("{}.frames", id);
+        self.clip_dirs
+            .lock()
+            .unwrap()
+            .values()
+            .map(|dir| dir.join(&file))
+            .find(|path| path.is_file() || path.with_extension("json").is_file())
+    }
+}
+
+/// What identifies a camera's stream to recorders
+#[derive(Clone, Serialize)]
+struct StreamIdentity {
+    name: String,
+    /// Unset when the identity file couldn't be read or created
+    uuid: Option<uuid::Uuid>,
+    ssrc: u32,
+    cname: Option<String>,
+}
+
+#[derive(Serialize)]
+struct DestinationList {
+    destinations: Vec<DestinationStats>,
+}
+
+#[derive(Serialize)]
+struct EventList {
+    events: Vec<AnalyticsEvent>,
+}
+
+#[derive(Serialize)]
+struct RecordingList {
+    recordings: Vec<ClipMetadata>,
+}
+
+#[derive(Serialize)]
+struct ErrorBody<'a> {
+    error: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    code: Option<&'a str>,
+}
+
+/// Status code and body of a response
+struct Reply {
+    status: u16,
+    content_type: &'static str,
+    body: Vec<u8>,
+}
+
+impl Reply {
+    fn json(status: u16, body: &impl Serialize) -> Self {
+        Self {
+            status,
+            content_type: "application/json",
+            body: serde_json::to_vec(body).unwrap_or_else(|_| b"{}".to_vec()),
+        }
+    }
+
+    fn metrics(body: String) -> Self {
+        Self {
+            status: 200,
+            content_type: metrics::CONTENT_TYPE,
+            body: body.into_bytes(),
+        }
+    }
+
+    fn jpeg(body: Vec<u8>) -> Self {
+        Self {
+            status: 200,
+            content_type: "image/jpeg",
+            body,
+        }
+    }
+
+    fn error(status: u16, error: String, code: Option<&str>) -> Self {
+        Self::json(status, &ErrorBody { error, code })
+    }
+}
+
+/// Serves the API on `listener` until `token` is cancelled
+pub async fn serve(listener: TcpListener, registry: ApiRegistry, token: CancellationToken) {
+    if let Ok(addr) = listener.local_addr() {
+        info!(%addr, "Control API listening");
+    }
+    loop {
+        let (stream, peer) = tokio::select! {
+            _ = token.cancelled() => break,
+            accepted = listener.accept() => match accepted {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    warn!(error = %e, "Failed to accept API connection");
+                    continue;
+                }
+            },
+        };
+        let registry = registry.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle_connection(stream, &registry).await {
+                debug!(%peer, error = %e, "API connection failed");
+            }
+        });
+    }
+}
+
+async fn handle_connection(mut stream: TcpStream, registry: &ApiRegistry) -> std::io::Result<()> {
+    let mut buf = vec![0u8; MAX_REQUEST_BYTES];
+    let len = stream.read(&mut buf).await?;
+    let request = String::from_utf8_lossy(&buf[..len]);
+    let mut request_line = request
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .split_whitespace();
+    let method = request_line.next().unwrap_or_default().to_string();
+    let path = request_line.next().unwrap_or_default().to_string();
+
+    // Recordings are read from disk
+    let registry = registry.clone();
+    let reply = tokio::task::spawn_blocking(move || route(&method, &path, &registry))
+        .await
+        .map_err(std::io::Error::other)?;
+    let header = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\n\
+         Connection: close\r\n\r\n",
+        reply.status,
+        reason_phrase(reply.status),
+        reply.content_type,
+        reply.body.len()
+    );
+    stream.write_all(header.as_bytes()).await?;
+    stream.write_all(&reply.body).await?;
+    stream.shutdown().await
+}
+
+fn route(method: &str, path: &str, registry: &ApiRegistry) -> Reply {
+    let (path, query) = path.split_once('?').unwrap_or((path, ""));
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+    let (name, rest) = match segments.as_slice() {
+        ["cameras", name, rest @ ..] => (*name, rest),
+        ["recordings", id, rest @ ..] => return recording(method, id, rest, registry),
+        ["timeline"] if method == "GET" => return timeline(query, registry),
+        ["timeline"] => return Reply::error(405, format!("{} not allowed here", method), None),
+        ["status"] if method == "GET" => return Reply::json(200, &registry.components.snapshot()),
+        ["status"] => return Reply::error(405, format!("{} not allowed here", method), None),
+        ["metrics"] if method == "GET" => {
+            return Reply::metrics(metrics::render(&registry.labelled_stats()))
+        }
+        ["metrics"] => return Reply::error(405, format!("{} not allowed here", method), None),
+        _ => return Reply::error(404, "not found".to_string(), None),
+    };
+    let Some(camera) = registry.get(name) else {
+        return Reply::error(404, format!("unknown camera '{}'", name), None);
+    };
+
+    match (method, rest) {
+        ("GET", ["stats"]) => Reply::json(200, &camera.stats.get()),
+        ("GET", ["identity"]) => Reply::json(200, &camera.identity),
+        ("GET", ["destinations"]) => Reply::json(
+            200,
+            &DestinationList {
+                destinations: camera.destinations.stats(),
+            },
+        ),
+        ("GET", ["events"]) => Reply::json(
+            200,
+            &EventList {
+                events: registry.events.recent(name),
+            },
+        ),
+        ("GET", ["recordings"]) => {
+            let dir = registry.clip_dirs.lock().unwrap().get(name).cloned();
+            let recordings = match dir.map(|dir| clips::list(&dir)).transpose() {
+                Ok(recordings) => recordings.unwrap_or_default(),
+                Err(e) => {
+                    return Reply::error(
+                        e.code().http_status(),
+                        e.to_string(),
+                        Some(e.code().as_str()),
+                    )
+                }
+            };
+            Reply::json(
+                200,
+                &RecordingList {
+                    recordings: recordings
+                        .into_iter()
+                        .filter(|clip| clip.camera == name)
+                        .collect(),
+                },
+            )
+        }
+        ("POST" | "DELETE", ["destinations", addr]) => {
+            let addr = match percent_decode(addr).parse::<SocketAddr>() {
+                Ok(addr) => addr,
+                Err(e) => {
+                    return Reply::error(400, format!("{}: {}", addr, e), Some("invalid_input"))
+                }
+            };
+            if method == "POST" {
+                add_destination(name, &camera.destinations, addr)
+            } else if camera.destinations.remove(addr) {
+                info!(camera = %name, dest = %addr, "Removed RTP destination");
+                Reply::json(
+                    200,
+                    &DestinationList {
+                        destinations: camera.destinations.stats(),
+                    },
+                )
+            } else {
+                Reply::error(404, format!("{} is not a destination", addr), None)
+            }
+        }
+        (_, ["stats"] | ["destinations"] | ["events"] | ["recordings"] | ["destinations", _]) => {
+            Reply::error(405, format!("{} not allowed here", method), None)
+        }
+        _ => Reply::error(404, "not found".to_string(), None),
+    }
+}
+
+fn recording(method: &str, id: &str, rest: &[&str], registry: &ApiRegistry) -> Reply {
+    if method != "GET" {
+        return Reply::error(405, format!("{} not allowed here", method), None);
+    }
+    let Some(clip) = registry.clip(id) else {
+        return Reply::error(404, format!("unknown recording '{}'", id), None);
+    };
+    match rest {
+        ["thumb"] => match std::fs::read(clip.with_extension("jpg")) {
+            Ok(jpeg) => Reply::jpeg(jpeg),
+            // Written when the clip closes
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                Reply::error(404, format!("no thumbnail for '{}' yet", id), None)
+            }
+            Err(e) => Reply::error(500, e.to_string(), None),
+        },
+        ["frames", frame] => {
+            let Ok(frame) = frame.parse::<u64>() else {
+                let error = format!("bad frame id '{}'", frame);
+                return Reply::error(400, error, Some("invalid_input"));
+            };
+            if !clip.is_file() {
+                let error = format!("'{}' was transcoded; its frames are in the .mp4", id);
+                return Reply::error(404, error, None);
+            }
+            match clips::nearest_frame(&clip, frame) {
+                Ok(Some(frame)) => Reply::jpeg(frame.data.to_vec()),
+                Ok(None) => Reply::error(404, format!("'{}' has no frames", id), None),
+                Err(e) => Reply::error(
+                    e.code().http_status(),
+                    e.to_string(),
+                    Some(e.code().as_str()),
+                ),
+            }
+        }
+        _ => Reply::error(404, "not found".to_string(), None),
+    }
+}
+
+fn timeline(query: &str, registry: &ApiRegistry) -> Reply {
+    let query = match timeline_query(query) {
+        Ok(query) => query,
+        Err(reply) => return reply,
+    };
+
+    // Cameras may share a clip directory
+    let dirs: HashSet<PathBuf> = registry
+        .clip_dirs
+        .lock()
+        .unwrap()
+        .values()
+        .cloned()
+        .collect();
+    let mut recordings = Vec::new();
+    for dir in dirs {
+        match clips::list(&dir) {
+            Ok(clips) => recordings.extend(clips),
+            Err(e) => {
+                return Reply::error(
+                    e.code().http_status(),
+                    e.to_string(),
+                    Some(e.code().as_str()),
+                )
+            }
+        }
+    }
+    recordings.sort_by_key(|clip| clip.started_ms);
+    Reply::json(
+        200,
+        &Timeline::build(
+            recordings,
+            registry.events.recent_all(),
+            registry.alerts.recent(),
+            &query,
+        ),
+    )
+}
+
+fn timeline_query(query: &str) -> Result<TimelineQuery, Reply> {
+    let mut timeline = TimelineQuery::default();
+    for (key, value) in query.split('&').filter_map(|pair| pair.split_once('=')) {
+        let value = percent_decode(value);
+        let bad = |_| {
+            let error = format!("{}: bad value '{}'", key, value);
+            Reply::error(400, error, Some("invalid_input"))
+        };
+        match key {
+            "from" => timeline.from_ms = value.parse().map_err(bad)?,
+            "to" => timeline.to_ms = value.parse().map_err(bad)?,
+            "offset" => timeline.offset = value.parse().map_err(bad)?,
+            "limit" => timeline.limit = value.parse::<usize>().map_err(bad)?.clamp(1, MAX_LIMIT),
+            "camera" => timeline.camera = Some(value),
+            _ => {}
+        }
+    }
+    Ok(timeline)
+}
+
+fn add_destination(name: &str, destinations: &Destinations, addr: SocketAddr) -> Reply {
+    match destinations.add(addr) {
+        Ok(added) => {
+            if added {
+                info!(camera = %name, dest = %addr, "Added RTP destination");
+            }
+            let status = if added { 201 } else { 200 };
+            Reply::json(
+                status,
+                &DestinationList {
+                    destinations: destinations.stats(),
+                },
+            )
+        }
+        Err(e) => Reply::error(
+            e.code().http_status(),
+            e.to_string(),
+            Some(e.code().as_str()),
+        ),
+    }
+}
+
+/// Decodes `%XX` escapes, as clients send for IPv6 brackets and colons
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3).and_then(|hex| {
+            std::str::from_utf8(hex)
+                .ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        });
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                out.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                out.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+fn reason_phrase(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        201 => "Created",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        501 => "Not Implemented",
+        503 => "Service Unavailable",
+        _ => "Internal Server Error",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frame::Frame;
+    use crate::recording::FrameWriter;
+    use crate::StreamerConfig;
+    use bytes::Bytes;
+
+    impl Reply {
+        fn text(&self) -> &str {
+            std::str::from_utf8(&self.body).unwrap()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_routes() {
+        let streamer = Streamer::new(StreamerConfig::default()).await.unwrap();
+        let registry = ApiRegistry::default();
+        let dir = tempfile::tempdir().unwrap();
+        let identity = CameraIdentity::load_or_create(dir.path(), "camera1").unwrap();
+        registry.register("camera1", &streamer, Some(&identity));
+
+        let reply = route("GET", "/cameras/camera1/identity", &registry);
+        let body: serde_json::Value = serde_json::from_slice(&reply.body).unwrap();
+        assert_eq!(body["uuid"], identity.uuid.to_string());
+        assert_eq!(body["ssrc"], streamer.ssrc());
+
+        let reply = route(
+            "POST",
+            "/cameras/camera1/destinations/127.0.0.1:6000",
+            &registry,
+        );
+        assert_eq!(reply.status, 201);
+        assert!(reply.text().contains("127.0.0.1:6000"));
+        assert_eq!(
+            streamer.destinations().list(),
+            vec!["127.0.0.1:6000".parse().unwrap()]
+        );
+        let reply = route(
+            "POST",
+            "/cameras/camera1/destinations/127.0.0.1:6000",
+            &registry,
+        );
+        assert_eq!(reply.status, 200);
+
+        // Wrong family for the 127.0.0.1 primary
+        let reply = route(
+            "POST",
+            "/cameras/camera1/destinations/%5B%3A%3A1%5D:6000",
+            &registry,
+        );
+        assert_eq!(reply.status, 400);
+        assert!(reply.text().contains("invalid_input"));
+        assert_eq!(
+            route("POST", "/cameras/camera1/destinations/nope", &registry).status,
+            400
+        );
+
+        let reply = route("GET", "/cameras/camera1/stats", &registry);
+        let stats: StreamerStats = serde_json::from_slice(&reply.body).unwrap();
+        assert_eq!(stats.destinations.len(), 1);
+        assert_eq!(registry.stats()["camera1"].destinations.len(), 1);
+
+        let reply = route("GET", "/metrics", &registry);
+        assert_eq!(reply.content_type, metrics::CONTENT_TYPE);
+        assert!(reply.text().contains("session_id=\""));
+        assert!(!reply.text().contains("127.0.0.1"));
+        assert_eq!(route("POST", "/metrics", &registry).status, 405);
+
+        let reply = route(
+            "DELETE",
+            "/cameras/camera1/destinations/127.0.0.1:6000",
+            &registry,
+        );
+        assert_eq!(reply.status, 200);
+        assert!(streamer.destinations().list().is_empty());
+        let reply = route(
+            "DELETE",
+            "/cameras/camera1/destinations/127.0.0.1:6000",
+            &registry,
+        );
+        assert_eq!(reply.status, 404);
+
+        registry.events().publish(AnalyticsEvent::new(
+            "camera1",
+            "people",
+            3,
+            serde_json::json!({"count": 1}),
+        ));
+        let reply = route("GET", "/cameras/camera1/events", &registry);
+        assert_eq!(reply.status, 200);
+        assert!(reply.text().contains(r#""count":1"#), "{}", reply.text());
+
+        assert_eq!(
+            route("GET", "/cameras/camera2/stats", &registry).status,
+            404
+        );
+        assert_eq!(
+            route("PUT", "/cameras/camera1/stats", &registry).status,
+            405
+        );
+
+        // No supervisor has reported yet
+        let reply = route("GET", "/status", &registry);
+        assert_eq!((reply.status, reply.text()), (200, "[]"));
+        assert_eq!(route("POST", "/status", &registry).status, 405);
+    }
+
+    #[tokio::test]
+    async fn test_recordings() {
+        let dir = tempfile::tempdir().unwrap();
+        let clip = dir.path().join("camera1-1000-8.frames");
+        let mut writer = FrameWriter::create(&clip).unwrap();
+        for id in 8..12 {
+            writer
+                .write_frame(&Frame::new(id, Bytes::from(vec![id as u8; 4])))
+                .unwrap();
+        }
+        writer.finish().unwrap();
+        std::fs::write(clip.with_extension("jpg"), [0xFF, 0xD8]).unwrap();
+        let metadata = ClipMetadata {
+            camera: "camera1".to_string(),
+            clip: clip.to_string_lossy().into_owned(),
+            thumbnail: None,
+            started_ms: 1000,
+            ended_ms: 2000,
+            frames: 4,
+            trigger: AnalyticsEvent::new("camera1", "people", 9, serde_json::json!({})),
+            events: Vec::new(),
+        };
+        std::fs::write(
+            clip.with_extension("json"),
+            serde_json::to_vec(&metadata).unwrap(),
+        )
+        .unwrap();
+
+        let registry = ApiRegistry::default();
+        assert_eq!(
+            route("GET", "/recordings/camera1-1000-8/thumb", &registry).status,
+            404
+        );
+        registry.register_clips("camera1", dir.path().to_str().unwrap());
+
+        let reply = route("GET", "/recordings/camera1-1000-8/thumb", &registry);
+        assert_eq!((reply.status, reply.content_type), (200, "image/jpeg"));
+        assert_eq!(reply.body, vec![0xFF, 0xD8]);
+        let reply = route("GET", "/recordings/camera1-1000-8/frames/10", &registry);
+        assert_eq!(reply.body, vec![10; 4]);
+        let reply = route("GET", "/recordings/camera1-1000-8/frames/99", &registry);
+        assert_eq!(reply.body, vec![11; 4]);
+        let reply = route("GET", "/recordings/camera1-1000-8/frames/x", &registry);
+        assert_eq!(reply.status, 400);
+        assert_eq!(
+            route("GET", "/recordings/..%2Fcamera1-1000-8/thumb", &registry).status,
+            404
+        );
+        assert_eq!(
+            route("GET", "/recordings/camera1-2000-8/thumb", &registry).status,
+            404
+        );
+        assert_eq!(
+            route("DELETE", "/recordings/camera1-1000-8/thumb", &registry).status,
+            405
+        );
+        let transcoded = dir.path().join("camera1-1000-8.frames.bak");
+        std::fs::rename(&clip, &transcoded).unwrap();
+        assert_eq!(
+            route("GET", "/recordings/camera1-1000-8/thumb", &registry).status,
+            200
+        );
+        assert_eq!(
+            route("GET", "/recordings/camera1-1000-8/frames/10", &registry).status,
+            404
+        );
+        std::fs::rename(&transcoded, &clip).unwrap();
+
+        // Listed under the camera while it runs
+        assert_eq!(
+            route("GET", "/cameras/camera1/recordings", &registry).status,
+            404
+        );
+        let streamer = Streamer::new(StreamerConfig::default()).await.unwrap();
+        registry.register("camera1", &streamer, None);
+        let reply = route("GET", "/cameras/camera1/recordings", &registry);
+        assert_eq!(reply.status, 200);
+        let list: serde_json::Value = serde_json::from_slice(&reply.body).unwrap();
+        assert_eq!(list["recordings"][0]["frames"], 4);
+
+        registry.events().publish(AnalyticsEvent::new(
+            "camera2",
+            "people",
+            1,
+            serde_json::json!({}),
+        ));
+        let reply = route("GET", "/timeline?from=1500", &registry);
+        assert_eq!(reply.status, 200);
+        let timeline: Timeline = serde_json::from_slice(&reply.body).unwrap();
+        // The clip, its trigger and camera2's event
+        assert_eq!(timeline.total, 3);
+        let reply = route("GET", "/timeline?camera=camera2&limit=1", &registry);
+        let timeline: Timeline = serde_json::from_slice(&reply.body).unwrap();
+        assert_eq!((timeline.total, timeline.next_offset), (1, None));
+        assert_eq!(timeline.entries[0].camera(), "camera2");
+        assert_eq!(route("GET", "/timeline?from=soon", &registry).status, 400);
+        assert_eq!(route("POST", "/timeline", &registry).status, 405);
+    }
+}
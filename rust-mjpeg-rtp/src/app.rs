@@ -0,0 +1,926 @@
+//! The streaming runtime behind the `stream` command
+//!
+//! [`start`] brings up everything `[mjpeg-rtp]` enables: the cameras, the
+//! relay, transcoding, coordination and the control API. Logging and
+//! telemetry are left to the caller, so the same runtime runs under the
+//! `mjpeg-rtp` binary or inside another application sharing the process.
+
+use crate::api::{self, ApiRegistry};
+use crate::capture::{detect_pi_model, ModelPreset};
+use crate::clips::ClipRecorder;
+use crate::config::{CameraConfig, Config, MjpegRtpConfig, ProcessorConfig, ReplayPacing};
+use crate::congestion::{AdaptiveQuality, RtcpFeedback};
+use crate::continuity::RtpState;
+use crate::coordination::{Coordinator, Role};
+use crate::identity::CameraIdentity;
+use crate::log_limited;
+use crate::mdns::{Advertiser, MdnsStream};
+#[cfg(feature = "otel")]
+use crate::metrics::MetricLabels;
+use crate::overlay::Overlay;
+use crate::processor::{ProcessorChain, ProcessorContext};
+use crate::pull::PullListener;
+use crate::ratelimit::LogLimiter;
+use crate::relay::Relay;
+use crate::rtp::{sdp_dimensions_attribute, sdp_extmap_attribute, MAX_DIMENSION};
+use crate::stats_report::StatsReporter;
+use crate::supervisor::{Supervisor, TaskComponent};
+use crate::tap::{DeviceTaps, TapSource};
+use crate::task::CancellationToken;
+#[cfg(feature = "otel")]
+use crate::telemetry;
+use crate::transcode::Transcoder;
+use crate::{
+    Capture, CaptureConfig, CaptureError, CaptureStats, Frame, Recorder, Replay, SharedLimit,
+    SharedSocket, Streamer, StreamerConfig, StreamerStats,
+};
+use anyhow::{Context, Result};
+use std::net::SocketAddr;
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::watch;
+use tracing::{error, info, warn};
+
+/// Backoff between attempts to open a busy camera
+const BUSY_RETRY_MIN: Duration = Duration::from_secs(1);
+const BUSY_RETRY_MAX: Duration = Duration::from_secs(30);
+
+/// Starts every enabled camera and service under a new supervisor; an
+/// empty one when nothing is enabled. The control API comes up first, then
+/// the election and relay, the cameras, and the transcoder that works on
+/// their recordings. Cameras register with `api_registry` while they stream,
+/// and take frames from a tap in `taps` when their device has one. Shutting
+/// the supervisor down stops them.
+pub async fn start(
+    config: &Config,
+    api_registry: &ApiRegistry,
+    taps: &DeviceTaps,
+) -> Result<Supervisor> {
+    let mut supervisor = Supervisor::with_states("mjpeg-rtp", api_registry.components().clone());
+    if !config.mjpeg_rtp.enabled {
+        info!("MJPEG-RTP mode is disabled in configuration");
+        return Ok(supervisor);
+    }
+
+    info!(
+        camera1_enabled = %config.mjpeg_rtp.camera1.enabled,
+        camera2_enabled = %config.mjpeg_rtp.camera2.enabled,
+        "Configuration loaded"
+    );
+
+    let preset = config.mjpeg_rtp.platform.resolve(detect_pi_model());
+    info!(
+        model = ?preset.model,
+        encoder = ?preset.encoder,
+        max_width = ?preset.max_width,
+        max_height = ?preset.max_height,
+        encoder_threads = preset.encoder_threads,
+        "Platform preset selected"
+    );
+
+    let cameras: Vec<(&'static str, &CameraConfig)> = [
+        ("camera1", &config.mjpeg_rtp.camera1),
+        ("camera2", &config.mjpeg_rtp.camera2),
+    ]
+    .into_iter()
+    .filter(|(_, camera)| camera.enabled)
+    .collect();
+    // A relay needs no camera, so a gateway can run it alone
+    let relay_config = &config.mjpeg_rtp.relay;
+    if cameras.is_empty() && !relay_config.enabled {
+        info!("No cameras or relay enabled");
+        return Ok(supervisor);
+    }
+
+    if let Some(addr) = config.mjpeg_rtp.api_listen {
+        let api_registry = api_registry.clone();
+        supervisor.add(TaskComponent::new("control api", move |token| {
+            let api_registry = api_registry.clone();
+            async move {
+                let listener = tokio::net::TcpListener::bind(addr)
+                    .await
+                    .with_context(|| format!("cannot listen on {} for the control API", addr))?;
+                api::serve(listener, api_registry, token).await;
+                Ok(())
+            }
+        }));
+    }
+
+    // Only the elected leader streams. The cameras follow this channel, so
+    // they keep their role while the election restarts.
+    let coordination = &config.mjpeg_rtp.coordination;
+    let (role_tx, _) = watch::channel(Role::Standby);
+    let mut camera_dependencies = Vec::new();
+    if coordination.enabled {
+        let node_id = coordination
+            .node_id
+            .unwrap_or_else(|| uuid::Uuid::new_v4().as_u128() as u64);
+        info!(
+            node_id = %format!("{:#018x}", node_id),
+            priority = coordination.priority,
+            "Coordination enabled, starting as standby"
+        );
+        let coordination = coordination.clone();
+        let role_tx = role_tx.clone();
+        supervisor.add(TaskComponent::new("coordination", move |token| {
+            let (coordination, role_tx) = (coordination.clone(), role_tx.clone());
+            async move {
+                let coordinator = Coordinator::bind(&coordination, node_id)
+                    .await
+                    .with_context(|| {
+                        format!("cannot listen on {} for heartbeats", coordination.listen)
+                    })?;
+                let mut role = coordinator.subscribe();
+                let forward = async {
+                    while role.changed().await.is_ok() {
+                        role_tx.send_replace(*role.borrow_and_update());
+                    }
+                };
+                tokio::join!(coordinator.run(token), forward);
+                Ok(())
+            }
+        }));
+        camera_dependencies.push("coordination");
+    }
+
+    if relay_config.enabled {
+        let relay_config = relay_config.clone();
+        let interval = Duration::from_secs(config.mjpeg_rtp.stats_interval_seconds.max(1));
+        supervisor.add(TaskComponent::new("relay", move |token| {
+            let relay_config = relay_config.clone();
+            async move {
+                let relay = Relay::bind(&relay_config).await.with_context(|| {
+                    format!("cannot listen on {} for the relay", relay_config.listen)
+                })?;
+                relay.run(token, interval).await;
+                Ok(())
+            }
+        }));
+    }
+
+    let bundle = config.mjpeg_rtp.bundle.then(SharedSocket::new);
+    if bundle.is_some() {
+        info!("Bundling both cameras onto one socket");
+    }
+    let shaping = &config.mjpeg_rtp.shaping;
+    let shared_limit = shaping
+        .total_kbps
+        .map(|kbps| SharedLimit::new(kbps, Duration::from_millis(shaping.burst_ms)));
+    for &(name, camera_config) in &cameras {
+        info!("Starting {}...", name);
+        // The camera follows this channel, so it keeps its receivers while
+        // the listener restarts
+        let pull_rx = camera_config.pull.enabled.then(|| {
+            let (receivers_tx, receivers_rx) = watch::channel(Vec::new());
+            let pull_config = camera_config.pull.clone();
+            supervisor.add(TaskComponent::new(format!("{} pull", name), move |token| {
+                let (pull_config, receivers_tx) = (pull_config.clone(), receivers_tx.clone());
+                async move {
+                    let listener = PullListener::bind(&pull_config, receivers_tx)
+                        .await
+                        .with_context(|| {
+                            format!("cannot listen on {} for pull requests", pull_config.listen)
+                        })?;
+                    listener.run(token).await;
+                    Ok(())
+                }
+            }));
+            receivers_rx
+        });
+        let camera_config = camera_config.clone();
+        let rtp_config = config.mjpeg_rtp.clone();
+        let registry = api_registry.clone();
+        let taps = taps.clone();
+        let role_tx = coordination.enabled.then(|| role_tx.clone());
+        let bundle = bundle.clone();
+        let shared_limit = shared_limit.clone();
+        supervisor.add(
+            TaskComponent::new(name, move |token| {
+                let camera = run_camera(
+                    name,
+                    camera_config.clone(),
+                    rtp_config.clone(),
+                    preset,
+                    registry.clone(),
+                    taps.clone(),
+                    role_tx.as_ref().map(watch::Sender::subscribe),
+                    pull_rx.clone(),
+                    bundle.clone(),
+                    shared_limit.clone(),
+                    token,
+                );
+                async move { Ok(camera.await?) }
+            })
+            .after(&camera_dependencies),
+        );
+    }
+
+    let mdns_config = &config.mjpeg_rtp.mdns;
+    if mdns_config.enabled && !cameras.is_empty() {
+        // Receivers find a pulled camera by its pull port, a pushed one by
+        // the port its packets come from
+        let streams: Vec<MdnsStream> = cameras
+            .iter()
+            .map(|&(name, camera_config)| {
+                let identity = load_identity(name, &config.mjpeg_rtp);
+                MdnsStream {
+                    camera: name.to_string(),
+                    port: if camera_config.pull.enabled {
+                        camera_config.pull.listen.port()
+                    } else {
+                        camera_config.local_port
+                    },
+                    uuid: identity.as_ref().map(|identity| identity.uuid),
+                    cname: identity.as_ref().map(CameraIdentity::cname),
+                }
+            })
+            .collect();
+        let mdns_config = mdns_config.clone();
+        supervisor.add(TaskComponent::new("mdns", move |token| {
+            let (mdns_config, streams) = (mdns_config.clone(), streams.clone());
+            async move {
+                let advertiser = Advertiser::bind(&mdns_config, &streams)
+                    .await
+                    .context("cannot join mDNS on port 5353")?;
+                advertiser.run(token).await;
+                Ok(())
+            }
+        }));
+    }
+
+    let transcode = &config.mjpeg_rtp.transcode;
+    if transcode.enabled {
+        let dirs = config.mjpeg_rtp.transcode_dirs();
+        info!(
+            dirs = ?dirs,
+            after_hours = transcode.after_hours,
+            encoder = ?transcode.encoder,
+            "Transcoding old recordings to H.265"
+        );
+        let transcode = transcode.clone();
+        let recorders: Vec<&str> = cameras.iter().map(|&(name, _)| name).collect();
+        supervisor.add(
+            TaskComponent::new("transcode", move |token| {
+                let transcoder = Transcoder::new(&transcode, dirs.clone());
+                async move {
+                    transcoder.run(token).await;
+                    Ok(())
+                }
+            })
+            .after(&recorders),
+        );
+    }
+
+    supervisor.start().await?;
+    Ok(supervisor)
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn run_camera(
+    name: &str,
+    camera_config: CameraConfig,
+    rtp_config: MjpegRtpConfig,
+    preset: ModelPreset,
+    api_registry: ApiRegistry,
+    taps: DeviceTaps,
+    mut role_rx: Option<watch::Receiver<Role>>,
+    mut pull_rx: Option<watch::Receiver<Vec<SocketAddr>>>,
+    bundle: Option<SharedSocket>,
+    shared_limit: Option<SharedLimit>,
+    token: CancellationToken,
+) -> Result<()> {
+    let draws_overlay = camera_config
+        .processors
+        .iter()
+        .any(|p| matches!(p, ProcessorConfig::Detect(detector) if detector.overlay));
+    let overlay = draws_overlay.then(Overlay::new);
+    let capture_config = CaptureConfig {
+        overlay: overlay.clone(),
+        ..capture_config(name, &camera_config, preset)
+    };
+    let (width, height) = (capture_config.width, capture_config.height);
+
+    let identity = load_identity(name, &rtp_config);
+    let saved_state = rtp_config
+        .resume_rtp_state
+        .then(|| load_rtp_state(name, &rtp_config))
+        .flatten();
+    let ssrc = camera_config
+        .ssrc
+        .or_else(|| identity.as_ref().map(CameraIdentity::ssrc))
+        .or_else(|| saved_state.map(|state| state.ssrc))
+        .unwrap_or_else(rand_ssrc);
+    info!(
+        camera = name,
+        uuid = %identity.as_ref().map(|id| id.uuid.to_string()).unwrap_or_default(),
+        ssrc = %format!("{:#010x}", ssrc),
+        "Stream identity"
+    );
+
+    let mut capture = match camera_config.replay {
+        Some(ref path) => FrameSource::Replay(
+            Replay::new(path, camera_config.replay_loop)
+                .with_context(|| format!("{}: cannot replay {}", name, path))?
+                .with_fps(
+                    (camera_config.replay_pacing == ReplayPacing::Fps).then_some(camera_config.fps),
+                ),
+        ),
+        None => match taps.get(&camera_config.device) {
+            Some(tap) => {
+                info!(camera = name, device = %camera_config.device, "Device is already open in this process, taking frames from it");
+                FrameSource::Tap(TapSource::new(tap, &capture_config))
+            }
+            None => FrameSource::Camera(Capture::new(capture_config)?),
+        },
+    };
+    let Some(mut frame_rx) = start_source(name, &mut capture, &token).await? else {
+        return Ok(());
+    };
+    let recorder = match camera_config.record {
+        Some(ref path) => Some(
+            Recorder::start(path)
+                .with_context(|| format!("{}: cannot record to {}", name, path))?,
+        ),
+        None => None,
+    };
+    let mut clips = match camera_config.event_recording.dir {
+        Some(ref dir) => {
+            let clips = ClipRecorder::new(
+                &camera_config.event_recording,
+                dir,
+                name,
+                api_registry.events(),
+            )
+            .with_context(|| format!("{}: cannot record clips to {}", name, dir))?;
+            api_registry.register_clips(name, dir);
+            Some(clips)
+        }
+        None => None,
+    };
+
+    // Create streamer
+    let mut streamer_config = streamer_config(
+        &camera_config,
+        &rtp_config,
+        (width, height),
+        ssrc,
+        identity.as_ref(),
+    );
+    streamer_config.bundle = bundle;
+    streamer_config.shared_limit = shared_limit;
+    if !camera_config.rtcp_mux && !camera_config.dest_port.is_multiple_of(2) {
+        warn!(
+            camera = name,
+            dest_port = camera_config.dest_port,
+            "RTP destination port is odd; receivers expect RTP on even ports with RTCP one above"
+        );
+    }
+
+    if width > MAX_DIMENSION || height > MAX_DIMENSION {
+        info!(
+            camera = name,
+            sdp = %sdp_dimensions_attribute(width, height),
+            "Frame size exceeds RFC 2435 header limits; receivers need this SDP attribute"
+        );
+    }
+    if let Some(id) = rtp_config.frame_info_extension {
+        info!(
+            camera = name,
+            sdp = %sdp_extmap_attribute(id),
+            "Stamping packets with the frame-info header extension"
+        );
+    }
+
+    let mut streamer = Streamer::new(streamer_config.clone()).await?;
+    // A changed SSRC is a new stream anyway
+    if let Some(state) = saved_state.filter(|state| state.ssrc == ssrc) {
+        let max_gap = Duration::from_secs(rtp_config.resume_max_gap_secs);
+        match state.resume_at(SystemTime::now(), max_gap) {
+            Some((sequence, timestamp)) => {
+                info!(
+                    camera = name,
+                    sequence, timestamp, "Continuing the RTP stream of the last run"
+                );
+                streamer.resume(sequence, timestamp);
+            }
+            None => info!(
+                camera = name,
+                ?max_gap,
+                "Last run stopped too long ago, starting a new RTP sequence"
+            ),
+        }
+    }
+    let mut events = streamer.subscribe();
+    let mut leader = role_rx
+        .as_mut()
+        .is_none_or(|rx| *rx.borrow_and_update() == Role::Leader);
+    // Extra destinations added for pulling receivers
+    let mut pulled = Vec::new();
+    let mut sending = match pull_rx {
+        Some(ref mut rx) => {
+            let receivers = rx.borrow_and_update().clone();
+            follow_pulls(
+                name,
+                &mut streamer,
+                &streamer_config,
+                &receivers,
+                &mut pulled,
+                leader,
+            )
+            .await?
+        }
+        None => {
+            if leader {
+                streamer.start().await?;
+            }
+            leader
+        }
+    };
+    if !leader {
+        info!(camera = name, "Standing by until elected leader");
+    } else if !sending {
+        info!(camera = name, "Waiting for a receiver to pull the stream");
+    }
+
+    #[cfg(feature = "otel")]
+    telemetry::register_streamer_metrics(
+        &MetricLabels::new(name, width, height),
+        streamer.stats_handle(),
+    );
+
+    api_registry.register(name, &streamer, identity.as_ref());
+    info!(camera = name, "Camera streaming started");
+
+    let mut adaptive_quality =
+        AdaptiveQuality::from_config(&rtp_config.congestion, camera_config.quality);
+    if let Some(ref aq) = adaptive_quality {
+        info!(
+            camera = name,
+            controller = aq.controller().name(),
+            target_kbps = aq.controller().target_bitrate() / 1000,
+            "Adaptive JPEG quality enabled"
+        );
+    }
+    let mut feedback_rx = adaptive_quality.as_ref().map(|_| streamer.feedback());
+    let mut last_rate_sample = (Instant::now(), 0u64);
+
+    let context = ProcessorContext {
+        camera: name.to_string(),
+        events: api_registry.events().clone(),
+        overlay,
+    };
+    let mut processors = ProcessorChain::from_config(&camera_config.processors, &context)?;
+    if !processors.is_empty() {
+        info!(camera = name, processors = ?processors.names(), "Frame processors enabled");
+    }
+
+    // Forward frames from capture to streamer
+    let mut frame_count = 0u64;
+    let log = LogLimiter::default();
+    let mut reporter = StatsReporter::new(name);
+    let mut stats_ticker = tokio::time::interval(Duration::from_secs(
+        rtp_config.stats_interval_seconds.max(1),
+    ));
+    stats_ticker.tick().await;
+    loop {
+        let frame = tokio::select! {
+            biased;
+            _ = token.cancelled() => break,
+            _ = stats_ticker.tick() => {
+                reporter.report(capture.get_stats(), streamer.get_stats());
+                continue;
+            }
+            Ok(event) = events.recv() => {
+                warn!(camera = name, ?event, "Stream destination changed");
+                api_registry.alerts().push(name, event);
+                continue;
+            }
+            role = role_changed(&mut role_rx) => {
+                leader = role == Role::Leader;
+                if leader {
+                    info!(camera = name, "Elected leader, streaming");
+                } else {
+                    info!(camera = name, "No longer leader, standing by");
+                }
+                sending = match pull_rx {
+                    Some(ref rx) => {
+                        let receivers = rx.borrow().clone();
+                        follow_pulls(
+                            name,
+                            &mut streamer,
+                            &streamer_config,
+                            &receivers,
+                            &mut pulled,
+                            leader,
+                        )
+                        .await?
+                    }
+                    None => {
+                        if leader {
+                            streamer.start().await?;
+                        } else {
+                            streamer.stop().await?;
+                        }
+                        leader
+                    }
+                };
+                continue;
+            }
+            feedback = feedback_changed(&mut feedback_rx) => {
+                if let Some(ref mut aq) = adaptive_quality {
+                    aq.on_rtcp_feedback(&feedback, Instant::now());
+                }
+                continue;
+            }
+            receivers = pulls_changed(&mut pull_rx) => {
+                sending = follow_pulls(
+                    name,
+                    &mut streamer,
+                    &streamer_config,
+                    &receivers,
+                    &mut pulled,
+                    leader,
+                )
+                .await?;
+                continue;
+            }
+            frame = frame_rx.recv() => match frame {
+                Some(frame) => frame,
+                None => break,
+            },
+        };
+        if let Some(ref recorder) = recorder {
+            recorder.record(&frame);
+        }
+        if let Some(ref mut clips) = clips {
+            clips.record(&frame);
+        }
+        if !sending {
+            continue;
+        }
+        let frames = processors.process(frame);
+        if frames.is_empty() {
+            streamer.count_paced_drop();
+        }
+        for frame in frames {
+            if let Err(e) = streamer.send_frame(frame).await {
+                log_limited!(
+                    log,
+                    "send_frame",
+                    error,
+                    camera = name,
+                    error = %e,
+                    code = %e.code(),
+                    "Failed to send frame"
+                );
+                if e.code().is_retryable() {
+                    continue;
+                }
+                // The sender task is gone; stop capturing instead of logging every frame
+                api_registry.unregister(name);
+                capture.stop().await?;
+                return Err(e.into());
+            }
+        }
+
+        frame_count += 1;
+
+        // Steer JPEG quality towards the congestion controller's target
+        if frame_count.is_multiple_of(100) {
+            if let Some(ref mut aq) = adaptive_quality {
+                let streamer_stats = streamer.get_stats();
+                let (last_at, last_bytes) = last_rate_sample;
+                let elapsed = last_at.elapsed().as_secs_f64();
+                let bitrate = if elapsed > 0.0 {
+                    (streamer_stats.bytes_sent.saturating_sub(last_bytes) as f64 * 8.0 / elapsed)
+                        as u64
+                } else {
+                    0
+                };
+                last_rate_sample = (Instant::now(), streamer_stats.bytes_sent);
+
+                if let Some(quality) = aq.update(bitrate) {
+                    info!(
+                        camera = name,
+                        quality,
+                        bitrate_kbps = bitrate / 1000,
+                        target_kbps = aq.controller().target_bitrate() / 1000,
+                        "Adjusting JPEG quality"
+                    );
+                    if let Err(e) = capture.set_quality(quality) {
+                        error!(camera = name, error = %e, "Failed to set JPEG quality");
+                    }
+                }
+            }
+        }
+    }
+
+    if rtp_config.resume_rtp_state {
+        // Without BYE receivers keep the source around for the next run
+        streamer.stop_without_bye().await?;
+        save_rtp_state(name, &rtp_config, ssrc, &streamer.get_stats());
+    }
+    api_registry.unregister(name);
+    capture.stop().await?;
+    if let Some(recorder) = recorder {
+        recorder.finish()?;
+    }
+    if let Some(clips) = clips {
+        clips.finish()?;
+    }
+    info!(camera = name, "Camera stopped");
+
+    // A replay may run out; a camera ending on its own needs restarting
+    if !token.is_cancelled() && !matches!(capture, FrameSource::Replay(_)) {
+        anyhow::bail!("{} stopped delivering frames", name);
+    }
+    Ok(())
+}
+
+/// Waits for the next coordination role; never resolves without coordination
+/// or once the election has stopped
+async fn role_changed(role_rx: &mut Option<watch::Receiver<Role>>) -> Role {
+    match role_rx {
+        Some(rx) => match rx.changed().await {
+            Ok(()) => *rx.borrow_and_update(),
+            Err(_) => std::future::pending().await,
+        },
+        None => std::future::pending().await,
+    }
+}
+
+/// Waits for the next receiver report; never resolves without adaptive
+/// quality
+async fn feedback_changed(
+    feedback_rx: &mut Option<watch::Receiver<Option<RtcpFeedback>>>,
+) -> RtcpFeedback {
+    let Some(rx) = feedback_rx else {
+        return std::future::pending().await;
+    };
+    loop {
+        if rx.changed().await.is_err() {
+            return std::future::pending().await;
+        }
+        if let Some(feedback) = *rx.borrow_and_update() {
+            return feedback;
+        }
+    }
+}
+
+/// Waits for the next set of pulling receivers; never resolves without pull
+/// mode or once the listener is gone for good
+async fn pulls_changed(pull_rx: &mut Option<watch::Receiver<Vec<SocketAddr>>>) -> Vec<SocketAddr> {
+    match pull_rx {
+        Some(rx) => match rx.changed().await {
+            Ok(()) => rx.borrow_and_update().clone(),
+            Err(_) => std::future::pending().await,
+        },
+        None => std::future::pending().await,
+    }
+}
+
+/// Points the streamer at the receivers pulling the stream: the first as its
+/// destination, the others as extra destinations (tracked in `pulled`). It
+/// is stopped while none are pulling or this instance isn't the leader.
+/// Returns whether frames should be sent.
+async fn follow_pulls(
+    name: &str,
+    streamer: &mut Streamer,
+    config: &StreamerConfig,
+    receivers: &[SocketAddr],
+    pulled: &mut Vec<SocketAddr>,
+    leader: bool,
+) -> Result<bool> {
+    let receivers = if leader { receivers } else { &[] };
+    let others = receivers.get(1..).unwrap_or_default();
+    for &addr in pulled.iter().filter(|addr| !others.contains(addr)) {
+        streamer.remove_destination(addr);
+    }
+    pulled.retain(|addr| others.contains(addr));
+
+    let Some(&primary) = receivers.first() else {
+        if streamer.is_running() {
+            info!(camera = name, "No receiver pulling the stream, pausing it");
+            streamer.stop().await?;
+        }
+        return Ok(false);
+    };
+    if !streamer.is_running() || streamer.get_destination() != Some(primary) {
+        info!(camera = name, dest = %primary, "Streaming to the receiver that pulled it");
+        let config = StreamerConfig {
+            dest_host: primary.ip().to_string(),
+            dest_port: primary.port(),
+            ..config.clone()
+        };
+        // A receiver must not take the camera down
+        if let Err(e) = streamer.restart(config).await {
+            warn!(
+                camera = name,
+                dest = %primary,
+                error = %e,
+                "Cannot stream to the pulling receiver"
+            );
+            return Ok(false);
+        }
+    }
+    for &addr in others {
+        if pulled.contains(&addr) {
+            continue;
+        }
+        match streamer.add_destination(addr) {
+            Ok(_) => pulled.push(addr),
+            Err(e) => warn!(
+                camera = name,
+                dest = %addr,
+                error = %e,
+                "Cannot stream to the pulling receiver"
+            ),
+        }
+    }
+    Ok(true)
+}
+
+/// The camera's persisted UUID, created on first use
+pub fn load_identity(name: &str, rtp_config: &MjpegRtpConfig) -> Option<CameraIdentity> {
+    match CameraIdentity::load_or_create(&rtp_config.state_dir, name) {
+        Ok(identity) => Some(identity),
+        Err(e) => {
+            tracing::warn!(camera = name, error = %e, "No persistent stream identity");
+            None
+        }
+    }
+}
+
+/// A camera's RTP state from its last run, if it saved one that can be read
+fn load_rtp_state(name: &str, rtp_config: &MjpegRtpConfig) -> Option<RtpState> {
+    match RtpState::load(&rtp_config.state_dir, name) {
+        Ok(state) => state,
+        Err(e) => {
+            warn!(camera = name, error = %e, "Cannot continue the RTP stream of the last run");
+            None
+        }
+    }
+}
+
+/// Saves where the camera's stream stopped, unless it never sent anything
+fn save_rtp_state(name: &str, rtp_config: &MjpegRtpConfig, ssrc: u32, stats: &StreamerStats) {
+    if stats.rtp_packets_sent == 0 {
+        return;
+    }
+    let state = RtpState::new(ssrc, stats.current_seq_num as u16, stats.current_timestamp);
+    if let Err(e) = state.save(&rtp_config.state_dir, name) {
+        warn!(camera = name, error = %e, "Failed to save the RTP state");
+    }
+}
+
+/// Streamer settings for a camera section
+pub fn streamer_config(
+    camera_config: &CameraConfig,
+    rtp_config: &MjpegRtpConfig,
+    (width, height): (u32, u32),
+    ssrc: u32,
+    identity: Option<&CameraIdentity>,
+) -> StreamerConfig {
+    StreamerConfig {
+        dest_host: camera_config.dest_host.clone(),
+        dest_port: camera_config.dest_port,
+        dns_refresh: Duration::from_secs(camera_config.dns_refresh_secs),
+        local_port: camera_config.local_port,
+        rtcp_mux: camera_config.rtcp_mux,
+        width,
+        height,
+        fps: camera_config.fps,
+        ssrc,
+        sender_core: camera_config.affinity.sender_core,
+        sender_rt_priority: camera_config.affinity.sender_rt_priority,
+        oversize_dimensions: rtp_config.oversize_dimensions,
+        parallel_packetize_bytes: rtp_config.parallel_packetize_bytes,
+        frame_info_id: rtp_config.frame_info_extension,
+        qtable_policy: rtp_config.qtable_policy,
+        qtable_refresh_frames: rtp_config.qtable_refresh_frames,
+        timestamp_source: rtp_config.timestamp_source,
+        drift_correction: rtp_config.drift_correction,
+        cname: identity.map(CameraIdentity::cname),
+        ..Default::default()
+    }
+    .with_network(&rtp_config.network_for(camera_config))
+    .with_failover(&camera_config.failover)
+    .with_keepalive(&camera_config.keepalive)
+    .with_shaping(&rtp_config.shaping, rtp_config.max_kbps_for(camera_config))
+}
+
+/// Where a streaming camera's frames come from
+enum FrameSource {
+    /// A local camera, or an IP camera when `device` is a URL
+    Camera(Capture),
+    /// A `.frames` recording played back in place of the camera
+    Replay(Replay),
+    /// A camera the embedding application already captures
+    Tap(TapSource),
+}
+
+impl FrameSource {
+    async fn start(&mut self) -> Result<tokio::sync::mpsc::Receiver<Frame>> {
+        Ok(match self {
+            FrameSource::Camera(capture) => capture.start().await?,
+            FrameSource::Replay(replay) => replay.start().await?,
+            FrameSource::Tap(tap) => tap.start().await?,
+        })
+    }
+
+    async fn stop(&mut self) -> Result<()> {
+        match self {
+            FrameSource::Camera(capture) => capture.stop().await?,
+            FrameSource::Replay(replay) => replay.stop().await?,
+            FrameSource::Tap(tap) => tap.stop().await?,
+        }
+        Ok(())
+    }
+
+    /// Recorded frames keep the quality they were captured at
+    fn set_quality(&mut self, quality: u32) -> Result<()> {
+        match self {
+            FrameSource::Camera(capture) => capture.set_quality(quality)?,
+            FrameSource::Tap(tap) => tap.set_quality(quality),
+            FrameSource::Replay(_) => {}
+        }
+        Ok(())
+    }
+
+    fn get_stats(&self) -> CaptureStats {
+        match self {
+            FrameSource::Camera(capture) => capture.get_stats(),
+            FrameSource::Replay(replay) => replay.get_stats(),
+            FrameSource::Tap(tap) => tap.get_stats(),
+        }
+    }
+}
+
+/// Starts the source, retrying with backoff while the camera is held by
+/// someone else; None when cancelled while waiting
+async fn start_source(
+    name: &str,
+    source: &mut FrameSource,
+    token: &CancellationToken,
+) -> Result<Option<tokio::sync::mpsc::Receiver<Frame>>> {
+    let mut delay = BUSY_RETRY_MIN;
+    loop {
+        match source.start().await {
+            Ok(frame_rx) => return Ok(Some(frame_rx)),
+            Err(e) if matches!(e.downcast_ref(), Some(CaptureError::Busy { .. })) => {
+                warn!(camera = name, error = %e, retry_in = ?delay, "Camera busy, retrying");
+                tokio::select! {
+                    _ = token.cancelled() => return Ok(None),
+                    _ = tokio::time::sleep(delay) => {}
+                }
+                delay = (delay * 2).min(BUSY_RETRY_MAX);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// SSRC for a camera without an explicit or persisted one; changes every run
+pub fn rand_ssrc() -> u32 {
+    uuid::Uuid::new_v4().as_u128() as u32 | 1
+}
+
+/// Capture settings for a camera section, scaled down to the platform ceiling
+pub fn capture_config(
+    name: &str,
+    camera_config: &CameraConfig,
+    preset: ModelPreset,
+) -> CaptureConfig {
+    let (width, height) = preset.clamp_resolution(camera_config.width, camera_config.height);
+    if (width, height) != (camera_config.width, camera_config.height) {
+        tracing::warn!(
+            camera = name,
+            requested = %format!("{}x{}", camera_config.width, camera_config.height),
+            using = %format!("{}x{}", width, height),
+            "Resolution exceeds the platform ceiling, scaling down"
+        );
+    }
+
+    CaptureConfig {
+        device_path: camera_config.device.clone(),
+        width,
+        height,
+        fps: camera_config.fps,
+        quality: camera_config.quality,
+        flip_method: camera_config.flip_method.clone(),
+        encoder: preset.encoder,
+        encoder_threads: preset.encoder_threads,
+        capture_core: camera_config.affinity.capture_core,
+        encoder_core: camera_config.affinity.encoder_core,
+        timecode: false,
+        overlay: None,
+        pipeline: camera_config.pipeline.clone(),
+    }
+}
+
+/// The `[mjpeg-rtp]` section of `camera` ("camera1" or "camera2")
+pub fn camera_section<'a>(config: &'a Config, camera: &str) -> &'a CameraConfig {
+    match camera {
+        "camera2" => &config.mjpeg_rtp.camera2,
+        _ => &config.mjpeg_rtp.camera1,
+    }
+}
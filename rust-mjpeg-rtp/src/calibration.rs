@@ -0,0 +1,587 @@
+//! Bandwidth calibration
+//!
+//! `mjpeg-rtp calibrate` streams synthetic JPEG frames to an echo server
+//! (`mjpeg-rtp calibrate-echo` on the receiving machine) at increasing
+//! bitrates. The echo server answers every sender with RTCP receiver reports,
+//! from which the loss of each step is measured. Steps stop at the first one
+//! losing more than the threshold; the best clean throughput, less headroom,
+//! becomes the recommended `[mjpeg-rtp.congestion]` settings, so adaptive
+//! quality keeps the cameras within what the link sustains.
+
+use crate::error::ErrorCode;
+use crate::pacing::FramePacer;
+use crate::rtp::{
+    build_receiver_report, parse_report_blocks, PacketizerError, ReportBlock, RtpHeader,
+    RtpPacketizer, RTP_CLOCK_RATE, RTP_VERSION,
+};
+use crate::task::CancellationToken;
+use std::io;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tokio::net::UdpSocket;
+use tokio::sync::watch;
+use tracing::{debug, info};
+
+/// How often the echo server reports on the stream it receives
+pub const REPORT_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Wait after a step for the reports covering its last packets
+const REPORT_GRACE: Duration = Duration::from_millis(700);
+
+/// Smallest synthetic frame, so low rates still fill a packet or two
+const MIN_FRAME_BYTES: usize = 1024;
+
+/// Share of the sustained throughput recommended as the target bitrate
+const TARGET_SHARE: f64 = 0.7;
+
+/// Share of the sustained throughput quality adaptation may climb to
+const MAX_SHARE: f64 = 0.9;
+
+#[derive(Error, Debug)]
+pub enum CalibrationError {
+    #[error("calibration I/O error: {0}")]
+    Io(#[from] io::Error),
+
+    #[error(transparent)]
+    Packetizer(#[from] PacketizerError),
+
+    #[error("no RTCP receiver reports from {0}; is `mjpeg-rtp calibrate-echo` running there?")]
+    NoFeedback(SocketAddr),
+
+    #[error("cannot update the config: {0}")]
+    Config(String),
+}
+
+impl CalibrationError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            CalibrationError::Io(_) | CalibrationError::NoFeedback(_) => ErrorCode::Io,
+            CalibrationError::Packetizer(e) => e.code(),
+            CalibrationError::Config(_) => ErrorCode::InvalidConfig,
+        }
+    }
+}
+
+/// What to send and how to ramp it up
+#[derive(Debug, Clone)]
+pub struct CalibrationConfig {
+    /// Echo server
+    pub dest: SocketAddr,
+    pub start_kbps: u64,
+    pub max_kbps: u64,
+    /// Each step sends this many times the previous step's rate
+    pub step_factor: f64,
+    pub step_duration: Duration,
+    /// Size and rate of the synthetic frames, normally the camera's
+    pub width: u32,
+    pub height: u32,
+    pub fps: u32,
+    pub mtu: usize,
+    /// Loss (0.0 - 1.0) above which a step fails and the ramp stops
+    pub max_loss: f32,
+}
+
+/// Outcome of sending at one rate
+#[derive(Debug, Clone, PartialEq)]
+pub struct StepResult {
+    pub target_kbps: u64,
+    /// Rate actually put on the wire, headers included
+    pub sent_kbps: u64,
+    pub packets_sent: u64,
+    /// Share of the step's packets the echo server didn't receive
+    pub loss: f32,
+    /// Interarrival jitter at the end of the step
+    pub jitter_ms: f64,
+}
+
+impl StepResult {
+    /// Rate that made it to the receiver
+    pub fn goodput_kbps(&self) -> u64 {
+        (self.sent_kbps as f64 * (1.0 - self.loss as f64)) as u64
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CalibrationReport {
+    pub steps: Vec<StepResult>,
+    pub max_loss: f32,
+}
+
+/// Congestion settings derived from a calibration, per camera
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Recommendation {
+    pub target_bitrate_kbps: u64,
+    pub max_bitrate_kbps: u64,
+}
+
+impl CalibrationReport {
+    /// Best goodput of a step within the loss threshold, `None` when even
+    /// the first step lost too much
+    pub fn sustained_kbps(&self) -> Option<u64> {
+        self.steps
+            .iter()
+            .take_while(|step| step.loss <= self.max_loss)
+            .map(StepResult::goodput_kbps)
+            .max()
+    }
+
+    /// Settings sharing the sustained throughput between `cameras`
+    pub fn recommend(&self, cameras: u32) -> Option<Recommendation> {
+        let per_camera = self.sustained_kbps()? as f64 / cameras.max(1) as f64;
+        Some(Recommendation {
+            target_bitrate_kbps: (per_camera * TARGET_SHARE) as u64,
+            max_bitrate_kbps: (per_camera * MAX_SHARE) as u64,
+        })
+    }
+}
+
+/// A baseline 4:2:0 JPEG of `width` x `height` whose entropy-coded data is
+/// `size` bytes of noise. Not decodable, but packetized like camera output,
+/// and incompressible for links that compress.
+pub fn synthetic_frame(width: u32, height: u32, size: usize) -> Vec<u8> {
+    let mut jpeg = vec![0xFF, 0xD8]; // SOI
+    for table in 0..2u8 {
+        jpeg.extend([0xFF, 0xDB, 0x00, 0x43, table]); // DQT
+        jpeg.extend([16u8; 64]);
+    }
+    let (w, h) = (width as u16, height as u16);
+    jpeg.extend([0xFF, 0xC0, 0x00, 0x11, 0x08]); // SOF0, 8-bit
+    jpeg.extend(h.to_be_bytes());
+    jpeg.extend(w.to_be_bytes());
+    jpeg.extend([0x03, 0x01, 0x22, 0x00, 0x02, 0x11, 0x01, 0x03, 0x11, 0x01]);
+    jpeg.extend([
+        0xFF, 0xDA, 0x00, 0x0C, 0x03, 0x01, 0x00, 0x02, 0x11, 0x03, 0x11, 0x00, 0x3F, 0x00,
+    ]);
+
+    // xorshift noise; 0xFF would read as a marker
+    let mut state = 0x2545_F491_u32;
+    jpeg.extend((0..size).map(|_| {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        (state % 0xFF) as u8
+    }));
+    jpeg.extend([0xFF, 0xD9]); // EOI
+    jpeg
+}
+
+/// Ramps the rate up until a step loses too much, or `max_kbps` is reached
+pub async fn run(config: &CalibrationConfig) -> Result<CalibrationReport, CalibrationError> {
+    let bind: SocketAddr = if config.dest.is_ipv4() {
+        "0.0.0.0:0".parse().unwrap()
+    } else {
+        "[::]:0".parse().unwrap()
+    };
+    let socket = Arc::new(UdpSocket::bind(bind).await?);
+    let ssrc = uuid::Uuid::new_v4().as_u128() as u32 | 1;
+    let packetizer = RtpPacketizer::new(ssrc, config.mtu);
+
+    // The echo server reports to the address packets come from
+    let (report_tx, mut reports) = watch::channel(None);
+    let reader = tokio::spawn(read_reports(socket.clone(), ssrc, report_tx));
+
+    let mut steps = Vec::new();
+    let mut kbps = config.start_kbps.max(1);
+    let result = loop {
+        let step = match send_step(&socket, &packetizer, &mut reports, config, kbps).await {
+            Ok(step) => step,
+            Err(e) => break Err(e),
+        };
+        info!(
+            target_kbps = step.target_kbps,
+            sent_kbps = step.sent_kbps,
+            loss = %format!("{:.2}%", step.loss * 100.0),
+            jitter_ms = %format!("{:.1}", step.jitter_ms),
+            "Calibration step"
+        );
+        let failed = step.loss > config.max_loss;
+        steps.push(step);
+        if failed || kbps >= config.max_kbps {
+            break Ok(());
+        }
+        kbps = ((kbps as f64 * config.step_factor) as u64)
+            .max(kbps + 1)
+            .min(config.max_kbps);
+    };
+    reader.abort();
+
+    result.map(|()| CalibrationReport {
+        steps,
+        max_loss: config.max_loss,
+    })
+}
+
+async fn send_step(
+    socket: &UdpSocket,
+    packetizer: &RtpPacketizer,
+    reports: &mut watch::Receiver<Option<ReportBlock>>,
+    config: &CalibrationConfig,
+    kbps: u64,
+) -> Result<StepResult, CalibrationError> {
+    let fps = config.fps.max(1);
+    let frame_bytes = ((kbps * 1000 / 8) as usize / fps as usize).max(MIN_FRAME_BYTES);
+    let frame = synthetic_frame(config.width, config.height, frame_bytes);
+
+    // Before the first report, count from the first packet sent
+    let before = reports.borrow_and_update().unwrap_or(ReportBlock {
+        highest_seq: packetizer.get_sequence_number().wrapping_sub(1),
+        ..ReportBlock::default()
+    });
+
+    let mut pacer = FramePacer::new(fps);
+    let base_ts = packetizer.get_next_timestamp();
+    let (mut packets_sent, mut bytes_sent) = (0u64, 0u64);
+    let started = Instant::now();
+    while started.elapsed() < config.step_duration {
+        let slot = pacer.tick().await;
+        let timestamp = base_ts.wrapping_add(pacer.timestamp(slot) as u32);
+        packetizer.set_timestamp(base_ts.wrapping_add(pacer.timestamp(slot + 1) as u32));
+        for packet in packetizer.packetize_jpeg(&frame, config.width, config.height, timestamp)? {
+            packets_sent += 1;
+            // A full socket buffer is loss like any other
+            match socket.send_to(&packet, config.dest).await {
+                Ok(len) => bytes_sent += len as u64,
+                Err(e) => debug!(error = %e, "Calibration send failed"),
+            }
+        }
+    }
+    let elapsed = started.elapsed().as_secs_f64();
+
+    tokio::time::sleep(REPORT_GRACE).await;
+    let after = match *reports.borrow_and_update() {
+        Some(after) if after.highest_seq != before.highest_seq => after,
+        _ => return Err(CalibrationError::NoFeedback(config.dest)),
+    };
+    let received = after.highest_seq.wrapping_sub(before.highest_seq) as i64
+        - (after.cumulative_lost as i64 - before.cumulative_lost as i64);
+    let loss = 1.0 - (received.max(0) as f64 / packets_sent.max(1) as f64).min(1.0);
+
+    Ok(StepResult {
+        target_kbps: kbps,
+        sent_kbps: (bytes_sent as f64 * 8.0 / elapsed / 1000.0) as u64,
+        packets_sent,
+        loss: loss as f32,
+        jitter_ms: after.jitter as f64 * 1000.0 / RTP_CLOCK_RATE as f64,
+    })
+}
+
+/// Publishes the latest report block about `ssrc`
+async fn read_reports(
+    socket: Arc<UdpSocket>,
+    ssrc: u32,
+    reports: watch::Sender<Option<ReportBlock>>,
+) {
+    let mut buf = [0u8; 1500];
+    while let Ok(len) = socket.recv(&mut buf).await {
+        if let Some(block) = parse_report_blocks(&buf[..len])
+            .into_iter()
+            .find(|b| b.ssrc == ssrc)
+        {
+            reports.send_replace(Some(block));
+        }
+    }
+}
+
+/// `elapsed` in 90 kHz units, wrapping like RTP timestamps
+fn rtp_time(elapsed: Duration) -> u32 {
+    (elapsed.as_secs_f64() * RTP_CLOCK_RATE as f64) as u64 as u32
+}
+
+/// Reception statistics of one source (RFC 3550 Appendix A.1, A.3, A.8)
+struct Reception {
+    ssrc: u32,
+    base_seq: u32,
+    max_seq: u16,
+    cycles: u32,
+    received: u64,
+    expected_prior: u64,
+    received_prior: u64,
+    /// In RTP timestamp units, scaled by 16 as in A.8
+    jitter: u32,
+    last_transit: Option<u32>,
+}
+
+impl Reception {
+    fn new(ssrc: u32, seq: u16) -> Self {
+        Self {
+            ssrc,
+            base_seq: seq as u32,
+            max_seq: seq,
+            cycles: 0,
+            received: 0,
+            expected_prior: 0,
+            received_prior: 0,
+            jitter: 0,
+            last_transit: None,
+        }
+    }
+
+    /// `arrival` is in RTP timestamp units
+    fn on_packet(&mut self, seq: u16, timestamp: u32, arrival: u32) {
+        let ahead = seq.wrapping_sub(self.max_seq);
+        if ahead != 0 && ahead < 0x8000 {
+            if seq < self.max_seq {
+                self.cycles = self.cycles.wrapping_add(1 << 16);
+            }
+            self.max_seq = seq;
+        }
+        self.received += 1;
+
+        let transit = arrival.wrapping_sub(timestamp);
+        if let Some(last) = self.last_transit {
+            let d = (transit.wrapping_sub(last) as i32).unsigned_abs();
+            self.jitter = self
+                .jitter
+                .wrapping_add(d)
+                .wrapping_sub((self.jitter.wrapping_add(8)) >> 4);
+        }
+        self.last_transit = Some(transit);
+    }
+
+    fn report(&mut self) -> ReportBlock {
+        let extended_max = self.cycles.wrapping_add(self.max_seq as u32);
+        let expected = extended_max.wrapping_sub(self.base_seq) as u64 + 1;
+        let lost = expected as i64 - self.received as i64;
+
+        let expected_interval = expected.saturating_sub(self.expected_prior);
+        let received_interval = self.received.saturating_sub(self.received_prior);
+        self.expected_prior = expected;
+        self.received_prior = self.received;
+        let lost_interval = expected_interval.saturating_sub(received_interval);
+        let fraction_lost = (lost_interval << 8)
+            .checked_div(expected_interval)
+            .map_or(0, |fraction| fraction.min(255) as u8);
+
+        ReportBlock {
+            ssrc: self.ssrc,
+            fraction_lost,
+            cumulative_lost: lost.clamp(-0x80_0000, 0x7F_FFFF) as i32,
+            highest_seq: extended_max,
+            jitter: self.jitter >> 4,
+            last_sr: 0,
+            delay_since_last_sr: 0,
+        }
+    }
+}
+
+/// Receives calibration streams on `socket` and reports on each sender
+/// until cancelled. A sender showing up with a new SSRC starts afresh.
+pub async fn run_echo(socket: UdpSocket, token: CancellationToken) -> io::Result<()> {
+    let reporter_ssrc = uuid::Uuid::new_v4().as_u128() as u32 | 1;
+    let started = Instant::now();
+    let mut ticker = tokio::time::interval(REPORT_INTERVAL);
+    let mut source: Option<(SocketAddr, Reception)> = None;
+    let mut buf = vec![0u8; 65536];
+
+    loop {
+        tokio::select! {
+            _ = token.cancelled() => return Ok(()),
+            _ = ticker.tick() => {
+                if let Some((addr, ref mut reception)) = source {
+                    let report = build_receiver_report(reporter_ssrc, &[reception.report()]);
+                    if let Err(e) = socket.send_to(&report, addr).await {
+                        debug!(error = %e, %addr, "Failed to send receiver report");
+                    }
+                }
+            }
+            received = socket.recv_from(&mut buf) => {
+                let (len, from) = received?;
+                let Some(header) = RtpHeader::from_bytes(&buf[..len]) else {
+                    continue;
+                };
+                if header.version != RTP_VERSION {
+                    continue;
+                }
+                let arrival = rtp_time(started.elapsed());
+                match source {
+                    Some((ref mut addr, ref mut reception)) if reception.ssrc == header.ssrc => {
+                        *addr = from;
+                        reception.on_packet(header.sequence_number, header.timestamp, arrival);
+                    }
+                    _ => {
+                        let ssrc = format!("{:#010x}", header.ssrc);
+                        info!(%from, %ssrc, "Calibration sender");
+                        let mut reception = Reception::new(header.ssrc, header.sequence_number);
+                        reception.on_packet(header.sequence_number, header.timestamp, arrival);
+                        source = Some((from, reception));
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Writes `recommendation` into the `[mjpeg-rtp.congestion]` section of the
+/// config file at `path`, keeping the rest of the file (comments included)
+pub fn write_recommendation(
+    path: &Path,
+    recommendation: &Recommendation,
+) -> Result<(), CalibrationError> {
+    let text = std::fs::read_to_string(path)?;
+    let mut doc: toml_edit::DocumentMut = text
+        .parse()
+        .map_err(|e: toml_edit::TomlError| CalibrationError::Config(e.to_string()))?;
+
+    let congestion = &mut doc["mjpeg-rtp"]["congestion"];
+    if congestion.is_none() {
+        *congestion = toml_edit::table();
+    }
+    let max = recommendation.max_bitrate_kbps as i64;
+    congestion["controller"] = toml_edit::value("aimd");
+    congestion["target_bitrate_kbps"] = toml_edit::value(recommendation.target_bitrate_kbps as i64);
+    congestion["max_bitrate_kbps"] = toml_edit::value(max);
+    // The floor has to stay below the new ceiling
+    let min = congestion
+        .get("min_bitrate_kbps")
+        .and_then(|v| v.as_integer());
+    if min.is_some_and(|min| min > max) || (min.is_none() && max < 500) {
+        congestion["min_bitrate_kbps"] = toml_edit::value(max / 4);
+    }
+
+    std::fs::write(path, doc.to_string())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rtp::parse_jpeg_for_rtp;
+
+    #[test]
+    fn test_synthetic_frame() {
+        let frame = synthetic_frame(1280, 720, 5000);
+        let info = parse_jpeg_for_rtp(&frame).unwrap();
+        assert_eq!((info.width, info.height), (1280, 720));
+        assert_eq!(info.jpeg_type, 0);
+        assert_eq!(info.q_tables.len(), 2);
+        assert_eq!(info.scan_data.len(), 5000);
+        assert!(!info.scan_data.contains(&0xFF));
+    }
+
+    #[test]
+    fn test_reception_counts_loss_across_wrap() {
+        let mut reception = Reception::new(7, 65530);
+        for seq in (65530..=65535).chain(0..10).filter(|seq| seq % 4 != 0) {
+            reception.on_packet(seq as u16, 0, 0);
+        }
+        let report = reception.report();
+        // 65532 and 0, 4, 8 missing out of 16: a quarter, 64/256
+        assert_eq!(report.highest_seq, 65536 + 9);
+        assert_eq!(report.cumulative_lost, 4);
+        assert_eq!(report.fraction_lost, 64);
+        // Nothing new since: no interval loss
+        assert_eq!(reception.report().fraction_lost, 0);
+    }
+
+    #[test]
+    fn test_recommendation() {
+        let step = |target_kbps, loss| StepResult {
+            target_kbps,
+            sent_kbps: target_kbps,
+            packets_sent: 100,
+            loss,
+            jitter_ms: 0.0,
+        };
+        let report = CalibrationReport {
+            steps: vec![step(1000, 0.0), step(2000, 0.005), step(4000, 0.2)],
+            max_loss: 0.01,
+        };
+        assert_eq!(report.sustained_kbps(), Some(1990));
+        assert_eq!(
+            report.recommend(2),
+            Some(Recommendation {
+                target_bitrate_kbps: 696,
+                max_bitrate_kbps: 895,
+            })
+        );
+
+        let lossy = CalibrationReport {
+            steps: vec![step(1000, 0.5)],
+            max_loss: 0.01,
+        };
+        assert_eq!(lossy.recommend(1), None);
+    }
+
+    #[test]
+    fn test_write_recommendation_keeps_the_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        std::fs::write(
+            &path,
+            "# cameras\n[mjpeg-rtp]\nenabled = true\n\n\
+             [mjpeg-rtp.congestion]\nmin_bitrate_kbps = 800\n",
+        )
+        .unwrap();
+
+        let recommendation = Recommendation {
+            target_bitrate_kbps: 3000,
+            max_bitrate_kbps: 4000,
+        };
+        write_recommendation(&path, &recommendation).unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+        assert!(text.starts_with("# cameras\n"));
+        let value: toml::Value = toml::from_str(&text).unwrap();
+        let congestion = &value["mjpeg-rtp"]["congestion"];
+        assert_eq!(congestion["controller"].as_str(), Some("aimd"));
+        assert_eq!(congestion["target_bitrate_kbps"].as_integer(), Some(3000));
+        assert_eq!(congestion["max_bitrate_kbps"].as_integer(), Some(4000));
+        assert_eq!(congestion["min_bitrate_kbps"].as_integer(), Some(800));
+    }
+
+    #[tokio::test]
+    async fn test_calibrates_against_echo() {
+        let echo = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let dest = echo.local_addr().unwrap();
+        let token = CancellationToken::new();
+        let server = tokio::spawn(run_echo(echo, token.clone()));
+
+        let config = CalibrationConfig {
+            dest,
+            start_kbps: 500,
+            max_kbps: 1000,
+            step_factor: 2.0,
+            step_duration: Duration::from_millis(400),
+            width: 320,
+            height: 240,
+            fps: 20,
+            mtu: 1400,
+            max_loss: 0.05,
+        };
+        let report = run(&config).await.unwrap();
+        token.cancel();
+        server.await.unwrap().unwrap();
+
+        assert_eq!(report.steps.len(), 2);
+        assert_eq!(report.steps[1].target_kbps, 1000);
+        assert!(
+            report.steps.iter().all(|step| step.loss <= 0.05),
+            "{:?}",
+            report
+        );
+        assert!(report.recommend(1).is_some());
+    }
+
+    #[tokio::test]
+    async fn test_no_echo_server() {
+        let silent = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let config = CalibrationConfig {
+            dest: silent.local_addr().unwrap(),
+            start_kbps: 500,
+            max_kbps: 500,
+            step_factor: 2.0,
+            step_duration: Duration::from_millis(100),
+            width: 320,
+            height: 240,
+            fps: 20,
+            mtu: 1400,
+            max_loss: 0.05,
+        };
+        assert!(matches!(
+            run(&config).await,
+            Err(CalibrationError::NoFeedback(_))
+        ));
+    }
+}
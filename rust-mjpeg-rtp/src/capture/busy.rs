@@ -0,0 +1,198 @@
+//! Finding out who holds a busy camera
+//!
+//! A camera that another process, or another pipeline of ours, already has
+//! open fails to start with a generic state change error. The element's bus
+//! message says the device is busy; the holders are then found by scanning
+//! `/proc/<pid>/fd` for the camera's device nodes, so the error can name the
+//! process to stop. Processes of other users are only visible to root.
+
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A process with one of a camera's device nodes open
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceHolder {
+    pub pid: u32,
+    /// Command name from `/proc/<pid>/comm`
+    pub command: String,
+    /// The device node it has open
+    pub node: PathBuf,
+}
+
+impl DeviceHolder {
+    /// Whether the holder is this process, e.g. a second camera configured
+    /// with the same device
+    pub fn is_self(&self) -> bool {
+        self.pid == std::process::id()
+    }
+}
+
+impl fmt::Display for DeviceHolder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "pid {} ({})", self.pid, self.command)
+    }
+}
+
+/// Whether an error message from a source element means the device is
+/// already in use (v4l2src "Device is busy", libcamerasrc "already in use",
+/// EBUSY)
+pub fn is_busy_message(message: &str) -> bool {
+    let message = message.to_ascii_lowercase();
+    ["busy", "already in use", "failed to acquire"]
+        .iter()
+        .any(|needle| message.contains(needle))
+}
+
+/// Processes holding `device` open: the node itself for a `/dev/` path,
+/// the media controller nodes for a libcamera camera name
+pub fn device_holders(device: &str) -> Vec<DeviceHolder> {
+    holders_in(Path::new("/proc"), &camera_nodes(Path::new("/dev"), device))
+}
+
+/// How to free the camera, given its holders
+pub fn describe_holders(holders: &[DeviceHolder]) -> String {
+    let mut others: Vec<String> = holders
+        .iter()
+        .filter(|holder| !holder.is_self())
+        .map(ToString::to_string)
+        .collect();
+    // A process holding several nodes is named once
+    others.dedup();
+    if !others.is_empty() {
+        format!(
+            "held by {}; stop it or use another camera",
+            others.join(", ")
+        )
+    } else if !holders.is_empty() {
+        "already open in this process; check that no two cameras use the same device".to_string()
+    } else {
+        "held by a process this user cannot see (try `fuser -v /dev/media* /dev/video*` as root)"
+            .to_string()
+    }
+}
+
+fn camera_nodes(dev: &Path, device: &str) -> Vec<PathBuf> {
+    let path = Path::new(device);
+    if path.starts_with(dev) {
+        return vec![fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())];
+    }
+    let Ok(entries) = fs::read_dir(dev) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter(|entry| entry.file_name().to_string_lossy().starts_with("media"))
+        .map(|entry| entry.path())
+        .collect()
+}
+
+fn holders_in(proc_root: &Path, nodes: &[PathBuf]) -> Vec<DeviceHolder> {
+    if nodes.is_empty() {
+        return Vec::new();
+    }
+    let Ok(processes) = fs::read_dir(proc_root) else {
+        return Vec::new();
+    };
+
+    let mut holders = Vec::new();
+    for process in processes.flatten() {
+        let Ok(pid) = process.file_name().to_string_lossy().parse::<u32>() else {
+            continue;
+        };
+        // Other users' fds are unreadable without root
+        let Ok(fds) = fs::read_dir(process.path().join("fd")) else {
+            continue;
+        };
+        for fd in fds.flatten() {
+            let Ok(target) = fs::read_link(fd.path()) else {
+                continue;
+            };
+            if nodes.contains(&target) {
+                let command = fs::read_to_string(process.path().join("comm")).unwrap_or_default();
+                holders.push(DeviceHolder {
+                    pid,
+                    command: command.trim().to_string(),
+                    node: target,
+                });
+            }
+        }
+    }
+    holders.sort_by_key(|holder| holder.pid);
+    holders.dedup();
+    holders
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::symlink;
+
+    #[test]
+    fn test_busy_messages() {
+        assert!(is_busy_message("Device '/dev/video0' is busy"));
+        assert!(is_busy_message(
+            "Camera '/base/soc/i2c0mux/imx708@1a' is already in use."
+        ));
+        assert!(is_busy_message(
+            "Could not open device: Device or resource busy"
+        ));
+        assert!(!is_busy_message("Internal data stream error."));
+    }
+
+    #[test]
+    fn test_holders_from_proc() {
+        let root = tempfile::tempdir().unwrap();
+        // Device paths are canonicalized, so the fds must point at canonical paths
+        let base = fs::canonicalize(root.path()).unwrap();
+        let (proc_root, dev) = (base.join("proc"), base.join("dev"));
+        fs::create_dir_all(&dev).unwrap();
+        for node in ["media0", "media1", "video0"] {
+            fs::write(dev.join(node), "").unwrap();
+        }
+        let process = |pid: u32, command: &str, fds: &[&str]| {
+            let fd_dir = proc_root.join(pid.to_string()).join("fd");
+            fs::create_dir_all(&fd_dir).unwrap();
+            fs::write(
+                proc_root.join(pid.to_string()).join("comm"),
+                format!("{}\n", command),
+            )
+            .unwrap();
+            for (fd, node) in fds.iter().enumerate() {
+                symlink(dev.join(node), fd_dir.join(fd.to_string())).unwrap();
+            }
+        };
+        process(812, "rpicam-vid", &["media0", "media1"]);
+        process(900, "bash", &["video0"]);
+        fs::create_dir_all(proc_root.join("self")).unwrap();
+
+        // A libcamera name matches the media nodes
+        let holders = holders_in(
+            &proc_root,
+            &camera_nodes(&dev, "/base/soc/i2c0mux/imx708@1a"),
+        );
+        let pids: Vec<u32> = holders.iter().map(|holder| holder.pid).collect();
+        assert_eq!(pids, [812, 812]);
+        assert_eq!(holders[0].command, "rpicam-vid");
+        assert_eq!(
+            describe_holders(&holders),
+            "held by pid 812 (rpicam-vid); stop it or use another camera"
+        );
+
+        let video0 = dev.join("video0");
+        let holders = holders_in(&proc_root, &camera_nodes(&dev, video0.to_str().unwrap()));
+        assert_eq!(holders.len(), 1);
+        assert_eq!(holders[0].pid, 900);
+    }
+
+    #[test]
+    fn test_describe_own_process() {
+        let own = DeviceHolder {
+            pid: std::process::id(),
+            command: "mjpeg-rtp".to_string(),
+            node: PathBuf::from("/dev/media0"),
+        };
+        assert!(describe_holders(&[own]).contains("this process"));
+        assert!(describe_holders(&[]).contains("fuser"));
+    }
+}
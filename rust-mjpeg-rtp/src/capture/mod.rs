@@ -1,18 +1,32 @@
 //! GStreamer-based MJPEG capture
 
+mod busy;
+mod network;
+mod overrides;
 mod platform;
 
-pub use platform::PlatformInfo;
-
+pub use busy::{describe_holders, device_holders, is_busy_message, DeviceHolder};
+pub use network::{redact_device, validate_device, NetworkSource};
+pub use overrides::{PipelineOverrides, PropertyValue};
+pub use platform::{detect_pi_model, JpegEncoder, ModelPreset, PiModel, PlatformInfo};
+
+use crate::affinity;
+use crate::error::ErrorCode;
+use crate::frame::Frame;
+use crate::log_limited;
+use crate::overlay::Overlay;
+use crate::ratelimit::LogLimiter;
 use bytes::Bytes;
 use gstreamer as gst;
 use gstreamer::prelude::*;
 use gstreamer_app as gst_app;
+use std::collections::HashSet;
 use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
 use thiserror::Error;
 use tokio::sync::mpsc;
-use tracing::{debug, info, warn};
+use tracing::{debug, info, trace_span, warn};
 
 #[derive(Error, Debug)]
 pub enum CaptureError {
@@ -23,16 +37,38 @@ pub enum CaptureError {
     GstBool(#[from] gst::glib::BoolError),
 
     #[error("state change error: {0}")]
-    StateChange(String),
+    StateChange(#[from] gst::StateChangeError),
 
     #[error("pipeline error: {0}")]
     Pipeline(String),
 
+    /// The camera is open elsewhere; `detail` says who holds it
+    #[error("camera {device} is busy: {detail}")]
+    Busy { device: String, detail: String },
+
     #[error("channel send error")]
     ChannelSend,
 
     #[error("capture not running")]
     NotRunning,
+
+    #[error("capture already running")]
+    AlreadyRunning,
+}
+
+impl CaptureError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            CaptureError::Gst(_)
+            | CaptureError::GstBool(_)
+            | CaptureError::StateChange(_)
+            | CaptureError::Pipeline(_)
+            | CaptureError::Busy { .. } => ErrorCode::Device,
+            CaptureError::ChannelSend => ErrorCode::ChannelClosed,
+            CaptureError::NotRunning => ErrorCode::NotRunning,
+            CaptureError::AlreadyRunning => ErrorCode::AlreadyRunning,
+        }
+    }
 }
 
 /// Capture configuration
@@ -44,16 +80,118 @@ pub struct CaptureConfig {
     pub fps: u32,
     pub quality: u32,
     pub flip_method: Option<String>,
+    /// JPEG encoder element (Raspberry Pi pipeline only)
+    pub encoder: JpegEncoder,
+    /// `videoconvert n-threads` (0 = GStreamer default)
+    pub encoder_threads: u32,
+    /// Core for the appsink callback thread
+    pub capture_core: Option<usize>,
+    /// Core for the colour conversion / JPEG encoder streaming thread
+    pub encoder_core: Option<usize>,
+    /// Paint a [`timecode`](crate::timecode) into every frame before
+    /// encoding, for latency measurement
+    pub timecode: bool,
+    /// Boxes to outline in every frame before encoding
+    pub overlay: Option<Overlay>,
+    /// Extra elements and property overrides
+    pub pipeline: PipelineOverrides,
+}
+
+impl Default for CaptureConfig {
+    fn default() -> Self {
+        Self {
+            device_path: "0".to_string(),
+            width: 640,
+            height: 480,
+            fps: 30,
+            quality: 85,
+            flip_method: None,
+            encoder: JpegEncoder::Software,
+            encoder_threads: 0,
+            capture_core: None,
+            encoder_core: None,
+            timecode: false,
+            overlay: None,
+            pipeline: PipelineOverrides::default(),
+        }
+    }
 }
 
 /// Statistics for capture
 #[derive(Debug, Clone, Default)]
 pub struct CaptureStats {
     pub frames_captured: u64,
+    /// Sum of the three drop counters below
     pub frames_dropped: u64,
+    /// Raw frames the queue in front of the encoder leaked because the
+    /// encoder fell behind
+    pub dropped_pre_encode: u64,
+    /// Encoded frames the appsink discarded from its full queue before they
+    /// were pulled; may include up to `max-buffers` still waiting
+    pub dropped_in_appsink: u64,
+    /// Pulled frames the consumer's channel had no room for
+    pub dropped_in_channel: u64,
     pub is_running: bool,
 }
 
+/// A video source found by GStreamer's device monitor
+#[derive(Debug, Clone)]
+pub struct CameraInfo {
+    /// Human-readable name reported by the device provider
+    pub name: String,
+    /// Value for a camera's `device` config key, when the source element
+    /// exposes one (`camera-name`, `device` or `device-index`)
+    pub device: Option<String>,
+    /// Source element the provider would use, e.g. `libcamerasrc`
+    pub element: Option<String>,
+    /// Formats offered, one caps structure per entry
+    pub caps: Vec<String>,
+}
+
+/// Lists the video sources currently visible to GStreamer
+pub fn list_cameras() -> Result<Vec<CameraInfo>, CaptureError> {
+    gst::init()?;
+
+    let monitor = gst::DeviceMonitor::new();
+    monitor.add_filter(Some("Video/Source"), None);
+    monitor.start()?;
+    let devices = monitor.devices();
+    monitor.stop();
+
+    let cameras = devices
+        .iter()
+        .map(|device| {
+            let element = device.create_element(None).ok();
+            let device_key = element.as_ref().and_then(|element| {
+                ["camera-name", "device", "device-index"]
+                    .into_iter()
+                    .find(|name| element.find_property(name).is_some())
+                    .and_then(|name| {
+                        let value = element.property_value(name);
+                        value
+                            .get::<String>()
+                            .ok()
+                            .or_else(|| value.get::<i32>().ok().map(|index| index.to_string()))
+                    })
+            });
+
+            CameraInfo {
+                name: device.display_name().to_string(),
+                device: device_key,
+                element: element
+                    .and_then(|element| element.factory())
+                    .map(|factory| factory.name().to_string()),
+                caps: device
+                    .caps()
+                    .map(|caps| caps.iter().map(|s| s.to_string()).collect())
+                    .unwrap_or_default(),
+            }
+        })
+        .collect();
+
+    Ok(cameras)
+}
+
 /// GStreamer MJPEG capture
 pub struct Capture {
     config: CaptureConfig,
@@ -63,14 +201,16 @@ pub struct Capture {
     app_sink: Option<gst_app::AppSink>,
 
     // Frame output
-    frame_tx: mpsc::Sender<Bytes>,
+    frame_tx: mpsc::Sender<Frame>,
 
     // State
     is_running: Arc<AtomicBool>,
 
     // Statistics
     frame_count: Arc<AtomicU64>,
-    drop_count: Arc<AtomicU64>,
+    channel_drops: Arc<AtomicU64>,
+    pre_encode_drops: Arc<AtomicU64>,
+    sink_arrivals: Arc<AtomicU64>,
 }
 
 impl Capture {
@@ -88,18 +228,20 @@ impl Capture {
             frame_tx,
             is_running: Arc::new(AtomicBool::new(false)),
             frame_count: Arc::new(AtomicU64::new(0)),
-            drop_count: Arc::new(AtomicU64::new(0)),
+            channel_drops: Arc::new(AtomicU64::new(0)),
+            pre_encode_drops: Arc::new(AtomicU64::new(0)),
+            sink_arrivals: Arc::new(AtomicU64::new(0)),
         })
     }
 
     /// Starts capture
-    pub async fn start(&mut self) -> Result<mpsc::Receiver<Bytes>, CaptureError> {
+    pub async fn start(&mut self) -> Result<mpsc::Receiver<Frame>, CaptureError> {
         if self.is_running.load(Ordering::Relaxed) {
-            return Err(CaptureError::Pipeline("Already running".to_string()));
+            return Err(CaptureError::AlreadyRunning);
         }
 
         info!(
-            device = %self.config.device_path,
+            device = %redact_device(&self.config.device_path),
             resolution = %format!("{}x{}", self.config.width, self.config.height),
             fps = %self.config.fps,
             quality = %self.config.quality,
@@ -114,6 +256,8 @@ impl Capture {
             .dynamic_cast::<gst::Pipeline>()
             .map_err(|_| CaptureError::Pipeline("Not a pipeline".to_string()))?;
 
+        self.apply_property_overrides(&pipeline)?;
+
         // Get appsink
         let app_sink = pipeline
             .by_name("sink")
@@ -125,10 +269,71 @@ impl Capture {
         let (frame_tx, frame_rx) = mpsc::channel(5);
         self.frame_tx = frame_tx.clone();
 
+        // Pin the encoder's streaming thread the first time a buffer reaches it
+        if let Some(core) = self.config.encoder_core {
+            let enc_sink = pipeline
+                .by_name("enc")
+                .and_then(|enc| enc.static_pad("sink"))
+                .ok_or_else(|| CaptureError::Pipeline("No encoder sink pad found".to_string()))?;
+            enc_sink.add_probe(gst::PadProbeType::BUFFER, move |_, _| {
+                affinity::pin_current_thread(core, "encoder");
+                gst::PadProbeReturn::Remove
+            });
+        }
+
+        if self.config.timecode {
+            let stamp = pipeline
+                .by_name("timecode")
+                .and_then(|stamp| stamp.static_pad("src"))
+                .ok_or_else(|| CaptureError::Pipeline("No timecode element found".to_string()))?;
+            stamp.add_probe(gst::PadProbeType::BUFFER, |pad, info| {
+                // Frames too narrow for it are left alone
+                paint_luma(pad, info, |luma, stride, width, height| {
+                    let value = crate::timecode::now();
+                    crate::timecode::paint(luma, stride, width, height, value);
+                })
+            });
+        }
+
+        if let Some(overlay) = self.config.overlay.clone() {
+            let draw = pipeline
+                .by_name("overlay")
+                .and_then(|draw| draw.static_pad("src"))
+                .ok_or_else(|| CaptureError::Pipeline("No overlay element found".to_string()))?;
+            draw.add_probe(gst::PadProbeType::BUFFER, move |pad, info| {
+                paint_luma(pad, info, |luma, stride, width, height| {
+                    overlay.draw(luma, stride, width, height)
+                })
+            });
+        }
+
+        // The leaky queue signals every time it is full and about to leak
+        let pre_encode = pipeline
+            .by_name("preenc")
+            .ok_or_else(|| CaptureError::Pipeline("No pre-encoder queue found".to_string()))?;
+        let pre_encode_drops = Arc::clone(&self.pre_encode_drops);
+        pre_encode.connect("overrun", false, move |_| {
+            pre_encode_drops.fetch_add(1, Ordering::Relaxed);
+            None
+        });
+
+        // Buffers reaching the appsink but never pulled were dropped there
+        let sink_arrivals = Arc::clone(&self.sink_arrivals);
+        app_sink
+            .static_pad("sink")
+            .ok_or_else(|| CaptureError::Pipeline("No appsink pad found".to_string()))?
+            .add_probe(gst::PadProbeType::BUFFER, move |_, _| {
+                sink_arrivals.fetch_add(1, Ordering::Relaxed);
+                gst::PadProbeReturn::Ok
+            });
+
         // Setup appsink callbacks
         let frame_count = Arc::clone(&self.frame_count);
-        let drop_count = Arc::clone(&self.drop_count);
+        let channel_drops = Arc::clone(&self.channel_drops);
         let is_running = Arc::clone(&self.is_running);
+        let mut next_frame_id = 0u64;
+        let capture_core = self.config.capture_core;
+        let log = LogLimiter::default();
 
         // Configure AppSink for minimal memory usage
         app_sink.set_property("max-buffers", 2u32); // Limit internal queue to 2 frames
@@ -142,7 +347,22 @@ impl Capture {
                         return Ok(gst::FlowSuccess::Ok);
                     }
 
-                    let sample = sink.pull_sample().map_err(|_| gst::FlowError::Error)?;
+                    if next_frame_id == 0 {
+                        if let Some(core) = capture_core {
+                            affinity::pin_current_thread(core, "capture");
+                        }
+                    }
+
+                    // Every sample gets an id, including ones dropped below,
+                    // so gaps in the sequence are visible downstream
+                    let frame_id = next_frame_id;
+                    next_frame_id += 1;
+                    let _span = trace_span!("capture", frame_id).entered();
+
+                    let sample = sink.pull_sample().map_err(|e| {
+                        log_limited!(log, "pull_sample", warn, error = %e, "Failed to pull sample");
+                        gst::FlowError::Error
+                    })?;
                     let buffer = sample.buffer().ok_or(gst::FlowError::Error)?;
 
                     // Map buffer to read JPEG data - use zero-copy when possible
@@ -150,14 +370,33 @@ impl Capture {
                     // GStreamer buffer must be copied since it's owned by pipeline
                     // but we minimize allocations by going directly to Bytes
                     let jpeg_data = Bytes::copy_from_slice(map.as_slice());
+                    // Live sources stamp buffers with pipeline running time
+                    let pts = buffer.pts().map(|pts| Duration::from_nanos(pts.nseconds()));
 
                     // Send frame (non-blocking)
-                    match frame_tx.try_send(jpeg_data) {
+                    match frame_tx.try_send(Frame::new(frame_id, jpeg_data).with_pts(pts)) {
                         Ok(_) => {
                             frame_count.fetch_add(1, Ordering::Relaxed);
                         }
-                        Err(_) => {
-                            drop_count.fetch_add(1, Ordering::Relaxed);
+                        Err(mpsc::error::TrySendError::Full(_)) => {
+                            channel_drops.fetch_add(1, Ordering::Relaxed);
+                            log_limited!(
+                                log,
+                                "queue_full",
+                                warn,
+                                frame_id,
+                                "Frame queue full, dropping frame"
+                            );
+                        }
+                        Err(mpsc::error::TrySendError::Closed(_)) => {
+                            channel_drops.fetch_add(1, Ordering::Relaxed);
+                            log_limited!(
+                                log,
+                                "closed",
+                                warn,
+                                frame_id,
+                                "Frame receiver gone, dropping frame"
+                            );
                         }
                     }
 
@@ -167,9 +406,11 @@ impl Capture {
         );
 
         // Start pipeline
-        pipeline
-            .set_state(gst::State::Playing)
-            .map_err(|e| CaptureError::StateChange(format!("{:?}", e)))?;
+        if let Err(e) = pipeline.set_state(gst::State::Playing) {
+            let error = self.start_error(&pipeline, e);
+            let _ = pipeline.set_state(gst::State::Null);
+            return Err(error);
+        }
 
         self.pipeline = Some(pipeline);
         self.app_sink = Some(app_sink);
@@ -191,23 +432,61 @@ impl Capture {
         self.is_running.store(false, Ordering::Relaxed);
 
         if let Some(pipeline) = self.pipeline.take() {
-            pipeline
-                .set_state(gst::State::Null)
-                .map_err(|e| CaptureError::StateChange(format!("{:?}", e)))?;
+            pipeline.set_state(gst::State::Null)?;
         }
 
         let stats = self.get_stats();
         info!(
             frames = %stats.frames_captured,
             dropped = %stats.frames_dropped,
+            pre_encode = %stats.dropped_pre_encode,
+            appsink = %stats.dropped_in_appsink,
+            channel = %stats.dropped_in_channel,
             "MJPEG capture stopped"
         );
 
         Ok(())
     }
 
+    /// Why the pipeline failed to start: a busy camera with its holders,
+    /// or the first error the pipeline posted
+    fn start_error(&self, pipeline: &gst::Pipeline, e: gst::StateChangeError) -> CaptureError {
+        let mut messages = Vec::new();
+        if let Some(bus) = pipeline.bus() {
+            while let Some(message) = bus.pop_filtered(&[gst::MessageType::Error]) {
+                if let gst::MessageView::Error(err) = message.view() {
+                    let busy = err.error().matches(gst::ResourceError::Busy);
+                    let message = match err.debug() {
+                        Some(debug) => format!("{} ({})", err.error(), debug),
+                        None => err.error().to_string(),
+                    };
+                    messages.push((busy, message));
+                }
+            }
+        }
+
+        if messages
+            .iter()
+            .any(|(busy, message)| *busy || is_busy_message(message))
+        {
+            let holders = device_holders(&self.config.device_path);
+            return CaptureError::Busy {
+                device: redact_device(&self.config.device_path),
+                detail: describe_holders(&holders),
+            };
+        }
+        match messages.into_iter().next() {
+            Some((_, message)) => CaptureError::Pipeline(message),
+            None => CaptureError::StateChange(e),
+        }
+    }
+
     /// Builds GStreamer pipeline string
     fn build_pipeline_string(&self) -> String {
+        if let Some(source) = NetworkSource::from_device(&self.config.device_path) {
+            return self.build_network_pipeline(source);
+        }
+
         let platform = platform::detect_platform();
 
         match platform {
@@ -217,6 +496,27 @@ impl Capture {
         }
     }
 
+    /// Builds IP camera pipeline (rtspsrc or souphttpsrc), decoded and
+    /// re-encoded
+    fn build_network_pipeline(&self, source: NetworkSource) -> String {
+        let mut pipeline = source.pipeline_head(
+            &self.config.device_path,
+            self.config.width,
+            self.config.height,
+            self.config.fps,
+        );
+
+        // Add flip if configured
+        if let Some(ref flip) = self.config.flip_method {
+            pipeline.push_str(&self.get_flip_element(flip));
+        }
+
+        // Encoding pipeline
+        pipeline.push_str(&self.encoding_tail(&self.jpeg_encoder_element()));
+
+        pipeline
+    }
+
     /// Builds macOS pipeline (avfvideosrc)
     fn build_macos_pipeline(&self) -> String {
         let mut pipeline = format!(
@@ -230,10 +530,7 @@ impl Capture {
         }
 
         // Encoding pipeline
-        pipeline.push_str(&format!(
-            " ! queue max-size-buffers=2 leaky=downstream ! videoconvert ! jpegenc quality={} ! appsink name=sink",
-            self.config.quality
-        ));
+        pipeline.push_str(&self.encoding_tail(&format!("jpegenc quality={}", self.config.quality)));
 
         pipeline
     }
@@ -254,14 +551,119 @@ impl Capture {
         }
 
         // Encoding pipeline
-        pipeline.push_str(&format!(
-            " ! queue max-size-buffers=2 leaky=downstream ! videoconvert ! jpegenc quality={} ! appsink name=sink",
-            self.config.quality
-        ));
+        pipeline.push_str(&self.encoding_tail(&self.jpeg_encoder_element()));
 
         pipeline
     }
 
+    /// Queue, colour conversion, encoder and appsink shared by all platforms,
+    /// with the configured fragments around them
+    ///
+    /// Everything after the queue runs on one streaming thread. When the
+    /// encoder and the appsink callback are pinned to different cores, a
+    /// second queue splits them onto separate threads.
+    fn encoding_tail(&self, encoder: &str) -> String {
+        let split = match (self.config.encoder_core, self.config.capture_core) {
+            (Some(enc), Some(cb)) => enc != cb,
+            _ => false,
+        };
+        // Overlay boxes and the timecode are painted into I420 frames between
+        // conversion and encoder, the timecode last so it stays readable
+        let mut paint = String::new();
+        if self.config.overlay.is_some() {
+            paint.push_str(" ! identity name=overlay");
+        }
+        if self.config.timecode {
+            paint.push_str(" ! identity name=timecode");
+        }
+        if !paint.is_empty() {
+            paint.insert_str(0, " ! video/x-raw,format=I420");
+        }
+        paint.push_str(&self.config.pipeline.before_encoder_link());
+        format!(
+            "{} ! queue name=preenc max-size-buffers=2 leaky=downstream ! {} ! {} name=enc{} \
+             ! appsink name=sink",
+            self.config.pipeline.after_source_link(),
+            self.videoconvert_element() + &paint,
+            encoder,
+            if split {
+                " ! queue max-size-buffers=1"
+            } else {
+                ""
+            }
+        )
+    }
+
+    /// Sets `pipeline.properties` on the built pipeline's elements
+    fn apply_property_overrides(&self, pipeline: &gst::Pipeline) -> Result<(), CaptureError> {
+        let overrides = &self.config.pipeline;
+        if overrides.properties.is_empty() {
+            return Ok(());
+        }
+
+        let mut matched = HashSet::new();
+        for element in pipeline.iterate_recurse().into_iter().flatten() {
+            let name = element.name().to_string();
+            let factory = element
+                .factory()
+                .map(|factory| factory.name().to_string())
+                .unwrap_or_default();
+            for key in [&name, &factory] {
+                if overrides.properties.contains_key(key) {
+                    matched.insert(key.clone());
+                }
+            }
+
+            for (property, value) in overrides.properties_for(&name, &factory) {
+                let invalid = |reason: &str| {
+                    CaptureError::Pipeline(format!("{}.{} = {}: {}", name, property, value, reason))
+                };
+                let pspec = element
+                    .find_property(property)
+                    .ok_or_else(|| invalid("no such property"))?;
+                if !pspec.flags().contains(gst::glib::ParamFlags::WRITABLE) {
+                    return Err(invalid("read-only"));
+                }
+                let parsed = gst::glib::Value::deserialize(&value.to_string(), pspec.value_type())
+                    .map_err(|_| invalid(&format!("not a valid {}", pspec.value_type())))?;
+                element.set_property_from_value(property, &parsed);
+                debug!(element = %name, property, value = %value, "Pipeline property overridden");
+            }
+        }
+
+        match overrides
+            .properties
+            .keys()
+            .find(|key| !matched.contains(*key))
+        {
+            Some(key) => Err(CaptureError::Pipeline(format!(
+                "pipeline.properties: no element named {}",
+                key
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// `videoconvert`, with a thread count when configured
+    fn videoconvert_element(&self) -> String {
+        if self.config.encoder_threads > 0 {
+            format!("videoconvert n-threads={}", self.config.encoder_threads)
+        } else {
+            "videoconvert".to_string()
+        }
+    }
+
+    /// JPEG encoder element for the configured encoder type
+    fn jpeg_encoder_element(&self) -> String {
+        match self.config.encoder {
+            JpegEncoder::Software => format!("jpegenc quality={}", self.config.quality),
+            JpegEncoder::Hardware => format!(
+                "v4l2jpegenc extra-controls=\"c,compression_quality={}\"",
+                self.config.quality
+            ),
+        }
+    }
+
     /// Builds generic Linux pipeline (v4l2src)
     fn build_generic_linux_pipeline(&self) -> String {
         let mut pipeline = format!(
@@ -275,10 +677,7 @@ impl Capture {
         }
 
         // Encoding pipeline
-        pipeline.push_str(&format!(
-            " ! queue max-size-buffers=2 leaky=downstream ! videoconvert ! jpegenc quality={} ! appsink name=sink",
-            self.config.quality
-        ));
+        pipeline.push_str(&self.encoding_tail(&format!("jpegenc quality={}", self.config.quality)));
 
         pipeline
     }
@@ -298,11 +697,46 @@ impl Capture {
         }
     }
 
+    /// Changes JPEG quality on the running encoder
+    pub fn set_quality(&mut self, quality: u32) -> Result<(), CaptureError> {
+        let encoder = self
+            .pipeline
+            .as_ref()
+            .and_then(|p| p.by_name("enc"))
+            .ok_or(CaptureError::NotRunning)?;
+
+        let factory = encoder.factory().map(|f| f.name().to_string());
+        match factory.as_deref() {
+            Some("v4l2jpegenc") => {
+                let controls = gst::Structure::builder("c")
+                    .field("compression_quality", quality as i32)
+                    .build();
+                encoder.set_property("extra-controls", controls);
+            }
+            _ => encoder.set_property("quality", quality as i32),
+        }
+
+        self.config.quality = quality;
+        debug!(quality, "JPEG quality updated");
+        Ok(())
+    }
+
     /// Gets capture statistics
     pub fn get_stats(&self) -> CaptureStats {
+        let frames_captured = self.frame_count.load(Ordering::Relaxed);
+        let dropped_in_channel = self.channel_drops.load(Ordering::Relaxed);
+        let dropped_pre_encode = self.pre_encode_drops.load(Ordering::Relaxed);
+        // Every pulled frame was either captured or dropped in the channel
+        let dropped_in_appsink = self
+            .sink_arrivals
+            .load(Ordering::Relaxed)
+            .saturating_sub(frames_captured + dropped_in_channel);
         CaptureStats {
-            frames_captured: self.frame_count.load(Ordering::Relaxed),
-            frames_dropped: self.drop_count.load(Ordering::Relaxed),
+            frames_captured,
+            frames_dropped: dropped_pre_encode + dropped_in_appsink + dropped_in_channel,
+            dropped_pre_encode,
+            dropped_in_appsink,
+            dropped_in_channel,
             is_running: self.is_running.load(Ordering::Relaxed),
         }
     }
@@ -321,3 +755,27 @@ impl Drop for Capture {
         }
     }
 }
+
+/// Pad probe body running `paint(luma, stride, width, height)` on the Y
+/// plane of I420 buffers
+fn paint_luma(
+    pad: &gst::Pad,
+    info: &mut gst::PadProbeInfo,
+    paint: impl FnOnce(&mut [u8], usize, usize, usize),
+) -> gst::PadProbeReturn {
+    let size = pad.current_caps().and_then(|caps| {
+        let s = caps.structure(0)?;
+        Some((s.get::<i32>("width").ok()?, s.get::<i32>("height").ok()?))
+    });
+    if let (Some((width, height)), Some(gst::PadProbeData::Buffer(ref mut buffer))) =
+        (size, &mut info.data)
+    {
+        let (width, height) = (width as usize, height as usize);
+        // Default I420 layout: the Y plane comes first, rows padded to 4 bytes
+        let stride = (width + 3) & !3;
+        if let Ok(mut map) = buffer.make_mut().map_writable() {
+            paint(map.as_mut_slice(), stride, width, height);
+        }
+    }
+    gst::PadProbeReturn::Ok
+}
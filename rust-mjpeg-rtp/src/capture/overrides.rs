@@ -0,0 +1,118 @@
+//! Site-specific additions to the capture pipeline
+//!
+//! Set under `[mjpeg-rtp.cameraN.pipeline]`: gst-launch fragments spliced in
+//! at fixed points, and property values applied to elements once the
+//! pipeline is built. Lets a site add a denoiser or tune `jpegenc` without
+//! patching the pipeline builders.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Extra elements and property overrides for one camera's pipeline
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PipelineOverrides {
+    /// Fragment run on raw frames straight after the source (and flip),
+    /// e.g. `"videoconvert ! bilateral"`
+    #[serde(default)]
+    pub after_source: Option<String>,
+
+    /// Fragment run on converted (and painted) frames right before the
+    /// JPEG encoder
+    #[serde(default)]
+    pub before_encoder: Option<String>,
+
+    /// Property values by element name or factory name, e.g.
+    /// `jpegenc = { idct-method = "float" }`. `enc` is the encoder whatever
+    /// its factory.
+    #[serde(default)]
+    pub properties: BTreeMap<String, BTreeMap<String, PropertyValue>>,
+}
+
+impl PipelineOverrides {
+    /// `after_source` as a link to append to the source chain
+    pub fn after_source_link(&self) -> String {
+        link(self.after_source.as_deref())
+    }
+
+    /// `before_encoder` as a link to append to the conversion chain
+    pub fn before_encoder_link(&self) -> String {
+        link(self.before_encoder.as_deref())
+    }
+
+    /// Properties for an element, by its name and factory; the name's
+    /// values win where both set the same property
+    pub fn properties_for(&self, name: &str, factory: &str) -> BTreeMap<&str, &PropertyValue> {
+        let mut properties = BTreeMap::new();
+        for key in [factory, name] {
+            for (property, value) in self.properties.get(key).into_iter().flatten() {
+                properties.insert(property.as_str(), value);
+            }
+        }
+        properties
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.after_source.is_none() && self.before_encoder.is_none() && self.properties.is_empty()
+    }
+}
+
+fn link(fragment: Option<&str>) -> String {
+    match fragment.map(str::trim) {
+        Some(fragment) if !fragment.is_empty() => format!(" ! {}", fragment),
+        _ => String::new(),
+    }
+}
+
+/// A property value as written in the config; GStreamer parses its text
+/// for the property's type, so enums take their nick (`"float"`)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum PropertyValue {
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    String(String),
+}
+
+impl fmt::Display for PropertyValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PropertyValue::Bool(value) => write!(f, "{}", value),
+            PropertyValue::Int(value) => write!(f, "{}", value),
+            PropertyValue::Float(value) => write!(f, "{}", value),
+            PropertyValue::String(value) => f.write_str(value),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_overrides() {
+        let overrides: PipelineOverrides = toml::from_str(
+            r#"
+after_source = "videoconvert ! bilateral "
+before_encoder = "  "
+
+[properties]
+jpegenc = { idct-method = "float", quality = 70 }
+enc = { quality = 90 }
+videoconvert = { dither = 0, qos = false }
+"#,
+        )
+        .unwrap();
+        assert_eq!(overrides.after_source_link(), " ! videoconvert ! bilateral");
+        assert_eq!(overrides.before_encoder_link(), "");
+
+        let encoder = overrides.properties_for("enc", "jpegenc");
+        assert_eq!(encoder["idct-method"].to_string(), "float");
+        assert_eq!(encoder["quality"], &PropertyValue::Int(90));
+        let convert = overrides.properties_for("videoconvert0", "videoconvert");
+        assert_eq!(convert["qos"].to_string(), "false");
+        assert!(overrides.properties_for("sink", "appsink").is_empty());
+        assert!(PipelineOverrides::default().is_empty());
+    }
+}
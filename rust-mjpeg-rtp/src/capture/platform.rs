@@ -1,5 +1,6 @@
 //! Platform detection for camera sources
 
+use serde::{Deserialize, Serialize};
 use std::env;
 
 /// Platform information
@@ -40,6 +41,115 @@ fn is_raspberry_pi() -> bool {
         || std::path::Path::new("/sys/firmware/devicetree/base/model").exists()
 }
 
+/// Raspberry Pi board model, from the device-tree model string
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PiModel {
+    Zero2,
+    Pi3,
+    Pi4,
+    Pi5,
+    /// Not a Pi, or a model without a dedicated preset
+    Other,
+}
+
+/// JPEG encoder element used by the capture pipeline
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JpegEncoder {
+    /// `jpegenc` (libjpeg, CPU)
+    Software,
+    /// `v4l2jpegenc` (VideoCore hardware block, Pi 4 and older)
+    Hardware,
+}
+
+/// Per-model defaults so one config file works across a mixed fleet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ModelPreset {
+    pub model: PiModel,
+    pub encoder: JpegEncoder,
+    /// Resolution ceiling; larger configured sizes are scaled down.
+    /// Unset outside the known Pi models
+    pub max_width: Option<u32>,
+    pub max_height: Option<u32>,
+    /// Worker threads for colour conversion (`videoconvert n-threads`)
+    pub encoder_threads: u32,
+}
+
+impl PiModel {
+    /// Parses a device-tree model string such as
+    /// "Raspberry Pi 4 Model B Rev 1.4"
+    pub fn from_model_string(model: &str) -> Self {
+        let model = model.trim_end_matches('\0').trim();
+        if model.starts_with("Raspberry Pi Zero 2") {
+            PiModel::Zero2
+        } else if model.starts_with("Raspberry Pi 3") {
+            PiModel::Pi3
+        } else if model.starts_with("Raspberry Pi 4")
+            || model.starts_with("Raspberry Pi Compute Module 4")
+        {
+            PiModel::Pi4
+        } else if model.starts_with("Raspberry Pi 5")
+            || model.starts_with("Raspberry Pi Compute Module 5")
+        {
+            PiModel::Pi5
+        } else {
+            PiModel::Other
+        }
+    }
+
+    /// Default encoder, resolution ceiling and thread count for this model
+    pub fn preset(self) -> ModelPreset {
+        let (encoder, ceiling, encoder_threads) = match self {
+            // 512 MB RAM, 4 slow cores: keep it small and let the HW block work
+            PiModel::Zero2 => (JpegEncoder::Hardware, Some((1280, 720)), 1),
+            PiModel::Pi3 => (JpegEncoder::Hardware, Some((1280, 720)), 2),
+            PiModel::Pi4 => (JpegEncoder::Hardware, Some((1920, 1080)), 2),
+            // The Pi 5 dropped the hardware JPEG encoder, but its A76 cores cope
+            PiModel::Pi5 => (JpegEncoder::Software, Some((1920, 1080)), 3),
+            // Laptops and x86 boxes: nothing known to size a ceiling by
+            PiModel::Other => (JpegEncoder::Software, None, 0),
+        };
+        ModelPreset {
+            model: self,
+            encoder,
+            max_width: ceiling.map(|(width, _)| width),
+            max_height: ceiling.map(|(_, height)| height),
+            encoder_threads,
+        }
+    }
+}
+
+/// Detects the Raspberry Pi model from the device tree
+pub fn detect_pi_model() -> PiModel {
+    [
+        "/proc/device-tree/model",
+        "/sys/firmware/devicetree/base/model",
+    ]
+    .iter()
+    .find_map(|path| std::fs::read_to_string(path).ok())
+    .map(|model| PiModel::from_model_string(&model))
+    .unwrap_or(PiModel::Other)
+}
+
+impl ModelPreset {
+    /// Scales `width`x`height` down to fit the ceiling, keeping the aspect
+    /// ratio and rounding to multiples of 8
+    pub fn clamp_resolution(&self, width: u32, height: u32) -> (u32, u32) {
+        let max_width = self.max_width.unwrap_or(u32::MAX);
+        let max_height = self.max_height.unwrap_or(u32::MAX);
+        if width <= max_width && height <= max_height {
+            return (width, height);
+        }
+        let scale = f64::min(
+            max_width as f64 / width as f64,
+            max_height as f64 / height as f64,
+        );
+        let round8 = |v: f64| ((v as u32) / 8 * 8).max(8);
+        (round8(width as f64 * scale), round8(height as f64 * scale))
+    }
+}
+
 /// Gets platform-specific camera device path format
 pub fn default_device_path(platform: PlatformInfo, camera_index: usize) -> String {
     match platform {
@@ -88,6 +198,50 @@ mod tests {
         assert_eq!(path, "/dev/video1");
     }
 
+    #[test]
+    fn test_pi_model_from_string() {
+        assert_eq!(
+            PiModel::from_model_string("Raspberry Pi Zero 2 W Rev 1.0\0"),
+            PiModel::Zero2
+        );
+        assert_eq!(
+            PiModel::from_model_string("Raspberry Pi 3 Model B Plus Rev 1.3"),
+            PiModel::Pi3
+        );
+        assert_eq!(
+            PiModel::from_model_string("Raspberry Pi 4 Model B Rev 1.4"),
+            PiModel::Pi4
+        );
+        assert_eq!(
+            PiModel::from_model_string("Raspberry Pi 5 Model B Rev 1.0\0"),
+            PiModel::Pi5
+        );
+        assert_eq!(PiModel::from_model_string("Generic x86"), PiModel::Other);
+    }
+
+    #[test]
+    fn test_pi5_uses_software_encoder() {
+        assert_eq!(PiModel::Pi5.preset().encoder, JpegEncoder::Software);
+        assert_eq!(PiModel::Pi4.preset().encoder, JpegEncoder::Hardware);
+    }
+
+    #[test]
+    fn test_clamp_resolution() {
+        let preset = PiModel::Zero2.preset();
+        assert_eq!(preset.clamp_resolution(640, 480), (640, 480));
+        assert_eq!(preset.clamp_resolution(1920, 1080), (1280, 720));
+
+        let (w, h) = preset.clamp_resolution(1640, 1232);
+        assert!(w <= 1280 && h <= 720);
+        assert_eq!((w % 8, h % 8), (0, 0));
+
+        // No ceiling off a Pi
+        assert_eq!(
+            PiModel::Other.preset().clamp_resolution(3840, 2160),
+            (3840, 2160)
+        );
+    }
+
     #[test]
     fn test_default_device_path_pi() {
         let path = default_device_path(PlatformInfo::RaspberryPi, 0);
@@ -0,0 +1,456 @@
+//! Recordings triggered by analytics events
+//!
+//! Instead of recording continuously, a [`ClipRecorder`] keeps the last
+//! `pre_roll_ms` of frames in memory and starts a `.frames` clip when a
+//! frame processor publishes an event about its camera, such as a detection
+//! or a WASM plugin's motion report. Every further event extends the clip to
+//! `post_roll_ms` after it; once that passes without one, the clip is closed
+//! and a JSON file next to it records the event that started it and those
+//! that followed. Clips are split at `max_clip_secs` so that constant
+//! activity doesn't produce one unbounded file.
+//!
+//! Clips are named `<camera>-<start ms since the Unix epoch>-<first frame
+//! id>.frames` and play back with `replay` like any recording. The captured
+//! JPEG nearest the trigger's frame is kept as the clip's `<clip>.jpg`
+//! thumbnail; [`nearest_frame`] pulls out the one for any later event.
+
+use crate::config::EventRecordingConfig;
+use crate::events::{AnalyticsEvent, EventBus};
+use crate::frame::Frame;
+use crate::recording::{FrameReader, RecordedFrame, Recorder, RecordingError};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::broadcast::{self, error::TryRecvError};
+use tracing::{info, warn};
+
+/// Events kept in a clip's metadata after the trigger; later ones still
+/// extend the clip
+const MAX_CLIP_EVENTS: usize = 100;
+
+/// Sidecar written as `<clip>.json` when a clip is closed
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ClipMetadata {
+    pub camera: String,
+    /// The `.frames` file
+    pub clip: String,
+    /// The `.jpg` of the frame nearest the trigger
+    #[serde(default)]
+    pub thumbnail: Option<String>,
+    /// Wall-clock start and end (ms since the Unix epoch)
+    pub started_ms: u64,
+    pub ended_ms: u64,
+    /// Frames in the clip, pre-roll included
+    pub frames: u64,
+    /// The event that started the clip
+    pub trigger: AnalyticsEvent,
+    /// Events during the clip, after the trigger
+    pub events: Vec<AnalyticsEvent>,
+}
+
+/// A clip being written
+struct Clip {
+    recorder: Recorder,
+    path: PathBuf,
+    started: Instant,
+    /// Capture time after which the clip ends, absent further events
+    until: Instant,
+    /// Frame nearest the trigger so far
+    thumbnail: Option<Frame>,
+    metadata: ClipMetadata,
+}
+
+impl Clip {
+    fn record(&mut self, frame: &Frame) {
+        let target = self.metadata.trigger.frame_id;
+        if self
+            .thumbnail
+            .as_ref()
+            .is_none_or(|thumb| frame.id.abs_diff(target) < thumb.id.abs_diff(target))
+        {
+            self.thumbnail = Some(frame.clone());
+        }
+        self.recorder.record(frame);
+    }
+
+    /// Waits for the clip to be written out, then writes its metadata
+    fn close(mut self) -> Result<ClipMetadata, RecordingError> {
+        self.metadata.frames = self.recorder.finish()?;
+        self.metadata.ended_ms = now_ms();
+        if let Some(thumb) = self.thumbnail.take() {
+            let path = self.path.with_extension("jpg");
+            std::fs::write(&path, &thumb.data)?;
+            self.metadata.thumbnail = Some(path.to_string_lossy().into_owned());
+        }
+        let json = serde_json::to_vec_pretty(&self.metadata).map_err(std::io::Error::other)?;
+        std::fs::write(self.path.with_extension("json"), json)?;
+        info!(
+            camera = %self.metadata.camera,
+            clip = %self.path.display(),
+            frames = self.metadata.frames,
+            events = self.metadata.events.len() + 1,
+            "Event clip finished"
+        );
+        Ok(self.metadata)
+    }
+}
+
+/// Records a camera's frames around analytics events; see the
+/// [module docs](self)
+pub struct ClipRecorder {
+    camera: String,
+    dir: PathBuf,
+    pre_roll: Duration,
+    post_roll: Duration,
+    max_clip: Duration,
+    sources: Vec<String>,
+    events: broadcast::Receiver<AnalyticsEvent>,
+    buffer: VecDeque<Frame>,
+    clip: Option<Clip>,
+}
+
+impl ClipRecorder {
+    /// Creates `dir` and starts watching `events` for `camera`
+    pub fn new(
+        config: &EventRecordingConfig,
+        dir: &str,
+        camera: &str,
+        events: &EventBus,
+    ) -> Result<Self, RecordingError> {
+        std::fs::create_dir_all(dir)?;
+        Ok(Self {
+            camera: camera.to_string(),
+            dir: PathBuf::from(dir),
+            pre_roll: Duration::from_millis(config.pre_roll_ms),
+            post_roll: Duration::from_millis(config.post_roll_ms),
+            max_clip: Duration::from_secs(config.max_clip_secs),
+            sources: config.sources.clone(),
+            events: events.subscribe(),
+            buffer: VecDeque::new(),
+            clip: None,
+        })
+    }
+
+    /// Whether a clip is being written
+    pub fn is_recording(&self) -> bool {
+        self.clip.is_some()
+    }
+
+    /// Takes the next frame: written to the open clip, or kept as pre-roll.
+    /// Never blocks; clips are written and closed on other threads.
+    pub fn record(&mut self, frame: &Frame) {
+        let now = frame.captured_at;
+        loop {
+            match self.events.try_recv() {
+                Ok(event) if self.triggers(&event) => self.trigger(event, frame),
+                Ok(_) | Err(TryRecvError::Lagged(_)) => {}
+                Err(TryRecvError::Empty | TryRecvError::Closed) => break,
+            }
+        }
+
+        let Some(clip) = self.clip.as_mut() else {
+            self.buffer.push_back(frame.clone());
+            while self.buffer.front().is_some_and(|oldest| {
+                now.saturating_duration_since(oldest.captured_at) > self.pre_roll
+            }) {
+                self.buffer.pop_front();
+            }
+            return;
+        };
+        if now > clip.until {
+            self.close_in_background();
+            self.buffer.push_back(frame.clone());
+            return;
+        }
+        if now.saturating_duration_since(clip.started) >= self.max_clip {
+            // Carry on in a new clip, started by the latest event
+            let clip = self.clip.take().expect("clip is open");
+            let trigger = clip
+                .metadata
+                .events
+                .last()
+                .unwrap_or(&clip.metadata.trigger)
+                .clone();
+            let until = clip.until;
+            self.close_clip(clip);
+            self.open(trigger, frame, until);
+            if self.clip.is_none() {
+                return;
+            }
+        }
+        if let Some(ref mut clip) = self.clip {
+            clip.record(frame);
+        }
+    }
+
+    /// Closes the open clip, if any, and waits for it to be written
+    pub fn finish(mut self) -> Result<Option<ClipMetadata>, RecordingError> {
+        self.clip.take().map(Clip::close).transpose()
+    }
+
+    fn triggers(&self, event: &AnalyticsEvent) -> bool {
+        event.camera == self.camera
+            && (self.sources.is_empty() || self.sources.contains(&event.source))
+    }
+
+    fn trigger(&mut self, event: AnalyticsEvent, frame: &Frame) {
+        let until = frame.captured_at + self.post_roll;
+        match self.clip {
+            Some(ref mut clip) => {
+                clip.until = clip.until.max(until);
+                if clip.metadata.events.len() < MAX_CLIP_EVENTS {
+                    clip.metadata.events.push(event);
+                }
+            }
+            None => self.open(event, frame, until),
+        }
+    }
+
+    /// Starts a clip with the buffered pre-roll, ahead of `frame`
+    fn open(&mut self, trigger: AnalyticsEvent, frame: &Frame, until: Instant) {
+        let started_ms = now_ms();
+        let name = format!("{}-{}-{}.frames", self.camera, started_ms, frame.id);
+        let path = self.dir.join(name);
+        let pre_roll: Vec<Frame> = self.buffer.drain(..).collect();
+        let thumbnail = pre_roll
+            .iter()
+            .min_by_key(|buffered| buffered.id.abs_diff(trigger.frame_id))
+            .cloned();
+        match Recorder::start_with(&path, pre_roll) {
+            Ok(recorder) => {
+                info!(
+                    camera = %self.camera,
+                    clip = %path.display(),
+                    source = %trigger.source,
+                    "Event clip started"
+                );
+                self.clip = Some(Clip {
+                    recorder,
+                    metadata: ClipMetadata {
+                        camera: self.camera.clone(),
+                        clip: path.to_string_lossy().into_owned(),
+                        thumbnail: None,
+                        started_ms,
+                        ended_ms: 0,
+                        frames: 0,
+                        trigger,
+                        events: Vec::new(),
+                    },
+                    path,
+                    started: frame.captured_at,
+                    until,
+                    thumbnail,
+                });
+            }
+            Err(e) => {
+                warn!(
+                    camera = %self.camera,
+                    clip = %path.display(),
+                    error = %e,
+                    "Cannot start clip"
+                );
+            }
+        }
+    }
+
+    fn close_in_background(&mut self) {
+        if let Some(clip) = self.clip.take() {
+            self.close_clip(clip);
+        }
+    }
+
+    fn close_clip(&self, clip: Clip) {
+        let camera = self.camera.clone();
+        let spawned = std::thread::Builder::new()
+            .name("clip-writer".to_string())
+            .spawn(move || {
+                if let Err(e) = clip.close() {
+                    warn!(camera = %camera, error = %e, "Event clip incomplete");
+                }
+            });
+        if let Err(e) = spawned {
+            warn!(camera = %self.camera, error = %e, "Cannot close event clip");
+        }
+    }
+}
+
+impl Drop for ClipRecorder {
+    fn drop(&mut self) {
+        if let Some(clip) = self.clip.take() {
+            if let Err(e) = clip.close() {
+                warn!(camera = %self.camera, error = %e, "Event clip incomplete");
+            }
+        }
+    }
+}
+
+/// Metadata of the closed clips in `dir`, oldest first
+pub fn list(dir: &Path) -> Result<Vec<ClipMetadata>, RecordingError> {
+    let mut clips = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == "json") {
+            match serde_json::from_slice(&std::fs::read(&path)?) {
+                Ok(clip) => clips.push(clip),
+                Err(e) => warn!(path = %path.display(), error = %e, "Skipping clip metadata"),
+            }
+        }
+    }
+    clips.sort_by_key(|clip: &ClipMetadata| clip.started_ms);
+    Ok(clips)
+}
+
+/// The frame of a clip (or any recording) whose id is closest to `id`
+pub fn nearest_frame<P: AsRef<Path>>(
+    path: P,
+    id: u64,
+) -> Result<Option<RecordedFrame>, RecordingError> {
+    let mut nearest: Option<RecordedFrame> = None;
+    for frame in FrameReader::open(path)? {
+        let frame = frame?;
+        match nearest {
+            Some(ref best) if best.id.abs_diff(id) <= frame.id.abs_diff(id) => {
+                // Ids only grow, so nothing later is closer
+                if frame.id > id {
+                    break;
+                }
+            }
+            _ => nearest = Some(frame),
+        }
+    }
+    Ok(nearest)
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    use serde_json::json;
+
+    fn config(pre_roll_ms: u64, post_roll_ms: u64, max_clip_secs: u64) -> EventRecordingConfig {
+        EventRecordingConfig {
+            dir: None,
+            pre_roll_ms,
+            post_roll_ms,
+            max_clip_secs,
+            sources: vec!["people".to_string()],
+        }
+    }
+
+    /// Frame `id` captured `id * interval` after `start`
+    fn frame(start: Instant, id: u64, interval: Duration) -> Frame {
+        Frame {
+            captured_at: start + interval * id as u32,
+            ..Frame::new(id, Bytes::from(vec![id as u8; 16]))
+        }
+    }
+
+    fn event(camera: &str, source: &str, frame_id: u64) -> AnalyticsEvent {
+        AnalyticsEvent::new(camera, source, frame_id, json!({"count": 1}))
+    }
+
+    fn ids(path: &str) -> Vec<u64> {
+        FrameReader::open(path)
+            .unwrap()
+            .map(|frame| frame.unwrap().id)
+            .collect()
+    }
+
+    /// Metadata of the clips closed so far, oldest first
+    fn closed_clips(dir: &Path, count: usize) -> Vec<ClipMetadata> {
+        for _ in 0..500 {
+            let mut clips: Vec<ClipMetadata> = std::fs::read_dir(dir)
+                .unwrap()
+                .map(|entry| entry.unwrap().path())
+                .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+                .filter_map(|path| serde_json::from_slice(&std::fs::read(path).ok()?).ok())
+                .collect();
+            if clips.len() >= count {
+                clips.sort_by_key(|clip| ids(&clip.clip)[0]);
+                return clips;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        panic!("fewer than {} clips closed", count);
+    }
+
+    #[test]
+    fn test_clip_around_event() {
+        let dir = tempfile::tempdir().unwrap();
+        let events = EventBus::new();
+        let path = dir.path().to_str().unwrap();
+        let mut clips =
+            ClipRecorder::new(&config(100, 200, 300), path, "camera1", &events).unwrap();
+        let (start, interval) = (Instant::now(), Duration::from_millis(50));
+
+        for id in 0..10 {
+            clips.record(&frame(start, id, interval));
+        }
+        assert!(!clips.is_recording());
+        // Ignored: another camera, a source not listed
+        events.publish(event("camera2", "people", 0));
+        events.publish(event("camera1", "motion", 0));
+        clips.record(&frame(start, 10, interval));
+        assert!(!clips.is_recording());
+
+        events.publish(event("camera1", "people", 9));
+        for id in 11..20 {
+            clips.record(&frame(start, id, interval));
+        }
+        assert!(!clips.is_recording());
+        assert!(clips.finish().unwrap().is_none());
+
+        let clip = &closed_clips(dir.path(), 1)[0];
+        // 100ms of pre-roll, the frame that saw the event and 200ms after it
+        assert_eq!(ids(&clip.clip), (8..=15).collect::<Vec<_>>());
+        assert_eq!(clip.frames, 8);
+        assert_eq!(clip.camera, "camera1");
+        assert_eq!(clip.trigger.source, "people");
+        assert!(clip.events.is_empty());
+        assert!(clip.ended_ms >= clip.started_ms);
+        // The trigger's frame, from the pre-roll
+        let thumbnail = std::fs::read(clip.thumbnail.as_ref().unwrap()).unwrap();
+        assert_eq!(thumbnail, vec![9u8; 16]);
+        let nearest = nearest_frame(&clip.clip, 12).unwrap().unwrap();
+        assert_eq!(nearest.id, 12);
+        assert_eq!(nearest_frame(&clip.clip, 100).unwrap().unwrap().id, 15);
+        assert_eq!(nearest_frame(&clip.clip, 0).unwrap().unwrap().id, 8);
+        assert_eq!(list(dir.path()).unwrap(), vec![clip.clone()]);
+    }
+
+    #[test]
+    fn test_events_extend_and_split_clips() {
+        let dir = tempfile::tempdir().unwrap();
+        let events = EventBus::new();
+        let path = dir.path().to_str().unwrap();
+        let mut clips = ClipRecorder::new(&config(0, 500, 1), path, "camera1", &events).unwrap();
+        let (start, interval) = (Instant::now(), Duration::from_millis(400));
+
+        events.publish(event("camera1", "people", 0));
+        clips.record(&frame(start, 0, interval));
+        clips.record(&frame(start, 1, interval));
+        events.publish(event("camera1", "people", 0));
+        clips.record(&frame(start, 2, interval));
+        // 1.2s in: split into a second clip
+        clips.record(&frame(start, 3, interval));
+        assert!(clips.is_recording());
+        let second = clips.finish().unwrap().unwrap();
+
+        let first = &closed_clips(dir.path(), 2)[0];
+        assert_eq!(ids(&first.clip), vec![0, 1, 2]);
+        assert_eq!(first.events.len(), 1);
+        assert_eq!(ids(&second.clip), vec![3]);
+        assert_eq!(second.trigger, first.events[0]);
+        // Nothing nearer the trigger than the clip's own first frame
+        let thumbnail = std::fs::read(second.thumbnail.unwrap()).unwrap();
+        assert_eq!(thumbnail, vec![3u8; 16]);
+    }
+}
@@ -1,7 +1,14 @@
 //! Configuration management for MJPEG-RTP streaming
 
+use crate::capture::{validate_device, JpegEncoder, ModelPreset, PiModel, PipelineOverrides};
+use crate::congestion::ControllerKind;
+use crate::error::ErrorCode;
+use crate::realtime;
+use crate::rtp::{QTablePolicy, TimestampSource, MAX_DIMENSION};
+use crate::streamer::{KeepaliveKind, DEFAULT_DNS_REFRESH, DEFAULT_PARALLEL_PACKETIZE_BYTES};
 use serde::{Deserialize, Serialize};
-use std::path::Path;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -16,11 +23,60 @@ pub enum ConfigError {
     Invalid(String),
 }
 
+impl ConfigError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            ConfigError::Io(_) => ErrorCode::Io,
+            ConfigError::Parse(_) | ConfigError::Invalid(_) => ErrorCode::InvalidConfig,
+        }
+    }
+}
+
 /// Complete MJPEG-RTP configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
     #[serde(default, rename = "mjpeg-rtp")]
     pub mjpeg_rtp: MjpegRtpConfig,
+
+    /// OpenTelemetry export (requires the `otel` feature)
+    #[serde(default)]
+    pub telemetry: TelemetryConfig,
+}
+
+/// OpenTelemetry (OTLP) export configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TelemetryConfig {
+    /// Export traces and metrics via OTLP
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// OTLP/gRPC collector endpoint
+    #[serde(default = "default_otlp_endpoint")]
+    pub otlp_endpoint: String,
+
+    /// `service.name` resource attribute
+    #[serde(default = "default_service_name")]
+    pub service_name: String,
+
+    /// Optional `service.instance.id` (e.g. hostname of the Pi)
+    #[serde(default)]
+    pub instance_id: Option<String>,
+
+    /// Metric export interval (seconds)
+    #[serde(default = "default_metrics_interval")]
+    pub metrics_interval_seconds: u64,
+}
+
+impl Default for TelemetryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            otlp_endpoint: default_otlp_endpoint(),
+            service_name: default_service_name(),
+            instance_id: None,
+            metrics_interval_seconds: default_metrics_interval(),
+        }
+    }
 }
 
 /// MJPEG-RTP streaming configuration
@@ -38,17 +94,552 @@ pub struct MjpegRtpConfig {
     #[serde(default)]
     pub camera2: CameraConfig,
 
-    /// Maximum transmission unit (bytes)
+    /// Maximum transmission unit (bytes). `network.mtu` takes precedence.
     #[serde(default = "default_mtu")]
     pub mtu: usize,
 
-    /// DSCP value for QoS (0-63)
+    /// DSCP value for QoS (0-63). `network.dscp` / `network.tos` take precedence.
     #[serde(default)]
     pub dscp: u8,
 
+    /// Socket settings shared by all cameras
+    #[serde(default)]
+    pub network: NetworkConfig,
+
+    /// Send both cameras from one local socket to one destination port, told
+    /// apart by SSRC and described by a single SDP with two m-lines (BUNDLE,
+    /// RFC 8843). Needs matching `dest_host`, `dest_port`, `local_port` and
+    /// `rtcp_mux = true` on both cameras.
+    #[serde(default)]
+    pub bundle: bool,
+
     /// Statistics reporting interval (seconds)
     #[serde(default = "default_stats_interval")]
     pub stats_interval_seconds: u64,
+
+    /// Overrides for the per-board presets
+    #[serde(default)]
+    pub platform: PlatformConfig,
+
+    /// Allow frames wider or taller than 2040 px. RFC 2435 can't encode
+    /// them, so the header dimension fields are sent as 0 and the receiver
+    /// must use the SDP `a=x-dimensions` attribute instead.
+    #[serde(default)]
+    pub oversize_dimensions: bool,
+
+    /// Frames of at least this many bytes (4K MJPEG is 1-2 MB) are
+    /// packetized in chunks on the blocking pool while earlier chunks are
+    /// sent, instead of all before the first send. 0 turns it off.
+    #[serde(default = "default_parallel_packetize_bytes")]
+    pub parallel_packetize_bytes: usize,
+
+    /// Stamp every RTP packet with an RFC 8285 header extension carrying the
+    /// frame id and the CRC32 of its scan data, under this extension ID
+    /// (1-14), so receivers can verify reassembly. Off when unset.
+    #[serde(default)]
+    pub frame_info_extension: Option<u8>,
+
+    /// How quantization tables are sent: `always` (in every frame) or
+    /// `cached` (when they change or every `qtable_refresh_frames` frames,
+    /// for receivers to cache; saves about 130 bytes per frame)
+    #[serde(default)]
+    pub qtable_policy: QTablePolicy,
+
+    /// Frames between resending unchanged tables under `cached`, so
+    /// receivers that join late or lost them recover (0 = only on change)
+    #[serde(default = "default_qtable_refresh_frames")]
+    pub qtable_refresh_frames: u32,
+
+    /// What RTP timestamps are derived from: `frame_count` (nominal frame
+    /// interval), `pts` (capture buffer PTS) or `wallclock` (capture time)
+    #[serde(default)]
+    pub timestamp_source: TimestampSource,
+
+    /// Slowly steer `frame_count` timestamps towards the capture clock, so a
+    /// camera running slightly off its nominal rate (29.97 vs 30 fps) stays
+    /// in sync over hours. The drift is measured and reported either way.
+    #[serde(default = "default_drift_correction")]
+    pub drift_correction: bool,
+
+    /// Congestion control / adaptive JPEG quality
+    #[serde(default)]
+    pub congestion: CongestionConfig,
+
+    /// Outgoing bitrate limits, so video can't crowd out other traffic on
+    /// the link
+    #[serde(default)]
+    pub shaping: ShapingConfig,
+
+    /// Directory holding the persisted per-camera UUIDs
+    #[serde(default = "default_state_dir")]
+    pub state_dir: String,
+
+    /// Save each camera's SSRC, sequence number and timestamp under
+    /// `state_dir` when it stops and continue from them on the next start,
+    /// so receivers see a restart as a gap rather than a new stream
+    #[serde(default)]
+    pub resume_rtp_state: bool,
+
+    /// Longest a camera may have been stopped for its saved RTP state to be
+    /// used (seconds); after that it starts a fresh sequence
+    #[serde(default = "default_resume_max_gap_secs")]
+    pub resume_max_gap_secs: u64,
+
+    /// Address of the HTTP control API (adding RTP destinations at runtime,
+    /// stats), e.g. "127.0.0.1:8090". Off when unset.
+    #[serde(default)]
+    pub api_listen: Option<SocketAddr>,
+
+    /// Announcing the streams and their identities over mDNS/DNS-SD
+    #[serde(default)]
+    pub mdns: MdnsConfig,
+
+    /// Leader election between redundant instances
+    #[serde(default)]
+    pub coordination: CoordinationConfig,
+
+    /// Repeating a received RTP/JPEG stream to other destinations
+    #[serde(default)]
+    pub relay: RelayConfig,
+
+    /// Re-encoding old recordings to H.265 in the background
+    #[serde(default)]
+    pub transcode: TranscodeConfig,
+}
+
+/// Socket and packetizer settings
+///
+/// Set under `[mjpeg-rtp.network]` for every camera and under
+/// `[mjpeg-rtp.cameraN.network]` to override single keys for one camera.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct NetworkConfig {
+    /// Maximum transmission unit (bytes)
+    #[serde(default)]
+    pub mtu: Option<usize>,
+
+    /// DSCP value for QoS (0-63)
+    #[serde(default)]
+    pub dscp: Option<u8>,
+
+    /// Raw IP TOS byte, for when the ECN bits matter too (instead of `dscp`)
+    #[serde(default)]
+    pub tos: Option<u8>,
+
+    /// Socket send buffer size (bytes, unset = OS default)
+    #[serde(default)]
+    pub sndbuf: Option<usize>,
+
+    /// Local address to send from (unset = any)
+    #[serde(default)]
+    pub bind_address: Option<IpAddr>,
+}
+
+impl NetworkConfig {
+    /// Keys set here win over `base`. `dscp` and `tos` both describe the IP
+    /// traffic class, so they are inherited together.
+    pub fn or(&self, base: &NetworkConfig) -> NetworkConfig {
+        let (dscp, tos) = if self.dscp.is_some() || self.tos.is_some() {
+            (self.dscp, self.tos)
+        } else {
+            (base.dscp, base.tos)
+        };
+        NetworkConfig {
+            mtu: self.mtu.or(base.mtu),
+            dscp,
+            tos,
+            sndbuf: self.sndbuf.or(base.sndbuf),
+            bind_address: self.bind_address.or(base.bind_address),
+        }
+    }
+}
+
+/// Network settings of one camera after inheritance
+#[derive(Debug, Clone, PartialEq)]
+pub struct NetworkSettings {
+    pub mtu: usize,
+    pub dscp: u8,
+    pub tos: Option<u8>,
+    pub sndbuf: Option<usize>,
+    pub bind_address: Option<IpAddr>,
+}
+
+/// Congestion control and adaptive quality configuration
+///
+/// Quality adaptation is off unless `target_bitrate_kbps` is set.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CongestionConfig {
+    /// "fixed" or "aimd" (loss-based, driven by RTCP receiver reports)
+    #[serde(default)]
+    pub controller: ControllerKind,
+
+    /// Target (fixed) or starting (aimd) bitrate per camera
+    #[serde(default)]
+    pub target_bitrate_kbps: Option<u64>,
+
+    /// AIMD bounds
+    #[serde(default = "default_min_bitrate_kbps")]
+    pub min_bitrate_kbps: u64,
+    #[serde(default = "default_max_bitrate_kbps")]
+    pub max_bitrate_kbps: u64,
+
+    /// Lowest JPEG quality adaptation may go down to
+    #[serde(default = "default_min_quality")]
+    pub min_quality: u32,
+}
+
+impl Default for CongestionConfig {
+    fn default() -> Self {
+        Self {
+            controller: ControllerKind::default(),
+            target_bitrate_kbps: None,
+            min_bitrate_kbps: default_min_bitrate_kbps(),
+            max_bitrate_kbps: default_max_bitrate_kbps(),
+            min_quality: default_min_quality(),
+        }
+    }
+}
+
+/// Egress rate limits (token buckets ahead of the UDP send)
+///
+/// Frames wait until they fit under the limits and are dropped if that
+/// would take longer than `max_delay_ms`. Off unless a limit is set.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ShapingConfig {
+    /// Limit per camera, overridden by a camera's `max_kbps`
+    #[serde(default)]
+    pub camera_kbps: Option<u64>,
+
+    /// Limit on all cameras together
+    #[serde(default)]
+    pub total_kbps: Option<u64>,
+
+    /// Traffic allowed above the limits at once, as milliseconds at their
+    /// rate; a frame larger than that is sent once the bucket is full and
+    /// the following frames wait off the excess
+    #[serde(default = "default_shaping_burst_ms")]
+    pub burst_ms: u64,
+
+    /// Longest a frame waits for the limits before it is dropped
+    #[serde(default = "default_shaping_max_delay_ms")]
+    pub max_delay_ms: u64,
+}
+
+impl Default for ShapingConfig {
+    fn default() -> Self {
+        Self {
+            camera_kbps: None,
+            total_kbps: None,
+            burst_ms: default_shaping_burst_ms(),
+            max_delay_ms: default_shaping_max_delay_ms(),
+        }
+    }
+}
+
+/// DNS-SD advertisement of the cameras over mDNS
+///
+/// Each enabled camera becomes an instance of `service` whose TXT record
+/// carries its UUID and RTCP CNAME.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MdnsConfig {
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// DNS-SD service type, without `.local`
+    #[serde(default = "default_mdns_service")]
+    pub service: String,
+
+    /// Host name the instances point at, without `.local` (unset = the
+    /// system's)
+    #[serde(default)]
+    pub hostname: Option<String>,
+}
+
+impl Default for MdnsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            service: default_mdns_service(),
+            hostname: None,
+        }
+    }
+}
+
+/// Leader election between instances sending to the same destination
+///
+/// Only the leader streams; standbys capture (and record) but send nothing
+/// until the leader has been silent for `takeover_timeout_ms`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CoordinationConfig {
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// Local address heartbeats are received on
+    #[serde(default = "default_coordination_listen")]
+    pub listen: SocketAddr,
+
+    /// Heartbeat addresses of the other instances
+    #[serde(default)]
+    pub peers: Vec<SocketAddr>,
+
+    /// Preferred leader when none is running (higher wins). A running
+    /// leader is never preempted.
+    #[serde(default = "default_coordination_priority")]
+    pub priority: u8,
+
+    /// Breaks ties between equal priorities (unset = random per run)
+    #[serde(default)]
+    pub node_id: Option<u64>,
+
+    #[serde(default = "default_heartbeat_interval_ms")]
+    pub heartbeat_interval_ms: u64,
+
+    /// Leader silence before a standby takes over (milliseconds)
+    #[serde(default = "default_takeover_timeout_ms")]
+    pub takeover_timeout_ms: u64,
+}
+
+/// Repeater fanning a received RTP/JPEG stream out without re-encoding
+///
+/// Payloads are sent on untouched; only the SSRC, sequence numbers and
+/// timestamps are rewritten to keep the stream continuous across sender
+/// restarts.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RelayConfig {
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// Local address the stream is received on (and relayed from)
+    #[serde(default = "default_relay_listen")]
+    pub listen: SocketAddr,
+
+    /// Where every received packet is sent
+    #[serde(default)]
+    pub destinations: Vec<SocketAddr>,
+
+    /// SSRC of the relayed stream (unset = the first sender's)
+    #[serde(default)]
+    pub ssrc: Option<u32>,
+}
+
+impl Default for RelayConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            listen: default_relay_listen(),
+            destinations: Vec::new(),
+            ssrc: None,
+        }
+    }
+}
+
+/// Background H.265 transcoding of archived `.frames` recordings; see
+/// [`crate::transcode`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TranscodeConfig {
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// Directories searched for recordings (empty = every enabled camera's
+    /// `event_recording.dir` and the directory of its `record` file)
+    #[serde(default)]
+    pub dirs: Vec<String>,
+
+    /// Recordings untouched for this long are transcoded (hours)
+    #[serde(default = "default_transcode_after_hours")]
+    pub after_hours: u64,
+
+    /// How often the directories are searched (seconds)
+    #[serde(default = "default_transcode_scan_interval_secs")]
+    pub scan_interval_secs: u64,
+
+    /// `ffmpeg` (libx265) or `gstreamer` (x265enc)
+    #[serde(default)]
+    pub encoder: TranscodeEncoder,
+
+    /// x265 constant rate factor (0-51, lower is better and bigger)
+    #[serde(default = "default_transcode_crf")]
+    pub crf: u32,
+
+    /// x265 speed preset, e.g. "ultrafast" .. "veryslow"
+    #[serde(default = "default_transcode_preset")]
+    pub preset: String,
+
+    /// Scheduling priority of the encoder process (-20..19; 19 yields the
+    /// CPU to the live streams)
+    #[serde(default = "default_transcode_nice")]
+    pub nice: i32,
+
+    /// Keep the `.frames` file next to the `.mp4` instead of deleting it
+    #[serde(default)]
+    pub keep_original: bool,
+
+    /// Encoder command replacing the built-in one, fed JPEG frames on stdin.
+    /// `{output}`, `{fps}`, `{crf}` and `{preset}` are substituted.
+    #[serde(default)]
+    pub command: Vec<String>,
+}
+
+impl Default for TranscodeConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            dirs: Vec::new(),
+            after_hours: default_transcode_after_hours(),
+            scan_interval_secs: default_transcode_scan_interval_secs(),
+            encoder: TranscodeEncoder::default(),
+            crf: default_transcode_crf(),
+            preset: default_transcode_preset(),
+            nice: default_transcode_nice(),
+            keep_original: false,
+            command: Vec::new(),
+        }
+    }
+}
+
+/// Timing of a camera's `replay`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReplayPacing {
+    #[default]
+    Recorded,
+    Fps,
+}
+
+/// Built-in encoder used by the transcoder
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TranscodeEncoder {
+    #[default]
+    Ffmpeg,
+    Gstreamer,
+}
+
+fn default_transcode_after_hours() -> u64 {
+    24
+}
+
+fn default_transcode_scan_interval_secs() -> u64 {
+    600
+}
+
+fn default_transcode_crf() -> u32 {
+    28
+}
+
+fn default_transcode_preset() -> String {
+    "veryfast".to_string()
+}
+
+fn default_transcode_nice() -> i32 {
+    19
+}
+
+impl Default for CoordinationConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            listen: default_coordination_listen(),
+            peers: Vec::new(),
+            priority: default_coordination_priority(),
+            node_id: None,
+            heartbeat_interval_ms: default_heartbeat_interval_ms(),
+            takeover_timeout_ms: default_takeover_timeout_ms(),
+        }
+    }
+}
+
+/// Overrides for the presets picked from the detected Raspberry Pi model
+///
+/// Anything left unset comes from the model's preset.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PlatformConfig {
+    /// Force a model instead of reading the device tree
+    /// ("zero2", "pi3", "pi4", "pi5", "other")
+    #[serde(default)]
+    pub model: Option<PiModel>,
+
+    /// JPEG encoder ("software" or "hardware")
+    #[serde(default)]
+    pub encoder: Option<JpegEncoder>,
+
+    /// Resolution ceiling
+    #[serde(default)]
+    pub max_width: Option<u32>,
+    #[serde(default)]
+    pub max_height: Option<u32>,
+
+    /// Colour conversion threads (0 = GStreamer default)
+    #[serde(default)]
+    pub encoder_threads: Option<u32>,
+}
+
+impl PlatformConfig {
+    /// Applies the overrides on top of the preset for `detected`
+    /// (ignored when `model` is set)
+    pub fn resolve(&self, detected: PiModel) -> ModelPreset {
+        let base = self.model.unwrap_or(detected).preset();
+        ModelPreset {
+            model: base.model,
+            encoder: self.encoder.unwrap_or(base.encoder),
+            max_width: self.max_width.or(base.max_width),
+            max_height: self.max_height.or(base.max_height),
+            encoder_threads: self.encoder_threads.unwrap_or(base.encoder_threads),
+        }
+    }
+}
+
+impl MjpegRtpConfig {
+    /// Directories the transcoder searches: `transcode.dirs`, or wherever
+    /// the enabled cameras record
+    pub fn transcode_dirs(&self) -> Vec<PathBuf> {
+        if !self.transcode.dirs.is_empty() {
+            return self.transcode.dirs.iter().map(PathBuf::from).collect();
+        }
+        let mut dirs = Vec::new();
+        for camera in [&self.camera1, &self.camera2]
+            .into_iter()
+            .filter(|cam| cam.enabled)
+        {
+            let record_dir = camera
+                .record
+                .as_deref()
+                .map(|path| match Path::new(path).parent() {
+                    Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+                    _ => PathBuf::from("."),
+                });
+            let clip_dir = camera.event_recording.dir.as_deref().map(PathBuf::from);
+            for dir in record_dir.into_iter().chain(clip_dir) {
+                if !dirs.contains(&dir) {
+                    dirs.push(dir);
+                }
+            }
+        }
+        dirs
+    }
+
+    /// Resolves a camera's network settings: its own `network` keys, then
+    /// `[mjpeg-rtp.network]`, then the top-level `mtu` and `dscp`
+    pub fn network_for(&self, camera: &CameraConfig) -> NetworkSettings {
+        let network = camera.network.or(&self.network);
+        let (dscp, tos) = if network.dscp.is_some() || network.tos.is_some() {
+            (network.dscp.unwrap_or(0), network.tos)
+        } else {
+            (self.dscp, None)
+        };
+        NetworkSettings {
+            mtu: network.mtu.unwrap_or(self.mtu),
+            dscp,
+            tos,
+            sndbuf: network.sndbuf,
+            bind_address: network.bind_address,
+        }
+    }
+
+    /// A camera's outgoing rate limit: its own `max_kbps`, then
+    /// `[mjpeg-rtp.shaping] camera_kbps`
+    pub fn max_kbps_for(&self, camera: &CameraConfig) -> Option<u64> {
+        camera.max_kbps.or(self.shaping.camera_kbps)
+    }
 }
 
 impl Default for MjpegRtpConfig {
@@ -59,7 +650,27 @@ impl Default for MjpegRtpConfig {
             camera2: CameraConfig::default_camera2(),
             mtu: default_mtu(),
             dscp: 0,
+            network: NetworkConfig::default(),
+            bundle: false,
             stats_interval_seconds: default_stats_interval(),
+            platform: PlatformConfig::default(),
+            oversize_dimensions: false,
+            parallel_packetize_bytes: default_parallel_packetize_bytes(),
+            frame_info_extension: None,
+            qtable_policy: QTablePolicy::default(),
+            qtable_refresh_frames: default_qtable_refresh_frames(),
+            timestamp_source: TimestampSource::default(),
+            drift_correction: default_drift_correction(),
+            congestion: CongestionConfig::default(),
+            shaping: ShapingConfig::default(),
+            state_dir: default_state_dir(),
+            resume_rtp_state: false,
+            resume_max_gap_secs: default_resume_max_gap_secs(),
+            api_listen: None,
+            mdns: MdnsConfig::default(),
+            coordination: CoordinationConfig::default(),
+            relay: RelayConfig::default(),
+            transcode: TranscodeConfig::default(),
         }
     }
 }
@@ -74,6 +685,7 @@ pub struct CameraConfig {
     /// Camera device path
     /// - macOS: "0" for first webcam, "1" for second, etc.
     /// - Raspberry Pi: "/base/axi/pcie@1000120000/rp1/i2c@88000/imx219@10"
+    /// - IP camera: "rtsp://..." or an MJPEG "http://..." URL
     pub device: String,
 
     /// Frame width in pixels
@@ -101,54 +713,436 @@ pub struct CameraConfig {
     #[serde(default)]
     pub flip_method: Option<String>,
 
-    /// RTP destination host
+    /// RTP destination host: an IP address, or a name (e.g. dynamic DNS)
+    /// that is looked up again every `dns_refresh_secs`
     #[serde(default = "default_dest_host")]
     pub dest_host: String,
 
     /// RTP destination port
     pub dest_port: u16,
 
-    /// Local port (0 = auto-assign)
+    /// Seconds between lookups of a `dest_host` name (0 = only at start)
+    #[serde(default = "default_dns_refresh_secs")]
+    pub dns_refresh_secs: u64,
+
+    /// Local RTP port (0 = auto-assign). Must be even unless `rtcp_mux` is
+    /// set, as RTCP is sent from the port above it.
     #[serde(default)]
     pub local_port: u16,
 
-    /// RTP SSRC identifier
-    pub ssrc: u32,
-}
+    /// Multiplex RTCP onto the RTP port (RFC 5761) instead of an even/odd
+    /// port pair, so only one port has to get through NAT or a firewall
+    #[serde(default)]
+    pub rtcp_mux: bool,
 
-impl CameraConfig {
-    fn default_camera1() -> Self {
-        Self {
-            enabled: false,
-            device: "0".to_string(), // macOS webcam by default
-            width: default_width(),
-            height: default_height(),
-            fps: default_fps(),
-            quality: default_quality(),
-            flip_method: None,
-            dest_host: default_dest_host(),
-            dest_port: 5000,
-            local_port: 0,
-            ssrc: 0x12345678,
-        }
-    }
+    /// RTP SSRC identifier. Derived from the camera's persisted UUID when
+    /// unset, so it stays the same across restarts.
+    #[serde(default)]
+    pub ssrc: Option<u32>,
 
-    fn default_camera2() -> Self {
-        Self {
-            enabled: false,
-            device: "1".to_string(),
-            width: default_width(),
-            height: default_height(),
-            fps: default_fps(),
-            quality: default_quality(),
-            flip_method: None,
-            dest_host: default_dest_host(),
-            dest_port: 5002,
-            local_port: 0,
-            ssrc: 0x12345679,
-        }
-    }
-}
+    /// CPU cores for this camera's hot-path threads
+    #[serde(default)]
+    pub affinity: AffinityConfig,
+
+    /// Overrides for `[mjpeg-rtp.network]`
+    #[serde(default)]
+    pub network: NetworkConfig,
+
+    /// Backup destinations (disabled when `backups` is empty)
+    #[serde(default)]
+    pub failover: FailoverConfig,
+
+    /// Packets that hold NAT mappings open while no frames go out
+    /// (disabled when `interval_ms` is 0)
+    #[serde(default)]
+    pub keepalive: KeepaliveConfig,
+
+    /// Send only to receivers that ask for the stream, instead of
+    /// `dest_host:dest_port`
+    #[serde(default)]
+    pub pull: PullConfig,
+
+    /// Outgoing rate limit in kbit/s, instead of `shaping.camera_kbps`
+    #[serde(default)]
+    pub max_kbps: Option<u64>,
+
+    /// Write every captured frame to this `.frames` file while streaming
+    #[serde(default)]
+    pub record: Option<String>,
+
+    /// Stream this `.frames` recording instead of the camera
+    #[serde(default)]
+    pub replay: Option<String>,
+
+    /// Start the replay over when it ends
+    #[serde(default)]
+    pub replay_loop: bool,
+
+    /// How replayed frames are timed: `recorded` (their original offsets,
+    /// jitter and gaps included) or `fps` (exactly `fps` on the RTP clock,
+    /// for benchmarks and soak tests)
+    #[serde(default)]
+    pub replay_pacing: ReplayPacing,
+
+    /// Frame processors run in order on every frame before it is sent
+    #[serde(default)]
+    pub processors: Vec<ProcessorConfig>,
+
+    /// Clips recorded around analytics events (disabled when `dir` is unset)
+    #[serde(default)]
+    pub event_recording: EventRecordingConfig,
+
+    /// Extra capture pipeline elements and element property overrides
+    #[serde(default)]
+    pub pipeline: PipelineOverrides,
+}
+
+/// Recording only while processors report events; see [`crate::clips`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EventRecordingConfig {
+    /// Directory clips and their `.json` metadata are written to
+    #[serde(default)]
+    pub dir: Option<String>,
+
+    /// Frames kept from before the event that starts a clip (milliseconds)
+    #[serde(default = "default_pre_roll_ms")]
+    pub pre_roll_ms: u64,
+
+    /// How long a clip continues after the last event (milliseconds)
+    #[serde(default = "default_post_roll_ms")]
+    pub post_roll_ms: u64,
+
+    /// Longer activity is split into clips of this length (seconds)
+    #[serde(default = "default_max_clip_secs")]
+    pub max_clip_secs: u64,
+
+    /// Event sources (plugin or model names) that start a clip; empty = any
+    #[serde(default)]
+    pub sources: Vec<String>,
+}
+
+impl Default for EventRecordingConfig {
+    fn default() -> Self {
+        Self {
+            dir: None,
+            pre_roll_ms: default_pre_roll_ms(),
+            post_roll_ms: default_post_roll_ms(),
+            max_clip_secs: default_max_clip_secs(),
+            sources: Vec::new(),
+        }
+    }
+}
+
+fn default_pre_roll_ms() -> u64 {
+    5000
+}
+
+fn default_post_roll_ms() -> u64 {
+    10000
+}
+
+fn default_max_clip_secs() -> u64 {
+    300
+}
+
+/// One stage of a camera's frame processor chain, e.g.
+/// `{ type = "decimate", keep_every = 2 }`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ProcessorConfig {
+    /// Drop frames identical to the last one sent, passing every
+    /// `keepalive`th repeat in a row (0 = none)
+    Dedup {
+        #[serde(default = "default_dedup_keepalive")]
+        keepalive: u32,
+    },
+
+    /// Send one frame in `keep_every`
+    Decimate { keep_every: u32 },
+
+    /// Analyse frames with a WASM module (needs the `wasm` feature)
+    Wasm(WasmPluginConfig),
+
+    /// Detect objects with an ONNX model (needs the `inference` feature)
+    Detect(DetectorConfig),
+}
+
+fn default_dedup_keepalive() -> u32 {
+    30
+}
+
+/// A WASM analytics plugin; see [`crate::plugin`] for the module interface
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WasmPluginConfig {
+    /// `.wasm` (or `.wat`) module
+    pub path: String,
+
+    /// Size of the greyscale frames the module is given
+    #[serde(default = "default_plugin_width")]
+    pub width: u32,
+    #[serde(default = "default_plugin_height")]
+    pub height: u32,
+
+    /// Minimum time between analysed frames (milliseconds)
+    #[serde(default = "default_plugin_interval_ms")]
+    pub interval_ms: u64,
+
+    /// Wasmtime fuel (roughly instructions) per analysed frame
+    #[serde(default = "default_plugin_fuel")]
+    pub fuel: u64,
+}
+
+fn default_plugin_width() -> u32 {
+    320
+}
+
+fn default_plugin_height() -> u32 {
+    240
+}
+
+fn default_plugin_interval_ms() -> u64 {
+    200
+}
+
+fn default_plugin_fuel() -> u64 {
+    1_000_000_000
+}
+
+/// An object detection model; see [`crate::inference`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DetectorConfig {
+    /// `.onnx` model
+    pub model: String,
+
+    /// Layout of the model's input and outputs
+    #[serde(default)]
+    pub format: DetectionFormat,
+
+    /// Text file with one class label per line, in class order
+    #[serde(default)]
+    pub labels: Option<String>,
+
+    /// Size the model takes its RGB input at
+    #[serde(default = "default_detector_input_size")]
+    pub input_width: u32,
+    #[serde(default = "default_detector_input_size")]
+    pub input_height: u32,
+
+    /// Minimum time between analysed frames (milliseconds)
+    #[serde(default = "default_detector_interval_ms")]
+    pub interval_ms: u64,
+
+    /// Minimum confidence (0-1) of a reported detection
+    #[serde(default = "default_detector_threshold")]
+    pub threshold: f32,
+
+    /// Most detections reported per frame, highest confidence first
+    #[serde(default = "default_detector_max_detections")]
+    pub max_detections: usize,
+
+    /// Outline the latest detections in the streamed frames
+    #[serde(default)]
+    pub overlay: bool,
+}
+
+/// Model families with different tensor layouts
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DetectionFormat {
+    /// YOLOv5/v8 export: float NCHW input scaled to 0-1, one output of
+    /// candidate boxes in input pixels
+    #[default]
+    Yolo,
+    /// TensorFlow SSD export: uint8 NHWC input, outputs of normalized boxes,
+    /// classes and scores
+    Ssd,
+}
+
+fn default_detector_input_size() -> u32 {
+    320
+}
+
+fn default_detector_interval_ms() -> u64 {
+    500
+}
+
+fn default_detector_threshold() -> f32 {
+    0.5
+}
+
+fn default_detector_max_detections() -> usize {
+    20
+}
+
+/// Destinations to switch to when the primary stops receiving
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FailoverConfig {
+    /// Tried in order after `dest_host:dest_port`, then back to the primary
+    #[serde(default)]
+    pub backups: Vec<SocketAddr>,
+
+    /// Local port receivers send RTCP to. When set, this long without RTCP
+    /// from the active destination triggers a switch.
+    #[serde(default)]
+    pub rtcp_port: Option<u16>,
+
+    /// RTCP silence before switching, and the minimum time spent on a
+    /// destination before switching again (milliseconds)
+    #[serde(default = "default_failover_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+impl Default for FailoverConfig {
+    fn default() -> Self {
+        Self {
+            backups: Vec::new(),
+            rtcp_port: None,
+            timeout_ms: default_failover_timeout_ms(),
+        }
+    }
+}
+
+/// Keepalives sent to the destination while no frames go out, e.g. while
+/// paused, so NAT mappings and firewall pinholes don't expire
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct KeepaliveConfig {
+    /// Time without frames before a keepalive is sent, and between
+    /// keepalives (milliseconds, 0 = off). Well under the NAT's UDP timeout,
+    /// often 30 s.
+    #[serde(default)]
+    pub interval_ms: u64,
+
+    /// `rtp` (padding-only RTP packets) or `rtcp` (empty receiver reports).
+    /// Without rtcp_mux, `rtcp` keeps only the RTCP ports open.
+    #[serde(default)]
+    pub kind: KeepaliveKind,
+}
+
+/// Receiver-driven streaming (see `pull`): receivers ask for the stream
+/// with a UDP request, and get it until they stop repeating it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PullConfig {
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// Local address pull requests are received on
+    #[serde(default = "default_pull_listen")]
+    pub listen: SocketAddr,
+
+    /// Time a receiver keeps the stream after its last request
+    /// (milliseconds)
+    #[serde(default = "default_pull_idle_timeout_ms")]
+    pub idle_timeout_ms: u64,
+
+    /// Most receivers streamed to at once
+    #[serde(default = "default_pull_max_receivers")]
+    pub max_receivers: usize,
+
+    /// Addresses a request may name as the destination besides the one it
+    /// came from (empty = only the sender's own address)
+    #[serde(default)]
+    pub allow: Vec<IpAddr>,
+}
+
+impl Default for PullConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            listen: default_pull_listen(),
+            idle_timeout_ms: default_pull_idle_timeout_ms(),
+            max_receivers: default_pull_max_receivers(),
+            allow: Vec::new(),
+        }
+    }
+}
+
+/// Shortest keepalive interval, so a typo can't flood the link
+const MIN_KEEPALIVE_INTERVAL_MS: u64 = 100;
+
+/// CPU core pinning for one camera's hot-path threads (unset = not pinned)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AffinityConfig {
+    /// Appsink callback thread that hands frames to the streamer
+    #[serde(default)]
+    pub capture_core: Option<usize>,
+
+    /// GStreamer streaming thread running colour conversion and JPEG encoding
+    #[serde(default)]
+    pub encoder_core: Option<usize>,
+
+    /// Packetizer/sender task (moved to a dedicated thread when set)
+    #[serde(default)]
+    pub sender_core: Option<usize>,
+
+    /// Opt-in SCHED_FIFO priority (1-99) for the sender thread. Needs
+    /// CAP_SYS_NICE or RLIMIT_RTPRIO; falls back to normal scheduling.
+    #[serde(default)]
+    pub sender_rt_priority: Option<u8>,
+}
+
+impl CameraConfig {
+    fn default_camera1() -> Self {
+        Self {
+            enabled: false,
+            device: "0".to_string(), // macOS webcam by default
+            width: default_width(),
+            height: default_height(),
+            fps: default_fps(),
+            quality: default_quality(),
+            flip_method: None,
+            dest_host: default_dest_host(),
+            dest_port: 5000,
+            dns_refresh_secs: default_dns_refresh_secs(),
+            local_port: 0,
+            rtcp_mux: false,
+            affinity: AffinityConfig::default(),
+            ssrc: None,
+            network: NetworkConfig::default(),
+            failover: FailoverConfig::default(),
+            keepalive: KeepaliveConfig::default(),
+            pull: PullConfig::default(),
+            max_kbps: None,
+            record: None,
+            replay: None,
+            replay_loop: false,
+            replay_pacing: ReplayPacing::default(),
+            processors: Vec::new(),
+            event_recording: EventRecordingConfig::default(),
+            pipeline: PipelineOverrides::default(),
+        }
+    }
+
+    fn default_camera2() -> Self {
+        Self {
+            enabled: false,
+            device: "1".to_string(),
+            width: default_width(),
+            height: default_height(),
+            fps: default_fps(),
+            quality: default_quality(),
+            flip_method: None,
+            dest_host: default_dest_host(),
+            dest_port: 5002,
+            dns_refresh_secs: default_dns_refresh_secs(),
+            local_port: 0,
+            rtcp_mux: false,
+            affinity: AffinityConfig::default(),
+            ssrc: None,
+            network: NetworkConfig::default(),
+            failover: FailoverConfig::default(),
+            keepalive: KeepaliveConfig::default(),
+            pull: PullConfig::default(),
+            max_kbps: None,
+            record: None,
+            replay: None,
+            replay_loop: false,
+            replay_pacing: ReplayPacing::default(),
+            processors: Vec::new(),
+            event_recording: EventRecordingConfig::default(),
+            pipeline: PipelineOverrides::default(),
+        }
+    }
+}
 
 impl Default for CameraConfig {
     fn default() -> Self {
@@ -178,6 +1172,78 @@ fn default_quality() -> u32 {
 fn default_dest_host() -> String {
     "127.0.0.1".to_string()
 }
+fn default_dns_refresh_secs() -> u64 {
+    DEFAULT_DNS_REFRESH.as_secs()
+}
+fn default_min_bitrate_kbps() -> u64 {
+    500
+}
+fn default_max_bitrate_kbps() -> u64 {
+    20_000
+}
+fn default_min_quality() -> u32 {
+    30
+}
+fn default_shaping_burst_ms() -> u64 {
+    100
+}
+fn default_shaping_max_delay_ms() -> u64 {
+    100
+}
+fn default_failover_timeout_ms() -> u64 {
+    5000
+}
+fn default_parallel_packetize_bytes() -> usize {
+    DEFAULT_PARALLEL_PACKETIZE_BYTES
+}
+fn default_qtable_refresh_frames() -> u32 {
+    crate::streamer::DEFAULT_QTABLE_REFRESH_FRAMES
+}
+fn default_drift_correction() -> bool {
+    true
+}
+fn default_mdns_service() -> String {
+    "_mjpeg-rtp._udp".to_string()
+}
+fn default_coordination_listen() -> SocketAddr {
+    SocketAddr::from(([0, 0, 0, 0], 5600))
+}
+fn default_coordination_priority() -> u8 {
+    100
+}
+fn default_heartbeat_interval_ms() -> u64 {
+    200
+}
+fn default_takeover_timeout_ms() -> u64 {
+    1000
+}
+fn default_relay_listen() -> SocketAddr {
+    SocketAddr::from(([0, 0, 0, 0], 5000))
+}
+fn default_pull_listen() -> SocketAddr {
+    SocketAddr::from(([0, 0, 0, 0], 5700))
+}
+fn default_pull_idle_timeout_ms() -> u64 {
+    10_000
+}
+fn default_pull_max_receivers() -> usize {
+    4
+}
+fn default_resume_max_gap_secs() -> u64 {
+    60
+}
+fn default_state_dir() -> String {
+    "/var/lib/mjpeg-rtp".to_string()
+}
+fn default_otlp_endpoint() -> String {
+    "http://localhost:4317".to_string()
+}
+fn default_service_name() -> String {
+    "rust-mjpeg-rtp".to_string()
+}
+fn default_metrics_interval() -> u64 {
+    10
+}
 
 impl Config {
     /// Loads configuration from TOML file
@@ -199,6 +1265,7 @@ impl Config {
     pub fn default() -> Self {
         Self {
             mjpeg_rtp: MjpegRtpConfig::default(),
+            telemetry: TelemetryConfig::default(),
         }
     }
 
@@ -222,6 +1289,149 @@ impl Config {
             )));
         }
 
+        Self::validate_network(&cfg.network, "network")?;
+        Self::validate_network(&cfg.camera1.network, "camera1.network")?;
+        Self::validate_network(&cfg.camera2.network, "camera2.network")?;
+
+        if let Some(id) = cfg.frame_info_extension {
+            if !(1..=14).contains(&id) {
+                return Err(ConfigError::Invalid(format!(
+                    "frame_info_extension must be between 1 and 14, got {}",
+                    id
+                )));
+            }
+        }
+
+        if cfg.platform.max_width == Some(0) || cfg.platform.max_height == Some(0) {
+            return Err(ConfigError::Invalid(
+                "platform.max_width and platform.max_height must be > 0".to_string(),
+            ));
+        }
+
+        let cc = &cfg.congestion;
+        if cc.min_bitrate_kbps > cc.max_bitrate_kbps {
+            return Err(ConfigError::Invalid(format!(
+                "congestion.min_bitrate_kbps ({}) must not exceed max_bitrate_kbps ({})",
+                cc.min_bitrate_kbps, cc.max_bitrate_kbps
+            )));
+        }
+        if cc.min_quality == 0 || cc.min_quality > 100 {
+            return Err(ConfigError::Invalid(format!(
+                "congestion.min_quality must be between 1 and 100, got {}",
+                cc.min_quality
+            )));
+        }
+
+        let shaping = &cfg.shaping;
+        if shaping.camera_kbps == Some(0) || shaping.total_kbps == Some(0) {
+            return Err(ConfigError::Invalid(
+                "shaping.camera_kbps and shaping.total_kbps must be > 0".to_string(),
+            ));
+        }
+
+        let coord = &cfg.coordination;
+        if coord.enabled {
+            if coord.peers.is_empty() {
+                return Err(ConfigError::Invalid(
+                    "coordination.peers must list the other instances".to_string(),
+                ));
+            }
+            if coord.heartbeat_interval_ms == 0 {
+                return Err(ConfigError::Invalid(
+                    "coordination.heartbeat_interval_ms must be > 0".to_string(),
+                ));
+            }
+            // A single lost heartbeat must not hand over leadership
+            if coord.takeover_timeout_ms < 2 * coord.heartbeat_interval_ms {
+                return Err(ConfigError::Invalid(format!(
+                    "coordination.takeover_timeout_ms ({}) must be at least twice \
+                     heartbeat_interval_ms ({})",
+                    coord.takeover_timeout_ms, coord.heartbeat_interval_ms
+                )));
+            }
+        }
+
+        let relay = &cfg.relay;
+        if relay.enabled {
+            if relay.destinations.is_empty() {
+                return Err(ConfigError::Invalid(
+                    "relay.destinations must list where to send the stream".to_string(),
+                ));
+            }
+            if relay.destinations.contains(&relay.listen) {
+                return Err(ConfigError::Invalid(format!(
+                    "relay.destinations includes the relay's own listen address {}",
+                    relay.listen
+                )));
+            }
+        }
+
+        let mdns = &cfg.mdns;
+        if mdns.enabled {
+            // "_name._udp" or "_name._tcp" (RFC 6763 Section 7)
+            let valid_service = mdns.service.split_once('.').is_some_and(|(name, proto)| {
+                name.len() > 1 && name.starts_with('_') && matches!(proto, "_udp" | "_tcp")
+            });
+            if !valid_service {
+                return Err(ConfigError::Invalid(format!(
+                    "mdns.service must look like \"_name._udp\", got \"{}\"",
+                    mdns.service
+                )));
+            }
+            if mdns
+                .hostname
+                .as_deref()
+                .is_some_and(|host| host.is_empty() || host.contains('.'))
+            {
+                return Err(ConfigError::Invalid(
+                    "mdns.hostname must be a single label, without .local".to_string(),
+                ));
+            }
+        }
+
+        let transcode = &cfg.transcode;
+        if transcode.enabled {
+            if cfg.transcode_dirs().is_empty() {
+                return Err(ConfigError::Invalid(
+                    "transcode.dirs must be set when no camera records".to_string(),
+                ));
+            }
+            if transcode.after_hours == 0 || transcode.scan_interval_secs == 0 {
+                return Err(ConfigError::Invalid(
+                    "transcode.after_hours and scan_interval_secs must be > 0".to_string(),
+                ));
+            }
+            if transcode.crf > 51 {
+                return Err(ConfigError::Invalid(format!(
+                    "transcode.crf must be between 0 and 51, got {}",
+                    transcode.crf
+                )));
+            }
+            if !(-20..=19).contains(&transcode.nice) {
+                return Err(ConfigError::Invalid(format!(
+                    "transcode.nice must be between -20 and 19, got {}",
+                    transcode.nice
+                )));
+            }
+            if !transcode.command.is_empty()
+                && !transcode.command.iter().any(|arg| arg.contains("{output}"))
+            {
+                return Err(ConfigError::Invalid(
+                    "transcode.command must write to {output}".to_string(),
+                ));
+            }
+        }
+
+        if cfg.bundle {
+            Self::validate_bundle(cfg)?;
+        }
+
+        if self.telemetry.enabled && self.telemetry.metrics_interval_seconds == 0 {
+            return Err(ConfigError::Invalid(
+                "telemetry.metrics_interval_seconds must be > 0".to_string(),
+            ));
+        }
+
         // Validate camera1 if enabled
         if cfg.camera1.enabled {
             self.validate_camera(&cfg.camera1, "camera1")?;
@@ -232,10 +1442,97 @@ impl Config {
             self.validate_camera(&cfg.camera2, "camera2")?;
         }
 
+        let pulled = [&cfg.camera1, &cfg.camera2].map(|cam| cam.enabled && cam.pull.enabled);
+        if pulled == [true, true] && cfg.camera1.pull.listen == cfg.camera2.pull.listen {
+            return Err(ConfigError::Invalid(format!(
+                "camera1 and camera2 can't both take pull requests on {}",
+                cfg.camera1.pull.listen
+            )));
+        }
+
+        Ok(())
+    }
+
+    fn validate_bundle(cfg: &MjpegRtpConfig) -> Result<(), ConfigError> {
+        let (cam1, cam2) = (&cfg.camera1, &cfg.camera2);
+        if !cam1.enabled || !cam2.enabled {
+            return Err(ConfigError::Invalid(
+                "bundle needs both cameras enabled".to_string(),
+            ));
+        }
+        if !cam1.rtcp_mux || !cam2.rtcp_mux {
+            return Err(ConfigError::Invalid(
+                "bundle needs rtcp_mux = true on both cameras".to_string(),
+            ));
+        }
+        if (&cam1.dest_host, cam1.dest_port) != (&cam2.dest_host, cam2.dest_port) {
+            return Err(ConfigError::Invalid(format!(
+                "bundle needs one destination, got {}:{} and {}:{}",
+                cam1.dest_host, cam1.dest_port, cam2.dest_host, cam2.dest_port
+            )));
+        }
+        if cam1.local_port != cam2.local_port
+            || cfg.network_for(cam1).bind_address != cfg.network_for(cam2).bind_address
+        {
+            return Err(ConfigError::Invalid(
+                "bundle needs the same local_port and bind_address on both cameras".to_string(),
+            ));
+        }
+        if !cam1.failover.backups.is_empty() || !cam2.failover.backups.is_empty() {
+            return Err(ConfigError::Invalid(
+                "bundle can't be combined with failover backups".to_string(),
+            ));
+        }
+        if cam1.pull.enabled || cam2.pull.enabled {
+            return Err(ConfigError::Invalid(
+                "bundle can't be combined with pull".to_string(),
+            ));
+        }
+        if cam1.ssrc.is_some() && cam1.ssrc == cam2.ssrc {
+            return Err(ConfigError::Invalid(
+                "bundled cameras need different SSRCs".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    fn validate_network(network: &NetworkConfig, name: &str) -> Result<(), ConfigError> {
+        if let Some(mtu) = network.mtu {
+            if !(500..=9000).contains(&mtu) {
+                return Err(ConfigError::Invalid(format!(
+                    "{}.mtu must be between 500 and 9000, got {}",
+                    name, mtu
+                )));
+            }
+        }
+
+        if let Some(dscp) = network.dscp {
+            if dscp > 63 {
+                return Err(ConfigError::Invalid(format!(
+                    "{}.dscp must be between 0 and 63, got {}",
+                    name, dscp
+                )));
+            }
+        }
+
+        if network.dscp.is_some() && network.tos.is_some() {
+            return Err(ConfigError::Invalid(format!(
+                "{}: set either dscp or tos, not both",
+                name
+            )));
+        }
+
+        if network.sndbuf == Some(0) {
+            return Err(ConfigError::Invalid(format!("{}.sndbuf must be > 0", name)));
+        }
+
         Ok(())
     }
 
     fn validate_camera(&self, cam: &CameraConfig, name: &str) -> Result<(), ConfigError> {
+        validate_device(&cam.device)
+            .map_err(|e| ConfigError::Invalid(format!("{}: device: {}", name, e)))?;
+
         // Validate dimensions
         if cam.width == 0 || cam.height == 0 {
             return Err(ConfigError::Invalid(format!(
@@ -244,10 +1541,13 @@ impl Config {
             )));
         }
 
-        if cam.width % 8 != 0 || cam.height % 8 != 0 {
+        if (cam.width > MAX_DIMENSION || cam.height > MAX_DIMENSION)
+            && !self.mjpeg_rtp.oversize_dimensions
+        {
             return Err(ConfigError::Invalid(format!(
-                "{}: width and height must be multiples of 8",
-                name
+                "{}: {}x{} exceeds the RFC 2435 limit of {} pixels; set oversize_dimensions = true \
+                 to carry the size in the SDP instead",
+                name, cam.width, cam.height, MAX_DIMENSION
             )));
         }
 
@@ -267,14 +1567,171 @@ impl Config {
             )));
         }
 
-        // Validate destination port
-        if cam.dest_port == 0 {
+        if let Some(priority) = cam.affinity.sender_rt_priority {
+            if !(realtime::MIN_PRIORITY..=realtime::MAX_PRIORITY).contains(&priority) {
+                return Err(ConfigError::Invalid(format!(
+                    "{}: affinity.sender_rt_priority must be between {} and {}, got {}",
+                    name,
+                    realtime::MIN_PRIORITY,
+                    realtime::MAX_PRIORITY,
+                    priority
+                )));
+            }
+        }
+
+        if cam.dest_host.trim().is_empty() {
+            return Err(ConfigError::Invalid(format!(
+                "{}: dest_host must be set",
+                name
+            )));
+        }
+
+        // Validate destination port
+        if cam.dest_port == 0 {
             return Err(ConfigError::Invalid(format!(
                 "{}: dest_port must be > 0",
                 name
             )));
         }
 
+        if !cam.rtcp_mux && !cam.local_port.is_multiple_of(2) {
+            return Err(ConfigError::Invalid(format!(
+                "{}: local_port must be even so RTCP can use the port above it, got {} \
+                 (or set rtcp_mux = true)",
+                name, cam.local_port
+            )));
+        }
+
+        if !cam.failover.backups.is_empty() && cam.failover.timeout_ms == 0 {
+            return Err(ConfigError::Invalid(format!(
+                "{}: failover.timeout_ms must be > 0",
+                name
+            )));
+        }
+        if cam.failover.rtcp_port == Some(0) {
+            return Err(ConfigError::Invalid(format!(
+                "{}: failover.rtcp_port must be > 0",
+                name
+            )));
+        }
+        if cam.pull.enabled {
+            if cam.pull.idle_timeout_ms == 0 || cam.pull.max_receivers == 0 {
+                return Err(ConfigError::Invalid(format!(
+                    "{}: pull.idle_timeout_ms and pull.max_receivers must be > 0",
+                    name
+                )));
+            }
+            if !cam.failover.backups.is_empty() {
+                return Err(ConfigError::Invalid(format!(
+                    "{}: pull can't be combined with failover backups",
+                    name
+                )));
+            }
+        }
+        if cam.keepalive.interval_ms != 0 && cam.keepalive.interval_ms < MIN_KEEPALIVE_INTERVAL_MS {
+            return Err(ConfigError::Invalid(format!(
+                "{}: keepalive.interval_ms must be 0 (off) or at least {}",
+                name, MIN_KEEPALIVE_INTERVAL_MS
+            )));
+        }
+        if cam.max_kbps == Some(0) {
+            return Err(ConfigError::Invalid(format!(
+                "{}: max_kbps must be > 0",
+                name
+            )));
+        }
+
+        for processor in &cam.processors {
+            match processor {
+                ProcessorConfig::Decimate { keep_every: 0 } => {
+                    return Err(ConfigError::Invalid(format!(
+                        "{}: decimate keep_every must be > 0",
+                        name
+                    )));
+                }
+                ProcessorConfig::Wasm(_) if !cfg!(feature = "wasm") => {
+                    return Err(ConfigError::Invalid(format!(
+                        "{}: wasm processors need a build with --features wasm",
+                        name
+                    )));
+                }
+                ProcessorConfig::Wasm(plugin) if plugin.width == 0 || plugin.height == 0 => {
+                    return Err(ConfigError::Invalid(format!(
+                        "{}: wasm plugin width and height must be > 0",
+                        name
+                    )));
+                }
+                ProcessorConfig::Detect(_) if !cfg!(feature = "inference") => {
+                    return Err(ConfigError::Invalid(format!(
+                        "{}: detect processors need a build with --features inference",
+                        name
+                    )));
+                }
+                ProcessorConfig::Detect(detector) => Self::validate_detector(name, detector)?,
+                _ => {}
+            }
+        }
+
+        if cam.event_recording.dir.is_some() {
+            let analysed = cam
+                .processors
+                .iter()
+                .any(|p| matches!(p, ProcessorConfig::Wasm(_) | ProcessorConfig::Detect(_)));
+            if !analysed {
+                return Err(ConfigError::Invalid(format!(
+                    "{}: event_recording needs a wasm or detect processor to report events",
+                    name
+                )));
+            }
+            if cam.event_recording.max_clip_secs == 0 {
+                return Err(ConfigError::Invalid(format!(
+                    "{}: event_recording.max_clip_secs must be > 0",
+                    name
+                )));
+            }
+        }
+
+        if cam.record.is_some() && cam.replay.is_some() {
+            return Err(ConfigError::Invalid(format!(
+                "{}: set either record or replay, not both",
+                name
+            )));
+        }
+
+        let fragments = [&cam.pipeline.after_source, &cam.pipeline.before_encoder];
+        for fragment in fragments.into_iter().flatten() {
+            // The builder adds the links on both sides
+            let fragment = fragment.trim();
+            if fragment.starts_with('!') || fragment.ends_with('!') {
+                return Err(ConfigError::Invalid(format!(
+                    "{}: pipeline fragment \"{}\" must not start or end with a link",
+                    name, fragment
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn validate_detector(name: &str, detector: &DetectorConfig) -> Result<(), ConfigError> {
+        if detector.model.ends_with(".tflite") {
+            return Err(ConfigError::Invalid(format!(
+                "{}: {} is a TensorFlow Lite model; convert it to ONNX (e.g. with tf2onnx)",
+                name, detector.model
+            )));
+        }
+        if detector.input_width == 0 || detector.input_height == 0 {
+            return Err(ConfigError::Invalid(format!(
+                "{}: detect input_width and input_height must be > 0",
+                name
+            )));
+        }
+        if !(0.0..=1.0).contains(&detector.threshold) {
+            return Err(ConfigError::Invalid(format!(
+                "{}: detect threshold must be between 0 and 1",
+                name
+            )));
+        }
         Ok(())
     }
 
@@ -290,6 +1747,7 @@ impl Config {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::capture::PropertyValue;
 
     #[test]
     fn test_default_config() {
@@ -343,7 +1801,7 @@ ssrc = 0xCAFEBABE
         assert_eq!(config.mjpeg_rtp.camera1.quality, 95);
         assert_eq!(config.mjpeg_rtp.camera1.dest_host, "192.168.1.100");
         assert_eq!(config.mjpeg_rtp.camera1.dest_port, 5000);
-        assert_eq!(config.mjpeg_rtp.camera1.ssrc, 0xDEADBEEF);
+        assert_eq!(config.mjpeg_rtp.camera1.ssrc, Some(0xDEADBEEF));
     }
 
     #[test]
@@ -363,7 +1821,7 @@ mtu = 10000
 [mjpeg-rtp.camera1]
 enabled = true
 device = "0"
-width = 641
+width = 0
 height = 480
 dest_port = 5000
 ssrc = 123
@@ -373,6 +1831,785 @@ ssrc = 123
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_unaligned_dimensions_accepted() {
+        // Not a multiple of 8: the packetizer pads the advertised size
+        let toml = r#"
+[mjpeg-rtp.camera1]
+enabled = true
+device = "0"
+width = 1366
+height = 768
+dest_port = 5000
+ssrc = 123
+        "#;
+
+        let config = Config::from_str(toml).unwrap();
+        assert_eq!(config.mjpeg_rtp.camera1.width, 1366);
+    }
+
+    #[test]
+    fn test_ssrc_optional() {
+        let toml = r#"
+[mjpeg-rtp]
+state_dir = "/tmp/mjpeg-rtp"
+
+[mjpeg-rtp.camera1]
+enabled = true
+device = "0"
+dest_port = 5000
+        "#;
+
+        let config = Config::from_str(toml).unwrap();
+        assert_eq!(config.mjpeg_rtp.camera1.ssrc, None);
+        assert_eq!(config.mjpeg_rtp.state_dir, "/tmp/mjpeg-rtp");
+    }
+
+    #[test]
+    fn test_oversize_dimensions() {
+        let camera = r#"
+[mjpeg-rtp.camera1]
+enabled = true
+device = "0"
+width = 3840
+height = 2160
+dest_port = 5000
+ssrc = 123
+        "#;
+
+        assert!(Config::from_str(camera).is_err());
+
+        let toml = format!("[mjpeg-rtp]\noversize_dimensions = true\n{}", camera);
+        let config = Config::from_str(&toml).unwrap();
+        assert!(config.mjpeg_rtp.oversize_dimensions);
+    }
+
+    #[test]
+    fn test_timestamp_source() {
+        let config = Config::from_str("").unwrap();
+        assert_eq!(
+            config.mjpeg_rtp.timestamp_source,
+            TimestampSource::FrameCount
+        );
+
+        let toml = r#"
+[mjpeg-rtp]
+timestamp_source = "pts"
+        "#;
+        let config = Config::from_str(toml).unwrap();
+        assert_eq!(config.mjpeg_rtp.timestamp_source, TimestampSource::Pts);
+
+        let toml = r#"
+[mjpeg-rtp]
+timestamp_source = "sensor"
+        "#;
+        assert!(Config::from_str(toml).is_err());
+    }
+
+    #[test]
+    fn test_qtable_policy() {
+        let config = Config::from_str("").unwrap();
+        assert_eq!(config.mjpeg_rtp.qtable_policy, QTablePolicy::Always);
+        assert_eq!(config.mjpeg_rtp.qtable_refresh_frames, 30);
+
+        let toml = r#"
+[mjpeg-rtp]
+qtable_policy = "cached"
+qtable_refresh_frames = 0
+        "#;
+        let config = Config::from_str(toml).unwrap();
+        assert_eq!(config.mjpeg_rtp.qtable_policy, QTablePolicy::Cached);
+        assert_eq!(config.mjpeg_rtp.qtable_refresh_frames, 0);
+    }
+
+    #[test]
+    fn test_drift_correction() {
+        let config = Config::from_str("").unwrap();
+        assert!(config.mjpeg_rtp.drift_correction);
+
+        let toml = r#"
+[mjpeg-rtp]
+drift_correction = false
+        "#;
+        let config = Config::from_str(toml).unwrap();
+        assert!(!config.mjpeg_rtp.drift_correction);
+    }
+
+    #[test]
+    fn test_resume_rtp_state() {
+        let config = Config::from_str("").unwrap();
+        assert!(!config.mjpeg_rtp.resume_rtp_state);
+        assert_eq!(config.mjpeg_rtp.resume_max_gap_secs, 60);
+
+        let toml = r#"
+[mjpeg-rtp]
+resume_rtp_state = true
+resume_max_gap_secs = 10
+        "#;
+        let config = Config::from_str(toml).unwrap();
+        assert!(config.mjpeg_rtp.resume_rtp_state);
+        assert_eq!(config.mjpeg_rtp.resume_max_gap_secs, 10);
+    }
+
+    #[test]
+    fn test_api_listen() {
+        let config = Config::from_str("").unwrap();
+        assert_eq!(config.mjpeg_rtp.api_listen, None);
+
+        let toml = r#"
+[mjpeg-rtp]
+api_listen = "127.0.0.1:8090"
+        "#;
+        let config = Config::from_str(toml).unwrap();
+        assert_eq!(
+            config.mjpeg_rtp.api_listen,
+            Some("127.0.0.1:8090".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn test_congestion_section() {
+        let toml = r#"
+[mjpeg-rtp.congestion]
+controller = "aimd"
+target_bitrate_kbps = 8000
+        "#;
+
+        let config = Config::from_str(toml).unwrap();
+        let cc = &config.mjpeg_rtp.congestion;
+        assert_eq!(cc.controller, ControllerKind::Aimd);
+        assert_eq!(cc.target_bitrate_kbps, Some(8000));
+        assert_eq!(cc.min_bitrate_kbps, 500);
+        assert_eq!(cc.min_quality, 30);
+    }
+
+    #[test]
+    fn test_shaping_section() {
+        let toml = r#"
+[mjpeg-rtp.shaping]
+camera_kbps = 6000
+total_kbps = 10000
+
+[mjpeg-rtp.camera2]
+device = "1"
+dest_port = 5002
+max_kbps = 2000
+        "#;
+
+        let config = Config::from_str(toml).unwrap();
+        let cfg = &config.mjpeg_rtp;
+        assert_eq!(cfg.shaping.total_kbps, Some(10000));
+        assert_eq!(cfg.shaping.burst_ms, 100);
+        assert_eq!(cfg.max_kbps_for(&cfg.camera1), Some(6000));
+        assert_eq!(cfg.max_kbps_for(&cfg.camera2), Some(2000));
+        assert_eq!(MjpegRtpConfig::default().max_kbps_for(&cfg.camera1), None);
+
+        for section in [
+            "[mjpeg-rtp.shaping]\ntotal_kbps = 0",
+            "[mjpeg-rtp.camera1]\nenabled = true\ndevice = \"0\"\ndest_port = 5000\nmax_kbps = 0",
+        ] {
+            assert!(Config::from_str(section).is_err(), "accepted {:?}", section);
+        }
+    }
+
+    #[test]
+    fn test_telemetry_section() {
+        let toml = r#"
+[telemetry]
+enabled = true
+otlp_endpoint = "http://collector:4317"
+instance_id = "pi-garage"
+        "#;
+
+        let config = Config::from_str(toml).unwrap();
+        assert!(config.telemetry.enabled);
+        assert_eq!(config.telemetry.otlp_endpoint, "http://collector:4317");
+        assert_eq!(config.telemetry.service_name, "rust-mjpeg-rtp");
+        assert_eq!(config.telemetry.instance_id.as_deref(), Some("pi-garage"));
+        assert_eq!(config.telemetry.metrics_interval_seconds, 10);
+    }
+
+    #[test]
+    fn test_platform_overrides() {
+        let toml = r#"
+[mjpeg-rtp.platform]
+encoder = "software"
+max_width = 1280
+        "#;
+
+        let config = Config::from_str(toml).unwrap();
+        let preset = config.mjpeg_rtp.platform.resolve(PiModel::Pi4);
+        assert_eq!(preset.model, PiModel::Pi4);
+        assert_eq!(preset.encoder, JpegEncoder::Software);
+        assert_eq!(preset.max_width, Some(1280));
+        assert_eq!(preset.max_height, Some(1080));
+    }
+
+    #[test]
+    fn test_platform_forced_model() {
+        let toml = r#"
+[mjpeg-rtp.platform]
+model = "zero2"
+        "#;
+
+        let config = Config::from_str(toml).unwrap();
+        let preset = config.mjpeg_rtp.platform.resolve(PiModel::Pi5);
+        assert_eq!(preset, PiModel::Zero2.preset());
+    }
+
+    #[test]
+    fn test_camera_affinity() {
+        let toml = r#"
+[mjpeg-rtp.camera1]
+enabled = true
+device = "0"
+dest_port = 5000
+ssrc = 1
+
+[mjpeg-rtp.camera1.affinity]
+sender_core = 3
+encoder_core = 2
+        "#;
+
+        let config = Config::from_str(toml).unwrap();
+        let affinity = &config.mjpeg_rtp.camera1.affinity;
+        assert_eq!(affinity.sender_core, Some(3));
+        assert_eq!(affinity.encoder_core, Some(2));
+        assert_eq!(affinity.capture_core, None);
+        assert_eq!(config.mjpeg_rtp.camera2.affinity.sender_core, None);
+    }
+
+    #[test]
+    fn test_record_and_replay_are_exclusive() {
+        let toml = r#"
+[mjpeg-rtp.camera1]
+enabled = true
+device = "0"
+dest_port = 5000
+record = "/tmp/camera1.frames"
+        "#;
+        let config = Config::from_str(toml).unwrap();
+        assert_eq!(
+            config.mjpeg_rtp.camera1.record.as_deref(),
+            Some("/tmp/camera1.frames")
+        );
+        assert!(config.mjpeg_rtp.camera1.replay.is_none());
+
+        let replay = toml.replace("record =", "replay_pacing = \"fps\"\nreplay =");
+        let config = Config::from_str(&replay).unwrap();
+        assert_eq!(config.mjpeg_rtp.camera1.replay_pacing, ReplayPacing::Fps);
+
+        let toml = format!("{}replay = \"/tmp/camera1.frames\"\n", toml);
+        assert!(Config::from_str(&toml).is_err());
+    }
+
+    #[test]
+    fn test_pipeline_overrides() {
+        let toml = r#"
+[mjpeg-rtp.camera1]
+enabled = true
+device = "0"
+dest_port = 5000
+[mjpeg-rtp.camera1.pipeline]
+after_source = "videoconvert ! bilateral name=denoise"
+[mjpeg-rtp.camera1.pipeline.properties]
+jpegenc = { idct-method = "float" }
+denoise = { sigma-color = 0.1 }
+        "#;
+        let config = Config::from_str(toml).unwrap();
+        let pipeline = &config.mjpeg_rtp.camera1.pipeline;
+        assert_eq!(
+            pipeline.properties["denoise"]["sigma-color"],
+            PropertyValue::Float(0.1)
+        );
+
+        let dangling = toml.replace("name=denoise\"", "name=denoise !\"");
+        assert!(Config::from_str(&dangling).is_err());
+    }
+
+    #[test]
+    fn test_network_camera_device() {
+        let camera = |device: &str| {
+            format!(
+                "[mjpeg-rtp.camera1]\nenabled = true\ndevice = \"{}\"\ndest_port = 5000\n",
+                device
+            )
+        };
+        assert!(Config::from_str(&camera("rtsp://admin:pw@10.0.0.5:554/stream1")).is_ok());
+        assert!(Config::from_str(&camera("http://10.0.0.6/mjpg/video.mjpg")).is_ok());
+        assert!(Config::from_str(&camera("onvif://10.0.0.5")).is_err());
+    }
+
+    #[test]
+    fn test_local_port_must_be_even_without_rtcp_mux() {
+        let camera = |extra: &str| {
+            format!(
+                "[mjpeg-rtp.camera1]\nenabled = true\ndevice = \"0\"\ndest_port = 5000\n{}",
+                extra
+            )
+        };
+        assert!(Config::from_str(&camera("local_port = 6000")).is_ok());
+        assert!(Config::from_str(&camera("local_port = 6001")).is_err());
+
+        let config = Config::from_str(&camera("local_port = 6001\nrtcp_mux = true")).unwrap();
+        assert!(config.mjpeg_rtp.camera1.rtcp_mux);
+    }
+
+    #[test]
+    fn test_invalid_rt_priority() {
+        let toml = r#"
+[mjpeg-rtp.camera1]
+enabled = true
+device = "0"
+dest_port = 5000
+ssrc = 1
+
+[mjpeg-rtp.camera1.affinity]
+sender_rt_priority = 0
+        "#;
+
+        assert!(Config::from_str(toml).is_err());
+    }
+
+    #[test]
+    fn test_network_inheritance() {
+        let toml = r#"
+[mjpeg-rtp]
+mtu = 1400
+dscp = 10
+
+[mjpeg-rtp.network]
+mtu = 1200
+dscp = 46
+sndbuf = 1048576
+bind_address = "192.168.1.10"
+
+[mjpeg-rtp.camera1]
+enabled = true
+device = "0"
+dest_port = 5000
+
+[mjpeg-rtp.camera1.network]
+mtu = 9000
+tos = 0xb9
+
+[mjpeg-rtp.camera2]
+enabled = true
+device = "1"
+dest_port = 5002
+        "#;
+
+        let config = Config::from_str(toml).unwrap();
+        let rtp = &config.mjpeg_rtp;
+
+        // camera1 overrides mtu and the traffic class, inherits the rest
+        let camera1 = rtp.network_for(&rtp.camera1);
+        assert_eq!(camera1.mtu, 9000);
+        assert_eq!(camera1.dscp, 0);
+        assert_eq!(camera1.tos, Some(0xb9));
+        assert_eq!(camera1.sndbuf, Some(1 << 20));
+        assert_eq!(camera1.bind_address, Some("192.168.1.10".parse().unwrap()));
+
+        // camera2 takes everything from [mjpeg-rtp.network]
+        let camera2 = rtp.network_for(&rtp.camera2);
+        assert_eq!(camera2.mtu, 1200);
+        assert_eq!(camera2.dscp, 46);
+        assert_eq!(camera2.tos, None);
+    }
+
+    #[test]
+    fn test_network_falls_back_to_top_level() {
+        let toml = r#"
+[mjpeg-rtp]
+mtu = 1300
+dscp = 34
+        "#;
+
+        let config = Config::from_str(toml).unwrap();
+        let rtp = &config.mjpeg_rtp;
+        let network = rtp.network_for(&rtp.camera1);
+        assert_eq!(network.mtu, 1300);
+        assert_eq!(network.dscp, 34);
+        assert_eq!(network.tos, None);
+        assert_eq!(network.sndbuf, None);
+        assert_eq!(network.bind_address, None);
+    }
+
+    #[test]
+    fn test_invalid_network() {
+        for section in [
+            "[mjpeg-rtp.network]\nmtu = 100",
+            "[mjpeg-rtp.network]\ndscp = 64",
+            "[mjpeg-rtp.network]\ndscp = 46\ntos = 184",
+            "[mjpeg-rtp.network]\nsndbuf = 0",
+        ] {
+            assert!(Config::from_str(section).is_err(), "accepted {:?}", section);
+        }
+    }
+
+    #[test]
+    fn test_failover_config() {
+        let toml = r#"
+[mjpeg-rtp.camera1]
+enabled = true
+device = "0"
+dest_host = "10.0.0.5"
+dest_port = 5000
+
+[mjpeg-rtp.camera1.failover]
+backups = ["10.0.0.6:5000", "10.0.0.7:5004"]
+rtcp_port = 5001
+        "#;
+        let config = Config::from_str(toml).unwrap();
+        let failover = &config.mjpeg_rtp.camera1.failover;
+        assert_eq!(failover.backups.len(), 2);
+        assert_eq!(failover.backups[1], "10.0.0.7:5004".parse().unwrap());
+        assert_eq!(failover.rtcp_port, Some(5001));
+        assert_eq!(failover.timeout_ms, 5000);
+
+        assert!(config.mjpeg_rtp.camera2.failover.backups.is_empty());
+
+        let bad = toml.replace("rtcp_port = 5001", "timeout_ms = 0");
+        assert!(Config::from_str(&bad).is_err());
+    }
+
+    #[test]
+    fn test_keepalive_config() {
+        let config = Config::default();
+        assert_eq!(config.mjpeg_rtp.camera1.keepalive.interval_ms, 0);
+
+        let toml = r#"
+[mjpeg-rtp.camera1]
+enabled = true
+device = "0"
+dest_host = "10.0.0.5"
+dest_port = 5000
+
+[mjpeg-rtp.camera1.keepalive]
+interval_ms = 15000
+kind = "rtcp"
+        "#;
+        let config = Config::from_str(toml).unwrap();
+        let keepalive = &config.mjpeg_rtp.camera1.keepalive;
+        assert_eq!(keepalive.interval_ms, 15000);
+        assert_eq!(keepalive.kind, KeepaliveKind::Rtcp);
+
+        let bad = toml.replace("interval_ms = 15000", "interval_ms = 10");
+        assert!(Config::from_str(&bad).is_err());
+    }
+
+    #[test]
+    fn test_mdns_config() {
+        let toml = r#"
+[mjpeg-rtp.mdns]
+enabled = true
+hostname = "garage-pi"
+        "#;
+        let config = Config::from_str(toml).unwrap();
+        assert_eq!(config.mjpeg_rtp.mdns.service, "_mjpeg-rtp._udp");
+        assert_eq!(config.mjpeg_rtp.mdns.hostname.as_deref(), Some("garage-pi"));
+
+        let bad = format!("{}service = \"mjpeg\"\n", toml);
+        assert!(matches!(
+            Config::from_str(&bad),
+            Err(ConfigError::Invalid(_))
+        ));
+        let bad = toml.replace("garage-pi", "garage-pi.local");
+        assert!(matches!(
+            Config::from_str(&bad),
+            Err(ConfigError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn test_pull_config() {
+        let config = Config::default();
+        assert!(!config.mjpeg_rtp.camera1.pull.enabled);
+
+        let toml = r#"
+[mjpeg-rtp.camera1]
+enabled = true
+device = "0"
+dest_port = 5000
+
+[mjpeg-rtp.camera1.pull]
+enabled = true
+idle_timeout_ms = 5000
+
+[mjpeg-rtp.camera2]
+enabled = true
+device = "1"
+dest_port = 5002
+
+[mjpeg-rtp.camera2.pull]
+enabled = true
+listen = "0.0.0.0:5701"
+allow = ["192.168.1.50"]
+        "#;
+        let config = Config::from_str(toml).unwrap();
+        let pull = &config.mjpeg_rtp.camera1.pull;
+        assert_eq!(pull.listen, "0.0.0.0:5700".parse().unwrap());
+        assert_eq!(pull.idle_timeout_ms, 5000);
+        assert_eq!(pull.max_receivers, 4);
+        assert!(pull.allow.is_empty());
+        let allow = &config.mjpeg_rtp.camera2.pull.allow;
+        assert_eq!(*allow, vec!["192.168.1.50".parse::<IpAddr>().unwrap()]);
+
+        // One port can't take requests for both
+        let shared = toml.replace("listen = \"0.0.0.0:5701\"", "");
+        assert!(Config::from_str(&shared).is_err());
+        let bad = toml.replace("idle_timeout_ms = 5000", "max_receivers = 0");
+        assert!(Config::from_str(&bad).is_err());
+    }
+
+    #[test]
+    fn test_coordination_section() {
+        let config = Config::default();
+        assert!(!config.mjpeg_rtp.coordination.enabled);
+
+        let toml = r#"
+[mjpeg-rtp.coordination]
+enabled = true
+peers = ["192.168.1.21:5600"]
+priority = 150
+        "#;
+        let config = Config::from_str(toml).unwrap();
+        let coord = &config.mjpeg_rtp.coordination;
+        assert_eq!(coord.listen, "0.0.0.0:5600".parse().unwrap());
+        assert_eq!(coord.peers, vec!["192.168.1.21:5600".parse().unwrap()]);
+        assert_eq!(coord.priority, 150);
+        assert_eq!(coord.node_id, None);
+        assert_eq!(coord.heartbeat_interval_ms, 200);
+        assert_eq!(coord.takeover_timeout_ms, 1000);
+
+        let no_peers = toml.replace(r#"peers = ["192.168.1.21:5600"]"#, "");
+        assert!(Config::from_str(&no_peers).is_err());
+        let hasty = format!("{}\ntakeover_timeout_ms = 300\n", toml.trim_end());
+        assert!(Config::from_str(&hasty).is_err());
+    }
+
+    #[test]
+    fn test_relay_section() {
+        let config = Config::default();
+        assert!(!config.mjpeg_rtp.relay.enabled);
+
+        let toml = r#"
+[mjpeg-rtp.relay]
+enabled = true
+destinations = ["192.168.1.100:5000", "192.168.1.101:5000"]
+        "#;
+        let config = Config::from_str(toml).unwrap();
+        let relay = &config.mjpeg_rtp.relay;
+        assert_eq!(relay.listen, "0.0.0.0:5000".parse().unwrap());
+        assert_eq!(relay.destinations.len(), 2);
+        assert_eq!(relay.ssrc, None);
+
+        let nowhere = toml.replace(r#""192.168.1.100:5000", "192.168.1.101:5000""#, "");
+        assert!(Config::from_str(&nowhere).is_err());
+        let looped = format!("{}\nlisten = \"192.168.1.100:5000\"\n", toml.trim_end());
+        assert!(Config::from_str(&looped).is_err());
+    }
+
+    #[test]
+    fn test_transcode_section() {
+        let config = Config::default();
+        assert!(!config.mjpeg_rtp.transcode.enabled);
+
+        let toml = r#"
+[mjpeg-rtp.camera1]
+enabled = true
+device = "0"
+width = 1920
+height = 1080
+fps = 30
+quality = 95
+dest_host = "192.168.1.100"
+dest_port = 5000
+ssrc = 0xDEADBEEF
+record = "/data/camera1.frames"
+[mjpeg-rtp.camera1.event_recording]
+dir = "/data/clips"
+[mjpeg-rtp.transcode]
+enabled = true
+        "#;
+        // Parsed only: event recording needs a processor, which needs a build feature
+        let config: Config = toml::from_str(toml).unwrap();
+        let transcode = &config.mjpeg_rtp.transcode;
+        assert_eq!(transcode.after_hours, 24);
+        assert_eq!(transcode.encoder, TranscodeEncoder::Ffmpeg);
+        assert_eq!((transcode.crf, transcode.nice), (28, 19));
+        assert_eq!(
+            config.mjpeg_rtp.transcode_dirs(),
+            vec![PathBuf::from("/data"), PathBuf::from("/data/clips")]
+        );
+
+        let toml = r#"
+[mjpeg-rtp.transcode]
+enabled = true
+dirs = ["/archive"]
+encoder = "gstreamer"
+command = ["sh", "-c", "cat > {output}"]
+        "#;
+        let config = Config::from_str(toml).unwrap();
+        assert_eq!(
+            config.mjpeg_rtp.transcode_dirs(),
+            vec![PathBuf::from("/archive")]
+        );
+        assert_eq!(
+            config.mjpeg_rtp.transcode.encoder,
+            TranscodeEncoder::Gstreamer
+        );
+
+        let nowhere = toml.replace(r#"dirs = ["/archive"]"#, "");
+        assert!(Config::from_str(&nowhere).is_err());
+        let no_output = toml.replace("cat > {output}", "cat");
+        assert!(Config::from_str(&no_output).is_err());
+        let bad_crf = format!("{}\ncrf = 60\n", toml.trim_end());
+        assert!(Config::from_str(&bad_crf).is_err());
+    }
+
+    #[test]
+    fn test_bundle_section() {
+        let camera = |n: u32| {
+            format!(
+                r#"
+[mjpeg-rtp.camera{n}]
+enabled = true
+device = "{n}"
+width = 1280
+height = 720
+fps = 30
+quality = 85
+dest_host = "192.168.1.100"
+dest_port = 5000
+local_port = 6000
+rtcp_mux = true
+ssrc = {n}
+"#
+            )
+        };
+        let toml = format!("[mjpeg-rtp]\nbundle = true\n{}{}", camera(1), camera(2));
+        let config = Config::from_str(&toml).unwrap();
+        assert!(config.mjpeg_rtp.bundle);
+
+        let unmuxed = toml.replacen("rtcp_mux = true", "rtcp_mux = false", 1);
+        assert!(Config::from_str(&unmuxed).is_err());
+        let split = toml.replacen("dest_port = 5000", "dest_port = 5002", 1);
+        assert!(Config::from_str(&split).is_err());
+        let same_ssrc = toml.replacen("ssrc = 1", "ssrc = 2", 1);
+        assert!(Config::from_str(&same_ssrc).is_err());
+        let single = format!("[mjpeg-rtp]\nbundle = true\n{}", camera(1));
+        assert!(Config::from_str(&single).is_err());
+    }
+
+    #[test]
+    fn test_processors() {
+        assert!(Config::default().mjpeg_rtp.camera1.processors.is_empty());
+
+        let toml = r#"
+[mjpeg-rtp.camera1]
+enabled = true
+device = "0"
+dest_port = 5000
+processors = [{ type = "decimate", keep_every = 2 }, { type = "dedup" }]
+        "#;
+        let config = Config::from_str(toml).unwrap();
+        assert_eq!(
+            config.mjpeg_rtp.camera1.processors,
+            vec![
+                ProcessorConfig::Decimate { keep_every: 2 },
+                ProcessorConfig::Dedup { keepalive: 30 },
+            ]
+        );
+
+        assert!(Config::from_str(&toml.replace("keep_every = 2", "keep_every = 0")).is_err());
+
+        let wasm = toml.replace(
+            r#"type = "dedup""#,
+            r#"type = "wasm", path = "people.wasm""#,
+        );
+        let parsed = Config::from_str(&wasm);
+        if cfg!(feature = "wasm") {
+            let processors = parsed.unwrap().mjpeg_rtp.camera1.processors;
+            let ProcessorConfig::Wasm(plugin) = &processors[1] else {
+                panic!("{:?}", processors);
+            };
+            assert_eq!(plugin.path, "people.wasm");
+            assert_eq!((plugin.width, plugin.height), (320, 240));
+        } else {
+            assert!(parsed.is_err());
+        }
+        assert!(Config::from_str(&toml.replace("\"dedup\"", "\"blur\"")).is_err());
+
+        let detect = toml.replace(
+            r#"type = "dedup""#,
+            r#"type = "detect", model = "yolov8n.onnx""#,
+        );
+        let parsed = Config::from_str(&detect);
+        if cfg!(feature = "inference") {
+            let processors = parsed.unwrap().mjpeg_rtp.camera1.processors;
+            let ProcessorConfig::Detect(detector) = &processors[1] else {
+                panic!("{:?}", processors);
+            };
+            assert_eq!(detector.format, DetectionFormat::Yolo);
+            assert_eq!((detector.input_width, detector.input_height), (320, 320));
+            assert_eq!(detector.threshold, 0.5);
+            assert!(!detector.overlay);
+
+            let tflite = detect.replace("yolov8n.onnx", "ssd.tflite");
+            assert!(Config::from_str(&tflite).is_err());
+            let threshold = detect.replace(".onnx\"", ".onnx\", threshold = 1.5");
+            assert!(Config::from_str(&threshold).is_err());
+        } else {
+            assert!(parsed.is_err());
+        }
+    }
+
+    #[test]
+    fn test_event_recording() {
+        assert_eq!(
+            Config::default().mjpeg_rtp.camera1.event_recording.dir,
+            None
+        );
+
+        let toml = r#"
+[mjpeg-rtp.camera1]
+enabled = true
+device = "0"
+dest_port = 5000
+
+[mjpeg-rtp.camera1.event_recording]
+dir = "/var/lib/clips"
+sources = ["people"]
+        "#;
+        // Nothing would report events
+        assert!(Config::from_str(toml).is_err());
+
+        if cfg!(feature = "wasm") {
+            let toml = toml.replace(
+                "dest_port = 5000",
+                "dest_port = 5000\nprocessors = [{ type = \"wasm\", path = \"people.wasm\" }]",
+            );
+            let recording = Config::from_str(&toml)
+                .unwrap()
+                .mjpeg_rtp
+                .camera1
+                .event_recording;
+            assert_eq!(recording.dir.as_deref(), Some("/var/lib/clips"));
+            assert_eq!(recording.sources, vec!["people"]);
+            assert_eq!(
+                (
+                    recording.pre_roll_ms,
+                    recording.post_roll_ms,
+                    recording.max_clip_secs
+                ),
+                (5000, 10000, 300)
+            );
+            let unbounded = toml.replace("sources", "max_clip_secs = 0\nsources");
+            assert!(Config::from_str(&unbounded).is_err());
+        }
+    }
+
     #[test]
     fn test_roundtrip() {
         let config = Config::default();
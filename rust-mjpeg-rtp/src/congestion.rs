@@ -0,0 +1,276 @@
+//! Pluggable congestion control for the RTP path
+//!
+//! A [`CongestionController`] turns receiver feedback into a target bitrate.
+//! [`AdaptiveQuality`] then steers the JPEG quality so the measured send rate
+//! tracks that target; MJPEG has no rate control of its own, so quality is
+//! the only knob. Two controllers are provided: [`FixedRate`] and a
+//! loss-based AIMD ([`LossBasedAimd`]). Research controllers (GCC, NADA, ...)
+//! only need to implement the trait.
+
+use crate::config::CongestionConfig;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+/// Receiver feedback, as carried by an RTCP receiver report
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RtcpFeedback {
+    /// Fraction of packets lost since the previous report (0.0 - 1.0)
+    pub fraction_lost: f32,
+
+    /// Interarrival jitter in RTP timestamp units
+    pub jitter: u32,
+
+    /// Round-trip time, when it could be computed from LSR/DLSR
+    pub rtt: Option<Duration>,
+}
+
+/// Computes a target send bitrate from receiver feedback
+pub trait CongestionController: Send {
+    /// Feeds one receiver report into the controller
+    fn on_rtcp_feedback(&mut self, feedback: &RtcpFeedback, now: Instant);
+
+    /// Current target bitrate (bits per second)
+    fn target_bitrate(&self) -> u64;
+
+    /// Short name for logs
+    fn name(&self) -> &'static str;
+}
+
+/// Ignores feedback and always targets the same bitrate
+#[derive(Debug, Clone)]
+pub struct FixedRate {
+    bitrate: u64,
+}
+
+impl FixedRate {
+    pub fn new(bitrate: u64) -> Self {
+        Self { bitrate }
+    }
+}
+
+impl CongestionController for FixedRate {
+    fn on_rtcp_feedback(&mut self, _feedback: &RtcpFeedback, _now: Instant) {}
+
+    fn target_bitrate(&self) -> u64 {
+        self.bitrate
+    }
+
+    fn name(&self) -> &'static str {
+        "fixed"
+    }
+}
+
+/// Loss-based additive-increase / multiplicative-decrease
+///
+/// Above 10% loss the rate is cut in proportion to the loss (as in the
+/// loss-based half of GCC); below 2% it grows by a fixed step at most once
+/// per [`LossBasedAimd::INCREASE_INTERVAL`]; in between it holds.
+#[derive(Debug, Clone)]
+pub struct LossBasedAimd {
+    bitrate: u64,
+    min_bitrate: u64,
+    max_bitrate: u64,
+    increase_step: u64,
+    last_increase: Option<Instant>,
+}
+
+impl LossBasedAimd {
+    pub const HIGH_LOSS: f32 = 0.10;
+    pub const LOW_LOSS: f32 = 0.02;
+    pub const INCREASE_INTERVAL: Duration = Duration::from_secs(1);
+
+    pub fn new(start_bitrate: u64, min_bitrate: u64, max_bitrate: u64) -> Self {
+        Self {
+            bitrate: start_bitrate.clamp(min_bitrate, max_bitrate),
+            min_bitrate,
+            max_bitrate,
+            // ~5% of the range per step
+            increase_step: ((max_bitrate - min_bitrate) / 20).max(1),
+            last_increase: None,
+        }
+    }
+}
+
+impl CongestionController for LossBasedAimd {
+    fn on_rtcp_feedback(&mut self, feedback: &RtcpFeedback, now: Instant) {
+        let loss = feedback.fraction_lost.clamp(0.0, 1.0);
+
+        if loss > Self::HIGH_LOSS {
+            let factor = 1.0 - 0.5 * loss as f64;
+            self.bitrate = ((self.bitrate as f64 * factor).round() as u64).max(self.min_bitrate);
+        } else if loss < Self::LOW_LOSS {
+            let due = self
+                .last_increase
+                .is_none_or(|t| now.duration_since(t) >= Self::INCREASE_INTERVAL);
+            if due {
+                self.bitrate = (self.bitrate + self.increase_step).min(self.max_bitrate);
+                self.last_increase = Some(now);
+            }
+        }
+    }
+
+    fn target_bitrate(&self) -> u64 {
+        self.bitrate
+    }
+
+    fn name(&self) -> &'static str {
+        "aimd"
+    }
+}
+
+/// Which controller to use, from configuration
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ControllerKind {
+    #[default]
+    Fixed,
+    Aimd,
+}
+
+/// Steers JPEG quality so the send rate follows the controller's target
+pub struct AdaptiveQuality {
+    controller: Box<dyn CongestionController>,
+    quality: u32,
+    min_quality: u32,
+    max_quality: u32,
+}
+
+impl AdaptiveQuality {
+    pub fn new(
+        controller: Box<dyn CongestionController>,
+        quality: u32,
+        min_quality: u32,
+        max_quality: u32,
+    ) -> Self {
+        Self {
+            controller,
+            quality: quality.clamp(min_quality, max_quality),
+            min_quality,
+            max_quality,
+        }
+    }
+
+    /// Builds the configured controller, or `None` when adaptation is off
+    /// (no `target_bitrate_kbps`)
+    pub fn from_config(config: &CongestionConfig, quality: u32) -> Option<Self> {
+        let target = config.target_bitrate_kbps? * 1000;
+        let controller: Box<dyn CongestionController> = match config.controller {
+            ControllerKind::Fixed => Box::new(FixedRate::new(target)),
+            ControllerKind::Aimd => Box::new(LossBasedAimd::new(
+                target,
+                config.min_bitrate_kbps * 1000,
+                config.max_bitrate_kbps * 1000,
+            )),
+        };
+        Some(Self::new(controller, quality, config.min_quality, quality))
+    }
+
+    pub fn on_rtcp_feedback(&mut self, feedback: &RtcpFeedback, now: Instant) {
+        self.controller.on_rtcp_feedback(feedback, now);
+    }
+
+    pub fn controller(&self) -> &dyn CongestionController {
+        self.controller.as_ref()
+    }
+
+    pub fn quality(&self) -> u32 {
+        self.quality
+    }
+
+    /// Adjusts quality given the measured send rate (bits per second)
+    ///
+    /// Returns the new quality when it changed. A dead band of -20%/+10%
+    /// around the target avoids oscillating on frame-size noise.
+    pub fn update(&mut self, observed_bitrate: u64) -> Option<u32> {
+        let target = self.controller.target_bitrate().max(1) as f64;
+        let ratio = observed_bitrate as f64 / target;
+
+        let step: i64 = if ratio > 1.5 {
+            -5
+        } else if ratio > 1.1 {
+            -2
+        } else if ratio < 0.5 {
+            5
+        } else if ratio < 0.8 {
+            2
+        } else {
+            0
+        };
+
+        let quality = (self.quality as i64 + step)
+            .clamp(self.min_quality as i64, self.max_quality as i64) as u32;
+        if quality == self.quality {
+            return None;
+        }
+        self.quality = quality;
+        Some(quality)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn feedback(fraction_lost: f32) -> RtcpFeedback {
+        RtcpFeedback {
+            fraction_lost,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_fixed_rate_ignores_feedback() {
+        let mut cc = FixedRate::new(4_000_000);
+        cc.on_rtcp_feedback(&feedback(0.5), Instant::now());
+        assert_eq!(cc.target_bitrate(), 4_000_000);
+    }
+
+    #[test]
+    fn test_aimd_decreases_on_loss() {
+        let mut cc = LossBasedAimd::new(10_000_000, 1_000_000, 20_000_000);
+        cc.on_rtcp_feedback(&feedback(0.2), Instant::now());
+        assert_eq!(cc.target_bitrate(), 9_000_000);
+
+        for _ in 0..50 {
+            cc.on_rtcp_feedback(&feedback(1.0), Instant::now());
+        }
+        assert_eq!(cc.target_bitrate(), 1_000_000);
+    }
+
+    #[test]
+    fn test_aimd_increases_at_most_once_per_interval() {
+        let mut cc = LossBasedAimd::new(10_000_000, 0, 20_000_000);
+        let t0 = Instant::now();
+
+        cc.on_rtcp_feedback(&feedback(0.0), t0);
+        cc.on_rtcp_feedback(&feedback(0.0), t0 + Duration::from_millis(100));
+        assert_eq!(cc.target_bitrate(), 11_000_000);
+
+        cc.on_rtcp_feedback(&feedback(0.0), t0 + Duration::from_secs(1));
+        assert_eq!(cc.target_bitrate(), 12_000_000);
+    }
+
+    #[test]
+    fn test_aimd_holds_on_moderate_loss() {
+        let mut cc = LossBasedAimd::new(10_000_000, 0, 20_000_000);
+        cc.on_rtcp_feedback(&feedback(0.05), Instant::now());
+        assert_eq!(cc.target_bitrate(), 10_000_000);
+    }
+
+    #[test]
+    fn test_adaptive_quality_tracks_target() {
+        let mut aq = AdaptiveQuality::new(Box::new(FixedRate::new(1_000_000)), 85, 30, 95);
+
+        assert_eq!(aq.update(2_000_000), Some(80));
+        assert_eq!(aq.update(1_200_000), Some(78));
+        assert_eq!(aq.update(1_000_000), None);
+        assert_eq!(aq.update(700_000), Some(80));
+    }
+
+    #[test]
+    fn test_adaptive_quality_clamps() {
+        let mut aq = AdaptiveQuality::new(Box::new(FixedRate::new(1_000_000)), 32, 30, 95);
+        assert_eq!(aq.update(10_000_000), Some(30));
+        assert_eq!(aq.update(10_000_000), None);
+    }
+}
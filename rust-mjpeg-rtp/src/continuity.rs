@@ -0,0 +1,178 @@
+//! RTP numbering carried across restarts
+//!
+//! With `resume_rtp_state` set, a camera saves where its stream left off
+//! (SSRC, next sequence number, last timestamp) under the state directory
+//! when it stops, and continues from there when it starts again soon after.
+//! Recorders and receivers that key on SSRC and sequence continuity then see
+//! a short gap instead of a new stream. The timestamp is advanced by the
+//! time the stream was down, so the gap also shows on the RTP clock.
+
+use crate::error::ErrorCode;
+use crate::rtp::RTP_CLOCK_RATE;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum ContinuityError {
+    #[error("failed to access RTP state file {path}: {source}")]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[error("RTP state file {path} is not valid: {source}")]
+    Corrupt {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+}
+
+impl ContinuityError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            ContinuityError::Io { .. } => ErrorCode::Io,
+            ContinuityError::Corrupt { .. } => ErrorCode::InvalidInput,
+        }
+    }
+}
+
+/// Where a camera's RTP stream left off
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RtpState {
+    pub ssrc: u32,
+    /// Sequence number of the next packet
+    pub next_sequence: u16,
+    /// Timestamp of the last frame sent
+    pub timestamp: u32,
+    /// When the stream stopped, in milliseconds since the Unix epoch
+    pub saved_at_ms: u64,
+}
+
+impl RtpState {
+    /// The state of a stream stopping now
+    pub fn new(ssrc: u32, next_sequence: u16, timestamp: u32) -> Self {
+        Self {
+            ssrc,
+            next_sequence,
+            timestamp,
+            saved_at_ms: unix_ms(SystemTime::now()),
+        }
+    }
+
+    /// `<state_dir>/<name>.rtp.json`
+    pub fn path(state_dir: impl AsRef<Path>, name: &str) -> PathBuf {
+        state_dir.as_ref().join(format!("{}.rtp.json", name))
+    }
+
+    /// Reads a camera's saved state, `None` if there is none
+    pub fn load(state_dir: impl AsRef<Path>, name: &str) -> Result<Option<Self>, ContinuityError> {
+        let path = Self::path(state_dir, name);
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(source) => return Err(ContinuityError::Io { path, source }),
+        };
+        serde_json::from_str(&content)
+            .map(Some)
+            .map_err(|source| ContinuityError::Corrupt { path, source })
+    }
+
+    /// Writes the state for the camera's next start, replacing the old one
+    pub fn save(&self, state_dir: impl AsRef<Path>, name: &str) -> Result<(), ContinuityError> {
+        let state_dir = state_dir.as_ref();
+        let path = Self::path(state_dir, name);
+        let io_err = |source| ContinuityError::Io {
+            path: path.clone(),
+            source,
+        };
+        fs::create_dir_all(state_dir).map_err(io_err)?;
+
+        // Write-then-rename so a crash never leaves a truncated state behind
+        let tmp = path.with_extension("json.tmp");
+        let json = serde_json::to_vec(self).expect("plain struct serializes");
+        let mut file = fs::File::create(&tmp).map_err(io_err)?;
+        file.write_all(&json).map_err(io_err)?;
+        file.sync_all().map_err(io_err)?;
+        fs::rename(&tmp, &path).map_err(io_err)
+    }
+
+    /// Sequence number and first timestamp to continue with at `now`, or
+    /// `None` if the stream has been down longer than `max_gap` (or the
+    /// clock went backwards since it stopped)
+    pub fn resume_at(&self, now: SystemTime, max_gap: Duration) -> Option<(u16, u32)> {
+        let gap = Duration::from_millis(unix_ms(now).checked_sub(self.saved_at_ms)?);
+        if gap > max_gap {
+            return None;
+        }
+        // At least one tick, so the first frame never repeats the last one's
+        // timestamp
+        let ticks = (gap.as_millis() as u64 * RTP_CLOCK_RATE as u64 / 1000).max(1);
+        Some((
+            self.next_sequence,
+            self.timestamp.wrapping_add(ticks as u32),
+        ))
+    }
+}
+
+fn unix_ms(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_millis() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_state_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(RtpState::load(dir.path(), "camera1").unwrap(), None);
+
+        let state = RtpState::new(0xDEADBEEF, 65535, 123_456);
+        state.save(dir.path().join("state"), "camera1").unwrap();
+        assert_eq!(
+            RtpState::load(dir.path().join("state"), "camera1").unwrap(),
+            Some(state)
+        );
+
+        fs::write(RtpState::path(dir.path(), "camera2"), "{\"ssrc\":1}").unwrap();
+        assert!(matches!(
+            RtpState::load(dir.path(), "camera2"),
+            Err(ContinuityError::Corrupt { .. })
+        ));
+    }
+
+    #[test]
+    fn test_resume_advances_timestamp_by_gap() {
+        let state = RtpState {
+            ssrc: 1,
+            next_sequence: 100,
+            timestamp: u32::MAX - 89_999,
+            saved_at_ms: 1_700_000_000_000,
+        };
+        let saved = UNIX_EPOCH + Duration::from_millis(state.saved_at_ms);
+        let max_gap = Duration::from_secs(60);
+
+        // Two seconds down: 180000 ticks later, wrapping
+        assert_eq!(
+            state.resume_at(saved + Duration::from_secs(2), max_gap),
+            Some((100, 90_000))
+        );
+        assert_eq!(
+            state.resume_at(saved, max_gap),
+            Some((100, u32::MAX - 89_998))
+        );
+        assert_eq!(
+            state.resume_at(saved + Duration::from_secs(61), max_gap),
+            None
+        );
+        assert_eq!(
+            state.resume_at(saved - Duration::from_secs(1), max_gap),
+            None
+        );
+    }
+}
@@ -0,0 +1,391 @@
+//! Leader election between redundant streamers
+//!
+//! Two Pis watching the same scene can both capture, but only one of them
+//! should send to the shared destination. With `[mjpeg-rtp.coordination]`
+//! enabled, each instance sends a small UDP heartbeat to its peers every
+//! `heartbeat_interval_ms`, and the one that holds leadership streams:
+//!
+//! - A leader keeps leadership while it is alive, even when a peer with a
+//!   higher priority (re)joins, so a flapping Pi doesn't interrupt the stream.
+//! - When no leader has been heard for `takeover_timeout_ms`, the alive node
+//!   with the highest `(priority, node_id)` takes over. A freshly started node
+//!   waits one timeout to hear from its peers before claiming.
+//! - If two leaders meet (after a partition heals), the lower one steps down.
+//! - A node shutting down cleanly says so, and its standby takes over on the
+//!   next heartbeat instead of waiting for the timeout.
+//!
+//! Heartbeats are not authenticated; keep them on a trusted network.
+
+use crate::config::CoordinationConfig;
+use crate::log_limited;
+use crate::ratelimit::LogLimiter;
+use crate::task::CancellationToken;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+use tokio::net::UdpSocket;
+use tokio::sync::watch;
+use tracing::{debug, info};
+
+const MAGIC: &[u8; 4] = b"MJLE";
+const VERSION: u8 = 1;
+/// Magic, version, flags, priority, reserved, node id
+const HEARTBEAT_LEN: usize = 16;
+
+const FLAG_LEADER: u8 = 0x01;
+const FLAG_LEAVING: u8 = 0x02;
+
+/// Whether this instance should stream
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    Leader,
+    Standby,
+}
+
+/// State announced by one node
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Heartbeat {
+    pub node_id: u64,
+    pub priority: u8,
+    pub leader: bool,
+    /// The node is shutting down and gives up leadership now
+    pub leaving: bool,
+}
+
+impl Heartbeat {
+    pub fn encode(&self) -> [u8; HEARTBEAT_LEN] {
+        let mut buf = [0u8; HEARTBEAT_LEN];
+        buf[..4].copy_from_slice(MAGIC);
+        buf[4] = VERSION;
+        if self.leader {
+            buf[5] |= FLAG_LEADER;
+        }
+        if self.leaving {
+            buf[5] |= FLAG_LEAVING;
+        }
+        buf[6] = self.priority;
+        buf[8..].copy_from_slice(&self.node_id.to_be_bytes());
+        buf
+    }
+
+    /// Parses a heartbeat; `None` for anything else sent to the port
+    pub fn decode(buf: &[u8]) -> Option<Self> {
+        if buf.len() != HEARTBEAT_LEN || &buf[..4] != MAGIC || buf[4] != VERSION {
+            return None;
+        }
+        Some(Self {
+            node_id: u64::from_be_bytes(buf[8..].try_into().ok()?),
+            priority: buf[6],
+            leader: buf[5] & FLAG_LEADER != 0,
+            leaving: buf[5] & FLAG_LEAVING != 0,
+        })
+    }
+}
+
+#[derive(Debug)]
+struct Peer {
+    priority: u8,
+    leader: bool,
+    last_seen: Instant,
+}
+
+/// Election state of one node, driven by received heartbeats and a clock
+#[derive(Debug)]
+pub struct Election {
+    node_id: u64,
+    priority: u8,
+    timeout: Duration,
+    started: Instant,
+    role: Role,
+    peers: HashMap<u64, Peer>,
+}
+
+impl Election {
+    pub fn new(node_id: u64, priority: u8, timeout: Duration, now: Instant) -> Self {
+        Self {
+            node_id,
+            priority,
+            timeout,
+            started: now,
+            role: Role::Standby,
+            peers: HashMap::new(),
+        }
+    }
+
+    pub fn role(&self) -> Role {
+        self.role
+    }
+
+    /// The heartbeat to send for the current role
+    pub fn heartbeat(&self) -> Heartbeat {
+        Heartbeat {
+            node_id: self.node_id,
+            priority: self.priority,
+            leader: self.role == Role::Leader,
+            leaving: false,
+        }
+    }
+
+    pub fn on_heartbeat(&mut self, heartbeat: &Heartbeat, now: Instant) {
+        if heartbeat.node_id == self.node_id {
+            return;
+        }
+        if heartbeat.leaving {
+            self.peers.remove(&heartbeat.node_id);
+            return;
+        }
+        self.peers.insert(
+            heartbeat.node_id,
+            Peer {
+                priority: heartbeat.priority,
+                leader: heartbeat.leader,
+                last_seen: now,
+            },
+        );
+    }
+
+    /// Forgets peers not heard from within the timeout and decides the role
+    pub fn tick(&mut self, now: Instant) -> Role {
+        let timeout = self.timeout;
+        self.peers
+            .retain(|_, peer| now.duration_since(peer.last_seen) < timeout);
+
+        let rank = (self.priority, self.node_id);
+        let outranked_by = |leader_only: bool| {
+            self.peers
+                .iter()
+                .any(|(&id, peer)| (!leader_only || peer.leader) && (peer.priority, id) > rank)
+        };
+
+        self.role = match self.role {
+            Role::Leader if outranked_by(true) => Role::Standby,
+            Role::Leader => Role::Leader,
+            Role::Standby if self.peers.values().any(|peer| peer.leader) => Role::Standby,
+            Role::Standby if now.duration_since(self.started) < timeout => Role::Standby,
+            Role::Standby if outranked_by(false) => Role::Standby,
+            Role::Standby => Role::Leader,
+        };
+        self.role
+    }
+}
+
+/// Runs the election over UDP and publishes the role
+pub struct Coordinator {
+    socket: UdpSocket,
+    peers: Vec<SocketAddr>,
+    interval: Duration,
+    election: Election,
+    role_tx: watch::Sender<Role>,
+}
+
+impl Coordinator {
+    /// Binds the heartbeat socket. `node_id` breaks ties between equal
+    /// priorities and must differ between the instances.
+    pub async fn bind(config: &CoordinationConfig, node_id: u64) -> std::io::Result<Self> {
+        let socket = UdpSocket::bind(config.listen).await?;
+        let election = Election::new(
+            node_id,
+            config.priority,
+            Duration::from_millis(config.takeover_timeout_ms),
+            Instant::now(),
+        );
+        Ok(Self {
+            socket,
+            peers: config.peers.clone(),
+            interval: Duration::from_millis(config.heartbeat_interval_ms),
+            election,
+            role_tx: watch::channel(Role::Standby).0,
+        })
+    }
+
+    /// Follows the role, starting as standby
+    pub fn subscribe(&self) -> watch::Receiver<Role> {
+        self.role_tx.subscribe()
+    }
+
+    /// Exchanges heartbeats until `token` is cancelled, then tells the peers
+    /// this node is leaving
+    pub async fn run(mut self, token: CancellationToken) {
+        if let Ok(addr) = self.socket.local_addr() {
+            info!(%addr, peers = ?self.peers, "Leader election started");
+        }
+        let log = LogLimiter::default();
+        let mut ticker = tokio::time::interval(self.interval);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        let mut buf = [0u8; 64];
+        loop {
+            tokio::select! {
+                _ = token.cancelled() => break,
+                _ = ticker.tick() => {
+                    let role = self.election.tick(Instant::now());
+                    self.role_tx.send_if_modified(|current| {
+                        if *current == role {
+                            return false;
+                        }
+                        info!(?role, "Coordination role changed");
+                        *current = role;
+                        true
+                    });
+                    self.send(&self.election.heartbeat(), &log).await;
+                }
+                received = self.socket.recv_from(&mut buf) => match received {
+                    Ok((len, from)) => match Heartbeat::decode(&buf[..len]) {
+                        Some(heartbeat) => self.election.on_heartbeat(&heartbeat, Instant::now()),
+                        None => debug!(%from, len, "Ignoring datagram that is not a heartbeat"),
+                    },
+                    Err(e) => {
+                        log_limited!(log, "recv", warn, error = %e, "Failed to receive heartbeat");
+                    }
+                },
+            }
+        }
+
+        let leaving = Heartbeat {
+            leader: false,
+            leaving: true,
+            ..self.election.heartbeat()
+        };
+        self.send(&leaving, &log).await;
+        self.role_tx.send_replace(Role::Standby);
+    }
+
+    async fn send(&self, heartbeat: &Heartbeat, log: &LogLimiter) {
+        let packet = heartbeat.encode();
+        for peer in &self.peers {
+            if let Err(e) = self.socket.send_to(&packet, peer).await {
+                log_limited!(log, "send", warn, %peer, error = %e, "Failed to send heartbeat");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TIMEOUT: Duration = Duration::from_millis(1000);
+
+    fn heartbeat(node_id: u64, priority: u8, leader: bool) -> Heartbeat {
+        Heartbeat {
+            node_id,
+            priority,
+            leader,
+            leaving: false,
+        }
+    }
+
+    #[test]
+    fn test_heartbeat_roundtrip() {
+        let hb = Heartbeat {
+            node_id: 0x0123_4567_89ab_cdef,
+            priority: 200,
+            leader: true,
+            leaving: false,
+        };
+        let encoded = hb.encode();
+        assert_eq!(Heartbeat::decode(&encoded), Some(hb));
+        assert_eq!(Heartbeat::decode(&encoded[..15]), None);
+
+        let mut other_version = encoded;
+        other_version[4] = 2;
+        assert_eq!(Heartbeat::decode(&other_version), None);
+    }
+
+    #[test]
+    fn test_waits_for_peers_before_claiming() {
+        let start = Instant::now();
+        let mut election = Election::new(1, 100, TIMEOUT, start);
+
+        assert_eq!(
+            election.tick(start + Duration::from_millis(500)),
+            Role::Standby
+        );
+        assert_eq!(election.tick(start + TIMEOUT), Role::Leader);
+        assert!(election.heartbeat().leader);
+    }
+
+    #[test]
+    fn test_standby_takes_over_after_timeout() {
+        let start = Instant::now();
+        let mut election = Election::new(1, 200, TIMEOUT, start);
+
+        // A leader is already running; a higher priority doesn't preempt it
+        let mut now = start;
+        for _ in 0..20 {
+            election.on_heartbeat(&heartbeat(2, 100, true), now);
+            now += Duration::from_millis(200);
+            assert_eq!(election.tick(now), Role::Standby);
+        }
+
+        // The leader goes silent
+        assert_eq!(
+            election.tick(now + Duration::from_millis(500)),
+            Role::Standby
+        );
+        assert_eq!(election.tick(now + TIMEOUT), Role::Leader);
+    }
+
+    #[test]
+    fn test_leaving_leader_hands_over_at_once() {
+        let start = Instant::now();
+        let mut election = Election::new(1, 100, TIMEOUT, start);
+        let now = start + 2 * TIMEOUT;
+        election.on_heartbeat(&heartbeat(2, 100, true), now);
+        assert_eq!(election.tick(now), Role::Standby);
+
+        let leaving = Heartbeat {
+            leaving: true,
+            ..heartbeat(2, 100, false)
+        };
+        election.on_heartbeat(&leaving, now);
+        assert_eq!(
+            election.tick(now + Duration::from_millis(200)),
+            Role::Leader
+        );
+    }
+
+    #[test]
+    fn test_highest_rank_claims_when_no_leader() {
+        let start = Instant::now();
+        let now = start + TIMEOUT;
+        let mut low = Election::new(1, 100, TIMEOUT, start);
+        let mut high = Election::new(2, 100, TIMEOUT, start);
+        low.on_heartbeat(&high.heartbeat(), now);
+        high.on_heartbeat(&low.heartbeat(), now);
+
+        // Equal priorities: the node id decides
+        assert_eq!(low.tick(now), Role::Standby);
+        assert_eq!(high.tick(now), Role::Leader);
+
+        // Priority outweighs the node id
+        let mut preferred = Election::new(1, 150, TIMEOUT, start);
+        preferred.on_heartbeat(&heartbeat(2, 100, false), now);
+        assert_eq!(preferred.tick(now), Role::Leader);
+    }
+
+    #[test]
+    fn test_split_brain_resolves_to_higher_rank() {
+        let start = Instant::now();
+        let now = start + TIMEOUT;
+        let mut a = Election::new(1, 100, TIMEOUT, start);
+        let mut b = Election::new(2, 100, TIMEOUT, start);
+        assert_eq!(a.tick(now), Role::Leader);
+        assert_eq!(b.tick(now), Role::Leader);
+
+        // The partition heals
+        a.on_heartbeat(&b.heartbeat(), now);
+        b.on_heartbeat(&a.heartbeat(), now);
+        assert_eq!(a.tick(now), Role::Standby);
+        assert_eq!(b.tick(now), Role::Leader);
+    }
+
+    #[test]
+    fn test_ignores_own_heartbeat() {
+        let start = Instant::now();
+        let mut election = Election::new(7, 100, TIMEOUT, start);
+        election.on_heartbeat(&heartbeat(7, 255, true), start);
+        assert_eq!(election.tick(start + TIMEOUT), Role::Leader);
+    }
+}
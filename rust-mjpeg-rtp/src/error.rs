@@ -0,0 +1,200 @@
+//! Error codes shared by the library's error types
+//!
+//! Each public error enum maps its variants to a stable [`ErrorCode`], so API
+//! layers can report failures and retry logic can act on them without
+//! matching every variant of every module.
+
+use crate::calibration::CalibrationError;
+use crate::capture::CaptureError;
+use crate::config::ConfigError;
+use crate::continuity::ContinuityError;
+use crate::identity::IdentityError;
+use crate::latency::LatencyError;
+use crate::processor::ProcessorError;
+use crate::receiver::ReceiverError;
+use crate::recording::RecordingError;
+use crate::rtp::{JpegParseError, PacketizerError};
+use crate::streamer::StreamerError;
+use crate::supervisor::SupervisorError;
+use crate::tap::TapError;
+#[cfg(feature = "otel")]
+use crate::telemetry::TelemetryError;
+use crate::transcode::TranscodeError;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use thiserror::Error;
+
+/// Stable, machine-readable failure class
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// Configuration rejected at load time
+    InvalidConfig,
+    /// Malformed argument or data (JPEG frame, RTP packet, address)
+    InvalidInput,
+    /// Camera, encoder or GStreamer pipeline failure
+    Device,
+    /// Socket or file I/O failure
+    Io,
+    /// The component has not been started or has stopped
+    NotRunning,
+    /// The component is already running
+    AlreadyRunning,
+    /// A bounded queue was full and the item was dropped
+    Busy,
+    /// The task on the other end of a channel has exited
+    ChannelClosed,
+    /// Not supported by this build or platform
+    Unsupported,
+}
+
+impl ErrorCode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ErrorCode::InvalidConfig => "invalid_config",
+            ErrorCode::InvalidInput => "invalid_input",
+            ErrorCode::Device => "device",
+            ErrorCode::Io => "io",
+            ErrorCode::NotRunning => "not_running",
+            ErrorCode::AlreadyRunning => "already_running",
+            ErrorCode::Busy => "busy",
+            ErrorCode::ChannelClosed => "channel_closed",
+            ErrorCode::Unsupported => "unsupported",
+        }
+    }
+
+    /// HTTP status an API should answer with
+    pub fn http_status(self) -> u16 {
+        match self {
+            ErrorCode::InvalidConfig | ErrorCode::InvalidInput => 400,
+            ErrorCode::NotRunning | ErrorCode::AlreadyRunning => 409,
+            ErrorCode::Unsupported => 501,
+            ErrorCode::Device | ErrorCode::Busy => 503,
+            ErrorCode::Io | ErrorCode::ChannelClosed => 500,
+        }
+    }
+
+    /// Whether repeating the same call later may succeed. Devices and
+    /// sockets can come back; bad input, bad config and a dead component
+    /// won't fix themselves.
+    pub fn is_retryable(self) -> bool {
+        matches!(self, ErrorCode::Device | ErrorCode::Io | ErrorCode::Busy)
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Any library error, for callers driving several components
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+
+    #[error(transparent)]
+    Capture(#[from] CaptureError),
+
+    #[error(transparent)]
+    Streamer(#[from] StreamerError),
+
+    #[error(transparent)]
+    Packetizer(#[from] PacketizerError),
+
+    #[error(transparent)]
+    JpegParse(#[from] JpegParseError),
+
+    #[error(transparent)]
+    Identity(#[from] IdentityError),
+
+    #[error(transparent)]
+    Continuity(#[from] ContinuityError),
+
+    #[error(transparent)]
+    Receiver(#[from] ReceiverError),
+
+    #[error(transparent)]
+    Recording(#[from] RecordingError),
+
+    #[error(transparent)]
+    Calibration(#[from] CalibrationError),
+
+    #[error(transparent)]
+    Latency(#[from] LatencyError),
+
+    #[error(transparent)]
+    Processor(#[from] ProcessorError),
+
+    #[error(transparent)]
+    Transcode(#[from] TranscodeError),
+
+    #[error(transparent)]
+    Tap(#[from] TapError),
+
+    #[error(transparent)]
+    Supervisor(#[from] SupervisorError),
+
+    #[cfg(feature = "otel")]
+    #[error(transparent)]
+    Telemetry(#[from] TelemetryError),
+}
+
+impl Error {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Error::Config(e) => e.code(),
+            Error::Capture(e) => e.code(),
+            Error::Streamer(e) => e.code(),
+            Error::Packetizer(e) => e.code(),
+            Error::JpegParse(e) => e.code(),
+            Error::Identity(e) => e.code(),
+            Error::Continuity(e) => e.code(),
+            Error::Receiver(e) => e.code(),
+            Error::Recording(e) => e.code(),
+            Error::Calibration(e) => e.code(),
+            Error::Latency(e) => e.code(),
+            Error::Processor(e) => e.code(),
+            Error::Transcode(e) => e.code(),
+            Error::Tap(e) => e.code(),
+            Error::Supervisor(e) => e.code(),
+            #[cfg(feature = "otel")]
+            Error::Telemetry(e) => e.code(),
+        }
+    }
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_codes_pass_through() {
+        let err: Error = StreamerError::QueueFull.into();
+        assert_eq!(err.code(), ErrorCode::Busy);
+        assert!(err.code().is_retryable());
+        assert_eq!(err.to_string(), StreamerError::QueueFull.to_string());
+
+        let err: Error = ConfigError::Invalid("mtu".to_string()).into();
+        assert_eq!(err.code(), ErrorCode::InvalidConfig);
+        assert!(!err.code().is_retryable());
+        assert_eq!(err.code().http_status(), 400);
+    }
+
+    #[test]
+    fn test_packetizer_keeps_parse_cause() {
+        let err = PacketizerError::from(JpegParseError::MissingSoi);
+        assert!(matches!(
+            err,
+            PacketizerError::InvalidJpeg(JpegParseError::MissingSoi)
+        ));
+        assert_eq!(err.code(), ErrorCode::InvalidInput);
+        assert_eq!(
+            PacketizerError::from(JpegParseError::Unsupported).code(),
+            ErrorCode::Unsupported
+        );
+    }
+}
@@ -0,0 +1,137 @@
+//! Analytics events reported by frame processors
+//!
+//! Processors that analyse frames, such as WASM plugins, publish
+//! [`AnalyticsEvent`]s on an [`EventBus`]. Subscribers receive them as they
+//! happen, and the control API serves the most recent ones per camera at
+//! `GET /cameras/{name}/events`.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::broadcast;
+
+/// Events kept for [`EventBus::recent`]
+pub const RECENT_EVENTS: usize = 100;
+
+/// One result reported about a frame
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AnalyticsEvent {
+    pub camera: String,
+    /// What reported it, e.g. the plugin's file name
+    pub source: String,
+    /// Capture id of the frame it is about
+    pub frame_id: u64,
+    /// Wall-clock time it was reported (ms since the Unix epoch)
+    pub timestamp_ms: u64,
+    /// Whatever the source reported, e.g. `{"label": "person", "score": 0.9}`
+    pub payload: Value,
+}
+
+impl AnalyticsEvent {
+    /// An event stamped with the current time
+    pub fn new(camera: &str, source: &str, frame_id: u64, payload: Value) -> Self {
+        Self {
+            camera: camera.to_string(),
+            source: source.to_string(),
+            frame_id,
+            timestamp_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_millis() as u64)
+                .unwrap_or(0),
+            payload,
+        }
+    }
+}
+
+/// Fans events out to subscribers and remembers the last [`RECENT_EVENTS`]
+#[derive(Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<AnalyticsEvent>,
+    recent: Arc<Mutex<VecDeque<AnalyticsEvent>>>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self {
+            sender: broadcast::channel(64).0,
+            recent: Arc::new(Mutex::new(VecDeque::with_capacity(RECENT_EVENTS))),
+        }
+    }
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn publish(&self, event: AnalyticsEvent) {
+        {
+            let mut recent = self.recent.lock().unwrap();
+            if recent.len() == RECENT_EVENTS {
+                recent.pop_front();
+            }
+            recent.push_back(event.clone());
+        }
+        // Nobody subscribed is fine
+        let _ = self.sender.send(event);
+    }
+
+    /// Events published from now on. A subscriber that falls behind loses
+    /// the oldest ones.
+    pub fn subscribe(&self) -> broadcast::Receiver<AnalyticsEvent> {
+        self.sender.subscribe()
+    }
+
+    /// The most recent events about any camera, oldest first
+    pub fn recent_all(&self) -> Vec<AnalyticsEvent> {
+        self.recent.lock().unwrap().iter().cloned().collect()
+    }
+
+    /// The most recent events about `camera`, oldest first
+    pub fn recent(&self, camera: &str) -> Vec<AnalyticsEvent> {
+        self.recent
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|event| event.camera == camera)
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_publish() {
+        let bus = EventBus::new();
+        let mut events = bus.subscribe();
+        bus.publish(AnalyticsEvent::new(
+            "camera1",
+            "people",
+            7,
+            json!({"count": 2}),
+        ));
+
+        let event = events.recv().await.unwrap();
+        assert_eq!(event.frame_id, 7);
+        assert_eq!(event.payload["count"], 2);
+        assert_eq!(bus.recent("camera1"), vec![event]);
+        assert!(bus.recent("camera2").is_empty());
+    }
+
+    #[test]
+    fn test_recent_is_bounded() {
+        let bus = EventBus::new();
+        for id in 0..RECENT_EVENTS as u64 + 10 {
+            bus.publish(AnalyticsEvent::new("camera1", "people", id, Value::Null));
+        }
+        let recent = bus.recent("camera1");
+        assert_eq!(recent.len(), RECENT_EVENTS);
+        assert_eq!(recent[0].frame_id, 10);
+    }
+}
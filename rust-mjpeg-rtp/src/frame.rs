@@ -0,0 +1,81 @@
+//! Captured frame passed from capture to streamer
+
+use bytes::Bytes;
+use std::ops::Deref;
+use std::time::{Duration, Instant};
+
+/// A single JPEG frame with the metadata needed to trace it through the
+/// capture → packetize → send pipeline.
+#[derive(Debug, Clone)]
+pub struct Frame {
+    /// Monotonic frame identifier assigned at capture (gaps indicate drops)
+    pub id: u64,
+
+    /// Complete JPEG data (SOI..EOI)
+    pub data: Bytes,
+
+    /// Instant the frame left the capture pipeline
+    pub captured_at: Instant,
+
+    /// Presentation timestamp from the capture pipeline (running time since
+    /// it started), when the source provides one
+    pub pts: Option<Duration>,
+}
+
+impl Frame {
+    /// Creates a frame stamped with the current instant
+    pub fn new(id: u64, data: Bytes) -> Self {
+        Self {
+            id,
+            data,
+            captured_at: Instant::now(),
+            pts: None,
+        }
+    }
+
+    /// Sets the capture presentation timestamp
+    pub fn with_pts(mut self, pts: Option<Duration>) -> Self {
+        self.pts = pts;
+        self
+    }
+
+    /// Microseconds elapsed since capture
+    pub fn age_us(&self) -> u64 {
+        self.captured_at.elapsed().as_micros() as u64
+    }
+}
+
+impl Deref for Frame {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+impl From<Bytes> for Frame {
+    /// Wraps raw JPEG data that did not come from [`crate::Capture`] (id 0)
+    fn from(data: Bytes) -> Self {
+        Self::new(0, data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_derefs_to_jpeg_bytes() {
+        let frame = Frame::new(7, Bytes::from_static(&[0xFF, 0xD8, 0xFF, 0xD9]));
+        assert_eq!(frame.id, 7);
+        assert_eq!(frame.len(), 4);
+        assert_eq!(frame[1], 0xD8);
+    }
+
+    #[test]
+    fn test_frame_from_bytes() {
+        let frame: Frame = Bytes::from_static(&[1, 2, 3]).into();
+        assert_eq!(frame.id, 0);
+        assert_eq!(&frame[..], &[1, 2, 3]);
+    }
+}
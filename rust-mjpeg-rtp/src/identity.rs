@@ -0,0 +1,157 @@
+//! Stable per-camera identity
+//!
+//! Each camera gets a UUID generated on first start and persisted under the
+//! state directory. The SSRC and RTCP CNAME are derived from it, so
+//! downstream recorders see the same source across reboots instead of a new
+//! anonymous stream each time.
+
+use crate::error::ErrorCode;
+use serde::Serialize;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+use uuid::Uuid;
+
+#[derive(Error, Debug)]
+pub enum IdentityError {
+    #[error("failed to access identity file {path}: {source}")]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[error("identity file {path} does not contain a UUID: {source}")]
+    Corrupt { path: PathBuf, source: uuid::Error },
+}
+
+impl IdentityError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            IdentityError::Io { .. } => ErrorCode::Io,
+            // Needs a person to fix or remove the file
+            IdentityError::Corrupt { .. } => ErrorCode::InvalidConfig,
+        }
+    }
+}
+
+/// Persistent identifier of one camera stream
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CameraIdentity {
+    /// Camera name from the config ("camera1", "camera2")
+    pub name: String,
+    pub uuid: Uuid,
+}
+
+impl CameraIdentity {
+    /// Reads `<state_dir>/<name>.uuid`, creating it with a fresh UUID if missing
+    ///
+    /// A file that exists but can't be parsed is an error rather than being
+    /// silently replaced, as that would change the stream's identity.
+    pub fn load_or_create(state_dir: impl AsRef<Path>, name: &str) -> Result<Self, IdentityError> {
+        let state_dir = state_dir.as_ref();
+        let path = state_dir.join(format!("{}.uuid", name));
+        let io_err = |source| IdentityError::Io {
+            path: path.clone(),
+            source,
+        };
+
+        match fs::read_to_string(&path) {
+            Ok(content) => {
+                let uuid =
+                    Uuid::parse_str(content.trim()).map_err(|source| IdentityError::Corrupt {
+                        path: path.clone(),
+                        source,
+                    })?;
+                return Ok(Self {
+                    name: name.to_string(),
+                    uuid,
+                });
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(io_err(e)),
+        }
+
+        let uuid = Uuid::new_v4();
+        fs::create_dir_all(state_dir).map_err(io_err)?;
+
+        // Write-then-rename so a crash never leaves a truncated identity behind
+        let tmp = path.with_extension("uuid.tmp");
+        let mut file = fs::File::create(&tmp).map_err(io_err)?;
+        writeln!(file, "{}", uuid.hyphenated()).map_err(io_err)?;
+        file.sync_all().map_err(io_err)?;
+        fs::rename(&tmp, &path).map_err(io_err)?;
+
+        Ok(Self {
+            name: name.to_string(),
+            uuid,
+        })
+    }
+
+    /// SSRC derived from the UUID (never 0)
+    pub fn ssrc(&self) -> u32 {
+        let b = self.uuid.as_bytes();
+        let ssrc = b
+            .chunks_exact(4)
+            .map(|c| u32::from_be_bytes([c[0], c[1], c[2], c[3]]))
+            .fold(0, |acc, word| acc ^ word);
+        if ssrc == 0 {
+            1
+        } else {
+            ssrc
+        }
+    }
+
+    /// RTCP SDES CNAME for this stream
+    pub fn cname(&self) -> String {
+        format!("{}@mjpeg-rtp", self.uuid.hyphenated())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_identity_persists() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let first = CameraIdentity::load_or_create(dir.path(), "camera1").unwrap();
+        let again = CameraIdentity::load_or_create(dir.path(), "camera1").unwrap();
+        assert_eq!(first, again);
+        assert_eq!(first.ssrc(), again.ssrc());
+
+        let other = CameraIdentity::load_or_create(dir.path(), "camera2").unwrap();
+        assert_ne!(first.uuid, other.uuid);
+    }
+
+    #[test]
+    fn test_creates_state_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let nested = dir.path().join("state/ids");
+
+        CameraIdentity::load_or_create(&nested, "camera1").unwrap();
+        assert!(nested.join("camera1.uuid").exists());
+    }
+
+    #[test]
+    fn test_corrupt_file_is_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("camera1.uuid"), "not-a-uuid\n").unwrap();
+
+        assert!(matches!(
+            CameraIdentity::load_or_create(dir.path(), "camera1"),
+            Err(IdentityError::Corrupt { .. })
+        ));
+    }
+
+    #[test]
+    fn test_derived_values() {
+        let id = CameraIdentity {
+            name: "camera1".to_string(),
+            uuid: Uuid::parse_str("00000001-0000-0002-0000-000400000008").unwrap(),
+        };
+        assert_eq!(id.ssrc(), 1 ^ 2 ^ 4 ^ 8);
+        assert_eq!(id.cname(), "00000001-0000-0002-0000-000400000008@mjpeg-rtp");
+    }
+}
@@ -0,0 +1,153 @@
+//! Decoding and scaling frames for analysis
+//!
+//! Analytics processors work on small uncompressed copies of frames; these
+//! helpers turn a captured JPEG into one.
+
+use thiserror::Error;
+use zune_jpeg::zune_core::colorspace::ColorSpace;
+use zune_jpeg::zune_core::options::DecoderOptions;
+use zune_jpeg::JpegDecoder;
+
+#[derive(Error, Debug)]
+#[error("cannot decode frame: {0}")]
+pub struct DecodeError(String);
+
+/// Interleaved 8-bit pixels, row by row
+#[derive(Debug, Clone, PartialEq)]
+pub struct Image {
+    pub pixels: Vec<u8>,
+    pub width: usize,
+    pub height: usize,
+    /// 1 for luma, 3 for RGB
+    pub channels: usize,
+}
+
+impl Image {
+    /// Decodes a JPEG to greyscale (`gray`) or RGB
+    pub fn decode(jpeg: &[u8], gray: bool) -> Result<Self, DecodeError> {
+        let (colorspace, channels) = if gray {
+            (ColorSpace::Luma, 1)
+        } else {
+            (ColorSpace::RGB, 3)
+        };
+        let options = DecoderOptions::default().jpeg_set_out_colorspace(colorspace);
+        let mut decoder = JpegDecoder::new_with_options(jpeg, options);
+        let pixels = decoder
+            .decode()
+            .map_err(|e| DecodeError(format!("{:?}", e)))?;
+        let info = decoder
+            .info()
+            .ok_or_else(|| DecodeError("no frame header".to_string()))?;
+        Ok(Self {
+            pixels,
+            width: info.width as usize,
+            height: info.height as usize,
+            channels,
+        })
+    }
+
+    /// Scaled to exactly `width`×`height`: averaged over the source pixels
+    /// each output pixel covers when shrinking, repeated when growing
+    pub fn resize(&self, width: usize, height: usize) -> Self {
+        if (width, height) == (self.width, self.height) {
+            return self.clone();
+        }
+        let channels = self.channels;
+        let span = |i: usize, from: usize, to: usize| {
+            let start = i * from / to;
+            (start, ((i + 1) * from / to).max(start + 1))
+        };
+        let mut pixels = Vec::with_capacity(width * height * channels);
+        for y in 0..height {
+            let (y0, y1) = span(y, self.height, height);
+            for x in 0..width {
+                let (x0, x1) = span(x, self.width, width);
+                let count = ((y1 - y0) * (x1 - x0)) as u32;
+                for c in 0..channels {
+                    let sum: u32 = (y0..y1)
+                        .flat_map(|row| {
+                            (x0..x1).map(move |col| (row * self.width + col) * channels)
+                        })
+                        .map(|i| self.pixels[i + c] as u32)
+                        .sum();
+                    pixels.push((sum / count) as u8);
+                }
+            }
+        }
+        Self {
+            pixels,
+            width,
+            height,
+            channels,
+        }
+    }
+}
+
+/// 640x48 4:2:0 JPEG whose top-left blocks are bright when the top bit of
+/// `value` is set and dark otherwise (see [`crate::timecode::flat_scan`]).
+/// The AVI1 marker tells the decoder to use the standard Huffman tables.
+#[cfg(test)]
+pub(crate) fn test_jpeg(value: u32) -> bytes::Bytes {
+    let mut jpeg = vec![0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x07];
+    jpeg.extend(b"AVI1\0");
+    for table in 0..2u8 {
+        jpeg.extend([0xFF, 0xDB, 0x00, 0x43, table]);
+        jpeg.extend([8u8; 64]);
+    }
+    jpeg.extend([0xFF, 0xC0, 0x00, 0x11, 0x08, 0x00, 0x30, 0x02, 0x80]);
+    jpeg.extend([0x03, 0x01, 0x22, 0x00, 0x02, 0x11, 0x01, 0x03, 0x11, 0x01]);
+    jpeg.extend([
+        0xFF, 0xDA, 0x00, 0x0C, 0x03, 0x01, 0x00, 0x02, 0x11, 0x03, 0x11,
+    ]);
+    jpeg.extend([0x00, 0x3F, 0x00]);
+    jpeg.extend(crate::timecode::flat_scan(640, 48, value));
+    jpeg.extend([0xFF, 0xD9]);
+    bytes::Bytes::from(jpeg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gray(width: usize, height: usize, pixels: Vec<u8>) -> Image {
+        Image {
+            pixels,
+            width,
+            height,
+            channels: 1,
+        }
+    }
+
+    #[test]
+    fn test_resize() {
+        let image = gray(4, 4, (0..16).map(|i| i * 10).collect());
+        let small = image.resize(2, 2);
+        // Averages of [0, 10, 40, 50], [20, 30, 60, 70], ...
+        assert_eq!(small, gray(2, 2, vec![25, 45, 105, 125]));
+        assert_eq!(image.resize(4, 4), image);
+
+        let large = gray(2, 1, vec![10, 20]).resize(4, 2);
+        assert_eq!(large.pixels, vec![10, 10, 20, 20, 10, 10, 20, 20]);
+
+        let rgb = Image {
+            pixels: vec![0, 100, 200, 20, 120, 220],
+            width: 2,
+            height: 1,
+            channels: 3,
+        };
+        assert_eq!(rgb.resize(1, 1).pixels, vec![10, 110, 210]);
+    }
+
+    #[test]
+    fn test_decode() {
+        let image = Image::decode(&test_jpeg(u32::MAX), true).unwrap();
+        assert_eq!((image.width, image.height, image.channels), (640, 48, 1));
+        assert!(image.pixels[0] > 150, "{}", image.pixels[0]);
+        let image = Image::decode(&test_jpeg(0), true).unwrap();
+        assert!(image.pixels[0] < 100, "{}", image.pixels[0]);
+
+        let rgb = Image::decode(&test_jpeg(u32::MAX), false).unwrap();
+        assert_eq!(rgb.pixels.len(), 640 * 48 * 3);
+        assert!(Image::decode(b"not a jpeg", true).is_err());
+    }
+}
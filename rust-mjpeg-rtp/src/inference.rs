@@ -0,0 +1,551 @@
+//! Object detection with ONNX models
+//!
+//! A `detect` processor runs an object detection model on an RGB copy of a
+//! frame, stretched to the model's input size, at most every `interval_ms`,
+//! and publishes what it finds on the [`EventBus`] as
+//! `{"detections": [{"label", "class", "score", "x", "y", "width", "height"}]}`
+//! with boxes in fractions of the frame size. With `overlay` set the boxes
+//! are also outlined in the streamed frames until the next analysed frame
+//! replaces them. Frames pass through unchanged; like
+//! [`WasmPlugin`](crate::plugin::WasmPlugin), inference runs on its own
+//! thread and frames that arrive while it is busy are not analysed.
+//!
+//! Models are run with [tract](https://github.com/sonos/tract), so no native
+//! runtime is needed. Two layouts are understood:
+//!
+//! - [`Yolo`](DetectionFormat::Yolo): YOLOv5/YOLOv8 exports, taking
+//!   `[1, 3, height, width]` floats in 0-1 and producing candidates as
+//!   `[1, 4 + classes, n]` (v8) or `[1, n, 5 + classes]` (v5, with an
+//!   objectness score) in input pixels; overlapping candidates of a class are
+//!   merged here
+//! - [`Ssd`](DetectionFormat::Ssd): TensorFlow object detection exports,
+//!   taking `[1, height, width, 3]` bytes and producing normalized
+//!   `[ymin, xmin, ymax, xmax]` boxes, classes and scores, found by output
+//!   name or else in that order
+//!
+//! TensorFlow Lite models can be converted with tf2onnx.
+
+use crate::config::{DetectionFormat, DetectorConfig};
+use crate::events::{AnalyticsEvent, EventBus};
+use crate::frame::Frame;
+use crate::imaging::Image;
+use crate::overlay::{Overlay, OverlayBox};
+use crate::processor::{FrameProcessor, Processed, ProcessorContext, ProcessorError};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
+use tract_onnx::prelude::tract_ndarray::{Array4, ArrayViewD, Axis, Ix3};
+use tract_onnx::prelude::*;
+
+/// Candidates of the same class overlapping more than this are merged
+const NMS_IOU: f32 = 0.45;
+
+type Plan = TypedRunnableModel<TypedModel>;
+
+/// One detected object
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Detection {
+    /// From the labels file, or the class number without one
+    pub label: String,
+    pub class: usize,
+    pub score: f32,
+    /// Box in fractions of the frame's width and height
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Detection {
+    fn overlay_box(&self) -> OverlayBox {
+        OverlayBox {
+            x: self.x,
+            y: self.y,
+            width: self.width,
+            height: self.height,
+        }
+    }
+
+    fn iou(&self, other: &Detection) -> f32 {
+        let width = (self.x + self.width).min(other.x + other.width) - self.x.max(other.x);
+        let height = (self.y + self.height).min(other.y + other.height) - self.y.max(other.y);
+        let overlap = width.max(0.0) * height.max(0.0);
+        let union = self.width * self.height + other.width * other.height - overlap;
+        if union > 0.0 {
+            overlap / union
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Runs an object detection model on frames; see the [module docs](self)
+pub struct Detector {
+    interval: Duration,
+    last_sent: Option<Instant>,
+    frames: SyncSender<Frame>,
+}
+
+impl Detector {
+    /// Loads and optimizes the model, then starts its inference thread
+    pub fn load(
+        config: &DetectorConfig,
+        context: &ProcessorContext,
+    ) -> Result<Self, ProcessorError> {
+        let error = |path: &str, reason: String| ProcessorError::Model {
+            path: path.to_string(),
+            reason,
+        };
+        let labels = match config.labels {
+            Some(ref path) => std::fs::read_to_string(path)
+                .map_err(|e| error(path, e.to_string()))?
+                .lines()
+                .map(|line| line.trim().to_string())
+                .collect(),
+            None => Vec::new(),
+        };
+        let model = Model::load(config).map_err(|e| error(&config.model, format!("{:#}", e)))?;
+        let source = Path::new(&config.model).file_stem().map_or_else(
+            || config.model.clone(),
+            |s| s.to_string_lossy().into_owned(),
+        );
+
+        let mut runner = Runner {
+            camera: context.camera.clone(),
+            source: source.clone(),
+            model,
+            labels,
+            threshold: config.threshold,
+            max_detections: config.max_detections,
+            events: context.events.clone(),
+            overlay: context.overlay.clone().filter(|_| config.overlay),
+        };
+        // One frame in flight: a busy model skips frames rather than queueing them
+        let (frames, rx) = mpsc::sync_channel::<Frame>(0);
+        thread::Builder::new()
+            .name(format!("detect-{}", source))
+            .spawn(move || {
+                while let Ok(frame) = rx.recv() {
+                    if let Err(e) = runner.analyze(&frame) {
+                        warn!(
+                            model = %runner.source,
+                            frame_id = frame.id,
+                            error = %format!("{:#}", e),
+                            "Detection failed on a frame"
+                        );
+                    }
+                }
+            })
+            .map_err(|e| error(&config.model, e.to_string()))?;
+
+        info!(
+            camera = %context.camera,
+            model = %source,
+            format = ?config.format,
+            input = %format!("{}x{}", config.input_width, config.input_height),
+            "Loaded detection model"
+        );
+        Ok(Self {
+            interval: Duration::from_millis(config.interval_ms),
+            last_sent: None,
+            frames,
+        })
+    }
+}
+
+impl FrameProcessor for Detector {
+    fn process(&mut self, frame: Frame) -> Processed {
+        let due = self
+            .last_sent
+            .is_none_or(|at| at.elapsed() >= self.interval);
+        if due {
+            match self.frames.try_send(frame.clone()) {
+                Ok(()) => self.last_sent = Some(Instant::now()),
+                // Still busy with the last one, or the thread is gone
+                Err(TrySendError::Full(_) | TrySendError::Disconnected(_)) => {}
+            }
+        }
+        Processed::Frame(frame)
+    }
+
+    fn name(&self) -> &'static str {
+        "detect"
+    }
+}
+
+/// Inference thread state
+struct Runner {
+    camera: String,
+    source: String,
+    model: Model,
+    labels: Vec<String>,
+    threshold: f32,
+    max_detections: usize,
+    events: EventBus,
+    overlay: Option<Overlay>,
+}
+
+impl Runner {
+    fn analyze(&mut self, frame: &Frame) -> TractResult<()> {
+        let image = Image::decode(&frame.data, false)?;
+        let mut detections = self.model.detect(&image, self.threshold)?;
+        detections = nms(detections, self.max_detections);
+        for detection in &mut detections {
+            detection.label = self
+                .labels
+                .get(detection.class)
+                .cloned()
+                .unwrap_or_else(|| detection.class.to_string());
+        }
+
+        if let Some(ref overlay) = self.overlay {
+            overlay.set(detections.iter().map(Detection::overlay_box).collect());
+        }
+        if detections.is_empty() {
+            return Ok(());
+        }
+        debug!(
+            camera = %self.camera,
+            model = %self.source,
+            frame_id = frame.id,
+            count = detections.len(),
+            "Objects detected"
+        );
+        let payload = serde_json::json!({ "detections": detections });
+        let event = AnalyticsEvent::new(&self.camera, &self.source, frame.id, payload);
+        self.events.publish(event);
+        Ok(())
+    }
+}
+
+/// An optimized model and how to feed it
+struct Model {
+    plan: Plan,
+    format: DetectionFormat,
+    width: usize,
+    height: usize,
+    /// Boxes, classes and scores outputs of an SSD model
+    ssd_outputs: [usize; 3],
+}
+
+impl Model {
+    fn load(config: &DetectorConfig) -> TractResult<Self> {
+        let (width, height) = (config.input_width as usize, config.input_height as usize);
+        let fact: InferenceFact = match config.format {
+            DetectionFormat::Yolo => f32::fact([1, 3, height, width]).into(),
+            DetectionFormat::Ssd => u8::fact([1, height, width, 3]).into(),
+        };
+        let model = tract_onnx::onnx()
+            .model_for_path(&config.model)?
+            .with_input_fact(0, fact)?
+            .into_optimized()?;
+
+        let names: Vec<String> = model
+            .output_outlets()?
+            .iter()
+            .map(|&outlet| {
+                let node = model.node(outlet.node).name.as_str();
+                model.outlet_label(outlet).unwrap_or(node).to_lowercase()
+            })
+            .collect();
+        let mut ssd_outputs = [0, 1, 2];
+        for (index, key) in ["box", "class", "score"].iter().enumerate() {
+            if let Some(found) = names.iter().position(|name| name.contains(key)) {
+                ssd_outputs[index] = found;
+            }
+        }
+        let needed = match config.format {
+            DetectionFormat::Yolo => 1,
+            DetectionFormat::Ssd => 3,
+        };
+        if names.len() < needed {
+            return Err(TractError::msg(format!(
+                "expected {} outputs, the model has {}",
+                needed,
+                names.len()
+            )));
+        }
+
+        Ok(Self {
+            plan: model.into_runnable()?,
+            format: config.format,
+            width,
+            height,
+            ssd_outputs,
+        })
+    }
+
+    /// Candidates scoring at least `threshold`, before merging
+    fn detect(&self, image: &Image, threshold: f32) -> TractResult<Vec<Detection>> {
+        let rgb = image.resize(self.width, self.height).pixels;
+        let shape = (1, self.height, self.width, 3);
+        let input: Tensor = match self.format {
+            DetectionFormat::Yolo => {
+                let (width, height) = (self.width, self.height);
+                Array4::from_shape_fn((1, 3, height, width), |(_, c, y, x)| {
+                    rgb[(y * width + x) * 3 + c] as f32 / 255.0
+                })
+                .into()
+            }
+            DetectionFormat::Ssd => Array4::from_shape_vec(shape, rgb)?.into(),
+        };
+        let outputs = self.plan.run(tvec!(input.into()))?;
+
+        match self.format {
+            DetectionFormat::Yolo => parse_yolo(
+                outputs[0].to_array_view::<f32>()?,
+                (self.width, self.height),
+                threshold,
+            ),
+            DetectionFormat::Ssd => {
+                let [boxes, classes, scores] =
+                    self.ssd_outputs.map(|i| outputs[i].cast_to::<f32>());
+                parse_ssd(
+                    boxes?.to_array_view::<f32>()?,
+                    classes?.to_array_view::<f32>()?,
+                    scores?.to_array_view::<f32>()?,
+                    threshold,
+                )
+            }
+        }
+    }
+}
+
+/// Candidates from a YOLO output, with boxes scaled from the
+/// `width`×`height` input to fractions
+fn parse_yolo(
+    output: ArrayViewD<f32>,
+    (width, height): (usize, usize),
+    threshold: f32,
+) -> TractResult<Vec<Detection>> {
+    let output = output.into_dimensionality::<Ix3>()?;
+    let output = output.index_axis(Axis(0), 0);
+    // v8 puts candidates in columns, v5 in rows after an objectness score
+    let (candidates, first_class) = if output.nrows() < output.ncols() {
+        (output.reversed_axes(), 4)
+    } else {
+        (output, 5)
+    };
+    let (width, height) = (width as f32, height as f32);
+
+    let mut detections = Vec::new();
+    for row in candidates.outer_iter() {
+        let objectness = if first_class == 5 { row[4] } else { 1.0 };
+        let best = (first_class..row.len())
+            .map(|i| (i - first_class, row[i] * objectness))
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+        let Some((class, score)) = best else {
+            continue;
+        };
+        if score < threshold {
+            continue;
+        }
+        let (cx, cy, w, h) = (row[0], row[1], row[2], row[3]);
+        detections.push(Detection {
+            label: String::new(),
+            class,
+            score,
+            x: (cx - w / 2.0) / width,
+            y: (cy - h / 2.0) / height,
+            width: w / width,
+            height: h / height,
+        });
+    }
+    Ok(detections)
+}
+
+/// Detections from SSD outputs: `[1, n, 4]` boxes and `[1, n]` classes and
+/// scores
+fn parse_ssd(
+    boxes: ArrayViewD<f32>,
+    classes: ArrayViewD<f32>,
+    scores: ArrayViewD<f32>,
+    threshold: f32,
+) -> TractResult<Vec<Detection>> {
+    let boxes: Vec<f32> = boxes.iter().copied().collect();
+    let classes: Vec<f32> = classes.iter().copied().collect();
+    let scores: Vec<f32> = scores.iter().copied().collect();
+    if boxes.len() != scores.len() * 4 || classes.len() != scores.len() {
+        return Err(TractError::msg("mismatched output sizes"));
+    }
+    Ok(scores
+        .iter()
+        .zip(&classes)
+        .zip(boxes.chunks(4))
+        .filter(|((&score, _), _)| score >= threshold)
+        .map(|((&score, &class), b)| Detection {
+            label: String::new(),
+            class: class.max(0.0) as usize,
+            score,
+            x: b[1],
+            y: b[0],
+            width: b[3] - b[1],
+            height: b[2] - b[0],
+        })
+        .collect())
+}
+
+/// The `max` best detections, dropping any that overlap a better one of the
+/// same class
+fn nms(mut candidates: Vec<Detection>, max: usize) -> Vec<Detection> {
+    candidates.sort_by(|a, b| b.score.total_cmp(&a.score));
+    let mut kept: Vec<Detection> = Vec::new();
+    for candidate in candidates {
+        if kept.len() == max {
+            break;
+        }
+        let distinct = kept
+            .iter()
+            .all(|k| k.class != candidate.class || k.iou(&candidate) <= NMS_IOU);
+        if distinct {
+            kept.push(candidate);
+        }
+    }
+    kept
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tract_onnx::prelude::tract_ndarray::{arr2, arr3, Array3};
+
+    fn detection(class: usize, score: f32, x: f32) -> Detection {
+        Detection {
+            label: String::new(),
+            class,
+            score,
+            x,
+            y: 0.0,
+            width: 0.2,
+            height: 0.2,
+        }
+    }
+
+    /// `[1, rows, columns]` output with the given leading rows
+    fn output(rows: usize, columns: usize, values: &[&[f32]]) -> Array3<f32> {
+        let mut output = Array3::zeros((1, rows, columns));
+        for (row, values) in values.iter().enumerate() {
+            for (column, &v) in values.iter().enumerate() {
+                output[[0, row, column]] = v;
+            }
+        }
+        output
+    }
+
+    #[test]
+    fn test_parse_yolo_v8() {
+        // Box rows and 2 class rows, 8 candidates in columns, 100x50 input
+        let output = output(
+            6,
+            8,
+            &[
+                &[50.0, 10.0],
+                &[25.0, 10.0],
+                &[20.0, 10.0],
+                &[10.0, 10.0],
+                &[0.2, 0.1],
+                &[0.9, 0.3],
+            ],
+        );
+        let detections = parse_yolo(output.view().into_dyn(), (100, 50), 0.5).unwrap();
+        assert_eq!(detections.len(), 1);
+        let d = &detections[0];
+        assert_eq!((d.class, d.score), (1, 0.9));
+        assert_eq!((d.x, d.y, d.width, d.height), (0.4, 0.4, 0.2, 0.2));
+    }
+
+    #[test]
+    fn test_parse_yolo_v5() {
+        // 8 candidates in rows: box, objectness, 2 classes
+        let output = output(
+            8,
+            7,
+            &[
+                &[40.0, 40.0, 20.0, 40.0, 0.8, 0.9, 0.1],
+                // Confident class but unlikely object
+                &[40.0, 40.0, 20.0, 40.0, 0.3, 1.0, 0.0],
+            ],
+        );
+        let detections = parse_yolo(output.view().into_dyn(), (80, 80), 0.5).unwrap();
+        assert_eq!(detections.len(), 1);
+        let d = &detections[0];
+        assert_eq!(d.class, 0);
+        assert!((d.score - 0.72).abs() < 1e-6);
+        assert_eq!((d.x, d.y, d.width, d.height), (0.375, 0.25, 0.25, 0.5));
+
+        let flat = arr2(&[[1.0f32, 2.0]]);
+        assert!(parse_yolo(flat.view().into_dyn(), (80, 80), 0.5).is_err());
+    }
+
+    #[test]
+    fn test_parse_ssd() {
+        let boxes = arr3(&[[[0.1f32, 0.2, 0.5, 0.6], [0.0, 0.0, 1.0, 1.0]]]);
+        let classes = arr2(&[[3.0f32, 1.0]]);
+        let scores = arr2(&[[0.8f32, 0.2]]);
+        let detections = parse_ssd(
+            boxes.view().into_dyn(),
+            classes.view().into_dyn(),
+            scores.view().into_dyn(),
+            0.5,
+        )
+        .unwrap();
+        assert_eq!(detections.len(), 1);
+        let d = &detections[0];
+        assert_eq!((d.class, d.score), (3, 0.8));
+        assert_eq!((d.x, d.y), (0.2, 0.1));
+        assert!((d.width - 0.4).abs() < 1e-6 && (d.height - 0.4).abs() < 1e-6);
+
+        let short = arr2(&[[0.8f32]]);
+        let mismatched = parse_ssd(
+            boxes.view().into_dyn(),
+            classes.view().into_dyn(),
+            short.view().into_dyn(),
+            0.5,
+        );
+        assert!(mismatched.is_err());
+    }
+
+    #[test]
+    fn test_nms() {
+        let candidates = vec![
+            detection(0, 0.6, 0.02),
+            detection(0, 0.9, 0.0),
+            // Same place, different class
+            detection(1, 0.7, 0.0),
+            detection(0, 0.5, 0.5),
+        ];
+        let kept = nms(candidates.clone(), 10);
+        let kept: Vec<(usize, f32)> = kept.iter().map(|d| (d.class, d.score)).collect();
+        assert_eq!(kept, vec![(0, 0.9), (1, 0.7), (0, 0.5)]);
+        assert_eq!(nms(candidates, 1).len(), 1);
+    }
+
+    #[test]
+    fn test_load_errors() {
+        let config = DetectorConfig {
+            model: "/nonexistent/model.onnx".to_string(),
+            format: DetectionFormat::Yolo,
+            labels: None,
+            input_width: 320,
+            input_height: 320,
+            interval_ms: 500,
+            threshold: 0.5,
+            max_detections: 20,
+            overlay: false,
+        };
+        let result = Detector::load(&config, &ProcessorContext::default());
+        assert!(matches!(result, Err(ProcessorError::Model { .. })));
+
+        let labels = DetectorConfig {
+            labels: Some("/nonexistent/labels.txt".to_string()),
+            ..config
+        };
+        let result = Detector::load(&labels, &ProcessorContext::default());
+        assert!(
+            matches!(result, Err(ProcessorError::Model { path, .. }) if path.ends_with(".txt"))
+        );
+    }
+}
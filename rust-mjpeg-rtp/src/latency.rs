@@ -0,0 +1,263 @@
+//! Capture-to-decode latency measurement
+//!
+//! `mjpeg-rtp latency` captures with a [`timecode`](crate::timecode) painted
+//! into every raw frame, streams the camera to a receiver on the loopback
+//! interface through the normal packetizer and sender, reassembles the frames
+//! with the [`receiver`](crate::receiver) and reads the timecode back. The
+//! difference to the clock at that point covers colour conversion, JPEG
+//! encoding, queueing, packetization, sending and reassembly; only the
+//! sensor exposure and the network itself are left out.
+
+use crate::error::ErrorCode;
+use crate::frame::Frame;
+use crate::receiver::{Depacketizer, ReceiverConfig};
+use crate::streamer::{Streamer, StreamerConfig, StreamerError};
+use crate::task::CancellationToken;
+use crate::timecode;
+use std::io;
+use std::time::Duration;
+use thiserror::Error;
+use tokio::net::UdpSocket;
+use tokio::sync::mpsc;
+use tracing::debug;
+
+/// How long the receiver keeps reading after the last frame is sent
+const DRAIN: Duration = Duration::from_millis(300);
+
+#[derive(Error, Debug)]
+pub enum LatencyError {
+    #[error("latency receiver I/O error: {0}")]
+    Io(#[from] io::Error),
+
+    #[error(transparent)]
+    Streamer(#[from] StreamerError),
+
+    #[error("no frames were captured")]
+    NoFrames,
+}
+
+impl LatencyError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            LatencyError::Io(_) => ErrorCode::Io,
+            LatencyError::Streamer(e) => e.code(),
+            LatencyError::NoFrames => ErrorCode::Device,
+        }
+    }
+}
+
+/// Latencies measured over a run
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LatencyReport {
+    pub frames_sent: u64,
+    /// Frames reassembled by the receiver
+    pub frames_received: u64,
+    /// Reassembled frames whose timecode couldn't be read
+    pub frames_unreadable: u64,
+    /// Capture-to-decode latency of each readable frame, sorted
+    pub samples: Vec<Duration>,
+}
+
+impl LatencyReport {
+    /// Nearest-rank percentile, `p` between 0 and 100
+    pub fn percentile(&self, p: f64) -> Option<Duration> {
+        let last = self.samples.len().checked_sub(1)?;
+        let rank = (p.clamp(0.0, 100.0) / 100.0 * self.samples.len() as f64).ceil() as usize;
+        Some(self.samples[rank.saturating_sub(1).min(last)])
+    }
+
+    pub fn min(&self) -> Option<Duration> {
+        self.samples.first().copied()
+    }
+
+    pub fn max(&self) -> Option<Duration> {
+        self.samples.last().copied()
+    }
+
+    pub fn mean(&self) -> Option<Duration> {
+        let count = u32::try_from(self.samples.len()).ok().filter(|&n| n > 0)?;
+        Some(self.samples.iter().sum::<Duration>() / count)
+    }
+}
+
+/// Streams `frames` for `duration` to a receiver on 127.0.0.1 and measures
+/// each frame's timecode on arrival. `config` supplies everything but the
+/// destination, which is the receiver's port with RTCP multiplexed.
+pub async fn run(
+    mut frames: mpsc::Receiver<Frame>,
+    config: StreamerConfig,
+    duration: Duration,
+) -> Result<LatencyReport, LatencyError> {
+    let socket = UdpSocket::bind("127.0.0.1:0").await?;
+    let receiver_config = ReceiverConfig {
+        expected_ssrc: Some(config.ssrc),
+        frame_info_id: config.frame_info_id,
+    };
+    let config = StreamerConfig {
+        dest_host: "127.0.0.1".to_string(),
+        dest_port: socket.local_addr()?.port(),
+        local_port: 0,
+        rtcp_mux: true,
+        bind_address: None,
+        backup_destinations: Vec::new(),
+        rtcp_port: None,
+        ..config
+    };
+
+    let token = CancellationToken::new();
+    let receiver = tokio::spawn(receive(socket, receiver_config, token.clone()));
+    let mut streamer = Streamer::new(config).await?;
+    streamer.start().await?;
+
+    let mut frames_sent = 0;
+    let deadline = tokio::time::sleep(duration);
+    tokio::pin!(deadline);
+    let result = loop {
+        tokio::select! {
+            _ = &mut deadline => break Ok(()),
+            frame = frames.recv() => match frame {
+                Some(frame) => {
+                    if let Err(e) = streamer.send_frame(frame).await {
+                        break Err(e);
+                    }
+                    frames_sent += 1;
+                }
+                None => break Ok(()),
+            },
+        }
+    };
+
+    tokio::time::sleep(DRAIN).await;
+    streamer.stop().await?;
+    token.cancel();
+    let report = receiver.await.map_err(io::Error::other)??;
+    result?;
+    if frames_sent == 0 {
+        return Err(LatencyError::NoFrames);
+    }
+
+    Ok(LatencyReport {
+        frames_sent,
+        ..report
+    })
+}
+
+async fn receive(
+    socket: UdpSocket,
+    config: ReceiverConfig,
+    token: CancellationToken,
+) -> io::Result<LatencyReport> {
+    let mut depacketizer = Depacketizer::new(config);
+    let mut report = LatencyReport::default();
+    let mut buf = vec![0u8; 65536];
+    loop {
+        let len = tokio::select! {
+            _ = token.cancelled() => break,
+            received = socket.recv(&mut buf) => received?,
+        };
+        // RTCP shares the port; the depacketizer rejects it
+        let frame = match depacketizer.push(&buf[..len]) {
+            Ok(Some(frame)) => frame,
+            Ok(None) => continue,
+            Err(e) => {
+                debug!(error = %e, "Latency receiver dropped a packet");
+                continue;
+            }
+        };
+        report.frames_received += 1;
+        match timecode::read(&frame) {
+            Some(stamp) => {
+                let latency = timecode::now().wrapping_sub(stamp);
+                report.samples.push(Duration::from_micros(latency as u64));
+            }
+            None => report.frames_unreadable += 1,
+        }
+    }
+    report.samples.sort();
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+
+    /// 4:2:0 JPEG around a scan from [`timecode::flat_scan`]
+    fn jpeg(width: u16, height: u16, scan: &[u8]) -> Bytes {
+        let mut jpeg = vec![0xFF, 0xD8];
+        for table in 0..2u8 {
+            jpeg.extend([0xFF, 0xDB, 0x00, 0x43, table]);
+            jpeg.extend([8u8; 64]);
+        }
+        jpeg.extend([0xFF, 0xC0, 0x00, 0x11, 0x08]);
+        jpeg.extend(height.to_be_bytes());
+        jpeg.extend(width.to_be_bytes());
+        jpeg.extend([0x03, 0x01, 0x22, 0x00, 0x02, 0x11, 0x01, 0x03, 0x11, 0x01]);
+        jpeg.extend([
+            0xFF, 0xDA, 0x00, 0x0C, 0x03, 0x01, 0x00, 0x02, 0x11, 0x03, 0x11,
+        ]);
+        jpeg.extend([0x00, 0x3F, 0x00]);
+        jpeg.extend(scan);
+        jpeg.extend([0xFF, 0xD9]);
+        Bytes::from(jpeg)
+    }
+
+    #[test]
+    fn test_percentiles() {
+        let report = LatencyReport {
+            samples: (1..=100).map(Duration::from_millis).collect(),
+            ..Default::default()
+        };
+        assert_eq!(report.min(), Some(Duration::from_millis(1)));
+        assert_eq!(report.percentile(50.0), Some(Duration::from_millis(50)));
+        assert_eq!(report.percentile(99.0), Some(Duration::from_millis(99)));
+        assert_eq!(report.percentile(100.0), Some(Duration::from_millis(100)));
+        assert_eq!(report.max(), Some(Duration::from_millis(100)));
+        assert_eq!(report.mean(), Some(Duration::from_micros(50_500)));
+
+        let empty = LatencyReport::default();
+        assert_eq!(empty.percentile(50.0), None);
+        assert_eq!(empty.mean(), None);
+    }
+
+    #[tokio::test]
+    async fn test_measures_loopback() {
+        let (tx, rx) = mpsc::channel(4);
+        let source = tokio::spawn(async move {
+            for id in 0..10 {
+                let scan = timecode::flat_scan(640, 48, timecode::now());
+                let frame = Frame::new(id, jpeg(640, 48, &scan));
+                if tx.send(frame).await.is_err() {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        });
+
+        let config = StreamerConfig {
+            width: 640,
+            height: 48,
+            ..Default::default()
+        };
+        let report = run(rx, config, Duration::from_millis(400)).await.unwrap();
+        source.await.unwrap();
+
+        assert_eq!(report.frames_sent, 10);
+        assert_eq!(report.frames_received, 10);
+        assert_eq!(report.frames_unreadable, 0);
+        assert_eq!(report.samples.len(), 10);
+        assert!(
+            report.max().unwrap() < Duration::from_secs(1),
+            "{:?}",
+            report
+        );
+    }
+
+    #[tokio::test]
+    async fn test_no_frames() {
+        let (tx, rx) = mpsc::channel(1);
+        drop(tx);
+        let result = run(rx, StreamerConfig::default(), Duration::from_millis(100)).await;
+        assert!(matches!(result, Err(LatencyError::NoFrames)));
+    }
+}
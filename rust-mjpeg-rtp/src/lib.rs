@@ -16,12 +16,63 @@
 //! // let packets = packetizer.packetize_jpeg(&jpeg_data, 1920, 1080, timestamp)?;
 //! ```
 
+pub mod affinity;
+pub mod api;
+pub mod app;
+pub mod calibration;
 pub mod capture;
+pub mod clips;
 pub mod config;
+pub mod congestion;
+pub mod continuity;
+pub mod coordination;
+pub mod error;
+pub mod events;
+pub mod frame;
+pub mod identity;
+#[cfg(any(feature = "wasm", feature = "inference"))]
+pub mod imaging;
+#[cfg(feature = "inference")]
+pub mod inference;
+pub mod latency;
+pub mod mdns;
+pub mod metrics;
+pub mod overlay;
+pub mod pacing;
+#[cfg(feature = "wasm")]
+pub mod plugin;
+pub mod processor;
+pub mod pull;
+pub mod ratelimit;
+pub mod realtime;
+pub mod receiver;
+pub mod recording;
+pub mod relay;
 pub mod rtp;
+pub mod stats_report;
 pub mod streamer;
+pub mod supervisor;
+pub mod tap;
+pub mod task;
+#[cfg(feature = "otel")]
+pub mod telemetry;
+pub mod timecode;
+pub mod timeline;
+pub mod transcode;
 
 // Re-exports for convenience
-pub use capture::{Capture, CaptureConfig, CaptureStats, PlatformInfo};
-pub use rtp::{PacketizerStats, RtpPacketizer, TimestampGenerator};
-pub use streamer::{Streamer, StreamerConfig, StreamerStats};
+pub use capture::{
+    list_cameras, CameraInfo, Capture, CaptureConfig, CaptureError, CaptureStats, PlatformInfo,
+};
+pub use error::{Error, ErrorCode, Result};
+pub use frame::Frame;
+pub use recording::{Recorder, RecordingError, Replay};
+pub use rtp::{
+    ClockDriftStats, JpegProbe, PacketPlan, PacketizerError, PacketizerStats, QTablePolicy,
+    RtpPacketizer, TimestampGenerator, TimestampSource,
+};
+pub use streamer::{
+    DestinationStats, Destinations, FailoverReason, HealthStats, HealthStatus, KeepaliveKind,
+    SendErrorStats, SendTimingStats, ShapingStats, SharedLimit, SharedSocket, Streamer,
+    StreamerConfig, StreamerError, StreamerEvent, StreamerStats, StreamerStatsHandle,
+};
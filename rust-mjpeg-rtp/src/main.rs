@@ -1,88 +1,329 @@
 //! MJPEG-RTP streaming CLI application
+//!
+//! `stream` (the default) runs the streamer; the other subcommands are
+//! one-shot tools for checking cameras and JPEG output in the field.
 
 // Use jemalloc for better memory management (optional feature)
 #[cfg(feature = "jemalloc")]
 #[global_allocator]
 static GLOBAL: tikv_jemallocator::Jemalloc = tikv_jemallocator::Jemalloc;
 
-use anyhow::Result;
-use clap::Parser;
+use anyhow::{anyhow, bail, Context, Result};
+use clap::{Parser, Subcommand};
+use rust_mjpeg_rtp::api::ApiRegistry;
+use rust_mjpeg_rtp::app::{
+    self, camera_section, capture_config, load_identity, rand_ssrc, streamer_config,
+};
+use rust_mjpeg_rtp::calibration::{self, CalibrationConfig};
+use rust_mjpeg_rtp::capture::{detect_pi_model, list_cameras};
 use rust_mjpeg_rtp::config::Config;
-use rust_mjpeg_rtp::{Capture, CaptureConfig, Streamer, StreamerConfig};
-use tracing::{error, info};
+use rust_mjpeg_rtp::identity::CameraIdentity;
+use rust_mjpeg_rtp::latency;
+use rust_mjpeg_rtp::rtp::{
+    sdp_dimensions_attribute, BundleDescription, SessionDescription, MAX_DIMENSION,
+};
+use rust_mjpeg_rtp::tap::DeviceTaps;
+use rust_mjpeg_rtp::task::CancellationToken;
+#[cfg(feature = "otel")]
+use rust_mjpeg_rtp::telemetry::Telemetry;
+use rust_mjpeg_rtp::timecode;
+use rust_mjpeg_rtp::{Capture, CaptureConfig, JpegProbe, RtpPacketizer};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+use tracing_subscriber::fmt::format::FmtSpan;
+use tracing_subscriber::prelude::*;
 use tracing_subscriber::{fmt, EnvFilter};
 
+/// How long components get to stop after Ctrl+C before being aborted
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(5);
+
+/// How long `snapshot` waits for a frame after the warm-up frames
+const SNAPSHOT_TIMEOUT: Duration = Duration::from_secs(10);
+
 #[derive(Parser, Debug)]
 #[command(name = "mjpeg-rtp")]
 #[command(about = "High-performance MJPEG-RTP streaming for Raspberry Pi dual cameras")]
 #[command(version)]
 struct Cli {
     /// Path to configuration file
-    #[arg(short, long, default_value = "config.toml")]
+    #[arg(short, long, default_value = "config.toml", global = true)]
     config: String,
 
     /// Enable verbose logging
-    #[arg(short, long)]
+    #[arg(short, long, global = true)]
     verbose: bool,
+
+    /// When streaming, log per-frame spans (queue wait, packetize and send timings) on close
+    #[arg(long, global = true)]
+    trace_frames: bool,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Stream the enabled cameras over RTP (the default)
+    Stream,
+
+    /// Capture a single JPEG frame to a file
+    Snapshot {
+        /// File to write the frame to
+        #[arg(short, long)]
+        output: PathBuf,
+
+        /// Camera section of the config to capture from
+        #[arg(long, default_value = "camera1", value_parser = ["camera1", "camera2"])]
+        camera: String,
+
+        /// Frames to discard first, while exposure settles
+        #[arg(long, default_value_t = 10)]
+        warmup: u32,
+    },
+
+    /// List the cameras GStreamer can see
+    ListCameras,
+
+    /// Capture without sending and report frame rate, frame size and packetization cost
+    Benchmark {
+        /// Camera section of the config to capture from
+        #[arg(long, default_value = "camera1", value_parser = ["camera1", "camera2"])]
+        camera: String,
+
+        /// How long to capture for
+        #[arg(long, default_value_t = 10)]
+        seconds: u64,
+    },
+
+    /// Check that a JPEG file can be sent as RFC 2435 RTP/JPEG
+    ProbeJpeg {
+        /// JPEG file, e.g. one written by `snapshot`
+        file: PathBuf,
+    },
+
+    /// Print the SDP a receiver can open a camera's stream with (both
+    /// cameras' with `bundle`)
+    Sdp {
+        /// Camera section of the config to describe
+        #[arg(long, default_value = "camera1", value_parser = ["camera1", "camera2"])]
+        camera: String,
+    },
+
+    /// Report capture-to-decode latency, streaming a camera with a timecode over loopback
+    Latency {
+        /// Camera section of the config to measure
+        #[arg(long, default_value = "camera1", value_parser = ["camera1", "camera2"])]
+        camera: String,
+
+        /// How long to measure for
+        #[arg(long, default_value_t = 10)]
+        seconds: u64,
+    },
+
+    /// Measure the bandwidth to a `calibrate-echo` server and recommend congestion settings
+    Calibrate {
+        /// Address of the machine running `calibrate-echo`, e.g. 192.168.1.10:5004
+        #[arg(long)]
+        dest: String,
+
+        /// Camera section of the config whose frame size, rate and MTU to send with
+        #[arg(long, default_value = "camera1", value_parser = ["camera1", "camera2"])]
+        camera: String,
+
+        /// Rate of the first step
+        #[arg(long, default_value_t = 1000)]
+        start_kbps: u64,
+
+        /// Stop ramping up at this rate
+        #[arg(long, default_value_t = 50_000)]
+        max_kbps: u64,
+
+        /// How long to send at each rate
+        #[arg(long, default_value_t = 3)]
+        step_seconds: u64,
+
+        /// Loss (percent) at which a step fails
+        #[arg(long, default_value_t = 2.0)]
+        max_loss_percent: f32,
+
+        /// Write the recommendation into [mjpeg-rtp.congestion] of the config file
+        #[arg(long)]
+        write: bool,
+    },
+
+    /// Receive `calibrate` streams and answer them with RTCP receiver reports
+    CalibrateEcho {
+        /// Address to listen on
+        #[arg(long, default_value = "0.0.0.0:5004")]
+        listen: String,
+    },
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
     let cli = Cli::parse();
 
+    match cli.command {
+        None | Some(Command::Stream) => stream(&cli).await,
+        Some(Command::Snapshot {
+            ref output,
+            ref camera,
+            warmup,
+        }) => {
+            init_tool_logging(cli.verbose);
+            snapshot(&Config::load(&cli.config)?, camera, output, warmup).await
+        }
+        Some(Command::ListCameras) => {
+            init_tool_logging(cli.verbose);
+            print_cameras(cli.verbose)
+        }
+        Some(Command::Benchmark {
+            ref camera,
+            seconds,
+        }) => {
+            init_tool_logging(cli.verbose);
+            benchmark(
+                &Config::load(&cli.config)?,
+                camera,
+                Duration::from_secs(seconds),
+            )
+            .await
+        }
+        Some(Command::ProbeJpeg { ref file }) => probe_jpeg(file),
+        Some(Command::Sdp { ref camera }) => {
+            init_tool_logging(cli.verbose);
+            print_sdp(&Config::load(&cli.config)?, camera)
+        }
+        Some(Command::Latency {
+            ref camera,
+            seconds,
+        }) => {
+            init_tool_logging(cli.verbose);
+            measure_latency(
+                &Config::load(&cli.config)?,
+                camera,
+                Duration::from_secs(seconds),
+            )
+            .await
+        }
+        Some(Command::Calibrate {
+            ref dest,
+            ref camera,
+            start_kbps,
+            max_kbps,
+            step_seconds,
+            max_loss_percent,
+            write,
+        }) => {
+            init_tool_logging(cli.verbose);
+            let config = Config::load(&cli.config)?;
+            let dest = tokio::net::lookup_host(dest)
+                .await
+                .with_context(|| format!("resolving {}", dest))?
+                .next()
+                .ok_or_else(|| anyhow!("{} has no address", dest))?;
+            let camera_config = camera_section(&config, camera);
+            let calibration = CalibrationConfig {
+                dest,
+                start_kbps,
+                max_kbps,
+                step_factor: 1.5,
+                step_duration: Duration::from_secs(step_seconds),
+                width: camera_config.width,
+                height: camera_config.height,
+                fps: camera_config.fps,
+                mtu: config.mjpeg_rtp.network_for(camera_config).mtu,
+                max_loss: max_loss_percent / 100.0,
+            };
+            let output = write.then(|| Path::new(&cli.config));
+            calibrate(&config, &calibration, output).await
+        }
+        Some(Command::CalibrateEcho { ref listen }) => {
+            init_tool_logging(cli.verbose);
+            calibrate_echo(listen).await
+        }
+    }
+}
+
+/// Tools print their results to stdout; logs go to stderr and stay quiet
+/// unless `--verbose` is given
+fn init_tool_logging(verbose: bool) {
+    let filter = if verbose {
+        EnvFilter::new("debug")
+    } else {
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("warn"))
+    };
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_target(false)
+        .with_writer(std::io::stderr)
+        .init();
+}
+
+async fn stream(cli: &Cli) -> Result<()> {
+    // Load configuration first so the [telemetry] section can shape logging
+    let config = Config::load(&cli.config)?;
+
     // Setup logging
     let filter = if cli.verbose {
         EnvFilter::new("debug")
+    } else if cli.trace_frames {
+        EnvFilter::new("info,rust_mjpeg_rtp::streamer=debug")
     } else {
         EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"))
     };
 
-    fmt().with_env_filter(filter).with_target(false).init();
+    let span_events = if cli.trace_frames {
+        FmtSpan::CLOSE
+    } else {
+        FmtSpan::NONE
+    };
 
-    info!("MJPEG-RTP Streamer starting");
-    info!(config_path = %cli.config, "Loading configuration");
+    let registry = tracing_subscriber::registry().with(
+        fmt::layer()
+            .with_span_events(span_events)
+            .with_target(false)
+            .with_filter(filter),
+    );
 
-    // Load configuration
-    let config = Config::load(&cli.config)?;
+    #[cfg(feature = "otel")]
+    let telemetry = if config.telemetry.enabled {
+        Some(Telemetry::init(&config.telemetry)?)
+    } else {
+        None
+    };
 
-    if !config.mjpeg_rtp.enabled {
-        info!("MJPEG-RTP mode is disabled in configuration");
-        return Ok(());
-    }
+    #[cfg(feature = "otel")]
+    registry
+        .with(telemetry.as_ref().map(|t| {
+            // Per-frame spans are debug level; export them regardless of console verbosity
+            t.tracing_layer()
+                .with_filter(EnvFilter::new("info,rust_mjpeg_rtp=debug"))
+        }))
+        .init();
 
-    info!(
-        camera1_enabled = %config.mjpeg_rtp.camera1.enabled,
-        camera2_enabled = %config.mjpeg_rtp.camera2.enabled,
-        "Configuration loaded"
-    );
+    #[cfg(not(feature = "otel"))]
+    registry.init();
 
-    // Start camera1 if enabled
-    let mut tasks = vec![];
+    info!("MJPEG-RTP Streamer starting");
+    info!(config_path = %cli.config, "Configuration loaded from file");
 
-    if config.mjpeg_rtp.camera1.enabled {
-        info!("Starting camera1...");
-        let camera_config = config.mjpeg_rtp.camera1.clone();
-        let task = tokio::spawn(async move {
-            if let Err(e) = run_camera("camera1", camera_config).await {
-                error!(camera = "camera1", error = %e, "Camera failed");
-            }
-        });
-        tasks.push(task);
+    #[cfg(not(feature = "otel"))]
+    if config.telemetry.enabled {
+        tracing::warn!(
+            "[telemetry] is enabled but this build lacks the `otel` feature; not exporting"
+        );
     }
 
-    if config.mjpeg_rtp.camera2.enabled {
-        info!("Starting camera2...");
-        let camera_config = config.mjpeg_rtp.camera2.clone();
-        let task = tokio::spawn(async move {
-            if let Err(e) = run_camera("camera2", camera_config).await {
-                error!(camera = "camera2", error = %e, "Camera failed");
-            }
-        });
-        tasks.push(task);
+    #[cfg(feature = "otel")]
+    if telemetry.is_some() {
+        info!(endpoint = %config.telemetry.otlp_endpoint, "Exporting traces and metrics via OTLP");
     }
 
-    if tasks.is_empty() {
-        info!("No cameras enabled, exiting");
+    let api_registry = ApiRegistry::default();
+    let mut supervisor = app::start(&config, &api_registry, &DeviceTaps::default()).await?;
+    if supervisor.is_empty() {
         return Ok(());
     }
 
@@ -91,70 +332,389 @@ async fn main() -> Result<()> {
     tokio::signal::ctrl_c().await?;
     info!("Shutting down");
 
-    // Tasks will be cancelled when they go out of scope
+    // Cameras stop capturing and drop their streamers, which ends the senders
+    let aborted = supervisor.shutdown(SHUTDOWN_GRACE).await;
+    if aborted > 0 {
+        warn!(aborted, "Components did not stop in time");
+    }
+
+    #[cfg(feature = "otel")]
+    if let Some(telemetry) = telemetry {
+        telemetry.shutdown();
+    }
+
     Ok(())
 }
 
-async fn run_camera(name: &str, camera_config: rust_mjpeg_rtp::config::CameraConfig) -> Result<()> {
-    // Create capture
-    let capture_config = CaptureConfig {
-        device_path: camera_config.device.clone(),
-        width: camera_config.width,
-        height: camera_config.height,
-        fps: camera_config.fps,
-        quality: camera_config.quality,
-        flip_method: camera_config.flip_method.clone(),
-    };
+async fn snapshot(config: &Config, camera: &str, output: &Path, warmup: u32) -> Result<()> {
+    let preset = config.mjpeg_rtp.platform.resolve(detect_pi_model());
+    let capture_config = capture_config(camera, camera_section(config, camera), preset);
+    let (width, height) = (capture_config.width, capture_config.height);
 
     let mut capture = Capture::new(capture_config)?;
-    let mut frame_rx = capture.start().await?;
-
-    // Create streamer
-    let streamer_config = StreamerConfig {
-        dest_host: camera_config.dest_host.clone(),
-        dest_port: camera_config.dest_port,
-        local_port: camera_config.local_port,
-        width: camera_config.width,
-        height: camera_config.height,
-        fps: camera_config.fps,
-        mtu: 1400, // TODO: get from global config
-        ssrc: camera_config.ssrc,
-        dscp: 0, // TODO: get from global config
-    };
+    let mut frames = capture.start().await?;
+    let frame = tokio::time::timeout(SNAPSHOT_TIMEOUT, async {
+        let mut skipped = 0;
+        while let Some(frame) = frames.recv().await {
+            if skipped >= warmup {
+                return Some(frame);
+            }
+            skipped += 1;
+        }
+        None
+    })
+    .await;
+    capture.stop().await?;
 
-    let mut streamer = Streamer::new(streamer_config).await?;
-    streamer.start().await?;
+    let frame = frame
+        .map_err(|_| anyhow!("no frame from {} within {:?}", camera, SNAPSHOT_TIMEOUT))?
+        .ok_or_else(|| anyhow!("{} stopped before producing a frame", camera))?;
+    std::fs::write(output, &frame.data).with_context(|| format!("writing {}", output.display()))?;
+
+    println!(
+        "Wrote {} ({}x{}, {} bytes)",
+        output.display(),
+        width,
+        height,
+        frame.data.len()
+    );
+    Ok(())
+}
 
-    info!(camera = name, "Camera streaming started");
+fn print_cameras(verbose: bool) -> Result<()> {
+    let cameras = list_cameras()?;
+    if cameras.is_empty() {
+        println!("No cameras found");
+        return Ok(());
+    }
 
-    // Forward frames from capture to streamer
-    let mut frame_count = 0u64;
-    while let Some(frame) = frame_rx.recv().await {
-        if let Err(e) = streamer.send_frame(frame).await {
-            error!(camera = name, error = %e, "Failed to send frame");
-            continue;
+    for (index, camera) in cameras.iter().enumerate() {
+        println!("{}: {}", index, camera.name);
+        println!("   device  = {}", camera.device.as_deref().unwrap_or("?"));
+        println!("   element = {}", camera.element.as_deref().unwrap_or("?"));
+        if verbose {
+            for caps in &camera.caps {
+                println!("   {}", caps);
+            }
+        } else {
+            println!("   {} formats (--verbose to list)", camera.caps.len());
         }
+    }
+    Ok(())
+}
+
+async fn benchmark(config: &Config, camera: &str, duration: Duration) -> Result<()> {
+    let camera_config = camera_section(config, camera);
+    let preset = config.mjpeg_rtp.platform.resolve(detect_pi_model());
+    let capture_config = capture_config(camera, camera_config, preset);
+    let (width, height, fps) = (
+        capture_config.width,
+        capture_config.height,
+        capture_config.fps,
+    );
+
+    let mtu = config.mjpeg_rtp.network_for(camera_config).mtu;
+    let packetizer = RtpPacketizer::new(rand_ssrc(), mtu)
+        .with_oversize_dimensions(config.mjpeg_rtp.oversize_dimensions)
+        .with_frame_info(config.mjpeg_rtp.frame_info_extension)
+        .with_qtable_policy(
+            config.mjpeg_rtp.qtable_policy,
+            config.mjpeg_rtp.qtable_refresh_frames,
+        );
+
+    let mut capture = Capture::new(capture_config)?;
+    let mut frames = capture.start().await?;
 
-        frame_count += 1;
-
-        // Log stats periodically
-        if frame_count % 100 == 0 {
-            let capture_stats = capture.get_stats();
-            let streamer_stats = streamer.get_stats();
-
-            info!(
-                camera = name,
-                captured = %capture_stats.frames_captured,
-                sent = %streamer_stats.frames_sent,
-                dropped = %streamer_stats.frames_dropped,
-                rtp_packets = %streamer_stats.rtp_packets_sent,
-                "Stats"
-            );
+    let mut count = 0u64;
+    let mut first_id = None;
+    let mut last_id = 0;
+    let (mut bytes, mut max_bytes) = (0usize, 0usize);
+    let (mut packets, mut packetize_errors) = (0usize, 0u64);
+    let (mut packetize_time, mut max_packetize) = (Duration::ZERO, Duration::ZERO);
+
+    let started = Instant::now();
+    let deadline = tokio::time::sleep(duration);
+    tokio::pin!(deadline);
+    loop {
+        let frame = tokio::select! {
+            _ = &mut deadline => break,
+            frame = frames.recv() => match frame {
+                Some(frame) => frame,
+                None => break,
+            },
+        };
+
+        count += 1;
+        first_id.get_or_insert(frame.id);
+        last_id = frame.id;
+        bytes += frame.data.len();
+        max_bytes = max_bytes.max(frame.data.len());
+
+        let packetize_start = Instant::now();
+        match packetizer.packetize_jpeg(&frame.data, width, height, 0) {
+            Ok(frame_packets) => packets += frame_packets.len(),
+            Err(_) => packetize_errors += 1,
         }
+        let elapsed = packetize_start.elapsed();
+        packetize_time += elapsed;
+        max_packetize = max_packetize.max(elapsed);
     }
+    let elapsed = started.elapsed().as_secs_f64();
+    capture.stop().await?;
+
+    if count == 0 {
+        return Err(anyhow!("no frames from {} in {:?}", camera, duration));
+    }
+
+    let missing = first_id.map_or(0, |first| (last_id - first + 1).saturating_sub(count));
+    let packetized = (count - packetize_errors).max(1);
+    println!(
+        "{}: {}x{} @ {} fps requested, {:.1} s, {:?} encoder",
+        camera, width, height, fps, elapsed, preset.encoder
+    );
+    println!(
+        "  frames     {} ({:.1} fps), {} missing from the id sequence",
+        count,
+        count as f64 / elapsed,
+        missing
+    );
+    println!(
+        "  frame size avg {:.1} KiB, max {:.1} KiB ({:.1} Mbit/s)",
+        bytes as f64 / count as f64 / 1024.0,
+        max_bytes as f64 / 1024.0,
+        bytes as f64 * 8.0 / elapsed / 1_000_000.0
+    );
+    println!(
+        "  packetize  avg {} us, max {} us, {:.1} packets/frame at MTU {}, {} failed",
+        packetize_time.as_micros() as u64 / count,
+        max_packetize.as_micros(),
+        packets as f64 / packetized as f64,
+        mtu,
+        packetize_errors
+    );
+    Ok(())
+}
+
+fn print_sdp(config: &Config, camera: &str) -> Result<()> {
+    if config.mjpeg_rtp.bundle {
+        let bundle = BundleDescription {
+            name: "cameras".to_string(),
+            streams: vec![
+                session_description(config, "camera1")?,
+                session_description(config, "camera2")?,
+            ],
+        };
+        print!("{}", bundle);
+    } else {
+        print!("{}", session_description(config, camera)?);
+    }
+    Ok(())
+}
+
+/// SDP for one camera section
+fn session_description(config: &Config, camera: &str) -> Result<SessionDescription> {
+    let rtp_config = &config.mjpeg_rtp;
+    let camera_config = camera_section(config, camera);
+    let preset = rtp_config.platform.resolve(detect_pi_model());
+    let capture_config = capture_config(camera, camera_config, preset);
+
+    let identity = load_identity(camera, rtp_config);
+    let ssrc = camera_config
+        .ssrc
+        .or_else(|| identity.as_ref().map(CameraIdentity::ssrc));
+    let mut sdp = streamer_config(
+        camera_config,
+        rtp_config,
+        (capture_config.width, capture_config.height),
+        ssrc.unwrap_or_default(),
+        identity.as_ref(),
+    )
+    .session_description(camera)?;
+    // Without a fixed SSRC each run picks a new one; don't pin a stale value
+    sdp.ssrc = ssrc;
+    Ok(sdp)
+}
+
+async fn measure_latency(config: &Config, camera: &str, duration: Duration) -> Result<()> {
+    let camera_config = camera_section(config, camera);
+    let preset = config.mjpeg_rtp.platform.resolve(detect_pi_model());
+    let capture_config = CaptureConfig {
+        timecode: true,
+        ..capture_config(camera, camera_config, preset)
+    };
+    let (width, height) = (capture_config.width, capture_config.height);
+    if (width as usize) < timecode::MIN_WIDTH {
+        bail!(
+            "the timecode needs frames at least {} pixels wide",
+            timecode::MIN_WIDTH
+        );
+    }
+    let streamer_config = streamer_config(
+        camera_config,
+        &config.mjpeg_rtp,
+        (width, height),
+        rand_ssrc(),
+        None,
+    );
 
+    let mut capture = Capture::new(capture_config)?;
+    let frames = capture.start().await?;
+    let result = latency::run(frames, streamer_config, duration).await;
     capture.stop().await?;
-    info!(camera = name, "Camera stopped");
+    let report = result?;
+
+    println!(
+        "{} {}x{} @ {} fps, {}s: {} frames sent, {} received, {} without a readable timecode",
+        camera,
+        width,
+        height,
+        camera_config.fps,
+        duration.as_secs(),
+        report.frames_sent,
+        report.frames_received,
+        report.frames_unreadable
+    );
+    if report.samples.is_empty() {
+        bail!("no frame's timecode could be read");
+    }
+    let ms = |latency: Option<Duration>| latency.unwrap_or_default().as_secs_f64() * 1000.0;
+    println!("Capture to decode latency (ms):");
+    println!(
+        "  min {:.1}  p50 {:.1}  p90 {:.1}  p99 {:.1}  max {:.1}  mean {:.1}",
+        ms(report.min()),
+        ms(report.percentile(50.0)),
+        ms(report.percentile(90.0)),
+        ms(report.percentile(99.0)),
+        ms(report.max()),
+        ms(report.mean())
+    );
+    Ok(())
+}
+
+async fn calibrate(
+    config: &Config,
+    calibration: &CalibrationConfig,
+    output: Option<&Path>,
+) -> Result<()> {
+    println!(
+        "Calibrating to {} with {}x{} frames at {} fps, up to {} kbps",
+        calibration.dest,
+        calibration.width,
+        calibration.height,
+        calibration.fps,
+        calibration.max_kbps
+    );
+    let report = calibration::run(calibration).await?;
+
+    println!(
+        "{:>12} {:>12} {:>8} {:>10}",
+        "target kbps", "sent kbps", "loss", "jitter"
+    );
+    for step in &report.steps {
+        println!(
+            "{:>12} {:>12} {:>7.2}% {:>8.1}ms",
+            step.target_kbps,
+            step.sent_kbps,
+            step.loss * 100.0,
+            step.jitter_ms
+        );
+    }
+
+    let cameras = [&config.mjpeg_rtp.camera1, &config.mjpeg_rtp.camera2]
+        .iter()
+        .filter(|camera| camera.enabled)
+        .count()
+        .max(1) as u32;
+    let Some(recommendation) = report.recommend(cameras) else {
+        bail!(
+            "the first step at {} kbps already lost too much; retry with a lower --start-kbps",
+            calibration.start_kbps
+        );
+    };
+    let sustained = report.sustained_kbps().unwrap_or_default();
+    match report.steps.last() {
+        Some(last) if last.loss > report.max_loss => {
+            println!("Sustained {} kbps; loss set in above that", sustained)
+        }
+        _ => println!(
+            "Sustained {} kbps without reaching the link's limit",
+            sustained
+        ),
+    }
+    println!("Recommended for {} camera(s):", cameras);
+    println!("  [mjpeg-rtp.congestion]");
+    println!("  controller = \"aimd\"");
+    println!(
+        "  target_bitrate_kbps = {}",
+        recommendation.target_bitrate_kbps
+    );
+    println!("  max_bitrate_kbps = {}", recommendation.max_bitrate_kbps);
 
+    if let Some(path) = output {
+        calibration::write_recommendation(path, &recommendation)?;
+        println!("Written to {}", path.display());
+    }
     Ok(())
 }
+
+async fn calibrate_echo(listen: &str) -> Result<()> {
+    let socket = tokio::net::UdpSocket::bind(listen)
+        .await
+        .with_context(|| format!("binding {}", listen))?;
+    println!("Answering calibration streams on {}", socket.local_addr()?);
+
+    let token = CancellationToken::new();
+    let echo = tokio::spawn(calibration::run_echo(socket, token.clone()));
+    tokio::signal::ctrl_c().await?;
+    token.cancel();
+    echo.await??;
+    Ok(())
+}
+
+fn probe_jpeg(file: &Path) -> Result<()> {
+    let data = std::fs::read(file).with_context(|| format!("reading {}", file.display()))?;
+    let probe = JpegProbe::new(&data).with_context(|| format!("parsing {}", file.display()))?;
+
+    println!("{}: {} bytes", file.display(), data.len());
+    println!("  dimensions  {}x{}", probe.width, probe.height);
+    println!("  sof         {:#04x}", probe.sof_marker);
+    let sampling: Vec<String> = probe
+        .components
+        .iter()
+        .map(|c| format!("{}x{}", c.horizontal, c.vertical))
+        .collect();
+    println!("  sampling    {}", sampling.join(" "));
+    println!(
+        "  type        {} ({})",
+        probe.jpeg_type,
+        if probe.jpeg_type == 0 {
+            "4:2:0"
+        } else {
+            "4:2:2"
+        }
+    );
+    for table in &probe.q_tables {
+        println!("  q-table {}   {}-bit", table.id, table.precision);
+    }
+    println!("  restart     {}", probe.restart_interval);
+    println!("  scan data   {} bytes", probe.scan_size);
+    if u32::from(probe.width) > MAX_DIMENSION || u32::from(probe.height) > MAX_DIMENSION {
+        println!(
+            "  sdp         {}",
+            sdp_dimensions_attribute(probe.width.into(), probe.height.into())
+        );
+    }
+
+    if probe.is_compatible() {
+        println!("RFC 2435 compatible");
+        return Ok(());
+    }
+    for issue in &probe.issues {
+        println!("  ! {}", issue);
+    }
+    bail!(
+        "{} RFC 2435 issue(s) in {}",
+        probe.issues.len(),
+        file.display()
+    )
+}
@@ -0,0 +1,474 @@
+//! DNS-SD advertisement of the camera streams over mDNS (RFC 6762, 6763)
+//!
+//! With `[mjpeg-rtp.mdns]` enabled, each enabled camera is announced as the
+//! instance `<hostname>-<camera>` of `service` (`_mjpeg-rtp._udp.local` by
+//! default). Its TXT record carries the stream's stable identity (see
+//! [`crate::identity`]): `camera`, `uuid` and `cname`, so a recorder can find
+//! the stream and tell it is the same one after a reboot. The SRV port is the
+//! camera's pull listen port in pull mode, otherwise its local RTP port (0
+//! when that is auto-assigned).
+//!
+//! The records are announced at start, answered when queried and withdrawn
+//! on shutdown. `<hostname>.local` itself is left to the system's responder
+//! (avahi), which shares port 5353. IPv4 only.
+
+use crate::config::MdnsConfig;
+use crate::log_limited;
+use crate::ratelimit::LogLimiter;
+use crate::task::CancellationToken;
+use socket2::{Domain, Protocol, Socket, Type};
+use std::net::{Ipv4Addr, SocketAddr};
+use std::time::Duration;
+use tokio::net::UdpSocket;
+use tracing::{debug, info};
+use uuid::Uuid;
+
+const MDNS_PORT: u16 = 5353;
+const MDNS_GROUP: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 251);
+
+/// Lists the service types on the link (RFC 6763 Section 9)
+const SERVICES: &str = "_services._dns-sd._udp.local";
+
+const TYPE_PTR: u16 = 12;
+const TYPE_TXT: u16 = 16;
+const TYPE_SRV: u16 = 33;
+const TYPE_ANY: u16 = 255;
+const CLASS_IN: u16 = 1;
+const CLASS_ANY: u16 = 255;
+/// Set on records only this host answers for (RFC 6762 Section 10.2)
+const CACHE_FLUSH: u16 = 0x8000;
+
+/// TTLs RFC 6762 Section 10 recommends: records naming a host, and others
+const HOST_TTL: u32 = 120;
+const OTHER_TTL: u32 = 4500;
+/// Cap for answers to one-shot (legacy unicast) queries (Section 6.7)
+const LEGACY_TTL: u32 = 10;
+
+/// One camera stream to advertise
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MdnsStream {
+    pub camera: String,
+    pub port: u16,
+    pub uuid: Option<Uuid>,
+    pub cname: Option<String>,
+}
+
+/// A resource record, rdata already encoded
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Record {
+    name: String,
+    rtype: u16,
+    /// Only this host answers for it
+    unique: bool,
+    ttl: u32,
+    rdata: Vec<u8>,
+}
+
+/// A question of a query
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Question {
+    name: String,
+    qtype: u16,
+    qclass: u16,
+}
+
+/// The records of every advertised stream
+pub struct Records {
+    service: String,
+    /// `(instance name, SRV, TXT)` per stream
+    instances: Vec<(String, Record, Record)>,
+}
+
+impl Records {
+    /// Records for `streams` as instances of `service` (without `.local`)
+    /// on `hostname`
+    pub fn new(service: &str, hostname: &str, streams: &[MdnsStream]) -> Self {
+        let service = format!("{}.local", service);
+        let target = format!("{}.local", hostname);
+        let instances = streams
+            .iter()
+            .map(|stream| {
+                let instance = format!("{}-{}.{}", hostname, stream.camera, service);
+                let mut srv = Vec::new();
+                srv.extend_from_slice(&0u16.to_be_bytes()); // priority
+                srv.extend_from_slice(&0u16.to_be_bytes()); // weight
+                srv.extend_from_slice(&stream.port.to_be_bytes());
+                put_name(&mut srv, &target);
+
+                let mut txt = Vec::new();
+                let uuid = stream.uuid.map(|uuid| uuid.hyphenated().to_string());
+                let entries = [
+                    Some(format!("camera={}", stream.camera)),
+                    uuid.map(|uuid| format!("uuid={}", uuid)),
+                    stream
+                        .cname
+                        .as_ref()
+                        .map(|cname| format!("cname={}", cname)),
+                ];
+                for entry in entries.into_iter().flatten() {
+                    let entry = &entry.as_bytes()[..entry.len().min(255)];
+                    txt.push(entry.len() as u8);
+                    txt.extend_from_slice(entry);
+                }
+
+                let record = |rtype, ttl, rdata| Record {
+                    name: instance.clone(),
+                    rtype,
+                    unique: true,
+                    ttl,
+                    rdata,
+                };
+                let srv = record(TYPE_SRV, HOST_TTL, srv);
+                let txt = record(TYPE_TXT, OTHER_TTL, txt);
+                (instance, srv, txt)
+            })
+            .collect();
+        Self { service, instances }
+    }
+
+    fn ptr(name: &str, target: &str) -> Record {
+        let mut rdata = Vec::new();
+        put_name(&mut rdata, target);
+        Record {
+            name: name.to_string(),
+            rtype: TYPE_PTR,
+            unique: false,
+            ttl: OTHER_TTL,
+            rdata,
+        }
+    }
+
+    /// Every record, as announced
+    fn all(&self) -> Vec<Record> {
+        let mut records = vec![Self::ptr(SERVICES, &self.service)];
+        for (instance, srv, txt) in &self.instances {
+            records.push(Self::ptr(&self.service, instance));
+            records.push(srv.clone());
+            records.push(txt.clone());
+        }
+        records
+    }
+
+    /// Answers and additional records for `question`
+    fn answer(&self, question: &Question, answers: &mut Vec<Record>, additional: &mut Vec<Record>) {
+        let wants = |rtype| question.qtype == rtype || question.qtype == TYPE_ANY;
+        // The top bit of a question's class asks for a unicast response
+        let class = question.qclass & !CACHE_FLUSH;
+        if class != CLASS_IN && class != CLASS_ANY {
+            return;
+        }
+        if question.name.eq_ignore_ascii_case(SERVICES) && wants(TYPE_PTR) {
+            answers.push(Self::ptr(SERVICES, &self.service));
+        } else if question.name.eq_ignore_ascii_case(&self.service) && wants(TYPE_PTR) {
+            for (instance, srv, txt) in &self.instances {
+                answers.push(Self::ptr(&self.service, instance));
+                additional.extend([srv.clone(), txt.clone()]);
+            }
+        } else if let Some((_, srv, txt)) = self
+            .instances
+            .iter()
+            .find(|(instance, _, _)| question.name.eq_ignore_ascii_case(instance))
+        {
+            if wants(TYPE_SRV) {
+                answers.push(srv.clone());
+            }
+            if wants(TYPE_TXT) {
+                answers.push(txt.clone());
+            }
+        }
+    }
+
+    /// Response to `query`, or `None` when it asks for nothing advertised
+    /// here. A legacy query (not from port 5353) gets its id and questions
+    /// back, with short TTLs.
+    fn respond(&self, query: &[u8], legacy: bool) -> Option<Vec<u8>> {
+        let (id, questions) = parse_query(query)?;
+        let mut answers = Vec::new();
+        let mut additional = Vec::new();
+        for question in &questions {
+            self.answer(question, &mut answers, &mut additional);
+        }
+        if answers.is_empty() {
+            return None;
+        }
+        additional.retain(|record| !answers.contains(record));
+        if !legacy {
+            return Some(encode_response(0, &[], &answers, &additional, None));
+        }
+        Some(encode_response(
+            id,
+            &questions,
+            &answers,
+            &additional,
+            Some(LEGACY_TTL),
+        ))
+    }
+}
+
+/// Advertises the streams until cancelled
+pub struct Advertiser {
+    socket: UdpSocket,
+    records: Records,
+}
+
+impl Advertiser {
+    /// Joins the mDNS group on port 5353, shared with other responders
+    pub async fn bind(config: &MdnsConfig, streams: &[MdnsStream]) -> std::io::Result<Self> {
+        let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+        socket.set_reuse_address(true)?;
+        socket.set_nonblocking(true)?;
+        socket.bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, MDNS_PORT)).into())?;
+        socket.join_multicast_v4(&MDNS_GROUP, &Ipv4Addr::UNSPECIFIED)?;
+        socket.set_multicast_ttl_v4(255)?;
+        let socket = UdpSocket::from_std(socket.into())?;
+
+        let hostname = config.hostname.clone().unwrap_or_else(system_hostname);
+        Ok(Self {
+            socket,
+            records: Records::new(&config.service, &hostname, streams),
+        })
+    }
+
+    /// Announces the records, answers queries until `token` is cancelled,
+    /// then withdraws them
+    pub async fn run(self, token: CancellationToken) {
+        let instances: Vec<&str> = self
+            .records
+            .instances
+            .iter()
+            .map(|(name, _, _)| name.as_str())
+            .collect();
+        info!(?instances, "Advertising the streams over mDNS");
+        let log = LogLimiter::default();
+        let group = SocketAddr::from((MDNS_GROUP, MDNS_PORT));
+        let announcement = encode_response(0, &[], &self.records.all(), &[], None);
+
+        // Announced twice, a second apart (RFC 6762 Section 8.3)
+        let mut announce = tokio::time::interval(Duration::from_secs(1));
+        let mut announcements = 0;
+        let mut buf = [0u8; 9000];
+        loop {
+            tokio::select! {
+                _ = token.cancelled() => break,
+                _ = announce.tick(), if announcements < 2 => {
+                    announcements += 1;
+                    self.send(&announcement, group, &log).await;
+                }
+                received = self.socket.recv_from(&mut buf) => match received {
+                    Ok((len, from)) => {
+                        let legacy = from.port() != MDNS_PORT;
+                        if let Some(response) = self.records.respond(&buf[..len], legacy) {
+                            debug!(%from, "Answering mDNS query");
+                            self.send(&response, if legacy { from } else { group }, &log).await;
+                        }
+                    }
+                    Err(e) => log_limited!(log, "mdns_recv", debug, error = %e, "Failed to receive mDNS"),
+                },
+            }
+        }
+
+        // TTL 0 tells caches to drop them (Section 10.1)
+        let goodbye = encode_response(0, &[], &self.records.all(), &[], Some(0));
+        self.send(&goodbye, group, &log).await;
+    }
+
+    async fn send(&self, packet: &[u8], to: SocketAddr, log: &LogLimiter) {
+        if let Err(e) = self.socket.send_to(packet, to).await {
+            log_limited!(log, "mdns_send", warn, error = %e, dest = %to, "Failed to send mDNS response");
+        }
+    }
+}
+
+/// The host's name, without a domain
+fn system_hostname() -> String {
+    ["/proc/sys/kernel/hostname", "/etc/hostname"]
+        .iter()
+        .find_map(|path| std::fs::read_to_string(path).ok())
+        .and_then(|name| name.trim().split('.').next().map(str::to_string))
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "mjpeg-rtp".to_string())
+}
+
+/// Appends `name` as uncompressed labels
+fn put_name(buf: &mut Vec<u8>, name: &str) {
+    for label in name.split('.').filter(|label| !label.is_empty()) {
+        let label = &label.as_bytes()[..label.len().min(63)];
+        buf.push(label.len() as u8);
+        buf.extend_from_slice(label);
+    }
+    buf.push(0);
+}
+
+/// Reads the name at `pos`, following compression pointers. Returns it and
+/// where the data after it starts.
+fn read_name(packet: &[u8], mut pos: usize) -> Option<(String, usize)> {
+    let mut labels: Vec<String> = Vec::new();
+    let mut end = None;
+    // Bounds pointer loops
+    for _ in 0..64 {
+        let len = *packet.get(pos)? as usize;
+        match len {
+            0 => return Some((labels.join("."), end.unwrap_or(pos + 1))),
+            0xC0.. => {
+                let target = (len & 0x3F) << 8 | *packet.get(pos + 1)? as usize;
+                end.get_or_insert(pos + 2);
+                pos = target;
+            }
+            1..=63 => {
+                let label = packet.get(pos + 1..pos + 1 + len)?;
+                labels.push(String::from_utf8_lossy(label).into_owned());
+                pos += 1 + len;
+            }
+            _ => return None,
+        }
+    }
+    None
+}
+
+/// Id and questions of a query; `None` for responses and malformed packets
+fn parse_query(packet: &[u8]) -> Option<(u16, Vec<Question>)> {
+    let header = packet.get(..12)?;
+    let id = u16::from_be_bytes([header[0], header[1]]);
+    // QR set: a response, not a query
+    if header[2] & 0x80 != 0 {
+        return None;
+    }
+    let count = u16::from_be_bytes([header[4], header[5]]);
+    let mut pos = 12;
+    let mut questions = Vec::new();
+    for _ in 0..count {
+        let (name, next) = read_name(packet, pos)?;
+        let fields = packet.get(next..next + 4)?;
+        questions.push(Question {
+            name,
+            qtype: u16::from_be_bytes([fields[0], fields[1]]),
+            qclass: u16::from_be_bytes([fields[2], fields[3]]),
+        });
+        pos = next + 4;
+    }
+    Some((id, questions))
+}
+
+/// Encodes an authoritative response. `ttl` overrides (caps, when not 0)
+/// the records' TTLs.
+fn encode_response(
+    id: u16,
+    questions: &[Question],
+    answers: &[Record],
+    additional: &[Record],
+    ttl: Option<u32>,
+) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(512);
+    buf.extend_from_slice(&id.to_be_bytes());
+    buf.extend_from_slice(&0x8400u16.to_be_bytes()); // QR, AA
+    for count in [questions.len(), answers.len(), 0, additional.len()] {
+        buf.extend_from_slice(&(count as u16).to_be_bytes());
+    }
+    for question in questions {
+        put_name(&mut buf, &question.name);
+        buf.extend_from_slice(&question.qtype.to_be_bytes());
+        buf.extend_from_slice(&(question.qclass & !CACHE_FLUSH).to_be_bytes());
+    }
+    for record in answers.iter().chain(additional) {
+        put_name(&mut buf, &record.name);
+        buf.extend_from_slice(&record.rtype.to_be_bytes());
+        // Legacy resolvers don't know the cache-flush bit
+        let flush = record.unique && questions.is_empty();
+        let class = if flush {
+            CLASS_IN | CACHE_FLUSH
+        } else {
+            CLASS_IN
+        };
+        buf.extend_from_slice(&class.to_be_bytes());
+        let ttl = match ttl {
+            Some(0) => 0,
+            Some(cap) => record.ttl.min(cap),
+            None => record.ttl,
+        };
+        buf.extend_from_slice(&ttl.to_be_bytes());
+        buf.extend_from_slice(&(record.rdata.len() as u16).to_be_bytes());
+        buf.extend_from_slice(&record.rdata);
+    }
+    buf
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn query(id: u16, name: &str, qtype: u16) -> Vec<u8> {
+        let mut packet = id.to_be_bytes().to_vec();
+        packet.extend_from_slice(&[0, 0, 0, 1, 0, 0, 0, 0, 0, 0]);
+        put_name(&mut packet, name);
+        packet.extend_from_slice(&qtype.to_be_bytes());
+        packet.extend_from_slice(&CLASS_IN.to_be_bytes());
+        packet
+    }
+
+    fn counts(response: &[u8]) -> [u16; 4] {
+        [4, 6, 8, 10].map(|i| u16::from_be_bytes([response[i], response[i + 1]]))
+    }
+
+    fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+        haystack
+            .windows(needle.len())
+            .any(|window| window == needle)
+    }
+
+    fn records() -> (Records, Uuid) {
+        let uuid = Uuid::new_v4();
+        let stream = MdnsStream {
+            camera: "camera1".to_string(),
+            port: 5700,
+            uuid: Some(uuid),
+            cname: Some(format!("{}@mjpeg-rtp", uuid)),
+        };
+        (Records::new("_mjpeg-rtp._udp", "pi", &[stream]), uuid)
+    }
+
+    #[test]
+    fn test_browse_returns_identity() {
+        let (records, uuid) = records();
+        let response = records
+            .respond(&query(7, "_mjpeg-rtp._udp.local", TYPE_PTR), false)
+            .unwrap();
+        // PTR, then its SRV and TXT as additional records
+        assert_eq!(counts(&response), [0, 1, 0, 2]);
+        assert_eq!(&response[..2], &[0, 0]);
+        assert!(contains(&response, b"\x0api-camera1"));
+        assert!(contains(&response, format!("uuid={}", uuid).as_bytes()));
+        assert!(contains(
+            &response,
+            format!("cname={}@mjpeg-rtp", uuid).as_bytes()
+        ));
+        assert!(contains(&response, &5700u16.to_be_bytes()));
+
+        assert!(records
+            .respond(&query(7, "_http._tcp.local", TYPE_PTR), false)
+            .is_none());
+    }
+
+    #[test]
+    fn test_legacy_query_echoes_id_and_question() {
+        let (records, _) = records();
+        let question = query(0x1234, "pi-camera1._mjpeg-rtp._udp.local", TYPE_TXT);
+        let response = records.respond(&question, true).unwrap();
+        assert_eq!(&response[..2], &[0x12, 0x34]);
+        assert_eq!(counts(&response), [1, 1, 0, 0]);
+        assert_eq!(&response[12..question.len()], &question[12..]);
+    }
+
+    #[test]
+    fn test_compressed_names() {
+        // "b.local" at 12, then "a" pointing at it
+        let mut packet = vec![0u8; 12];
+        put_name(&mut packet, "b.local");
+        packet.extend_from_slice(&[1, b'a', 0xC0, 12]);
+        assert_eq!(read_name(&packet, 12), Some(("b.local".to_string(), 21)));
+        assert_eq!(read_name(&packet, 21), Some(("a.b.local".to_string(), 25)));
+
+        // A pointer to itself
+        assert_eq!(read_name(&[0xC0, 0], 0), None);
+        assert!(parse_query(&[0u8; 11]).is_none());
+    }
+}
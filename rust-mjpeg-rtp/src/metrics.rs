@@ -0,0 +1,353 @@
+//! Streamer metrics with per-camera and per-session labels
+//!
+//! Every camera series carries `camera`, `codec` and `resolution` labels, so
+//! dashboards can slice by camera instead of summing both. Extra destinations
+//! also get a `session_id`, a hash of their address, which tells sessions
+//! apart without receivers' addresses ending up in the metrics store. A
+//! `build_info` series names the version and features.
+//!
+//! `GET /metrics` on the control API renders them as OpenMetrics text, with
+//! the id of the newest frame as exemplar on the frames-sent counter; the
+//! OTLP export (see `telemetry`) uses the same names and labels.
+
+use crate::streamer::{DestinationStats, StreamerStats};
+use std::fmt::Write;
+use std::net::SocketAddr;
+
+/// Payload format of every stream
+pub const CODEC: &str = "jpeg";
+
+/// Content type of [`render`]'s output
+pub const CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+/// A counter: OTLP name, description and the value it reads. OpenMetrics
+/// names have underscores for the dots.
+pub type Counter<T> = (&'static str, &'static str, fn(&T) -> u64);
+
+/// Counters of each camera
+pub const COUNTERS: [Counter<StreamerStats>; 15] = [
+    ("mjpeg_rtp.frames_sent", "Frames successfully sent", |s| {
+        s.frames_sent
+    }),
+    (
+        "mjpeg_rtp.frames_dropped",
+        "Frames dropped before sending",
+        |s| s.frames_dropped,
+    ),
+    (
+        "mjpeg_rtp.frames_dropped.channel",
+        "Frames dropped on a full send channel",
+        |s| s.dropped_in_channel,
+    ),
+    (
+        "mjpeg_rtp.frames_dropped.unreachable",
+        "Frames held back from an unreachable destination",
+        |s| s.dropped_unreachable,
+    ),
+    (
+        "mjpeg_rtp.frames_dropped.shaper",
+        "Frames over the egress bitrate limits",
+        |s| s.dropped_by_shaper,
+    ),
+    (
+        "mjpeg_rtp.shaper_delay_us",
+        "Time frames were held back by the egress bitrate limits",
+        |s| s.shaping.delay_us,
+    ),
+    (
+        "mjpeg_rtp.frames_dropped.pacer",
+        "Frames thinned out by decimate or dedup",
+        |s| s.dropped_by_pacer,
+    ),
+    ("mjpeg_rtp.send_errors", "Frames with send errors", |s| {
+        s.send_errors
+    }),
+    (
+        "mjpeg_rtp.send_errors.unreachable",
+        "Packet sends refused as unreachable",
+        |s| s.send_error_kinds.unreachable,
+    ),
+    (
+        "mjpeg_rtp.send_errors.message_too_long",
+        "Packet sends larger than the path MTU",
+        |s| s.send_error_kinds.message_too_long,
+    ),
+    (
+        "mjpeg_rtp.send_errors.no_buffers",
+        "Packet sends dropped for lack of buffers",
+        |s| s.send_error_kinds.no_buffers,
+    ),
+    (
+        "mjpeg_rtp.send_errors.other",
+        "Other failed packet sends",
+        |s| s.send_error_kinds.other,
+    ),
+    ("mjpeg_rtp.rtp_packets_sent", "RTP packets sent", |s| {
+        s.rtp_packets_sent
+    }),
+    ("mjpeg_rtp.bytes_sent", "JPEG bytes sent", |s| s.bytes_sent),
+    (
+        "mjpeg_rtp.keepalives_sent",
+        "Keepalives sent while no frames went out",
+        |s| s.keepalives_sent,
+    ),
+];
+
+/// Counters of each extra destination, labelled with its session id
+pub const SESSION_COUNTERS: [Counter<DestinationStats>; 4] = [
+    (
+        "mjpeg_rtp.session.frames_sent",
+        "Frames sent to an extra destination",
+        |d| d.frames_sent,
+    ),
+    (
+        "mjpeg_rtp.session.rtp_packets_sent",
+        "RTP packets sent to an extra destination",
+        |d| d.packets_sent,
+    ),
+    (
+        "mjpeg_rtp.session.bytes_sent",
+        "JPEG bytes sent to an extra destination",
+        |d| d.bytes_sent,
+    ),
+    (
+        "mjpeg_rtp.session.send_errors",
+        "Frames with send errors to an extra destination",
+        |d| d.send_errors,
+    ),
+];
+
+/// Name and description of the rolling health gauge
+pub const HEALTH_SCORE: (&str, &str) = (
+    "mjpeg_rtp.health_score",
+    "Rolling stream health, 0 (unusable) to 100",
+);
+
+/// Name and description of the build info series
+pub const BUILD_INFO: (&str, &str) = ("mjpeg_rtp.build", "Version and features of this build");
+
+/// Labels on every series of one camera
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MetricLabels {
+    pub camera: String,
+    pub codec: &'static str,
+    /// `WIDTHxHEIGHT` the camera streams at
+    pub resolution: String,
+}
+
+impl MetricLabels {
+    pub fn new(camera: &str, width: u32, height: u32) -> Self {
+        Self {
+            camera: camera.to_string(),
+            codec: CODEC,
+            resolution: format!("{}x{}", width, height),
+        }
+    }
+
+    /// The labels as (key, value) pairs
+    pub fn pairs(&self) -> [(&'static str, &str); 3] {
+        [
+            ("camera", &self.camera),
+            ("codec", self.codec),
+            ("resolution", &self.resolution),
+        ]
+    }
+}
+
+/// Short id of an extra destination, the same for the same address
+pub fn session_id(addr: SocketAddr) -> String {
+    format!("{:08x}", crc32fast::hash(addr.to_string().as_bytes()))
+}
+
+/// Labels of the build info series
+pub fn build_info() -> [(&'static str, String); 3] {
+    let features: Vec<&str> = [
+        ("jemalloc", cfg!(feature = "jemalloc")),
+        ("otel", cfg!(feature = "otel")),
+        ("wasm", cfg!(feature = "wasm")),
+        ("inference", cfg!(feature = "inference")),
+    ]
+    .into_iter()
+    .filter_map(|(name, enabled)| enabled.then_some(name))
+    .collect();
+    [
+        ("version", env!("CARGO_PKG_VERSION").to_string()),
+        ("arch", std::env::consts::ARCH.to_string()),
+        ("features", features.join(",")),
+    ]
+}
+
+/// OpenMetrics text of the cameras' stats
+pub fn render(cameras: &[(MetricLabels, StreamerStats)]) -> String {
+    let mut out = String::new();
+
+    let name = openmetrics_name(BUILD_INFO.0);
+    family(&mut out, &name, "info", BUILD_INFO.1);
+    let info = build_info();
+    let labels: Vec<(&str, &str)> = info
+        .iter()
+        .map(|(key, value)| (*key, value.as_str()))
+        .collect();
+    sample(&mut out, &format!("{}_info", name), &labels, 1, None);
+
+    for (name, help, value) in COUNTERS {
+        // Lets a dashboard jump from a spike to a frame in the clips
+        let exemplars = name == COUNTERS[0].0;
+        let name = openmetrics_name(name);
+        family(&mut out, &name, "counter", help);
+        for (labels, stats) in cameras {
+            let exemplar = stats.health.last_frame_id.filter(|_| exemplars);
+            sample(
+                &mut out,
+                &format!("{}_total", name),
+                &labels.pairs(),
+                value(stats),
+                exemplar,
+            );
+        }
+    }
+
+    let name = openmetrics_name(HEALTH_SCORE.0);
+    family(&mut out, &name, "gauge", HEALTH_SCORE.1);
+    for (labels, stats) in cameras {
+        sample(
+            &mut out,
+            &name,
+            &labels.pairs(),
+            stats.health.score as u64,
+            None,
+        );
+    }
+
+    for (name, help, value) in SESSION_COUNTERS {
+        let name = openmetrics_name(name);
+        family(&mut out, &name, "counter", help);
+        for (labels, stats) in cameras {
+            for destination in &stats.destinations {
+                let session = session_id(destination.addr);
+                let mut labels = labels.pairs().to_vec();
+                labels.push(("session_id", &session));
+                sample(
+                    &mut out,
+                    &format!("{}_total", name),
+                    &labels,
+                    value(destination),
+                    None,
+                );
+            }
+        }
+    }
+
+    out.push_str("# EOF\n");
+    out
+}
+
+fn openmetrics_name(otlp: &str) -> String {
+    otlp.replace('.', "_")
+}
+
+fn family(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+}
+
+/// One sample line; `exemplar` is a frame id, counted as one frame
+fn sample(
+    out: &mut String,
+    name: &str,
+    labels: &[(&str, &str)],
+    value: u64,
+    exemplar: Option<u64>,
+) {
+    out.push_str(name);
+    if !labels.is_empty() {
+        let labels: Vec<String> = labels
+            .iter()
+            .map(|(key, value)| format!("{}=\"{}\"", key, escape(value)))
+            .collect();
+        let _ = write!(out, "{{{}}}", labels.join(","));
+    }
+    let _ = write!(out, " {}", value);
+    if let Some(frame_id) = exemplar {
+        let _ = write!(out, " # {{frame_id=\"{}\"}} 1", frame_id);
+    }
+    out.push('\n');
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_labels_every_series() {
+        let mut stats = StreamerStats {
+            frames_sent: 42,
+            bytes_sent: 1000,
+            ..Default::default()
+        };
+        stats.health.score = 97;
+        stats.health.last_frame_id = Some(45);
+        stats.destinations.push(DestinationStats {
+            addr: "192.168.1.20:6000".parse().unwrap(),
+            frames_sent: 7,
+            packets_sent: 21,
+            bytes_sent: 700,
+            send_errors: 0,
+            added_secs: 3,
+        });
+        let text = render(&[
+            (MetricLabels::new("camera1", 1280, 720), stats),
+            (
+                MetricLabels::new("cam\"2", 640, 480),
+                StreamerStats::default(),
+            ),
+        ]);
+        let lines: Vec<&str> = text.lines().collect();
+
+        assert_eq!(lines.last(), Some(&"# EOF"));
+        assert!(lines.contains(&"# TYPE mjpeg_rtp_frames_sent counter"));
+        assert!(lines
+            .iter()
+            .any(|l| l.starts_with("mjpeg_rtp_build_info{version=\"") && l.ends_with("} 1")));
+        assert!(lines.contains(
+            &"mjpeg_rtp_frames_sent_total{camera=\"camera1\",codec=\"jpeg\",resolution=\"1280x720\"} 42 \
+              # {frame_id=\"45\"} 1"
+        ));
+        // Only the frame counter has exemplars, and only with a frame seen
+        assert!(lines.contains(
+            &"mjpeg_rtp_bytes_sent_total{camera=\"camera1\",codec=\"jpeg\",resolution=\"1280x720\"} 1000"
+        ));
+        assert!(lines.contains(
+            &"mjpeg_rtp_frames_sent_total{camera=\"cam\\\"2\",codec=\"jpeg\",resolution=\"640x480\"} 0"
+        ));
+        assert!(lines.contains(
+            &"mjpeg_rtp_health_score{camera=\"camera1\",codec=\"jpeg\",resolution=\"1280x720\"} 97"
+        ));
+
+        let session = session_id("192.168.1.20:6000".parse().unwrap());
+        assert_eq!(session.len(), 8);
+        assert!(!text.contains("192.168.1.20"));
+        assert!(lines.contains(
+            &format!(
+                "mjpeg_rtp_session_frames_sent_total{{camera=\"camera1\",codec=\"jpeg\",\
+                 resolution=\"1280x720\",session_id=\"{}\"}} 7",
+                session
+            )
+            .as_str()
+        ));
+
+        // Each family's samples stay together
+        let family_starts = lines.iter().filter(|l| l.starts_with("# TYPE")).count();
+        assert_eq!(
+            family_starts,
+            1 + COUNTERS.len() + 1 + SESSION_COUNTERS.len()
+        );
+    }
+}
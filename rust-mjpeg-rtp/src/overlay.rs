@@ -0,0 +1,151 @@
+//! Boxes drawn into frames before encoding
+//!
+//! An [`Overlay`] is shared between whatever decides what to highlight, such
+//! as an object detector, and the capture pipeline, which outlines the
+//! current boxes on the luma plane of every frame until they are replaced.
+//! Boxes are normalized to the frame size, so they can come from analysis of
+//! a downscaled copy.
+
+use std::sync::{Arc, Mutex};
+
+/// Outline width in pixels
+const LINE: usize = 2;
+
+/// A rectangle in fractions of the frame's width and height
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OverlayBox {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+/// The boxes currently drawn; clones share them
+#[derive(Debug, Clone, Default)]
+pub struct Overlay {
+    boxes: Arc<Mutex<Vec<OverlayBox>>>,
+}
+
+impl Overlay {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replaces the boxes drawn from the next frame on
+    pub fn set(&self, boxes: Vec<OverlayBox>) {
+        *self.boxes.lock().unwrap() = boxes;
+    }
+
+    pub fn boxes(&self) -> Vec<OverlayBox> {
+        self.boxes.lock().unwrap().clone()
+    }
+
+    /// Outlines the boxes in white on a `width`×`height` luma plane whose
+    /// rows are `stride` bytes apart. Edges outside the frame are clipped.
+    pub fn draw(&self, luma: &mut [u8], stride: usize, width: usize, height: usize) {
+        if width < LINE || height < LINE || luma.len() < stride * height {
+            return;
+        }
+        let mut fill = |x0: usize, y0: usize, x1: usize, y1: usize| {
+            for row in y0..y1 {
+                luma[row * stride + x0..row * stride + x1].fill(255);
+            }
+        };
+        let scale = |v: f32, size: usize| ((v.clamp(0.0, 1.0) * size as f32) as usize).min(size);
+        for b in self.boxes.lock().unwrap().iter() {
+            let (x0, x1) = (scale(b.x, width), scale(b.x + b.width, width));
+            let (y0, y1) = (scale(b.y, height), scale(b.y + b.height, height));
+            if x1 <= x0 || y1 <= y0 {
+                continue;
+            }
+            // Keep the outline inside the frame and at least LINE thick
+            let (x0, x1) = (x0.min(width - LINE), x1.max(x0 + LINE).min(width));
+            let (y0, y1) = (y0.min(height - LINE), y1.max(y0 + LINE).min(height));
+            fill(x0, y0, x1, y0 + LINE);
+            fill(x0, y1 - LINE, x1, y1);
+            fill(x0, y0, x0 + LINE, y1);
+            fill(x1 - LINE, y0, x1, y1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rows(luma: &[u8], stride: usize, width: usize) -> Vec<String> {
+        luma.chunks(stride)
+            .map(|row| {
+                row[..width]
+                    .iter()
+                    .map(|&v| if v == 255 { '#' } else { '.' })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_draw() {
+        let overlay = Overlay::new();
+        let (width, height, stride) = (10, 10, 12);
+        let mut luma = vec![0u8; stride * height];
+        overlay.draw(&mut luma, stride, width, height);
+        assert!(luma.iter().all(|&v| v == 0));
+
+        overlay.set(vec![OverlayBox {
+            x: 0.2,
+            y: 0.25,
+            width: 0.6,
+            height: 0.5,
+        }]);
+        overlay.clone().draw(&mut luma, stride, width, height);
+        assert_eq!(
+            rows(&luma, stride, width),
+            vec![
+                "..........",
+                "..........",
+                "..######..",
+                "..######..",
+                "..##..##..",
+                "..######..",
+                "..######..",
+                "..........",
+                "..........",
+                "..........",
+            ]
+        );
+        // Row padding is left alone
+        assert!(luma.chunks(stride).all(|row| row[width..] == [0, 0]));
+    }
+
+    #[test]
+    fn test_draw_clips() {
+        let overlay = Overlay::new();
+        overlay.set(vec![
+            OverlayBox {
+                x: 0.25,
+                y: -0.25,
+                width: 1.0,
+                height: 1.0,
+            },
+            OverlayBox {
+                x: 0.5,
+                y: 0.5,
+                width: 0.0,
+                height: 0.5,
+            },
+        ]);
+        let (width, height) = (8, 8);
+        let mut luma = vec![0u8; width * height];
+        overlay.draw(&mut luma, width, width, height);
+        assert_eq!(
+            rows(&luma, width, width),
+            vec![
+                "..######", "..######", "..##..##", "..##..##", "..######", "..######", "........",
+                "........",
+            ]
+        );
+        // Too small a buffer is left alone
+        overlay.draw(&mut luma[..8], width, width, height);
+    }
+}
@@ -0,0 +1,105 @@
+//! Frame pacing for synthetic and recorded sources
+//!
+//! A camera delivers frames on its sensor clock; replays and generated test
+//! frames have to be paced by us. Sleeping one `1s / fps` after each frame
+//! drifts by however long the frame took to produce and send, and `1s / fps`
+//! itself is rounded (33.333333 ms at 30 fps). [`FramePacer`] instead puts
+//! frame `n` at `n * 90000 / fps` ticks of the RTP clock after the start and
+//! sleeps until that instant, so timing errors never accumulate and the
+//! schedule matches the RTP timestamps a receiver sees.
+
+use crate::rtp::RTP_CLOCK_RATE;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// Schedules frames at a fixed rate on the 90 kHz RTP clock
+///
+/// Like a camera, a pacer that falls more than a frame behind (the consumer
+/// stalled) skips the slots it missed instead of bursting to catch up; the
+/// gap shows in the slot numbers [`FramePacer::tick`] returns.
+#[derive(Debug, Clone)]
+pub struct FramePacer {
+    fps: u32,
+    start: Instant,
+    next: u64,
+}
+
+impl FramePacer {
+    /// A pacer whose first frame is due now
+    pub fn new(fps: u32) -> Self {
+        Self {
+            fps: fps.max(1),
+            start: Instant::now(),
+            next: 0,
+        }
+    }
+
+    pub fn fps(&self) -> u32 {
+        self.fps
+    }
+
+    /// 90 kHz ticks from the start to frame `slot`
+    pub fn timestamp(&self, slot: u64) -> u64 {
+        slot * RTP_CLOCK_RATE as u64 / self.fps as u64
+    }
+
+    /// Time from the start to frame `slot`
+    pub fn offset(&self, slot: u64) -> Duration {
+        let nanos = self.timestamp(slot) as u128 * 1_000_000_000 / RTP_CLOCK_RATE as u128;
+        Duration::from_nanos(nanos as u64)
+    }
+
+    /// Waits until the next frame is due and returns its slot
+    pub async fn tick(&mut self) -> u64 {
+        let now = Instant::now();
+        let mut slot = self.next;
+        // More than a frame late: resume at the slot due now
+        if now > self.start + self.offset(slot + 1) {
+            let elapsed = now.duration_since(self.start).as_nanos();
+            slot = (elapsed * self.fps as u128 / 1_000_000_000) as u64;
+        }
+        tokio::time::sleep_until(self.start + self.offset(slot)).await;
+        self.next = slot + 1;
+        slot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_schedule_does_not_round() {
+        let pacer = FramePacer::new(30);
+        assert_eq!(pacer.timestamp(1), 3000);
+        assert_eq!(pacer.offset(3), Duration::from_millis(100));
+        assert_eq!(pacer.offset(30 * 3600), Duration::from_secs(3600));
+
+        // 90000 / 7 isn't whole; the error stays under a tick
+        let pacer = FramePacer::new(7);
+        assert_eq!(pacer.timestamp(7), 90000);
+        assert_eq!(pacer.timestamp(1), 12857);
+        assert_eq!(pacer.offset(7 * 60), Duration::from_secs(60));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_tick() {
+        let mut pacer = FramePacer::new(30);
+        let start = Instant::now();
+        for expected in 0..90 {
+            assert_eq!(pacer.tick().await, expected);
+            // Work between frames doesn't delay the next one
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        assert_eq!(pacer.tick().await, 90);
+        assert_eq!(start.elapsed(), Duration::from_secs(3));
+
+        // A stall skips the slots it covered
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        assert_eq!(pacer.tick().await, 105);
+        assert_eq!(pacer.tick().await, 106);
+        // The timer rounds up to whole milliseconds
+        let late = start.elapsed() - pacer.offset(106);
+        assert!(late < Duration::from_millis(1));
+    }
+}
@@ -0,0 +1,367 @@
+//! WASM plugins for frame analytics
+//!
+//! A `wasm` processor gives a user-provided WebAssembly module a greyscale
+//! copy of a frame, scaled to `width`×`height`, at most every `interval_ms`,
+//! and publishes what the module reports on the [`EventBus`]: person
+//! detection, plate reading and similar analytics without rebuilding the
+//! streamer. Frames pass through unchanged. Analysis runs on its own thread,
+//! and a frame that arrives while the last one is still being analysed is
+//! not analysed, so a slow plugin never holds up streaming.
+//!
+//! The module exports:
+//!
+//! - `memory`
+//! - `alloc(len: i32) -> i32`: address of a `len`-byte buffer in `memory`,
+//!   called again only if the frame size changes
+//! - `analyze(ptr: i32, width: i32, height: i32)`: one frame of 8-bit luma,
+//!   row by row, at `ptr`
+//!
+//! and may import from `env`:
+//!
+//! - `emit(ptr: i32, len: i32)`: report an event; the bytes are a UTF-8 JSON
+//!   value, published as the event's `payload`
+//! - `log(ptr: i32, len: i32)`: write a UTF-8 message to the log
+//!
+//! Each `analyze` call gets `fuel` units of wasmtime fuel (roughly
+//! instructions); a call that runs out or traps is abandoned with a warning
+//! and the next frame is tried as usual. No WASI is linked, so plugins have
+//! no access to files, the network or the clock.
+
+use crate::config::WasmPluginConfig;
+use crate::events::{AnalyticsEvent, EventBus};
+use crate::frame::Frame;
+use crate::imaging::Image;
+use crate::processor::{FrameProcessor, Processed, ProcessorError};
+use std::path::Path;
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
+use wasmtime::{Caller, Engine, Extern, Linker, Memory, Module, Store, TypedFunc};
+
+/// Events a single `analyze` call may emit; the rest are dropped
+const MAX_EVENTS_PER_FRAME: usize = 64;
+
+/// Runs a WASM module on frames; see the [module docs](self)
+pub struct WasmPlugin {
+    interval: Duration,
+    last_sent: Option<Instant>,
+    frames: SyncSender<Frame>,
+}
+
+impl WasmPlugin {
+    /// Compiles and instantiates the module, then starts its analysis thread
+    pub fn load(
+        config: &WasmPluginConfig,
+        camera: &str,
+        events: EventBus,
+    ) -> Result<Self, ProcessorError> {
+        let source = Path::new(&config.path)
+            .file_stem()
+            .map_or_else(|| config.path.clone(), |s| s.to_string_lossy().into_owned());
+        let error = |e: wasmtime::Error| ProcessorError::Plugin {
+            path: config.path.clone(),
+            reason: format!("{:#}", e),
+        };
+        let host = Host {
+            camera: camera.to_string(),
+            source: source.clone(),
+            frame_id: 0,
+            emitted: 0,
+            events,
+        };
+        let mut runner = Runner::new(config, host).map_err(error)?;
+
+        // One frame in flight: a busy plugin skips frames rather than queueing them
+        let (frames, rx) = mpsc::sync_channel::<Frame>(0);
+        thread::Builder::new()
+            .name(format!("plugin-{}", source))
+            .spawn(move || {
+                while let Ok(frame) = rx.recv() {
+                    if let Err(e) = runner.analyze(&frame) {
+                        warn!(
+                            plugin = %runner.source(),
+                            frame_id = frame.id,
+                            error = %format!("{:#}", e),
+                            "Plugin failed on a frame"
+                        );
+                    }
+                }
+            })
+            .map_err(|e| error(e.into()))?;
+
+        info!(
+            camera,
+            plugin = %source,
+            width = config.width,
+            height = config.height,
+            "Loaded WASM plugin"
+        );
+        Ok(Self {
+            interval: Duration::from_millis(config.interval_ms),
+            last_sent: None,
+            frames,
+        })
+    }
+}
+
+impl FrameProcessor for WasmPlugin {
+    fn process(&mut self, frame: Frame) -> Processed {
+        let due = self
+            .last_sent
+            .is_none_or(|at| at.elapsed() >= self.interval);
+        if due {
+            match self.frames.try_send(frame.clone()) {
+                Ok(()) => self.last_sent = Some(Instant::now()),
+                // Still busy with the last one, or the thread is gone
+                Err(TrySendError::Full(_) | TrySendError::Disconnected(_)) => {}
+            }
+        }
+        Processed::Frame(frame)
+    }
+
+    fn name(&self) -> &'static str {
+        "wasm"
+    }
+}
+
+/// State the host functions see
+struct Host {
+    camera: String,
+    source: String,
+    frame_id: u64,
+    emitted: usize,
+    events: EventBus,
+}
+
+/// An instantiated module and the buffer frames are written to
+struct Runner {
+    store: Store<Host>,
+    memory: Memory,
+    alloc: TypedFunc<i32, i32>,
+    analyze: TypedFunc<(i32, i32, i32), ()>,
+    buffer: Option<(i32, usize)>,
+    width: usize,
+    height: usize,
+    fuel: u64,
+}
+
+impl Runner {
+    fn new(config: &WasmPluginConfig, host: Host) -> wasmtime::Result<Self> {
+        let mut engine_config = wasmtime::Config::new();
+        engine_config.consume_fuel(true);
+        let engine = Engine::new(&engine_config)?;
+        let module = Module::from_file(&engine, &config.path)?;
+
+        let mut linker = Linker::new(&engine);
+        linker.func_wrap("env", "emit", emit)?;
+        linker.func_wrap("env", "log", log)?;
+        let mut store = Store::new(&engine, host);
+        let instance = linker.instantiate(&mut store, &module)?;
+
+        Ok(Self {
+            memory: instance
+                .get_memory(&mut store, "memory")
+                .ok_or_else(|| wasmtime::Error::msg("no exported memory"))?,
+            alloc: instance.get_typed_func(&mut store, "alloc")?,
+            analyze: instance.get_typed_func(&mut store, "analyze")?,
+            store,
+            buffer: None,
+            width: config.width as usize,
+            height: config.height as usize,
+            fuel: config.fuel,
+        })
+    }
+
+    fn source(&self) -> &str {
+        &self.store.data().source
+    }
+
+    fn analyze(&mut self, frame: &Frame) -> wasmtime::Result<()> {
+        // Smaller frames are passed as they are
+        let image = Image::decode(&frame.data, true)?;
+        let (width, height) = (self.width.min(image.width), self.height.min(image.height));
+        let luma = image.resize(width, height).pixels;
+
+        self.store.set_fuel(self.fuel)?;
+        let ptr = match self.buffer {
+            Some((ptr, len)) if len == luma.len() => ptr,
+            _ => {
+                let ptr = self.alloc.call(&mut self.store, luma.len() as i32)?;
+                self.buffer = Some((ptr, luma.len()));
+                ptr
+            }
+        };
+        self.memory
+            .write(&mut self.store, ptr as u32 as usize, &luma)?;
+
+        let host = self.store.data_mut();
+        host.frame_id = frame.id;
+        host.emitted = 0;
+        self.analyze
+            .call(&mut self.store, (ptr, width as i32, height as i32))
+    }
+}
+
+fn emit(mut caller: Caller<'_, Host>, ptr: i32, len: i32) -> wasmtime::Result<()> {
+    let bytes = guest_bytes(&mut caller, ptr, len)?;
+    let host = caller.data_mut();
+    host.emitted += 1;
+    if host.emitted > MAX_EVENTS_PER_FRAME {
+        return Ok(());
+    }
+    match serde_json::from_slice(&bytes) {
+        Ok(payload) => {
+            let event = AnalyticsEvent::new(&host.camera, &host.source, host.frame_id, payload);
+            debug!(
+                camera = %host.camera,
+                plugin = %host.source,
+                payload = %event.payload,
+                "Plugin event"
+            );
+            host.events.publish(event);
+        }
+        Err(e) => warn!(plugin = %host.source, error = %e, "Plugin emitted invalid JSON"),
+    }
+    Ok(())
+}
+
+fn log(mut caller: Caller<'_, Host>, ptr: i32, len: i32) -> wasmtime::Result<()> {
+    let bytes = guest_bytes(&mut caller, ptr, len)?;
+    info!(plugin = %caller.data().source, "{}", String::from_utf8_lossy(&bytes));
+    Ok(())
+}
+
+/// Copies `len` bytes at `ptr` out of the caller's memory
+fn guest_bytes(caller: &mut Caller<'_, Host>, ptr: i32, len: i32) -> wasmtime::Result<Vec<u8>> {
+    let memory = caller
+        .get_export("memory")
+        .and_then(Extern::into_memory)
+        .ok_or_else(|| wasmtime::Error::msg("no exported memory"))?;
+    let start = ptr as u32 as usize;
+    let end = start + len as u32 as usize;
+    memory
+        .data(&caller)
+        .get(start..end)
+        .map(<[u8]>::to_vec)
+        .ok_or_else(|| wasmtime::Error::msg("event out of bounds"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::imaging;
+    use std::io::Write;
+
+    /// Emits `{"bright":true}` when the first pixel is over 128
+    const BRIGHT: &str = r#"
+        (module
+          (import "env" "emit" (func $emit (param i32 i32)))
+          (memory (export "memory") 2)
+          (data (i32.const 16) "{\22bright\22:true}")
+          (func (export "alloc") (param i32) (result i32) (i32.const 1024))
+          (func (export "analyze") (param $ptr i32) (param $w i32) (param $h i32)
+            (if (i32.gt_u (i32.load8_u (local.get $ptr)) (i32.const 128))
+              (then (call $emit (i32.const 16) (i32.const 15))))))
+    "#;
+
+    /// Spins forever on every frame
+    const SPIN: &str = r#"
+        (module
+          (memory (export "memory") 2)
+          (func (export "alloc") (param i32) (result i32) (i32.const 1024))
+          (func (export "analyze") (param i32 i32 i32)
+            (loop $spin (br $spin))))
+    "#;
+
+    fn plugin_config(wat: &str) -> (tempfile::NamedTempFile, WasmPluginConfig) {
+        let mut file = tempfile::Builder::new().suffix(".wat").tempfile().unwrap();
+        file.write_all(wat.as_bytes()).unwrap();
+        let config = WasmPluginConfig {
+            path: file.path().to_string_lossy().into_owned(),
+            width: 64,
+            height: 24,
+            interval_ms: 0,
+            fuel: 1_000_000,
+        };
+        (file, config)
+    }
+
+    fn jpeg(value: u32) -> Frame {
+        Frame::new(5, imaging::test_jpeg(value))
+    }
+
+    fn runner(config: &WasmPluginConfig, events: &EventBus) -> Runner {
+        let host = Host {
+            camera: "camera1".to_string(),
+            source: "bright".to_string(),
+            frame_id: 0,
+            emitted: 0,
+            events: events.clone(),
+        };
+        Runner::new(config, host).unwrap()
+    }
+
+    #[test]
+    fn test_runner_emits() {
+        let (_file, config) = plugin_config(BRIGHT);
+        let events = EventBus::new();
+        let mut runner = runner(&config, &events);
+
+        runner.analyze(&jpeg(0)).unwrap();
+        assert!(events.recent("camera1").is_empty());
+        runner.analyze(&jpeg(u32::MAX)).unwrap();
+        let recent = events.recent("camera1");
+        assert_eq!(recent.len(), 1);
+        assert_eq!(recent[0].source, "bright");
+        assert_eq!(recent[0].frame_id, 5);
+        assert_eq!(recent[0].payload, serde_json::json!({"bright": true}));
+    }
+
+    #[test]
+    fn test_runaway_plugin_runs_out_of_fuel() {
+        let (_file, config) = plugin_config(SPIN);
+        let mut runner = runner(&config, &EventBus::new());
+        assert!(runner.analyze(&jpeg(0)).is_err());
+        // Refuelled for the next frame
+        assert!(runner.analyze(&jpeg(0)).is_err());
+    }
+
+    #[test]
+    fn test_load_errors() {
+        let (_file, config) = plugin_config("(module)");
+        let result = WasmPlugin::load(&config, "camera1", EventBus::new());
+        assert!(matches!(result, Err(ProcessorError::Plugin { .. })));
+
+        let missing = WasmPluginConfig {
+            path: "/nonexistent/plugin.wasm".to_string(),
+            ..config
+        };
+        assert!(WasmPlugin::load(&missing, "camera1", EventBus::new()).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_plugin_publishes() {
+        let (_file, config) = plugin_config(BRIGHT);
+        let events = EventBus::new();
+        let mut subscriber = events.subscribe();
+        let mut plugin = WasmPlugin::load(&config, "camera1", events).unwrap();
+
+        // Frames offered before the thread is waiting aren't analysed
+        let frame = jpeg(u32::MAX);
+        let event = tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                let passed = plugin.process(frame.clone());
+                assert!(matches!(passed, Processed::Frame(f) if f.id == frame.id));
+                tokio::select! {
+                    event = subscriber.recv() => break event.unwrap(),
+                    _ = tokio::time::sleep(Duration::from_millis(10)) => {}
+                }
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(event.camera, "camera1");
+        assert_eq!(event.payload["bright"], true);
+    }
+}
@@ -0,0 +1,338 @@
+//! Pluggable per-frame processing between capture and streamer
+//!
+//! A [`FrameProcessor`] sees each frame and passes it on, drops it or turns
+//! it into several. A camera's `processors` list is assembled into a
+//! [`ProcessorChain`] at startup and run in order on every frame after it is
+//! recorded and before it is sent, so per-frame features are added here
+//! rather than to [`Capture`](crate::Capture) or
+//! [`Streamer`](crate::Streamer). [`Dedup`] and [`Decimate`] are provided;
+//! with the `wasm` feature [`WasmPlugin`](crate::plugin::WasmPlugin) runs
+//! user-provided analytics, and with `inference`
+//! [`Detector`](crate::inference::Detector) runs object detection. Others
+//! only need to implement the trait.
+//!
+//! Frames are complete JPEGs at this point. Anything that needs pixels
+//! (masks, watermarks) either decodes them itself or draws in the capture
+//! pipeline before encoding, as the [`timecode`](crate::timecode) and the
+//! [`Overlay`] do. Frames keep their capture id, so a dropped frame leaves a gap: the
+//! streamer gives it its timestamp slot, keeping timing intact, and health
+//! stats count it as missing.
+
+use crate::config::ProcessorConfig;
+use crate::error::ErrorCode;
+use crate::events::EventBus;
+use crate::frame::Frame;
+#[cfg(feature = "inference")]
+use crate::inference::Detector;
+use crate::overlay::Overlay;
+#[cfg(feature = "wasm")]
+use crate::plugin::WasmPlugin;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum ProcessorError {
+    #[error("cannot load plugin {path}: {reason}")]
+    Plugin { path: String, reason: String },
+
+    #[error("cannot load model {path}: {reason}")]
+    Model { path: String, reason: String },
+
+    #[error("{0} processors need a build with --features {0}")]
+    Unsupported(&'static str),
+}
+
+impl ProcessorError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            ProcessorError::Plugin { .. } | ProcessorError::Model { .. } => {
+                ErrorCode::InvalidConfig
+            }
+            ProcessorError::Unsupported(_) => ErrorCode::Unsupported,
+        }
+    }
+}
+
+/// What a camera's processors are built with
+#[derive(Clone, Default)]
+pub struct ProcessorContext {
+    pub camera: String,
+    /// Where analytics processors publish
+    pub events: EventBus,
+    /// Drawn into the camera's frames, when any processor asks for it
+    pub overlay: Option<Overlay>,
+}
+
+/// What a processor made of one frame
+#[derive(Debug)]
+pub enum Processed {
+    Frame(Frame),
+    Drop,
+    /// Sent in order, all before the next captured frame
+    Multiple(Vec<Frame>),
+}
+
+/// One stage of a [`ProcessorChain`]
+pub trait FrameProcessor: Send {
+    fn process(&mut self, frame: Frame) -> Processed;
+
+    /// Short name for logs
+    fn name(&self) -> &'static str;
+}
+
+/// Drops frames identical to the last one passed on
+///
+/// A camera watching a static scene through a hardware encoder can produce
+/// byte-identical frames; sending them only costs bandwidth. Every
+/// `keepalive`th repeat in a row is passed anyway so receivers don't time
+/// out (0 drops all repeats).
+#[derive(Debug, Clone)]
+pub struct Dedup {
+    keepalive: u32,
+    last: Option<(usize, u32)>,
+    repeats: u32,
+}
+
+impl Dedup {
+    pub fn new(keepalive: u32) -> Self {
+        Self {
+            keepalive,
+            last: None,
+            repeats: 0,
+        }
+    }
+}
+
+impl FrameProcessor for Dedup {
+    fn process(&mut self, frame: Frame) -> Processed {
+        let key = (frame.data.len(), crc32fast::hash(&frame.data));
+        if self.last != Some(key) {
+            self.last = Some(key);
+            self.repeats = 0;
+            return Processed::Frame(frame);
+        }
+        self.repeats += 1;
+        if self.keepalive > 0 && self.repeats.is_multiple_of(self.keepalive) {
+            Processed::Frame(frame)
+        } else {
+            Processed::Drop
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        "dedup"
+    }
+}
+
+/// Passes one frame in `keep_every`, starting with the first
+#[derive(Debug, Clone)]
+pub struct Decimate {
+    keep_every: u32,
+    seen: u32,
+}
+
+impl Decimate {
+    pub fn new(keep_every: u32) -> Self {
+        Self {
+            keep_every: keep_every.max(1),
+            seen: 0,
+        }
+    }
+}
+
+impl FrameProcessor for Decimate {
+    fn process(&mut self, frame: Frame) -> Processed {
+        let keep = self.seen == 0;
+        self.seen = (self.seen + 1) % self.keep_every;
+        if keep {
+            Processed::Frame(frame)
+        } else {
+            Processed::Drop
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        "decimate"
+    }
+}
+
+/// Processors applied in order to every frame
+#[derive(Default)]
+pub struct ProcessorChain {
+    processors: Vec<Box<dyn FrameProcessor>>,
+}
+
+impl ProcessorChain {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Builds a camera's configured processors, in order
+    pub fn from_config(
+        configs: &[ProcessorConfig],
+        context: &ProcessorContext,
+    ) -> Result<Self, ProcessorError> {
+        let mut chain = Self::new();
+        for config in configs {
+            let processor: Box<dyn FrameProcessor> = match *config {
+                ProcessorConfig::Dedup { keepalive } => Box::new(Dedup::new(keepalive)),
+                ProcessorConfig::Decimate { keep_every } => Box::new(Decimate::new(keep_every)),
+                #[cfg(feature = "wasm")]
+                ProcessorConfig::Wasm(ref plugin) => Box::new(WasmPlugin::load(
+                    plugin,
+                    &context.camera,
+                    context.events.clone(),
+                )?),
+                #[cfg(not(feature = "wasm"))]
+                ProcessorConfig::Wasm(_) => return Err(ProcessorError::Unsupported("wasm")),
+                #[cfg(feature = "inference")]
+                ProcessorConfig::Detect(ref detector) => {
+                    Box::new(Detector::load(detector, context)?)
+                }
+                #[cfg(not(feature = "inference"))]
+                ProcessorConfig::Detect(_) => {
+                    let _ = context;
+                    return Err(ProcessorError::Unsupported("inference"));
+                }
+            };
+            chain.push(processor);
+        }
+        Ok(chain)
+    }
+
+    /// Appends a processor after the existing ones
+    pub fn push(&mut self, processor: Box<dyn FrameProcessor>) {
+        self.processors.push(processor);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.processors.is_empty()
+    }
+
+    /// Processor names in order, for logs
+    pub fn names(&self) -> Vec<&'static str> {
+        self.processors.iter().map(|p| p.name()).collect()
+    }
+
+    /// Runs `frame` through every processor; each frame one stage produces
+    /// goes through the rest. Returns the frames to send, in order.
+    pub fn process(&mut self, frame: Frame) -> Vec<Frame> {
+        let mut frames = vec![frame];
+        for processor in &mut self.processors {
+            let mut next = Vec::with_capacity(frames.len());
+            for frame in frames {
+                match processor.process(frame) {
+                    Processed::Frame(frame) => next.push(frame),
+                    Processed::Drop => {}
+                    Processed::Multiple(more) => next.extend(more),
+                }
+            }
+            if next.is_empty() {
+                return next;
+            }
+            frames = next;
+        }
+        frames
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+
+    fn frame(id: u64, data: &'static [u8]) -> Frame {
+        Frame::new(id, Bytes::from_static(data))
+    }
+
+    fn ids(frames: &[Frame]) -> Vec<u64> {
+        frames.iter().map(|f| f.id).collect()
+    }
+
+    /// Sends every frame twice
+    struct Double;
+
+    impl FrameProcessor for Double {
+        fn process(&mut self, frame: Frame) -> Processed {
+            Processed::Multiple(vec![frame.clone(), frame])
+        }
+
+        fn name(&self) -> &'static str {
+            "double"
+        }
+    }
+
+    #[test]
+    fn test_dedup() {
+        let mut dedup = Dedup::new(3);
+        let data: [&'static [u8]; 7] = [b"a", b"a", b"a", b"a", b"b", b"b", b"a"];
+        let passed: Vec<u64> = data
+            .iter()
+            .enumerate()
+            .filter_map(|(id, data)| match dedup.process(frame(id as u64, data)) {
+                Processed::Frame(frame) => Some(frame.id),
+                _ => None,
+            })
+            .collect();
+        // The third repeat of "a" is passed as a keepalive
+        assert_eq!(passed, vec![0, 3, 4, 6]);
+
+        let mut strict = Dedup::new(0);
+        assert!(matches!(
+            strict.process(frame(0, b"a")),
+            Processed::Frame(_)
+        ));
+        for id in 1..10 {
+            assert!(matches!(strict.process(frame(id, b"a")), Processed::Drop));
+        }
+    }
+
+    #[test]
+    fn test_decimate() {
+        let mut decimate = Decimate::new(3);
+        let kept: Vec<u64> = (0..7)
+            .filter(|&id| matches!(decimate.process(frame(id, b"x")), Processed::Frame(_)))
+            .collect();
+        assert_eq!(kept, vec![0, 3, 6]);
+
+        let mut all = Decimate::new(1);
+        assert!((0..5).all(|id| matches!(all.process(frame(id, b"x")), Processed::Frame(_))));
+    }
+
+    #[test]
+    fn test_chain_order() {
+        let mut chain = ProcessorChain::new();
+        assert!(chain.is_empty());
+        assert_eq!(ids(&chain.process(frame(7, b"x"))), vec![7]);
+
+        // Doubled frames are identical, so dedup drops the copies
+        chain.push(Box::new(Double));
+        chain.push(Box::new(Dedup::new(0)));
+        assert_eq!(chain.names(), vec!["double", "dedup"]);
+        assert_eq!(ids(&chain.process(frame(1, b"a"))), vec![1]);
+        assert_eq!(ids(&chain.process(frame(2, b"b"))), vec![2]);
+        assert!(chain.process(frame(3, b"b")).is_empty());
+
+        let mut reversed = ProcessorChain::new();
+        reversed.push(Box::new(Dedup::new(0)));
+        reversed.push(Box::new(Double));
+        assert_eq!(ids(&reversed.process(frame(1, b"a"))), vec![1, 1]);
+        assert!(reversed.process(frame(2, b"a")).is_empty());
+    }
+
+    #[test]
+    fn test_from_config() {
+        let context = ProcessorContext {
+            camera: "camera1".to_string(),
+            ..Default::default()
+        };
+        let chain = ProcessorChain::from_config(
+            &[
+                ProcessorConfig::Decimate { keep_every: 2 },
+                ProcessorConfig::Dedup { keepalive: 30 },
+            ],
+            &context,
+        )
+        .unwrap();
+        assert_eq!(chain.names(), vec!["decimate", "dedup"]);
+    }
+}
@@ -0,0 +1,547 @@
+//! Receiver-driven streaming ("pull" mode)
+//!
+//! With `pull` enabled on a camera, its `dest_host` is not used. The camera
+//! captures as usual, but only sends once a receiver asks for the stream
+//! with a small UDP request to `listen`:
+//!
+//! ```text
+//! 0      4        5     6     8        16
+//! "MJPL" version  kind  port  cookie   [IPv4 or IPv6 address]
+//! ```
+//!
+//! A `pull` request names the RTP destination: `port` 0 means the port it
+//! was sent from, and without an address the address it was sent from. A
+//! receiver that sends it from its RTP socket thus gets the stream through
+//! its NAT without knowing its public address. Any other address must be in
+//! `allow`, so a forged source can't point the stream at a third party.
+//!
+//! The streamer answers with an `ack` naming the destination and carrying a
+//! cookie, which is only sent to the request's source. Nothing is streamed
+//! until a pull echoes that cookie, proving the source receives what is sent
+//! to it. The answer to such a pull is another `ack`, or `full` when
+//! `max_receivers` are already pulling.
+//!
+//! Requests must be repeated: a receiver not heard from for
+//! `idle_timeout_ms` is dropped, and a `stop` with its cookie drops it at
+//! once. The first receiver gets the stream as the streamer's destination,
+//! the others as extra destinations; with none left, the streamer stops
+//! until the next request.
+//!
+//! Requests are not authenticated beyond that; keep the port on a trusted
+//! network.
+
+use crate::config::PullConfig;
+use crate::log_limited;
+use crate::ratelimit::LogLimiter;
+use crate::task::CancellationToken;
+use std::hash::{BuildHasher, RandomState};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::{Duration, Instant};
+use tokio::net::UdpSocket;
+use tokio::sync::watch;
+use tracing::{debug, info};
+
+const MAGIC: &[u8; 4] = b"MJPL";
+const VERSION: u8 = 2;
+/// Magic, version, kind, port, cookie
+const HEADER_LEN: usize = 16;
+
+/// What a pull message asks for or answers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PullKind {
+    /// Start or keep streaming to the destination
+    Pull = 1,
+    /// Stop streaming to the destination
+    Stop = 2,
+    /// The destination is taken; pulls echoing the cookie are streamed to
+    Ack = 3,
+    /// No more receivers are taken
+    Full = 4,
+}
+
+impl PullKind {
+    fn from_u8(kind: u8) -> Option<Self> {
+        match kind {
+            1 => Some(Self::Pull),
+            2 => Some(Self::Stop),
+            3 => Some(Self::Ack),
+            4 => Some(Self::Full),
+            _ => None,
+        }
+    }
+}
+
+/// One pull request or answer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PullMessage {
+    pub kind: PullKind,
+    /// RTP port (0 = the port the message came from)
+    pub port: u16,
+    /// RTP address (unset = the address the message came from)
+    pub ip: Option<IpAddr>,
+    /// The cookie of the last `ack` (0 before there was one)
+    pub cookie: u64,
+}
+
+impl PullMessage {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(HEADER_LEN + 16);
+        buf.extend_from_slice(MAGIC);
+        buf.push(VERSION);
+        buf.push(self.kind as u8);
+        buf.extend_from_slice(&self.port.to_be_bytes());
+        buf.extend_from_slice(&self.cookie.to_be_bytes());
+        match self.ip {
+            Some(IpAddr::V4(ip)) => buf.extend_from_slice(&ip.octets()),
+            Some(IpAddr::V6(ip)) => buf.extend_from_slice(&ip.octets()),
+            None => {}
+        }
+        buf
+    }
+
+    /// Parses a message; `None` for anything else sent to the port
+    pub fn decode(buf: &[u8]) -> Option<Self> {
+        if buf.len() < HEADER_LEN || &buf[..4] != MAGIC || buf[4] != VERSION {
+            return None;
+        }
+        let ip = match &buf[HEADER_LEN..] {
+            [] => None,
+            addr if addr.len() == 4 => {
+                Some(IpAddr::V4(Ipv4Addr::from(<[u8; 4]>::try_from(addr).ok()?)))
+            }
+            addr if addr.len() == 16 => {
+                Some(IpAddr::V6(Ipv6Addr::from(<[u8; 16]>::try_from(addr).ok()?)))
+            }
+            _ => return None,
+        };
+        Some(Self {
+            kind: PullKind::from_u8(buf[5])?,
+            port: u16::from_be_bytes([buf[6], buf[7]]),
+            ip,
+            cookie: u64::from_be_bytes(buf[8..HEADER_LEN].try_into().ok()?),
+        })
+    }
+
+    /// The RTP destination a message from `from` names
+    pub fn destination(&self, from: SocketAddr) -> SocketAddr {
+        let port = if self.port == 0 {
+            from.port()
+        } else {
+            self.port
+        };
+        SocketAddr::new(self.ip.unwrap_or(from.ip()), port)
+    }
+}
+
+/// Receivers pulling one camera's stream, driven by requests and a clock
+#[derive(Debug)]
+pub struct PullSessions {
+    timeout: Duration,
+    max_receivers: usize,
+    /// Destinations in the order they first pulled, with their last request
+    receivers: Vec<(SocketAddr, Instant)>,
+}
+
+impl PullSessions {
+    pub fn new(timeout: Duration, max_receivers: usize) -> Self {
+        Self {
+            timeout,
+            max_receivers,
+            receivers: Vec::new(),
+        }
+    }
+
+    /// Starts or keeps streaming to `dest`. Returns `false` if it is new
+    /// and `max_receivers` are already pulling.
+    pub fn pull(&mut self, dest: SocketAddr, now: Instant) -> bool {
+        if let Some((_, last_seen)) = self.receivers.iter_mut().find(|(addr, _)| *addr == dest) {
+            *last_seen = now;
+            return true;
+        }
+        if self.receivers.len() >= self.max_receivers {
+            return false;
+        }
+        self.receivers.push((dest, now));
+        true
+    }
+
+    /// Stops streaming to `dest`. Returns `false` if it wasn't pulling.
+    pub fn stop(&mut self, dest: SocketAddr) -> bool {
+        let before = self.receivers.len();
+        self.receivers.retain(|(addr, _)| *addr != dest);
+        self.receivers.len() != before
+    }
+
+    /// Forgets receivers not heard from within the timeout
+    pub fn expire(&mut self, now: Instant) {
+        let timeout = self.timeout;
+        self.receivers.retain(|(addr, last_seen)| {
+            let alive = now.duration_since(*last_seen) < timeout;
+            if !alive {
+                info!(receiver = %addr, "Receiver stopped pulling, dropping it");
+            }
+            alive
+        });
+    }
+
+    /// Destinations pulling the stream, the first one first
+    pub fn receivers(&self) -> Vec<SocketAddr> {
+        self.receivers.iter().map(|(addr, _)| *addr).collect()
+    }
+}
+
+/// Takes pull requests for one camera and publishes who is pulling
+pub struct PullListener {
+    socket: UdpSocket,
+    sessions: PullSessions,
+    receivers_tx: watch::Sender<Vec<SocketAddr>>,
+    allow: Vec<IpAddr>,
+    /// Key of the cookies, new for every listener
+    secret: RandomState,
+}
+
+impl PullListener {
+    /// Binds the request socket; the receivers pulling are published to
+    /// `receivers_tx`
+    pub async fn bind(
+        config: &PullConfig,
+        receivers_tx: watch::Sender<Vec<SocketAddr>>,
+    ) -> std::io::Result<Self> {
+        let socket = UdpSocket::bind(config.listen).await?;
+        Ok(Self {
+            socket,
+            sessions: PullSessions::new(
+                Duration::from_millis(config.idle_timeout_ms),
+                config.max_receivers,
+            ),
+            receivers_tx,
+            allow: config.allow.clone(),
+            secret: RandomState::new(),
+        })
+    }
+
+    /// Takes requests until `token` is cancelled, then publishes that
+    /// nobody is pulling
+    pub async fn run(mut self, token: CancellationToken) {
+        if let Ok(addr) = self.socket.local_addr() {
+            info!(%addr, "Waiting for receivers to pull the stream");
+        }
+        let log = LogLimiter::default();
+        let mut ticker =
+            tokio::time::interval((self.sessions.timeout / 4).max(Duration::from_millis(10)));
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        let mut buf = [0u8; 64];
+        loop {
+            tokio::select! {
+                _ = token.cancelled() => break,
+                _ = ticker.tick() => self.sessions.expire(Instant::now()),
+                received = self.socket.recv_from(&mut buf) => match received {
+                    Ok((len, from)) => match PullMessage::decode(&buf[..len]) {
+                        Some(message) => self.handle(&message, from, &log).await,
+                        None => debug!(%from, len, "Ignoring datagram that is not a pull request"),
+                    },
+                    Err(e) => {
+                        log_limited!(log, "recv", warn, error = %e, "Failed to receive pull request");
+                    }
+                },
+            }
+            self.publish();
+        }
+        self.receivers_tx.send_replace(Vec::new());
+    }
+
+    /// The cookie a request from `from` for `dest` must echo
+    fn cookie(&self, from: SocketAddr, dest: SocketAddr) -> u64 {
+        self.secret.hash_one((from, dest))
+    }
+
+    async fn handle(&mut self, message: &PullMessage, from: SocketAddr, log: &LogLimiter) {
+        let dest = message.destination(from);
+        let usable =
+            !dest.ip().is_unspecified() && dest.port() != 0 && dest.is_ipv4() == from.is_ipv4();
+        let allowed = dest.ip() == from.ip() || self.allow.contains(&dest.ip());
+        let cookie = self.cookie(from, dest);
+        let kind = match message.kind {
+            PullKind::Pull if !usable => {
+                log_limited!(
+                    log,
+                    "unusable",
+                    warn,
+                    %from,
+                    %dest,
+                    "Ignoring pull request for an unusable destination"
+                );
+                return;
+            }
+            PullKind::Pull if !allowed => {
+                log_limited!(
+                    log,
+                    "not allowed",
+                    warn,
+                    %from,
+                    %dest,
+                    "Ignoring pull request for an address not in pull.allow"
+                );
+                return;
+            }
+            // The source has yet to show it receives the answer
+            PullKind::Pull if message.cookie != cookie => PullKind::Ack,
+            PullKind::Pull if self.sessions.pull(dest, Instant::now()) => PullKind::Ack,
+            PullKind::Pull => {
+                log_limited!(
+                    log,
+                    "full",
+                    warn,
+                    %from,
+                    %dest,
+                    "Too many receivers pulling, refusing one more"
+                );
+                PullKind::Full
+            }
+            PullKind::Stop => {
+                if message.cookie == cookie && self.sessions.stop(dest) {
+                    info!(receiver = %dest, "Receiver stopped the stream");
+                }
+                return;
+            }
+            // Answers aren't for us
+            PullKind::Ack | PullKind::Full => return,
+        };
+        let answer = PullMessage {
+            kind,
+            port: dest.port(),
+            ip: Some(dest.ip()),
+            cookie,
+        };
+        if let Err(e) = self.socket.send_to(&answer.encode(), from).await {
+            log_limited!(log, "send", debug, %from, error = %e, "Failed to answer pull request");
+        }
+    }
+
+    fn publish(&self) {
+        let receivers = self.sessions.receivers();
+        self.receivers_tx.send_if_modified(|current| {
+            if *current == receivers {
+                return false;
+            }
+            info!(receivers = ?receivers, "Receivers pulling the stream changed");
+            *current = receivers;
+            true
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TIMEOUT: Duration = Duration::from_millis(1000);
+
+    fn addr(s: &str) -> SocketAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_message_roundtrip() {
+        for ip in [
+            None,
+            Some("10.0.0.5".parse().unwrap()),
+            Some("fd00::5".parse().unwrap()),
+        ] {
+            let message = PullMessage {
+                kind: PullKind::Pull,
+                port: 5000,
+                ip,
+                cookie: 0x0123_4567_89AB_CDEF,
+            };
+            assert_eq!(PullMessage::decode(&message.encode()), Some(message));
+        }
+
+        let encoded = PullMessage {
+            kind: PullKind::Stop,
+            port: 0,
+            ip: None,
+            cookie: 7,
+        }
+        .encode();
+        assert_eq!(
+            encoded,
+            b"MJPL\x02\x02\x00\x00\x00\x00\x00\x00\x00\x00\x00\x07"
+        );
+        assert_eq!(PullMessage::decode(&encoded[..15]), None);
+        // Version 1 requests carried no cookie
+        assert_eq!(PullMessage::decode(b"MJPL\x01\x01\x13\x88"), None);
+        let mut unknown = encoded.clone();
+        unknown[5] = 9;
+        assert_eq!(PullMessage::decode(&unknown), None);
+        let mut short_addr = encoded;
+        short_addr.extend_from_slice(&[10, 0]);
+        assert_eq!(PullMessage::decode(&short_addr), None);
+    }
+
+    #[test]
+    fn test_destination_defaults_to_sender() {
+        let from = addr("203.0.113.7:40000");
+        let bare = PullMessage {
+            kind: PullKind::Pull,
+            port: 0,
+            ip: None,
+            cookie: 0,
+        };
+        assert_eq!(bare.destination(from), from);
+
+        let port = PullMessage { port: 5000, ..bare };
+        assert_eq!(port.destination(from), addr("203.0.113.7:5000"));
+
+        let explicit = PullMessage {
+            ip: Some("192.168.1.50".parse().unwrap()),
+            ..port
+        };
+        assert_eq!(explicit.destination(from), addr("192.168.1.50:5000"));
+    }
+
+    #[test]
+    fn test_sessions_expire_without_requests() {
+        let start = Instant::now();
+        let mut sessions = PullSessions::new(TIMEOUT, 2);
+        let (a, b, c) = (
+            addr("10.0.0.1:5000"),
+            addr("10.0.0.2:5000"),
+            addr("10.0.0.3:5000"),
+        );
+
+        assert!(sessions.pull(a, start));
+        assert!(sessions.pull(b, start + Duration::from_millis(100)));
+        assert!(!sessions.pull(c, start + Duration::from_millis(100)));
+        assert_eq!(sessions.receivers(), vec![a, b]);
+
+        // Repeating the request keeps a receiver, and its place
+        assert!(sessions.pull(a, start + Duration::from_millis(900)));
+        sessions.expire(start + Duration::from_millis(1500));
+        assert_eq!(sessions.receivers(), vec![a]);
+
+        assert!(sessions.stop(a));
+        assert!(!sessions.stop(a));
+        assert!(sessions.receivers().is_empty());
+    }
+
+    /// Sends `message` from `socket` and returns the answer
+    async fn request(socket: &UdpSocket, listen: SocketAddr, message: PullMessage) -> PullMessage {
+        socket.send_to(&message.encode(), listen).await.unwrap();
+        let mut buf = [0u8; 64];
+        let len = socket.recv(&mut buf).await.unwrap();
+        PullMessage::decode(&buf[..len]).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_listener_answers_and_publishes() {
+        let config = PullConfig {
+            enabled: true,
+            listen: addr("127.0.0.1:0"),
+            idle_timeout_ms: 200,
+            max_receivers: 1,
+            allow: Vec::new(),
+        };
+        let (receivers_tx, mut receivers) = watch::channel(Vec::new());
+        let listener = PullListener::bind(&config, receivers_tx).await.unwrap();
+        let listen = listener.socket.local_addr().unwrap();
+        let token = CancellationToken::new();
+        let task = tokio::spawn(listener.run(token.clone()));
+
+        let receiver = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let pull = PullMessage {
+            kind: PullKind::Pull,
+            port: 0,
+            ip: None,
+            cookie: 0,
+        };
+        // Without the cookie the request is only answered
+        let answer = request(&receiver, listen, pull).await;
+        assert_eq!(answer.kind, PullKind::Ack);
+        assert_eq!(answer.destination(listen), receiver.local_addr().unwrap());
+        assert!(receivers.borrow().is_empty());
+
+        let echo = PullMessage {
+            cookie: answer.cookie,
+            ..pull
+        };
+        assert_eq!(request(&receiver, listen, echo).await, answer);
+        receivers.changed().await.unwrap();
+        assert_eq!(
+            *receivers.borrow_and_update(),
+            vec![receiver.local_addr().unwrap()]
+        );
+
+        // A second receiver is one too many
+        let other = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let cookie = request(&other, listen, pull).await.cookie;
+        assert_ne!(cookie, answer.cookie);
+        let refused = request(&other, listen, PullMessage { cookie, ..pull }).await;
+        assert_eq!(refused.kind, PullKind::Full);
+
+        // Without another request the first is dropped
+        receivers.changed().await.unwrap();
+        assert!(receivers.borrow_and_update().is_empty());
+
+        token.cancel();
+        task.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_listener_streams_elsewhere_only_when_allowed() {
+        let config = PullConfig {
+            enabled: true,
+            listen: addr("127.0.0.1:0"),
+            idle_timeout_ms: 10_000,
+            max_receivers: 2,
+            allow: vec!["10.0.0.5".parse().unwrap()],
+        };
+        let (receivers_tx, mut receivers) = watch::channel(Vec::new());
+        let listener = PullListener::bind(&config, receivers_tx).await.unwrap();
+        let listen = listener.socket.local_addr().unwrap();
+        let token = CancellationToken::new();
+        let task = tokio::spawn(listener.run(token.clone()));
+
+        let receiver = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let elsewhere = PullMessage {
+            kind: PullKind::Pull,
+            port: 5000,
+            ip: Some("10.0.0.6".parse().unwrap()),
+            cookie: 0,
+        };
+        // Not answered at all; the next answer is for the allowed address
+        receiver.send_to(&elsewhere.encode(), listen).await.unwrap();
+        let allowed = PullMessage {
+            ip: Some("10.0.0.5".parse().unwrap()),
+            ..elsewhere
+        };
+        let answer = request(&receiver, listen, allowed).await;
+        assert_eq!(answer.ip, allowed.ip);
+        let echo = PullMessage {
+            cookie: answer.cookie,
+            ..allowed
+        };
+        assert_eq!(request(&receiver, listen, echo).await.kind, PullKind::Ack);
+        receivers.changed().await.unwrap();
+        assert_eq!(*receivers.borrow_and_update(), vec![addr("10.0.0.5:5000")]);
+
+        // A stop without the cookie is ignored, with it the receiver is gone
+        let stop = PullMessage {
+            kind: PullKind::Stop,
+            ..allowed
+        };
+        receiver.send_to(&stop.encode(), listen).await.unwrap();
+        assert_eq!(request(&receiver, listen, echo).await.kind, PullKind::Ack);
+        assert!(!receivers.has_changed().unwrap());
+        let stop = PullMessage {
+            cookie: answer.cookie,
+            ..stop
+        };
+        receiver.send_to(&stop.encode(), listen).await.unwrap();
+        receivers.changed().await.unwrap();
+        assert!(receivers.borrow_and_update().is_empty());
+
+        token.cancel();
+        task.await.unwrap();
+    }
+}
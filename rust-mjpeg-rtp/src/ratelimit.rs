@@ -0,0 +1,128 @@
+//! Rate-limited logging for hot paths
+//!
+//! The send loop and the capture callback run once per packet or frame, so a
+//! persistent failure (a dead destination, a stalled consumer) would log
+//! thousands of identical lines a second and fill the SD card. Lines go
+//! through a [`LogLimiter`] instead: each kind of event is logged at most
+//! once per interval, and the next line that gets through carries a
+//! `suppressed` count of what was dropped in between.
+//!
+//! ```
+//! use rust_mjpeg_rtp::log_limited;
+//! use rust_mjpeg_rtp::ratelimit::LogLimiter;
+//!
+//! let limiter = LogLimiter::default();
+//! for packet in 0..1000 {
+//!     log_limited!(limiter, "send", warn, packet, "Failed to send RTP packet");
+//! }
+//! ```
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+#[doc(hidden)]
+pub use tracing as __tracing;
+
+/// Default minimum time between lines of the same kind
+pub const DEFAULT_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug)]
+struct Window {
+    logged_at: Instant,
+    suppressed: u64,
+}
+
+/// Decides which occurrences of each event kind get logged
+#[derive(Debug)]
+pub struct LogLimiter {
+    interval: Duration,
+    windows: Mutex<HashMap<&'static str, Window>>,
+}
+
+impl LogLimiter {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            windows: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Records an occurrence of `kind`. Returns how many occurrences were
+    /// suppressed since the last logged one if this one should be logged,
+    /// `None` if it should be dropped.
+    pub fn check(&self, kind: &'static str) -> Option<u64> {
+        self.check_at(kind, Instant::now())
+    }
+
+    fn check_at(&self, kind: &'static str, now: Instant) -> Option<u64> {
+        let mut windows = self.windows.lock().unwrap();
+        match windows.get_mut(kind) {
+            Some(window) if now.saturating_duration_since(window.logged_at) < self.interval => {
+                window.suppressed += 1;
+                None
+            }
+            Some(window) => {
+                window.logged_at = now;
+                Some(std::mem::take(&mut window.suppressed))
+            }
+            None => {
+                windows.insert(
+                    kind,
+                    Window {
+                        logged_at: now,
+                        suppressed: 0,
+                    },
+                );
+                Some(0)
+            }
+        }
+    }
+}
+
+impl Default for LogLimiter {
+    fn default() -> Self {
+        Self::new(DEFAULT_INTERVAL)
+    }
+}
+
+/// Logs through a [`LogLimiter`]: `log_limited!(limiter, kind, level, ...)`
+/// where `level` is a `tracing` macro name and the rest are its arguments.
+/// Lines that get through gain a `suppressed` field.
+#[macro_export]
+macro_rules! log_limited {
+    ($limiter:expr, $kind:expr, $level:ident, $($arg:tt)+) => {
+        if let Some(suppressed) = $limiter.check($kind) {
+            $crate::ratelimit::__tracing::$level!(suppressed, $($arg)+);
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_once_per_interval_per_kind() {
+        let limiter = LogLimiter::new(Duration::from_secs(1));
+        let start = Instant::now();
+
+        assert_eq!(limiter.check_at("send", start), Some(0));
+        for i in 1..=500 {
+            assert_eq!(
+                limiter.check_at("send", start + Duration::from_millis(i)),
+                None
+            );
+        }
+        // Other kinds have their own window
+        assert_eq!(limiter.check_at("packetize", start), Some(0));
+
+        let next = start + Duration::from_secs(1);
+        assert_eq!(limiter.check_at("send", next), Some(500));
+        assert_eq!(limiter.check_at("send", next), None);
+        assert_eq!(
+            limiter.check_at("send", next + Duration::from_secs(5)),
+            Some(1)
+        );
+    }
+}
@@ -0,0 +1,53 @@
+//! Opt-in real-time scheduling for the send loop
+//!
+//! At 60 fps a frame has ~16 ms; a sender preempted by CFS for a few ms shows
+//! up directly as frame pacing jitter. SCHED_FIFO needs CAP_SYS_NICE or an
+//! RLIMIT_RTPRIO allowance, so failure is expected and handled by staying on
+//! the normal scheduler.
+
+use tracing::{info, warn};
+
+/// Lowest and highest SCHED_FIFO priorities accepted by Linux
+pub const MIN_PRIORITY: u8 = 1;
+pub const MAX_PRIORITY: u8 = 99;
+
+/// Switches the calling thread to SCHED_FIFO at `priority`
+///
+/// Returns `false` (after logging why) when the platform or the process's
+/// privileges don't allow it; the thread then keeps its current policy.
+#[cfg(target_os = "linux")]
+pub fn set_current_thread_fifo(priority: u8, role: &str) -> bool {
+    let priority = priority.clamp(MIN_PRIORITY, MAX_PRIORITY);
+    // Zeroed rather than a struct literal: musl's sched_param has extra fields
+    // SAFETY: sched_param is plain old data
+    let mut param: libc::sched_param = unsafe { std::mem::zeroed() };
+    param.sched_priority = priority as libc::c_int;
+
+    // SAFETY: pid 0 targets the calling thread; `param` outlives the call
+    let rc = unsafe { libc::sched_setscheduler(0, libc::SCHED_FIFO, &param) };
+    if rc == 0 {
+        info!(role, priority, "Thread running with SCHED_FIFO");
+        return true;
+    }
+
+    let err = std::io::Error::last_os_error();
+    if err.raw_os_error() == Some(libc::EPERM) {
+        warn!(
+            role,
+            priority,
+            "SCHED_FIFO not permitted (needs CAP_SYS_NICE or RLIMIT_RTPRIO), using normal scheduling"
+        );
+    } else {
+        warn!(role, priority, error = %err, "Failed to enable SCHED_FIFO, using normal scheduling");
+    }
+    false
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn set_current_thread_fifo(priority: u8, role: &str) -> bool {
+    warn!(
+        role,
+        priority, "SCHED_FIFO is only supported on Linux, using normal scheduling"
+    );
+    false
+}
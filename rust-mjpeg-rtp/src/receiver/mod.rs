@@ -0,0 +1,618 @@
+//! RTP/JPEG depacketizer (RFC 2435 receive side)
+//!
+//! Reassembles frames from RTP packets. Packets from an unexpected SSRC and
+//! duplicated or replayed sequence numbers are rejected before they reach
+//! reassembly, so a co-channel stream or a replay can't corrupt a frame.
+//! Packets of a frame that is older than the newest one, or already finished,
+//! are dropped as late, so a straggler can't displace the frame being
+//! reassembled. When the sender stamps frames with the [`FrameInfo`]
+//! extension, each reassembled scan is checked against its CRC32.
+
+use crate::error::ErrorCode;
+use crate::rtp::{
+    FrameInfo, JpegHeader, RtpHeader, JPEG_HEADER_SIZE, RTP_HEADER_SIZE, RTP_PAYLOAD_TYPE_JPEG,
+    RTP_VERSION,
+};
+use bytes::{Bytes, BytesMut};
+use std::collections::BTreeMap;
+use thiserror::Error;
+
+/// Sequence numbers remembered for duplicate detection
+pub const REPLAY_WINDOW: u16 = 128;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum ReceiverError {
+    #[error("malformed packet: {0}")]
+    Malformed(&'static str),
+
+    #[error("unexpected SSRC {got:#010x}, expected {expected:#010x}")]
+    UnexpectedSsrc { expected: u32, got: u32 },
+
+    #[error("duplicate or replayed sequence number {0}")]
+    Duplicate(u16),
+
+    #[error("late packet for an earlier frame (timestamp {0})")]
+    Late(u32),
+
+    #[error("frame {frame_id} reassembled with a CRC32 mismatch")]
+    Corrupt { frame_id: u32 },
+}
+
+impl ReceiverError {
+    pub fn code(&self) -> ErrorCode {
+        ErrorCode::InvalidInput
+    }
+}
+
+/// Receiver configuration
+#[derive(Debug, Clone, Default)]
+pub struct ReceiverConfig {
+    /// Only accept this SSRC. When unset, the first SSRC seen is locked in.
+    pub expected_ssrc: Option<u32>,
+    /// Header extension ID of the sender's [`FrameInfo`]; frames carrying it
+    /// are verified against their CRC32
+    pub frame_info_id: Option<u8>,
+}
+
+/// Packet and frame counters
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReceiverStats {
+    pub packets_received: u64,
+    pub frames_completed: u64,
+    /// Frames discarded because fragments were missing
+    pub frames_incomplete: u64,
+    pub rejected_ssrc: u64,
+    pub rejected_duplicate: u64,
+    /// Packets that arrived after a newer frame had started
+    pub rejected_late: u64,
+    pub rejected_malformed: u64,
+    /// Frames whose reassembled scan matched their [`FrameInfo`] CRC32
+    pub frames_verified: u64,
+    /// Frames whose reassembled scan did not
+    pub frames_corrupt: u64,
+    /// Padding-only packets, sent to keep NAT bindings open
+    pub keepalives: u64,
+}
+
+/// A reassembled frame: JPEG header fields plus the scan data
+#[derive(Debug, Clone)]
+pub struct ReassembledFrame {
+    pub ssrc: u32,
+    pub timestamp: u32,
+    pub width: u32,
+    pub height: u32,
+    pub jpeg_type: u8,
+    pub q: u8,
+    /// Quantization tables from the first packet, when sent in-band
+    pub q_tables: Option<Bytes>,
+    pub scan_data: Bytes,
+    /// Sender's frame id, when the frame was verified against its
+    /// [`FrameInfo`] extension
+    pub frame_id: Option<u32>,
+}
+
+/// Sliding window over recently seen sequence numbers
+#[derive(Debug, Default)]
+struct ReplayWindow {
+    highest: Option<u16>,
+    /// Bit n set = `highest - n` was seen
+    seen: u128,
+    /// After a jump back past the window, the sequence number that confirms
+    /// the sender restarted (RFC 3550 Appendix A.1)
+    bad_seq: Option<u16>,
+}
+
+impl ReplayWindow {
+    /// Records `seq`; returns false if it is a duplicate or too old
+    fn accept(&mut self, seq: u16) -> bool {
+        let Some(highest) = self.highest.filter(|_| self.bad_seq != Some(seq)) else {
+            // First packet, or the second in a row after a large jump
+            self.highest = Some(seq);
+            self.seen = 1;
+            self.bad_seq = None;
+            return true;
+        };
+
+        let delta = seq.wrapping_sub(highest) as i16;
+        if delta > 0 {
+            let shift = delta as u32;
+            self.seen = if shift >= REPLAY_WINDOW as u32 {
+                0
+            } else {
+                self.seen << shift
+            };
+            self.seen |= 1;
+            self.highest = Some(seq);
+            true
+        } else {
+            let age = delta.unsigned_abs() as u32;
+            if age >= REPLAY_WINDOW as u32 {
+                // Too old, unless the next packet follows on from it
+                self.bad_seq = Some(seq.wrapping_add(1));
+                return false;
+            }
+            if self.seen & (1 << age) != 0 {
+                return false;
+            }
+            self.seen |= 1 << age;
+            true
+        }
+    }
+}
+
+/// Frame under reassembly
+#[derive(Debug)]
+struct PendingFrame {
+    timestamp: u32,
+    header: JpegHeader,
+    q_tables: Option<Bytes>,
+    /// Fragment offset -> scan data
+    fragments: BTreeMap<u32, Bytes>,
+    frame_info: Option<FrameInfo>,
+}
+
+/// RTP/JPEG depacketizer with SSRC filtering and replay protection
+#[derive(Debug, Default)]
+pub struct Depacketizer {
+    ssrc: Option<u32>,
+    frame_info_id: Option<u8>,
+    window: ReplayWindow,
+    /// Timestamp of the newest frame seen
+    newest_timestamp: Option<u32>,
+    pending: Option<PendingFrame>,
+    stats: ReceiverStats,
+}
+
+impl Depacketizer {
+    pub fn new(config: ReceiverConfig) -> Self {
+        Self {
+            ssrc: config.expected_ssrc,
+            frame_info_id: config.frame_info_id,
+            ..Default::default()
+        }
+    }
+
+    /// Feeds one UDP datagram; returns a frame when its last packet arrives
+    pub fn push(&mut self, packet: &[u8]) -> Result<Option<ReassembledFrame>, ReceiverError> {
+        self.stats.packets_received += 1;
+
+        let result = self.push_inner(packet);
+        match &result {
+            Err(ReceiverError::Malformed(_)) => self.stats.rejected_malformed += 1,
+            Err(ReceiverError::UnexpectedSsrc { .. }) => self.stats.rejected_ssrc += 1,
+            Err(ReceiverError::Duplicate(_)) => self.stats.rejected_duplicate += 1,
+            Err(ReceiverError::Late(_)) => self.stats.rejected_late += 1,
+            Err(ReceiverError::Corrupt { .. }) => self.stats.frames_corrupt += 1,
+            Ok(Some(frame)) => {
+                self.stats.frames_completed += 1;
+                if frame.frame_id.is_some() {
+                    self.stats.frames_verified += 1;
+                }
+            }
+            Ok(None) => {}
+        }
+        result
+    }
+
+    fn push_inner(&mut self, packet: &[u8]) -> Result<Option<ReassembledFrame>, ReceiverError> {
+        let rtp =
+            RtpHeader::from_bytes(packet).ok_or(ReceiverError::Malformed("short RTP header"))?;
+        if rtp.version != RTP_VERSION {
+            return Err(ReceiverError::Malformed("bad RTP version"));
+        }
+        if rtp.payload_type != RTP_PAYLOAD_TYPE_JPEG {
+            return Err(ReceiverError::Malformed("not a JPEG payload"));
+        }
+
+        // SSRC check comes before the replay window so another stream's
+        // sequence numbers never advance ours
+        match self.ssrc {
+            Some(expected) if expected != rtp.ssrc => {
+                return Err(ReceiverError::UnexpectedSsrc {
+                    expected,
+                    got: rtp.ssrc,
+                });
+            }
+            Some(_) => {}
+            None => self.ssrc = Some(rtp.ssrc),
+        }
+
+        let mut payload_start = RTP_HEADER_SIZE + 4 * rtp.csrc_count as usize;
+        let mut frame_info = None;
+        if rtp.extension {
+            let words = packet
+                .get(payload_start..payload_start + 4)
+                .ok_or(ReceiverError::Malformed("truncated header extension"))?;
+            let profile = u16::from_be_bytes([words[0], words[1]]);
+            let length = 4 * u16::from_be_bytes([words[2], words[3]]) as usize;
+            let data = packet
+                .get(payload_start + 4..payload_start + 4 + length)
+                .ok_or(ReceiverError::Malformed("truncated header extension"))?;
+            frame_info = self
+                .frame_info_id
+                .and_then(|id| FrameInfo::from_extension(profile, data, id));
+            payload_start += 4 + length;
+        }
+        let payload = packet
+            .get(payload_start..)
+            .ok_or(ReceiverError::Malformed("truncated CSRC list"))?;
+
+        // Nothing but padding: a keepalive between frames, which only takes
+        // a sequence number
+        if rtp.padding
+            && payload
+                .last()
+                .is_some_and(|&count| count as usize == payload.len())
+        {
+            if !self.window.accept(rtp.sequence_number) {
+                return Err(ReceiverError::Duplicate(rtp.sequence_number));
+            }
+            self.stats.keepalives += 1;
+            return Ok(None);
+        }
+        let header =
+            JpegHeader::from_bytes(payload).ok_or(ReceiverError::Malformed("short JPEG header"))?;
+        let mut data = &payload[JPEG_HEADER_SIZE..];
+
+        if !self.window.accept(rtp.sequence_number) {
+            return Err(ReceiverError::Duplicate(rtp.sequence_number));
+        }
+
+        // Older than the newest frame, or part of a frame already finished
+        // (completed or given up at its marker)
+        if let Some(newest) = self.newest_timestamp {
+            let age = newest.wrapping_sub(rtp.timestamp) as i32;
+            if age > 0 || (age == 0 && self.pending.is_none()) {
+                return Err(ReceiverError::Late(rtp.timestamp));
+            }
+        }
+
+        // A new timestamp starts a new frame; whatever was pending is lost
+        if self
+            .pending
+            .as_ref()
+            .is_some_and(|p| p.timestamp != rtp.timestamp)
+        {
+            self.pending = None;
+            self.stats.frames_incomplete += 1;
+        }
+
+        // Q 128-254: the first packet carries a quantization table header.
+        // Q=255 without a table header is what RtpPacketizer sends when it has
+        // no parsed tables, so it is treated as "no tables".
+        let mut q_tables = None;
+        if header.fragment_offset == 0 && (128..255).contains(&header.q) {
+            if data.len() < 4 {
+                return Err(ReceiverError::Malformed("short quantization table header"));
+            }
+            let length = u16::from_be_bytes([data[2], data[3]]) as usize;
+            let tables = data
+                .get(4..4 + length)
+                .ok_or(ReceiverError::Malformed("truncated quantization tables"))?;
+            q_tables = Some(Bytes::copy_from_slice(tables));
+            data = &data[4 + length..];
+        }
+
+        self.newest_timestamp = Some(rtp.timestamp);
+        let pending = self.pending.get_or_insert_with(|| PendingFrame {
+            timestamp: rtp.timestamp,
+            header: header.clone(),
+            q_tables: None,
+            fragments: BTreeMap::new(),
+            frame_info: None,
+        });
+        if pending.frame_info.is_none() {
+            pending.frame_info = frame_info;
+        }
+        if header.fragment_offset == 0 {
+            pending.header = header.clone();
+            pending.q_tables = q_tables;
+        }
+        pending
+            .fragments
+            .insert(header.fragment_offset, Bytes::copy_from_slice(data));
+
+        if !rtp.marker {
+            return Ok(None);
+        }
+
+        let pending = self.pending.take().expect("pending frame");
+        match assemble(&pending.fragments) {
+            Some(scan_data) => {
+                if let Some(info) = pending.frame_info {
+                    if !info.matches(&scan_data) {
+                        return Err(ReceiverError::Corrupt {
+                            frame_id: info.frame_id,
+                        });
+                    }
+                }
+                Ok(Some(ReassembledFrame {
+                    ssrc: rtp.ssrc,
+                    timestamp: pending.timestamp,
+                    width: pending.header.width(),
+                    height: pending.header.height(),
+                    jpeg_type: pending.header.jpeg_type as u8,
+                    q: pending.header.q,
+                    q_tables: pending.q_tables,
+                    scan_data,
+                    frame_id: pending.frame_info.map(|info| info.frame_id),
+                }))
+            }
+            None => {
+                self.stats.frames_incomplete += 1;
+                Ok(None)
+            }
+        }
+    }
+
+    pub fn get_stats(&self) -> ReceiverStats {
+        self.stats.clone()
+    }
+
+    /// SSRC currently accepted (configured or locked in from the first packet)
+    pub fn ssrc(&self) -> Option<u32> {
+        self.ssrc
+    }
+}
+
+/// Concatenates fragments if they cover the frame without gaps from offset 0
+fn assemble(fragments: &BTreeMap<u32, Bytes>) -> Option<Bytes> {
+    let mut out = BytesMut::new();
+    for (&offset, data) in fragments {
+        if offset as usize != out.len() {
+            return None;
+        }
+        out.extend_from_slice(data);
+    }
+    Some(out.freeze())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rtp::RtpPacketizer;
+
+    /// Baseline JPEG with one quantization table, so the first packet
+    /// carries a table header the way real camera frames do
+    fn create_test_jpeg(payload_size: usize) -> Vec<u8> {
+        let mut jpeg = vec![0xFF, 0xD8];
+        // DQT: 8-bit table 0
+        jpeg.extend(&[0xFF, 0xDB, 0x00, 0x43, 0x00]);
+        jpeg.extend(1..=64u8);
+        // SOF0: 640x480, one component
+        jpeg.extend(&[
+            0xFF, 0xC0, 0x00, 0x0B, 0x08, 0x01, 0xE0, 0x02, 0x80, 0x01, 0x01, 0x11, 0x00,
+        ]);
+        // SOS
+        jpeg.extend(&[0xFF, 0xDA, 0x00, 0x08, 0x01, 0x01, 0x00, 0x00, 0x3F, 0x00]);
+        jpeg.extend((0..payload_size).map(|i| (i % 251) as u8));
+        jpeg.extend(&[0xFF, 0xD9]);
+        jpeg
+    }
+
+    fn packets(ssrc: u32, timestamp: u32) -> Vec<Bytes> {
+        let p = RtpPacketizer::new(ssrc, 500);
+        p.packetize_jpeg(&create_test_jpeg(2000), 640, 480, timestamp)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_reassembles_frame() {
+        let mut d = Depacketizer::new(ReceiverConfig::default());
+        let pkts = packets(0x1234, 9000);
+
+        let mut frame = None;
+        for pkt in &pkts {
+            frame = d.push(pkt).unwrap();
+        }
+
+        let frame = frame.expect("frame completed on marker");
+        assert_eq!(frame.ssrc, 0x1234);
+        assert_eq!(frame.timestamp, 9000);
+        assert_eq!((frame.width, frame.height), (640, 480));
+        assert!(frame.q_tables.is_some());
+        assert_eq!(frame.scan_data.len(), 2000);
+        assert_eq!(d.get_stats().frames_completed, 1);
+    }
+
+    #[test]
+    fn test_rejects_unexpected_ssrc() {
+        let mut d = Depacketizer::new(ReceiverConfig {
+            expected_ssrc: Some(0x1234),
+            ..Default::default()
+        });
+
+        let other = packets(0x9999, 9000);
+        assert!(matches!(
+            d.push(&other[0]),
+            Err(ReceiverError::UnexpectedSsrc {
+                expected: 0x1234,
+                got: 0x9999
+            })
+        ));
+        assert_eq!(d.get_stats().rejected_ssrc, 1);
+    }
+
+    #[test]
+    fn test_locks_onto_first_ssrc() {
+        let mut d = Depacketizer::new(ReceiverConfig::default());
+        d.push(&packets(0x1234, 0)[0]).unwrap();
+        assert_eq!(d.ssrc(), Some(0x1234));
+        assert!(d.push(&packets(0x5678, 0)[0]).is_err());
+    }
+
+    #[test]
+    fn test_rejects_duplicates_and_replays() {
+        let mut d = Depacketizer::new(ReceiverConfig::default());
+        let pkts = packets(0x1234, 9000);
+
+        d.push(&pkts[0]).unwrap();
+        assert!(matches!(d.push(&pkts[0]), Err(ReceiverError::Duplicate(0))));
+
+        // Replaying the whole frame after completion yields nothing new
+        for pkt in &pkts[1..] {
+            d.push(pkt).unwrap();
+        }
+        for pkt in &pkts {
+            assert!(d.push(pkt).is_err());
+        }
+        let stats = d.get_stats();
+        assert_eq!(stats.frames_completed, 1);
+        assert_eq!(stats.rejected_duplicate, 1 + pkts.len() as u64);
+    }
+
+    #[test]
+    fn test_keepalive_between_frames() {
+        let mut d = Depacketizer::new(ReceiverConfig::default());
+        let p = RtpPacketizer::new(0x1234, 500);
+        let jpeg = create_test_jpeg(2000);
+
+        let mut pkts = p.packetize_jpeg(&jpeg, 640, 480, 9000).unwrap();
+        let keepalive = p.keepalive_packet();
+        pkts.push(keepalive.clone());
+        pkts.extend(p.packetize_jpeg(&jpeg, 640, 480, 12000).unwrap());
+
+        let frames = pkts.iter().filter_map(|pkt| d.push(pkt).unwrap()).count();
+        assert_eq!(frames, 2);
+        assert!(d.push(&keepalive).is_err());
+
+        let stats = d.get_stats();
+        assert_eq!(stats.keepalives, 1);
+        assert_eq!(stats.frames_incomplete, 0);
+    }
+
+    #[test]
+    fn test_reordered_packets_accepted() {
+        let mut d = Depacketizer::new(ReceiverConfig::default());
+        let pkts = packets(0x1234, 9000);
+        assert!(pkts.len() >= 3);
+
+        d.push(&pkts[1]).unwrap();
+        d.push(&pkts[0]).unwrap();
+        let mut frame = None;
+        for pkt in &pkts[2..] {
+            frame = d.push(pkt).unwrap();
+        }
+        assert!(frame.is_some());
+    }
+
+    #[test]
+    fn test_missing_fragment_is_incomplete() {
+        let mut d = Depacketizer::new(ReceiverConfig::default());
+        let pkts = packets(0x1234, 9000);
+
+        for (i, pkt) in pkts.iter().enumerate() {
+            if i != 1 {
+                assert!(d.push(pkt).unwrap().is_none());
+            }
+        }
+        assert_eq!(d.get_stats().frames_incomplete, 1);
+    }
+
+    #[test]
+    fn test_late_packet_does_not_displace_newer_frame() {
+        let mut d = Depacketizer::new(ReceiverConfig::default());
+        let p = RtpPacketizer::new(0x1234, 500);
+        let old = p
+            .packetize_jpeg(&create_test_jpeg(2000), 640, 480, 9000)
+            .unwrap();
+        let new = p
+            .packetize_jpeg(&create_test_jpeg(2000), 640, 480, 12000)
+            .unwrap();
+
+        d.push(&old[0]).unwrap();
+        d.push(&new[0]).unwrap();
+        assert!(matches!(d.push(&old[1]), Err(ReceiverError::Late(9000))));
+
+        let mut frame = None;
+        for pkt in &new[1..] {
+            frame = d.push(pkt).unwrap();
+        }
+        assert_eq!(frame.expect("newer frame completes").timestamp, 12000);
+        let stats = d.get_stats();
+        assert_eq!((stats.rejected_late, stats.frames_incomplete), (1, 1));
+    }
+
+    #[test]
+    fn test_verifies_frame_info() {
+        let p = RtpPacketizer::new(0x1234, 500).with_frame_info(Some(1));
+        let pkts = p
+            .packetize_frame(&create_test_jpeg(2000), 77, 640, 480, 9000)
+            .unwrap();
+
+        let mut d = Depacketizer::new(ReceiverConfig {
+            frame_info_id: Some(1),
+            ..Default::default()
+        });
+        let mut frame = None;
+        for pkt in &pkts {
+            frame = d.push(pkt).unwrap();
+        }
+        let frame = frame.expect("frame completes");
+        assert_eq!(frame.frame_id, Some(77));
+        assert_eq!(frame.scan_data.len(), 2000);
+        assert_eq!(d.get_stats().frames_verified, 1);
+
+        // Without the ID configured the extension is skipped, not verified
+        let mut d = Depacketizer::new(ReceiverConfig::default());
+        let frame = pkts.iter().map(|pkt| d.push(pkt).unwrap()).last().unwrap();
+        assert_eq!(frame.expect("frame completes").frame_id, None);
+    }
+
+    #[test]
+    fn test_detects_corrupt_reassembly() {
+        let p = RtpPacketizer::new(0x1234, 500).with_frame_info(Some(1));
+        let mut pkts: Vec<Vec<u8>> = p
+            .packetize_frame(&create_test_jpeg(2000), 5, 640, 480, 9000)
+            .unwrap()
+            .iter()
+            .map(|pkt| pkt.to_vec())
+            .collect();
+        // Flip a scan byte in the middle packet
+        let middle = pkts.len() / 2;
+        let last = pkts[middle].len() - 1;
+        pkts[middle][last] ^= 0xFF;
+
+        let mut d = Depacketizer::new(ReceiverConfig {
+            frame_info_id: Some(1),
+            ..Default::default()
+        });
+        let (last_pkt, rest) = pkts.split_last().unwrap();
+        for pkt in rest {
+            d.push(pkt).unwrap();
+        }
+        assert_eq!(
+            d.push(last_pkt).unwrap_err(),
+            ReceiverError::Corrupt { frame_id: 5 }
+        );
+        assert_eq!(d.get_stats().frames_corrupt, 1);
+    }
+
+    #[test]
+    fn test_replay_window_wraps() {
+        let mut w = ReplayWindow::default();
+        assert!(w.accept(65534));
+        assert!(w.accept(65535));
+        assert!(w.accept(0));
+        assert!(!w.accept(65535));
+        assert!(w.accept(1));
+    }
+
+    #[test]
+    fn test_replay_window_resyncs_after_restart() {
+        let mut w = ReplayWindow::default();
+        for seq in 5000..5010 {
+            assert!(w.accept(seq));
+        }
+        // A restarted sender: the first packet is dropped, the next one
+        // re-initialises the window
+        assert!(!w.accept(10));
+        assert!(w.accept(11));
+        assert!(w.accept(12));
+        assert!(!w.accept(12));
+
+        // A lone stale packet doesn't
+        assert!(!w.accept(40000));
+        assert!(w.accept(13));
+    }
+}
@@ -0,0 +1,663 @@
+//! Frame recordings for reproducing field issues
+//!
+//! [`Recorder`] writes the exact JPEG frames a capture delivered, with their
+//! ids and capture times, to a `.frames` file. [`Replay`] plays one back as a
+//! frame source with the original timing (or at an exact rate, see
+//! [`Replay::with_fps`]), so a stream that misbehaved on a device can be
+//! pushed through the packetizer and streamer again, byte for byte, on any
+//! machine.
+//!
+//! File format: the magic `MJPGFRM1`, then one record per frame:
+//!
+//! | field  | type   | meaning                                   |
+//! |--------|--------|-------------------------------------------|
+//! | id     | u64 BE | [`Frame::id`] as captured (gaps = drops)  |
+//! | offset | u64 BE | microseconds since the first frame        |
+//! | length | u32 BE | size of the JPEG data that follows        |
+//! | data   | bytes  | complete JPEG (SOI..EOI)                  |
+
+use crate::capture::CaptureStats;
+use crate::error::ErrorCode;
+use crate::frame::Frame;
+use crate::pacing::FramePacer;
+use bytes::Bytes;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{mpsc as std_mpsc, Arc};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tokio::runtime::Handle;
+use tokio::sync::mpsc;
+use tracing::{debug, info, warn};
+
+/// First bytes of every `.frames` file
+pub const MAGIC: &[u8; 8] = b"MJPGFRM1";
+
+/// Largest frame a recording may hold; anything bigger is a corrupt length
+pub const MAX_FRAME_SIZE: u32 = 16 * 1024 * 1024;
+
+/// Frames the recorder buffers while the disk catches up
+const RECORD_QUEUE: usize = 30;
+
+#[derive(Error, Debug)]
+pub enum RecordingError {
+    #[error("recording I/O error: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("not a frame recording (bad magic)")]
+    BadMagic,
+
+    #[error("recording truncated in the middle of a frame")]
+    Truncated,
+
+    #[error("recorded frame of {0} bytes exceeds the {MAX_FRAME_SIZE} byte limit")]
+    FrameTooLarge(u32),
+
+    #[error("recording contains no frames")]
+    Empty,
+
+    #[error("replay already running")]
+    AlreadyRunning,
+}
+
+impl RecordingError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            RecordingError::Io(_) => ErrorCode::Io,
+            RecordingError::BadMagic
+            | RecordingError::Truncated
+            | RecordingError::FrameTooLarge(_)
+            | RecordingError::Empty => ErrorCode::InvalidInput,
+            RecordingError::AlreadyRunning => ErrorCode::AlreadyRunning,
+        }
+    }
+}
+
+/// One frame read back from a recording
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordedFrame {
+    pub id: u64,
+    /// Capture time relative to the first frame of the recording
+    pub offset: Duration,
+    pub data: Bytes,
+}
+
+/// Writes `.frames` records to any writer
+pub struct FrameWriter<W: Write> {
+    writer: W,
+    first_capture: Option<Instant>,
+    frames: u64,
+}
+
+impl FrameWriter<BufWriter<File>> {
+    /// Creates (or truncates) a recording file
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self, RecordingError> {
+        Self::new(BufWriter::new(File::create(path)?))
+    }
+}
+
+impl<W: Write> FrameWriter<W> {
+    pub fn new(mut writer: W) -> Result<Self, RecordingError> {
+        writer.write_all(MAGIC)?;
+        Ok(Self {
+            writer,
+            first_capture: None,
+            frames: 0,
+        })
+    }
+
+    /// Appends a frame; its offset is taken from `captured_at`
+    pub fn write_frame(&mut self, frame: &Frame) -> Result<(), RecordingError> {
+        let first = *self.first_capture.get_or_insert(frame.captured_at);
+        let offset = frame.captured_at.saturating_duration_since(first);
+        self.write_record(frame.id, offset, &frame.data)
+    }
+
+    /// Appends a frame with an explicit offset
+    pub fn write_record(
+        &mut self,
+        id: u64,
+        offset: Duration,
+        data: &[u8],
+    ) -> Result<(), RecordingError> {
+        let len = u32::try_from(data.len())
+            .ok()
+            .filter(|&len| len <= MAX_FRAME_SIZE)
+            .ok_or(RecordingError::FrameTooLarge(
+                data.len().min(u32::MAX as usize) as u32,
+            ))?;
+
+        self.writer.write_all(&id.to_be_bytes())?;
+        self.writer
+            .write_all(&(offset.as_micros() as u64).to_be_bytes())?;
+        self.writer.write_all(&len.to_be_bytes())?;
+        self.writer.write_all(data)?;
+        self.frames += 1;
+        Ok(())
+    }
+
+    /// Frames written so far
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    /// Flushes and returns the underlying writer
+    pub fn finish(mut self) -> Result<W, RecordingError> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Reads `.frames` records from any reader
+pub struct FrameReader<R: Read> {
+    reader: R,
+}
+
+impl FrameReader<BufReader<File>> {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, RecordingError> {
+        Self::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read> FrameReader<R> {
+    /// Checks the magic and positions the reader at the first frame
+    pub fn new(mut reader: R) -> Result<Self, RecordingError> {
+        let mut magic = [0u8; 8];
+        match reader.read_exact(&mut magic) {
+            Ok(()) if &magic == MAGIC => Ok(Self { reader }),
+            Ok(()) => Err(RecordingError::BadMagic),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Err(RecordingError::BadMagic),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Next frame, or `None` at a clean end of file
+    pub fn next_frame(&mut self) -> Result<Option<RecordedFrame>, RecordingError> {
+        let mut header = [0u8; 20];
+        let mut filled = 0;
+        while filled < header.len() {
+            match self.reader.read(&mut header[filled..]) {
+                Ok(0) if filled == 0 => return Ok(None),
+                Ok(0) => return Err(RecordingError::Truncated),
+                Ok(n) => filled += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        let id = u64::from_be_bytes(header[0..8].try_into().unwrap());
+        let offset = Duration::from_micros(u64::from_be_bytes(header[8..16].try_into().unwrap()));
+        let len = u32::from_be_bytes(header[16..20].try_into().unwrap());
+        if len > MAX_FRAME_SIZE {
+            return Err(RecordingError::FrameTooLarge(len));
+        }
+
+        let mut data = vec![0u8; len as usize];
+        self.reader
+            .read_exact(&mut data)
+            .map_err(|e| match e.kind() {
+                io::ErrorKind::UnexpectedEof => RecordingError::Truncated,
+                _ => e.into(),
+            })?;
+
+        Ok(Some(RecordedFrame {
+            id,
+            offset,
+            data: Bytes::from(data),
+        }))
+    }
+}
+
+impl<R: Read> Iterator for FrameReader<R> {
+    type Item = Result<RecordedFrame, RecordingError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_frame().transpose()
+    }
+}
+
+/// Tees frames to a `.frames` file without blocking the stream
+///
+/// Frames are handed to a writer thread through a bounded queue. When the
+/// disk falls behind, frames are left out of the recording (counted in
+/// [`Recorder::dropped`]) rather than delaying the stream; the recorded ids
+/// still show where.
+pub struct Recorder {
+    path: PathBuf,
+    tx: Option<std_mpsc::SyncSender<Frame>>,
+    writer: Option<JoinHandle<Result<u64, RecordingError>>>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl Recorder {
+    /// Creates the file and starts the writer thread
+    pub fn start<P: AsRef<Path>>(path: P) -> Result<Self, RecordingError> {
+        Self::start_with(path, Vec::new())
+    }
+
+    /// Like [`start`](Self::start), writing `backlog` (e.g. frames buffered
+    /// before an event) ahead of anything recorded
+    pub fn start_with<P: AsRef<Path>>(
+        path: P,
+        backlog: Vec<Frame>,
+    ) -> Result<Self, RecordingError> {
+        let path = path.as_ref().to_path_buf();
+        let mut writer = FrameWriter::create(&path)?;
+        let (tx, rx) = std_mpsc::sync_channel::<Frame>(RECORD_QUEUE);
+
+        let handle = std::thread::Builder::new()
+            .name("frame-recorder".to_string())
+            .spawn(move || {
+                for frame in backlog.into_iter().chain(rx) {
+                    writer.write_frame(&frame)?;
+                }
+                let frames = writer.frames();
+                writer.finish()?;
+                Ok(frames)
+            })?;
+
+        info!(path = %path.display(), "Recording frames");
+        Ok(Self {
+            path,
+            tx: Some(tx),
+            writer: Some(handle),
+            dropped: Arc::new(AtomicU64::new(0)),
+        })
+    }
+
+    /// Queues a frame for writing. Never blocks.
+    pub fn record(&self, frame: &Frame) {
+        let Some(tx) = &self.tx else { return };
+        match tx.try_send(frame.clone()) {
+            Ok(()) => {}
+            Err(std_mpsc::TrySendError::Full(_)) => {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+            Err(std_mpsc::TrySendError::Disconnected(_)) => {
+                // The writer thread failed; finish() reports why
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Frames left out because the writer was behind or had failed
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Writes out queued frames, closes the file and returns how many
+    /// frames it holds
+    pub fn finish(mut self) -> Result<u64, RecordingError> {
+        let frames = self.close()?;
+        info!(
+            path = %self.path.display(),
+            frames,
+            dropped = self.dropped(),
+            "Recording finished"
+        );
+        Ok(frames)
+    }
+
+    fn close(&mut self) -> Result<u64, RecordingError> {
+        self.tx.take();
+        match self.writer.take() {
+            Some(handle) => handle
+                .join()
+                .unwrap_or_else(|_| Err(io::Error::other("recorder thread panicked").into())),
+            None => Ok(0),
+        }
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        if let Err(e) = self.close() {
+            warn!(path = %self.path.display(), error = %e, "Recording incomplete");
+        }
+    }
+}
+
+/// Frame source that plays back a `.frames` recording
+///
+/// Stands in for [`crate::Capture`]: frames arrive on the channel returned
+/// by [`Replay::start`] at their recorded offsets, with their recorded ids,
+/// the offsets as PTS and `captured_at` set to the moment they are replayed. Unlike a camera,
+/// replay waits for the consumer instead of dropping, so the same recording
+/// always produces the same frame sequence.
+pub struct Replay {
+    path: PathBuf,
+    looping: bool,
+    fps: Option<u32>,
+    running: Arc<AtomicBool>,
+    frame_count: Arc<AtomicU64>,
+}
+
+impl Replay {
+    /// Opens the recording to check it is one. With `looping`, playback
+    /// starts over at the end, shifting ids so they keep increasing.
+    pub fn new<P: AsRef<Path>>(path: P, looping: bool) -> Result<Self, RecordingError> {
+        let path = path.as_ref().to_path_buf();
+        if FrameReader::open(&path)?.next_frame()?.is_none() {
+            return Err(RecordingError::Empty);
+        }
+        Ok(Self {
+            path,
+            looping,
+            fps: None,
+            running: Arc::new(AtomicBool::new(false)),
+            frame_count: Arc::new(AtomicU64::new(0)),
+        })
+    }
+
+    /// Plays frames at exactly `fps` on the 90 kHz RTP clock instead of at
+    /// their recorded offsets, with PTS to match; the recorded offsets
+    /// carry the original jitter and drops
+    pub fn with_fps(mut self, fps: Option<u32>) -> Self {
+        self.fps = fps;
+        self
+    }
+
+    /// Starts playback on a dedicated thread
+    pub async fn start(&mut self) -> Result<mpsc::Receiver<Frame>, RecordingError> {
+        if self.running.swap(true, Ordering::Relaxed) {
+            return Err(RecordingError::AlreadyRunning);
+        }
+
+        let (frame_tx, frame_rx) = mpsc::channel(5);
+        let path = self.path.clone();
+        let looping = self.looping;
+        let running = Arc::clone(&self.running);
+        let frame_count = Arc::clone(&self.frame_count);
+        let pacer = self
+            .fps
+            .map(|fps| (Handle::current(), FramePacer::new(fps)));
+
+        let spawned = std::thread::Builder::new()
+            .name("frame-replay".to_string())
+            .spawn(move || {
+                if let Err(e) = play(&path, looping, pacer, &frame_tx, &running, &frame_count) {
+                    warn!(path = %path.display(), error = %e, "Replay stopped");
+                }
+                running.store(false, Ordering::Relaxed);
+            });
+        if let Err(e) = spawned {
+            self.running.store(false, Ordering::Relaxed);
+            return Err(e.into());
+        }
+
+        info!(
+            path = %self.path.display(),
+            looping = self.looping,
+            fps = ?self.fps,
+            "Replaying recorded frames"
+        );
+        Ok(frame_rx)
+    }
+
+    /// Stops playback after the frame in flight
+    pub async fn stop(&mut self) -> Result<(), RecordingError> {
+        if self.running.swap(false, Ordering::Relaxed) {
+            info!(
+                frames = self.frame_count.load(Ordering::Relaxed),
+                "Replay stopped"
+            );
+        }
+        Ok(())
+    }
+
+    pub fn get_stats(&self) -> CaptureStats {
+        CaptureStats {
+            frames_captured: self.frame_count.load(Ordering::Relaxed),
+            is_running: self.is_running(),
+            ..Default::default()
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running.load(Ordering::Relaxed)
+    }
+}
+
+/// Plays the recording into `frame_tx`; `pacer`, when set, is ticked on the
+/// runtime's timer in place of the recorded offsets
+fn play(
+    path: &Path,
+    looping: bool,
+    mut pacer: Option<(Handle, FramePacer)>,
+    frame_tx: &mpsc::Sender<Frame>,
+    running: &AtomicBool,
+    frame_count: &AtomicU64,
+) -> Result<(), RecordingError> {
+    let mut base = Instant::now();
+    let mut pts_shift = Duration::ZERO;
+    let mut id_shift = 0u64;
+    let mut pass = 0u64;
+
+    loop {
+        let mut first_id = None;
+        let mut last = None;
+        let mut interval = Duration::ZERO;
+
+        for frame in FrameReader::open(path)? {
+            let frame = frame?;
+            if !running.load(Ordering::Relaxed) {
+                return Ok(());
+            }
+
+            first_id.get_or_insert(frame.id);
+            if let Some((_, previous)) = last {
+                interval = frame.offset.saturating_sub(previous);
+            }
+            last = Some((frame.id, frame.offset));
+
+            let pts = match pacer {
+                Some((ref runtime, ref mut pacer)) => {
+                    let slot = runtime.block_on(pacer.tick());
+                    pacer.offset(slot)
+                }
+                None => {
+                    let deadline = base + frame.offset;
+                    if let Some(wait) = deadline.checked_duration_since(Instant::now()) {
+                        std::thread::sleep(wait);
+                    }
+                    pts_shift + frame.offset
+                }
+            };
+            if frame_tx
+                .blocking_send(Frame::new(frame.id + id_shift, frame.data).with_pts(Some(pts)))
+                .is_err()
+            {
+                debug!("Replay receiver dropped");
+                return Ok(());
+            }
+            frame_count.fetch_add(1, Ordering::Relaxed);
+        }
+
+        pass += 1;
+        let Some((last_id, last_offset)) = last else {
+            return Err(RecordingError::Empty);
+        };
+        if !looping {
+            info!(
+                frames = frame_count.load(Ordering::Relaxed),
+                "Replay finished"
+            );
+            return Ok(());
+        }
+
+        // Next pass starts one frame interval after the last frame, with ids
+        // continuing where this pass left off
+        debug!(pass, "Replay looping");
+        base += last_offset + interval;
+        pts_shift += last_offset + interval;
+        id_shift += last_id.saturating_sub(first_id.unwrap_or(last_id)) + 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_round_trip() {
+        let mut writer = FrameWriter::new(Vec::new()).unwrap();
+        let start = Instant::now();
+        let frames = [
+            Frame {
+                captured_at: start,
+                ..Frame::new(3, Bytes::from_static(&[0xFF, 0xD8, 1, 0xFF, 0xD9]))
+            },
+            Frame {
+                captured_at: start + Duration::from_millis(66),
+                ..Frame::new(5, Bytes::from_static(&[0xFF, 0xD8, 2, 2, 0xFF, 0xD9]))
+            },
+        ];
+        for frame in &frames {
+            writer.write_frame(frame).unwrap();
+        }
+        assert_eq!(writer.frames(), 2);
+        let bytes = writer.finish().unwrap();
+
+        let read: Vec<_> = FrameReader::new(Cursor::new(bytes))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(read.len(), 2);
+        assert_eq!((read[0].id, read[0].offset), (3, Duration::ZERO));
+        assert_eq!((read[1].id, read[1].offset), (5, Duration::from_millis(66)));
+        assert_eq!(read[1].data, frames[1].data);
+    }
+
+    #[test]
+    fn test_rejects_bad_files() {
+        assert!(matches!(
+            FrameReader::new(Cursor::new(b"NOTFRAME".to_vec())),
+            Err(RecordingError::BadMagic)
+        ));
+        assert!(matches!(
+            FrameReader::new(Cursor::new(b"MJP".to_vec())),
+            Err(RecordingError::BadMagic)
+        ));
+
+        let mut writer = FrameWriter::new(Vec::new()).unwrap();
+        writer
+            .write_record(0, Duration::ZERO, &[1, 2, 3, 4])
+            .unwrap();
+        let bytes = writer.finish().unwrap();
+
+        // Cut inside the header, then inside the data
+        for cut in [MAGIC.len() + 10, bytes.len() - 1] {
+            let mut reader = FrameReader::new(Cursor::new(bytes[..cut].to_vec())).unwrap();
+            assert!(matches!(
+                reader.next_frame(),
+                Err(RecordingError::Truncated)
+            ));
+        }
+
+        let mut oversize = MAGIC.to_vec();
+        oversize.extend([0u8; 16]);
+        oversize.extend((MAX_FRAME_SIZE + 1).to_be_bytes());
+        let mut reader = FrameReader::new(Cursor::new(oversize)).unwrap();
+        assert!(matches!(
+            reader.next_frame(),
+            Err(RecordingError::FrameTooLarge(_))
+        ));
+    }
+
+    #[test]
+    fn test_recorder_writes_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("recorder.frames");
+        let recorder = Recorder::start(&path).unwrap();
+        for id in 0..10 {
+            recorder.record(&Frame::new(id, Bytes::from(vec![id as u8; 100])));
+        }
+        assert_eq!(recorder.finish().unwrap(), 10);
+
+        let ids: Vec<_> = FrameReader::open(&path)
+            .unwrap()
+            .map(|frame| frame.unwrap().id)
+            .collect();
+        assert_eq!(ids, (0..10).collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn test_replay_keeps_timing_and_loops() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("replay.frames");
+        let mut writer = FrameWriter::create(&path).unwrap();
+        for (id, ms) in [(10, 0), (11, 40), (13, 80)] {
+            writer
+                .write_record(id, Duration::from_millis(ms), &[id as u8; 8])
+                .unwrap();
+        }
+        writer.finish().unwrap();
+
+        let mut replay = Replay::new(&path, true).unwrap();
+        let mut rx = replay.start().await.unwrap();
+        assert!(matches!(
+            replay.start().await,
+            Err(RecordingError::AlreadyRunning)
+        ));
+
+        let start = Instant::now();
+        let mut received = Vec::new();
+        let mut last_pts = None;
+        for _ in 0..6 {
+            let frame = rx.recv().await.unwrap();
+            received.push((frame.id, frame.data[0], start.elapsed()));
+            last_pts = frame.pts;
+        }
+        replay.stop().await.unwrap();
+
+        let ids: Vec<_> = received.iter().map(|&(id, _, _)| id).collect();
+        assert_eq!(ids, [10, 11, 13, 14, 15, 17]);
+        assert_eq!(received[5].1, 13);
+        assert_eq!(last_pts, Some(Duration::from_millis(200)));
+        // Second pass starts one interval (40ms) after the last frame (80ms)
+        assert!(received[2].2 >= Duration::from_millis(75));
+        assert!(received[3].2 >= Duration::from_millis(115));
+        assert!(received[5].2 >= Duration::from_millis(195));
+    }
+
+    #[tokio::test]
+    async fn test_replay_at_fps() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("replay.frames");
+        let mut writer = FrameWriter::create(&path).unwrap();
+        // Recorded with jitter and a long gap
+        for (id, ms) in [(0, 0), (1, 45), (2, 70), (3, 500)] {
+            writer
+                .write_record(id, Duration::from_millis(ms), &[id as u8; 8])
+                .unwrap();
+        }
+        writer.finish().unwrap();
+
+        let mut replay = Replay::new(&path, true).unwrap().with_fps(Some(50));
+        let mut rx = replay.start().await.unwrap();
+        let start = Instant::now();
+        let mut pts = Vec::new();
+        for _ in 0..8 {
+            pts.push(rx.recv().await.unwrap().pts.unwrap().as_millis());
+        }
+        let elapsed = start.elapsed();
+        replay.stop().await.unwrap();
+
+        assert_eq!(pts, [0, 20, 40, 60, 80, 100, 120, 140]);
+        assert!(elapsed >= Duration::from_millis(135));
+        assert!(elapsed < Duration::from_millis(400));
+    }
+
+    #[test]
+    fn test_replay_rejects_empty_recording() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("empty.frames");
+        FrameWriter::create(&path).unwrap().finish().unwrap();
+        let result = Replay::new(&path, false);
+        assert!(matches!(result, Err(RecordingError::Empty)));
+    }
+}
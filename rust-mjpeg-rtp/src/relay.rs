@@ -0,0 +1,356 @@
+//! RTP/JPEG repeater
+//!
+//! With `[mjpeg-rtp.relay]` enabled, RTP/JPEG packets received on `listen`
+//! are sent on to every destination without touching their payload, so a
+//! wired gateway can fan out a WiFi camera's stream at no encoding cost.
+//! Only the RTP header is rewritten:
+//!
+//! - The SSRC becomes the configured one, or the first one received, so
+//!   receivers see one source even when the sender restarts.
+//! - Sequence numbers and timestamps keep the sender's spacing, but continue
+//!   from the last packet sent when the sender changes, so receivers see no
+//!   jump.
+//!
+//! Packets that aren't RTP/JPEG are dropped. While a sender is active, other
+//! senders are ignored; a new one is taken over once the current one has
+//! been silent for [`SOURCE_TIMEOUT`]. RTCP is not relayed.
+
+use crate::config::RelayConfig;
+use crate::log_limited;
+use crate::ratelimit::LogLimiter;
+use crate::rtp::{RtpHeader, RTP_PAYLOAD_TYPE_JPEG, RTP_VERSION};
+use crate::task::CancellationToken;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+use tokio::net::UdpSocket;
+use tracing::info;
+
+/// Silence after which another sender may take over
+pub const SOURCE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Timestamp gap put between two senders: one frame at 30 fps
+const SWITCH_TIMESTAMP_GAP: u32 = 3000;
+
+/// Largest datagram relayed (jumbo frames included)
+const MAX_PACKET: usize = 9000;
+
+/// Why a packet was not relayed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rejected {
+    /// Not an RTP/JPEG packet
+    NotRtpJpeg,
+    /// From another sender while the current one is active
+    OtherSource(u32),
+}
+
+/// Packet counters
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RelayStats {
+    pub packets_received: u64,
+    pub packets_relayed: u64,
+    pub rejected_not_rtp_jpeg: u64,
+    pub rejected_other_source: u64,
+    /// Times a new sender was taken over
+    pub source_switches: u64,
+    pub send_errors: u64,
+}
+
+/// Sender currently relayed, and how its header fields map to ours
+#[derive(Debug, Clone, Copy)]
+struct Source {
+    ssrc: u32,
+    seq_offset: u16,
+    ts_offset: u32,
+    last_seen: Instant,
+}
+
+/// Rewrites RTP headers in place so the relayed stream stays continuous
+#[derive(Debug)]
+pub struct Rewriter {
+    /// SSRC sent; the first sender's when not configured
+    ssrc: Option<u32>,
+    source: Option<Source>,
+    /// Sequence number and timestamp of the last packet sent
+    last_sent: Option<(u16, u32)>,
+}
+
+impl Rewriter {
+    pub fn new(ssrc: Option<u32>) -> Self {
+        Self {
+            ssrc,
+            source: None,
+            last_sent: None,
+        }
+    }
+
+    /// Rewrites the header of `packet` received at `now`; the payload is
+    /// left alone. Returns whether a new sender was taken over.
+    pub fn rewrite(&mut self, packet: &mut [u8], now: Instant) -> Result<bool, Rejected> {
+        let header = RtpHeader::from_bytes(packet).ok_or(Rejected::NotRtpJpeg)?;
+        if header.version != RTP_VERSION || header.payload_type != RTP_PAYLOAD_TYPE_JPEG {
+            return Err(Rejected::NotRtpJpeg);
+        }
+
+        let switched = match self.source {
+            Some(ref mut source) if source.ssrc == header.ssrc => {
+                source.last_seen = now;
+                false
+            }
+            Some(source) if now.duration_since(source.last_seen) < SOURCE_TIMEOUT => {
+                return Err(Rejected::OtherSource(header.ssrc));
+            }
+            _ => {
+                // Carry on from the last packet sent, as if the new sender's
+                // first packet followed it
+                let (seq_offset, ts_offset) = match self.last_sent {
+                    Some((seq, ts)) => (
+                        seq.wrapping_add(1).wrapping_sub(header.sequence_number),
+                        ts.wrapping_add(SWITCH_TIMESTAMP_GAP)
+                            .wrapping_sub(header.timestamp),
+                    ),
+                    None => (0, 0),
+                };
+                let switched = self.source.is_some();
+                self.source = Some(Source {
+                    ssrc: header.ssrc,
+                    seq_offset,
+                    ts_offset,
+                    last_seen: now,
+                });
+                switched
+            }
+        };
+
+        let source = self.source.expect("source was just set");
+        let ssrc = *self.ssrc.get_or_insert(header.ssrc);
+        let seq = header.sequence_number.wrapping_add(source.seq_offset);
+        let timestamp = header.timestamp.wrapping_add(source.ts_offset);
+        packet[2..4].copy_from_slice(&seq.to_be_bytes());
+        packet[4..8].copy_from_slice(&timestamp.to_be_bytes());
+        packet[8..12].copy_from_slice(&ssrc.to_be_bytes());
+        self.last_sent = Some((seq, timestamp));
+        Ok(switched)
+    }
+}
+
+/// Receives on one socket and repeats to every destination
+pub struct Relay {
+    socket: UdpSocket,
+    destinations: Vec<SocketAddr>,
+    rewriter: Rewriter,
+    stats: RelayStats,
+}
+
+impl Relay {
+    /// Binds the receive socket, which packets are also sent from
+    pub async fn bind(config: &RelayConfig) -> std::io::Result<Self> {
+        Ok(Self {
+            socket: UdpSocket::bind(config.listen).await?,
+            destinations: config.destinations.clone(),
+            rewriter: Rewriter::new(config.ssrc),
+            stats: RelayStats::default(),
+        })
+    }
+
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    /// Relays until `token` is cancelled, logging the counters every
+    /// `stats_interval`
+    pub async fn run(mut self, token: CancellationToken, stats_interval: Duration) -> RelayStats {
+        if let Ok(addr) = self.socket.local_addr() {
+            info!(%addr, destinations = ?self.destinations, "Relay started");
+        }
+        let log = LogLimiter::default();
+        let mut stats_ticker = tokio::time::interval(stats_interval);
+        stats_ticker.tick().await;
+        let mut buf = vec![0u8; MAX_PACKET];
+        loop {
+            tokio::select! {
+                _ = token.cancelled() => break,
+                _ = stats_ticker.tick() => {
+                    let s = &self.stats;
+                    info!(
+                        received = s.packets_received,
+                        relayed = s.packets_relayed,
+                        not_rtp_jpeg = s.rejected_not_rtp_jpeg,
+                        other_source = s.rejected_other_source,
+                        switches = s.source_switches,
+                        send_errors = s.send_errors,
+                        "Relay stats"
+                    );
+                }
+                received = self.socket.recv_from(&mut buf) => match received {
+                    Ok((len, from)) => self.relay(&mut buf[..len], from, &log).await,
+                    Err(e) => {
+                        log_limited!(log, "recv", warn, error = %e, "Failed to receive");
+                    }
+                },
+            }
+        }
+        info!(relayed = self.stats.packets_relayed, "Relay stopped");
+        self.stats
+    }
+
+    async fn relay(&mut self, packet: &mut [u8], from: SocketAddr, log: &LogLimiter) {
+        self.stats.packets_received += 1;
+        match self.rewriter.rewrite(packet, Instant::now()) {
+            Ok(switched) => {
+                if switched {
+                    self.stats.source_switches += 1;
+                    info!(%from, "Relaying a new sender");
+                }
+            }
+            Err(Rejected::NotRtpJpeg) => {
+                self.stats.rejected_not_rtp_jpeg += 1;
+                log_limited!(log, "not_rtp_jpeg", warn, %from, "Dropping non RTP/JPEG packet");
+                return;
+            }
+            Err(Rejected::OtherSource(ssrc)) => {
+                self.stats.rejected_other_source += 1;
+                log_limited!(
+                    log,
+                    "other_source",
+                    warn,
+                    %from,
+                    ssrc = %format!("{:#010x}", ssrc),
+                    "Dropping packet from a second sender"
+                );
+                return;
+            }
+        }
+
+        for dest in &self.destinations {
+            if let Err(e) = self.socket.send_to(packet, dest).await {
+                self.stats.send_errors += 1;
+                log_limited!(log, "send", warn, %dest, error = %e, "Failed to relay packet");
+            }
+        }
+        self.stats.packets_relayed += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rtp::RTP_HEADER_SIZE;
+
+    fn packet(ssrc: u32, seq: u16, timestamp: u32) -> Vec<u8> {
+        let header = RtpHeader {
+            version: RTP_VERSION,
+            padding: false,
+            extension: false,
+            csrc_count: 0,
+            marker: false,
+            payload_type: RTP_PAYLOAD_TYPE_JPEG,
+            sequence_number: seq,
+            timestamp,
+            ssrc,
+        };
+        let mut packet = header.to_bytes().to_vec();
+        packet.extend_from_slice(b"jpeg payload");
+        packet
+    }
+
+    fn fields(packet: &[u8]) -> (u32, u16, u32) {
+        let header = RtpHeader::from_bytes(packet).unwrap();
+        (header.ssrc, header.sequence_number, header.timestamp)
+    }
+
+    #[test]
+    fn test_first_sender_passes_unchanged() {
+        let mut rewriter = Rewriter::new(None);
+        let now = Instant::now();
+        let mut p = packet(0xAAAA, 100, 9000);
+        let original = p.clone();
+        assert_eq!(rewriter.rewrite(&mut p, now), Ok(false));
+        assert_eq!(p, original);
+    }
+
+    #[test]
+    fn test_configured_ssrc_replaces_the_senders() {
+        let mut rewriter = Rewriter::new(Some(0x1234));
+        let mut p = packet(0xAAAA, 7, 9000);
+        rewriter.rewrite(&mut p, Instant::now()).unwrap();
+        assert_eq!(fields(&p), (0x1234, 7, 9000));
+        assert_eq!(&p[RTP_HEADER_SIZE..], b"jpeg payload");
+    }
+
+    #[test]
+    fn test_new_sender_continues_the_stream() {
+        let mut rewriter = Rewriter::new(None);
+        let start = Instant::now();
+        let mut p = packet(0xAAAA, 65535, 90_000);
+        rewriter.rewrite(&mut p, start).unwrap();
+
+        // A second sender is ignored while the first is active
+        let mut other = packet(0xBBBB, 10, 5);
+        assert_eq!(
+            rewriter.rewrite(&mut other, start + Duration::from_millis(500)),
+            Err(Rejected::OtherSource(0xBBBB))
+        );
+
+        // ... and taken over once it has gone quiet
+        let later = start + SOURCE_TIMEOUT;
+        let mut p = packet(0xBBBB, 10, 5);
+        assert_eq!(rewriter.rewrite(&mut p, later), Ok(true));
+        assert_eq!(fields(&p), (0xAAAA, 0, 90_000 + SWITCH_TIMESTAMP_GAP));
+        let mut p = packet(0xBBBB, 11, 3005);
+        assert_eq!(rewriter.rewrite(&mut p, later), Ok(false));
+        assert_eq!(fields(&p), (0xAAAA, 1, 93_000 + SWITCH_TIMESTAMP_GAP));
+    }
+
+    #[test]
+    fn test_rejects_other_payloads() {
+        let mut rewriter = Rewriter::new(None);
+        let now = Instant::now();
+        let mut short = vec![0x80, 26, 0, 1];
+        assert_eq!(rewriter.rewrite(&mut short, now), Err(Rejected::NotRtpJpeg));
+        let mut h264 = packet(0xAAAA, 1, 0);
+        h264[1] = 96;
+        assert_eq!(rewriter.rewrite(&mut h264, now), Err(Rejected::NotRtpJpeg));
+        let mut rtcp = packet(0xAAAA, 1, 0);
+        rtcp[0] = 0x40;
+        assert_eq!(rewriter.rewrite(&mut rtcp, now), Err(Rejected::NotRtpJpeg));
+    }
+
+    #[tokio::test]
+    async fn test_relays_to_every_destination() {
+        let first = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let second = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let config = RelayConfig {
+            enabled: true,
+            listen: "127.0.0.1:0".parse().unwrap(),
+            destinations: vec![first.local_addr().unwrap(), second.local_addr().unwrap()],
+            ssrc: Some(0x5151),
+        };
+        let relay = Relay::bind(&config).await.unwrap();
+        let relay_addr = relay.local_addr().unwrap();
+        let token = CancellationToken::new();
+        let task = tokio::spawn(relay.run(token.clone(), Duration::from_secs(60)));
+
+        let sender = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        sender.send_to(b"not rtp", relay_addr).await.unwrap();
+        sender
+            .send_to(&packet(0xAAAA, 42, 1000), relay_addr)
+            .await
+            .unwrap();
+
+        let mut buf = [0u8; 64];
+        for socket in [&first, &second] {
+            let len = tokio::time::timeout(Duration::from_secs(2), socket.recv(&mut buf))
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(fields(&buf[..len]), (0x5151, 42, 1000));
+            assert_eq!(&buf[RTP_HEADER_SIZE..len], b"jpeg payload");
+        }
+
+        token.cancel();
+        let stats = task.await.unwrap();
+        assert_eq!(stats.packets_received, 2);
+        assert_eq!(stats.packets_relayed, 1);
+        assert_eq!(stats.rejected_not_rtp_jpeg, 1);
+    }
+}
@@ -0,0 +1,209 @@
+//! Drift between frame-count timestamps and the capture clock
+//!
+//! Frame-count timestamps advance by exactly one nominal interval per frame
+//! slot, so a camera delivering 29.97 fps against a configured 30 runs
+//! 3.6 s an hour behind its RTP clock, and long recordings drift out of sync
+//! with audio or other cameras. [`ClockDrift`] compares each slot's nominal
+//! time with the frame's capture time (PTS, or when it left the pipeline),
+//! filters out per-frame jitter, and once it has measured for
+//! [`SETTLE_SECS`] slews a correction towards the difference by at most
+//! [`MAX_SLEW`] of a frame interval per frame, so timestamps stay monotonic
+//! and playback speed barely changes.
+
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Largest correction per frame, as a share of the frame interval; a few
+/// times the 0.1% between 29.97 and 30 fps
+pub const MAX_SLEW: f64 = 0.005;
+
+/// Nominal time measured before correcting, so a burst of queued frames at
+/// startup isn't taken for drift
+pub const SETTLE_SECS: f64 = 10.0;
+
+/// Weight of each frame's measurement in the filtered drift
+const FILTER_WEIGHT: f64 = 1.0 / 64.0;
+
+/// A jump this far from the filtered drift is a discontinuity (a restarted
+/// camera or sender) rather than drift; measuring starts over from there
+const RESYNC_THRESHOLD_SECS: f64 = 2.0;
+
+/// Snapshot of the measured drift
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ClockDriftStats {
+    /// Nominal frame-count time minus capture time, filtered; negative when
+    /// the camera is slower than the configured frame rate
+    pub drift_ms: f64,
+
+    /// Drift rate since measuring started, in parts per million
+    pub drift_ppm: f64,
+
+    /// Correction currently added to frame-count timestamps
+    pub correction_ms: f64,
+
+    /// Times measuring started over after a jump
+    pub resyncs: u64,
+}
+
+/// Drift measurement and correction state, see the module docs
+#[derive(Debug, Default)]
+pub(crate) struct ClockDrift {
+    /// Slot and capture time (seconds) that nominal time is measured from
+    anchor: Option<(u64, f64)>,
+    /// First slot measured, for the drift rate
+    first_slot: u64,
+    /// Filtered drift, seconds
+    drift: f64,
+    /// Seconds the slot's nominal time is moved back by
+    correction: f64,
+    /// Nominal time measured over, seconds
+    span: f64,
+    resyncs: u64,
+}
+
+impl ClockDrift {
+    /// Measures the frame in `slot`, captured at `captured`, and returns the
+    /// correction for its timestamp in seconds
+    pub fn update(&mut self, slot: u64, captured: Duration, interval: f64) -> f64 {
+        let captured = captured.as_secs_f64();
+        let (anchor_slot, anchor_time) = match self.anchor {
+            Some((anchor_slot, anchor_time)) if slot >= anchor_slot => (anchor_slot, anchor_time),
+            Some(_) => self.resync(slot, captured),
+            None => {
+                self.first_slot = slot;
+                *self.anchor.insert((slot, captured))
+            }
+        };
+
+        let mut measured = (slot - anchor_slot) as f64 * interval - (captured - anchor_time);
+        if (measured - self.drift).abs() > RESYNC_THRESHOLD_SECS {
+            self.resync(slot, captured);
+            measured = self.drift;
+        }
+        self.drift += (measured - self.drift) * FILTER_WEIGHT;
+        self.span = slot.saturating_sub(self.first_slot) as f64 * interval;
+
+        let target = if self.span >= SETTLE_SECS {
+            self.drift
+        } else {
+            0.0
+        };
+        let max_step = interval * MAX_SLEW;
+        self.correction += (target - self.correction).clamp(-max_step, max_step);
+        self.correction
+    }
+
+    /// Measures on from `slot` so the filtered drift carries over unchanged
+    fn resync(&mut self, slot: u64, captured: f64) -> (u64, f64) {
+        self.resyncs += 1;
+        *self.anchor.insert((slot, captured + self.drift))
+    }
+
+    pub fn stats(&self) -> ClockDriftStats {
+        let ppm = if self.span > 0.0 {
+            self.drift / self.span * 1e6
+        } else {
+            0.0
+        };
+        ClockDriftStats {
+            drift_ms: self.drift * 1000.0,
+            drift_ppm: ppm,
+            correction_ms: -self.correction * 1000.0,
+            resyncs: self.resyncs,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INTERVAL: f64 = 1.0 / 30.0;
+
+    /// Runs `frames` frames from a camera at `fps` through `drift`
+    fn run(drift: &mut ClockDrift, start_slot: u64, fps: f64, frames: u64) -> f64 {
+        let mut correction = 0.0;
+        for i in 0..frames {
+            let captured = Duration::from_secs_f64(i as f64 / fps);
+            correction = drift.update(start_slot + i, captured, INTERVAL);
+        }
+        correction
+    }
+
+    #[test]
+    fn test_nominal_rate_has_no_drift() {
+        let mut drift = ClockDrift::default();
+        assert!(run(&mut drift, 0, 30.0, 10_000).abs() < 1e-9);
+        let stats = drift.stats();
+        assert!(
+            stats.drift_ms.abs() < 1e-6 && stats.drift_ppm.abs() < 1e-6,
+            "{:?}",
+            stats
+        );
+        assert_eq!(stats.resyncs, 0);
+    }
+
+    #[test]
+    fn test_slow_camera_is_corrected() {
+        // An hour at 29.97 fps: the slots run 3.6 s ahead of the camera
+        let mut drift = ClockDrift::default();
+        let frames = 29.97 * 3600.0;
+        let correction = run(&mut drift, 0, 29.97, frames as u64);
+
+        let stats = drift.stats();
+        assert!((stats.drift_ppm + 1000.0).abs() < 10.0, "{:?}", stats);
+        assert!((stats.drift_ms + 3600.0).abs() < 10.0, "{:?}", stats);
+        // Correction keeps up within the filter's lag
+        assert!(
+            (correction - stats.drift_ms / 1000.0).abs() < 0.01,
+            "{}",
+            correction
+        );
+        assert_eq!(stats.correction_ms, -correction * 1000.0);
+    }
+
+    #[test]
+    fn test_settles_before_correcting() {
+        // Queued frames arriving back to back look like the slots running
+        // ahead, but aren't corrected for
+        let mut drift = ClockDrift::default();
+        for slot in 0..(SETTLE_SECS / INTERVAL) as u64 {
+            assert_eq!(drift.update(slot, Duration::ZERO, INTERVAL), 0.0);
+        }
+        assert!(drift.stats().drift_ms > 0.0);
+    }
+
+    #[test]
+    fn test_correction_slews() {
+        let mut drift = ClockDrift::default();
+        let settled = (SETTLE_SECS / INTERVAL) as u64;
+        assert_eq!(run(&mut drift, 0, 30.0, settled), 0.0);
+        // Half a second late, within the resync threshold
+        let mut previous = 0.0;
+        for slot in settled..settled + 200 {
+            let captured = Duration::from_secs_f64(slot as f64 * INTERVAL + 0.5);
+            let correction = drift.update(slot, captured, INTERVAL);
+            assert!((correction - previous).abs() <= INTERVAL * MAX_SLEW + 1e-12);
+            previous = correction;
+        }
+        assert!(previous < 0.0);
+        assert_eq!(drift.stats().resyncs, 0);
+    }
+
+    #[test]
+    fn test_jumps_resync() {
+        let mut drift = ClockDrift::default();
+        run(&mut drift, 0, 29.97, 3000);
+        let before = drift.stats();
+
+        // The sender restarts its slot count while capture time carries on
+        let correction = drift.update(0, Duration::from_secs(100), INTERVAL);
+        assert_eq!(drift.stats().resyncs, 1);
+        assert!((drift.stats().drift_ms - before.drift_ms).abs() < 1e-9);
+        assert!((correction * 1000.0 + before.correction_ms).abs() < INTERVAL * MAX_SLEW * 1000.0);
+
+        // A capture clock jump (camera restart) too
+        drift.update(1, Duration::from_secs(10), INTERVAL);
+        assert_eq!(drift.stats().resyncs, 2);
+    }
+}
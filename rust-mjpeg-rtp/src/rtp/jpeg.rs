@@ -1,5 +1,30 @@
 //! JPEG-specific RTP header structures (RFC 2435)
 
+/// Largest width/height the 8-bit block fields can carry (255 * 8 pixels)
+pub const MAX_DIMENSION: u32 = 2040;
+
+/// Encodes frame dimensions as 8-pixel block counts for the JPEG header
+///
+/// Sizes that aren't multiples of 8 are rounded up: JPEG pads the last MCU
+/// row/column anyway, and rounding down makes decoders use a shorter line
+/// stride than the encoder did, which shears the image (e.g. 1366x768).
+///
+/// If either dimension exceeds [`MAX_DIMENSION`], both fields are sent as 0
+/// and the receiver has to take the real size from the SDP
+/// (see [`sdp_dimensions_attribute`]), as rtpjpegpay/rtpjpegdepay do.
+pub fn dimension_blocks(width: u32, height: u32) -> (u8, u8) {
+    if width > MAX_DIMENSION || height > MAX_DIMENSION {
+        (0, 0)
+    } else {
+        (width.div_ceil(8) as u8, height.div_ceil(8) as u8)
+    }
+}
+
+/// SDP attribute carrying full frame dimensions out of band
+pub fn sdp_dimensions_attribute(width: u32, height: u32) -> String {
+    format!("a=x-dimensions:{},{}", width, height)
+}
+
 /// JPEG type identifiers
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u8)]
@@ -46,13 +71,14 @@ pub struct JpegHeader {
 impl JpegHeader {
     /// Creates a new JPEG header
     pub fn new(fragment_offset: u32, width: u32, height: u32, jpeg_type: JpegType, q: u8) -> Self {
+        let (width_blocks, height_blocks) = dimension_blocks(width, height);
         Self {
             type_specific: 0,
             fragment_offset,
             jpeg_type,
             q,
-            width_blocks: (width / 8) as u8,
-            height_blocks: (height / 8) as u8,
+            width_blocks,
+            height_blocks,
         }
     }
 
@@ -126,6 +152,11 @@ impl JpegHeader {
     pub fn height(&self) -> u32 {
         self.height_blocks as u32 * 8
     }
+
+    /// True when the dimensions are carried out of band (SDP), not in the header
+    pub fn dimensions_out_of_band(&self) -> bool {
+        self.width_blocks == 0 || self.height_blocks == 0
+    }
 }
 
 #[cfg(test)]
@@ -153,6 +184,31 @@ mod tests {
         assert_eq!(header.height(), 1080);
     }
 
+    #[test]
+    fn test_max_dimension() {
+        assert_eq!(dimension_blocks(2040, 2040), (255, 255));
+
+        let header = JpegHeader::new(0, 3840, 2160, JpegType::Baseline420, 128);
+        assert!(header.dimensions_out_of_band());
+        assert_eq!((header.width_blocks, header.height_blocks), (0, 0));
+
+        assert_eq!(
+            sdp_dimensions_attribute(3840, 2160),
+            "a=x-dimensions:3840,2160"
+        );
+    }
+
+    #[test]
+    fn test_unaligned_dimensions_round_up() {
+        assert_eq!(dimension_blocks(1366, 768), (171, 96));
+        assert_eq!(dimension_blocks(641, 479), (81, 60));
+        assert_eq!(dimension_blocks(2033, 8), (255, 1));
+
+        let header = JpegHeader::new(0, 1366, 768, JpegType::Baseline420, 128);
+        assert_eq!(header.width(), 1368);
+        assert_eq!(header.height(), 768);
+    }
+
     #[test]
     fn test_fragment_offset() {
         let header = JpegHeader::new(0x123456, 640, 480, JpegType::Baseline420, 128);
@@ -7,6 +7,8 @@
 use bytes::Bytes;
 use thiserror::Error;
 
+use crate::error::ErrorCode;
+
 #[derive(Error, Debug)]
 pub enum JpegParseError {
     #[error("invalid JPEG: too short")]
@@ -25,6 +27,15 @@ pub enum JpegParseError {
     Unsupported,
 }
 
+impl JpegParseError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            JpegParseError::Unsupported => ErrorCode::Unsupported,
+            _ => ErrorCode::InvalidInput,
+        }
+    }
+}
+
 /// JPEG marker codes
 #[allow(dead_code)]
 mod markers {
@@ -34,6 +45,7 @@ mod markers {
     pub const DQT: u8 = 0xDB; // Define Quantization Table
     pub const SOF0: u8 = 0xC0; // Start of Frame (Baseline)
     pub const DHT: u8 = 0xC4; // Define Huffman Table
+    pub const DRI: u8 = 0xDD; // Define Restart Interval
     pub const APP0: u8 = 0xE0; // Application segment 0
     pub const COM: u8 = 0xFE; // Comment
 }
@@ -53,6 +65,9 @@ pub struct JpegInfo {
     /// JPEG type (0 = 4:2:0, 1 = 4:2:2)
     pub jpeg_type: u8,
 
+    /// MCUs between restart markers (0 = no restart markers)
+    pub restart_interval: u16,
+
     /// Scan data (entropy-coded payload) - uses Bytes for zero-copy
     pub scan_data: Bytes,
 }
@@ -73,6 +88,7 @@ pub fn parse_jpeg_for_rtp(data: &[u8]) -> Result<JpegInfo, JpegParseError> {
     let mut width = 0u16;
     let mut height = 0u16;
     let mut jpeg_type = 0u8;
+    let mut restart_interval = 0u16;
     let mut scan_start = 0usize;
 
     // Parse JPEG markers
@@ -124,6 +140,7 @@ pub fn parse_jpeg_for_rtp(data: &[u8]) -> Result<JpegInfo, JpegParseError> {
                     width,
                     height,
                     jpeg_type,
+                    restart_interval,
                     scan_data,
                 });
             }
@@ -135,17 +152,36 @@ pub fn parse_jpeg_for_rtp(data: &[u8]) -> Result<JpegInfo, JpegParseError> {
                 }
                 let length = u16::from_be_bytes([data[pos], data[pos + 1]]) as usize;
 
-                if pos + length > data.len() {
+                if length < 2 || pos + length > data.len() {
                     break;
                 }
 
-                // Extract Q table data (skip length bytes)
-                let table_data = data[pos + 2..pos + length].to_vec();
-                q_tables.push(table_data);
+                // One segment may define several tables, each a Pq/Tq byte
+                // then 64 (8-bit) or 128 (16-bit) values. RFC 2435 carries
+                // the values only.
+                let mut table = pos + 2;
+                while table < pos + length {
+                    let size = if data[table] >> 4 == 0 { 64 } else { 128 };
+                    let Some(values) = data.get(table + 1..table + 1 + size) else {
+                        break;
+                    };
+                    q_tables.push(values.to_vec());
+                    table += 1 + size;
+                }
 
                 pos += length;
             }
 
+            markers::DRI => {
+                // Restart interval: length(2) + interval(2)
+                if pos + 4 > data.len() {
+                    break;
+                }
+                let length = u16::from_be_bytes([data[pos], data[pos + 1]]) as usize;
+                restart_interval = u16::from_be_bytes([data[pos + 2], data[pos + 3]]);
+                pos += length;
+            }
+
             markers::SOF0 => {
                 // Start of Frame - get dimensions
                 if pos + 2 > data.len() {
@@ -162,7 +198,7 @@ pub fn parse_jpeg_for_rtp(data: &[u8]) -> Result<JpegInfo, JpegParseError> {
                 width = u16::from_be_bytes([data[pos + 5], data[pos + 6]]);
 
                 // Determine JPEG type from component info
-                if pos + 9 <= data.len() {
+                if pos + 10 <= data.len() {
                     let num_components = data[pos + 7];
                     if num_components == 3 {
                         // Check sampling factors
@@ -205,6 +241,7 @@ pub fn parse_jpeg_for_rtp(data: &[u8]) -> Result<JpegInfo, JpegParseError> {
         width: width.max(640), // Default if not found
         height: height.max(480),
         jpeg_type,
+        restart_interval,
         scan_data: Bytes::copy_from_slice(data), // Fallback: use full JPEG
     })
 }
@@ -250,6 +287,17 @@ mod tests {
         assert!(!info.scan_data.is_empty());
     }
 
+    #[test]
+    fn test_malformed_segments_do_not_panic() {
+        // DQT with a length field shorter than the length itself
+        let _ = parse_jpeg_for_rtp(&[0xFF, 0xD8, 0xFF, 0xDB, 0x00, 0x00, 0xFF, 0xD9]);
+
+        // Three-component SOF0 cut off right before the sampling factors
+        let _ = parse_jpeg_for_rtp(&[
+            0xFF, 0xD8, 0xFF, 0xC0, 0x00, 0x11, 0x08, 0x01, 0xE0, 0x02, 0x80, 0x03, 0x01,
+        ]);
+    }
+
     fn create_minimal_jpeg(width: u16, height: u16) -> Vec<u8> {
         let mut jpeg = Vec::new();
 
@@ -4,19 +4,39 @@
 //! It handles fragmentation of JPEG frames into RTP packets with proper headers
 //! and timing.
 
+mod drift;
+mod frame_info;
 mod jpeg;
 mod jpeg_parser;
 mod packet;
+mod probe;
+mod rtcp;
+mod sdp;
 
-pub use jpeg::{JpegHeader, JpegType};
+pub use drift::ClockDriftStats;
+pub use frame_info::{sdp_extmap_attribute, FrameInfo, FRAME_INFO_EXTENSION_SIZE, FRAME_INFO_URI};
+pub use jpeg::{dimension_blocks, sdp_dimensions_attribute, JpegHeader, JpegType, MAX_DIMENSION};
 pub use jpeg_parser::{parse_jpeg_for_rtp, validate_jpeg, JpegInfo, JpegParseError};
 pub use packet::{RtpHeader, RtpPacket};
+pub use probe::{Component, JpegProbe, ProbeIssue, QuantizationTable};
+pub use rtcp::{
+    build_bye, build_cname_report, build_goodbye, build_receiver_report, build_sdes_cname,
+    parse_report_blocks, ReportBlock, RTCP_PT_BYE, RTCP_PT_RR, RTCP_PT_SDES, RTCP_PT_SR,
+};
+pub use sdp::{BundleDescription, SessionDescription};
 
 use bytes::{BufMut, Bytes, BytesMut};
-use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
-use std::sync::Mutex;
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use thiserror::Error;
 
+use self::drift::ClockDrift;
+use crate::error::ErrorCode;
+use crate::frame::Frame;
+
 /// RTP protocol constants
 pub const RTP_VERSION: u8 = 2;
 pub const RTP_PAYLOAD_TYPE_JPEG: u8 = 26;
@@ -28,6 +48,9 @@ pub const DEFAULT_MTU: usize = 1400;
 /// Maximum payload size per RTP packet (MTU - headers)
 pub const MAX_PAYLOAD_SIZE: usize = DEFAULT_MTU - RTP_HEADER_SIZE - JPEG_HEADER_SIZE;
 
+/// Padding octets of a keepalive packet
+const KEEPALIVE_PADDING: usize = 4;
+
 #[derive(Error, Debug)]
 pub enum PacketizerError {
     #[error("empty JPEG data")]
@@ -40,13 +63,26 @@ pub enum PacketizerError {
     MissingEoiMarker,
 
     #[error("invalid JPEG: {0}")]
-    InvalidJpeg(String),
+    InvalidJpeg(#[from] JpegParseError),
 
     #[error("JPEG frame too large: {0} bytes")]
     FrameTooLarge(usize),
 
     #[error("invalid MTU: {0}")]
     InvalidMtu(usize),
+
+    #[error("frame {width}x{height} exceeds the RFC 2435 limit of 2040 pixels")]
+    DimensionsTooLarge { width: u32, height: u32 },
+}
+
+impl PacketizerError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            PacketizerError::InvalidJpeg(e) => e.code(),
+            PacketizerError::DimensionsTooLarge { .. } => ErrorCode::Unsupported,
+            _ => ErrorCode::InvalidInput,
+        }
+    }
 }
 
 /// Statistics for RTP packetizer
@@ -57,6 +93,8 @@ pub struct PacketizerStats {
     pub frames_sent: u64,
     pub current_seq: u32,
     pub current_ts: u32,
+    /// Timestamp of the last frame packetized
+    pub last_ts: u32,
 }
 
 /// RTP/JPEG packetizer with zero-copy optimization
@@ -67,20 +105,26 @@ pub struct RtpPacketizer {
     // Configuration
     payload_type: u8,
     ssrc: u32,
-    mtu: usize,
-    max_payload_size: usize,
+    /// Lowered at runtime when the path can't carry the configured size
+    mtu: AtomicUsize,
+    oversize_dimensions: bool,
+    /// Header extension ID for the per-frame [`FrameInfo`] (off when unset)
+    frame_info_id: Option<u8>,
+    qtable_policy: QTablePolicy,
+    /// Frames between resending unchanged cached tables (never when 0)
+    qtable_refresh_frames: u32,
+    qtable_cache: Mutex<QTableCache>,
+    warned_unaligned: AtomicBool,
 
     // State (atomic for lock-free access)
     sequence_number: AtomicU32,
     timestamp: AtomicU32,
+    last_timestamp: AtomicU32,
 
     // Statistics
     packets_sent: AtomicU64,
     bytes_sent: AtomicU64,
     frames_sent: AtomicU64,
-
-    // Cached JPEG info for current frame
-    cached_jpeg_info: Mutex<Option<JpegInfo>>,
 }
 
 impl RtpPacketizer {
@@ -91,22 +135,53 @@ impl RtpPacketizer {
     /// * `mtu` - Maximum transmission unit (default: 1400)
     pub fn new(ssrc: u32, mtu: usize) -> Self {
         let mtu = if mtu == 0 { DEFAULT_MTU } else { mtu };
-        let max_payload_size = mtu.saturating_sub(RTP_HEADER_SIZE + JPEG_HEADER_SIZE);
 
         Self {
             payload_type: RTP_PAYLOAD_TYPE_JPEG,
             ssrc,
-            mtu,
-            max_payload_size: max_payload_size.max(1), // Ensure at least 1 byte
+            mtu: AtomicUsize::new(mtu),
+            oversize_dimensions: false,
+            frame_info_id: None,
+            qtable_policy: QTablePolicy::default(),
+            qtable_refresh_frames: 0,
+            qtable_cache: Mutex::new(QTableCache::default()),
+            warned_unaligned: AtomicBool::new(false),
             sequence_number: AtomicU32::new(0),
             timestamp: AtomicU32::new(0),
+            last_timestamp: AtomicU32::new(0),
             packets_sent: AtomicU64::new(0),
             bytes_sent: AtomicU64::new(0),
             frames_sent: AtomicU64::new(0),
-            cached_jpeg_info: Mutex::new(None),
         }
     }
 
+    /// Allows frames wider or taller than [`MAX_DIMENSION`]
+    ///
+    /// Their header dimension fields are sent as 0, so the receiver must get
+    /// the size from the SDP (`a=x-dimensions`). Without this such frames are
+    /// rejected with [`PacketizerError::DimensionsTooLarge`].
+    pub fn with_oversize_dimensions(mut self, enabled: bool) -> Self {
+        self.oversize_dimensions = enabled;
+        self
+    }
+
+    /// Stamps every packet with a [`FrameInfo`] header extension using this
+    /// RFC 8285 one-byte ID (1-14), so receivers can verify reassembly.
+    /// The extension takes [`FRAME_INFO_EXTENSION_SIZE`] bytes of each packet.
+    pub fn with_frame_info(mut self, id: Option<u8>) -> Self {
+        self.frame_info_id = id.filter(|id| (1..=14).contains(id));
+        self
+    }
+
+    /// Sets how quantization tables are sent; under [`QTablePolicy::Cached`]
+    /// unchanged tables are also resent every `refresh_frames` frames (only
+    /// when they change if 0), so late-joining receivers pick them up
+    pub fn with_qtable_policy(mut self, policy: QTablePolicy, refresh_frames: u32) -> Self {
+        self.qtable_policy = policy;
+        self.qtable_refresh_frames = refresh_frames;
+        self
+    }
+
     /// Packetizes a JPEG frame into RTP packets
     ///
     /// # Arguments
@@ -124,152 +199,113 @@ impl RtpPacketizer {
         height: u32,
         timestamp: u32,
     ) -> Result<Vec<Bytes>, PacketizerError> {
+        let frame_id = self.frames_sent.load(Ordering::Relaxed);
+        self.packetize_frame(jpeg_data, frame_id, width, height, timestamp)
+    }
+
+    /// Packetizes a JPEG frame, with `frame_id` as the id carried by the
+    /// frame-info extension (see [`RtpPacketizer::with_frame_info`])
+    pub fn packetize_frame(
+        &self,
+        jpeg_data: &[u8],
+        frame_id: u64,
+        width: u32,
+        height: u32,
+        timestamp: u32,
+    ) -> Result<Vec<Bytes>, PacketizerError> {
+        let plan = self.plan_frame(jpeg_data, frame_id, width, height, timestamp)?;
+        Ok(plan.packets(0..plan.len()))
+    }
+
+    /// Parses a JPEG frame and reserves its sequence numbers without
+    /// building any packets yet; see [`PacketPlan`]
+    pub fn plan_frame(
+        &self,
+        jpeg_data: &[u8],
+        frame_id: u64,
+        width: u32,
+        height: u32,
+        timestamp: u32,
+    ) -> Result<PacketPlan, PacketizerError> {
         if jpeg_data.is_empty() {
             return Err(PacketizerError::EmptyData);
         }
 
-        // Validate JPEG markers
-        self.validate_jpeg(jpeg_data)?;
-
-        // Extract JPEG payload (scan data only per RFC 2435)
-        let jpeg_payload = self.extract_jpeg_payload(jpeg_data)?;
-
-        // Calculate number of packets needed
-        let num_packets = (jpeg_payload.len() + self.max_payload_size - 1) / self.max_payload_size;
-        let mut packets = Vec::with_capacity(num_packets);
-
-        // Get current sequence number
-        let mut seq_num = self.sequence_number.load(Ordering::Relaxed);
-
-        // Fragment the JPEG payload
-        let mut fragment_offset: u32 = 0;
-        let mut offset = 0;
-
-        while offset < jpeg_payload.len() {
-            let payload_size = (jpeg_payload.len() - offset).min(self.max_payload_size);
-            let is_last = (offset + payload_size) >= jpeg_payload.len();
+        if (width > MAX_DIMENSION || height > MAX_DIMENSION) && !self.oversize_dimensions {
+            return Err(PacketizerError::DimensionsTooLarge { width, height });
+        }
 
-            // Build RTP packet with JPEG header
-            let packet = self.build_rtp_packet(
-                seq_num,
-                timestamp,
-                fragment_offset,
+        if (!width.is_multiple_of(8) || !height.is_multiple_of(8))
+            && !self.warned_unaligned.swap(true, Ordering::Relaxed)
+        {
+            let (width_blocks, height_blocks) = dimension_blocks(width, height);
+            tracing::warn!(
                 width,
                 height,
-                is_last,
-                &jpeg_payload[offset..offset + payload_size],
+                advertised = %format!("{}x{}", width_blocks as u32 * 8, height_blocks as u32 * 8),
+                "Frame size is not a multiple of 8; advertising padded dimensions"
             );
-
-            packets.push(packet);
-
-            // Update for next packet
-            seq_num = seq_num.wrapping_add(1) & 0xFFFF;
-            fragment_offset += payload_size as u32;
-            offset += payload_size;
         }
 
-        // Update state atomically
-        self.sequence_number.store(seq_num, Ordering::Relaxed);
-        self.packets_sent
-            .fetch_add(packets.len() as u64, Ordering::Relaxed);
-        self.bytes_sent
-            .fetch_add(jpeg_data.len() as u64, Ordering::Relaxed);
-        self.frames_sent.fetch_add(1, Ordering::Relaxed);
-
-        Ok(packets)
-    }
-
-    /// Builds a single RTP packet with JPEG header and payload
-    fn build_rtp_packet(
-        &self,
-        seq_num: u32,
-        timestamp: u32,
-        fragment_offset: u32,
-        width: u32,
-        height: u32,
-        marker: bool,
-        payload: &[u8],
-    ) -> Bytes {
-        // Get cached JPEG info if available
-        let jpeg_info = self.cached_jpeg_info.lock().unwrap();
-
-        // Determine if we need to include quantization tables (only in first packet)
-        let include_qtables = fragment_offset == 0 && jpeg_info.is_some();
+        // Validate JPEG markers
+        self.validate_jpeg(jpeg_data)?;
 
-        // Calculate quantization table header size if needed
-        let qtable_header_size = if include_qtables {
-            let info = jpeg_info.as_ref().unwrap();
-            if !info.q_tables.is_empty() {
-                // Qtable header: MBZ(1) + Precision(1) + Length(2) + tables
-                let tables_size: usize = info.q_tables.iter().map(|t| t.len()).sum();
-                4 + tables_size
-            } else {
-                0
+        // Extract JPEG payload (scan data only per RFC 2435)
+        let (payload, jpeg_info) = self.extract_jpeg_payload(jpeg_data)?;
+        let extension = self
+            .frame_info_id
+            .map(|id| FrameInfo::new(frame_id, &payload).to_extension(id));
+
+        // Cached tables are sent under a Q the receiver keeps them for
+        let (cached_q, send_tables) = match (&jpeg_info, self.qtable_policy) {
+            (Some(info), QTablePolicy::Cached) if !info.q_tables.is_empty() => {
+                let mut cache = self.qtable_cache.lock().unwrap();
+                let (q, send) = cache.next(&info.q_tables, self.qtable_refresh_frames);
+                (Some(q), send)
             }
-        } else {
-            0
+            _ => (None, true),
         };
 
-        let total_size = RTP_HEADER_SIZE + JPEG_HEADER_SIZE + qtable_header_size + payload.len();
-        let mut buf = BytesMut::with_capacity(total_size);
-
-        // Build RTP header (12 bytes) - RFC 3550 Section 5.1
-        buf.put_u8((RTP_VERSION << 6) | 0); // V=2, P=0, X=0, CC=0
-        buf.put_u8(if marker {
-            0x80 | self.payload_type
-        } else {
-            self.payload_type
-        });
-        buf.put_u16(seq_num as u16); // Sequence number
-        buf.put_u32(timestamp); // Timestamp
-        buf.put_u32(self.ssrc); // SSRC
-
-        // Build JPEG header (8 bytes) - RFC 2435 Section 3.1
-        let type_specific = if include_qtables { 0 } else { 0 };
-        buf.put_u8(type_specific);
-
-        // Fragment offset (24 bits, big-endian)
-        buf.put_u8((fragment_offset >> 16) as u8);
-        buf.put_u8((fragment_offset >> 8) as u8);
-        buf.put_u8(fragment_offset as u8);
-
-        // Type field (from parsed JPEG or default to 0)
-        let jpeg_type = jpeg_info.as_ref().map(|i| i.jpeg_type).unwrap_or(0);
-        buf.put_u8(jpeg_type);
-
-        // Q field: 128+ means dynamic quantization tables included
-        let q_value = if include_qtables { 128 } else { 255 }; // 255 = no qtables
-        buf.put_u8(q_value);
-
-        buf.put_u8((width / 8) as u8); // Width in 8-pixel blocks
-        buf.put_u8((height / 8) as u8); // Height in 8-pixel blocks
-
-        // Add Quantization Table Header if needed (RFC 2435 Section 3.1.8)
-        if include_qtables {
-            if let Some(info) = jpeg_info.as_ref() {
-                if !info.q_tables.is_empty() {
-                    // MBZ (must be zero)
-                    buf.put_u8(0);
-
-                    // Precision (0 = 8-bit)
-                    buf.put_u8(0);
-
-                    // Length of all quantization tables
-                    let tables_size: usize = info.q_tables.iter().map(|t| t.len()).sum();
-                    buf.put_u16(tables_size as u16);
-
-                    // Append all quantization tables
-                    for table in &info.q_tables {
-                        buf.put_slice(table);
-                    }
-                }
-            }
-        }
+        // Calculate number of packets needed; the first one also carries
+        // the quantization tables
+        let max_payload_size = self.max_payload_size();
+        let first_payload_size = max_payload_size
+            .saturating_sub(qtable_header_size(jpeg_info.as_ref(), send_tables))
+            .max(1);
+        let len = match payload.len() {
+            0 => 0,
+            size if size <= first_payload_size => 1,
+            size => 1 + (size - first_payload_size).div_ceil(max_payload_size),
+        };
 
-        // Add payload
-        buf.put_slice(payload);
+        // Reserve the frame's sequence numbers and count it as sent
+        let first_seq = self.sequence_number.load(Ordering::Relaxed);
+        self.sequence_number.store(
+            first_seq.wrapping_add(len as u32) & 0xFFFF,
+            Ordering::Relaxed,
+        );
+        self.last_timestamp.store(timestamp, Ordering::Relaxed);
+        self.packets_sent.fetch_add(len as u64, Ordering::Relaxed);
+        self.bytes_sent
+            .fetch_add(jpeg_data.len() as u64, Ordering::Relaxed);
+        self.frames_sent.fetch_add(1, Ordering::Relaxed);
 
-        buf.freeze()
+        Ok(PacketPlan {
+            payload_type: self.payload_type,
+            ssrc: self.ssrc,
+            jpeg_info,
+            cached_q,
+            send_tables,
+            payload,
+            extension,
+            first_seq,
+            timestamp,
+            width,
+            height,
+            first_payload_size,
+            max_payload_size,
+            len,
+        })
     }
 
     /// Validates JPEG markers
@@ -294,23 +330,24 @@ impl RtpPacketizer {
 
     /// Extracts JPEG payload according to RFC 2435
     ///
-    /// Parses JPEG and extracts scan data (entropy-coded payload) only.
-    /// This is required for compatibility with standard RFC 2435 receivers.
-    fn extract_jpeg_payload(&self, data: &[u8]) -> Result<Bytes, PacketizerError> {
+    /// Parses JPEG and extracts scan data (entropy-coded payload) only,
+    /// along with the parsed info for the RTP JPEG header. This is required
+    /// for compatibility with standard RFC 2435 receivers.
+    fn extract_jpeg_payload(
+        &self,
+        data: &[u8],
+    ) -> Result<(Bytes, Option<JpegInfo>), PacketizerError> {
         // Parse JPEG to extract scan data and metadata
         match parse_jpeg_for_rtp(data) {
             Ok(info) => {
-                // Store parsed info for use in RTP JPEG header
                 let scan_data = info.scan_data.clone(); // Just increments refcount, no copy!
-                *self.cached_jpeg_info.lock().unwrap() = Some(info);
-                Ok(scan_data)
+                Ok((scan_data, Some(info)))
             }
             Err(e) => {
                 // Fallback: basic validation and send full JPEG
                 tracing::warn!("Failed to parse JPEG properly: {}, using full JPEG", e);
-                validate_jpeg(data).map_err(|e| PacketizerError::InvalidJpeg(format!("{}", e)))?;
-                *self.cached_jpeg_info.lock().unwrap() = None;
-                Ok(Bytes::copy_from_slice(data))
+                validate_jpeg(data)?;
+                Ok((Bytes::copy_from_slice(data), None))
             }
         }
     }
@@ -336,6 +373,56 @@ impl RtpPacketizer {
         self.sequence_number.load(Ordering::Relaxed)
     }
 
+    /// Sets the sequence number the next packet gets, e.g. to continue a
+    /// stream from an earlier run
+    pub fn set_sequence_number(&self, seq: u16) {
+        self.sequence_number.store(seq as u32, Ordering::Relaxed);
+    }
+
+    /// Builds an RTP packet without payload, only padding (RFC 3550
+    /// Section 5.1), that keeps NAT bindings open between frames. It takes
+    /// the next sequence number, so receivers see no gap, and repeats the
+    /// last frame's timestamp.
+    pub fn keepalive_packet(&self) -> Bytes {
+        let seq_num = self.sequence_number.load(Ordering::Relaxed);
+        self.sequence_number
+            .store(seq_num.wrapping_add(1) & 0xFFFF, Ordering::Relaxed);
+
+        let mut buf = BytesMut::with_capacity(RTP_HEADER_SIZE + KEEPALIVE_PADDING);
+        buf.put_u8((RTP_VERSION << 6) | 0x20); // V=2, P=1, X=0, CC=0
+        buf.put_u8(self.payload_type);
+        buf.put_u16(seq_num as u16);
+        buf.put_u32(self.last_timestamp.load(Ordering::Relaxed));
+        buf.put_u32(self.ssrc);
+        // The last octet counts the padding, itself included
+        buf.put_bytes(0, KEEPALIVE_PADDING - 1);
+        buf.put_u8(KEEPALIVE_PADDING as u8);
+        buf.freeze()
+    }
+
+    /// Gets the MTU packets are sized for
+    pub fn mtu(&self) -> usize {
+        self.mtu.load(Ordering::Relaxed)
+    }
+
+    /// Changes the packet size from the next frame on
+    pub fn set_mtu(&self, mtu: usize) {
+        self.mtu.store(mtu, Ordering::Relaxed);
+    }
+
+    /// Scan bytes per packet after the RTP and JPEG headers and any
+    /// header extension (at least 1)
+    fn max_payload_size(&self) -> usize {
+        let extension_size = if self.frame_info_id.is_some() {
+            FRAME_INFO_EXTENSION_SIZE
+        } else {
+            0
+        };
+        self.mtu()
+            .saturating_sub(RTP_HEADER_SIZE + extension_size + JPEG_HEADER_SIZE)
+            .max(1)
+    }
+
     /// Gets packetizer statistics
     pub fn get_stats(&self) -> PacketizerStats {
         PacketizerStats {
@@ -344,6 +431,7 @@ impl RtpPacketizer {
             frames_sent: self.frames_sent.load(Ordering::Relaxed),
             current_seq: self.sequence_number.load(Ordering::Relaxed),
             current_ts: self.timestamp.load(Ordering::Relaxed),
+            last_ts: self.last_timestamp.load(Ordering::Relaxed),
         }
     }
 
@@ -351,18 +439,272 @@ impl RtpPacketizer {
     pub fn reset(&self) {
         self.sequence_number.store(0, Ordering::Relaxed);
         self.timestamp.store(0, Ordering::Relaxed);
+        self.last_timestamp.store(0, Ordering::Relaxed);
         self.packets_sent.store(0, Ordering::Relaxed);
         self.bytes_sent.store(0, Ordering::Relaxed);
         self.frames_sent.store(0, Ordering::Relaxed);
+        *self.qtable_cache.lock().unwrap() = QTableCache::default();
     }
 }
 
+/// How quantization tables reach the receiver (RFC 2435 Section 3.1.8)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QTablePolicy {
+    /// Every frame's first packet carries the tables
+    #[default]
+    Always,
+    /// Tables are sent when they change (and periodically) under a Q of
+    /// 128-254, which receivers cache them for; other frames carry an empty
+    /// table header. Saves about 130 bytes per frame, but a receiver that
+    /// misses the tables can't decode until they are sent again.
+    Cached,
+}
+
+/// Tables last sent under [`QTablePolicy::Cached`]
+#[derive(Debug, Default)]
+struct QTableCache {
+    tables: Vec<Vec<u8>>,
+    /// Q they were sent under, 0 before any were
+    q: u8,
+    /// Frames since they were last sent
+    age: u32,
+}
+
+impl QTableCache {
+    /// Q for a frame with `tables`, and whether its first packet has to
+    /// carry them
+    fn next(&mut self, tables: &[Vec<u8>], refresh_frames: u32) -> (u8, bool) {
+        if self.q == 0 || self.tables != tables {
+            // A new Q per table set, so a receiver that misses the new tables
+            // drops frames instead of decoding them with stale ones
+            self.q = if (128..254).contains(&self.q) {
+                self.q + 1
+            } else {
+                128
+            };
+            self.tables = tables.to_vec();
+            self.age = 0;
+            return (self.q, true);
+        }
+        self.age += 1;
+        let refresh = refresh_frames > 0 && self.age >= refresh_frames;
+        if refresh {
+            self.age = 0;
+        }
+        (self.q, refresh)
+    }
+}
+
+/// A frame parsed and given its sequence numbers by
+/// [`RtpPacketizer::plan_frame`]
+///
+/// Each packet depends only on the plan, so a large frame's packets can be
+/// built in chunks on several threads and sent as each chunk is ready.
+#[derive(Debug)]
+pub struct PacketPlan {
+    payload_type: u8,
+    ssrc: u32,
+    jpeg_info: Option<JpegInfo>,
+    /// Q the tables are cached under, see [`QTablePolicy::Cached`]
+    cached_q: Option<u8>,
+    /// Whether the table header carries the tables or is empty
+    send_tables: bool,
+    payload: Bytes,
+    extension: Option<[u8; FRAME_INFO_EXTENSION_SIZE]>,
+    first_seq: u32,
+    timestamp: u32,
+    width: u32,
+    height: u32,
+    /// Scan bytes in the first packet, which leaves room for the q-tables
+    first_payload_size: usize,
+    max_payload_size: usize,
+    len: usize,
+}
+
+impl PacketPlan {
+    /// Number of packets the frame takes
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Builds the packets in `range`, in order
+    pub fn packets(&self, range: Range<usize>) -> Vec<Bytes> {
+        range.map(|index| self.packet(index)).collect()
+    }
+
+    /// Scan bytes carried by packet `index`
+    fn payload_range(&self, index: usize) -> Range<usize> {
+        let (start, size) = match index {
+            0 => (0, self.first_payload_size),
+            _ => (
+                self.first_payload_size + (index - 1) * self.max_payload_size,
+                self.max_payload_size,
+            ),
+        };
+        start..(start + size).min(self.payload.len())
+    }
+
+    /// Builds packet `index` of the frame, with its JPEG header and payload
+    fn packet(&self, index: usize) -> Bytes {
+        let range = self.payload_range(index);
+        let offset = range.start;
+        let payload = &self.payload[range];
+        let seq_num = self.first_seq.wrapping_add(index as u32) & 0xFFFF;
+        let fragment_offset = offset as u32;
+        let marker = index + 1 == self.len;
+        let extension = self.extension.as_ref().map(|block| &block[..]);
+        let jpeg_info = &self.jpeg_info;
+
+        // Determine if we need to include quantization tables (only in first packet)
+        let include_qtables = fragment_offset == 0 && jpeg_info.is_some();
+
+        // Calculate quantization table header size if needed
+        let qtable_header_size = if include_qtables {
+            qtable_header_size(jpeg_info.as_ref(), self.send_tables)
+        } else {
+            0
+        };
+
+        let extension_size = extension.map_or(0, <[u8]>::len);
+        let total_size = RTP_HEADER_SIZE
+            + extension_size
+            + JPEG_HEADER_SIZE
+            + qtable_header_size
+            + payload.len();
+        let mut buf = BytesMut::with_capacity(total_size);
+
+        // Build RTP header (12 bytes) - RFC 3550 Section 5.1
+        let x_bit = if extension.is_some() { 0x10 } else { 0 };
+        buf.put_u8((RTP_VERSION << 6) | x_bit); // V=2, P=0, X, CC=0
+        buf.put_u8(if marker {
+            0x80 | self.payload_type
+        } else {
+            self.payload_type
+        });
+        buf.put_u16(seq_num as u16); // Sequence number
+        buf.put_u32(self.timestamp); // Timestamp
+        buf.put_u32(self.ssrc); // SSRC
+
+        // Header extension (RFC 3550 Section 5.3.1)
+        if let Some(extension) = extension {
+            buf.put_slice(extension);
+        }
+
+        // Build JPEG header (8 bytes) - RFC 2435 Section 3.1
+        let type_specific = if include_qtables { 0 } else { 0 };
+        buf.put_u8(type_specific);
+
+        // Fragment offset (24 bits, big-endian)
+        buf.put_u8((fragment_offset >> 16) as u8);
+        buf.put_u8((fragment_offset >> 8) as u8);
+        buf.put_u8(fragment_offset as u8);
+
+        // Type field (from parsed JPEG or default to 0)
+        let jpeg_type = jpeg_info.as_ref().map(|i| i.jpeg_type).unwrap_or(0);
+        buf.put_u8(jpeg_type);
+
+        // Q field: 128+ means dynamic quantization tables included
+        let q_value = match self.cached_q {
+            // Every packet of the frame names the cached tables
+            Some(q) => q,
+            None if include_qtables => 128,
+            None => 255, // 255 = no qtables
+        };
+        buf.put_u8(q_value);
+
+        // Width/height in 8-pixel blocks (0 = out of band, see dimension_blocks)
+        let (width_blocks, height_blocks) = dimension_blocks(self.width, self.height);
+        buf.put_u8(width_blocks);
+        buf.put_u8(height_blocks);
+
+        // Add Quantization Table Header if needed (RFC 2435 Section 3.1.8)
+        if include_qtables {
+            if let Some(info) = jpeg_info.as_ref() {
+                if !info.q_tables.is_empty() {
+                    // MBZ (must be zero)
+                    buf.put_u8(0);
+
+                    // Precision: bit i set when table i has 16-bit values
+                    let precision = info
+                        .q_tables
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, table)| table.len() == 128)
+                        .fold(0u8, |bits, (i, _)| bits | 1 << i);
+                    buf.put_u8(precision);
+
+                    // Length of all quantization tables, 0 when the receiver
+                    // uses the ones it cached
+                    if self.send_tables {
+                        let tables_size: usize = info.q_tables.iter().map(|t| t.len()).sum();
+                        buf.put_u16(tables_size as u16);
+
+                        // Append all quantization tables
+                        for table in &info.q_tables {
+                            buf.put_slice(table);
+                        }
+                    } else {
+                        buf.put_u16(0);
+                    }
+                }
+            }
+        }
+
+        // Add payload
+        buf.put_slice(payload);
+
+        buf.freeze()
+    }
+}
+
+/// Size of the quantization table header a frame's first packet carries
+/// (RFC 2435 Section 3.1.8), 0 without tables
+fn qtable_header_size(jpeg_info: Option<&JpegInfo>, send_tables: bool) -> usize {
+    match jpeg_info {
+        // MBZ(1) + Precision(1) + Length(2) + tables
+        Some(info) if !info.q_tables.is_empty() && send_tables => {
+            4 + info.q_tables.iter().map(Vec::len).sum::<usize>()
+        }
+        Some(info) if !info.q_tables.is_empty() => 4,
+        _ => 0,
+    }
+}
+
+/// What RTP timestamps are derived from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TimestampSource {
+    /// One nominal frame interval per frame slot. Frames dropped on the way
+    /// to the sender keep their slot, but a camera delivering fewer frames
+    /// than configured still drifts behind real time.
+    #[default]
+    FrameCount,
+    /// The capture pipeline's buffer PTS, falling back to `wallclock` for
+    /// frames without one
+    Pts,
+    /// When the frame left the capture pipeline ([`Frame::captured_at`])
+    Wallclock,
+}
+
 /// Timestamp generator for consistent frame timing
+///
+/// Clones share the drift measurement, so the streamer can report what its
+/// sender task measures.
 #[derive(Clone)]
 pub struct TimestampGenerator {
     start_time: std::time::Instant,
     clock_rate: u32,
     fps: u32,
+    source: TimestampSource,
+    drift_correction: bool,
+    drift: Arc<Mutex<ClockDrift>>,
+    /// Added to every timestamp from [`TimestampGenerator::for_frame`]
+    offset: u32,
 }
 
 impl TimestampGenerator {
@@ -372,7 +714,81 @@ impl TimestampGenerator {
             start_time: std::time::Instant::now(),
             clock_rate: RTP_CLOCK_RATE,
             fps,
+            source: TimestampSource::default(),
+            drift_correction: false,
+            drift: Arc::new(Mutex::new(ClockDrift::default())),
+            offset: 0,
+        }
+    }
+
+    /// Selects what [`TimestampGenerator::for_frame`] derives timestamps from
+    pub fn with_source(mut self, source: TimestampSource) -> Self {
+        self.source = source;
+        self
+    }
+
+    /// Steers frame-count timestamps towards the capture clock (see
+    /// [`ClockDriftStats`]); the drift is measured either way
+    pub fn with_drift_correction(mut self, enabled: bool) -> Self {
+        self.drift_correction = enabled;
+        self
+    }
+
+    /// Starts timestamps at `offset` instead of 0, e.g. to continue a
+    /// stream from an earlier run
+    pub fn with_offset(mut self, offset: u32) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    pub fn source(&self) -> TimestampSource {
+        self.source
+    }
+
+    /// Drift of frame-count timestamps against the capture clock; all zero
+    /// for the other sources, which follow the capture clock already
+    pub fn drift(&self) -> ClockDriftStats {
+        self.drift.lock().unwrap().stats()
+    }
+
+    /// Timestamp for `frame`, the `frame_count`th frame sent, according to
+    /// the configured source
+    pub fn for_frame(&self, frame: &Frame, frame_count: u64) -> u32 {
+        let timestamp = match (self.source, frame.pts) {
+            (TimestampSource::FrameCount, _) => self.drift_corrected(frame, frame_count),
+            (TimestampSource::Pts, Some(pts)) => self.ticks(pts),
+            (TimestampSource::Pts, None) | (TimestampSource::Wallclock, _) => {
+                self.ticks(self.captured(frame))
+            }
+        };
+        timestamp.wrapping_add(self.offset)
+    }
+
+    /// Frame-count timestamp, measuring its drift and correcting it if
+    /// enabled
+    fn drift_corrected(&self, frame: &Frame, frame_count: u64) -> u32 {
+        let interval = (self.clock_rate / self.fps) as f64 / self.clock_rate as f64;
+        let captured = frame.pts.unwrap_or_else(|| self.captured(frame));
+        let correction = self
+            .drift
+            .lock()
+            .unwrap()
+            .update(frame_count, captured, interval);
+        let timestamp = self.next_frame_based(frame_count);
+        if !self.drift_correction {
+            return timestamp;
         }
+        timestamp.wrapping_add_signed(-(correction * self.clock_rate as f64).round() as i32)
+    }
+
+    /// When `frame` left the capture pipeline, since the generator started
+    fn captured(&self, frame: &Frame) -> Duration {
+        frame.captured_at.saturating_duration_since(self.start_time)
+    }
+
+    /// `elapsed` in clock ticks, wrapping at 32 bits
+    fn ticks(&self, elapsed: Duration) -> u32 {
+        (elapsed.as_nanos() * self.clock_rate as u128 / 1_000_000_000) as u32
     }
 
     /// Returns next timestamp based on elapsed time
@@ -403,9 +819,9 @@ mod tests {
     fn test_new_packetizer() {
         let p = RtpPacketizer::new(0x12345678, 1400);
         assert_eq!(p.ssrc, 0x12345678);
-        assert_eq!(p.mtu, 1400);
+        assert_eq!(p.mtu(), 1400);
         assert_eq!(
-            p.max_payload_size,
+            p.max_payload_size(),
             1400 - RTP_HEADER_SIZE - JPEG_HEADER_SIZE
         );
     }
@@ -443,6 +859,236 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_oversize_dimensions_rejected() {
+        let jpeg = create_test_jpeg(100);
+        let p = RtpPacketizer::new(0x12345678, 1400);
+
+        let result = p.packetize_jpeg(&jpeg, 3840, 2160, 1000);
+        assert!(matches!(
+            result,
+            Err(PacketizerError::DimensionsTooLarge {
+                width: 3840,
+                height: 2160
+            })
+        ));
+    }
+
+    #[test]
+    fn test_oversize_dimensions_out_of_band() {
+        let jpeg = create_test_jpeg(100);
+        let p = RtpPacketizer::new(0x12345678, 1400).with_oversize_dimensions(true);
+
+        let packets = p.packetize_jpeg(&jpeg, 3840, 2160, 1000).unwrap();
+        let header = JpegHeader::from_bytes(&packets[0][RTP_HEADER_SIZE..]).unwrap();
+        assert!(header.dimensions_out_of_band());
+    }
+
+    #[test]
+    fn test_frame_info_extension() {
+        let jpeg = create_test_jpeg(3000);
+        let p = RtpPacketizer::new(0x12345678, 1400).with_frame_info(Some(2));
+
+        let packets = p.packetize_frame(&jpeg, 42, 640, 480, 1000).unwrap();
+        assert!(packets.len() > 1);
+        for pkt in &packets {
+            assert!(pkt.len() <= 1400);
+            assert_eq!(pkt[0] & 0x10, 0x10);
+            let info = FrameInfo::from_extension(0xBEDE, &pkt[16..28], 2).unwrap();
+            assert_eq!(info.frame_id, 42);
+            assert!(info.matches(&jpeg));
+        }
+
+        let plain = RtpPacketizer::new(0x12345678, 1400)
+            .packetize_jpeg(&jpeg, 640, 480, 1000)
+            .unwrap();
+        assert_eq!(plain[0][0] & 0x10, 0);
+    }
+
+    #[test]
+    fn test_plan_chunks_match_whole_frame() {
+        let jpeg = create_test_jpeg(10_000);
+        let whole = RtpPacketizer::new(0x12345678, 1400)
+            .with_frame_info(Some(2))
+            .packetize_frame(&jpeg, 7, 640, 480, 1000)
+            .unwrap();
+
+        let p = RtpPacketizer::new(0x12345678, 1400).with_frame_info(Some(2));
+        let plan = p.plan_frame(&jpeg, 7, 640, 480, 1000).unwrap();
+        assert_eq!(plan.len(), whole.len());
+        // Sequence numbers are taken when planning
+        assert_eq!(p.get_sequence_number(), whole.len() as u32);
+
+        let chunked: Vec<Bytes> = (0..plan.len())
+            .step_by(3)
+            .flat_map(|start| plan.packets(start..(start + 3).min(plan.len())))
+            .collect();
+        assert_eq!(chunked, whole);
+    }
+
+    /// A one-component JPEG with a single quantization table of `q`s
+    fn create_jpeg_with_qtable(q: u8, scan_size: usize) -> Vec<u8> {
+        let mut jpeg = vec![0xFF, 0xD8]; // SOI
+        jpeg.extend(&[0xFF, 0xDB, 0x00, 0x43, 0x00]); // DQT, table 0
+        jpeg.extend([q; 64]);
+        // SOF0 (640x480, one component), then SOS
+        jpeg.extend(&[
+            0xFF, 0xC0, 0x00, 0x0B, 0x08, 0x01, 0xE0, 0x02, 0x80, 0x01, 0x01, 0x11,
+        ]);
+        jpeg.push(0x00);
+        jpeg.extend(&[0xFF, 0xDA, 0x00, 0x08, 0x01, 0x01, 0x00, 0x00, 0x3F, 0x00]);
+        jpeg.extend((0..scan_size).map(|i| (i % 255) as u8));
+        jpeg.extend(&[0xFF, 0xD9]); // EOI
+        jpeg
+    }
+
+    #[test]
+    fn test_qtable_cache() {
+        let tables = vec![vec![1u8; 64]];
+        let mut cache = QTableCache::default();
+        assert_eq!(cache.next(&tables, 3), (128, true));
+        assert_eq!(cache.next(&tables, 3), (128, false));
+        assert_eq!(cache.next(&tables, 3), (128, false));
+        // Refreshed every third frame
+        assert_eq!(cache.next(&tables, 3), (128, true));
+        assert_eq!(cache.next(&tables, 3), (128, false));
+
+        // New tables get the next Q, and Q wraps before 255
+        assert_eq!(cache.next(&[vec![2u8; 64]], 3), (129, true));
+        cache.q = 254;
+        assert_eq!(cache.next(&tables, 0), (128, true));
+        for _ in 0..100 {
+            assert_eq!(cache.next(&tables, 0), (128, false));
+        }
+    }
+
+    #[test]
+    fn test_cached_qtables() {
+        let jpeg = create_jpeg_with_qtable(10, 100);
+        let p = RtpPacketizer::new(0x12345678, 1400).with_qtable_policy(QTablePolicy::Cached, 2);
+        let first = |jpeg: &[u8]| p.packetize_jpeg(jpeg, 640, 480, 1000).unwrap()[0].clone();
+        // Q, then the table header's length field
+        let q_and_length = |pkt: &Bytes| (pkt[17], u16::from_be_bytes([pkt[22], pkt[23]]));
+
+        let sent = first(&jpeg);
+        assert_eq!(q_and_length(&sent), (128, 64));
+        // The values, without the DQT's precision/id byte
+        assert_eq!(&sent[24..88], &jpeg[7..71]);
+        let cached = first(&jpeg);
+        assert_eq!(q_and_length(&cached), (128, 0));
+        assert_eq!(cached.len(), sent.len() - 64);
+        assert_eq!(&cached[24..], &sent[88..]);
+        assert_eq!(q_and_length(&first(&jpeg)), (128, 64));
+
+        // Changed tables (e.g. a new quality) are sent right away
+        assert_eq!(
+            q_and_length(&first(&create_jpeg_with_qtable(20, 100))),
+            (129, 64)
+        );
+
+        // Until reset, which sends them again
+        assert_eq!(q_and_length(&first(&jpeg)), (130, 64));
+        assert_eq!(q_and_length(&first(&jpeg)), (130, 0));
+        p.reset();
+        assert_eq!(q_and_length(&first(&jpeg)), (128, 64));
+
+        // The default keeps sending them with every frame
+        let p = RtpPacketizer::new(0x12345678, 1400);
+        for _ in 0..3 {
+            let pkt = p.packetize_jpeg(&jpeg, 640, 480, 1000).unwrap()[0].clone();
+            assert_eq!(q_and_length(&pkt), (128, 64));
+        }
+    }
+
+    #[test]
+    fn test_timestamp_sources() {
+        let ts_gen = TimestampGenerator::new(30);
+        let frame = Frame {
+            captured_at: ts_gen.start_time + Duration::from_millis(500),
+            ..Frame::new(0, Bytes::new())
+        }
+        .with_pts(Some(Duration::from_secs(2)));
+
+        assert_eq!(ts_gen.for_frame(&frame, 3), 9000);
+        let pts = ts_gen.clone().with_source(TimestampSource::Pts);
+        assert_eq!(pts.for_frame(&frame, 3), 180_000);
+        let wallclock = ts_gen.clone().with_source(TimestampSource::Wallclock);
+        assert_eq!(wallclock.for_frame(&frame, 3), 45_000);
+
+        // No PTS: capture time instead
+        let frame = frame.with_pts(None);
+        assert_eq!(pts.for_frame(&frame, 3), 45_000);
+
+        // Wraps instead of saturating after ~13 hours
+        let late = Frame::new(0, Bytes::new()).with_pts(Some(Duration::from_secs(50_000)));
+        assert_eq!(pts.for_frame(&late, 0), (50_000u64 * 90_000) as u32);
+
+        // An offset carries on from an earlier run, wrapping too
+        let resumed = ts_gen.clone().with_offset(u32::MAX - 2999);
+        assert_eq!(resumed.for_frame(&frame, 0), u32::MAX - 2999);
+        assert_eq!(resumed.for_frame(&frame, 1), 0);
+    }
+
+    #[test]
+    fn test_drift_correction() {
+        let ts_gen = TimestampGenerator::new(30).with_drift_correction(true);
+        let uncorrected = TimestampGenerator::new(30);
+        // A camera at 29.97 fps for five minutes: the nominal slots run ahead
+        let frame_at = |slot: u64| {
+            Frame::new(slot, Bytes::new())
+                .with_pts(Some(Duration::from_secs_f64(slot as f64 / 29.97)))
+        };
+        let mut previous = 0;
+        for slot in 0..9000 {
+            let timestamp = ts_gen.for_frame(&frame_at(slot), slot);
+            assert_eq!(
+                uncorrected.for_frame(&frame_at(slot), slot),
+                slot as u32 * 3000
+            );
+            if slot > 0 {
+                assert!(timestamp > previous);
+            }
+            previous = timestamp;
+        }
+
+        // 0.1% behind over 300 s; the correction follows with some lag
+        let drift = ts_gen.drift();
+        assert!((drift.drift_ppm + 1000.0).abs() < 20.0, "{:?}", drift);
+        assert!(
+            (drift.correction_ms + drift.drift_ms).abs() < 20.0,
+            "{:?}",
+            drift
+        );
+        let capture_ticks = (8999.0 / 29.97 * 90_000.0) as i64;
+        assert!((previous as i64 - capture_ticks).abs() < 90 * 20);
+        assert_eq!(uncorrected.drift(), drift);
+    }
+
+    #[test]
+    fn test_keepalive_continues_sequence() {
+        let p = RtpPacketizer::new(0x12345678, 1400);
+        let frame = p
+            .packetize_jpeg(&create_test_jpeg(100), 640, 480, 3000)
+            .unwrap();
+
+        let keepalive = p.keepalive_packet();
+        let header = RtpHeader::from_bytes(&keepalive).unwrap();
+        assert!(header.padding);
+        assert_eq!(header.sequence_number, frame.len() as u16);
+        assert_eq!(header.timestamp, 3000);
+        assert_eq!(header.ssrc, 0x12345678);
+        assert_eq!(keepalive.len(), RTP_HEADER_SIZE + KEEPALIVE_PADDING);
+        assert_eq!(keepalive[keepalive.len() - 1] as usize, KEEPALIVE_PADDING);
+
+        let next = p
+            .packetize_jpeg(&create_test_jpeg(100), 640, 480, 6000)
+            .unwrap();
+        assert_eq!(
+            RtpHeader::from_bytes(&next[0]).unwrap().sequence_number,
+            frame.len() as u16 + 1
+        );
+    }
+
     #[test]
     fn test_empty_jpeg() {
         let p = RtpPacketizer::new(0x12345678, 1400);
@@ -0,0 +1,395 @@
+//! RFC 2435 compatibility check for a single JPEG
+//!
+//! [`JpegProbe`] reports what the packetizer extracts from a frame (type,
+//! quantization tables, scan size, restart interval and dimensions) and lists
+//! anything about it that RTP/JPEG receivers can't reconstruct. Camera output
+//! can be checked this way before deployment, e.g. with
+//! `mjpeg-rtp probe-jpeg frame.jpg`.
+
+use std::fmt;
+
+use super::jpeg::MAX_DIMENSION;
+use super::jpeg_parser::{parse_jpeg_for_rtp, JpegParseError};
+
+const SOF0: u8 = 0xC0;
+const DHT: u8 = 0xC4;
+const JPG: u8 = 0xC8;
+const DAC: u8 = 0xCC;
+const EOI: u8 = 0xD9;
+const SOS: u8 = 0xDA;
+const DQT: u8 = 0xDB;
+
+/// One quantization table from a DQT segment
+#[derive(Debug, Clone, PartialEq)]
+pub struct QuantizationTable {
+    /// Table slot (0-3) referenced by the frame components
+    pub id: u8,
+    /// Bits per value, 8 or 16
+    pub precision: u8,
+    /// 64 values in zigzag order
+    pub values: Vec<u16>,
+}
+
+/// Sampling factors and table of one frame component
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Component {
+    pub id: u8,
+    pub horizontal: u8,
+    pub vertical: u8,
+    pub q_table: u8,
+}
+
+/// Something in a JPEG that RFC 2435 types 0 and 1 can't carry
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProbeIssue {
+    /// Not baseline sequential DCT; the marker is the SOF in use
+    /// (e.g. 0xC2 for progressive)
+    NotBaseline { marker: u8 },
+    /// Types 0 and 1 are three-component YCbCr
+    ComponentCount(u8),
+    /// Luma sampling other than 2x2 (4:2:0) or 2x1 (4:2:2), or subsampled chroma
+    UnsupportedSampling { horizontal: u8, vertical: u8 },
+    /// 16-bit quantization tables
+    SixteenBitTables,
+    /// No DQT segment, so there are no tables to send in-band
+    MissingTables,
+    /// The scan has restart markers, but frames are sent as type 0/1
+    /// without a restart marker header
+    RestartMarkers { interval: u16 },
+    /// Wider or taller than 2040 pixels; needs `oversize_dimensions` and the
+    /// SDP dimensions attribute
+    OversizeDimensions,
+    /// Not a multiple of 8 pixels; receivers decode the padded size
+    UnalignedDimensions,
+}
+
+impl fmt::Display for ProbeIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProbeIssue::NotBaseline { marker } => {
+                write!(
+                    f,
+                    "not baseline JPEG (SOF marker {:#04x}, RFC 2435 needs 0xc0)",
+                    marker
+                )
+            }
+            ProbeIssue::ComponentCount(n) => {
+                write!(f, "{} component(s), RFC 2435 types 0/1 need 3 (YCbCr)", n)
+            }
+            ProbeIssue::UnsupportedSampling {
+                horizontal,
+                vertical,
+            } => write!(
+                f,
+                "luma sampling {}x{} with these chroma factors is neither 4:2:0 nor 4:2:2",
+                horizontal, vertical
+            ),
+            ProbeIssue::SixteenBitTables => write!(f, "16-bit quantization tables are not sent"),
+            ProbeIssue::MissingTables => write!(f, "no quantization tables"),
+            ProbeIssue::RestartMarkers { interval } => write!(
+                f,
+                "restart interval {} but frames are sent without a restart marker header",
+                interval
+            ),
+            ProbeIssue::OversizeDimensions => write!(
+                f,
+                "larger than {} pixels, needs oversize_dimensions and an SDP dimensions attribute",
+                MAX_DIMENSION
+            ),
+            ProbeIssue::UnalignedDimensions => {
+                write!(
+                    f,
+                    "dimensions are not a multiple of 8, receivers decode the padded size"
+                )
+            }
+        }
+    }
+}
+
+/// Parsed RFC 2435 metadata for one JPEG
+#[derive(Debug, Clone, PartialEq)]
+pub struct JpegProbe {
+    pub width: u16,
+    pub height: u16,
+    /// RTP/JPEG type the packetizer sends (0 = 4:2:0, 1 = 4:2:2)
+    pub jpeg_type: u8,
+    /// Start-of-frame marker (0xC0 for baseline)
+    pub sof_marker: u8,
+    pub components: Vec<Component>,
+    pub q_tables: Vec<QuantizationTable>,
+    /// MCUs between restart markers (0 = none)
+    pub restart_interval: u16,
+    /// Entropy-coded bytes sent as the RTP payload
+    pub scan_size: usize,
+    pub issues: Vec<ProbeIssue>,
+}
+
+impl JpegProbe {
+    /// Parses the headers of a complete JPEG (SOI..EOI)
+    pub fn new(data: &[u8]) -> Result<Self, JpegParseError> {
+        let mut probe = Self::read_headers(data)?;
+
+        let info = parse_jpeg_for_rtp(data)?;
+        probe.jpeg_type = info.jpeg_type;
+        probe.restart_interval = info.restart_interval;
+        probe.scan_size = info.scan_data.len();
+
+        probe.issues = probe.find_issues();
+        Ok(probe)
+    }
+
+    /// True when the JPEG can be sent as-is to any RFC 2435 receiver
+    pub fn is_compatible(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    /// Walks the marker segments up to SOS
+    fn read_headers(data: &[u8]) -> Result<Self, JpegParseError> {
+        if data.len() < 4 {
+            return Err(JpegParseError::TooShort);
+        }
+        if data[0] != 0xFF || data[1] != 0xD8 {
+            return Err(JpegParseError::MissingSoi);
+        }
+
+        let mut probe = JpegProbe {
+            width: 0,
+            height: 0,
+            jpeg_type: 0,
+            sof_marker: 0,
+            components: Vec::new(),
+            q_tables: Vec::new(),
+            restart_interval: 0,
+            scan_size: 0,
+            issues: Vec::new(),
+        };
+
+        let mut pos = 2;
+        loop {
+            if data.get(pos) != Some(&0xFF) {
+                return Err(JpegParseError::MissingSos);
+            }
+            // Markers may be preceded by any number of 0xFF fill bytes
+            while data.get(pos) == Some(&0xFF) {
+                pos += 1;
+            }
+            let Some(&marker) = data.get(pos) else {
+                return Err(JpegParseError::MissingSos);
+            };
+            pos += 1;
+
+            match marker {
+                SOS => return Ok(probe),
+                EOI => return Err(JpegParseError::MissingSos),
+                // Standalone markers have no length
+                0x01 | 0xD0..=0xD8 => continue,
+                _ => {}
+            }
+
+            if pos + 2 > data.len() {
+                return Err(JpegParseError::TooShort);
+            }
+            let length = u16::from_be_bytes([data[pos], data[pos + 1]]) as usize;
+            if length < 2 || pos + length > data.len() {
+                return Err(JpegParseError::TooShort);
+            }
+            let segment = &data[pos + 2..pos + length];
+
+            match marker {
+                DQT => probe.read_tables(segment)?,
+                0xC0..=0xCF if marker != DHT && marker != JPG && marker != DAC => {
+                    probe.read_frame(marker, segment)?
+                }
+                _ => {}
+            }
+            pos += length;
+        }
+    }
+
+    fn read_tables(&mut self, mut segment: &[u8]) -> Result<(), JpegParseError> {
+        while let Some((&pq_tq, rest)) = segment.split_first() {
+            let precision = if pq_tq >> 4 == 0 { 8 } else { 16 };
+            let size = 64 * (precision as usize / 8);
+            if rest.len() < size {
+                return Err(JpegParseError::TooShort);
+            }
+
+            let values = if precision == 8 {
+                rest[..size].iter().map(|&v| v as u16).collect()
+            } else {
+                rest[..size]
+                    .chunks_exact(2)
+                    .map(|v| u16::from_be_bytes([v[0], v[1]]))
+                    .collect()
+            };
+            self.q_tables.push(QuantizationTable {
+                id: pq_tq & 0x0F,
+                precision,
+                values,
+            });
+            segment = &rest[size..];
+        }
+        Ok(())
+    }
+
+    fn read_frame(&mut self, marker: u8, segment: &[u8]) -> Result<(), JpegParseError> {
+        // precision(1) + height(2) + width(2) + count(1), then 3 bytes per component
+        if segment.len() < 6 {
+            return Err(JpegParseError::TooShort);
+        }
+        self.sof_marker = marker;
+        self.height = u16::from_be_bytes([segment[1], segment[2]]);
+        self.width = u16::from_be_bytes([segment[3], segment[4]]);
+
+        let count = segment[5] as usize;
+        let components = segment[6..].chunks_exact(3).take(count).map(|c| Component {
+            id: c[0],
+            horizontal: c[1] >> 4,
+            vertical: c[1] & 0x0F,
+            q_table: c[2],
+        });
+        self.components = components.collect();
+        if self.components.len() != count {
+            return Err(JpegParseError::TooShort);
+        }
+        Ok(())
+    }
+
+    fn find_issues(&self) -> Vec<ProbeIssue> {
+        let mut issues = Vec::new();
+
+        if self.sof_marker != SOF0 {
+            issues.push(ProbeIssue::NotBaseline {
+                marker: self.sof_marker,
+            });
+        }
+
+        if self.components.len() != 3 {
+            issues.push(ProbeIssue::ComponentCount(self.components.len() as u8));
+        } else {
+            let y = self.components[0];
+            let chroma_full = self.components[1..]
+                .iter()
+                .all(|c| c.horizontal == 1 && c.vertical == 1);
+            let luma_ok = matches!((y.horizontal, y.vertical), (2, 2) | (2, 1));
+            if !(luma_ok && chroma_full) {
+                issues.push(ProbeIssue::UnsupportedSampling {
+                    horizontal: y.horizontal,
+                    vertical: y.vertical,
+                });
+            }
+        }
+
+        if self.q_tables.is_empty() {
+            issues.push(ProbeIssue::MissingTables);
+        } else if self.q_tables.iter().any(|t| t.precision == 16) {
+            issues.push(ProbeIssue::SixteenBitTables);
+        }
+
+        if self.restart_interval > 0 {
+            issues.push(ProbeIssue::RestartMarkers {
+                interval: self.restart_interval,
+            });
+        }
+
+        let (width, height) = (self.width as u32, self.height as u32);
+        if width > MAX_DIMENSION || height > MAX_DIMENSION {
+            issues.push(ProbeIssue::OversizeDimensions);
+        }
+        if width % 8 != 0 || height % 8 != 0 {
+            issues.push(ProbeIssue::UnalignedDimensions);
+        }
+
+        issues
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segment(jpeg: &mut Vec<u8>, marker: u8, body: &[u8]) {
+        jpeg.extend([0xFF, marker]);
+        jpeg.extend(((body.len() + 2) as u16).to_be_bytes());
+        jpeg.extend(body);
+    }
+
+    /// SOI, two 8-bit tables, a frame with the given SOF marker and luma
+    /// sampling, optional DRI, SOS, four scan bytes and EOI
+    fn jpeg(sof: u8, width: u16, height: u16, luma: u8, restart: u16) -> Vec<u8> {
+        let mut jpeg = vec![0xFF, 0xD8];
+
+        let mut tables = vec![0x00];
+        tables.extend([16u8; 64]);
+        tables.push(0x01);
+        tables.extend([17u8; 64]);
+        segment(&mut jpeg, DQT, &tables);
+
+        let mut frame = vec![8];
+        frame.extend(height.to_be_bytes());
+        frame.extend(width.to_be_bytes());
+        frame.extend([3, 1, luma, 0, 2, 0x11, 1, 3, 0x11, 1]);
+        segment(&mut jpeg, sof, &frame);
+
+        if restart > 0 {
+            segment(&mut jpeg, 0xDD, &restart.to_be_bytes());
+        }
+
+        segment(&mut jpeg, SOS, &[3, 1, 0x00, 2, 0x11, 3, 0x11, 0, 63, 0]);
+        jpeg.extend([0x12, 0x34, 0x56, 0x78]);
+        jpeg.extend([0xFF, 0xD9]);
+        jpeg
+    }
+
+    #[test]
+    fn test_baseline_420_is_compatible() {
+        let probe = JpegProbe::new(&jpeg(SOF0, 640, 480, 0x22, 0)).unwrap();
+
+        assert_eq!((probe.width, probe.height), (640, 480));
+        assert_eq!(probe.jpeg_type, 0);
+        assert_eq!(probe.q_tables.len(), 2);
+        assert_eq!(probe.q_tables[1].id, 1);
+        assert_eq!(probe.q_tables[1].values, vec![17; 64]);
+        assert_eq!(probe.components.len(), 3);
+        assert_eq!(probe.scan_size, 4);
+        assert!(probe.is_compatible(), "{:?}", probe.issues);
+    }
+
+    #[test]
+    fn test_422_with_restart_interval() {
+        let probe = JpegProbe::new(&jpeg(SOF0, 1280, 720, 0x21, 8)).unwrap();
+
+        assert_eq!(probe.jpeg_type, 1);
+        assert_eq!(probe.restart_interval, 8);
+        assert_eq!(
+            probe.issues,
+            vec![ProbeIssue::RestartMarkers { interval: 8 }]
+        );
+    }
+
+    #[test]
+    fn test_progressive_444_reports_issues() {
+        let probe = JpegProbe::new(&jpeg(0xC2, 2100, 1001, 0x11, 0)).unwrap();
+
+        assert_eq!(probe.sof_marker, 0xC2);
+        assert_eq!(
+            probe.issues,
+            vec![
+                ProbeIssue::NotBaseline { marker: 0xC2 },
+                ProbeIssue::UnsupportedSampling {
+                    horizontal: 1,
+                    vertical: 1
+                },
+                ProbeIssue::OversizeDimensions,
+                ProbeIssue::UnalignedDimensions,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_truncated_headers() {
+        let data = jpeg(SOF0, 640, 480, 0x22, 0);
+        assert!(JpegProbe::new(&data[..40]).is_err());
+        assert!(JpegProbe::new(&[0xFF, 0xD8, 0xFF, 0xD9]).is_err());
+    }
+}
@@ -0,0 +1,288 @@
+//! Minimal RTCP packet construction (RFC 3550 Section 6)
+//!
+//! Receiver reports are also parsed, for the loss and jitter they carry.
+
+use super::RTP_VERSION;
+use crate::congestion::RtcpFeedback;
+use bytes::{BufMut, Bytes, BytesMut};
+
+/// RTCP packet type for sender reports
+pub const RTCP_PT_SR: u8 = 200;
+
+/// RTCP packet type for receiver reports
+pub const RTCP_PT_RR: u8 = 201;
+
+/// RTCP packet type for source descriptions
+pub const RTCP_PT_SDES: u8 = 202;
+
+/// RTCP packet type for goodbye
+pub const RTCP_PT_BYE: u8 = 203;
+
+/// SDES item type for the canonical name
+const SDES_CNAME: u8 = 1;
+
+/// Builds an SDES packet carrying a single CNAME item for `ssrc`
+///
+/// ```text
+///  0                   1                   2                   3
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// |V=2|P|  SC=1   |  PT=SDES=202  |             length            |
+/// +=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+
+/// |                              SSRC                             |
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// |    CNAME=1    |     length    | user and domain name ...      |
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// ```
+///
+/// CNAMEs longer than 255 bytes are truncated.
+pub fn build_sdes_cname(ssrc: u32, cname: &str) -> Bytes {
+    let text = &cname.as_bytes()[..cname.len().min(255)];
+
+    // SSRC + item header + text + at least one null terminator, 32-bit aligned
+    let chunk_len = (4 + 2 + text.len() + 1).div_ceil(4) * 4;
+    let mut buf = BytesMut::with_capacity(4 + chunk_len);
+
+    buf.put_u8((RTP_VERSION << 6) | 1); // V=2, P=0, SC=1
+    buf.put_u8(RTCP_PT_SDES);
+    buf.put_u16((chunk_len / 4) as u16); // length in words minus one (header word)
+
+    buf.put_u32(ssrc);
+    buf.put_u8(SDES_CNAME);
+    buf.put_u8(text.len() as u8);
+    buf.put_slice(text);
+    buf.put_bytes(0, 4 + chunk_len - buf.len());
+
+    buf.freeze()
+}
+
+/// Builds the compound packet announcing `ssrc`'s CNAME (RFC 3550 Section
+/// 6.1): an empty receiver report, since a compound packet must start with
+/// a report, then the SDES
+pub fn build_cname_report(ssrc: u32, cname: &str) -> Bytes {
+    let mut buf = BytesMut::from(&build_receiver_report(ssrc, &[])[..]);
+    buf.extend_from_slice(&build_sdes_cname(ssrc, cname));
+    buf.freeze()
+}
+
+/// Builds a BYE packet announcing that `ssrc` is leaving the session
+///
+/// ```text
+///  0                   1                   2                   3
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// |V=2|P|  SC=1   |  PT=BYE=203   |           length=1            |
+/// +=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+
+/// |                              SSRC                             |
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// ```
+pub fn build_bye(ssrc: u32) -> Bytes {
+    let mut buf = BytesMut::with_capacity(8);
+    buf.put_u8((RTP_VERSION << 6) | 1); // V=2, P=0, SC=1
+    buf.put_u8(RTCP_PT_BYE);
+    buf.put_u16(1);
+    buf.put_u32(ssrc);
+    buf.freeze()
+}
+
+/// Builds the compound packet `ssrc` leaves the session with (RFC 3550
+/// Section 6.1): an empty receiver report, the SDES CNAME if there is one,
+/// then the BYE, which must come last
+pub fn build_goodbye(ssrc: u32, cname: Option<&str>) -> Bytes {
+    let mut buf = match cname {
+        Some(cname) => BytesMut::from(&build_cname_report(ssrc, cname)[..]),
+        None => BytesMut::from(&build_receiver_report(ssrc, &[])[..]),
+    };
+    buf.extend_from_slice(&build_bye(ssrc));
+    buf.freeze()
+}
+
+/// One reception report block of a sender or receiver report (RFC 3550
+/// Section 6.4.1), describing how `ssrc` is being received
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ReportBlock {
+    pub ssrc: u32,
+    /// Fraction lost since the previous report, in 1/256
+    pub fraction_lost: u8,
+    /// Packets lost since the start of reception (24-bit, signed)
+    pub cumulative_lost: i32,
+    /// Highest sequence number received, extended with the wrap count
+    pub highest_seq: u32,
+    /// Interarrival jitter in RTP timestamp units
+    pub jitter: u32,
+    pub last_sr: u32,
+    pub delay_since_last_sr: u32,
+}
+
+impl ReportBlock {
+    const SIZE: usize = 24;
+
+    fn put(&self, buf: &mut BytesMut) {
+        buf.put_u32(self.ssrc);
+        buf.put_u8(self.fraction_lost);
+        let lost = self.cumulative_lost.clamp(-0x80_0000, 0x7F_FFFF) as u32 & 0xFF_FFFF;
+        buf.put_slice(&lost.to_be_bytes()[1..]);
+        buf.put_u32(self.highest_seq);
+        buf.put_u32(self.jitter);
+        buf.put_u32(self.last_sr);
+        buf.put_u32(self.delay_since_last_sr);
+    }
+
+    fn parse(data: &[u8]) -> Self {
+        let word = |i: usize| u32::from_be_bytes([data[i], data[i + 1], data[i + 2], data[i + 3]]);
+        // Sign-extend the 24-bit count
+        let lost = ((word(4) << 8) as i32) >> 8;
+        Self {
+            ssrc: word(0),
+            fraction_lost: data[4],
+            cumulative_lost: lost,
+            highest_seq: word(8),
+            jitter: word(12),
+            last_sr: word(16),
+            delay_since_last_sr: word(20),
+        }
+    }
+
+    /// The block as congestion controller input
+    pub fn feedback(&self) -> RtcpFeedback {
+        RtcpFeedback {
+            fraction_lost: self.fraction_lost as f32 / 256.0,
+            jitter: self.jitter,
+            rtt: None,
+        }
+    }
+}
+
+/// Builds a receiver report from `reporter_ssrc` with up to 31 blocks
+///
+/// ```text
+///  0                   1                   2                   3
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// |V=2|P|    RC   |   PT=RR=201   |             length            |
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// |                     SSRC of packet sender                     |
+/// +=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+
+/// |                 report blocks, 24 bytes each                  |
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// ```
+pub fn build_receiver_report(reporter_ssrc: u32, blocks: &[ReportBlock]) -> Bytes {
+    let blocks = &blocks[..blocks.len().min(31)];
+    let mut buf = BytesMut::with_capacity(8 + blocks.len() * ReportBlock::SIZE);
+    buf.put_u8((RTP_VERSION << 6) | blocks.len() as u8);
+    buf.put_u8(RTCP_PT_RR);
+    buf.put_u16((1 + blocks.len() * ReportBlock::SIZE / 4) as u16);
+    buf.put_u32(reporter_ssrc);
+    for block in blocks {
+        block.put(&mut buf);
+    }
+    buf.freeze()
+}
+
+/// Report blocks of every SR and RR in a (compound) RTCP packet. Malformed
+/// packets end the walk; whatever was parsed before is returned.
+pub fn parse_report_blocks(mut data: &[u8]) -> Vec<ReportBlock> {
+    let mut blocks = Vec::new();
+    while data.len() >= 4 && data[0] >> 6 == RTP_VERSION {
+        let count = (data[0] & 0x1F) as usize;
+        let len = (u16::from_be_bytes([data[2], data[3]]) as usize + 1) * 4;
+        if len > data.len() {
+            break;
+        }
+        // Sender info (20 bytes) comes before the blocks of an SR
+        let first = match data[1] {
+            RTCP_PT_SR => Some(28),
+            RTCP_PT_RR => Some(8),
+            _ => None,
+        };
+        if let Some(first) = first {
+            blocks.extend(
+                (0..count)
+                    .map(|i| first + i * ReportBlock::SIZE)
+                    .take_while(|&at| at + ReportBlock::SIZE <= len)
+                    .map(|at| ReportBlock::parse(&data[at..at + ReportBlock::SIZE])),
+            );
+        }
+        data = &data[len..];
+    }
+    blocks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sdes_layout() {
+        let pkt = build_sdes_cname(0xDEADBEEF, "cam@pi");
+
+        assert_eq!(pkt.len() % 4, 0);
+        assert_eq!(pkt[0], 0x81);
+        assert_eq!(pkt[1], RTCP_PT_SDES);
+        let words = u16::from_be_bytes([pkt[2], pkt[3]]) as usize;
+        assert_eq!((words + 1) * 4, pkt.len());
+        assert_eq!(&pkt[4..8], &0xDEADBEEFu32.to_be_bytes());
+        assert_eq!(pkt[8], SDES_CNAME);
+        assert_eq!(pkt[9], 6);
+        assert_eq!(&pkt[10..16], b"cam@pi");
+        assert!(pkt[16..].iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn test_sdes_always_null_terminated() {
+        // 4 + 2 + 2 = 8 bytes of chunk: the terminator forces another word
+        let pkt = build_sdes_cname(1, "ab");
+        assert_eq!(pkt.len(), 16);
+        assert_eq!(pkt[12], 0);
+    }
+
+    #[test]
+    fn test_cname_report_is_compound() {
+        let pkt = build_cname_report(0xDEADBEEF, "cam@pi");
+        assert_eq!(&pkt[..8], &build_receiver_report(0xDEADBEEF, &[])[..]);
+        assert_eq!(&pkt[8..], &build_sdes_cname(0xDEADBEEF, "cam@pi")[..]);
+        assert!(parse_report_blocks(&pkt).is_empty());
+    }
+
+    #[test]
+    fn test_bye_layout() {
+        let pkt = build_bye(0xDEADBEEF);
+        assert_eq!(&pkt[..], &[0x81, RTCP_PT_BYE, 0, 1, 0xDE, 0xAD, 0xBE, 0xEF]);
+    }
+
+    #[test]
+    fn test_goodbye_is_compound() {
+        let pkt = build_goodbye(0xDEADBEEF, Some("cam@pi"));
+        let report = build_cname_report(0xDEADBEEF, "cam@pi");
+        assert_eq!(&pkt[..report.len()], &report[..]);
+        assert_eq!(&pkt[report.len()..], &build_bye(0xDEADBEEF)[..]);
+
+        let pkt = build_goodbye(0xDEADBEEF, None);
+        assert_eq!(pkt[1], RTCP_PT_RR);
+        assert_eq!(&pkt[8..], &build_bye(0xDEADBEEF)[..]);
+    }
+
+    #[test]
+    fn test_receiver_report_roundtrip() {
+        let block = ReportBlock {
+            ssrc: 0x1234_5678,
+            fraction_lost: 64,
+            cumulative_lost: -3,
+            highest_seq: 0x0001_0005,
+            jitter: 900,
+            last_sr: 7,
+            delay_since_last_sr: 8,
+        };
+        let pkt = build_receiver_report(0xCAFE, &[block]);
+        assert_eq!(pkt.len(), 32);
+        assert_eq!(pkt[0], 0x81);
+        assert_eq!(pkt[1], RTCP_PT_RR);
+        assert_eq!(u16::from_be_bytes([pkt[2], pkt[3]]), 7);
+
+        // Found inside a compound packet too
+        let mut compound = build_sdes_cname(0xCAFE, "echo").to_vec();
+        compound.extend_from_slice(&pkt);
+        assert_eq!(parse_report_blocks(&compound), vec![block]);
+        assert_eq!(block.feedback().fraction_lost, 0.25);
+
+        assert!(parse_report_blocks(&build_bye(1)).is_empty());
+        assert!(parse_report_blocks(&pkt[..20]).is_empty());
+    }
+}
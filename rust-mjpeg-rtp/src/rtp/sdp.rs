@@ -0,0 +1,212 @@
+//! SDP session description for receivers (RFC 4566)
+//!
+//! Describes one camera's stream the way a receiver needs it: where RTP
+//! arrives, where RTCP goes (the next port up, or the same port with
+//! rtcp-mux), and the attributes RFC 2435 can't carry in-band. With
+//! `bundle`, [`BundleDescription`] puts both cameras in one session.
+
+use super::frame_info::sdp_extmap_attribute;
+use super::jpeg::{sdp_dimensions_attribute, MAX_DIMENSION};
+use super::{RTP_CLOCK_RATE, RTP_PAYLOAD_TYPE_JPEG};
+use std::fmt;
+use std::net::IpAddr;
+
+/// Everything a receiver needs to open one MJPEG stream
+#[derive(Debug, Clone)]
+pub struct SessionDescription {
+    /// Session name (`s=`)
+    pub name: String,
+    /// Address the stream is sent to (`c=`)
+    pub dest_ip: IpAddr,
+    /// RTP port the stream is sent to (`m=`)
+    pub dest_port: u16,
+    /// RTCP shares `dest_port` instead of using `dest_port + 1`
+    pub rtcp_mux: bool,
+    pub width: u32,
+    pub height: u32,
+    pub fps: u32,
+    /// Announced with its CNAME when both are known
+    pub ssrc: Option<u32>,
+    pub cname: Option<String>,
+    /// Header extension ID of the frame-info extension, when enabled
+    pub frame_info_id: Option<u8>,
+}
+
+impl SessionDescription {
+    fn family(&self) -> &'static str {
+        if self.dest_ip.is_ipv4() {
+            "IP4"
+        } else {
+            "IP6"
+        }
+    }
+
+    /// Session-level lines, up to the first `m=`
+    fn write_session(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // SDP lines end in CRLF (RFC 4566 Section 5)
+        write!(f, "v=0\r\n")?;
+        write!(
+            f,
+            "o=- {} 0 IN {} {}\r\n",
+            self.ssrc.unwrap_or(0),
+            self.family(),
+            self.dest_ip
+        )?;
+        write!(f, "s={}\r\n", self.name)?;
+        write!(f, "c=IN {} {}\r\n", self.family(), self.dest_ip)?;
+        write!(f, "t=0 0\r\n")
+    }
+
+    /// The stream's `m=` section; tagged with `mid` inside a bundle, where
+    /// the SSRC is always announced as receivers demultiplex by it
+    fn write_media(&self, f: &mut fmt::Formatter<'_>, mid: Option<&str>) -> fmt::Result {
+        write!(
+            f,
+            "m=video {} RTP/AVP {}\r\n",
+            self.dest_port, RTP_PAYLOAD_TYPE_JPEG
+        )?;
+        write!(
+            f,
+            "a=rtpmap:{} JPEG/{}\r\n",
+            RTP_PAYLOAD_TYPE_JPEG, RTP_CLOCK_RATE
+        )?;
+        if let Some(mid) = mid {
+            write!(f, "a=mid:{}\r\n", mid)?;
+        }
+        if self.rtcp_mux {
+            write!(f, "a=rtcp-mux\r\n")?;
+        } else if let Some(rtcp_port) = self.dest_port.checked_add(1) {
+            write!(f, "a=rtcp:{}\r\n", rtcp_port)?;
+        }
+        write!(f, "a=framerate:{}\r\n", self.fps)?;
+        if self.width > MAX_DIMENSION || self.height > MAX_DIMENSION {
+            write!(
+                f,
+                "{}\r\n",
+                sdp_dimensions_attribute(self.width, self.height)
+            )?;
+        }
+        if let Some(id) = self.frame_info_id {
+            write!(f, "{}\r\n", sdp_extmap_attribute(id))?;
+        }
+        let cname = self.cname.as_deref().or(mid);
+        if let (Some(ssrc), Some(cname)) = (self.ssrc, cname) {
+            write!(f, "a=ssrc:{} cname:{}\r\n", ssrc, cname)?;
+        }
+        write!(f, "a=recvonly\r\n")
+    }
+}
+
+impl fmt::Display for SessionDescription {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write_session(f)?;
+        self.write_media(f, None)
+    }
+}
+
+/// Several streams sent from one socket to one port (RFC 8843), each in its
+/// own `m=` section named after its session
+#[derive(Debug, Clone)]
+pub struct BundleDescription {
+    /// Session name (`s=`)
+    pub name: String,
+    /// Must share `dest_ip` and `dest_port`
+    pub streams: Vec<SessionDescription>,
+}
+
+impl fmt::Display for BundleDescription {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Some(first) = self.streams.first() else {
+            return Ok(());
+        };
+        SessionDescription {
+            name: self.name.clone(),
+            ..first.clone()
+        }
+        .write_session(f)?;
+        write!(f, "a=group:BUNDLE")?;
+        for stream in &self.streams {
+            write!(f, " {}", stream.name)?;
+        }
+        write!(f, "\r\n")?;
+        for stream in &self.streams {
+            stream.write_media(f, Some(&stream.name))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    fn description() -> SessionDescription {
+        SessionDescription {
+            name: "camera1".to_string(),
+            dest_ip: IpAddr::V4(Ipv4Addr::new(192, 168, 1, 100)),
+            dest_port: 5000,
+            rtcp_mux: false,
+            width: 1920,
+            height: 1080,
+            fps: 30,
+            ssrc: None,
+            cname: None,
+            frame_info_id: None,
+        }
+    }
+
+    #[test]
+    fn test_rtcp_pair() {
+        let sdp = description().to_string();
+        assert!(sdp.starts_with("v=0\r\n"));
+        assert!(sdp.contains("c=IN IP4 192.168.1.100\r\n"));
+        assert!(sdp.contains("m=video 5000 RTP/AVP 26\r\n"));
+        assert!(sdp.contains("a=rtpmap:26 JPEG/90000\r\n"));
+        assert!(sdp.contains("a=rtcp:5001\r\n"));
+        assert!(!sdp.contains("rtcp-mux"));
+        assert!(!sdp.contains("x-dimensions"));
+    }
+
+    #[test]
+    fn test_rtcp_mux_and_optional_attributes() {
+        let sdp = SessionDescription {
+            rtcp_mux: true,
+            width: 3840,
+            height: 2160,
+            ssrc: Some(0xDEADBEEF),
+            cname: Some("cam@pi".to_string()),
+            frame_info_id: Some(1),
+            ..description()
+        }
+        .to_string();
+        assert!(sdp.contains("a=rtcp-mux\r\n"));
+        assert!(!sdp.contains("a=rtcp:"));
+        assert!(sdp.contains("a=x-dimensions:3840,2160\r\n"));
+        assert!(sdp.contains("a=extmap:1 "));
+        assert!(sdp.contains("a=ssrc:3735928559 cname:cam@pi\r\n"));
+    }
+
+    #[test]
+    fn test_bundle() {
+        let stream = |name: &str, ssrc| SessionDescription {
+            name: name.to_string(),
+            rtcp_mux: true,
+            ssrc: Some(ssrc),
+            ..description()
+        };
+        let sdp = BundleDescription {
+            name: "cameras".to_string(),
+            streams: vec![stream("camera1", 1), stream("camera2", 2)],
+        }
+        .to_string();
+        assert_eq!(sdp.matches("v=0\r\n").count(), 1);
+        assert!(sdp.contains("s=cameras\r\n"));
+        assert!(sdp.contains("a=group:BUNDLE camera1 camera2\r\n"));
+        assert_eq!(sdp.matches("m=video 5000 RTP/AVP 26\r\n").count(), 2);
+        assert_eq!(sdp.matches("a=rtcp-mux\r\n").count(), 2);
+        let second = &sdp[sdp.find("a=mid:camera2").unwrap()..];
+        assert!(second.contains("a=ssrc:2 cname:camera2\r\n"));
+        assert!(!second.contains("a=ssrc:1 "));
+    }
+}
@@ -0,0 +1,301 @@
+//! Periodic stats log with rates
+//!
+//! The counters in [`CaptureStats`] and [`StreamerStats`] only ever grow, so
+//! reading a log of them means subtracting one line from the next to see how
+//! the stream was doing at the time. [`StatsReporter`] keeps the previous
+//! snapshot and logs what happened since: frame rates in and out, bitrate,
+//! the share of frames dropped and the packet rate.
+
+use crate::capture::CaptureStats;
+use crate::streamer::StreamerStats;
+use std::time::Instant;
+use tracing::info;
+
+/// What happened over one reporting interval
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct StatsRates {
+    /// Frames delivered by the capture per second
+    pub fps_in: f64,
+
+    /// Frames sent per second
+    pub fps_out: f64,
+
+    /// JPEG payload sent, in Mbit/s
+    pub mbps: f64,
+
+    /// Frames lost in capture or before sending, as a percentage of the
+    /// frames the camera produced. Decimate and dedup drops are deliberate
+    /// and not included.
+    pub drop_percent: f64,
+
+    /// RTP packets sent per second
+    pub packets_per_sec: f64,
+
+    /// Frames dropped per stage
+    pub dropped_pre_encode: u64,
+    pub dropped_in_appsink: u64,
+    pub dropped_capture_channel: u64,
+    pub dropped_send_channel: u64,
+    pub dropped_unreachable: u64,
+    pub dropped_by_shaper: u64,
+    pub dropped_by_pacer: u64,
+
+    /// Time frames were held back by the egress rate limits
+    pub shaper_delay_ms: u64,
+
+    /// Frames with send errors
+    pub send_errors: u64,
+
+    /// Frames missing from the `frame_id` sequence
+    pub frames_missing: u64,
+
+    /// Average time for a frame's packets to leave the process
+    pub avg_wire_us: u64,
+}
+
+impl StatsRates {
+    /// Rates between two snapshots taken `elapsed_secs` apart
+    pub fn between(
+        previous: (&CaptureStats, &StreamerStats),
+        current: (&CaptureStats, &StreamerStats),
+        elapsed_secs: f64,
+    ) -> Self {
+        let ((prev_capture, prev_streamer), (capture, streamer)) = (previous, current);
+        let delta = |now: u64, before: u64| now.saturating_sub(before);
+        let per_sec = |count: u64| {
+            if elapsed_secs > 0.0 {
+                count as f64 / elapsed_secs
+            } else {
+                0.0
+            }
+        };
+
+        let captured = delta(capture.frames_captured, prev_capture.frames_captured);
+        let capture_dropped = delta(capture.frames_dropped, prev_capture.frames_dropped);
+        let streamer_dropped = delta(streamer.frames_dropped, prev_streamer.frames_dropped);
+        let produced = captured + capture_dropped;
+        let drop_percent = if produced > 0 {
+            ((capture_dropped + streamer_dropped) as f64 * 100.0 / produced as f64).min(100.0)
+        } else {
+            0.0
+        };
+
+        // The timing snapshot holds running averages; weigh them back into
+        // totals to get this interval's average
+        let timed = delta(
+            streamer.send_timing.frames,
+            prev_streamer.send_timing.frames,
+        );
+        let wire_total = delta(
+            streamer.send_timing.avg_wire_us * streamer.send_timing.frames,
+            prev_streamer.send_timing.avg_wire_us * prev_streamer.send_timing.frames,
+        );
+
+        Self {
+            fps_in: per_sec(captured),
+            fps_out: streamer.calculate_fps(prev_streamer, elapsed_secs),
+            mbps: streamer.calculate_bitrate_kbps(prev_streamer, elapsed_secs) / 1000.0,
+            drop_percent,
+            packets_per_sec: per_sec(delta(
+                streamer.rtp_packets_sent,
+                prev_streamer.rtp_packets_sent,
+            )),
+            dropped_pre_encode: delta(capture.dropped_pre_encode, prev_capture.dropped_pre_encode),
+            dropped_in_appsink: delta(capture.dropped_in_appsink, prev_capture.dropped_in_appsink),
+            dropped_capture_channel: delta(
+                capture.dropped_in_channel,
+                prev_capture.dropped_in_channel,
+            ),
+            dropped_send_channel: delta(
+                streamer.dropped_in_channel,
+                prev_streamer.dropped_in_channel,
+            ),
+            dropped_unreachable: delta(
+                streamer.dropped_unreachable,
+                prev_streamer.dropped_unreachable,
+            ),
+            dropped_by_shaper: delta(streamer.dropped_by_shaper, prev_streamer.dropped_by_shaper),
+            dropped_by_pacer: delta(streamer.dropped_by_pacer, prev_streamer.dropped_by_pacer),
+            shaper_delay_ms: delta(streamer.shaping.delay_us, prev_streamer.shaping.delay_us)
+                / 1000,
+            send_errors: delta(streamer.send_errors, prev_streamer.send_errors),
+            frames_missing: delta(
+                streamer.health.frames_missing,
+                prev_streamer.health.frames_missing,
+            ),
+            avg_wire_us: wire_total / timed.max(1),
+        }
+    }
+}
+
+/// Logs one camera's stats as rates since the previous report
+pub struct StatsReporter {
+    camera: String,
+    last_at: Instant,
+    last_capture: CaptureStats,
+    last_streamer: StreamerStats,
+}
+
+impl StatsReporter {
+    /// A reporter whose first interval starts now, from zeroed counters
+    pub fn new(camera: impl Into<String>) -> Self {
+        Self {
+            camera: camera.into(),
+            last_at: Instant::now(),
+            last_capture: CaptureStats::default(),
+            last_streamer: StreamerStats::default(),
+        }
+    }
+
+    /// Rates since the previous call (or since [`StatsReporter::new`]),
+    /// keeping these snapshots for the next one
+    pub fn rates(
+        &mut self,
+        now: Instant,
+        capture: CaptureStats,
+        streamer: StreamerStats,
+    ) -> StatsRates {
+        let elapsed = now.saturating_duration_since(self.last_at).as_secs_f64();
+        let rates = StatsRates::between(
+            (&self.last_capture, &self.last_streamer),
+            (&capture, &streamer),
+            elapsed,
+        );
+        self.last_at = now;
+        self.last_capture = capture;
+        self.last_streamer = streamer;
+        rates
+    }
+
+    /// Logs the rates since the previous report, along with the stream's
+    /// current health
+    pub fn report(&mut self, capture: CaptureStats, streamer: StreamerStats) {
+        let health = streamer.health.clone();
+        let drift_ms = streamer.clock_drift.drift_ms.round();
+        let r = self.rates(Instant::now(), capture, streamer);
+        info!(
+            camera = %self.camera,
+            fps_in = %format!("{:.1}", r.fps_in),
+            fps_out = %format!("{:.1}", r.fps_out),
+            mbps = %format!("{:.2}", r.mbps),
+            drop_pct = %format!("{:.1}", r.drop_percent),
+            pps = %format!("{:.0}", r.packets_per_sec),
+            dropped_pre_encode = r.dropped_pre_encode,
+            dropped_in_appsink = r.dropped_in_appsink,
+            dropped_capture_channel = r.dropped_capture_channel,
+            dropped_send_channel = r.dropped_send_channel,
+            dropped_unreachable = r.dropped_unreachable,
+            dropped_by_shaper = r.dropped_by_shaper,
+            dropped_by_pacer = r.dropped_by_pacer,
+            shaper_delay_ms = r.shaper_delay_ms,
+            send_errors = r.send_errors,
+            missing = r.frames_missing,
+            avg_wire_us = r.avg_wire_us,
+            health = health.score,
+            status = ?health.status,
+            drift_ms,
+            "Stats"
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn capture(captured: u64, pre_encode: u64, channel: u64) -> CaptureStats {
+        CaptureStats {
+            frames_captured: captured,
+            frames_dropped: pre_encode + channel,
+            dropped_pre_encode: pre_encode,
+            dropped_in_channel: channel,
+            ..Default::default()
+        }
+    }
+
+    fn streamer(sent: u64, bytes: u64, packets: u64, unreachable: u64) -> StreamerStats {
+        StreamerStats {
+            frames_sent: sent,
+            bytes_sent: bytes,
+            rtp_packets_sent: packets,
+            frames_dropped: unreachable,
+            dropped_unreachable: unreachable,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_rates_from_deltas() {
+        let start = Instant::now();
+        let mut reporter = StatsReporter::new("camera1");
+        reporter.last_at = start;
+
+        // 10 s in: 300 frames captured, 290 sent
+        let r = reporter.rates(
+            start + Duration::from_secs(10),
+            capture(300, 0, 0),
+            streamer(290, 12_500_000, 3000, 10),
+        );
+        assert_eq!(r.fps_in, 30.0);
+        assert_eq!(r.fps_out, 29.0);
+        assert_eq!(r.mbps, 10.0);
+        assert_eq!(r.packets_per_sec, 300.0);
+        assert!((r.drop_percent - 10.0 * 100.0 / 300.0).abs() < 1e-9);
+        assert_eq!(r.dropped_unreachable, 10);
+
+        // The next interval only counts what happened in it
+        let r = reporter.rates(
+            start + Duration::from_secs(15),
+            capture(375, 15, 10),
+            streamer(365, 15_000_000, 3750, 10),
+        );
+        assert_eq!(r.fps_in, 15.0);
+        assert_eq!(r.fps_out, 15.0);
+        assert_eq!(r.mbps, 4.0);
+        assert_eq!(r.packets_per_sec, 150.0);
+        assert_eq!(r.drop_percent, 25.0);
+        assert_eq!((r.dropped_pre_encode, r.dropped_capture_channel), (15, 10));
+        assert_eq!(r.dropped_unreachable, 0);
+    }
+
+    #[test]
+    fn test_shaper_rates() {
+        let shaped = |dropped: u64, delay_us: u64| StreamerStats {
+            frames_dropped: dropped,
+            dropped_by_shaper: dropped,
+            shaping: crate::streamer::ShapingStats {
+                delay_us,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let r = StatsRates::between(
+            (&capture(100, 0, 0), &shaped(2, 40_000)),
+            (&capture(130, 0, 0), &shaped(5, 250_000)),
+            1.0,
+        );
+        assert_eq!(r.dropped_by_shaper, 3);
+        assert_eq!(r.shaper_delay_ms, 210);
+        assert_eq!(r.drop_percent, 10.0);
+    }
+
+    #[test]
+    fn test_idle_interval() {
+        let start = Instant::now();
+        let mut reporter = StatsReporter::new("camera1");
+        reporter.last_at = start;
+        let r = reporter.rates(start, CaptureStats::default(), StreamerStats::default());
+        assert_eq!(r, StatsRates::default());
+
+        // Counters reset by a restart don't produce huge rates
+        reporter.rates(start, capture(100, 0, 0), streamer(100, 1000, 100, 0));
+        let r = reporter.rates(
+            start + Duration::from_secs(1),
+            capture(5, 0, 0),
+            streamer(5, 50, 5, 0),
+        );
+        assert_eq!(r.fps_in, 0.0);
+        assert_eq!(r.mbps, 0.0);
+    }
+}
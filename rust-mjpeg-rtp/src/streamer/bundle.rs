@@ -0,0 +1,69 @@
+//! One local socket shared by several streamers (BUNDLE)
+//!
+//! With `bundle` set, both cameras send RTP and RTCP from the same local
+//! port to the same destination port, so a firewall needs one rule instead
+//! of one per camera. The receiver tells the streams apart by SSRC (both use
+//! payload type 26, which RFC 8843 allows for identical codec settings),
+//! guided by the `a=ssrc` lines of the bundled SDP.
+
+use std::sync::{Arc, Mutex, Weak};
+use tokio::net::UdpSocket;
+
+/// A socket bound by the first streamer to start and closed once every
+/// streamer using it has stopped; clones refer to the same one
+#[derive(Debug, Clone, Default)]
+pub struct SharedSocket {
+    socket: Arc<Mutex<Weak<UdpSocket>>>,
+}
+
+impl SharedSocket {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The socket in use, or a new one from `bind` if there is none
+    pub fn get_or_bind<E>(
+        &self,
+        bind: impl FnOnce() -> Result<UdpSocket, E>,
+    ) -> Result<Arc<UdpSocket>, E> {
+        let mut current = self.socket.lock().unwrap();
+        if let Some(socket) = current.upgrade() {
+            return Ok(socket);
+        }
+        let socket = Arc::new(bind()?);
+        *current = Arc::downgrade(&socket);
+        Ok(socket)
+    }
+
+    /// Whether some streamer holds the socket
+    pub fn is_bound(&self) -> bool {
+        self.socket.lock().unwrap().strong_count() > 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_bound_while_used() {
+        let shared = SharedSocket::new();
+        let bind = || {
+            let socket = std::net::UdpSocket::bind("127.0.0.1:0")?;
+            socket.set_nonblocking(true)?;
+            UdpSocket::from_std(socket)
+        };
+        let first = shared.get_or_bind(bind).unwrap();
+        let second = shared.clone().get_or_bind(bind).unwrap();
+        assert_eq!(first.local_addr().unwrap(), second.local_addr().unwrap());
+
+        drop(first);
+        assert!(shared.is_bound());
+        drop(second);
+        assert!(!shared.is_bound());
+        let failed: Result<_, std::io::Error> =
+            shared.get_or_bind(|| Err(std::io::ErrorKind::AddrInUse.into()));
+        assert!(failed.is_err());
+        assert!(!shared.is_bound());
+    }
+}
@@ -0,0 +1,205 @@
+//! Extra RTP destinations added and removed at runtime
+//!
+//! Besides its configured destination, a streamer can copy its packets to
+//! any number of extra ones, e.g. a ground station subscribing to the raw
+//! feed on demand, without restarting the camera pipeline. Extra
+//! destinations get exactly the primary's packets (same SSRC, sequence
+//! numbers and timestamps) from a separate unconnected socket, after the
+//! primary send, so they never delay it. Failover and the unreachable
+//! backoff only concern the primary destination.
+
+use super::send::SendReport;
+use super::StreamerError;
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+/// Counters of one extra destination
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DestinationStats {
+    pub addr: SocketAddr,
+    /// Frames whose packets all went out
+    pub frames_sent: u64,
+    pub packets_sent: u64,
+    /// Bytes of the frames counted in `frames_sent`
+    pub bytes_sent: u64,
+    /// Frames with at least one failed packet
+    pub send_errors: u64,
+    /// Time since the destination was added
+    pub added_secs: u64,
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    frames_sent: AtomicU64,
+    packets_sent: AtomicU64,
+    bytes_sent: AtomicU64,
+    send_errors: AtomicU64,
+}
+
+/// An extra destination as seen by the sender
+#[derive(Debug, Clone)]
+pub(crate) struct Destination {
+    pub addr: SocketAddr,
+    added: Instant,
+    counters: Arc<Counters>,
+}
+
+impl Destination {
+    /// Accounts for one frame sent to this destination
+    pub fn record(&self, packets: &[Bytes], report: &SendReport) {
+        let counters = &self.counters;
+        counters
+            .packets_sent
+            .fetch_add(report.sent as u64, Ordering::Relaxed);
+        if report.errors > 0 {
+            counters.send_errors.fetch_add(1, Ordering::Relaxed);
+        } else {
+            let bytes: usize = packets.iter().map(Bytes::len).sum();
+            counters.frames_sent.fetch_add(1, Ordering::Relaxed);
+            counters
+                .bytes_sent
+                .fetch_add(bytes as u64, Ordering::Relaxed);
+        }
+    }
+
+    fn stats(&self) -> DestinationStats {
+        DestinationStats {
+            addr: self.addr,
+            frames_sent: self.counters.frames_sent.load(Ordering::Relaxed),
+            packets_sent: self.counters.packets_sent.load(Ordering::Relaxed),
+            bytes_sent: self.counters.bytes_sent.load(Ordering::Relaxed),
+            send_errors: self.counters.send_errors.load(Ordering::Relaxed),
+            added_secs: self.added.elapsed().as_secs(),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    /// The configured destination, which extra ones must not duplicate and
+    /// whose address family they must share
+    primary: Option<SocketAddr>,
+    extra: Vec<Destination>,
+}
+
+/// Cloneable handle to a streamer's extra destinations, usable from other
+/// tasks (e.g. an API server) while the streamer runs. The set carries over
+/// restarts.
+#[derive(Debug, Clone, Default)]
+pub struct Destinations {
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl Destinations {
+    pub(crate) fn set_primary(&self, primary: Option<SocketAddr>) {
+        self.inner.lock().unwrap().primary = primary;
+    }
+
+    /// Starts copying packets to `addr`. Returns `false` if it was already
+    /// a destination.
+    pub fn add(&self, addr: SocketAddr) -> Result<bool, StreamerError> {
+        if addr.port() == 0 || addr.ip().is_unspecified() {
+            return Err(StreamerError::InvalidDestination(format!(
+                "{} is not a usable destination",
+                addr
+            )));
+        }
+
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(primary) = inner.primary {
+            if addr == primary {
+                return Err(StreamerError::InvalidDestination(format!(
+                    "{} is the primary destination",
+                    addr
+                )));
+            }
+            if addr.is_ipv4() != primary.is_ipv4() {
+                return Err(StreamerError::InvalidDestination(format!(
+                    "{} is not in the same address family as {}",
+                    addr, primary
+                )));
+            }
+        }
+        if inner.extra.iter().any(|d| d.addr == addr) {
+            return Ok(false);
+        }
+        inner.extra.push(Destination {
+            addr,
+            added: Instant::now(),
+            counters: Arc::default(),
+        });
+        Ok(true)
+    }
+
+    /// Stops sending to `addr`. Returns `false` if it wasn't a destination.
+    pub fn remove(&self, addr: SocketAddr) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+        let before = inner.extra.len();
+        inner.extra.retain(|d| d.addr != addr);
+        inner.extra.len() != before
+    }
+
+    /// Extra destinations in the order they were added
+    pub fn list(&self) -> Vec<SocketAddr> {
+        self.inner
+            .lock()
+            .unwrap()
+            .extra
+            .iter()
+            .map(|d| d.addr)
+            .collect()
+    }
+
+    /// Per-destination counters
+    pub fn stats(&self) -> Vec<DestinationStats> {
+        self.inner
+            .lock()
+            .unwrap()
+            .extra
+            .iter()
+            .map(Destination::stats)
+            .collect()
+    }
+
+    /// The current destinations, for one frame's fan-out
+    pub(crate) fn snapshot(&self) -> Vec<Destination> {
+        self.inner.lock().unwrap().extra.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_add_remove() {
+        let destinations = Destinations::default();
+        destinations.set_primary(Some("127.0.0.1:5000".parse().unwrap()));
+        let ground: SocketAddr = "127.0.0.1:6000".parse().unwrap();
+
+        assert!(destinations.add(ground).unwrap());
+        assert!(!destinations.add(ground).unwrap());
+        assert_eq!(destinations.list(), vec![ground]);
+
+        // The primary, the wrong family and unusable addresses are refused
+        for addr in [
+            "127.0.0.1:5000",
+            "[::1]:6000",
+            "0.0.0.0:6000",
+            "127.0.0.1:0",
+        ] {
+            assert!(matches!(
+                destinations.add(addr.parse().unwrap()),
+                Err(StreamerError::InvalidDestination(_))
+            ));
+        }
+
+        assert!(destinations.remove(ground));
+        assert!(!destinations.remove(ground));
+        assert!(destinations.list().is_empty());
+    }
+}
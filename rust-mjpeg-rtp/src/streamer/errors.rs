@@ -0,0 +1,164 @@
+//! Classification of UDP send errors
+//!
+//! Send failures are counted per class because each one calls for a
+//! different response: an unreachable destination is backed off from, a
+//! datagram too large for the path lowers the MTU, and a full socket buffer
+//! paces the sender. Anything else is only counted.
+
+use serde::{Deserialize, Serialize};
+use std::io::{self, ErrorKind};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// What a failed send ran into
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SendErrorKind {
+    /// ICMP port/host/network unreachable, reported by the kernel on the next
+    /// send from a connected socket
+    Unreachable,
+    /// EMSGSIZE: the datagram is larger than the path MTU
+    MessageTooLong,
+    /// ENOBUFS: the device queue is full
+    NoBuffers,
+    Other,
+}
+
+impl SendErrorKind {
+    pub fn classify(e: &io::Error) -> Self {
+        match e.kind() {
+            ErrorKind::ConnectionRefused
+            | ErrorKind::HostUnreachable
+            | ErrorKind::NetworkUnreachable => return SendErrorKind::Unreachable,
+            _ => {}
+        }
+
+        #[cfg(target_os = "linux")]
+        match e.raw_os_error() {
+            Some(libc::EMSGSIZE) => return SendErrorKind::MessageTooLong,
+            Some(libc::ENOBUFS) => return SendErrorKind::NoBuffers,
+            _ => {}
+        }
+
+        SendErrorKind::Other
+    }
+
+    /// Name of the class, as in [`SendErrorStats`]
+    pub fn name(self) -> &'static str {
+        match self {
+            SendErrorKind::Unreachable => "unreachable",
+            SendErrorKind::MessageTooLong => "message_too_long",
+            SendErrorKind::NoBuffers => "no_buffers",
+            SendErrorKind::Other => "other",
+        }
+    }
+}
+
+/// Failed packet sends per class
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SendErrorStats {
+    pub unreachable: u64,
+    pub message_too_long: u64,
+    pub no_buffers: u64,
+    pub other: u64,
+}
+
+impl SendErrorStats {
+    pub fn total(&self) -> u64 {
+        self.unreachable + self.message_too_long + self.no_buffers + self.other
+    }
+
+    pub(crate) fn add(&mut self, other: &SendErrorStats) {
+        self.unreachable += other.unreachable;
+        self.message_too_long += other.message_too_long;
+        self.no_buffers += other.no_buffers;
+        self.other += other.other;
+    }
+
+    pub(crate) fn count(&mut self, kind: SendErrorKind) {
+        match kind {
+            SendErrorKind::Unreachable => self.unreachable += 1,
+            SendErrorKind::MessageTooLong => self.message_too_long += 1,
+            SendErrorKind::NoBuffers => self.no_buffers += 1,
+            SendErrorKind::Other => self.other += 1,
+        }
+    }
+}
+
+/// Lock-free per-class totals shared between the sender task and stats readers
+#[derive(Debug, Default)]
+pub(crate) struct SendErrorCounters {
+    unreachable: AtomicU64,
+    message_too_long: AtomicU64,
+    no_buffers: AtomicU64,
+    other: AtomicU64,
+}
+
+impl SendErrorCounters {
+    pub fn record(&self, errors: &SendErrorStats) {
+        self.unreachable
+            .fetch_add(errors.unreachable, Ordering::Relaxed);
+        self.message_too_long
+            .fetch_add(errors.message_too_long, Ordering::Relaxed);
+        self.no_buffers
+            .fetch_add(errors.no_buffers, Ordering::Relaxed);
+        self.other.fetch_add(errors.other, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> SendErrorStats {
+        SendErrorStats {
+            unreachable: self.unreachable.load(Ordering::Relaxed),
+            message_too_long: self.message_too_long.load(Ordering::Relaxed),
+            no_buffers: self.no_buffers.load(Ordering::Relaxed),
+            other: self.other.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify() {
+        let kind = |e: io::Error| SendErrorKind::classify(&e);
+
+        assert_eq!(
+            kind(ErrorKind::ConnectionRefused.into()),
+            SendErrorKind::Unreachable
+        );
+        assert_eq!(
+            kind(ErrorKind::PermissionDenied.into()),
+            SendErrorKind::Other
+        );
+
+        #[cfg(target_os = "linux")]
+        {
+            assert_eq!(
+                kind(io::Error::from_raw_os_error(libc::EMSGSIZE)),
+                SendErrorKind::MessageTooLong
+            );
+            assert_eq!(
+                kind(io::Error::from_raw_os_error(libc::ENOBUFS)),
+                SendErrorKind::NoBuffers
+            );
+            assert_eq!(
+                kind(io::Error::from_raw_os_error(libc::EHOSTUNREACH)),
+                SendErrorKind::Unreachable
+            );
+        }
+    }
+
+    #[test]
+    fn test_counters_accumulate() {
+        let counters = SendErrorCounters::default();
+        let mut report = SendErrorStats::default();
+        report.count(SendErrorKind::MessageTooLong);
+        report.count(SendErrorKind::Other);
+
+        counters.record(&report);
+        counters.record(&report);
+
+        let snapshot = counters.snapshot();
+        assert_eq!(snapshot.message_too_long, 2);
+        assert_eq!(snapshot.total(), 4);
+    }
+}
@@ -0,0 +1,281 @@
+//! Switching between a primary and backup RTP destinations
+//!
+//! A destination is considered gone when the kernel reports it unreachable
+//! (ICMP port/host unreachable, seen on Linux once the socket is connected)
+//! or, if an RTCP port is configured, when no RTCP has arrived from it for
+//! the timeout. Destinations are tried in order and wrap back to the primary.
+//! Each one is kept for at least the timeout, so two dead destinations don't
+//! flap on every frame. A primary given by host name may have several
+//! addresses (see `resolve`); they are all tried before the backups.
+
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+use tokio::net::UdpSocket;
+
+/// Why the streamer left a destination
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FailoverReason {
+    /// The kernel reported ICMP port or host unreachable
+    Unreachable,
+    /// No RTCP from the destination within the timeout
+    RtcpTimeout,
+    /// `dest_host` no longer resolves to the address in use
+    Resolved,
+}
+
+/// Notification published by a running streamer
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StreamerEvent {
+    /// Packets now go to `to` instead of `from`
+    Failover {
+        from: SocketAddr,
+        to: SocketAddr,
+        reason: FailoverReason,
+    },
+}
+
+/// Failover state owned by the sender task
+pub(crate) struct Failover {
+    /// The primary's addresses first, then the backups
+    destinations: Vec<SocketAddr>,
+    /// How many of `destinations` are the primary's
+    primaries: usize,
+    active: usize,
+    rtcp: Option<UdpSocket>,
+    timeout: Duration,
+    last_rtcp: Instant,
+    switched_at: Instant,
+}
+
+impl Failover {
+    pub fn new(
+        primary: &[SocketAddr],
+        backups: &[SocketAddr],
+        rtcp: Option<UdpSocket>,
+        timeout: Duration,
+        now: Instant,
+    ) -> Self {
+        let mut destinations = primary.to_vec();
+        destinations.extend_from_slice(backups);
+        Self {
+            destinations,
+            primaries: primary.len(),
+            active: 0,
+            rtcp,
+            timeout,
+            last_rtcp: now,
+            switched_at: now,
+        }
+    }
+
+    pub fn active(&self) -> SocketAddr {
+        self.destinations[self.active]
+    }
+
+    /// Whether an RTCP port is configured
+    pub fn listens_for_rtcp(&self) -> bool {
+        self.rtcp.is_some()
+    }
+
+    /// Waits for the next RTCP packet into `buf`, returning its length and
+    /// source. Never resolves when no RTCP port is configured.
+    pub async fn recv_rtcp(&self, buf: &mut [u8]) -> std::io::Result<(usize, SocketAddr)> {
+        let Some(ref socket) = self.rtcp else {
+            return std::future::pending().await;
+        };
+        socket.recv_from(buf).await
+    }
+
+    /// Records RTCP from `from`; only the active destination's host counts
+    /// (its RTCP port differs from the RTP one)
+    pub fn rtcp_received(&mut self, from: SocketAddr, now: Instant) {
+        if from.ip() == self.active().ip() {
+            self.last_rtcp = now;
+        }
+    }
+
+    /// Moves to the next destination if the active one looks gone, returning
+    /// the event to publish
+    pub fn check(&mut self, unreachable: bool, now: Instant) -> Option<StreamerEvent> {
+        if now.duration_since(self.switched_at) < self.timeout || self.destinations.len() < 2 {
+            return None;
+        }
+
+        let reason = if unreachable {
+            FailoverReason::Unreachable
+        } else if self.rtcp.is_some() && now.duration_since(self.last_rtcp) >= self.timeout {
+            FailoverReason::RtcpTimeout
+        } else {
+            return None;
+        };
+
+        let from = self.active();
+        self.active = (self.active + 1) % self.destinations.len();
+        Some(self.switched(from, reason, now))
+    }
+
+    /// Takes the primary's addresses from a new lookup. The active
+    /// destination is kept if it is still among them (or a backup);
+    /// otherwise the first new address takes over, and the event to publish
+    /// is returned.
+    pub fn set_primary(&mut self, primary: &[SocketAddr], now: Instant) -> Option<StreamerEvent> {
+        let from = self.active();
+        let mut destinations = primary.to_vec();
+        destinations.extend_from_slice(&self.destinations[self.primaries..]);
+        self.destinations = destinations;
+        self.primaries = primary.len();
+
+        match self.destinations.iter().position(|&addr| addr == from) {
+            Some(active) => {
+                self.active = active;
+                None
+            }
+            None => {
+                self.active = 0;
+                Some(self.switched(from, FailoverReason::Resolved, now))
+            }
+        }
+    }
+
+    fn switched(
+        &mut self,
+        from: SocketAddr,
+        reason: FailoverReason,
+        now: Instant,
+    ) -> StreamerEvent {
+        self.switched_at = now;
+        self.last_rtcp = now;
+        StreamerEvent::Failover {
+            from,
+            to: self.active(),
+            reason,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    fn addr(s: &str) -> SocketAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_unreachable_waits_out_hold_then_cycles() {
+        let start = Instant::now();
+        let mut failover = Failover::new(
+            &[addr("10.0.0.5:5000")],
+            &[addr("10.0.0.6:5000")],
+            None,
+            TIMEOUT,
+            start,
+        );
+
+        assert_eq!(failover.check(true, start + Duration::from_secs(1)), None);
+
+        let later = start + TIMEOUT;
+        assert_eq!(
+            failover.check(true, later),
+            Some(StreamerEvent::Failover {
+                from: addr("10.0.0.5:5000"),
+                to: addr("10.0.0.6:5000"),
+                reason: FailoverReason::Unreachable,
+            })
+        );
+        assert_eq!(failover.active(), addr("10.0.0.6:5000"));
+
+        failover.check(true, later + TIMEOUT);
+        assert_eq!(failover.active(), addr("10.0.0.5:5000"));
+    }
+
+    #[test]
+    fn test_no_rtcp_socket_means_no_silence_detection() {
+        let start = Instant::now();
+        let mut failover = Failover::new(
+            &[addr("10.0.0.5:5000")],
+            &[addr("10.0.0.6:5000")],
+            None,
+            TIMEOUT,
+            start,
+        );
+        assert_eq!(failover.check(false, start + TIMEOUT * 10), None);
+    }
+
+    #[tokio::test]
+    async fn test_rtcp_silence_from_active_host() {
+        let rtcp = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let start = Instant::now();
+        let mut failover = Failover::new(
+            &[addr("10.0.0.5:5000")],
+            &[addr("10.0.0.6:5000")],
+            Some(rtcp),
+            TIMEOUT,
+            start,
+        );
+
+        // RTCP from the active host keeps it alive; other hosts don't
+        failover.rtcp_received(addr("10.0.0.5:5001"), start + TIMEOUT);
+        failover.rtcp_received(addr("10.0.0.9:5001"), start + TIMEOUT * 2);
+        assert_eq!(
+            failover.check(false, start + TIMEOUT + Duration::from_secs(1)),
+            None
+        );
+
+        match failover.check(false, start + TIMEOUT * 2) {
+            Some(StreamerEvent::Failover { reason, .. }) => {
+                assert_eq!(reason, FailoverReason::RtcpTimeout)
+            }
+            other => panic!("expected failover, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_new_addresses_keep_or_replace_active() {
+        let start = Instant::now();
+        let mut failover = Failover::new(
+            &[addr("10.0.0.5:5000"), addr("10.0.0.6:5000")],
+            &[addr("10.0.0.9:5000")],
+            None,
+            TIMEOUT,
+            start,
+        );
+        failover.check(true, start + TIMEOUT);
+        assert_eq!(failover.active(), addr("10.0.0.6:5000"));
+
+        // Still resolved, in another order: no switch
+        let reordered = [addr("10.0.0.6:5000"), addr("10.0.0.5:5000")];
+        assert_eq!(failover.set_primary(&reordered, start + TIMEOUT), None);
+        assert_eq!(failover.active(), addr("10.0.0.6:5000"));
+
+        let moved = [addr("10.0.0.7:5000")];
+        assert_eq!(
+            failover.set_primary(&moved, start + TIMEOUT),
+            Some(StreamerEvent::Failover {
+                from: addr("10.0.0.6:5000"),
+                to: addr("10.0.0.7:5000"),
+                reason: FailoverReason::Resolved,
+            })
+        );
+
+        // The backup stays last
+        failover.check(true, start + TIMEOUT * 2);
+        assert_eq!(failover.active(), addr("10.0.0.9:5000"));
+        assert_eq!(
+            failover.set_primary(&[addr("10.0.0.8:5000")], start + TIMEOUT * 2),
+            None
+        );
+        assert_eq!(failover.active(), addr("10.0.0.9:5000"));
+    }
+
+    #[test]
+    fn test_single_address_never_fails_over() {
+        let start = Instant::now();
+        let mut failover = Failover::new(&[addr("10.0.0.5:5000")], &[], None, TIMEOUT, start);
+        assert_eq!(failover.check(true, start + TIMEOUT), None);
+    }
+}
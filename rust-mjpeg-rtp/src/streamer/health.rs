@@ -0,0 +1,272 @@
+//! Frame continuity checks and rolling stream health score
+
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Weight of the newest frame in the rolling averages (~ last 30 frames)
+const EWMA_ALPHA: f64 = 1.0 / 16.0;
+
+/// Score at or above which a stream is green
+const GREEN_THRESHOLD: u8 = 80;
+
+/// Score at or above which a stream is yellow
+const YELLOW_THRESHOLD: u8 = 50;
+
+/// Missing this many frame intervals in a row marks the stream stalled
+const STALL_INTERVALS: u32 = 10;
+
+/// Traffic-light summary of [`HealthStats::score`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthStatus {
+    #[default]
+    Green,
+    Yellow,
+    Red,
+}
+
+impl HealthStatus {
+    fn from_score(score: u8) -> Self {
+        if score >= GREEN_THRESHOLD {
+            Self::Green
+        } else if score >= YELLOW_THRESHOLD {
+            Self::Yellow
+        } else {
+            Self::Red
+        }
+    }
+}
+
+/// Snapshot of a stream's health
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct HealthStats {
+    /// 0 (unusable) to 100 (perfect)
+    pub score: u8,
+    pub status: HealthStatus,
+
+    /// Frames whose ids were skipped (dropped in capture or before sending)
+    pub frames_missing: u64,
+
+    /// Frames arriving with an id at or below the previous one
+    pub frames_out_of_order: u64,
+
+    /// Rolling deviation of frame arrival from the nominal interval, in percent
+    pub arrival_jitter_pct: f64,
+
+    /// Rolling fraction of frames lost to id gaps
+    pub drop_rate: f64,
+
+    /// Rolling capture-to-wire latency
+    pub latency_us: u64,
+
+    /// Rolling fraction of frames with send errors
+    pub error_rate: f64,
+
+    /// No frame for [`STALL_INTERVALS`] frame intervals
+    pub stalled: bool,
+
+    /// Id of the newest frame to reach the sender
+    #[serde(default)]
+    pub last_frame_id: Option<u64>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    last_id: Option<u64>,
+    last_arrival: Option<Instant>,
+    frames_missing: u64,
+    frames_out_of_order: u64,
+    jitter: f64,
+    drop_rate: f64,
+    latency_us: f64,
+    error_rate: f64,
+}
+
+/// Per-stream health accumulator, written by the sender task
+#[derive(Debug)]
+pub(crate) struct HealthTracker {
+    interval: Duration,
+    state: Mutex<State>,
+}
+
+fn ewma(current: f64, sample: f64) -> f64 {
+    current + EWMA_ALPHA * (sample - current)
+}
+
+impl HealthTracker {
+    pub fn new(fps: u32) -> Self {
+        Self {
+            interval: Duration::from_secs(1) / fps.max(1),
+            state: Mutex::new(State::default()),
+        }
+    }
+
+    /// Records a frame as it reaches the sender. Id 0 restarts the sequence
+    /// (new capture, or frames not coming from [`crate::Capture`]).
+    /// Returns how many frames went missing just before this one.
+    pub fn frame_arrived(&self, id: u64, at: Instant) -> u64 {
+        let mut state = self.state.lock().unwrap();
+
+        let missing = match state.last_id {
+            _ if id == 0 => 0,
+            Some(last) if id > last => id - last - 1,
+            Some(_) => {
+                state.frames_out_of_order += 1;
+                0
+            }
+            None => 0,
+        };
+        if id == 0 || state.last_id.is_none_or(|last| id > last) {
+            state.last_id = Some(id);
+        }
+        state.frames_missing += missing;
+        state.drop_rate = ewma(state.drop_rate, missing as f64 / (missing + 1) as f64);
+
+        if let Some(previous) = state.last_arrival {
+            // Missing frames already count as drops; judge regularity per
+            // expected frame slot so a gap isn't punished twice
+            let expected = self.interval.as_secs_f64() * (missing + 1) as f64;
+            let actual = at.saturating_duration_since(previous).as_secs_f64();
+            let deviation = ((actual - expected).abs() / self.interval.as_secs_f64()).min(1.0);
+            state.jitter = ewma(state.jitter, deviation);
+        }
+        state.last_arrival = Some(at);
+        missing
+    }
+
+    /// Records the outcome of sending a frame
+    pub fn frame_sent(&self, latency_us: u64, had_errors: bool) {
+        let mut state = self.state.lock().unwrap();
+        state.latency_us = if state.latency_us == 0.0 {
+            latency_us as f64
+        } else {
+            ewma(state.latency_us, latency_us as f64)
+        };
+        state.error_rate = ewma(state.error_rate, if had_errors { 1.0 } else { 0.0 });
+    }
+
+    pub fn snapshot(&self) -> HealthStats {
+        self.snapshot_at(Instant::now())
+    }
+
+    fn snapshot_at(&self, now: Instant) -> HealthStats {
+        let state = self.state.lock().unwrap();
+        let stalled = state.last_arrival.is_some_and(|last| {
+            now.saturating_duration_since(last) > self.interval * STALL_INTERVALS
+        });
+
+        // Mostly failing to send leaves viewers with as little as a stall
+        let score = if stalled || state.error_rate > 0.5 {
+            0
+        } else {
+            // Each factor costs up to its weight once it reaches the
+            // threshold where viewers clearly notice it
+            let interval_us = self.interval.as_micros() as f64;
+            let penalty = 25.0 * (state.jitter / 0.5).min(1.0)
+                + 35.0 * (state.drop_rate / 0.10).min(1.0)
+                + 20.0 * (state.latency_us / (5.0 * interval_us)).min(1.0)
+                + 20.0 * (state.error_rate / 0.05).min(1.0);
+            (100.0 - penalty).round().clamp(0.0, 100.0) as u8
+        };
+
+        HealthStats {
+            score,
+            status: HealthStatus::from_score(score),
+            frames_missing: state.frames_missing,
+            frames_out_of_order: state.frames_out_of_order,
+            arrival_jitter_pct: state.jitter * 100.0,
+            drop_rate: state.drop_rate,
+            latency_us: state.latency_us as u64,
+            error_rate: state.error_rate,
+            stalled,
+            last_frame_id: state.last_id,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feeds frames with the given ids at the nominal interval
+    fn feed(
+        tracker: &HealthTracker,
+        start: Instant,
+        ids: impl IntoIterator<Item = u64>,
+    ) -> Instant {
+        let mut at = start;
+        for id in ids {
+            at += tracker.interval;
+            tracker.frame_arrived(id, at);
+            tracker.frame_sent(2_000, false);
+        }
+        at
+    }
+
+    #[test]
+    fn test_regular_stream_is_green() {
+        let tracker = HealthTracker::new(30);
+        let last = feed(&tracker, Instant::now(), 0..100);
+
+        let health = tracker.snapshot_at(last);
+        assert_eq!(health.frames_missing, 0);
+        assert_eq!(health.frames_out_of_order, 0);
+        assert_eq!(health.status, HealthStatus::Green);
+        assert!(health.score >= 95, "score {}", health.score);
+    }
+
+    #[test]
+    fn test_id_gaps_count_as_missing() {
+        let tracker = HealthTracker::new(30);
+        // Every third frame lost
+        let ids = (0..300).filter(|id| id % 3 != 2);
+        let last = feed(&tracker, Instant::now(), ids);
+
+        let health = tracker.snapshot_at(last);
+        assert_eq!(health.frames_missing, 99);
+        assert!(health.drop_rate > 0.2);
+        assert_ne!(health.status, HealthStatus::Green);
+    }
+
+    #[test]
+    fn test_out_of_order_and_restart() {
+        let tracker = HealthTracker::new(30);
+        let now = Instant::now();
+        tracker.frame_arrived(5, now);
+        tracker.frame_arrived(4, now);
+        // Restart from 0 is not a reorder, and the next id continues from it
+        tracker.frame_arrived(0, now);
+        tracker.frame_arrived(1, now);
+
+        let health = tracker.snapshot_at(now);
+        assert_eq!(health.frames_out_of_order, 1);
+        assert_eq!(health.frames_missing, 0);
+    }
+
+    #[test]
+    fn test_send_errors_turn_red() {
+        let tracker = HealthTracker::new(30);
+        let mut at = Instant::now();
+        for id in 0..100 {
+            at += tracker.interval;
+            tracker.frame_arrived(id, at);
+            tracker.frame_sent(2_000, true);
+        }
+
+        let health = tracker.snapshot_at(at);
+        assert_eq!(health.status, HealthStatus::Red);
+        assert!(health.error_rate > 0.9);
+    }
+
+    #[test]
+    fn test_stall_scores_zero() {
+        let tracker = HealthTracker::new(30);
+        let last = feed(&tracker, Instant::now(), 0..10);
+
+        let health = tracker.snapshot_at(last + Duration::from_secs(1));
+        assert!(health.stalled);
+        assert_eq!(health.score, 0);
+        assert_eq!(health.status, HealthStatus::Red);
+    }
+}
@@ -0,0 +1,86 @@
+//! Keepalives while no frames go out
+//!
+//! NAT mappings and stateful firewall pinholes for UDP typically expire
+//! after 30 seconds to a few minutes without traffic. While a stream is
+//! paused, or a camera stalls, the receiver would then stop getting packets
+//! once frames resume. With a keepalive interval set, the sender sends a
+//! small packet to the active destination whenever nothing went there for
+//! that long:
+//!
+//! - `rtp`: an RTP packet of padding only (RFC 3550 Section 5.1) from the
+//!   RTP port, with the next sequence number and the last timestamp.
+//!   Receivers drop it like any padding; ours counts it.
+//! - `rtcp`: an empty RTCP receiver report from the RTCP port. Without
+//!   rtcp-mux this only keeps the RTCP ports' mapping open.
+
+use crate::rtp::build_receiver_report;
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+/// What is sent to keep the path open
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KeepaliveKind {
+    /// Padding-only RTP packets
+    #[default]
+    Rtp,
+    /// Empty RTCP receiver reports
+    Rtcp,
+}
+
+/// When the sender is due to send a keepalive
+pub(crate) struct Keepalive {
+    pub kind: KeepaliveKind,
+    interval: Duration,
+    /// The RTCP keepalive, built once
+    pub rtcp: Bytes,
+    last_sent: Instant,
+}
+
+impl Keepalive {
+    pub fn new(kind: KeepaliveKind, interval: Duration, ssrc: u32, now: Instant) -> Self {
+        Self {
+            kind,
+            interval,
+            rtcp: build_receiver_report(ssrc, &[]),
+            last_sent: now,
+        }
+    }
+
+    /// Notes that something went to the destination at `now`
+    pub fn sent(&mut self, now: Instant) {
+        self.last_sent = now;
+    }
+
+    /// When the next keepalive is due, unless something is sent before
+    pub fn due(&self) -> Instant {
+        self.last_sent + self.interval
+    }
+}
+
+/// Waits until a keepalive is due; pending forever without keepalives
+pub(crate) async fn next_keepalive(keepalive: &Option<Keepalive>) {
+    match keepalive {
+        Some(keepalive) => tokio::time::sleep_until(keepalive.due().into()).await,
+        None => std::future::pending().await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_due_after_last_send() {
+        let start = Instant::now();
+        let mut keepalive =
+            Keepalive::new(KeepaliveKind::Rtcp, Duration::from_secs(15), 0x1234, start);
+        assert_eq!(keepalive.due(), start + Duration::from_secs(15));
+        // An empty receiver report: header and reporter SSRC
+        assert_eq!(keepalive.rtcp.len(), 8);
+
+        keepalive.sent(start + Duration::from_secs(10));
+        assert_eq!(keepalive.due(), start + Duration::from_secs(25));
+    }
+}
@@ -1,18 +1,60 @@
 //! UDP RTP streaming with QoS and statistics
 
+mod bundle;
+mod destinations;
+mod errors;
+mod failover;
+mod health;
+mod keepalive;
+mod resolve;
+mod send;
+mod shaper;
 mod stats;
+mod timing;
 
+pub use bundle::SharedSocket;
+pub use destinations::{DestinationStats, Destinations};
+pub use errors::SendErrorStats;
+pub use failover::{FailoverReason, StreamerEvent};
+pub use health::{HealthStats, HealthStatus};
+pub use keepalive::KeepaliveKind;
+pub use send::MAX_BATCH;
+pub use shaper::{ShapingStats, SharedLimit};
 pub use stats::StreamerStats;
+pub use timing::SendTimingStats;
 
-use crate::rtp::{RtpPacketizer, TimestampGenerator};
+use errors::SendErrorCounters;
+use failover::Failover;
+use health::HealthTracker;
+use keepalive::{next_keepalive, Keepalive};
+use send::SendReport;
+use shaper::{Admission, Shaper, ShapingCounters};
+use stats::DropCounters;
+use timing::SendTiming;
+
+use crate::affinity;
+use crate::config::{FailoverConfig, KeepaliveConfig, NetworkSettings, ShapingConfig};
+use crate::congestion::RtcpFeedback;
+use crate::error::ErrorCode;
+use crate::frame::Frame;
+use crate::log_limited;
+use crate::ratelimit::LogLimiter;
+use crate::realtime;
+use crate::rtp::{
+    build_cname_report, build_goodbye, parse_report_blocks, PacketPlan, QTablePolicy,
+    RtpPacketizer, SessionDescription, TimestampGenerator, TimestampSource,
+};
+use crate::task::{CancellationToken, TaskGroup};
 use bytes::Bytes;
-use std::net::SocketAddr;
+use socket2::{Domain, Protocol, Socket, Type};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs};
 use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use thiserror::Error;
 use tokio::net::UdpSocket;
-use tokio::sync::mpsc;
-use tracing::{debug, error, info};
+use tokio::sync::{broadcast, mpsc, oneshot, watch};
+use tracing::{debug, debug_span, error, info, trace, warn, Instrument};
 
 #[derive(Error, Debug)]
 pub enum StreamerError {
@@ -22,6 +64,9 @@ pub enum StreamerError {
     #[error("channel send error")]
     ChannelSend,
 
+    #[error("frame queue full, frame dropped")]
+    QueueFull,
+
     #[error("streamer not running")]
     NotRunning,
 
@@ -29,18 +74,258 @@ pub enum StreamerError {
     InvalidDestination(String),
 }
 
+impl StreamerError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            StreamerError::Io(_) => ErrorCode::Io,
+            StreamerError::ChannelSend => ErrorCode::ChannelClosed,
+            StreamerError::QueueFull => ErrorCode::Busy,
+            StreamerError::NotRunning => ErrorCode::NotRunning,
+            StreamerError::InvalidDestination(_) => ErrorCode::InvalidInput,
+        }
+    }
+}
+
 /// Configuration for UDP RTP streamer
 #[derive(Debug, Clone)]
 pub struct StreamerConfig {
+    /// IP address or host name of the receiver
     pub dest_host: String,
     pub dest_port: u16,
+    /// How often a host name `dest_host` is looked up again (only at start
+    /// when zero)
+    pub dns_refresh: Duration,
+    /// Local RTP port (0 = any free even port). Without `rtcp_mux`, RTCP is
+    /// sent from the port above it.
     pub local_port: u16,
+    /// Send RTCP on the RTP port to `dest_port` (RFC 5761) instead of using
+    /// an even/odd port pair
+    pub rtcp_mux: bool,
     pub width: u32,
     pub height: u32,
     pub fps: u32,
     pub mtu: usize,
     pub ssrc: u32,
     pub dscp: u8,
+    /// Raw IP TOS byte, used instead of `dscp` when set
+    pub tos: Option<u8>,
+    /// Socket send buffer size (OS default when unset)
+    pub sndbuf: Option<usize>,
+    /// Local address to send from (any address of the destination's family
+    /// when unset)
+    pub bind_address: Option<IpAddr>,
+    /// Run the sender on a dedicated thread pinned to this core
+    pub sender_core: Option<usize>,
+    /// Run the sender on a dedicated SCHED_FIFO thread at this priority (1-99)
+    pub sender_rt_priority: Option<u8>,
+    /// Allow frames above 2040 px, with dimensions carried in the SDP
+    pub oversize_dimensions: bool,
+    /// Frames at least this large are packetized in chunks on the blocking
+    /// pool while earlier chunks are sent (never when 0)
+    pub parallel_packetize_bytes: usize,
+    /// Header extension ID for the per-frame id/CRC32 extension (off when
+    /// unset)
+    pub frame_info_id: Option<u8>,
+    /// How quantization tables are sent
+    pub qtable_policy: QTablePolicy,
+    /// Frames between resending unchanged cached tables (never when 0)
+    pub qtable_refresh_frames: u32,
+    /// What RTP timestamps are derived from
+    pub timestamp_source: TimestampSource,
+    /// Steer frame-count timestamps towards the capture clock
+    pub drift_correction: bool,
+    /// RTCP SDES CNAME, sent every few seconds when set
+    pub cname: Option<String>,
+    /// Destinations to fail over to, in order (failover is off when empty)
+    pub backup_destinations: Vec<SocketAddr>,
+    /// Local port to receive RTCP on for silence detection
+    pub rtcp_port: Option<u16>,
+    /// RTCP silence before failing over, and minimum time per destination
+    pub failover_timeout: Duration,
+    /// Send from this socket, shared with other streamers, instead of one of
+    /// our own (needs `rtcp_mux`)
+    pub bundle: Option<SharedSocket>,
+    /// Outgoing rate limit of this stream in kbit/s (off when unset)
+    pub max_kbps: Option<u64>,
+    /// Rate limit shared with other streamers, for their total
+    pub shared_limit: Option<SharedLimit>,
+    /// Traffic allowed above the limits at once, as time at their rate
+    pub shaping_burst: Duration,
+    /// Longest a frame waits for the limits before it is dropped
+    pub shaping_max_delay: Duration,
+    /// Time without frames after which a keepalive is sent (never when
+    /// unset)
+    pub keepalive_interval: Option<Duration>,
+    /// What keepalives are sent as
+    pub keepalive_kind: KeepaliveKind,
+}
+
+impl StreamerConfig {
+    /// Applies a camera's resolved network settings
+    pub fn with_network(mut self, network: &NetworkSettings) -> Self {
+        self.mtu = network.mtu;
+        self.dscp = network.dscp;
+        self.tos = network.tos;
+        self.sndbuf = network.sndbuf;
+        self.bind_address = network.bind_address;
+        self
+    }
+
+    /// Applies a camera's failover settings
+    pub fn with_failover(mut self, failover: &FailoverConfig) -> Self {
+        self.backup_destinations = failover.backups.clone();
+        self.rtcp_port = failover.rtcp_port;
+        self.failover_timeout = Duration::from_millis(failover.timeout_ms);
+        self
+    }
+
+    /// Applies the egress limits, with `max_kbps` resolved for the camera
+    pub fn with_shaping(mut self, shaping: &ShapingConfig, max_kbps: Option<u64>) -> Self {
+        self.max_kbps = max_kbps;
+        self.shaping_burst = Duration::from_millis(shaping.burst_ms);
+        self.shaping_max_delay = Duration::from_millis(shaping.max_delay_ms);
+        self
+    }
+
+    /// Applies a camera's keepalive settings
+    pub fn with_keepalive(mut self, keepalive: &KeepaliveConfig) -> Self {
+        self.keepalive_interval =
+            Some(Duration::from_millis(keepalive.interval_ms)).filter(|i| !i.is_zero());
+        self.keepalive_kind = keepalive.kind;
+        self
+    }
+
+    /// IP TOS byte for outgoing packets: `tos` if set, otherwise `dscp`
+    /// shifted past the ECN bits
+    pub fn traffic_class(&self) -> u8 {
+        self.tos.unwrap_or((self.dscp & 0x3f) << 2)
+    }
+
+    /// Where RTCP for an RTP destination goes: the same port with
+    /// `rtcp_mux`, otherwise the next port up
+    pub fn rtcp_destination(&self, rtp: SocketAddr) -> Option<SocketAddr> {
+        rtcp_destination(rtp, self.rtcp_mux)
+    }
+
+    /// The configured destination address, when `dest_host` is an IP
+    /// address
+    pub fn destination(&self) -> Result<SocketAddr, StreamerError> {
+        let dest = format!("{}:{}", self.dest_host, self.dest_port);
+        dest.parse()
+            .map_err(|e| StreamerError::InvalidDestination(format!("{}: {}", dest, e)))
+    }
+
+    /// SDP a receiver can open this stream with. A host name `dest_host` is
+    /// looked up (blocking) for the address it has now.
+    pub fn session_description(&self, name: &str) -> Result<SessionDescription, StreamerError> {
+        let dest_ip = match self.dest_host.parse() {
+            Ok(ip) => ip,
+            Err(_) => (self.dest_host.as_str(), self.dest_port)
+                .to_socket_addrs()
+                .map_err(|e| {
+                    StreamerError::InvalidDestination(format!("{}: {}", self.dest_host, e))
+                })?
+                .next()
+                .ok_or_else(|| {
+                    StreamerError::InvalidDestination(format!("{} has no address", self.dest_host))
+                })?
+                .ip(),
+        };
+        Ok(SessionDescription {
+            name: name.to_string(),
+            dest_ip,
+            dest_port: self.dest_port,
+            rtcp_mux: self.rtcp_mux,
+            width: self.width,
+            height: self.height,
+            fps: self.fps,
+            ssrc: Some(self.ssrc),
+            cname: self.cname.clone(),
+            frame_info_id: self.frame_info_id,
+        })
+    }
+}
+
+fn rtcp_destination(rtp: SocketAddr, rtcp_mux: bool) -> Option<SocketAddr> {
+    if rtcp_mux {
+        return Some(rtp);
+    }
+    let port = rtp.port().checked_add(1)?;
+    Some(SocketAddr::new(rtp.ip(), port))
+}
+
+/// Default for [`StreamerConfig::dns_refresh`]
+pub const DEFAULT_DNS_REFRESH: Duration = Duration::from_secs(60);
+
+/// Interval between RTCP SDES packets
+const SDES_INTERVAL_SECS: u32 = 5;
+
+/// Time between probe frames while the destination is unreachable
+const UNREACHABLE_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Smallest MTU reached by shrinking after EMSGSIZE (the config's minimum)
+const MIN_MTU: usize = 500;
+
+/// Attempts at finding a free even/odd port pair before giving up
+const PORT_PAIR_ATTEMPTS: usize = 32;
+
+/// Default for [`StreamerConfig::parallel_packetize_bytes`]: 4K frames, not
+/// 1080p ones
+pub const DEFAULT_PARALLEL_PACKETIZE_BYTES: usize = 512 * 1024;
+
+/// Default for [`StreamerConfig::qtable_refresh_frames`]: about a second at
+/// 30 fps
+pub const DEFAULT_QTABLE_REFRESH_FRAMES: u32 = 30;
+
+/// Packets per chunk of a frame packetized in parallel; one sendmmsg batch
+const PACKETIZE_CHUNK: usize = send::MAX_BATCH;
+
+/// Largest UDP payload an IPv4 datagram can carry
+const MAX_UDP_PAYLOAD: usize = 65_507;
+
+/// How long `stop()` waits for queued frames to go out before aborting the
+/// sender
+const STOP_FLUSH_TIMEOUT: Duration = Duration::from_secs(2);
+
+impl Default for StreamerConfig {
+    fn default() -> Self {
+        Self {
+            dest_host: "127.0.0.1".to_string(),
+            dest_port: 5000,
+            dns_refresh: DEFAULT_DNS_REFRESH,
+            local_port: 0,
+            rtcp_mux: false,
+            width: 640,
+            height: 480,
+            fps: 30,
+            mtu: 1400,
+            ssrc: 0x12345678,
+            dscp: 0,
+            tos: None,
+            sndbuf: None,
+            bind_address: None,
+            sender_core: None,
+            sender_rt_priority: None,
+            oversize_dimensions: false,
+            parallel_packetize_bytes: DEFAULT_PARALLEL_PACKETIZE_BYTES,
+            frame_info_id: None,
+            qtable_policy: QTablePolicy::default(),
+            qtable_refresh_frames: DEFAULT_QTABLE_REFRESH_FRAMES,
+            timestamp_source: TimestampSource::default(),
+            drift_correction: true,
+            cname: None,
+            backup_destinations: Vec::new(),
+            rtcp_port: None,
+            failover_timeout: Duration::from_secs(5),
+            bundle: None,
+            max_kbps: None,
+            shared_limit: None,
+            shaping_burst: Duration::from_millis(100),
+            shaping_max_delay: Duration::from_millis(100),
+            keepalive_interval: None,
+            keepalive_kind: KeepaliveKind::default(),
+        }
+    }
 }
 
 /// UDP RTP streamer for MJPEG frames
@@ -51,25 +336,49 @@ pub struct Streamer {
 
     // Network
     socket: Option<Arc<UdpSocket>>,
-    dest_addr: Option<SocketAddr>,
+    /// Odd half of the port pair; RTCP uses `socket` with rtcp-mux
+    rtcp_socket: Option<Arc<UdpSocket>>,
+    /// Active destination, updated by the sender on failover
+    dest_addr: Arc<Mutex<Option<SocketAddr>>>,
+    /// Extra destinations receiving copies of the packets
+    destinations: Destinations,
+    events: broadcast::Sender<StreamerEvent>,
+    /// Latest receiver report about this stream
+    feedback: watch::Sender<Option<RtcpFeedback>>,
 
     // Frame channel
-    frame_tx: mpsc::Sender<Bytes>,
+    frame_tx: mpsc::Sender<Frame>,
 
     // State
     is_running: Arc<AtomicBool>,
+    tasks: TaskGroup,
 
     // Statistics
     frames_sent: Arc<AtomicU64>,
-    frames_dropped: Arc<AtomicU64>,
+    drops: Arc<DropCounters>,
     send_errors: Arc<AtomicU64>,
+    send_timing: Arc<SendTiming>,
+    send_error_kinds: Arc<SendErrorCounters>,
+    health: Arc<HealthTracker>,
+    shaping: Arc<ShapingCounters>,
+    keepalives_sent: Arc<AtomicU64>,
 }
 
 impl Streamer {
     /// Creates a new UDP RTP streamer
     pub async fn new(config: StreamerConfig) -> Result<Self, StreamerError> {
-        let packetizer = Arc::new(RtpPacketizer::new(config.ssrc, config.mtu));
-        let ts_gen = TimestampGenerator::new(config.fps);
+        let packetizer = Arc::new(
+            RtpPacketizer::new(config.ssrc, config.mtu)
+                .with_oversize_dimensions(config.oversize_dimensions)
+                .with_frame_info(config.frame_info_id)
+                .with_qtable_policy(config.qtable_policy, config.qtable_refresh_frames),
+        );
+        let ts_gen = TimestampGenerator::new(config.fps)
+            .with_source(config.timestamp_source)
+            .with_drift_correction(config.drift_correction);
+        let health = Arc::new(HealthTracker::new(config.fps));
+        let destinations = Destinations::default();
+        destinations.set_primary(config.destination().ok());
 
         let (frame_tx, _frame_rx) = mpsc::channel(10);
 
@@ -78,12 +387,22 @@ impl Streamer {
             packetizer,
             ts_gen,
             socket: None,
-            dest_addr: None,
+            rtcp_socket: None,
+            dest_addr: Arc::new(Mutex::new(None)),
+            destinations,
+            events: broadcast::channel(16).0,
+            feedback: watch::channel(None).0,
             frame_tx,
             is_running: Arc::new(AtomicBool::new(false)),
+            tasks: TaskGroup::new("streamer"),
             frames_sent: Arc::new(AtomicU64::new(0)),
-            frames_dropped: Arc::new(AtomicU64::new(0)),
+            drops: Arc::new(DropCounters::default()),
             send_errors: Arc::new(AtomicU64::new(0)),
+            send_timing: Arc::new(SendTiming::default()),
+            send_error_kinds: Arc::new(SendErrorCounters::default()),
+            health,
+            shaping: Arc::new(ShapingCounters::default()),
+            keepalives_sent: Arc::new(AtomicU64::new(0)),
         })
     }
 
@@ -101,38 +420,44 @@ impl Streamer {
             "Starting MJPEG-RTP streamer"
         );
 
-        // Resolve destination
-        let dest_str = format!("{}:{}", self.config.dest_host, self.config.dest_port);
-        let dest_addr: SocketAddr = dest_str
-            .parse()
-            .map_err(|e| StreamerError::InvalidDestination(format!("{}: {}", dest_str, e)))?;
-        self.dest_addr = Some(dest_addr);
+        // A name's addresses are kept to the bind address's family, or
+        // without one to the first address's; the socket reaches only one
+        let named = resolve::is_name(&self.config.dest_host);
+        let family = self.config.bind_address.filter(|_| named);
+        let mut resolved =
+            resolve::resolve(&self.config.dest_host, self.config.dest_port, family).await?;
+        let dest_addr = resolved[0];
+        resolved.retain(|addr| addr.is_ipv4() == dest_addr.is_ipv4());
+        self.destinations.set_primary(Some(dest_addr));
 
-        // Create UDP socket
-        let local_addr = if self.config.local_port > 0 {
-            format!("0.0.0.0:{}", self.config.local_port)
-        } else {
-            "0.0.0.0:0".to_string()
+        // Create UDP socket. Connecting it (Linux) makes the kernel report
+        // ICMP unreachable on the next send instead of dropping it silently.
+        let (socket, rtcp_socket) = match &self.config.bundle {
+            Some(bundle) => (bundle_socket(&self.config, bundle, dest_addr)?, None),
+            None => {
+                let (socket, rtcp_socket) = bind_sockets(&self.config, dest_addr)?;
+                (Arc::new(socket), rtcp_socket)
+            }
         };
-
-        let socket = UdpSocket::bind(&local_addr).await?;
-
-        // Set socket buffer size (using socket2 for cross-platform compatibility)
-        // Note: tokio::net::UdpSocket doesn't expose set_send_buffer_size directly
-        // For now, we rely on OS defaults. Can be improved with socket2 crate if needed.
-        debug!("UDP socket created, using OS default buffer size");
-
-        // TODO: Set DSCP for QoS if configured
-        if self.config.dscp > 0 {
-            debug!(dscp = %self.config.dscp, "DSCP QoS marking configured (not yet implemented)");
-        }
-
-        let socket = Arc::new(socket);
+        #[cfg(target_os = "linux")]
+        socket.connect(dest_addr).await?;
+        let rtcp_socket = match rtcp_socket {
+            Some(rtcp) => Arc::new(rtcp),
+            None => Arc::clone(&socket),
+        };
+        // Extra destinations need a socket that isn't connected to the primary
+        let fanout_addr = SocketAddr::new(local_ip(&self.config, dest_addr), 0);
+        let fanout_socket = bind_socket(&self.config, fanout_addr)?;
+        let failover = self.failover(&socket, &resolved).await?;
+        *self.dest_addr.lock().unwrap() = Some(dest_addr);
         self.socket = Some(Arc::clone(&socket));
+        self.rtcp_socket = Some(Arc::clone(&rtcp_socket));
 
         info!(
             local = %socket.local_addr()?,
+            rtcp_local = %rtcp_socket.local_addr()?,
             dest = %dest_addr,
+            rtcp_mux = self.config.rtcp_mux,
             "MJPEG-RTP streamer started"
         );
 
@@ -140,34 +465,267 @@ impl Streamer {
         let (frame_tx, frame_rx) = mpsc::channel(10);
         self.frame_tx = frame_tx;
 
+        // Bundled streams share one connected socket, so they stay with the
+        // address found at start
+        let refresh_every = self.config.dns_refresh;
+        let refresh = named && self.config.bundle.is_none() && !refresh_every.is_zero();
+        let (addrs_tx, addrs_rx) = watch::channel(resolved);
+
+        let sdes = self.config.cname.as_deref().and_then(|cname| {
+            let rtcp_dest = self.config.rtcp_destination(dest_addr)?;
+            info!(cname, rtcp_dest = %rtcp_dest, "Announcing RTCP CNAME");
+            Some((build_cname_report(self.config.ssrc, cname), rtcp_dest))
+        });
+
+        let shaper = Shaper::new(
+            self.config.max_kbps,
+            self.config.shared_limit.clone(),
+            self.config.shaping_burst,
+            self.config.shaping_max_delay,
+        );
+        if shaper.is_some() {
+            info!(
+                max_kbps = ?self.config.max_kbps,
+                shared = self.config.shared_limit.is_some(),
+                max_delay = ?self.config.shaping_max_delay,
+                "Limiting the outgoing bitrate"
+            );
+        }
+
+        let keepalive = self.config.keepalive_interval.map(|interval| {
+            info!(
+                every = ?interval,
+                kind = ?self.config.keepalive_kind,
+                "Sending keepalives while no frames go out"
+            );
+            Keepalive::new(
+                self.config.keepalive_kind,
+                interval,
+                self.config.ssrc,
+                Instant::now(),
+            )
+        });
+
         let sender_task = StreamerTask {
             socket,
+            rtcp_socket,
+            rtcp_mux: self.config.rtcp_mux,
+            reports_on_rtcp_socket: !self.config.rtcp_mux && self.config.bundle.is_none(),
+            ssrc: self.config.ssrc,
+            feedback: self.feedback.clone(),
             dest_addr,
+            fanout_socket,
+            destinations: self.destinations.clone(),
+            sdes,
+            sdes_every: (self.config.fps * SDES_INTERVAL_SECS).max(1) as u64,
             frame_rx,
             packetizer: Arc::clone(&self.packetizer),
             ts_gen: self.ts_gen.clone(),
             width: self.config.width,
             height: self.config.height,
+            parallel_packetize_bytes: self.config.parallel_packetize_bytes,
             frames_sent: Arc::clone(&self.frames_sent),
+            drops: Arc::clone(&self.drops),
             send_errors: Arc::clone(&self.send_errors),
+            send_timing: Arc::clone(&self.send_timing),
+            send_error_kinds: Arc::clone(&self.send_error_kinds),
+            health: Arc::clone(&self.health),
+            shaper,
+            shaping: Arc::clone(&self.shaping),
+            keepalive,
+            keepalives_sent: Arc::clone(&self.keepalives_sent),
             is_running: Arc::clone(&self.is_running),
+            failover,
+            addresses: refresh.then_some(addrs_rx),
+            unreachable_until: None,
+            active_dest: Arc::clone(&self.dest_addr),
+            events: self.events.clone(),
+            log: LogLimiter::default(),
         };
 
-        tokio::spawn(sender_task.run());
+        if self.config.sender_core.is_some() || self.config.sender_rt_priority.is_some() {
+            // The thread drops `done` on exit, which lets stop() wait for it
+            // through the task group like any other task
+            let (done_tx, done_rx) = oneshot::channel::<()>();
+            sender_task.spawn_dedicated(
+                self.config.sender_core,
+                self.config.sender_rt_priority,
+                self.tasks.token(),
+                done_tx,
+            )?;
+            self.tasks.spawn(|_token| async move {
+                let _ = done_rx.await;
+            });
+        } else {
+            self.tasks.spawn(|token| sender_task.run(token));
+        }
+
+        if refresh {
+            let host = self.config.dest_host.clone();
+            let port = self.config.dest_port;
+            info!(host, every = ?refresh_every, "Looking up the RTP destination periodically");
+            self.tasks.spawn(move |token| {
+                resolve::refresh(host, port, refresh_every, dest_addr.ip(), addrs_tx, token)
+            });
+        }
 
         self.is_running.store(true, Ordering::Relaxed);
 
         Ok(())
     }
 
+    /// Sets up failover when backups are configured or the primary is
+    /// given by name (not when bundled), binding the RTCP listener if there
+    /// is one
+    async fn failover(
+        &self,
+        socket: &UdpSocket,
+        primary: &[SocketAddr],
+    ) -> Result<Option<Failover>, StreamerError> {
+        let backups = &self.config.backup_destinations;
+        let named = resolve::is_name(&self.config.dest_host) && self.config.bundle.is_none();
+        if backups.is_empty() && !named {
+            return Ok(None);
+        }
+        let dest_addr = primary[0];
+        if let Some(backup) = backups.iter().find(|b| b.is_ipv4() != dest_addr.is_ipv4()) {
+            return Err(StreamerError::InvalidDestination(format!(
+                "backup {} is not in the same address family as {}",
+                backup, dest_addr
+            )));
+        }
+
+        let rtcp = match self.config.rtcp_port {
+            Some(port) => {
+                Some(UdpSocket::bind(SocketAddr::new(socket.local_addr()?.ip(), port)).await?)
+            }
+            None => None,
+        };
+        info!(
+            addresses = ?primary,
+            backups = ?backups,
+            rtcp_port = ?self.config.rtcp_port,
+            timeout = ?self.config.failover_timeout,
+            "Destination failover enabled"
+        );
+
+        Ok(Some(Failover::new(
+            primary,
+            backups,
+            rtcp,
+            self.config.failover_timeout,
+            Instant::now(),
+        )))
+    }
+
+    /// Stops the streamer: frames already queued are sent (for up to
+    /// `STOP_FLUSH_TIMEOUT`), an RTCP BYE goes out, and the sender is
+    /// aborted if it hasn't finished by then. The sockets are closed once
+    /// this returns, and `start()` may be called again.
+    pub async fn stop(&mut self) -> Result<(), StreamerError> {
+        self.stop_sending(true).await
+    }
+
+    /// [`stop`](Self::stop) without the RTCP BYE, for a process that will
+    /// continue the stream after a restart (see [`resume`](Self::resume)):
+    /// receivers see a gap rather than the source leaving
+    pub async fn stop_without_bye(&mut self) -> Result<(), StreamerError> {
+        self.stop_sending(false).await
+    }
+
+    /// Continues the numbering of an earlier run of the stream: the next
+    /// packet gets `next_sequence` and timestamps start at `timestamp`.
+    /// Takes effect on the next `start()`.
+    pub fn resume(&mut self, next_sequence: u16, timestamp: u32) {
+        self.packetizer.set_sequence_number(next_sequence);
+        self.ts_gen = self.ts_gen.clone().with_offset(timestamp);
+    }
+
+    async fn stop_sending(&mut self, bye: bool) -> Result<(), StreamerError> {
+        let Some(socket) = self.socket.take() else {
+            return Ok(());
+        };
+        let rtcp_socket = self.rtcp_socket.take().unwrap_or(socket);
+
+        // Closing the channel lets the sender drain what is queued and exit
+        self.frame_tx = mpsc::channel(1).0;
+        let flushed = tokio::time::timeout(STOP_FLUSH_TIMEOUT, async {
+            while self.tasks.join_next().await.is_some() {}
+        })
+        .await
+        .is_ok();
+        if !flushed {
+            warn!(timeout = ?STOP_FLUSH_TIMEOUT, "Sender did not drain in time, aborting");
+        }
+        self.tasks.shutdown(Duration::ZERO).await;
+        self.tasks = TaskGroup::new("streamer");
+        // A dedicated sender thread that missed the deadline may still clear
+        // its flag on the way out; it must not clear the next run's
+        self.is_running.store(false, Ordering::Relaxed);
+        self.is_running = Arc::new(AtomicBool::new(false));
+
+        let dest = self.dest_addr.lock().unwrap().take().filter(|_| bye);
+        if let Some(rtcp_dest) = dest.and_then(|dest| self.config.rtcp_destination(dest)) {
+            let bye = build_goodbye(self.config.ssrc, self.cname());
+            if let Err(e) = rtcp_socket.send_to(&bye, rtcp_dest).await {
+                debug!(error = %e, "Failed to send RTCP BYE");
+            }
+        }
+
+        info!(
+            frames_sent = self.frames_sent.load(Ordering::Relaxed),
+            "MJPEG-RTP streamer stopped"
+        );
+        Ok(())
+    }
+
+    /// Stops the streamer and starts it again with `config`, e.g. to move
+    /// the stream to another destination. Sequence numbers carry on unless
+    /// the SSRC, MTU, oversize, frame-info or q-table setting changes, which
+    /// starts a new packetizer; stats handles taken before then keep the old
+    /// counters.
+    pub async fn restart(&mut self, config: StreamerConfig) -> Result<(), StreamerError> {
+        self.stop().await?;
+
+        if config.ssrc != self.config.ssrc
+            || config.mtu != self.config.mtu
+            || config.oversize_dimensions != self.config.oversize_dimensions
+            || config.frame_info_id != self.config.frame_info_id
+            || config.qtable_policy != self.config.qtable_policy
+            || config.qtable_refresh_frames != self.config.qtable_refresh_frames
+        {
+            self.packetizer = Arc::new(
+                RtpPacketizer::new(config.ssrc, config.mtu)
+                    .with_oversize_dimensions(config.oversize_dimensions)
+                    .with_frame_info(config.frame_info_id)
+                    .with_qtable_policy(config.qtable_policy, config.qtable_refresh_frames),
+            );
+        }
+        if config.fps != self.config.fps
+            || config.timestamp_source != self.config.timestamp_source
+            || config.drift_correction != self.config.drift_correction
+        {
+            self.ts_gen = TimestampGenerator::new(config.fps)
+                .with_source(config.timestamp_source)
+                .with_drift_correction(config.drift_correction);
+        }
+        if config.fps != self.config.fps {
+            self.health = Arc::new(HealthTracker::new(config.fps));
+        }
+        self.destinations.set_primary(config.destination().ok());
+        self.config = config;
+
+        self.start().await
+    }
+
     /// Sends a JPEG frame
-    pub async fn send_frame(&self, jpeg_data: Bytes) -> Result<(), StreamerError> {
+    pub async fn send_frame(&self, frame: impl Into<Frame>) -> Result<(), StreamerError> {
         if !self.is_running.load(Ordering::Relaxed) {
             return Err(StreamerError::NotRunning);
         }
 
         self.frame_tx
-            .send(jpeg_data)
+            .send(frame.into())
             .await
             .map_err(|_| StreamerError::ChannelSend)?;
 
@@ -175,132 +733,822 @@ impl Streamer {
     }
 
     /// Sends a JPEG frame (non-blocking, drops on full channel)
-    pub fn send_frame_nonblocking(&self, jpeg_data: Bytes) -> Result<(), StreamerError> {
+    pub fn send_frame_nonblocking(&self, frame: impl Into<Frame>) -> Result<(), StreamerError> {
         if !self.is_running.load(Ordering::Relaxed) {
             return Err(StreamerError::NotRunning);
         }
 
-        match self.frame_tx.try_send(jpeg_data) {
+        match self.frame_tx.try_send(frame.into()) {
             Ok(_) => Ok(()),
-            Err(_) => {
-                self.frames_dropped.fetch_add(1, Ordering::Relaxed);
+            Err(mpsc::error::TrySendError::Full(_)) => {
+                self.drops.in_channel.fetch_add(1, Ordering::Relaxed);
+                Err(StreamerError::QueueFull)
+            }
+            Err(mpsc::error::TrySendError::Closed(_)) => {
+                self.drops.in_channel.fetch_add(1, Ordering::Relaxed);
                 Err(StreamerError::ChannelSend)
             }
         }
     }
 
+    /// Counts a frame the processors dropped before it reached
+    /// [`Streamer::send_frame`]
+    pub fn count_paced_drop(&self) {
+        self.drops.by_pacer.fetch_add(1, Ordering::Relaxed);
+    }
+
     /// Gets streamer statistics
     pub fn get_stats(&self) -> StreamerStats {
-        let packetizer_stats = self.packetizer.get_stats();
+        self.stats_handle().get()
+    }
 
-        StreamerStats {
-            frames_sent: self.frames_sent.load(Ordering::Relaxed),
-            frames_dropped: self.frames_dropped.load(Ordering::Relaxed),
-            send_errors: self.send_errors.load(Ordering::Relaxed),
-            rtp_packets_sent: packetizer_stats.packets_sent,
-            bytes_sent: packetizer_stats.bytes_sent,
-            current_seq_num: packetizer_stats.current_seq,
-            current_timestamp: packetizer_stats.current_ts,
+    /// Returns a cloneable handle for reading statistics from other tasks
+    pub fn stats_handle(&self) -> StreamerStatsHandle {
+        StreamerStatsHandle {
+            packetizer: Arc::clone(&self.packetizer),
+            frames_sent: Arc::clone(&self.frames_sent),
+            drops: Arc::clone(&self.drops),
+            send_errors: Arc::clone(&self.send_errors),
+            send_timing: Arc::clone(&self.send_timing),
+            send_error_kinds: Arc::clone(&self.send_error_kinds),
+            health: Arc::clone(&self.health),
+            shaping: Arc::clone(&self.shaping),
+            keepalives_sent: Arc::clone(&self.keepalives_sent),
+            ts_gen: self.ts_gen.clone(),
+            destinations: self.destinations.clone(),
         }
     }
 
+    /// Width and height of the frames it sends
+    pub fn frame_size(&self) -> (u32, u32) {
+        (self.config.width, self.config.height)
+    }
+
+    /// SSRC of the stream
+    pub fn ssrc(&self) -> u32 {
+        self.config.ssrc
+    }
+
+    /// RTCP CNAME announced for the stream, if any
+    pub fn cname(&self) -> Option<&str> {
+        self.config.cname.as_deref()
+    }
+
     /// Checks if streamer is running
     pub fn is_running(&self) -> bool {
         self.is_running.load(Ordering::Relaxed)
     }
 
-    /// Gets destination address
+    /// Gets the destination packets currently go to
     pub fn get_destination(&self) -> Option<SocketAddr> {
-        self.dest_addr
+        *self.dest_addr.lock().unwrap()
+    }
+
+    /// Starts copying the stream to `addr` as well, without interrupting it.
+    /// Returns `false` if `addr` already receives it. Extra destinations
+    /// must share the primary destination's address family.
+    pub fn add_destination(&self, addr: SocketAddr) -> Result<bool, StreamerError> {
+        let added = self.destinations.add(addr)?;
+        if added {
+            info!(dest = %addr, "Added RTP destination");
+        }
+        Ok(added)
+    }
+
+    /// Stops copying the stream to `addr`. Returns `false` if it wasn't
+    /// receiving it.
+    pub fn remove_destination(&self, addr: SocketAddr) -> bool {
+        let removed = self.destinations.remove(addr);
+        if removed {
+            info!(dest = %addr, "Removed RTP destination");
+        }
+        removed
+    }
+
+    /// Handle for managing extra destinations from other tasks
+    pub fn destinations(&self) -> Destinations {
+        self.destinations.clone()
+    }
+
+    /// Subscribes to events such as destination failover. Subscriptions
+    /// carry over restarts.
+    pub fn subscribe(&self) -> broadcast::Receiver<StreamerEvent> {
+        self.events.subscribe()
+    }
+
+    /// Follows the receiver reports about this stream, for congestion
+    /// control. They are read from the RTCP port (`failover.rtcp_port` when
+    /// set), not with rtcp-mux or bundling. Carries over restarts.
+    pub fn feedback(&self) -> watch::Receiver<Option<RtcpFeedback>> {
+        self.feedback.subscribe()
+    }
+
+    /// Gets the local address packets are sent from (once started)
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.socket
+            .as_ref()
+            .and_then(|socket| socket.local_addr().ok())
+    }
+}
+
+/// Creates the RTP socket and, without rtcp-mux, the RTCP socket on the odd
+/// port above it. With `local_port` 0 the pair is the first free one found.
+fn bind_sockets(
+    config: &StreamerConfig,
+    dest: SocketAddr,
+) -> Result<(UdpSocket, Option<UdpSocket>), StreamerError> {
+    let ip = local_ip(config, dest);
+    if ip.is_ipv4() != dest.is_ipv4() {
+        return Err(StreamerError::InvalidDestination(format!(
+            "{} cannot be reached from bind address {}",
+            dest, ip
+        )));
+    }
+
+    if config.rtcp_mux {
+        return Ok((
+            bind_socket(config, SocketAddr::new(ip, config.local_port))?,
+            None,
+        ));
+    }
+
+    if config.local_port != 0 {
+        let rtcp_port = config.local_port.checked_add(1).ok_or_else(|| {
+            StreamerError::InvalidDestination(format!(
+                "local_port {} leaves no room for RTCP",
+                config.local_port
+            ))
+        })?;
+        let rtp = bind_socket(config, SocketAddr::new(ip, config.local_port))?;
+        let rtcp = bind_socket(config, SocketAddr::new(ip, rtcp_port))?;
+        return Ok((rtp, Some(rtcp)));
+    }
+
+    // Let the OS pick a port and keep it if it is even and its neighbour is
+    // free; otherwise try again
+    for _ in 0..PORT_PAIR_ATTEMPTS {
+        let rtp = bind_socket(config, SocketAddr::new(ip, 0))?;
+        let port = rtp.local_addr()?.port();
+        if port % 2 != 0 || port == u16::MAX {
+            continue;
+        }
+        if let Ok(rtcp) = bind_socket(config, SocketAddr::new(ip, port + 1)) {
+            return Ok((rtp, Some(rtcp)));
+        }
+    }
+    Err(StreamerError::Io(std::io::Error::new(
+        std::io::ErrorKind::AddrInUse,
+        "no free RTP/RTCP port pair",
+    )))
+}
+
+/// The bundle's socket, bound on first use to `local_port` like an rtcp-mux
+/// streamer's own
+fn bundle_socket(
+    config: &StreamerConfig,
+    bundle: &SharedSocket,
+    dest: SocketAddr,
+) -> Result<Arc<UdpSocket>, StreamerError> {
+    if !config.rtcp_mux {
+        return Err(StreamerError::InvalidDestination(
+            "bundled streams need rtcp_mux".to_string(),
+        ));
+    }
+    bundle.get_or_bind(|| Ok(bind_sockets(config, dest)?.0))
+}
+
+/// Address to send from: the configured one, or any of `dest`'s family
+fn local_ip(config: &StreamerConfig, dest: SocketAddr) -> IpAddr {
+    config.bind_address.unwrap_or(match dest {
+        SocketAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        SocketAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+    })
+}
+
+/// Creates a socket bound to `local_addr` with the configured send buffer
+/// and traffic class
+fn bind_socket(
+    config: &StreamerConfig,
+    local_addr: SocketAddr,
+) -> Result<UdpSocket, StreamerError> {
+    let socket = Socket::new(
+        Domain::for_address(local_addr),
+        Type::DGRAM,
+        Some(Protocol::UDP),
+    )?;
+
+    if let Some(size) = config.sndbuf {
+        socket.set_send_buffer_size(size)?;
+        // Linux doubles the request and caps it at net.core.wmem_max
+        debug!(
+            requested = size,
+            actual = socket.send_buffer_size()?,
+            "UDP send buffer set"
+        );
+    }
+
+    let tos = config.traffic_class();
+    if tos != 0 {
+        if local_addr.is_ipv4() {
+            socket.set_tos(tos as u32)?;
+            debug!(tos = %format!("{:#04x}", tos), "IP TOS set");
+        } else {
+            warn!(
+                tos,
+                "Traffic class marking is only supported for IPv4, sending unmarked"
+            );
+        }
+    }
+
+    socket.bind(&local_addr.into())?;
+    socket.set_nonblocking(true)?;
+    Ok(UdpSocket::from_std(socket.into())?)
+}
+
+/// Read-only view of a streamer's counters that outlives borrows of the streamer
+#[derive(Clone)]
+pub struct StreamerStatsHandle {
+    packetizer: Arc<RtpPacketizer>,
+    frames_sent: Arc<AtomicU64>,
+    drops: Arc<DropCounters>,
+    send_errors: Arc<AtomicU64>,
+    send_timing: Arc<SendTiming>,
+    send_error_kinds: Arc<SendErrorCounters>,
+    health: Arc<HealthTracker>,
+    shaping: Arc<ShapingCounters>,
+    keepalives_sent: Arc<AtomicU64>,
+    ts_gen: TimestampGenerator,
+    destinations: Destinations,
+}
+
+impl StreamerStatsHandle {
+    /// Snapshots the current statistics
+    pub fn get(&self) -> StreamerStats {
+        let packetizer_stats = self.packetizer.get_stats();
+
+        let mut stats = StreamerStats {
+            frames_sent: self.frames_sent.load(Ordering::Relaxed),
+            send_errors: self.send_errors.load(Ordering::Relaxed),
+            rtp_packets_sent: packetizer_stats.packets_sent,
+            bytes_sent: packetizer_stats.bytes_sent,
+            current_seq_num: packetizer_stats.current_seq,
+            current_timestamp: packetizer_stats.last_ts,
+            send_timing: self.send_timing.snapshot(),
+            send_error_kinds: self.send_error_kinds.snapshot(),
+            health: self.health.snapshot(),
+            clock_drift: self.ts_gen.drift(),
+            shaping: self.shaping.snapshot(),
+            destinations: self.destinations.stats(),
+            keepalives_sent: self.keepalives_sent.load(Ordering::Relaxed),
+            ..Default::default()
+        };
+        self.drops.snapshot_into(&mut stats);
+        stats
     }
 }
 
 impl Drop for Streamer {
     fn drop(&mut self) {
         self.is_running.store(false, Ordering::Relaxed);
+        // Ends the sender even while other handles keep the socket alive
+        self.tasks.cancel();
     }
 }
 
 /// Task that sends RTP packets
 struct StreamerTask {
     socket: Arc<UdpSocket>,
+    /// Sends SDES; the same socket as `socket` with rtcp-mux
+    rtcp_socket: Arc<UdpSocket>,
+    rtcp_mux: bool,
+    /// Receiver reports are read from `rtcp_socket` unless it is the RTP
+    /// one, where reading would take the ICMP errors sends rely on, or
+    /// shared with other bundled streams
+    reports_on_rtcp_socket: bool,
+    ssrc: u32,
+    feedback: watch::Sender<Option<RtcpFeedback>>,
     dest_addr: SocketAddr,
-    frame_rx: mpsc::Receiver<Bytes>,
+    /// Unconnected socket for extra destinations
+    fanout_socket: UdpSocket,
+    destinations: Destinations,
+    /// Pre-built SDES packet and its RTCP destination
+    sdes: Option<(Bytes, SocketAddr)>,
+    sdes_every: u64,
+    frame_rx: mpsc::Receiver<Frame>,
     packetizer: Arc<RtpPacketizer>,
     ts_gen: TimestampGenerator,
     width: u32,
     height: u32,
+    parallel_packetize_bytes: usize,
     frames_sent: Arc<AtomicU64>,
+    drops: Arc<DropCounters>,
     send_errors: Arc<AtomicU64>,
+    send_timing: Arc<SendTiming>,
+    send_error_kinds: Arc<SendErrorCounters>,
+    health: Arc<HealthTracker>,
+    /// Egress limits (none configured when unset)
+    shaper: Option<Shaper>,
+    shaping: Arc<ShapingCounters>,
+    /// Keepalives while no frames go out (off when unset)
+    keepalive: Option<Keepalive>,
+    keepalives_sent: Arc<AtomicU64>,
     is_running: Arc<AtomicBool>,
+    failover: Option<Failover>,
+    /// Addresses of a named primary destination, as they are looked up
+    /// again (see `resolve`)
+    addresses: Option<watch::Receiver<Vec<SocketAddr>>>,
+    /// While set, frames are dropped except one probe per backoff period
+    unreachable_until: Option<Instant>,
+    active_dest: Arc<Mutex<Option<SocketAddr>>>,
+    events: broadcast::Sender<StreamerEvent>,
+    /// Keeps per-frame and per-packet failures to one line a second each
+    log: LogLimiter,
 }
 
 impl StreamerTask {
-    async fn run(mut self) {
+    /// Packetizes one frame; `slot` counts frame intervals since the start,
+    /// dropped frames included
+    fn packetize(&self, frame: &Frame, slot: u64) -> Option<Vec<Bytes>> {
+        debug_span!("packetize").in_scope(|| {
+            let plan = self.plan(frame, slot)?;
+            Some(plan.packets(0..plan.len()))
+        })
+    }
+
+    /// Parses one frame and takes its sequence numbers, see [`packetize`](Self::packetize)
+    fn plan(&self, frame: &Frame, slot: u64) -> Option<PacketPlan> {
+        let timestamp = self.ts_gen.for_frame(frame, slot);
+        match self
+            .packetizer
+            .plan_frame(&frame.data, frame.id, self.width, self.height, timestamp)
+        {
+            Ok(plan) => Some(plan),
+            Err(e) => {
+                log_limited!(self.log, "packetize", error, error = %e, "Failed to packetize JPEG");
+                self.send_errors.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+        }
+    }
+
+    /// Packetizes and sends one frame, returning how the sends to the
+    /// primary destination went (`None` if the frame could not be
+    /// packetized at all). Extra destinations are served afterwards.
+    /// Timing of each stage is recorded on the current `frame` span.
+    async fn process_frame(&self, frame: &Frame, slot: u64) -> Option<SendReport> {
+        if self.parallel_packetize_bytes > 0 && frame.len() >= self.parallel_packetize_bytes {
+            return self.process_chunked(frame, slot).await;
+        }
+        let span = tracing::Span::current();
+
+        let packetize_start = Instant::now();
+        let packets = self.packetize(frame, slot)?;
+        span.record("packetize_us", packetize_start.elapsed().as_micros() as u64);
+        span.record("packets", packets.len());
+
+        // Send all RTP packets, batched where the platform allows
+        let report = send::send_packets(&self.socket, &packets, self.dest_addr, &self.log)
+            .instrument(debug_span!("send"))
+            .await;
+        self.send_timing.record(&report);
+        span.record("send_us", report.wire_us());
+        span.record("batches", report.completions.len());
+
+        trace!(total_us = frame.age_us(), "Frame sent");
+
+        self.fan_out(&packets).await;
+        Some(report)
+    }
+
+    /// [`process_frame`](Self::process_frame) for large frames: all chunks
+    /// of [`PACKETIZE_CHUNK`] packets are built on the blocking pool at once
+    /// and each is sent as soon as it is ready, so building the rest
+    /// overlaps with sending. `packetize_us` is the time spent waiting for
+    /// chunks rather than the total build time.
+    async fn process_chunked(&self, frame: &Frame, slot: u64) -> Option<SendReport> {
+        let span = tracing::Span::current();
+
+        let packetize_start = Instant::now();
+        let plan = Arc::new(debug_span!("packetize").in_scope(|| self.plan(frame, slot))?);
+        let mut waited = packetize_start.elapsed();
+        span.record("packets", plan.len());
+
+        let chunks: Vec<_> = (0..plan.len())
+            .step_by(PACKETIZE_CHUNK)
+            .map(|start| {
+                let plan = Arc::clone(&plan);
+                let end = (start + PACKETIZE_CHUNK).min(plan.len());
+                tokio::task::spawn_blocking(move || plan.packets(start..end))
+            })
+            .collect();
+
+        let mut packets = Vec::with_capacity(plan.len());
+        let mut report: Option<SendReport> = None;
+        for chunk in chunks {
+            let wait_start = Instant::now();
+            let chunk = match chunk.await {
+                Ok(chunk) => chunk,
+                Err(e) => {
+                    // The frame's tail is lost; the receiver sees the gap
+                    log_limited!(self.log, "packetize", error, error = %e, "Failed to packetize JPEG");
+                    break;
+                }
+            };
+            waited += wait_start.elapsed();
+
+            let sent = send::send_packets(&self.socket, &chunk, self.dest_addr, &self.log)
+                .instrument(debug_span!("send"))
+                .await;
+            match report {
+                Some(ref mut report) => report.append(sent),
+                None => report = Some(sent),
+            }
+            packets.extend(chunk);
+        }
+        span.record("packetize_us", waited.as_micros() as u64);
+
+        let Some(report) = report else {
+            self.send_errors.fetch_add(1, Ordering::Relaxed);
+            return None;
+        };
+        self.send_timing.record(&report);
+        span.record("send_us", report.wire_us());
+        span.record("batches", report.completions.len());
+
+        trace!(total_us = frame.age_us(), "Frame sent");
+
+        self.fan_out(&packets).await;
+        Some(report)
+    }
+
+    /// Holds a frame back until the egress limits allow it, counting the
+    /// bytes for every destination it goes to. Returns `false` if it would
+    /// wait too long and was dropped instead.
+    async fn shape(&mut self, frame: &Frame, now: Instant) -> bool {
+        let Some(ref mut shaper) = self.shaper else {
+            return true;
+        };
+        let copies = 1 + self.destinations.snapshot().len();
+        let bytes = shaper::wire_bytes(frame.len(), self.packetizer.mtu()) * copies;
+        match shaper.admit(bytes, now) {
+            Admission::Send(delay) if delay.is_zero() => true,
+            Admission::Send(delay) => {
+                self.shaping.record_delay(delay);
+                tokio::time::sleep(delay).await;
+                true
+            }
+            Admission::Drop => {
+                self.drops.by_shaper.fetch_add(1, Ordering::Relaxed);
+                log_limited!(
+                    self.log,
+                    "shaper",
+                    debug,
+                    frame_id = frame.id,
+                    bytes,
+                    "Frame over the egress rate limit, dropped"
+                );
+                false
+            }
+        }
+    }
+
+    /// Sends a frame's packets to every extra destination
+    async fn fan_out(&self, packets: &[Bytes]) {
+        for destination in self.destinations.snapshot() {
+            let report =
+                send::send_packets(&self.fanout_socket, packets, destination.addr, &self.log).await;
+            destination.record(packets, &report);
+        }
+    }
+
+    /// Reacts to a frame's send errors: backs off from an unreachable
+    /// destination, shrinks packets after EMSGSIZE, and gives failover a
+    /// chance to move on
+    async fn handle_send_errors(&mut self, errors: &SendErrorStats, now: Instant) {
+        self.send_error_kinds.record(errors);
+
+        if errors.unreachable > 0 {
+            if self.unreachable_until.is_none() {
+                warn!(
+                    dest = %self.dest_addr,
+                    probe_every = ?UNREACHABLE_BACKOFF,
+                    "RTP destination unreachable, dropping frames between probes"
+                );
+            }
+            self.unreachable_until = Some(now + UNREACHABLE_BACKOFF);
+        } else if self.unreachable_until.take().is_some() {
+            info!(dest = %self.dest_addr, "RTP destination reachable again");
+        }
+
+        if errors.message_too_long > 0 {
+            self.lower_mtu();
+        }
+
+        self.check_failover(errors.unreachable > 0, now).await;
+    }
+
+    /// Passes on the report blocks about this stream in an RTCP packet
+    fn reports_received(&self, packet: &[u8]) {
+        for block in parse_report_blocks(packet) {
+            if block.ssrc == self.ssrc {
+                debug!(
+                    fraction_lost = block.fraction_lost,
+                    jitter = block.jitter,
+                    "Receiver report"
+                );
+                self.feedback.send_replace(Some(block.feedback()));
+            }
+        }
+    }
+
+    async fn check_failover(&mut self, unreachable: bool, now: Instant) {
+        if let Some(ref mut failover) = self.failover {
+            if let Some(event) = failover.check(unreachable, now) {
+                self.apply_failover(event).await;
+            }
+        }
+    }
+
+    /// Takes new addresses of the primary destination, moving off the
+    /// active one if it isn't among them any more
+    async fn set_primary(&mut self, addrs: &[SocketAddr]) {
+        if let Some(ref mut failover) = self.failover {
+            if let Some(event) = failover.set_primary(addrs, Instant::now()) {
+                self.apply_failover(event).await;
+            }
+        }
+    }
+
+    /// Shrinks packets to the kernel's path MTU when it knows a smaller one,
+    /// otherwise by an eighth
+    fn lower_mtu(&self) {
+        let current = self.packetizer.mtu();
+        let lowered = send::path_payload_size(&self.socket, self.dest_addr)
+            .filter(|&size| size < current)
+            .unwrap_or(current - current / 8)
+            .clamp(MIN_MTU, MAX_UDP_PAYLOAD);
+        if lowered < current {
+            warn!(
+                from = current,
+                to = lowered,
+                "Packets too large for the path, lowering MTU"
+            );
+            self.packetizer.set_mtu(lowered);
+        }
+    }
+
+    /// Points the socket, SDES and the streamer's view at the new destination
+    async fn apply_failover(&mut self, event: StreamerEvent) {
+        let StreamerEvent::Failover { from, to, reason } = event;
+        warn!(%from, %to, ?reason, "Switching RTP destination");
+
+        #[cfg(target_os = "linux")]
+        if let Err(e) = self.socket.connect(to).await {
+            warn!(error = %e, "Failed to connect socket to new destination");
+        }
+        self.dest_addr = to;
+        self.unreachable_until = None;
+        if let Some((_, ref mut addr)) = self.sdes {
+            if let Some(rtcp) = rtcp_destination(to, self.rtcp_mux) {
+                *addr = rtcp;
+            }
+        }
+        *self.active_dest.lock().unwrap() = Some(to);
+        // Nobody listening is fine
+        let _ = self.events.send(event);
+    }
+
+    /// Sends a keepalive to the active destination. None goes to one that
+    /// is unreachable; the frame probes find out when it is back.
+    async fn send_keepalive(&mut self) {
+        let Some(ref mut keepalive) = self.keepalive else {
+            return;
+        };
+        let now = Instant::now();
+        keepalive.sent(now);
+        if self.unreachable_until.is_some_and(|until| now < until) {
+            return;
+        }
+        let sent = match keepalive.kind {
+            KeepaliveKind::Rtp => {
+                let packet = self.packetizer.keepalive_packet();
+                let report =
+                    send::send_packets(&self.socket, &[packet], self.dest_addr, &self.log).await;
+                report.sent > 0
+            }
+            KeepaliveKind::Rtcp => {
+                let Some(addr) = rtcp_destination(self.dest_addr, self.rtcp_mux) else {
+                    return;
+                };
+                match self.rtcp_socket.send_to(&keepalive.rtcp, addr).await {
+                    Ok(_) => true,
+                    Err(e) => {
+                        log_limited!(
+                            self.log,
+                            "keepalive",
+                            debug,
+                            error = %e,
+                            "Failed to send RTCP keepalive"
+                        );
+                        false
+                    }
+                }
+            }
+        };
+        if sent {
+            self.keepalives_sent.fetch_add(1, Ordering::Relaxed);
+            trace!(dest = %self.dest_addr, "Keepalive sent");
+        }
+    }
+
+    /// Runs the task on its own OS thread with a single-threaded runtime, so
+    /// it can be pinned and/or given real-time priority without affecting the
+    /// tokio workers. The socket stays registered with the main runtime's
+    /// I/O driver. The thread exits once `token` is cancelled or the frame
+    /// channel closes, dropping `done` as it does.
+    fn spawn_dedicated(
+        self,
+        core: Option<usize>,
+        rt_priority: Option<u8>,
+        token: CancellationToken,
+        done: oneshot::Sender<()>,
+    ) -> Result<(), StreamerError> {
+        std::thread::Builder::new()
+            .name("rtp-sender".to_string())
+            .spawn(move || {
+                let _done = done;
+                if let Some(core) = core {
+                    affinity::pin_current_thread(core, "sender");
+                }
+                if let Some(priority) = rt_priority {
+                    realtime::set_current_thread_fifo(priority, "sender");
+                }
+                let runtime = match tokio::runtime::Builder::new_current_thread()
+                    .enable_all()
+                    .build()
+                {
+                    Ok(runtime) => runtime,
+                    Err(e) => {
+                        error!(error = %e, "Failed to build sender runtime");
+                        return;
+                    }
+                };
+                runtime.block_on(self.run(token));
+            })?;
+        Ok(())
+    }
+
+    async fn run(mut self, token: CancellationToken) {
         info!("Frame sender task started");
 
         let mut frame_count = 0u64;
+        // Frames dropped before reaching the sender (capture backlog, fan-out
+        // lag). Their timestamp slots are skipped rather than reused, so
+        // frame-count timestamps don't fall behind real time after a stall
+        // and play back fast.
+        let mut frames_skipped = 0u64;
+        let mut rtcp_buf = [0u8; 1500];
 
-        while let Some(jpeg_data) = self.frame_rx.recv().await {
+        loop {
+            // Only the wait is raced against shutdown; a frame that has been
+            // received is always sent in full
+            let frame = tokio::select! {
+                biased;
+                _ = token.cancelled() => break,
+                rtcp = next_rtcp(
+                    &self.failover,
+                    self.reports_on_rtcp_socket.then_some(&*self.rtcp_socket),
+                    &mut rtcp_buf,
+                ) => {
+                    match rtcp {
+                        Ok((len, from)) => {
+                            if let Some(ref mut failover) = self.failover {
+                                failover.rtcp_received(from, Instant::now());
+                            }
+                            self.reports_received(&rtcp_buf[..len]);
+                        }
+                        Err(e) => log_limited!(
+                            self.log,
+                            "rtcp_recv",
+                            debug,
+                            error = %e,
+                            "Failed to receive RTCP"
+                        ),
+                    }
+                    continue;
+                }
+                addrs = next_addresses(&mut self.addresses) => {
+                    match addrs {
+                        Some(addrs) => self.set_primary(&addrs).await,
+                        None => self.addresses = None,
+                    }
+                    continue;
+                }
+                _ = next_keepalive(&self.keepalive) => {
+                    self.send_keepalive().await;
+                    continue;
+                }
+                frame = self.frame_rx.recv() => match frame {
+                    Some(frame) => frame,
+                    None => break,
+                },
+            };
             if !self.is_running.load(Ordering::Relaxed) {
                 break;
             }
+            let now = Instant::now();
+            frames_skipped += self.health.frame_arrived(frame.id, now);
 
-            // Calculate timestamp
-            let timestamp = self.ts_gen.next_frame_based(frame_count);
+            if self.unreachable_until.is_some_and(|until| now < until) {
+                self.drops.unreachable.fetch_add(1, Ordering::Relaxed);
+                self.health.frame_sent(frame.age_us(), true);
+                // Extra destinations don't depend on the primary being up
+                if let Some(packets) = self.packetize(&frame, frame_count + frames_skipped) {
+                    self.fan_out(&packets).await;
+                }
+                self.check_failover(true, now).await;
+                // Keep timestamps moving with the capture clock
+                frame_count += 1;
+                continue;
+            }
 
-            // Packetize JPEG
-            let packets =
-                match self
-                    .packetizer
-                    .packetize_jpeg(&jpeg_data, self.width, self.height, timestamp)
-                {
-                    Ok(packets) => packets,
-                    Err(e) => {
-                        error!(error = %e, "Failed to packetize JPEG");
-                        self.send_errors.fetch_add(1, Ordering::Relaxed);
-                        continue;
+            if !self.shape(&frame, now).await {
+                frame_count += 1;
+                continue;
+            }
+
+            if frame_count.is_multiple_of(self.sdes_every) {
+                if let Some((ref packet, addr)) = self.sdes {
+                    if let Err(e) = self.rtcp_socket.send_to(packet, addr).await {
+                        log_limited!(
+                            self.log,
+                            "rtcp_sdes",
+                            debug,
+                            error = %e,
+                            "Failed to send RTCP SDES"
+                        );
                     }
-                };
+                }
+            }
+
+            let span = debug_span!(
+                "frame",
+                frame_id = frame.id,
+                bytes = frame.len(),
+                queue_wait_us = frame.age_us(),
+                packetize_us = tracing::field::Empty,
+                send_us = tracing::field::Empty,
+                packets = tracing::field::Empty,
+                batches = tracing::field::Empty,
+            );
 
-            // Send all RTP packets
-            let mut errors = 0;
-            for (i, packet) in packets.iter().enumerate() {
-                if let Err(e) = self.socket.send_to(packet, self.dest_addr).await {
-                    error!(
-                        error = %e,
-                        packet = %i,
-                        total = %packets.len(),
-                        "Failed to send RTP packet"
-                    );
-                    errors += 1;
+            let slot = frame_count + frames_skipped;
+            let report = match self.process_frame(&frame, slot).instrument(span).await {
+                Some(report) => report,
+                None => {
+                    self.health.frame_sent(frame.age_us(), true);
+                    continue;
                 }
+            };
+            if let Some(ref mut keepalive) = self.keepalive {
+                keepalive.sent(Instant::now());
             }
+            self.health.frame_sent(frame.age_us(), report.errors > 0);
 
-            if errors > 0 {
+            if report.errors > 0 {
                 self.send_errors.fetch_add(1, Ordering::Relaxed);
             } else {
                 self.frames_sent.fetch_add(1, Ordering::Relaxed);
             }
 
+            self.handle_send_errors(&report.error_kinds, Instant::now())
+                .await;
+
             frame_count += 1;
 
             // Log progress periodically
             if frame_count % 100 == 0 {
                 let stats = StreamerStats {
                     frames_sent: self.frames_sent.load(Ordering::Relaxed),
-                    frames_dropped: 0,
                     send_errors: self.send_errors.load(Ordering::Relaxed),
                     rtp_packets_sent: self.packetizer.get_stats().packets_sent,
                     bytes_sent: self.packetizer.get_stats().bytes_sent,
                     current_seq_num: 0,
                     current_timestamp: 0,
+                    send_timing: self.send_timing.snapshot(),
+                    send_error_kinds: self.send_error_kinds.snapshot(),
+                    health: self.health.snapshot(),
+                    clock_drift: self.ts_gen.drift(),
+                    ..Default::default()
                 };
 
                 debug!(
                     frames = %stats.frames_sent,
                     errors = %stats.send_errors,
                     rtp_packets = %stats.rtp_packets_sent,
+                    avg_wire_us = %stats.send_timing.avg_wire_us,
+                    max_gap_us = %stats.send_timing.max_gap_us,
+                    health = %stats.health.score,
                     "Streaming progress"
                 );
             }
@@ -310,3 +1558,753 @@ impl StreamerTask {
         info!("Frame sender task stopped");
     }
 }
+
+/// Next RTCP packet into `buf`, with its length and source: from the
+/// failover RTCP port when there is one, else from `socket`; pending forever
+/// with neither
+async fn next_rtcp(
+    failover: &Option<Failover>,
+    socket: Option<&UdpSocket>,
+    buf: &mut [u8],
+) -> std::io::Result<(usize, SocketAddr)> {
+    match (failover, socket) {
+        (Some(failover), _) if failover.listens_for_rtcp() => failover.recv_rtcp(buf).await,
+        (_, Some(socket)) => socket.recv_from(buf).await,
+        _ => std::future::pending().await,
+    }
+}
+
+/// New addresses of a named primary destination; pending forever when it
+/// isn't looked up again, `None` once the lookups have ended
+async fn next_addresses(
+    addresses: &mut Option<watch::Receiver<Vec<SocketAddr>>>,
+) -> Option<Vec<SocketAddr>> {
+    match addresses {
+        Some(addresses) => {
+            addresses.changed().await.ok()?;
+            Some(addresses.borrow_and_update().clone())
+        }
+        None => std::future::pending().await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::rtp::{build_receiver_report, ReportBlock};
+    use socket2::SockRef;
+
+    #[tokio::test]
+    async fn test_network_config_reaches_socket_and_packetizer() {
+        let toml = r#"
+[mjpeg-rtp.network]
+mtu = 1200
+dscp = 46
+sndbuf = 65536
+bind_address = "127.0.0.1"
+        "#;
+        let config = Config::from_str(toml).unwrap();
+        let rtp = &config.mjpeg_rtp;
+        let streamer_config =
+            StreamerConfig::default().with_network(&rtp.network_for(&rtp.camera1));
+
+        let mut streamer = Streamer::new(streamer_config).await.unwrap();
+        assert_eq!(streamer.packetizer.mtu(), 1200);
+
+        streamer.start().await.unwrap();
+        let socket = streamer.socket.as_ref().unwrap();
+        let sock = SockRef::from(socket.as_ref());
+        assert_eq!(sock.tos().unwrap(), 46 << 2);
+        // Linux reports double the requested size
+        assert!(sock.send_buffer_size().unwrap() >= 65536);
+        assert_eq!(
+            streamer.local_addr().unwrap().ip(),
+            IpAddr::from(Ipv4Addr::LOCALHOST)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_tos_replaces_dscp() {
+        let mut streamer = Streamer::new(StreamerConfig {
+            dscp: 46,
+            tos: Some(0xb9),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        streamer.start().await.unwrap();
+
+        let socket = streamer.socket.as_ref().unwrap();
+        assert_eq!(SockRef::from(socket.as_ref()).tos().unwrap(), 0xb9);
+    }
+
+    #[tokio::test]
+    async fn test_bind_address_family_mismatch() {
+        let mut streamer = Streamer::new(StreamerConfig {
+            bind_address: Some(IpAddr::V6(Ipv6Addr::LOCALHOST)),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        assert!(matches!(
+            streamer.start().await,
+            Err(StreamerError::InvalidDestination(_))
+        ));
+    }
+
+    /// Binds an RTP receiver and its RTCP neighbour on `port + 1`
+    async fn bind_rtp_pair() -> (UdpSocket, UdpSocket) {
+        loop {
+            let rtp = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+            let Some(rtcp_port) = rtp.local_addr().unwrap().port().checked_add(1) else {
+                continue;
+            };
+            if let Ok(rtcp) = UdpSocket::bind(("127.0.0.1", rtcp_port)).await {
+                return (rtp, rtcp);
+            }
+        }
+    }
+
+    async fn recv(socket: &UdpSocket) -> Vec<u8> {
+        let mut buf = vec![0u8; 2048];
+        let len = tokio::time::timeout(Duration::from_secs(2), socket.recv(&mut buf))
+            .await
+            .expect("nothing received")
+            .unwrap();
+        buf.truncate(len);
+        buf
+    }
+
+    fn test_frame() -> Bytes {
+        let mut jpeg = vec![0xFF, 0xD8];
+        jpeg.extend((0..100).map(|i| i as u8));
+        jpeg.extend([0xFF, 0xD9]);
+        Bytes::from(jpeg)
+    }
+
+    #[tokio::test]
+    async fn test_stop_flushes_queue_and_sends_bye() {
+        let (rtp, rtcp) = bind_rtp_pair().await;
+        let mut streamer = Streamer::new(StreamerConfig {
+            dest_port: rtp.local_addr().unwrap().port(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        streamer.start().await.unwrap();
+
+        for _ in 0..5 {
+            streamer.send_frame(test_frame()).await.unwrap();
+        }
+        streamer.stop().await.unwrap();
+
+        for _ in 0..5 {
+            recv(&rtp).await;
+        }
+        let ssrc = StreamerConfig::default().ssrc;
+        assert_eq!(recv(&rtcp).await, build_goodbye(ssrc, None));
+        assert_eq!(streamer.get_stats().frames_sent, 5);
+        assert!(!streamer.is_running());
+        assert!(streamer.local_addr().is_none());
+        assert!(matches!(
+            streamer.send_frame(test_frame()).await,
+            Err(StreamerError::NotRunning)
+        ));
+
+        // Stopping twice is a no-op
+        streamer.stop().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_resume_continues_numbering() {
+        let (rtp, rtcp) = bind_rtp_pair().await;
+        let mut streamer = Streamer::new(StreamerConfig {
+            dest_port: rtp.local_addr().unwrap().port(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        streamer.resume(65535, 1_000_000);
+        streamer.start().await.unwrap();
+
+        for id in [1, 2] {
+            streamer
+                .send_frame(Frame::new(id, test_frame()))
+                .await
+                .unwrap();
+        }
+        streamer.stop_without_bye().await.unwrap();
+
+        let headers: Vec<_> = [recv(&rtp).await, recv(&rtp).await]
+            .iter()
+            .map(|packet| crate::rtp::RtpHeader::from_bytes(packet).unwrap())
+            .map(|header| (header.sequence_number, header.timestamp))
+            .collect();
+        assert_eq!(headers, [(65535, 1_000_000), (0, 1_003_000)]);
+
+        let stats = streamer.get_stats();
+        assert_eq!(
+            (stats.current_seq_num, stats.current_timestamp),
+            (1, 1_003_000)
+        );
+        // No BYE
+        let mut buf = [0u8; 64];
+        assert!(
+            tokio::time::timeout(Duration::from_millis(100), rtcp.recv(&mut buf))
+                .await
+                .is_err()
+        );
+    }
+
+    async fn recv_from(socket: &UdpSocket) -> (Vec<u8>, SocketAddr) {
+        let mut buf = vec![0u8; 2048];
+        let (len, from) = tokio::time::timeout(Duration::from_secs(2), socket.recv_from(&mut buf))
+            .await
+            .expect("nothing received")
+            .unwrap();
+        buf.truncate(len);
+        (buf, from)
+    }
+
+    #[tokio::test]
+    async fn test_large_frames_packetized_in_chunks() {
+        let (rtp, _rtcp) = bind_rtp_pair().await;
+        let mut streamer = Streamer::new(StreamerConfig {
+            dest_port: rtp.local_addr().unwrap().port(),
+            mtu: MIN_MTU,
+            parallel_packetize_bytes: 1000,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        streamer.start().await.unwrap();
+
+        // Several chunks' worth of small packets, to fit the receive buffer
+        let mut jpeg = vec![0xFF, 0xD8];
+        jpeg.extend((0..PACKETIZE_CHUNK * 1200).map(|i| (i % 251) as u8));
+        jpeg.extend([0xFF, 0xD9]);
+        let expected = RtpPacketizer::new(StreamerConfig::default().ssrc, MIN_MTU)
+            .packetize_frame(&jpeg, 1, 640, 480, 0)
+            .unwrap();
+        assert!(expected.len() > 2 * PACKETIZE_CHUNK);
+
+        streamer
+            .send_frame(Frame::new(1, Bytes::from(jpeg)))
+            .await
+            .unwrap();
+        for packet in &expected {
+            assert_eq!(recv(&rtp).await, &packet[..]);
+        }
+        assert_eq!(streamer.get_stats().rtp_packets_sent, expected.len() as u64);
+
+        streamer.stop().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_dropped_frames_advance_timestamp() {
+        let (rtp, _rtcp) = bind_rtp_pair().await;
+        let mut streamer = Streamer::new(StreamerConfig {
+            dest_port: rtp.local_addr().unwrap().port(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        streamer.start().await.unwrap();
+
+        // Frames 3 and 4 were lost before reaching the streamer
+        let mut timestamps = Vec::new();
+        for id in [1, 2, 5] {
+            streamer
+                .send_frame(Frame::new(id, test_frame()))
+                .await
+                .unwrap();
+            let (packet, _) = recv_from(&rtp).await;
+            timestamps.push(u32::from_be_bytes(packet[4..8].try_into().unwrap()));
+        }
+        let increment = crate::rtp::RTP_CLOCK_RATE / StreamerConfig::default().fps;
+        assert_eq!(timestamps, [0, increment, 4 * increment]);
+
+        streamer.stop().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_egress_limit_delays_then_drops() {
+        let (rtp, _rtcp) = bind_rtp_pair().await;
+        // 1 byte/ms with room for 200 bytes; a test frame is 152 on the wire
+        let mut streamer = Streamer::new(StreamerConfig {
+            dest_port: rtp.local_addr().unwrap().port(),
+            max_kbps: Some(8),
+            shaping_burst: Duration::from_millis(200),
+            shaping_max_delay: Duration::from_millis(120),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        streamer.start().await.unwrap();
+
+        // The first frame fits, the second waits 104 ms for the rest and
+        // the third would wait 152 ms
+        for id in 1..=3 {
+            streamer
+                .send_frame(Frame::new(id, test_frame()))
+                .await
+                .unwrap();
+        }
+        streamer.stop().await.unwrap();
+
+        let stats = streamer.get_stats();
+        assert_eq!(stats.frames_sent, 2);
+        assert_eq!(stats.dropped_by_shaper, 1);
+        assert_eq!(stats.frames_dropped, 1);
+        assert_eq!(stats.shaping.frames_delayed, 1);
+        assert!(stats.shaping.max_delay_us >= 100_000);
+    }
+
+    #[tokio::test]
+    async fn test_receiver_reports_become_feedback() {
+        let (rtp, rtcp) = bind_rtp_pair().await;
+        let mut streamer = Streamer::new(StreamerConfig {
+            dest_port: rtp.local_addr().unwrap().port(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        let mut feedback = streamer.feedback();
+        streamer.start().await.unwrap();
+        let rtcp_local = streamer.rtcp_socket.as_ref().unwrap().local_addr().unwrap();
+
+        // A block about another source is ignored
+        let block = |ssrc| ReportBlock {
+            ssrc,
+            fraction_lost: 64,
+            jitter: 90,
+            ..Default::default()
+        };
+        let ssrc = StreamerConfig::default().ssrc;
+        let report = build_receiver_report(0x5678, &[block(ssrc ^ 1), block(ssrc)]);
+        rtcp.send_to(&report, rtcp_local).await.unwrap();
+
+        tokio::time::timeout(Duration::from_secs(2), feedback.changed())
+            .await
+            .expect("no feedback")
+            .unwrap();
+        let received = feedback.borrow_and_update().unwrap();
+        assert_eq!(received.fraction_lost, 0.25);
+        assert_eq!(received.jitter, 90);
+        streamer.stop().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_rtcp_uses_odd_port_above_rtp() {
+        let (rtp, rtcp) = bind_rtp_pair().await;
+        let mut streamer = Streamer::new(StreamerConfig {
+            dest_port: rtp.local_addr().unwrap().port(),
+            cname: Some("cam@test".to_string()),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        streamer.start().await.unwrap();
+        let local_port = streamer.local_addr().unwrap().port();
+        assert_eq!(local_port % 2, 0);
+
+        streamer.send_frame(test_frame()).await.unwrap();
+        let (sdes, from) = recv_from(&rtcp).await;
+        assert_eq!(
+            sdes,
+            build_cname_report(StreamerConfig::default().ssrc, "cam@test")
+        );
+        assert_eq!(from.port(), local_port + 1);
+        assert_eq!(recv_from(&rtp).await.1.port(), local_port);
+
+        streamer.stop().await.unwrap();
+        assert_eq!(recv_from(&rtcp).await.1.port(), local_port + 1);
+    }
+
+    #[tokio::test]
+    async fn test_rtcp_mux_shares_rtp_port() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let mut streamer = Streamer::new(StreamerConfig {
+            dest_port: receiver.local_addr().unwrap().port(),
+            rtcp_mux: true,
+            cname: Some("cam@test".to_string()),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        streamer.start().await.unwrap();
+        let local = streamer.local_addr().unwrap();
+
+        streamer.send_frame(test_frame()).await.unwrap();
+        let (sdes, from) = recv_from(&receiver).await;
+        assert_eq!(sdes[1], crate::rtp::RTCP_PT_RR);
+        assert_eq!(sdes[9], crate::rtp::RTCP_PT_SDES);
+        assert_eq!(from.port(), local.port());
+        let (rtp, _) = recv_from(&receiver).await;
+        assert_eq!(rtp[1] & 0x7F, crate::rtp::RTP_PAYLOAD_TYPE_JPEG);
+
+        streamer.stop().await.unwrap();
+        let goodbye = build_goodbye(StreamerConfig::default().ssrc, streamer.cname());
+        assert_eq!(recv_from(&receiver).await.0, goodbye);
+    }
+
+    #[tokio::test]
+    async fn test_bundle_shares_socket() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let bundle = SharedSocket::new();
+        let config = |ssrc| StreamerConfig {
+            dest_port: receiver.local_addr().unwrap().port(),
+            rtcp_mux: true,
+            ssrc,
+            bundle: Some(bundle.clone()),
+            ..Default::default()
+        };
+        let mut first = Streamer::new(config(1)).await.unwrap();
+        let mut second = Streamer::new(config(2)).await.unwrap();
+        first.start().await.unwrap();
+        second.start().await.unwrap();
+        let local = first.local_addr().unwrap();
+        assert_eq!(second.local_addr(), Some(local));
+
+        for (streamer, ssrc) in [(&first, 1u32), (&second, 2)] {
+            streamer.send_frame(test_frame()).await.unwrap();
+            let (packet, from) = recv_from(&receiver).await;
+            assert_eq!(from, local);
+            assert_eq!(u32::from_be_bytes(packet[8..12].try_into().unwrap()), ssrc);
+        }
+
+        // The socket stays open until the last streamer stops
+        first.stop().await.unwrap();
+        recv_from(&receiver).await;
+        second.send_frame(test_frame()).await.unwrap();
+        assert_eq!(recv_from(&receiver).await.1, local);
+        second.stop().await.unwrap();
+        recv_from(&receiver).await;
+        assert!(!bundle.is_bound());
+
+        let mut unmuxed = Streamer::new(StreamerConfig {
+            rtcp_mux: false,
+            ..config(3)
+        })
+        .await
+        .unwrap();
+        assert!(matches!(
+            unmuxed.start().await,
+            Err(StreamerError::InvalidDestination(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_extra_destination_gets_same_packets() {
+        let primary = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let ground = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let mut streamer = Streamer::new(StreamerConfig {
+            dest_port: primary.local_addr().unwrap().port(),
+            rtcp_mux: true,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        streamer.start().await.unwrap();
+
+        let ground_addr = ground.local_addr().unwrap();
+        assert!(streamer.add_destination(ground_addr).unwrap());
+        streamer.send_frame(test_frame()).await.unwrap();
+        let packet = recv(&primary).await;
+        assert_eq!(recv(&ground).await, packet);
+
+        let stats = streamer.get_stats().destinations;
+        assert_eq!(stats.len(), 1);
+        assert_eq!(stats[0].addr, ground_addr);
+        assert_eq!(stats[0].frames_sent, 1);
+        assert_eq!(stats[0].bytes_sent, packet.len() as u64);
+
+        assert!(streamer.remove_destination(ground_addr));
+        streamer.send_frame(test_frame()).await.unwrap();
+        recv(&primary).await;
+        let mut buf = [0u8; 2048];
+        assert!(
+            tokio::time::timeout(Duration::from_millis(200), ground.recv(&mut buf))
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_named_destination() {
+        let (rtp, _rtcp) = bind_rtp_pair().await;
+        let mut streamer = Streamer::new(StreamerConfig {
+            dest_host: "localhost".to_string(),
+            dest_port: rtp.local_addr().unwrap().port(),
+            bind_address: Some(IpAddr::V4(Ipv4Addr::LOCALHOST)),
+            dns_refresh: Duration::from_millis(50),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        streamer.start().await.unwrap();
+        assert_eq!(streamer.get_destination(), Some(rtp.local_addr().unwrap()));
+
+        // Lookups run beside the sender without disturbing it
+        tokio::time::sleep(Duration::from_millis(120)).await;
+        streamer.send_frame(test_frame()).await.unwrap();
+        recv(&rtp).await;
+
+        // The lookups end with the sender
+        let stopped = Instant::now();
+        streamer.stop().await.unwrap();
+        assert!(stopped.elapsed() < STOP_FLUSH_TIMEOUT);
+    }
+
+    #[tokio::test]
+    async fn test_keepalives_while_idle() {
+        let (rtp, rtcp) = bind_rtp_pair().await;
+        let config = StreamerConfig {
+            dest_port: rtp.local_addr().unwrap().port(),
+            keepalive_interval: Some(Duration::from_millis(50)),
+            ..Default::default()
+        };
+        let mut streamer = Streamer::new(config.clone()).await.unwrap();
+        streamer.start().await.unwrap();
+
+        // Padding only, taking a sequence number the frame carries on from
+        let keepalive = recv(&rtp).await;
+        let header = crate::rtp::RtpHeader::from_bytes(&keepalive).unwrap();
+        assert!(header.padding);
+        assert_eq!(header.ssrc, config.ssrc);
+        streamer.send_frame(test_frame()).await.unwrap();
+        let frame = loop {
+            let packet = recv(&rtp).await;
+            if !crate::rtp::RtpHeader::from_bytes(&packet).unwrap().padding {
+                break packet;
+            }
+        };
+        let seq = crate::rtp::RtpHeader::from_bytes(&frame)
+            .unwrap()
+            .sequence_number;
+        assert!(seq > header.sequence_number);
+        assert!(streamer.get_stats().keepalives_sent >= 1);
+
+        streamer
+            .restart(StreamerConfig {
+                keepalive_kind: KeepaliveKind::Rtcp,
+                ..config
+            })
+            .await
+            .unwrap();
+        // The BYE of the first run, then receiver reports
+        let mut packet = recv(&rtcp).await;
+        if packet.ends_with(&crate::rtp::build_bye(streamer.ssrc())) {
+            packet = recv(&rtcp).await;
+        }
+        assert_eq!(packet[1], crate::rtp::RTCP_PT_RR);
+        assert_eq!(packet.len(), 8);
+        streamer.stop().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_restart_moves_destination() {
+        let (first, _first_rtcp) = bind_rtp_pair().await;
+        let (second, _second_rtcp) = bind_rtp_pair().await;
+        let config = StreamerConfig {
+            dest_port: first.local_addr().unwrap().port(),
+            ..Default::default()
+        };
+        let mut streamer = Streamer::new(config.clone()).await.unwrap();
+        streamer.start().await.unwrap();
+
+        streamer.send_frame(test_frame()).await.unwrap();
+        let before = recv(&first).await;
+
+        streamer
+            .restart(StreamerConfig {
+                dest_port: second.local_addr().unwrap().port(),
+                ..config
+            })
+            .await
+            .unwrap();
+        assert!(streamer.is_running());
+        assert_eq!(
+            streamer.get_destination(),
+            Some(second.local_addr().unwrap())
+        );
+
+        streamer.send_frame(test_frame()).await.unwrap();
+        let after = recv(&second).await;
+
+        // Same SSRC and MTU, so the sequence carries on
+        let seq = |packet: &[u8]| u16::from_be_bytes([packet[2], packet[3]]);
+        assert_eq!(seq(&after), seq(&before).wrapping_add(1));
+    }
+
+    /// A local port with nothing bound to it
+    async fn closed_port() -> u16 {
+        UdpSocket::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port()
+    }
+
+    /// Sends frames until `receiver` gets one, returning the first event
+    async fn stream_until_received(streamer: &Streamer, receiver: &UdpSocket) -> StreamerEvent {
+        let mut events = streamer.subscribe();
+        let mut buf = [0u8; 2048];
+        for _ in 0..100 {
+            streamer.send_frame(test_frame()).await.unwrap();
+            if tokio::time::timeout(Duration::from_millis(20), receiver.recv(&mut buf))
+                .await
+                .is_ok()
+            {
+                return events.try_recv().unwrap();
+            }
+        }
+        panic!("backup never received a frame");
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_failover_on_unreachable() {
+        let primary = SocketAddr::from((Ipv4Addr::LOCALHOST, closed_port().await));
+        let backup = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let mut streamer = Streamer::new(StreamerConfig {
+            dest_port: primary.port(),
+            backup_destinations: vec![backup.local_addr().unwrap()],
+            failover_timeout: Duration::from_millis(50),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        streamer.start().await.unwrap();
+
+        let event = stream_until_received(&streamer, &backup).await;
+        assert_eq!(
+            event,
+            StreamerEvent::Failover {
+                from: primary,
+                to: backup.local_addr().unwrap(),
+                reason: FailoverReason::Unreachable,
+            }
+        );
+        assert_eq!(
+            streamer.get_destination(),
+            Some(backup.local_addr().unwrap())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_failover_on_rtcp_silence() {
+        let primary = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let backup = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let mut streamer = Streamer::new(StreamerConfig {
+            dest_port: primary.local_addr().unwrap().port(),
+            backup_destinations: vec![backup.local_addr().unwrap()],
+            rtcp_port: Some(closed_port().await),
+            failover_timeout: Duration::from_millis(50),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        streamer.start().await.unwrap();
+
+        match stream_until_received(&streamer, &backup).await {
+            StreamerEvent::Failover { reason, .. } => {
+                assert_eq!(reason, FailoverReason::RtcpTimeout)
+            }
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_unreachable_destination_backs_off() {
+        let mut streamer = Streamer::new(StreamerConfig {
+            dest_port: closed_port().await,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        streamer.start().await.unwrap();
+
+        for _ in 0..20 {
+            streamer.send_frame(test_frame()).await.unwrap();
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        streamer.stop().await.unwrap();
+
+        let stats = streamer.get_stats();
+        assert!(stats.send_error_kinds.unreachable >= 1);
+        assert_eq!(
+            stats.send_error_kinds.total(),
+            stats.send_error_kinds.unreachable
+        );
+        // Everything after the first report falls inside the backoff window
+        assert!(
+            stats.frames_dropped >= 15,
+            "dropped {}",
+            stats.frames_dropped
+        );
+        assert_eq!(stats.dropped_unreachable, stats.frames_dropped);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_oversized_packets_lower_mtu() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let mut streamer = Streamer::new(StreamerConfig {
+            dest_port: receiver.local_addr().unwrap().port(),
+            mtu: 70_000,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        streamer.start().await.unwrap();
+
+        let mut jpeg = vec![0xFF, 0xD8];
+        jpeg.resize(100_000, 0x55);
+        jpeg.extend([0xFF, 0xD9]);
+        streamer
+            .send_frame(Bytes::from(jpeg.clone()))
+            .await
+            .unwrap();
+        streamer.send_frame(Bytes::from(jpeg)).await.unwrap();
+        streamer.stop().await.unwrap();
+
+        let stats = streamer.get_stats();
+        assert!(stats.send_error_kinds.message_too_long >= 1);
+        assert!(streamer.packetizer.mtu() <= MAX_UDP_PAYLOAD);
+        assert_eq!(stats.frames_sent, 1);
+    }
+
+    #[tokio::test]
+    async fn test_backup_family_mismatch() {
+        let mut streamer = Streamer::new(StreamerConfig {
+            backup_destinations: vec!["[::1]:5000".parse().unwrap()],
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        assert!(matches!(
+            streamer.start().await,
+            Err(StreamerError::InvalidDestination(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_restart_dedicated_sender() {
+        let (rtp, _rtcp) = bind_rtp_pair().await;
+        let config = StreamerConfig {
+            dest_port: rtp.local_addr().unwrap().port(),
+            sender_core: Some(0),
+            ..Default::default()
+        };
+        let mut streamer = Streamer::new(config.clone()).await.unwrap();
+        streamer.start().await.unwrap();
+        streamer.restart(config).await.unwrap();
+
+        streamer.send_frame(test_frame()).await.unwrap();
+        recv(&rtp).await;
+    }
+}
@@ -0,0 +1,119 @@
+//! Destinations given by host name
+//!
+//! `dest_host` may be a name rather than an IP address, e.g. the dynamic DNS
+//! name of a receiver whose public IP changes. It is looked up when the
+//! streamer starts, and again every `dns_refresh` by a task beside the
+//! sender. All addresses it resolves to are the primary destination's:
+//! packets go to the first, and failover moves on to the next when it is
+//! gone, before any backups. When a lookup no longer returns the address in
+//! use, the sender moves to the first new one, published as a failover with
+//! reason `Resolved`.
+
+use super::StreamerError;
+use crate::task::CancellationToken;
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+use tokio::sync::watch;
+use tracing::{info, warn};
+
+/// Whether `host` has to be looked up
+pub(crate) fn is_name(host: &str) -> bool {
+    host.parse::<IpAddr>().is_err()
+}
+
+/// Addresses of `host` in the order the resolver gives them, only those of
+/// `family`'s address family when set
+pub(crate) async fn resolve(
+    host: &str,
+    port: u16,
+    family: Option<IpAddr>,
+) -> Result<Vec<SocketAddr>, StreamerError> {
+    let found = tokio::net::lookup_host((host, port))
+        .await
+        .map_err(|e| StreamerError::InvalidDestination(format!("{}: {}", host, e)))?;
+    let mut addrs: Vec<SocketAddr> = Vec::new();
+    for addr in found {
+        let usable = family.is_none_or(|ip| ip.is_ipv4() == addr.is_ipv4());
+        if usable && !addrs.contains(&addr) {
+            addrs.push(addr);
+        }
+    }
+    if addrs.is_empty() {
+        return Err(StreamerError::InvalidDestination(format!(
+            "{} has no address of the family sent from",
+            host
+        )));
+    }
+    Ok(addrs)
+}
+
+/// Looks `host` up every `every`, publishing its addresses when they
+/// change. Ends when cancelled or the sender drops its receiver.
+pub(crate) async fn refresh(
+    host: String,
+    port: u16,
+    every: Duration,
+    family: IpAddr,
+    addrs: watch::Sender<Vec<SocketAddr>>,
+    token: CancellationToken,
+) {
+    loop {
+        tokio::select! {
+            _ = token.cancelled() => break,
+            _ = addrs.closed() => break,
+            _ = tokio::time::sleep(every) => {}
+        }
+        let resolved = match resolve(&host, port, Some(family)).await {
+            Ok(resolved) => resolved,
+            Err(e) => {
+                warn!(host, error = %e, "Failed to look up the RTP destination, keeping its addresses");
+                continue;
+            }
+        };
+        addrs.send_if_modified(|current| {
+            // Round-robin DNS reorders the same records on every lookup
+            if same_addresses(current, &resolved) {
+                return false;
+            }
+            info!(host, from = ?current, to = ?resolved, "RTP destination resolves to new addresses");
+            *current = resolved;
+            true
+        });
+    }
+}
+
+fn same_addresses(a: &[SocketAddr], b: &[SocketAddr]) -> bool {
+    a.len() == b.len() && a.iter().all(|addr| b.contains(addr))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_resolve_names_and_literals() {
+        assert!(!is_name("192.168.1.100"));
+        assert!(!is_name("::1"));
+        assert!(is_name("localhost"));
+
+        let addrs = resolve("127.0.0.1", 5000, None).await.unwrap();
+        assert_eq!(addrs, vec!["127.0.0.1:5000".parse().unwrap()]);
+
+        let v4: IpAddr = "0.0.0.0".parse().unwrap();
+        let addrs = resolve("localhost", 5000, Some(v4)).await.unwrap();
+        assert!(addrs
+            .iter()
+            .all(|addr| addr.is_ipv4() && addr.port() == 5000));
+
+        let v6: IpAddr = "::".parse().unwrap();
+        assert!(resolve("127.0.0.1", 5000, Some(v6)).await.is_err());
+    }
+
+    #[test]
+    fn test_reordered_records_are_the_same() {
+        let a: SocketAddr = "10.0.0.5:5000".parse().unwrap();
+        let b: SocketAddr = "10.0.0.6:5000".parse().unwrap();
+        assert!(same_addresses(&[a, b], &[b, a]));
+        assert!(!same_addresses(&[a, b], &[a]));
+    }
+}
@@ -0,0 +1,258 @@
+//! Batched UDP sends with userland wire-out timestamps
+//!
+//! On Linux a frame's packets go out through `sendmmsg`, up to
+//! [`MAX_BATCH`] per syscall; elsewhere they are sent one by one. The time
+//! each syscall returns is recorded so the caller can derive how long the
+//! frame took to leave the process and the gaps between sends. These are
+//! userland timestamps: kernel queueing and NIC scheduling are not included.
+//!
+//! A packet refused with ENOBUFS is retried after a short pause, so a full
+//! device queue slows the sender down instead of dropping the rest of the
+//! frame. Failures are logged through a [`LogLimiter`], once per second per
+//! error class.
+
+use super::errors::{SendErrorKind, SendErrorStats};
+use crate::log_limited;
+use crate::ratelimit::LogLimiter;
+use bytes::Bytes;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+use tokio::net::UdpSocket;
+
+/// Packets handed to the kernel per `sendmmsg` call
+pub const MAX_BATCH: usize = 64;
+
+/// Pause before retrying a packet refused with ENOBUFS
+const NO_BUFFERS_PAUSE: Duration = Duration::from_millis(1);
+
+/// ENOBUFS retries per packet before it is skipped
+const NO_BUFFERS_RETRIES: u32 = 3;
+
+/// Outcome and timing of sending one frame's packets
+#[derive(Debug)]
+pub(crate) struct SendReport {
+    pub sent: usize,
+    pub errors: usize,
+    /// `errors` by class
+    pub error_kinds: SendErrorStats,
+    pub started: Instant,
+    /// When each send syscall returned, in order
+    pub completions: Vec<Instant>,
+}
+
+impl SendReport {
+    fn new() -> Self {
+        Self {
+            sent: 0,
+            errors: 0,
+            error_kinds: SendErrorStats::default(),
+            started: Instant::now(),
+            completions: Vec::new(),
+        }
+    }
+
+    /// Adds the sends of a later part of the same frame
+    pub fn append(&mut self, later: SendReport) {
+        self.sent += later.sent;
+        self.errors += later.errors;
+        self.error_kinds.add(&later.error_kinds);
+        self.completions.extend(later.completions);
+    }
+
+    /// Time from the first send call until the last one returned
+    pub fn wire_us(&self) -> u64 {
+        self.completions
+            .last()
+            .map(|last| last.duration_since(self.started).as_micros() as u64)
+            .unwrap_or(0)
+    }
+
+    /// Gaps between consecutive send completions
+    pub fn gaps_us(&self) -> impl Iterator<Item = u64> + '_ {
+        self.completions
+            .windows(2)
+            .map(|w| w[1].duration_since(w[0]).as_micros() as u64)
+    }
+
+    /// Records a failed packet send; returns whether to retry it
+    fn failed(
+        &mut self,
+        e: &std::io::Error,
+        packet: usize,
+        total: usize,
+        retries: &mut u32,
+        log: &LogLimiter,
+    ) -> bool {
+        let kind = SendErrorKind::classify(e);
+        if kind == SendErrorKind::NoBuffers && *retries < NO_BUFFERS_RETRIES {
+            *retries += 1;
+            return true;
+        }
+        *retries = 0;
+
+        match kind {
+            // Reported once per ICMP message; the streamer backs off instead
+            SendErrorKind::Unreachable => {
+                log_limited!(log, kind.name(), debug, error = %e, "RTP destination unreachable")
+            }
+            _ => log_limited!(
+                log,
+                kind.name(),
+                error,
+                error = %e,
+                packet = %packet,
+                total = %total,
+                "Failed to send RTP packet"
+            ),
+        }
+        self.errors += 1;
+        self.error_kinds.count(kind);
+        false
+    }
+}
+
+/// Largest UDP payload the kernel's path MTU to `dest` allows, when known
+#[cfg(target_os = "linux")]
+pub(crate) fn path_payload_size(socket: &UdpSocket, dest: SocketAddr) -> Option<usize> {
+    use std::os::fd::AsRawFd;
+
+    // IP + UDP headers
+    let (level, name, overhead) = match dest {
+        SocketAddr::V4(_) => (libc::IPPROTO_IP, libc::IP_MTU, 28),
+        SocketAddr::V6(_) => (libc::IPPROTO_IPV6, libc::IPV6_MTU, 48),
+    };
+    let mut mtu: libc::c_int = 0;
+    let mut len = std::mem::size_of::<libc::c_int>() as libc::socklen_t;
+    // SAFETY: `mtu` and `len` are valid for writes and `len` matches the
+    // size of `mtu`
+    let rc = unsafe {
+        libc::getsockopt(
+            socket.as_raw_fd(),
+            level,
+            name,
+            &mut mtu as *mut libc::c_int as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    (rc == 0 && mtu > 0).then(|| (mtu as usize).saturating_sub(overhead))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn path_payload_size(_socket: &UdpSocket, _dest: SocketAddr) -> Option<usize> {
+    None
+}
+
+#[cfg(target_os = "linux")]
+pub(crate) async fn send_packets(
+    socket: &UdpSocket,
+    packets: &[Bytes],
+    dest: SocketAddr,
+    log: &LogLimiter,
+) -> SendReport {
+    use std::os::fd::AsRawFd;
+    use tokio::io::Interest;
+
+    let addr = socket2::SockAddr::from(dest);
+    let fd = socket.as_raw_fd();
+    let mut report = SendReport::new();
+    let mut offset = 0;
+    let mut retries = 0;
+
+    while offset < packets.len() {
+        let end = (offset + MAX_BATCH).min(packets.len());
+        let chunk = &packets[offset..end];
+
+        match socket
+            .async_io(Interest::WRITABLE, || sendmmsg(fd, chunk, &addr))
+            .await
+        {
+            Ok(n) => {
+                report.sent += n;
+                offset += n;
+                retries = 0;
+            }
+            Err(e) => {
+                // sendmmsg stops at the failing message; retry or skip it and go on
+                if report.failed(&e, offset, packets.len(), &mut retries, log) {
+                    report.completions.push(Instant::now());
+                    tokio::time::sleep(NO_BUFFERS_PAUSE).await;
+                    continue;
+                }
+                offset += 1;
+            }
+        }
+        report.completions.push(Instant::now());
+    }
+
+    report
+}
+
+/// One `sendmmsg` call; returns how many packets the kernel accepted
+#[cfg(target_os = "linux")]
+fn sendmmsg(
+    fd: std::os::fd::RawFd,
+    chunk: &[Bytes],
+    addr: &socket2::SockAddr,
+) -> std::io::Result<usize> {
+    let mut iovecs: Vec<libc::iovec> = chunk
+        .iter()
+        .map(|packet| libc::iovec {
+            iov_base: packet.as_ptr() as *mut libc::c_void,
+            iov_len: packet.len(),
+        })
+        .collect();
+
+    let mut msgs: Vec<libc::mmsghdr> = iovecs
+        .iter_mut()
+        .map(|iov| {
+            // SAFETY: mmsghdr is plain old data; all pointers set below
+            // outlive the sendmmsg call
+            let mut msg: libc::mmsghdr = unsafe { std::mem::zeroed() };
+            msg.msg_hdr.msg_name = addr.as_ptr() as *mut libc::c_void;
+            msg.msg_hdr.msg_namelen = addr.len();
+            msg.msg_hdr.msg_iov = iov;
+            msg.msg_hdr.msg_iovlen = 1;
+            msg
+        })
+        .collect();
+
+    // SAFETY: `msgs` holds `msgs.len()` initialised headers pointing into
+    // `iovecs`, `chunk` and `addr`, all alive for the duration of the call
+    let rc = unsafe { libc::sendmmsg(fd, msgs.as_mut_ptr(), msgs.len() as _, 0) };
+    if rc < 0 {
+        Err(std::io::Error::last_os_error())
+    } else {
+        Ok(rc as usize)
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) async fn send_packets(
+    socket: &UdpSocket,
+    packets: &[Bytes],
+    dest: SocketAddr,
+    log: &LogLimiter,
+) -> SendReport {
+    let mut report = SendReport::new();
+    let mut retries = 0;
+
+    for (i, packet) in packets.iter().enumerate() {
+        loop {
+            let result = socket.send_to(packet, dest).await;
+            report.completions.push(Instant::now());
+            match result {
+                Ok(_) => report.sent += 1,
+                Err(e) => {
+                    if report.failed(&e, i, packets.len(), &mut retries, log) {
+                        tokio::time::sleep(NO_BUFFERS_PAUSE).await;
+                        continue;
+                    }
+                }
+            }
+            retries = 0;
+            break;
+        }
+    }
+
+    report
+}
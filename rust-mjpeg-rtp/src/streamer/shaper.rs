@@ -0,0 +1,256 @@
+//! Egress rate limit ahead of the UDP send
+//!
+//! JPEG frames of a busy scene can be several times the size of a quiet
+//! one, and on a radio link shared with the robot's command traffic those
+//! bursts crowd the commands out. Each frame waits until a token bucket for
+//! the camera, and one shared by all cameras, have room for it; a frame
+//! that would have to wait longer than `max_delay` is dropped instead, so
+//! the stream never falls further behind than that.
+
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Bytes a packet carries besides JPEG data: IPv4 and UDP (28), RTP (12)
+/// and the RFC 2435 main header (8)
+pub const PACKET_OVERHEAD: usize = 48;
+
+/// Bytes on the wire for a frame of `len` bytes split into `mtu`-sized
+/// packets
+pub fn wire_bytes(len: usize, mtu: usize) -> usize {
+    let payload = mtu.saturating_sub(PACKET_OVERHEAD).max(1);
+    len + len.div_ceil(payload).max(1) * PACKET_OVERHEAD
+}
+
+/// A rate in kbit/s with a burst allowance
+///
+/// A frame may go once the bucket holds as many bytes as the frame has, or
+/// is full; the frame's bytes are then taken, leaving the bucket in debt
+/// for frames larger than the burst, which the following frames wait off.
+#[derive(Debug)]
+pub struct TokenBucket {
+    /// Bytes per second
+    rate: f64,
+    /// Most bytes the bucket holds
+    burst: f64,
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    /// A full bucket for `kbps`, holding `burst` worth of traffic at that
+    /// rate
+    pub fn new(kbps: u64, burst: Duration, now: Instant) -> Self {
+        let rate = kbps.max(1) as f64 * 1000.0 / 8.0;
+        let burst = (rate * burst.as_secs_f64()).max(1.0);
+        Self {
+            rate,
+            burst,
+            tokens: burst,
+            updated: now,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.burst);
+        self.updated = now;
+    }
+
+    /// How long until `bytes` may go
+    pub fn wait(&mut self, bytes: usize, now: Instant) -> Duration {
+        self.refill(now);
+        let needed = (bytes as f64).min(self.burst);
+        if self.tokens >= needed {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64((needed - self.tokens) / self.rate)
+        }
+    }
+
+    /// Takes `bytes` sent after waiting as [`wait`](Self::wait) said
+    pub fn take(&mut self, bytes: usize, now: Instant) {
+        self.refill(now);
+        self.tokens -= bytes as f64;
+    }
+}
+
+/// A bucket for the total of several streamers; clones refer to the same
+/// one
+#[derive(Debug, Clone)]
+pub struct SharedLimit {
+    bucket: Arc<Mutex<TokenBucket>>,
+}
+
+impl SharedLimit {
+    pub fn new(kbps: u64, burst: Duration) -> Self {
+        Self {
+            bucket: Arc::new(Mutex::new(TokenBucket::new(kbps, burst, Instant::now()))),
+        }
+    }
+}
+
+/// What to do with a frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Admission {
+    /// Send it after this long
+    Send(Duration),
+    /// It would wait too long
+    Drop,
+}
+
+/// The limits one streamer's frames pass
+#[derive(Debug)]
+pub struct Shaper {
+    own: Option<TokenBucket>,
+    shared: Option<SharedLimit>,
+    max_delay: Duration,
+}
+
+impl Shaper {
+    /// A shaper for `kbps` of this stream and/or its share of `shared`;
+    /// `None` without either
+    pub fn new(
+        kbps: Option<u64>,
+        shared: Option<SharedLimit>,
+        burst: Duration,
+        max_delay: Duration,
+    ) -> Option<Self> {
+        if kbps.is_none() && shared.is_none() {
+            return None;
+        }
+        Some(Self {
+            own: kbps.map(|kbps| TokenBucket::new(kbps, burst, Instant::now())),
+            shared,
+            max_delay,
+        })
+    }
+
+    /// Decides on a frame of `bytes` on the wire, taking its bytes from
+    /// every bucket unless it is dropped
+    pub fn admit(&mut self, bytes: usize, now: Instant) -> Admission {
+        let mut shared = self
+            .shared
+            .as_ref()
+            .map(|limit| limit.bucket.lock().unwrap());
+        let wait = [self.own.as_mut(), shared.as_deref_mut()]
+            .into_iter()
+            .flatten()
+            .map(|bucket| bucket.wait(bytes, now))
+            .max()
+            .unwrap_or_default();
+        if wait > self.max_delay {
+            return Admission::Drop;
+        }
+        for bucket in [self.own.as_mut(), shared.as_deref_mut()]
+            .into_iter()
+            .flatten()
+        {
+            bucket.take(bytes, now);
+        }
+        Admission::Send(wait)
+    }
+}
+
+/// Snapshot of what the shaper did
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ShapingStats {
+    /// Frames held back before sending
+    pub frames_delayed: u64,
+
+    /// Total and longest time frames were held back
+    pub delay_us: u64,
+    pub max_delay_us: u64,
+}
+
+/// Accumulator shared between the sender task and stats readers
+#[derive(Debug, Default)]
+pub(crate) struct ShapingCounters {
+    frames_delayed: AtomicU64,
+    delay_us: AtomicU64,
+    max_delay_us: AtomicU64,
+}
+
+impl ShapingCounters {
+    pub fn record_delay(&self, delay: Duration) {
+        let delay_us = delay.as_micros() as u64;
+        self.frames_delayed.fetch_add(1, Ordering::Relaxed);
+        self.delay_us.fetch_add(delay_us, Ordering::Relaxed);
+        self.max_delay_us.fetch_max(delay_us, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> ShapingStats {
+        ShapingStats {
+            frames_delayed: self.frames_delayed.load(Ordering::Relaxed),
+            delay_us: self.delay_us.load(Ordering::Relaxed),
+            max_delay_us: self.max_delay_us.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MS: Duration = Duration::from_millis(1);
+
+    #[test]
+    fn test_wire_bytes() {
+        // 1400 - 48 = 1352 bytes of JPEG per packet
+        assert_eq!(wire_bytes(1352, 1400), 1400);
+        assert_eq!(wire_bytes(1353, 1400), 1353 + 2 * 48);
+        assert_eq!(wire_bytes(0, 1400), 48);
+    }
+
+    #[test]
+    fn test_bucket_rate_and_burst() {
+        let start = Instant::now();
+        // 8000 kbps = 1000 bytes/ms, 10 ms burst
+        let mut bucket = TokenBucket::new(8000, 10 * MS, start);
+
+        assert_eq!(bucket.wait(10_000, start), Duration::ZERO);
+        bucket.take(10_000, start);
+        assert_eq!(bucket.wait(5_000, start), 5 * MS);
+        assert_eq!(bucket.wait(5_000, start + 5 * MS), Duration::ZERO);
+
+        // A frame above the burst goes once the bucket is full, then its
+        // excess is waited off by the next one
+        let later = start + Duration::from_secs(1);
+        assert_eq!(bucket.wait(30_000, later), Duration::ZERO);
+        bucket.take(30_000, later);
+        assert_eq!(bucket.wait(1_000, later), 21 * MS);
+    }
+
+    #[test]
+    fn test_shaper_delays_then_drops() {
+        let start = Instant::now();
+        let mut shaper = Shaper::new(Some(8000), None, 10 * MS, 20 * MS).unwrap();
+
+        assert_eq!(shaper.admit(10_000, start), Admission::Send(Duration::ZERO));
+        assert_eq!(shaper.admit(10_000, start), Admission::Send(10 * MS));
+        // The bucket owes 10 ms and needs 10 more; that's 20 ms, still fine
+        assert_eq!(shaper.admit(10_000, start), Admission::Send(20 * MS));
+        assert_eq!(shaper.admit(10_000, start), Admission::Drop);
+        // A dropped frame takes nothing
+        assert_eq!(
+            shaper.admit(10_000, start + 30 * MS),
+            Admission::Send(Duration::ZERO)
+        );
+    }
+
+    #[test]
+    fn test_shared_limit_across_shapers() {
+        assert!(Shaper::new(None, None, 10 * MS, 20 * MS).is_none());
+
+        let start = Instant::now();
+        let shared = SharedLimit::new(8000, 10 * MS);
+        let mut first = Shaper::new(Some(80_000), Some(shared.clone()), 10 * MS, 5 * MS).unwrap();
+        let mut second = Shaper::new(None, Some(shared), 10 * MS, 5 * MS).unwrap();
+
+        assert_eq!(first.admit(10_000, start), Admission::Send(Duration::ZERO));
+        // The first camera used up the total
+        assert_eq!(second.admit(10_000, start), Admission::Drop);
+        assert_eq!(second.admit(2_000, start), Admission::Send(2 * MS));
+    }
+}
@@ -1,6 +1,13 @@
 //! Streaming statistics
 
+use super::destinations::DestinationStats;
+use super::errors::SendErrorStats;
+use super::health::HealthStats;
+use super::shaper::ShapingStats;
+use super::timing::SendTimingStats;
+use crate::rtp::ClockDriftStats;
 use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
 
 /// Statistics for UDP RTP streamer
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
@@ -8,12 +15,36 @@ pub struct StreamerStats {
     /// Total frames successfully sent
     pub frames_sent: u64,
 
-    /// Frames dropped due to full channel
+    /// Frames dropped due to a full channel, an unreachable destination or
+    /// the egress rate limits
     pub frames_dropped: u64,
 
+    /// Of `frames_dropped`, frames that found the send channel full or closed
+    #[serde(default)]
+    pub dropped_in_channel: u64,
+
+    /// Of `frames_dropped`, frames held back while the destination was
+    /// unreachable
+    #[serde(default)]
+    pub dropped_unreachable: u64,
+
+    /// Of `frames_dropped`, frames that would have waited longer than
+    /// `shaping_max_delay` for the egress rate limits
+    #[serde(default)]
+    pub dropped_by_shaper: u64,
+
+    /// Frames thinned out by the processors (decimate, dedup) before
+    /// reaching the streamer; deliberate, so not part of `frames_dropped`
+    #[serde(default)]
+    pub dropped_by_pacer: u64,
+
     /// Number of send errors
     pub send_errors: u64,
 
+    /// Failed packet sends by cause
+    #[serde(default)]
+    pub send_error_kinds: SendErrorStats,
+
     /// Total RTP packets sent
     pub rtp_packets_sent: u64,
 
@@ -23,8 +54,32 @@ pub struct StreamerStats {
     /// Current RTP sequence number
     pub current_seq_num: u32,
 
-    /// Current RTP timestamp
+    /// RTP timestamp of the last frame sent
     pub current_timestamp: u32,
+
+    /// Wire-out time per frame and gaps between sends
+    #[serde(default)]
+    pub send_timing: SendTimingStats,
+
+    /// Frame continuity and rolling health score
+    #[serde(default)]
+    pub health: HealthStats,
+
+    /// Drift of frame-count timestamps against the capture clock
+    #[serde(default)]
+    pub clock_drift: ClockDriftStats,
+
+    /// Frames held back by the egress rate limits
+    #[serde(default)]
+    pub shaping: ShapingStats,
+
+    /// Extra destinations added at runtime, with their own counters
+    #[serde(default)]
+    pub destinations: Vec<DestinationStats>,
+
+    /// Keepalives sent while no frames went out
+    #[serde(default)]
+    pub keepalives_sent: u64,
 }
 
 impl StreamerStats {
@@ -59,6 +114,28 @@ impl StreamerStats {
     }
 }
 
+/// Dropped frames per stage, shared between the streamer, its sender task
+/// and stats readers
+#[derive(Debug, Default)]
+pub(crate) struct DropCounters {
+    pub in_channel: AtomicU64,
+    pub unreachable: AtomicU64,
+    pub by_shaper: AtomicU64,
+    pub by_pacer: AtomicU64,
+}
+
+impl DropCounters {
+    /// Fills in the drop fields of `stats`
+    pub fn snapshot_into(&self, stats: &mut StreamerStats) {
+        stats.dropped_in_channel = self.in_channel.load(Ordering::Relaxed);
+        stats.dropped_unreachable = self.unreachable.load(Ordering::Relaxed);
+        stats.dropped_by_shaper = self.by_shaper.load(Ordering::Relaxed);
+        stats.dropped_by_pacer = self.by_pacer.load(Ordering::Relaxed);
+        stats.frames_dropped =
+            stats.dropped_in_channel + stats.dropped_unreachable + stats.dropped_by_shaper;
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -106,4 +183,31 @@ mod tests {
         let loss_rate = stats.packet_loss_rate();
         assert_eq!(loss_rate, 0.1); // 10% loss
     }
+
+    #[test]
+    fn test_drop_counters() {
+        let drops = DropCounters::default();
+        drops.in_channel.fetch_add(3, Ordering::Relaxed);
+        drops.unreachable.fetch_add(2, Ordering::Relaxed);
+        drops.by_shaper.fetch_add(1, Ordering::Relaxed);
+        drops.by_pacer.fetch_add(7, Ordering::Relaxed);
+
+        let mut stats = StreamerStats::default();
+        drops.snapshot_into(&mut stats);
+        assert_eq!(stats.dropped_in_channel, 3);
+        assert_eq!(stats.dropped_unreachable, 2);
+        assert_eq!(stats.dropped_by_shaper, 1);
+        assert_eq!(stats.dropped_by_pacer, 7);
+        // Pacer drops are deliberate and don't count as loss
+        assert_eq!(stats.frames_dropped, 6);
+
+        // Stats from before the split still parse
+        let old: StreamerStats = serde_json::from_str(
+            r#"{"frames_sent":1,"frames_dropped":4,"send_errors":0,"rtp_packets_sent":1,
+                "bytes_sent":10,"current_seq_num":1,"current_timestamp":0}"#,
+        )
+        .unwrap();
+        assert_eq!(old.frames_dropped, 4);
+        assert_eq!(old.dropped_by_pacer, 0);
+    }
 }
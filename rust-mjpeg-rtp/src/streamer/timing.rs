@@ -0,0 +1,129 @@
+//! Wire-out time and inter-send gap statistics
+
+use super::send::SendReport;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Snapshot of send timing, see [`super::send`] for what is measured
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SendTimingStats {
+    /// Frames with timing recorded
+    pub frames: u64,
+
+    /// Send syscalls (sendmmsg batches, or single sends off Linux)
+    pub batches: u64,
+
+    /// Average / maximum time for a frame's packets to leave the process
+    pub avg_wire_us: u64,
+    pub max_wire_us: u64,
+
+    /// Gaps between consecutive send completions within a frame
+    pub min_gap_us: u64,
+    pub avg_gap_us: u64,
+    pub max_gap_us: u64,
+}
+
+/// Lock-free accumulator shared between the sender task and stats readers
+#[derive(Debug)]
+pub(crate) struct SendTiming {
+    frames: AtomicU64,
+    batches: AtomicU64,
+    wire_us_total: AtomicU64,
+    wire_us_max: AtomicU64,
+    gaps: AtomicU64,
+    gap_us_total: AtomicU64,
+    gap_us_min: AtomicU64,
+    gap_us_max: AtomicU64,
+}
+
+impl Default for SendTiming {
+    fn default() -> Self {
+        Self {
+            frames: AtomicU64::new(0),
+            batches: AtomicU64::new(0),
+            wire_us_total: AtomicU64::new(0),
+            wire_us_max: AtomicU64::new(0),
+            gaps: AtomicU64::new(0),
+            gap_us_total: AtomicU64::new(0),
+            gap_us_min: AtomicU64::new(u64::MAX),
+            gap_us_max: AtomicU64::new(0),
+        }
+    }
+}
+
+impl SendTiming {
+    pub fn record(&self, report: &SendReport) {
+        let wire_us = report.wire_us();
+        self.frames.fetch_add(1, Ordering::Relaxed);
+        self.batches
+            .fetch_add(report.completions.len() as u64, Ordering::Relaxed);
+        self.wire_us_total.fetch_add(wire_us, Ordering::Relaxed);
+        self.wire_us_max.fetch_max(wire_us, Ordering::Relaxed);
+
+        for gap in report.gaps_us() {
+            self.gaps.fetch_add(1, Ordering::Relaxed);
+            self.gap_us_total.fetch_add(gap, Ordering::Relaxed);
+            self.gap_us_min.fetch_min(gap, Ordering::Relaxed);
+            self.gap_us_max.fetch_max(gap, Ordering::Relaxed);
+        }
+    }
+
+    pub fn snapshot(&self) -> SendTimingStats {
+        let frames = self.frames.load(Ordering::Relaxed);
+        let gaps = self.gaps.load(Ordering::Relaxed);
+        let min_gap = self.gap_us_min.load(Ordering::Relaxed);
+
+        SendTimingStats {
+            frames,
+            batches: self.batches.load(Ordering::Relaxed),
+            avg_wire_us: self.wire_us_total.load(Ordering::Relaxed) / frames.max(1),
+            max_wire_us: self.wire_us_max.load(Ordering::Relaxed),
+            min_gap_us: if gaps == 0 { 0 } else { min_gap },
+            avg_gap_us: self.gap_us_total.load(Ordering::Relaxed) / gaps.max(1),
+            max_gap_us: self.gap_us_max.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    fn report(started: Instant, offsets_us: &[u64]) -> SendReport {
+        SendReport {
+            sent: offsets_us.len(),
+            errors: 0,
+            error_kinds: Default::default(),
+            started,
+            completions: offsets_us
+                .iter()
+                .map(|us| started + Duration::from_micros(*us))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_empty_snapshot() {
+        let timing = SendTiming::default();
+        assert_eq!(timing.snapshot(), SendTimingStats::default());
+    }
+
+    #[test]
+    fn test_wire_time_and_gaps() {
+        let timing = SendTiming::default();
+        let now = Instant::now();
+
+        timing.record(&report(now, &[100, 300, 400]));
+        timing.record(&report(now, &[500]));
+
+        let stats = timing.snapshot();
+        assert_eq!(stats.frames, 2);
+        assert_eq!(stats.batches, 4);
+        assert_eq!(stats.avg_wire_us, 450);
+        assert_eq!(stats.max_wire_us, 500);
+        assert_eq!(stats.min_gap_us, 100);
+        assert_eq!(stats.avg_gap_us, 150);
+        assert_eq!(stats.max_gap_us, 200);
+    }
+}
@@ -0,0 +1,751 @@
+//! Components started in dependency order and restarted when they fail
+//!
+//! A [`Supervisor`] owns the long-running parts of an application (web
+//! server, sensors, cameras, recorders) as [`Component`]s. It starts them so
+//! that each one's dependencies are already running, checks their health
+//! every second, and restarts a failed component on its own, with backoff,
+//! once its dependencies are running again; the rest keep going. Shutdown
+//! stops them in reverse order. Each component's state is kept in
+//! [`ComponentStates`] for status endpoints.
+//!
+//! Most components are a task: [`TaskComponent`] runs one from a closure and
+//! counts it failed when it returns an error or panics. A task that returns
+//! `Ok` has finished its job and isn't restarted.
+
+use crate::error::ErrorCode;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+use tracing::{debug, error, info, warn};
+
+pub use tokio_util::sync::CancellationToken;
+
+/// Future returned by [`Component`] methods
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// Why a component failed, whatever error type it uses
+pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+#[derive(Error, Debug)]
+pub enum SupervisorError {
+    #[error("{component} depends on unknown component {dependency}")]
+    UnknownDependency {
+        component: String,
+        dependency: String,
+    },
+
+    #[error("circular dependencies between {}", .0.join(", "))]
+    CircularDependencies(Vec<String>),
+}
+
+impl SupervisorError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            SupervisorError::UnknownDependency { .. }
+            | SupervisorError::CircularDependencies(_) => ErrorCode::InvalidConfig,
+        }
+    }
+}
+
+/// How often components' health is checked
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Backoff between restarts of a failing component
+const RESTART_MIN: Duration = Duration::from_secs(1);
+const RESTART_MAX: Duration = Duration::from_secs(60);
+
+/// Running this long without failing resets a component's backoff
+const STABLE_AFTER: Duration = Duration::from_secs(60);
+
+/// How long a failed component gets to stop before it is restarted
+const STOP_GRACE: Duration = Duration::from_secs(5);
+
+/// Where a component is in its life
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ComponentState {
+    /// Not started yet
+    Pending,
+    Starting,
+    Running,
+    /// Failed, waiting for its backoff or its dependencies to restart
+    Restarting,
+    /// Finished its job, or stopped at shutdown
+    Stopped,
+}
+
+/// What a running component reports about itself
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Health {
+    Healthy,
+    /// Done, and doesn't need restarting
+    Finished,
+    /// Needs restarting, for this reason
+    Failed(String),
+}
+
+/// A long-running part of the application the [`Supervisor`] manages
+pub trait Component: Send + 'static {
+    fn name(&self) -> &str;
+
+    /// Names of the components that must be running before this one starts
+    fn dependencies(&self) -> &[String] {
+        &[]
+    }
+
+    /// Brings the component up. An error counts as a failure, retried with
+    /// backoff.
+    fn start(&mut self) -> BoxFuture<'_, Result<(), BoxError>>;
+
+    /// Stops the component, waiting up to `grace` before forcing it. Returns
+    /// false if it had to be forced.
+    fn stop(&mut self, grace: Duration) -> BoxFuture<'_, bool>;
+
+    /// Checked every second while the component runs
+    fn health(&self) -> Health;
+}
+
+/// One component's state, as reported by status endpoints
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ComponentStatus {
+    pub name: String,
+    pub state: ComponentState,
+    pub dependencies: Vec<String>,
+
+    /// Restarts after failures since the application started
+    pub restarts: u32,
+
+    /// Why the component last failed
+    pub last_error: Option<String>,
+}
+
+/// States of a supervisor's components, in start order
+#[derive(Clone, Default)]
+pub struct ComponentStates {
+    states: Arc<Mutex<Vec<ComponentStatus>>>,
+}
+
+impl ComponentStates {
+    pub fn snapshot(&self) -> Vec<ComponentStatus> {
+        self.states.lock().unwrap().clone()
+    }
+
+    fn update(&self, index: usize, update: impl FnOnce(&mut ComponentStatus)) {
+        if let Some(status) = self.states.lock().unwrap().get_mut(index) {
+            update(status);
+        }
+    }
+}
+
+/// A component and what the supervisor tracks about it
+struct Entry {
+    component: Box<dyn Component>,
+    state: ComponentState,
+    backoff: Duration,
+    retry_at: Instant,
+    running_since: Instant,
+}
+
+/// Starts, watches and stops a set of [`Component`]s
+pub struct Supervisor {
+    name: String,
+    entries: Vec<Entry>,
+    states: ComponentStates,
+    shutdown: Option<oneshot::Sender<Duration>>,
+    monitor: Option<JoinHandle<usize>>,
+    check_interval: Duration,
+    restart_min: Duration,
+    restart_max: Duration,
+}
+
+impl Supervisor {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self::with_states(name, ComponentStates::default())
+    }
+
+    /// A supervisor reporting into `states`, e.g. one an API already holds
+    pub fn with_states(name: impl Into<String>, states: ComponentStates) -> Self {
+        Self {
+            name: name.into(),
+            entries: Vec::new(),
+            states,
+            shutdown: None,
+            monitor: None,
+            check_interval: CHECK_INTERVAL,
+            restart_min: RESTART_MIN,
+            restart_max: RESTART_MAX,
+        }
+    }
+
+    /// Adds a component; call before [`Supervisor::start`]
+    pub fn add(&mut self, component: impl Component) {
+        let now = Instant::now();
+        self.entries.push(Entry {
+            component: Box::new(component),
+            state: ComponentState::Pending,
+            backoff: self.restart_min,
+            retry_at: now,
+            running_since: now,
+        });
+    }
+
+    pub fn states(&self) -> ComponentStates {
+        self.states.clone()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty() && self.monitor.is_none()
+    }
+
+    /// Starts every component after its dependencies, then watches them in
+    /// the background. Fails without starting anything if a dependency is
+    /// unknown or circular.
+    pub async fn start(&mut self) -> Result<(), SupervisorError> {
+        let order = start_order(&self.entries)?;
+        let mut entries: Vec<Option<Entry>> = self.entries.drain(..).map(Some).collect();
+        let entries: Vec<Entry> = order
+            .into_iter()
+            .filter_map(|i| entries[i].take())
+            .collect();
+
+        *self.states.states.lock().unwrap() = entries
+            .iter()
+            .map(|entry| ComponentStatus {
+                name: entry.component.name().to_string(),
+                state: ComponentState::Pending,
+                dependencies: entry.component.dependencies().to_vec(),
+                restarts: 0,
+                last_error: None,
+            })
+            .collect();
+
+        let mut monitor = Monitor {
+            supervisor: self.name.clone(),
+            entries,
+            states: self.states.clone(),
+            restart_min: self.restart_min,
+            restart_max: self.restart_max,
+        };
+        for index in 0..monitor.entries.len() {
+            // Dependents of a component that failed to start wait for it
+            if monitor.dependencies_running(index) {
+                monitor.start(index, false).await;
+            }
+        }
+        info!(supervisor = %self.name, components = monitor.entries.len(), "Components started");
+
+        let (shutdown_tx, shutdown_rx) = oneshot::channel();
+        self.shutdown = Some(shutdown_tx);
+        self.monitor = Some(tokio::spawn(monitor.run(self.check_interval, shutdown_rx)));
+        Ok(())
+    }
+
+    /// Stops every component in reverse start order, all within `grace`.
+    /// Returns how many had to be forced.
+    pub async fn shutdown(&mut self, grace: Duration) -> usize {
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(grace);
+        }
+        match self.monitor.take() {
+            Some(monitor) => monitor.await.unwrap_or_else(|e| {
+                error!(supervisor = %self.name, error = %e, "Supervisor panicked");
+                0
+            }),
+            None => 0,
+        }
+    }
+}
+
+/// Indices of `entries` with every component after its dependencies,
+/// otherwise in the order they were added
+fn start_order(entries: &[Entry]) -> Result<Vec<usize>, SupervisorError> {
+    let names: Vec<&str> = entries.iter().map(|entry| entry.component.name()).collect();
+    for entry in entries {
+        for dependency in entry.component.dependencies() {
+            if !names.contains(&dependency.as_str()) {
+                return Err(SupervisorError::UnknownDependency {
+                    component: entry.component.name().to_string(),
+                    dependency: dependency.clone(),
+                });
+            }
+        }
+    }
+
+    let mut started: HashSet<&str> = HashSet::new();
+    let mut order = Vec::with_capacity(entries.len());
+    while order.len() < entries.len() {
+        let next = entries.iter().enumerate().find(|(index, entry)| {
+            !order.contains(index)
+                && entry
+                    .component
+                    .dependencies()
+                    .iter()
+                    .all(|dependency| started.contains(dependency.as_str()))
+        });
+        let Some((index, entry)) = next else {
+            let waiting = (0..entries.len())
+                .filter(|index| !order.contains(index))
+                .map(|index| names[index].to_string())
+                .collect();
+            return Err(SupervisorError::CircularDependencies(waiting));
+        };
+        started.insert(entry.component.name());
+        order.push(index);
+    }
+    Ok(order)
+}
+
+/// The background side of a started [`Supervisor`]
+struct Monitor {
+    supervisor: String,
+    entries: Vec<Entry>,
+    states: ComponentStates,
+    restart_min: Duration,
+    restart_max: Duration,
+}
+
+impl Monitor {
+    async fn run(mut self, interval: Duration, mut shutdown: oneshot::Receiver<Duration>) -> usize {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        let grace = loop {
+            tokio::select! {
+                // A dropped supervisor stops its components straight away
+                grace = &mut shutdown => break grace.unwrap_or_default(),
+                _ = ticker.tick() => self.check().await,
+            }
+        };
+        self.stop_all(grace).await
+    }
+
+    /// Restarts what failed, once its backoff is over and its dependencies
+    /// are running
+    async fn check(&mut self) {
+        for index in 0..self.entries.len() {
+            let now = Instant::now();
+            let entry = &mut self.entries[index];
+            match entry.state {
+                ComponentState::Running => match entry.component.health() {
+                    Health::Healthy => {
+                        if now.duration_since(entry.running_since) >= STABLE_AFTER {
+                            entry.backoff = self.restart_min;
+                        }
+                    }
+                    Health::Finished => {
+                        info!(component = entry.component.name(), "Component finished");
+                        entry.component.stop(STOP_GRACE).await;
+                        self.set_state(index, ComponentState::Stopped);
+                    }
+                    Health::Failed(reason) => {
+                        error!(
+                            component = entry.component.name(),
+                            error = %reason,
+                            restart_in = ?entry.backoff,
+                            "Component failed"
+                        );
+                        entry.component.stop(STOP_GRACE).await;
+                        self.fail(index, reason);
+                    }
+                },
+                ComponentState::Pending | ComponentState::Restarting if now >= entry.retry_at => {
+                    let restart = entry.state == ComponentState::Restarting;
+                    if self.dependencies_running(index) {
+                        self.start(index, restart).await;
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    async fn start(&mut self, index: usize, restart: bool) {
+        self.set_state(index, ComponentState::Starting);
+        let entry = &mut self.entries[index];
+        debug!(component = entry.component.name(), "Starting component");
+        match entry.component.start().await {
+            Ok(()) => {
+                entry.running_since = Instant::now();
+                if restart {
+                    info!(component = entry.component.name(), "Component restarted");
+                    self.states.update(index, |status| status.restarts += 1);
+                }
+                self.set_state(index, ComponentState::Running);
+            }
+            Err(e) => {
+                error!(
+                    component = entry.component.name(),
+                    error = %e,
+                    restart_in = ?entry.backoff,
+                    "Component failed to start"
+                );
+                self.fail(index, error_chain(&*e));
+            }
+        }
+    }
+
+    /// Schedules a restart after the component's backoff, and doubles it
+    fn fail(&mut self, index: usize, reason: String) {
+        let entry = &mut self.entries[index];
+        entry.retry_at = Instant::now() + entry.backoff;
+        entry.backoff = (entry.backoff * 2).min(self.restart_max);
+        self.states
+            .update(index, |status| status.last_error = Some(reason));
+        self.set_state(index, ComponentState::Restarting);
+    }
+
+    fn dependencies_running(&self, index: usize) -> bool {
+        self.entries[index]
+            .component
+            .dependencies()
+            .iter()
+            .all(|dependency| {
+                self.entries.iter().any(|entry| {
+                    entry.component.name() == dependency && entry.state == ComponentState::Running
+                })
+            })
+    }
+
+    fn set_state(&mut self, index: usize, state: ComponentState) {
+        self.entries[index].state = state;
+        self.states.update(index, |status| status.state = state);
+    }
+
+    /// Stops the components in reverse start order, sharing `grace`
+    async fn stop_all(&mut self, grace: Duration) -> usize {
+        let deadline = Instant::now() + grace;
+        let mut forced = 0;
+        for index in (0..self.entries.len()).rev() {
+            let entry = &mut self.entries[index];
+            if entry.state == ComponentState::Running {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                if !entry.component.stop(remaining).await {
+                    warn!(
+                        component = entry.component.name(),
+                        "Component ignored shutdown, aborted"
+                    );
+                    forced += 1;
+                }
+            }
+            self.set_state(index, ComponentState::Stopped);
+        }
+        debug!(supervisor = %self.supervisor, "Components stopped");
+        forced
+    }
+}
+
+/// A component running one task
+///
+/// `run` is called with a fresh token on every start, and must return once
+/// the token is cancelled. Returning an error or panicking counts as a
+/// failure; returning `Ok` before being stopped means the task is done.
+pub struct TaskComponent<F> {
+    name: String,
+    dependencies: Vec<String>,
+    run: F,
+    token: CancellationToken,
+    task: Option<JoinHandle<()>>,
+    /// What the task returned, once it has
+    outcome: Arc<Mutex<Option<Result<(), String>>>>,
+}
+
+impl<F, Fut> TaskComponent<F>
+where
+    F: Fn(CancellationToken) -> Fut + Send + 'static,
+    Fut: Future<Output = Result<(), BoxError>> + Send + 'static,
+{
+    pub fn new(name: impl Into<String>, run: F) -> Self {
+        Self {
+            name: name.into(),
+            dependencies: Vec::new(),
+            run,
+            token: CancellationToken::new(),
+            task: None,
+            outcome: Arc::default(),
+        }
+    }
+
+    /// Starts only once `dependencies` are running
+    pub fn after<S: AsRef<str>>(mut self, dependencies: &[S]) -> Self {
+        self.dependencies
+            .extend(dependencies.iter().map(|name| name.as_ref().to_string()));
+        self
+    }
+}
+
+impl<F, Fut> Component for TaskComponent<F>
+where
+    F: Fn(CancellationToken) -> Fut + Send + 'static,
+    Fut: Future<Output = Result<(), BoxError>> + Send + 'static,
+{
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn dependencies(&self) -> &[String] {
+        &self.dependencies
+    }
+
+    fn start(&mut self) -> BoxFuture<'_, Result<(), BoxError>> {
+        self.token = CancellationToken::new();
+        self.outcome = Arc::default();
+        let outcome = Arc::clone(&self.outcome);
+        let run = (self.run)(self.token.clone());
+        self.task = Some(tokio::spawn(async move {
+            let result = run.await.map_err(|e| error_chain(&*e));
+            *outcome.lock().unwrap() = Some(result);
+        }));
+        Box::pin(async { Ok(()) })
+    }
+
+    fn stop(&mut self, grace: Duration) -> BoxFuture<'_, bool> {
+        self.token.cancel();
+        let task = self.task.take();
+        Box::pin(async move {
+            let Some(mut task) = task else {
+                return true;
+            };
+            if tokio::time::timeout(grace, &mut task).await.is_ok() {
+                return true;
+            }
+            task.abort();
+            let _ = task.await;
+            false
+        })
+    }
+
+    fn health(&self) -> Health {
+        match &self.task {
+            Some(task) if !task.is_finished() => return Health::Healthy,
+            None => return Health::Finished,
+            Some(_) => {}
+        }
+        match self.outcome.lock().unwrap().clone() {
+            Some(Ok(())) => Health::Finished,
+            Some(Err(e)) => Health::Failed(e),
+            None => Health::Failed("panicked".to_string()),
+        }
+    }
+}
+
+/// `error` followed by its causes, as anyhow prints a chain of contexts
+fn error_chain(error: &(dyn std::error::Error + 'static)) -> String {
+    let mut message = error.to_string();
+    let mut source = error.source();
+    while let Some(cause) = source {
+        message.push_str(": ");
+        message.push_str(&cause.to_string());
+        source = cause.source();
+    }
+    message
+}
+
+impl<F> Drop for TaskComponent<F> {
+    fn drop(&mut self) {
+        self.token.cancel();
+        if let Some(task) = &self.task {
+            task.abort();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    /// Records its starts and stops in a shared log
+    struct Probe {
+        name: String,
+        dependencies: Vec<String>,
+        log: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl Probe {
+        fn new(name: &str, dependencies: &[&str], log: &Arc<Mutex<Vec<String>>>) -> Self {
+            Self {
+                name: name.to_string(),
+                dependencies: dependencies.iter().map(ToString::to_string).collect(),
+                log: Arc::clone(log),
+            }
+        }
+    }
+
+    impl Component for Probe {
+        fn name(&self) -> &str {
+            &self.name
+        }
+
+        fn dependencies(&self) -> &[String] {
+            &self.dependencies
+        }
+
+        fn start(&mut self) -> BoxFuture<'_, Result<(), BoxError>> {
+            self.log
+                .lock()
+                .unwrap()
+                .push(format!("start {}", self.name));
+            Box::pin(async { Ok(()) })
+        }
+
+        fn stop(&mut self, _grace: Duration) -> BoxFuture<'_, bool> {
+            self.log.lock().unwrap().push(format!("stop {}", self.name));
+            Box::pin(async { true })
+        }
+
+        fn health(&self) -> Health {
+            Health::Healthy
+        }
+    }
+
+    fn fast(name: &str) -> Supervisor {
+        let mut supervisor = Supervisor::new(name);
+        supervisor.check_interval = Duration::from_millis(10);
+        supervisor.restart_min = Duration::from_millis(10);
+        supervisor
+    }
+
+    /// Waits for the states to satisfy `done`
+    async fn wait_for(states: &ComponentStates, done: impl Fn(&[ComponentStatus]) -> bool) {
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while !done(&states.snapshot()) {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+        })
+        .await
+        .expect("components never reached the expected states");
+    }
+
+    #[tokio::test]
+    async fn test_dependency_order() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut supervisor = Supervisor::new("test");
+        supervisor.add(Probe::new("recorder", &["camera"], &log));
+        supervisor.add(Probe::new("web", &[], &log));
+        supervisor.add(Probe::new("camera", &["web", "sensors"], &log));
+        supervisor.add(Probe::new("sensors", &[], &log));
+        supervisor.start().await.unwrap();
+
+        let states = supervisor.states().snapshot();
+        let names: Vec<&str> = states.iter().map(|status| status.name.as_str()).collect();
+        assert_eq!(names, ["web", "sensors", "camera", "recorder"]);
+        assert!(states
+            .iter()
+            .all(|status| status.state == ComponentState::Running));
+
+        assert_eq!(supervisor.shutdown(Duration::from_secs(1)).await, 0);
+        assert_eq!(
+            *log.lock().unwrap(),
+            [
+                "start web",
+                "start sensors",
+                "start camera",
+                "start recorder",
+                "stop recorder",
+                "stop camera",
+                "stop sensors",
+                "stop web",
+            ]
+        );
+        let states = supervisor.states().snapshot();
+        assert!(states
+            .iter()
+            .all(|status| status.state == ComponentState::Stopped));
+    }
+
+    #[tokio::test]
+    async fn test_bad_dependencies() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut supervisor = Supervisor::new("test");
+        supervisor.add(Probe::new("camera", &["web"], &log));
+        let error = supervisor.start().await.unwrap_err();
+        assert!(error.to_string().contains("unknown component web"));
+        assert_eq!(error.code(), ErrorCode::InvalidConfig);
+
+        let mut supervisor = Supervisor::new("test");
+        supervisor.add(Probe::new("a", &["b"], &log));
+        supervisor.add(Probe::new("b", &["a"], &log));
+        supervisor.add(Probe::new("c", &[], &log));
+        let error = supervisor.start().await.unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("circular dependencies between a, b"));
+        assert!(log.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_restarts_failed_component_alone() {
+        let mut supervisor = fast("test");
+        let flaky_starts = Arc::new(AtomicU32::new(0));
+        let steady_starts = Arc::new(AtomicU32::new(0));
+
+        let starts = Arc::clone(&steady_starts);
+        supervisor.add(TaskComponent::new("steady", move |token| {
+            starts.fetch_add(1, Ordering::SeqCst);
+            async move {
+                token.cancelled().await;
+                Ok(())
+            }
+        }));
+        let starts = Arc::clone(&flaky_starts);
+        supervisor.add(
+            TaskComponent::new("flaky", move |token| {
+                let attempt = starts.fetch_add(1, Ordering::SeqCst);
+                async move {
+                    if attempt == 0 {
+                        let cause = anyhow::anyhow!("device unplugged");
+                        return Err(cause.context("camera lost").into());
+                    }
+                    token.cancelled().await;
+                    Ok(())
+                }
+            })
+            .after(&["steady"]),
+        );
+        supervisor.start().await.unwrap();
+
+        let states = supervisor.states();
+        wait_for(&states, |states| {
+            states[1].restarts == 1 && states[1].state == ComponentState::Running
+        })
+        .await;
+        let flaky = &states.snapshot()[1];
+        assert_eq!(
+            flaky.last_error.as_deref(),
+            Some("camera lost: device unplugged")
+        );
+        assert_eq!(flaky_starts.load(Ordering::SeqCst), 2);
+        assert_eq!(steady_starts.load(Ordering::SeqCst), 1);
+        assert_eq!(states.snapshot()[0].restarts, 0);
+
+        assert_eq!(supervisor.shutdown(Duration::from_secs(1)).await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_finished_and_stuck_tasks() {
+        let mut supervisor = fast("test");
+        let starts = Arc::new(AtomicU32::new(0));
+        let counted = Arc::clone(&starts);
+        supervisor.add(TaskComponent::new("oneshot", move |_token| {
+            counted.fetch_add(1, Ordering::SeqCst);
+            async { Ok(()) }
+        }));
+        supervisor.add(TaskComponent::new("stuck", |_token| std::future::pending()));
+        supervisor.start().await.unwrap();
+
+        // A task that returns Ok is done, not restarted
+        let states = supervisor.states();
+        wait_for(&states, |states| states[0].state == ComponentState::Stopped).await;
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(starts.load(Ordering::SeqCst), 1);
+        assert_eq!(states.snapshot()[0].restarts, 0);
+
+        // One that ignores its token is aborted at shutdown
+        assert_eq!(supervisor.shutdown(Duration::from_millis(20)).await, 1);
+    }
+}
@@ -0,0 +1,225 @@
+//! Frames from a camera another part of the process already captures
+//!
+//! A V4L2 or libcamera device can only be opened once, so a second
+//! `libcamerasrc` on it simply fails. An application embedding the runtime
+//! (see [`crate::app`]) that streams a camera itself registers a
+//! [`FrameTap`] for the device in [`DeviceTaps`]; a camera on that device
+//! then takes JPEG frames from the tap through a [`TapSource`] instead of
+//! opening it. The tap delivers the application's processed video, so the
+//! camera's own flip and pipeline overrides don't apply.
+
+use crate::capture::CaptureStats;
+use crate::error::ErrorCode;
+use crate::frame::Frame;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use thiserror::Error;
+use tokio::sync::{mpsc, watch};
+use tokio::task::JoinHandle;
+
+/// Frames queued between the tap and the source, as for a capture
+const TAP_QUEUE: usize = 5;
+
+#[derive(Error, Debug)]
+pub enum TapError {
+    /// The application's capture couldn't start delivering frames
+    #[error("tap failed to start: {0}")]
+    Start(String),
+
+    #[error("tap already running")]
+    AlreadyRunning,
+}
+
+impl TapError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            TapError::Start(_) => ErrorCode::Device,
+            TapError::AlreadyRunning => ErrorCode::AlreadyRunning,
+        }
+    }
+}
+
+/// What a tap is asked to deliver
+pub struct TapRequest {
+    pub width: u32,
+    pub height: u32,
+    pub fps: u32,
+
+    /// JPEG quality; congestion control changes it while the tap runs
+    pub quality: watch::Receiver<u32>,
+
+    /// Where encoded frames go, with increasing ids (gaps for drops). The
+    /// tap stops once it is closed.
+    pub frames: mpsc::Sender<Frame>,
+}
+
+/// A running capture that can hand out JPEG frames
+pub trait FrameTap: Send + Sync {
+    /// Starts delivering frames as `request` asks, in the background
+    fn start(&self, request: TapRequest) -> Result<(), TapError>;
+}
+
+/// Taps by device, shared between the application and the runtime
+#[derive(Clone, Default)]
+pub struct DeviceTaps {
+    taps: Arc<Mutex<HashMap<String, Arc<dyn FrameTap>>>>,
+}
+
+impl DeviceTaps {
+    /// Frames for cameras on `device` come from `tap` from now on
+    pub fn insert(&self, device: impl Into<String>, tap: Arc<dyn FrameTap>) {
+        self.taps.lock().unwrap().insert(device.into(), tap);
+    }
+
+    pub fn get(&self, device: &str) -> Option<Arc<dyn FrameTap>> {
+        self.taps.lock().unwrap().get(device).cloned()
+    }
+}
+
+/// Frame source reading from a [`FrameTap`]
+///
+/// Stands in for [`crate::Capture`] with the capture's size, rate and
+/// quality. Frames the consumer has no room for are dropped and counted.
+pub struct TapSource {
+    tap: Arc<dyn FrameTap>,
+    width: u32,
+    height: u32,
+    fps: u32,
+    quality: watch::Sender<u32>,
+    forwarder: Option<JoinHandle<()>>,
+    running: Arc<AtomicBool>,
+    frames_captured: Arc<AtomicU64>,
+    dropped_in_channel: Arc<AtomicU64>,
+}
+
+impl TapSource {
+    pub fn new(tap: Arc<dyn FrameTap>, config: &crate::CaptureConfig) -> Self {
+        Self {
+            tap,
+            width: config.width,
+            height: config.height,
+            fps: config.fps,
+            quality: watch::channel(config.quality).0,
+            forwarder: None,
+            running: Arc::new(AtomicBool::new(false)),
+            frames_captured: Arc::new(AtomicU64::new(0)),
+            dropped_in_channel: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    pub async fn start(&mut self) -> Result<mpsc::Receiver<Frame>, TapError> {
+        if self.running.swap(true, Ordering::Relaxed) {
+            return Err(TapError::AlreadyRunning);
+        }
+
+        let (tap_tx, mut tap_rx) = mpsc::channel(TAP_QUEUE);
+        let request = TapRequest {
+            width: self.width,
+            height: self.height,
+            fps: self.fps,
+            quality: self.quality.subscribe(),
+            frames: tap_tx,
+        };
+        if let Err(e) = self.tap.start(request) {
+            self.running.store(false, Ordering::Relaxed);
+            return Err(e);
+        }
+
+        let (frame_tx, frame_rx) = mpsc::channel(TAP_QUEUE);
+        let running = Arc::clone(&self.running);
+        let frames_captured = Arc::clone(&self.frames_captured);
+        let dropped_in_channel = Arc::clone(&self.dropped_in_channel);
+        self.forwarder = Some(tokio::spawn(async move {
+            while let Some(frame) = tap_rx.recv().await {
+                frames_captured.fetch_add(1, Ordering::Relaxed);
+                match frame_tx.try_send(frame) {
+                    Ok(()) => {}
+                    Err(mpsc::error::TrySendError::Full(_)) => {
+                        dropped_in_channel.fetch_add(1, Ordering::Relaxed);
+                    }
+                    Err(mpsc::error::TrySendError::Closed(_)) => break,
+                }
+            }
+            running.store(false, Ordering::Relaxed);
+        }));
+
+        Ok(frame_rx)
+    }
+
+    /// Stops forwarding; dropping the tap's channel stops the tap
+    pub async fn stop(&mut self) -> Result<(), TapError> {
+        if let Some(forwarder) = self.forwarder.take() {
+            forwarder.abort();
+            let _ = forwarder.await;
+        }
+        self.running.store(false, Ordering::Relaxed);
+        Ok(())
+    }
+
+    pub fn set_quality(&mut self, quality: u32) {
+        self.quality.send_replace(quality);
+    }
+
+    pub fn get_stats(&self) -> CaptureStats {
+        let dropped = self.dropped_in_channel.load(Ordering::Relaxed);
+        CaptureStats {
+            frames_captured: self.frames_captured.load(Ordering::Relaxed),
+            frames_dropped: dropped,
+            dropped_in_channel: dropped,
+            is_running: self.running.load(Ordering::Relaxed),
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+
+    /// Sends three frames at whatever quality is asked for
+    struct TestTap;
+
+    impl FrameTap for TestTap {
+        fn start(&self, request: TapRequest) -> Result<(), TapError> {
+            let quality = *request.quality.borrow() as u8;
+            tokio::spawn(async move {
+                for id in 0..3 {
+                    let frame = Frame::new(id, Bytes::from(vec![quality]));
+                    if request.frames.send(frame).await.is_err() {
+                        break;
+                    }
+                }
+            });
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_frames_from_tap() {
+        let taps = DeviceTaps::default();
+        taps.insert("/dev/video0", Arc::new(TestTap));
+        assert!(taps.get("/dev/video1").is_none());
+
+        let config = crate::CaptureConfig {
+            quality: 70,
+            ..Default::default()
+        };
+        let mut source = TapSource::new(taps.get("/dev/video0").unwrap(), &config);
+        let mut frames = source.start().await.unwrap();
+        let again = source.start().await.unwrap_err();
+        assert_eq!(again.code(), ErrorCode::AlreadyRunning);
+        for id in 0..3 {
+            let frame = frames.recv().await.unwrap();
+            assert_eq!((frame.id, frame.data[0]), (id, 70));
+        }
+
+        // The tap ended, so the source did too
+        assert!(frames.recv().await.is_none());
+        let stats = source.get_stats();
+        assert_eq!(stats.frames_captured, 3);
+        assert!(!stats.is_running);
+        source.stop().await.unwrap();
+    }
+}
@@ -0,0 +1,159 @@
+//! Managed background tasks with cooperative shutdown
+//!
+//! Tasks spawned into a [`TaskGroup`] receive a [`CancellationToken`] and are
+//! tracked in a [`JoinSet`]. Shutting the group down cancels the token, waits
+//! for the tasks to wind down, and aborts any that don't. Dropping the group
+//! cancels and aborts everything, so nothing outlives its owner.
+
+use std::future::Future;
+use std::time::Duration;
+use tokio::task::{AbortHandle, JoinError, JoinSet};
+use tracing::{debug, warn};
+
+pub use tokio_util::sync::CancellationToken;
+
+/// Set of background tasks sharing one shutdown token
+pub struct TaskGroup {
+    name: String,
+    token: CancellationToken,
+    tasks: JoinSet<()>,
+}
+
+impl TaskGroup {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self::with_token(name, CancellationToken::new())
+    }
+
+    /// Creates a group that is also cancelled when `parent` is
+    pub fn with_parent(name: impl Into<String>, parent: &CancellationToken) -> Self {
+        Self::with_token(name, parent.child_token())
+    }
+
+    fn with_token(name: impl Into<String>, token: CancellationToken) -> Self {
+        Self {
+            name: name.into(),
+            token,
+            tasks: JoinSet::new(),
+        }
+    }
+
+    /// Token cancelled when the group shuts down, for work running outside
+    /// the group (e.g. on a dedicated thread)
+    pub fn token(&self) -> CancellationToken {
+        self.token.clone()
+    }
+
+    /// Spawns a task on the current runtime. The closure receives the token
+    /// the task must watch to exit at shutdown.
+    pub fn spawn<F, Fut>(&mut self, task: F) -> AbortHandle
+    where
+        F: FnOnce(CancellationToken) -> Fut,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.tasks.spawn(task(self.token.clone()))
+    }
+
+    /// Number of tasks that have not been joined yet
+    pub fn len(&self) -> usize {
+        self.tasks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tasks.is_empty()
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.token.is_cancelled()
+    }
+
+    /// Waits for the next task to finish (`None` once the group is empty)
+    pub async fn join_next(&mut self) -> Option<Result<(), JoinError>> {
+        self.tasks.join_next().await
+    }
+
+    /// Signals every task to stop without waiting
+    pub fn cancel(&self) {
+        self.token.cancel();
+    }
+
+    /// Cancels every task and waits up to `grace` for them to return, then
+    /// aborts the rest. Returns how many had to be aborted.
+    pub async fn shutdown(&mut self, grace: Duration) -> usize {
+        self.token.cancel();
+
+        let drained = tokio::time::timeout(grace, async {
+            while let Some(result) = self.tasks.join_next().await {
+                if let Err(e) = result {
+                    if e.is_panic() {
+                        warn!(group = %self.name, error = %e, "Task panicked");
+                    }
+                }
+            }
+        })
+        .await;
+
+        let aborted = self.tasks.len();
+        if drained.is_err() {
+            warn!(group = %self.name, aborted, ?grace, "Tasks ignored shutdown, aborting");
+            self.tasks.shutdown().await;
+        }
+        debug!(group = %self.name, "Task group shut down");
+        aborted
+    }
+}
+
+impl Drop for TaskGroup {
+    fn drop(&mut self) {
+        // The JoinSet aborts whatever is left when it drops; cancelling first
+        // also reaches work that only holds the token
+        self.token.cancel();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn test_shutdown_cancels_cooperative_tasks() {
+        let mut group = TaskGroup::new("test");
+        let finished = Arc::new(AtomicBool::new(false));
+
+        let flag = Arc::clone(&finished);
+        group.spawn(|token| async move {
+            token.cancelled().await;
+            flag.store(true, Ordering::SeqCst);
+        });
+
+        assert_eq!(group.shutdown(Duration::from_secs(1)).await, 0);
+        assert!(finished.load(Ordering::SeqCst));
+        assert!(group.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_aborts_stuck_tasks() {
+        let mut group = TaskGroup::new("test");
+        group.spawn(|_token| std::future::pending());
+
+        assert_eq!(group.shutdown(Duration::from_millis(20)).await, 1);
+        assert!(group.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_drop_cancels_token() {
+        let group = TaskGroup::new("test");
+        let token = group.token();
+        drop(group);
+        assert!(token.is_cancelled());
+    }
+
+    #[tokio::test]
+    async fn test_parent_cancels_child_group() {
+        let parent = CancellationToken::new();
+        let group = TaskGroup::with_parent("child", &parent);
+        parent.cancel();
+        assert!(group.is_cancelled());
+    }
+}
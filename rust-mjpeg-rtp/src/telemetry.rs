@@ -0,0 +1,178 @@
+//! OpenTelemetry (OTLP) export of traces and streamer metrics
+//!
+//! Enabled with the `otel` cargo feature and the `[telemetry]` config section.
+//! Per-frame `tracing` spans are exported as OTLP traces, and streamer counters
+//! are exported as observable OTLP metrics labelled by camera, codec and
+//! resolution (see `metrics`).
+
+use crate::config::TelemetryConfig;
+use crate::error::ErrorCode;
+use crate::metrics::{self, MetricLabels};
+use crate::streamer::StreamerStatsHandle;
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry::{global, KeyValue};
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::metrics::{PeriodicReader, SdkMeterProvider};
+use opentelemetry_sdk::trace::TracerProvider;
+use opentelemetry_sdk::{runtime, Resource};
+use std::time::Duration;
+use thiserror::Error;
+use tracing::Subscriber;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+const INSTRUMENTATION_NAME: &str = "rust-mjpeg-rtp";
+
+#[derive(Error, Debug)]
+pub enum TelemetryError {
+    #[error("failed to build OTLP exporter: {0}")]
+    Exporter(String),
+}
+
+impl TelemetryError {
+    pub fn code(&self) -> ErrorCode {
+        ErrorCode::InvalidConfig
+    }
+}
+
+/// Installed OTLP trace and metric providers
+pub struct Telemetry {
+    tracer_provider: TracerProvider,
+    meter_provider: SdkMeterProvider,
+}
+
+impl Telemetry {
+    /// Builds OTLP exporters and installs them as the global providers
+    ///
+    /// Must be called from within a Tokio runtime.
+    pub fn init(config: &TelemetryConfig) -> Result<Self, TelemetryError> {
+        let resource = build_resource(config);
+
+        let span_exporter = opentelemetry_otlp::SpanExporter::builder()
+            .with_tonic()
+            .with_endpoint(config.otlp_endpoint.clone())
+            .build()
+            .map_err(|e| TelemetryError::Exporter(e.to_string()))?;
+
+        let tracer_provider = TracerProvider::builder()
+            .with_batch_exporter(span_exporter, runtime::Tokio)
+            .with_resource(resource.clone())
+            .build();
+        global::set_tracer_provider(tracer_provider.clone());
+
+        let metric_exporter = opentelemetry_otlp::MetricExporter::builder()
+            .with_tonic()
+            .with_endpoint(config.otlp_endpoint.clone())
+            .build()
+            .map_err(|e| TelemetryError::Exporter(e.to_string()))?;
+
+        let reader = PeriodicReader::builder(metric_exporter, runtime::Tokio)
+            .with_interval(Duration::from_secs(config.metrics_interval_seconds))
+            .build();
+
+        let meter_provider = SdkMeterProvider::builder()
+            .with_reader(reader)
+            .with_resource(resource)
+            .build();
+        global::set_meter_provider(meter_provider.clone());
+        register_build_info();
+
+        Ok(Self {
+            tracer_provider,
+            meter_provider,
+        })
+    }
+
+    /// Returns a `tracing` layer forwarding spans to the OTLP tracer
+    pub fn tracing_layer<S>(&self) -> impl Layer<S>
+    where
+        S: Subscriber + for<'span> LookupSpan<'span>,
+    {
+        let tracer = self.tracer_provider.tracer(INSTRUMENTATION_NAME);
+        tracing_opentelemetry::layer().with_tracer(tracer)
+    }
+
+    /// Flushes pending spans and metrics
+    pub fn shutdown(self) {
+        if let Err(e) = self.tracer_provider.shutdown() {
+            tracing::warn!(error = %e, "Failed to shut down OTLP tracer provider");
+        }
+        if let Err(e) = self.meter_provider.shutdown() {
+            tracing::warn!(error = %e, "Failed to shut down OTLP meter provider");
+        }
+    }
+}
+
+fn build_resource(config: &TelemetryConfig) -> Resource {
+    let mut attributes = vec![
+        KeyValue::new("service.name", config.service_name.clone()),
+        KeyValue::new("service.version", env!("CARGO_PKG_VERSION")),
+    ];
+    if let Some(ref instance_id) = config.instance_id {
+        attributes.push(KeyValue::new("service.instance.id", instance_id.clone()));
+    }
+    Resource::new(attributes)
+}
+
+/// Registers observable metrics for one camera's streamer on the global meter
+///
+/// Harmless when telemetry is disabled: the global meter is a no-op then.
+pub fn register_streamer_metrics(labels: &MetricLabels, stats: StreamerStatsHandle) {
+    let meter = global::meter(INSTRUMENTATION_NAME);
+    let attributes: Vec<KeyValue> = labels
+        .pairs()
+        .into_iter()
+        .map(|(key, value)| KeyValue::new(key, value.to_string()))
+        .collect();
+
+    for (name, description, field) in metrics::COUNTERS {
+        let stats = stats.clone();
+        let attributes = attributes.clone();
+        meter
+            .u64_observable_counter(name)
+            .with_description(description)
+            .with_callback(move |observer| observer.observe(field(&stats.get()), &attributes))
+            .build();
+    }
+
+    // Destinations come and go, so each callback reports the current ones
+    for (name, description, field) in metrics::SESSION_COUNTERS {
+        let stats = stats.clone();
+        let attributes = attributes.clone();
+        meter
+            .u64_observable_counter(name)
+            .with_description(description)
+            .with_callback(move |observer| {
+                for destination in stats.get().destinations {
+                    let mut attributes = attributes.clone();
+                    attributes.push(KeyValue::new(
+                        "session_id",
+                        metrics::session_id(destination.addr),
+                    ));
+                    observer.observe(field(&destination), &attributes);
+                }
+            })
+            .build();
+    }
+
+    meter
+        .u64_observable_gauge(metrics::HEALTH_SCORE.0)
+        .with_description(metrics::HEALTH_SCORE.1)
+        .with_callback(move |observer| {
+            observer.observe(stats.get().health.score as u64, &attributes)
+        })
+        .build();
+}
+
+/// Reports the build's version and features as a constant gauge
+fn register_build_info() {
+    let attributes: Vec<KeyValue> = metrics::build_info()
+        .into_iter()
+        .map(|(key, value)| KeyValue::new(key, value))
+        .collect();
+    global::meter(INSTRUMENTATION_NAME)
+        .u64_observable_gauge(format!("{}_info", metrics::BUILD_INFO.0))
+        .with_description(metrics::BUILD_INFO.1)
+        .with_callback(move |observer| observer.observe(1, &attributes))
+        .build();
+}
@@ -0,0 +1,469 @@
+//! Machine-readable timecode painted into frames
+//!
+//! For latency measurement, capture can paint the current time into the top
+//! left corner of each raw frame before it is JPEG encoded: 40 bars, 8 pixels
+//! wide and 16 tall, black for 0 and white for 1, carrying a 32-bit
+//! microsecond clock followed by an 8-bit checksum. Each bar covers whole
+//! luma blocks, so it survives compression as the sign of the blocks' DC
+//! coefficient.
+//!
+//! Reading it back only needs the entropy-coded DC coefficients of the first
+//! MCU row. RFC 2435 payloads always use the standard Huffman tables of
+//! ITU-T T.81 Annex K, so the scan of a reassembled frame is decoded without
+//! rebuilding a JPEG or running a full decoder.
+
+use crate::receiver::ReassembledFrame;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Bars: 32 bits of time, 8 of checksum
+pub const BITS: usize = 40;
+
+/// Width of one bar, one luma block
+pub const BAR_WIDTH: usize = 8;
+
+/// Height of the bars, one 4:2:0 MCU row
+pub const BAR_HEIGHT: usize = 16;
+
+/// Narrowest frame the timecode fits in
+pub const MIN_WIDTH: usize = BITS * BAR_WIDTH;
+
+/// Luma of a 0 and a 1 bar (video range black and white)
+const BLACK: u8 = 16;
+const WHITE: u8 = 235;
+
+/// Current time in microseconds, wrapping every ~71 minutes. Differences
+/// taken with `wrapping_sub` stay correct across the wrap.
+pub fn now() -> u32 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_micros() as u32)
+        .unwrap_or_default()
+}
+
+fn checksum(value: u32) -> u8 {
+    crc32fast::hash(&value.to_be_bytes()) as u8
+}
+
+/// The bars for `value`, most significant bit first
+fn encode(value: u32) -> u64 {
+    ((value as u64) << 8) | checksum(value) as u64
+}
+
+fn decode(bits: u64) -> Option<u32> {
+    let value = (bits >> 8) as u32;
+    (checksum(value) == bits as u8).then_some(value)
+}
+
+/// Paints `value` into an 8-bit luma plane of `width` x `height` with rows
+/// `stride` bytes apart. Returns false, leaving the plane alone, when the
+/// frame is too small.
+pub fn paint(luma: &mut [u8], stride: usize, width: usize, height: usize, value: u32) -> bool {
+    let fits = width >= MIN_WIDTH && stride >= width && height >= BAR_HEIGHT;
+    if !fits || luma.len() < stride * (BAR_HEIGHT - 1) + MIN_WIDTH {
+        return false;
+    }
+    let bits = encode(value);
+    for row in luma.chunks_mut(stride).take(BAR_HEIGHT) {
+        for (bit, bar) in row[..MIN_WIDTH].chunks_mut(BAR_WIDTH).enumerate() {
+            let one = bits >> (BITS - 1 - bit) & 1 == 1;
+            bar.fill(if one { WHITE } else { BLACK });
+        }
+    }
+    true
+}
+
+/// Reads the timecode painted into a reassembled frame, `None` when there
+/// is none or the scan can't be decoded
+pub fn read(frame: &ReassembledFrame) -> Option<u32> {
+    // Luma blocks per MCU; types 64+ have restart markers, not supported
+    let luma_blocks: usize = match frame.jpeg_type {
+        0 => 4,
+        1 => 2,
+        _ => return None,
+    };
+    if (frame.width as usize) < MIN_WIDTH {
+        return None;
+    }
+
+    let tables = Tables::standard();
+    let mut scan = BitReader::new(&frame.scan_data);
+    let mut predictors = [0i32; 3];
+    let mut bits = 0u64;
+    // Each MCU carries two bars in its first two luma blocks
+    for _ in 0..BITS / 2 {
+        for block in 0..luma_blocks + 2 {
+            // Luma blocks, then Cb and Cr
+            let component = block.saturating_sub(luma_blocks - 1);
+            let (dc, ac) = if component == 0 {
+                (&tables.dc_luma, &tables.ac_luma)
+            } else {
+                (&tables.dc_chroma, &tables.ac_chroma)
+            };
+            predictors[component] += scan.dc_difference(dc)?;
+            scan.skip_ac(ac)?;
+            if block < 2 {
+                bits = bits << 1 | (predictors[0] > 0) as u64;
+            }
+        }
+    }
+    decode(bits)
+}
+
+/// Canonical Huffman table (T.81 F.2.2.3)
+struct HuffmanTable {
+    /// Per code length 1-16: the smallest code, the largest (-1 when there
+    /// are none) and the index of the first value of that length
+    min_code: [i32; 16],
+    max_code: [i32; 16],
+    first_value: [usize; 16],
+    values: Vec<u8>,
+}
+
+impl HuffmanTable {
+    fn new(counts: &[u8; 16], values: Vec<u8>) -> Self {
+        let mut table = Self {
+            min_code: [0; 16],
+            max_code: [-1; 16],
+            first_value: [0; 16],
+            values,
+        };
+        let (mut code, mut index) = (0i32, 0usize);
+        for (len, &count) in counts.iter().enumerate() {
+            if count > 0 {
+                table.first_value[len] = index;
+                table.min_code[len] = code;
+                code += count as i32;
+                index += count as usize;
+                table.max_code[len] = code - 1;
+            }
+            code <<= 1;
+        }
+        table
+    }
+
+    #[cfg(test)]
+    fn code(&self, value: u8) -> Option<(u32, u32)> {
+        let index = self.values.iter().position(|&v| v == value)?;
+        (0..16).find_map(|len| {
+            let first = self.first_value[len];
+            let in_length = self.max_code[len] >= 0
+                && index >= first
+                && index - first <= (self.max_code[len] - self.min_code[len]) as usize;
+            in_length.then(|| {
+                (
+                    (self.min_code[len] as usize + index - first) as u32,
+                    len as u32 + 1,
+                )
+            })
+        })
+    }
+}
+
+/// AC symbols (run << 4 | size) of T.81 Annex K, given the irregular head of
+/// the list; the rest follow in numeric order
+fn ac_values(head: &[u8]) -> Vec<u8> {
+    let mut values = head.to_vec();
+    for run in 0..16u8 {
+        for size in 1..=10u8 {
+            let symbol = run << 4 | size;
+            if !head.contains(&symbol) {
+                values.push(symbol);
+            }
+        }
+    }
+    values
+}
+
+struct Tables {
+    dc_luma: HuffmanTable,
+    dc_chroma: HuffmanTable,
+    ac_luma: HuffmanTable,
+    ac_chroma: HuffmanTable,
+}
+
+impl Tables {
+    /// T.81 Tables K.3 - K.6
+    fn standard() -> Self {
+        #[rustfmt::skip]
+        const AC_LUMA_HEAD: [u8; 37] = [
+            0x01, 0x02, 0x03, 0x00, 0x04, 0x11, 0x05, 0x12, 0x21, 0x31, 0x41, 0x06, 0x13,
+            0x51, 0x61, 0x07, 0x22, 0x71, 0x14, 0x32, 0x81, 0x91, 0xa1, 0x08, 0x23, 0x42,
+            0xb1, 0xc1, 0x15, 0x52, 0xd1, 0xf0, 0x24, 0x33, 0x62, 0x72, 0x82,
+        ];
+        #[rustfmt::skip]
+        const AC_CHROMA_HEAD: [u8; 43] = [
+            0x00, 0x01, 0x02, 0x03, 0x11, 0x04, 0x05, 0x21, 0x31, 0x06, 0x12, 0x41, 0x51,
+            0x07, 0x61, 0x71, 0x13, 0x22, 0x32, 0x81, 0x08, 0x14, 0x42, 0x91, 0xa1, 0xb1,
+            0xc1, 0x09, 0x23, 0x33, 0x52, 0xf0, 0x15, 0x62, 0x72, 0xd1, 0x0a, 0x16, 0x24,
+            0x34, 0xe1, 0x25, 0xf1,
+        ];
+        Self {
+            dc_luma: HuffmanTable::new(
+                &[0, 1, 5, 1, 1, 1, 1, 1, 1, 0, 0, 0, 0, 0, 0, 0],
+                (0..12).collect(),
+            ),
+            dc_chroma: HuffmanTable::new(
+                &[0, 3, 1, 1, 1, 1, 1, 1, 1, 1, 1, 0, 0, 0, 0, 0],
+                (0..12).collect(),
+            ),
+            ac_luma: HuffmanTable::new(
+                &[0, 2, 1, 3, 3, 2, 4, 3, 5, 5, 4, 4, 0, 0, 1, 0x7d],
+                ac_values(&AC_LUMA_HEAD),
+            ),
+            ac_chroma: HuffmanTable::new(
+                &[0, 2, 1, 2, 4, 4, 3, 4, 7, 5, 4, 4, 0, 1, 2, 0x77],
+                ac_values(&AC_CHROMA_HEAD),
+            ),
+        }
+    }
+}
+
+/// Reads entropy-coded data, dropping the 0x00 stuffed after 0xFF
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    byte: u8,
+    left: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self {
+            data,
+            pos: 0,
+            byte: 0,
+            left: 0,
+        }
+    }
+
+    /// `None` at the end of the data or at a marker
+    fn bit(&mut self) -> Option<i32> {
+        if self.left == 0 {
+            self.byte = *self.data.get(self.pos)?;
+            self.pos += 1;
+            if self.byte == 0xFF {
+                if *self.data.get(self.pos)? != 0x00 {
+                    return None;
+                }
+                self.pos += 1;
+            }
+            self.left = 8;
+        }
+        self.left -= 1;
+        Some((self.byte >> self.left & 1) as i32)
+    }
+
+    fn bits(&mut self, count: u8) -> Option<i32> {
+        (0..count).try_fold(0, |acc, _| Some(acc << 1 | self.bit()?))
+    }
+
+    fn symbol(&mut self, table: &HuffmanTable) -> Option<u8> {
+        let mut code = 0;
+        for len in 0..16 {
+            code = code << 1 | self.bit()?;
+            if code <= table.max_code[len] {
+                let index = table.first_value[len] + (code - table.min_code[len]) as usize;
+                return table.values.get(index).copied();
+            }
+        }
+        None
+    }
+
+    fn dc_difference(&mut self, table: &HuffmanTable) -> Option<i32> {
+        let size = self.symbol(table)?;
+        if size > 11 {
+            return None;
+        }
+        let value = self.bits(size)?;
+        // T.81 F.2.2.1 EXTEND: a leading 0 bit means negative
+        Some(if size > 0 && value < 1 << (size - 1) {
+            value - (1 << size) + 1
+        } else {
+            value
+        })
+    }
+
+    fn skip_ac(&mut self, table: &HuffmanTable) -> Option<()> {
+        let mut k = 1;
+        while k < 64 {
+            let symbol = self.symbol(table)?;
+            let (run, size) = (symbol >> 4, symbol & 0x0F);
+            if size == 0 {
+                if run != 15 {
+                    return Some(()); // end of block
+                }
+                k += 16;
+            } else {
+                self.bits(size)?;
+                k += run as usize + 1;
+            }
+        }
+        Some(())
+    }
+}
+
+/// Entropy-codes a 4:2:0 scan of flat blocks for tests: luma blocks take the
+/// bar they fall in, chroma is neutral. A 1 AC coefficient follows every
+/// luma DC so that AC decoding is exercised too.
+#[cfg(test)]
+pub(crate) fn flat_scan(width: usize, height: usize, value: u32) -> Vec<u8> {
+    let tables = Tables::standard();
+    let mut writer = BitWriter::default();
+    let bits = encode(value);
+    let mut previous = 0i32;
+    for mcu_y in 0..height.div_ceil(16) {
+        for mcu_x in 0..width.div_ceil(16) {
+            for block in 0..6 {
+                if block >= 4 {
+                    writer.dc(&tables.dc_chroma, 0);
+                    writer.eob(&tables.ac_chroma);
+                    continue;
+                }
+                let x = mcu_x * 2 + block % 2;
+                let in_bar = mcu_y == 0 && x < BITS;
+                let dc = match in_bar {
+                    true if bits >> (BITS - 1 - x) & 1 == 1 => 40,
+                    true => -40,
+                    false => 3,
+                };
+                writer.dc(&tables.dc_luma, dc - previous);
+                previous = dc;
+                // Coefficient 3 (run of 1), value -1
+                let (code, len) = tables.ac_luma.code(0x11).unwrap();
+                writer.put(code, len);
+                writer.put(0, 1);
+                writer.eob(&tables.ac_luma);
+            }
+        }
+    }
+    writer.finish()
+}
+
+#[cfg(test)]
+#[derive(Default)]
+struct BitWriter {
+    out: Vec<u8>,
+    acc: u32,
+    count: u32,
+}
+
+#[cfg(test)]
+impl BitWriter {
+    fn put(&mut self, code: u32, len: u32) {
+        for i in (0..len).rev() {
+            self.acc = self.acc << 1 | (code >> i & 1);
+            self.count += 1;
+            if self.count == 8 {
+                self.out.push(self.acc as u8);
+                if self.acc as u8 == 0xFF {
+                    self.out.push(0x00);
+                }
+                self.acc = 0;
+                self.count = 0;
+            }
+        }
+    }
+
+    fn dc(&mut self, table: &HuffmanTable, diff: i32) {
+        let size = 32 - diff.unsigned_abs().leading_zeros();
+        let (code, len) = table.code(size as u8).unwrap();
+        self.put(code, len);
+        let bits = if diff < 0 { diff - 1 } else { diff };
+        self.put(bits as u32 & ((1 << size) - 1), size);
+    }
+
+    fn eob(&mut self, table: &HuffmanTable) {
+        let (code, len) = table.code(0x00).unwrap();
+        self.put(code, len);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        // Pad with 1 bits, as encoders do
+        while self.count != 0 {
+            self.put(1, 1);
+        }
+        self.out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+
+    fn frame(width: u32, jpeg_type: u8, scan: Vec<u8>) -> ReassembledFrame {
+        ReassembledFrame {
+            ssrc: 1,
+            timestamp: 0,
+            width,
+            height: 240,
+            jpeg_type,
+            q: 80,
+            q_tables: None,
+            scan_data: Bytes::from(scan),
+            frame_id: None,
+        }
+    }
+
+    #[test]
+    fn test_standard_tables() {
+        let tables = Tables::standard();
+        // Spot checks against T.81 Tables K.3 - K.6
+        assert_eq!(tables.dc_luma.code(0), Some((0b00, 2)));
+        assert_eq!(tables.dc_luma.code(1), Some((0b010, 3)));
+        assert_eq!(tables.dc_chroma.code(1), Some((0b01, 2)));
+        assert_eq!(tables.ac_luma.code(0x00), Some((0b1010, 4)));
+        assert_eq!(tables.ac_luma.code(0xF0), Some((0b111_1111_1001, 11)));
+        assert_eq!(tables.ac_luma.code(0xFA), Some((0xFFFE, 16)));
+        assert_eq!(tables.ac_chroma.code(0x00), Some((0b00, 2)));
+        assert_eq!(tables.ac_chroma.code(0xF0), Some((0b11_1111_1010, 10)));
+        assert_eq!(tables.ac_chroma.code(0xFA), Some((0xFFFE, 16)));
+        for table in [&tables.ac_luma, &tables.ac_chroma] {
+            assert_eq!(table.values.len(), 162);
+        }
+    }
+
+    #[test]
+    fn test_paint() {
+        let (width, height, stride) = (330, 20, 336);
+        let mut luma = vec![128u8; stride * height];
+        assert!(paint(&mut luma, stride, width, height, 0x8000_0001));
+        // First bit set, then zeros
+        assert!(luma[..BAR_WIDTH].iter().all(|&y| y == WHITE));
+        assert!(luma[BAR_WIDTH..2 * BAR_WIDTH].iter().all(|&y| y == BLACK));
+        assert_eq!(luma[stride * (BAR_HEIGHT - 1)], WHITE);
+        // Right of and below the bars is untouched
+        assert_eq!(luma[MIN_WIDTH], 128);
+        assert_eq!(luma[stride * BAR_HEIGHT], 128);
+
+        let mut small = vec![128u8; 300 * 20];
+        assert!(!paint(&mut small, 300, 300, 20, 1));
+        assert!(small.iter().all(|&y| y == 128));
+    }
+
+    #[test]
+    fn test_read_flat_scan() {
+        for value in [0, 1, 0xDEAD_BEEF, u32::MAX, now()] {
+            let scan = flat_scan(640, 48, value);
+            assert_eq!(read(&frame(640, 0, scan)), Some(value), "{:#x}", value);
+        }
+    }
+
+    #[test]
+    fn test_read_rejects() {
+        let scan = flat_scan(640, 48, 1234);
+        // Restart markers, too narrow, truncated
+        assert_eq!(read(&frame(640, 64, scan.clone())), None);
+        assert_eq!(read(&frame(300, 0, scan.clone())), None);
+        assert_eq!(read(&frame(640, 0, scan[..10].to_vec())), None);
+    }
+
+    #[test]
+    fn test_checksum_catches_bit_errors() {
+        let bits = encode(0x1234_5678);
+        assert_eq!(decode(bits), Some(0x1234_5678));
+        for bit in 0..BITS {
+            assert_eq!(decode(bits ^ 1 << bit), None, "bit {}", bit);
+        }
+    }
+}
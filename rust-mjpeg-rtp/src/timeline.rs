@@ -0,0 +1,386 @@
+//! One chronological view of what happened on the cameras
+//!
+//! The control API's `GET /timeline` merges three sources into a single
+//! list, ordered by time and paged:
+//!
+//! - recordings: closed event clips, from their `.json` metadata
+//! - events: [`AnalyticsEvent`]s such as motion reports and detections,
+//!   both those kept in clip metadata and the most recent ones in memory
+//! - alerts: [`StreamerEvent`]s such as destination failovers
+//!
+//! Events and alerts outside any clip are only kept in memory: the last
+//! hundred of each, gone after a restart.
+
+use crate::clips::ClipMetadata;
+use crate::events::AnalyticsEvent;
+use crate::streamer::StreamerEvent;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::VecDeque;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Alerts kept for the timeline
+pub const RECENT_ALERTS: usize = 100;
+
+/// Entries per page unless asked otherwise
+pub const DEFAULT_LIMIT: usize = 100;
+
+/// Largest page served
+pub const MAX_LIMIT: usize = 1000;
+
+/// Something a camera's stream reported, stamped with when
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Alert {
+    pub camera: String,
+    /// Wall-clock time (ms since the Unix epoch)
+    pub timestamp_ms: u64,
+    pub event: StreamerEvent,
+}
+
+/// The last [`RECENT_ALERTS`] alerts of every camera; clones share them
+#[derive(Debug, Clone, Default)]
+pub struct AlertLog {
+    alerts: Arc<Mutex<VecDeque<Alert>>>,
+}
+
+impl AlertLog {
+    /// Records `event` about `camera` as of now
+    pub fn push(&self, camera: &str, event: StreamerEvent) {
+        let mut alerts = self.alerts.lock().unwrap();
+        if alerts.len() == RECENT_ALERTS {
+            alerts.pop_front();
+        }
+        alerts.push_back(Alert {
+            camera: camera.to_string(),
+            timestamp_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_millis() as u64)
+                .unwrap_or(0),
+            event,
+        });
+    }
+
+    /// Oldest first
+    pub fn recent(&self) -> Vec<Alert> {
+        self.alerts.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+/// One item on the timeline; `kind` tells them apart in JSON
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum TimelineEntry {
+    Recording {
+        camera: String,
+        /// The clip's id for `/recordings/{id}/...`
+        id: String,
+        start_ms: u64,
+        end_ms: u64,
+        frames: u64,
+        /// Whether `/recordings/{id}/thumb` has an image
+        thumbnail: bool,
+    },
+    Event {
+        camera: String,
+        start_ms: u64,
+        source: String,
+        frame_id: u64,
+        payload: Value,
+        /// The clip the event is in, if any
+        #[serde(skip_serializing_if = "Option::is_none")]
+        recording: Option<String>,
+    },
+    Alert {
+        camera: String,
+        start_ms: u64,
+        event: StreamerEvent,
+    },
+}
+
+impl TimelineEntry {
+    pub fn camera(&self) -> &str {
+        match self {
+            Self::Recording { camera, .. }
+            | Self::Event { camera, .. }
+            | Self::Alert { camera, .. } => camera,
+        }
+    }
+
+    pub fn start_ms(&self) -> u64 {
+        match *self {
+            Self::Recording { start_ms, .. }
+            | Self::Event { start_ms, .. }
+            | Self::Alert { start_ms, .. } => start_ms,
+        }
+    }
+
+    /// Last moment it covers; only recordings last
+    pub fn end_ms(&self) -> u64 {
+        match *self {
+            Self::Recording { end_ms, .. } => end_ms,
+            _ => self.start_ms(),
+        }
+    }
+
+    fn event(event: AnalyticsEvent, recording: Option<String>) -> Self {
+        Self::Event {
+            camera: event.camera,
+            start_ms: event.timestamp_ms,
+            source: event.source,
+            frame_id: event.frame_id,
+            payload: event.payload,
+            recording,
+        }
+    }
+}
+
+/// What to include: entries touching `[from_ms, to_ms)`, optionally of one
+/// camera, `limit` of them after skipping `offset`
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimelineQuery {
+    pub from_ms: u64,
+    pub to_ms: u64,
+    pub camera: Option<String>,
+    pub offset: usize,
+    pub limit: usize,
+}
+
+impl Default for TimelineQuery {
+    fn default() -> Self {
+        Self {
+            from_ms: 0,
+            to_ms: u64::MAX,
+            camera: None,
+            offset: 0,
+            limit: DEFAULT_LIMIT,
+        }
+    }
+}
+
+/// One page of the timeline
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Timeline {
+    pub entries: Vec<TimelineEntry>,
+    /// Entries matching the query, across all pages
+    pub total: usize,
+    /// `offset` of the next page, if there is one
+    pub next_offset: Option<usize>,
+}
+
+impl Timeline {
+    /// Merges the sources into the page `query` asks for, oldest first.
+    /// Events recorded in a clip and still in memory are listed once.
+    pub fn build(
+        clips: Vec<ClipMetadata>,
+        events: Vec<AnalyticsEvent>,
+        alerts: Vec<Alert>,
+        query: &TimelineQuery,
+    ) -> Self {
+        let mut entries = Vec::new();
+        let mut clip_events = Vec::new();
+        for clip in clips {
+            let id = clip_id(&clip.clip);
+            for event in std::iter::once(clip.trigger).chain(clip.events) {
+                clip_events.push((event, id.clone()));
+            }
+            entries.push(TimelineEntry::Recording {
+                camera: clip.camera,
+                start_ms: clip.started_ms,
+                end_ms: clip.ended_ms,
+                frames: clip.frames,
+                thumbnail: clip.thumbnail.is_some(),
+                id,
+            });
+        }
+        for event in events {
+            if !clip_events.iter().any(|(recorded, _)| *recorded == event) {
+                entries.push(TimelineEntry::event(event, None));
+            }
+        }
+        // A split clip's next part starts with its predecessor's last event
+        clip_events.dedup_by(|next, previous| next.0 == previous.0);
+        for (event, id) in clip_events {
+            entries.push(TimelineEntry::event(event, Some(id)));
+        }
+        for alert in alerts {
+            entries.push(TimelineEntry::Alert {
+                camera: alert.camera,
+                start_ms: alert.timestamp_ms,
+                event: alert.event,
+            });
+        }
+
+        entries.retain(|entry| {
+            entry.start_ms() < query.to_ms
+                && entry.end_ms() >= query.from_ms
+                && query
+                    .camera
+                    .as_deref()
+                    .is_none_or(|camera| entry.camera() == camera)
+        });
+        // Stable: on ties, recordings stay ahead of events and alerts
+        entries.sort_by_key(TimelineEntry::start_ms);
+
+        let total = entries.len();
+        let entries: Vec<_> = entries
+            .into_iter()
+            .skip(query.offset)
+            .take(query.limit)
+            .collect();
+        let end = query.offset + entries.len();
+        Self {
+            entries,
+            total,
+            next_offset: (end < total).then_some(end),
+        }
+    }
+}
+
+/// A clip's id: its file name without `.frames`
+pub fn clip_id(clip: &str) -> String {
+    Path::new(clip)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FailoverReason;
+    use serde_json::json;
+
+    fn event(camera: &str, timestamp_ms: u64) -> AnalyticsEvent {
+        AnalyticsEvent {
+            timestamp_ms,
+            ..AnalyticsEvent::new(camera, "people", timestamp_ms / 10, json!({"count": 1}))
+        }
+    }
+
+    fn clip(camera: &str, started_ms: u64, ended_ms: u64, events: &[u64]) -> ClipMetadata {
+        ClipMetadata {
+            camera: camera.to_string(),
+            clip: format!("/clips/{}-{}-0.frames", camera, started_ms),
+            thumbnail: None,
+            started_ms,
+            ended_ms,
+            frames: 10,
+            trigger: event(camera, events[0]),
+            events: events[1..].iter().map(|&ms| event(camera, ms)).collect(),
+        }
+    }
+
+    fn failover(camera: &str, timestamp_ms: u64) -> Alert {
+        Alert {
+            camera: camera.to_string(),
+            timestamp_ms,
+            event: StreamerEvent::Failover {
+                from: "127.0.0.1:5000".parse().unwrap(),
+                to: "127.0.0.1:5002".parse().unwrap(),
+                reason: FailoverReason::RtcpTimeout,
+            },
+        }
+    }
+
+    fn kinds(timeline: &Timeline) -> Vec<(&'static str, u64)> {
+        timeline
+            .entries
+            .iter()
+            .map(|entry| {
+                let kind = match entry {
+                    TimelineEntry::Recording { .. } => "recording",
+                    TimelineEntry::Event { .. } => "event",
+                    TimelineEntry::Alert { .. } => "alert",
+                };
+                (kind, entry.start_ms())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_merge() {
+        let clips = vec![clip("camera1", 1000, 3000, &[1500, 2000])];
+        // The clip's trigger is also still in memory
+        let events = vec![
+            event("camera1", 1500),
+            event("camera1", 4000),
+            event("camera2", 500),
+        ];
+        let alerts = vec![failover("camera1", 2500)];
+        let timeline = Timeline::build(clips, events, alerts, &TimelineQuery::default());
+        assert_eq!(
+            kinds(&timeline),
+            vec![
+                ("event", 500),
+                ("recording", 1000),
+                ("event", 1500),
+                ("event", 2000),
+                ("alert", 2500),
+                ("event", 4000),
+            ]
+        );
+        assert_eq!((timeline.total, timeline.next_offset), (6, None));
+        let TimelineEntry::Event { ref recording, .. } = timeline.entries[2] else {
+            panic!("not an event");
+        };
+        assert_eq!(recording.as_deref(), Some("camera1-1000-0"));
+        let TimelineEntry::Recording { ref id, .. } = timeline.entries[1] else {
+            panic!("not a recording");
+        };
+        assert_eq!(id, "camera1-1000-0");
+
+        let json = serde_json::to_value(&timeline.entries[4]).unwrap();
+        assert_eq!(json["kind"], "alert");
+        assert_eq!(json["camera"], "camera1");
+    }
+
+    #[test]
+    fn test_query() {
+        let clips = vec![
+            clip("camera1", 1000, 3000, &[1500]),
+            // Split from the first clip at its last event
+            clip("camera1", 3000, 5000, &[1500, 4500]),
+        ];
+        let events: Vec<_> = (0..10).map(|i| event("camera2", 6000 + i * 100)).collect();
+
+        // Clips overlapping the range count, events inside it
+        let query = TimelineQuery {
+            from_ms: 2000,
+            to_ms: 4600,
+            ..TimelineQuery::default()
+        };
+        let timeline = Timeline::build(clips.clone(), events.clone(), Vec::new(), &query);
+        assert_eq!(
+            kinds(&timeline),
+            vec![("recording", 1000), ("recording", 3000), ("event", 4500)]
+        );
+
+        let query = TimelineQuery {
+            camera: Some("camera2".to_string()),
+            offset: 4,
+            limit: 4,
+            ..TimelineQuery::default()
+        };
+        let timeline = Timeline::build(clips.clone(), events.clone(), Vec::new(), &query);
+        assert_eq!(timeline.entries.len(), 4);
+        assert_eq!(timeline.entries[0].start_ms(), 6400);
+        assert_eq!((timeline.total, timeline.next_offset), (10, Some(8)));
+        let query = TimelineQuery { offset: 8, ..query };
+        let timeline = Timeline::build(clips, events, Vec::new(), &query);
+        assert_eq!(timeline.entries.len(), 2);
+        assert_eq!(timeline.next_offset, None);
+    }
+
+    #[test]
+    fn test_alert_log() {
+        let log = AlertLog::default();
+        for _ in 0..RECENT_ALERTS + 5 {
+            log.clone().push("camera1", failover("camera1", 0).event);
+        }
+        let alerts = log.recent();
+        assert_eq!(alerts.len(), RECENT_ALERTS);
+        assert!(alerts[0].timestamp_ms > 0);
+    }
+}
@@ -0,0 +1,395 @@
+//! Background H.265 transcoding of archived recordings
+//!
+//! MJPEG keeps the live path simple and low-latency, but an archive of it is
+//! roughly ten times the size of the same footage in H.265. With
+//! `[mjpeg-rtp.transcode]` enabled, a [`Transcoder`] periodically looks for
+//! `.frames` recordings nobody has written to for `after_hours` and queues
+//! them, oldest first. One at a time, each is piped as a stream of JPEGs
+//! into an encoder process run under `nice`: ffmpeg with libx265, GStreamer
+//! with x265enc, or a configured command. The result is written to
+//! `<name>.mp4.part` and renamed to `<name>.mp4` once the encoder succeeds;
+//! only then is the `.frames` file deleted (unless `keep_original`).
+//!
+//! The frame rate is the recording's average, so the video keeps its length
+//! while per-frame timing is evened out. A recording that fails is skipped
+//! until the next restart.
+
+use crate::config::{TranscodeConfig, TranscodeEncoder};
+use crate::error::ErrorCode;
+use crate::recording::{FrameReader, RecordingError};
+use crate::task::CancellationToken;
+use std::collections::HashSet;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus, Stdio};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use thiserror::Error;
+use tracing::{debug, info, warn};
+
+/// Encoder commands; split on whitespace before `{...}` is substituted
+const FFMPEG: &str = "ffmpeg -hide_banner -loglevel error -y -f mjpeg -framerate {fps} -i pipe:0 \
+     -c:v libx265 -preset {preset} -crf {crf} -tag:v hvc1 -f mp4 {output}";
+
+const GSTREAMER: &str = "gst-launch-1.0 -q -e fdsrc fd=0 ! image/jpeg,framerate={fps}/1 ! \
+     jpegparse ! jpegdec ! videoconvert ! \
+     x265enc speed-preset={preset} option-string=crf={crf} ! h265parse ! mp4mux ! \
+     filesink location={output}";
+
+#[derive(Error, Debug)]
+pub enum TranscodeError {
+    #[error(transparent)]
+    Recording(#[from] RecordingError),
+
+    #[error("transcoder I/O error: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("cannot run {program}: {source}")]
+    Spawn { program: String, source: io::Error },
+
+    #[error("encoder exited with {0}")]
+    Failed(ExitStatus),
+
+    #[error("transcoding cancelled")]
+    Cancelled,
+}
+
+impl TranscodeError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            TranscodeError::Recording(e) => e.code(),
+            TranscodeError::Io(_) => ErrorCode::Io,
+            TranscodeError::Spawn { .. } | TranscodeError::Failed(_) => ErrorCode::Device,
+            TranscodeError::Cancelled => ErrorCode::NotRunning,
+        }
+    }
+}
+
+/// The encoder's argument vector, `nice` included, writing to `output`
+pub fn command_line(config: &TranscodeConfig, fps: u32, output: &Path) -> Vec<String> {
+    let template: Vec<&str> = if config.command.is_empty() {
+        match config.encoder {
+            TranscodeEncoder::Ffmpeg => FFMPEG.split_whitespace().collect(),
+            TranscodeEncoder::Gstreamer => GSTREAMER.split_whitespace().collect(),
+        }
+    } else {
+        config.command.iter().map(String::as_str).collect()
+    };
+    let output = output.to_string_lossy();
+    let mut args = Vec::new();
+    if config.nice != 0 {
+        args.extend([
+            "nice".to_string(),
+            "-n".to_string(),
+            config.nice.to_string(),
+        ]);
+    }
+    args.extend(template.iter().map(|arg| {
+        arg.replace("{fps}", &fps.to_string())
+            .replace("{crf}", &config.crf.to_string())
+            .replace("{preset}", &config.preset)
+            .replace("{output}", &output)
+    }));
+    args
+}
+
+/// Average frame rate of a recording, at least 1
+pub fn average_fps(path: &Path) -> Result<u32, TranscodeError> {
+    let (mut frames, mut span) = (0u64, Duration::ZERO);
+    for frame in FrameReader::open(path)? {
+        frames += 1;
+        span = frame?.offset;
+    }
+    if frames == 0 {
+        return Err(RecordingError::Empty.into());
+    }
+    if span.is_zero() {
+        return Ok(1);
+    }
+    Ok((((frames - 1) as f64 / span.as_secs_f64()).round() as u32).max(1))
+}
+
+/// Transcodes one recording to `<name>.mp4` next to it and returns that
+/// path. Blocks until the encoder exits; `token` stops it early.
+pub fn transcode_file(
+    config: &TranscodeConfig,
+    path: &Path,
+    token: &CancellationToken,
+) -> Result<PathBuf, TranscodeError> {
+    let output = path.with_extension("mp4");
+    let partial = path.with_extension("mp4.part");
+    let args = command_line(config, average_fps(path)?, &partial);
+    debug!(command = ?args, "Starting encoder");
+    let mut child = Command::new(&args[0])
+        .args(&args[1..])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()
+        .map_err(|source| TranscodeError::Spawn {
+            program: args[0].clone(),
+            source,
+        })?;
+
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let written = (|| -> Result<(), TranscodeError> {
+        for frame in FrameReader::open(path)? {
+            if token.is_cancelled() {
+                return Err(TranscodeError::Cancelled);
+            }
+            stdin.write_all(&frame?.data)?;
+        }
+        Ok(())
+    })();
+    drop(stdin);
+    if let Err(TranscodeError::Cancelled) = written {
+        let _ = child.kill();
+    }
+    let status = child.wait()?;
+
+    let result = match written {
+        Err(e @ TranscodeError::Cancelled) => Err(e),
+        _ if !status.success() => Err(TranscodeError::Failed(status)),
+        Err(e) => Err(e),
+        Ok(()) => std::fs::rename(&partial, &output).map_err(TranscodeError::from),
+    };
+    if let Err(e) = result {
+        let _ = std::fs::remove_file(&partial);
+        return Err(e);
+    }
+
+    let size = |path: &Path| std::fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+    info!(
+        recording = %path.display(),
+        output = %output.display(),
+        from_bytes = size(path),
+        to_bytes = size(&output),
+        "Recording transcoded"
+    );
+    if !config.keep_original {
+        std::fs::remove_file(path)?;
+    }
+    Ok(output)
+}
+
+/// Finds old recordings and transcodes them one by one; see the
+/// [module docs](self)
+pub struct Transcoder {
+    config: Arc<TranscodeConfig>,
+    dirs: Vec<PathBuf>,
+    /// Recordings that failed, not retried
+    failed: HashSet<PathBuf>,
+}
+
+impl Transcoder {
+    pub fn new(config: &TranscodeConfig, dirs: Vec<PathBuf>) -> Self {
+        Self {
+            config: Arc::new(config.clone()),
+            dirs,
+            failed: HashSet::new(),
+        }
+    }
+
+    /// Recordings last modified `after_hours` before `now` and not
+    /// transcoded yet, oldest first
+    pub fn pending(&self, now: SystemTime) -> Vec<PathBuf> {
+        let age = Duration::from_secs(self.config.after_hours * 3600);
+        let mut due = Vec::new();
+        for dir in &self.dirs {
+            let entries = match std::fs::read_dir(dir) {
+                Ok(entries) => entries,
+                Err(e) => {
+                    debug!(dir = %dir.display(), error = %e, "Cannot search for recordings");
+                    continue;
+                }
+            };
+            for path in entries.filter_map(|entry| Some(entry.ok()?.path())) {
+                if path.extension().is_none_or(|ext| ext != "frames")
+                    || path.with_extension("mp4").exists()
+                    || self.failed.contains(&path)
+                {
+                    continue;
+                }
+                let modified = std::fs::metadata(&path).and_then(|m| m.modified());
+                match modified {
+                    Ok(modified) if now.duration_since(modified).is_ok_and(|d| d >= age) => {
+                        due.push((modified, path));
+                    }
+                    _ => {}
+                }
+            }
+        }
+        due.sort();
+        due.into_iter().map(|(_, path)| path).collect()
+    }
+
+    /// Searches every `scan_interval_secs` and works through the queue
+    /// until `token` is cancelled
+    pub async fn run(mut self, token: CancellationToken) {
+        let interval = Duration::from_secs(self.config.scan_interval_secs);
+        loop {
+            let queue = self.pending(SystemTime::now());
+            if !queue.is_empty() {
+                info!(queued = queue.len(), "Transcoding archived recordings");
+            }
+            for path in queue {
+                if token.is_cancelled() {
+                    return;
+                }
+                let (config, input, job_token) = (self.config.clone(), path.clone(), token.clone());
+                let job = tokio::task::spawn_blocking(move || {
+                    transcode_file(&config, &input, &job_token)
+                });
+                match job.await {
+                    Ok(Ok(_)) => {}
+                    Ok(Err(TranscodeError::Cancelled)) => return,
+                    Ok(Err(e)) => {
+                        warn!(recording = %path.display(), error = %e, "Transcoding failed");
+                        self.failed.insert(path);
+                    }
+                    Err(e) => {
+                        warn!(recording = %path.display(), error = %e, "Transcoder panicked");
+                        self.failed.insert(path);
+                    }
+                }
+            }
+            tokio::select! {
+                _ = token.cancelled() => return,
+                _ = tokio::time::sleep(interval) => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recording::FrameWriter;
+    use crate::Frame;
+    use bytes::Bytes;
+    use std::fs::File;
+    use std::time::Instant;
+
+    fn config(command: &[&str]) -> TranscodeConfig {
+        TranscodeConfig {
+            enabled: true,
+            nice: 0,
+            command: command.iter().map(|arg| arg.to_string()).collect(),
+            ..TranscodeConfig::default()
+        }
+    }
+
+    /// `frames` frames at 10 fps
+    fn write_recording(path: &Path, frames: u64) {
+        let mut writer = FrameWriter::create(path).unwrap();
+        let start = Instant::now();
+        for id in 0..frames {
+            let frame = Frame {
+                captured_at: start + Duration::from_millis(100 * id),
+                ..Frame::new(id, Bytes::from(vec![b'a' + id as u8; 3]))
+            };
+            writer.write_frame(&frame).unwrap();
+        }
+        writer.finish().unwrap();
+    }
+
+    #[test]
+    fn test_command_line() {
+        let output = Path::new("/clips/a.mp4.part");
+        let ffmpeg = command_line(&TranscodeConfig::default(), 30, output);
+        assert_eq!(ffmpeg[..4], ["nice", "-n", "19", "ffmpeg"]);
+        assert!(ffmpeg.windows(2).any(|pair| pair == ["-framerate", "30"]));
+        assert!(ffmpeg.windows(2).any(|pair| pair == ["-crf", "28"]));
+        assert_eq!(ffmpeg.last().unwrap(), "/clips/a.mp4.part");
+
+        let gstreamer = TranscodeConfig {
+            encoder: TranscodeEncoder::Gstreamer,
+            preset: "ultrafast".to_string(),
+            nice: 0,
+            ..TranscodeConfig::default()
+        };
+        let gst = command_line(&gstreamer, 15, output);
+        assert_eq!(gst[0], "gst-launch-1.0");
+        assert!(gst.contains(&"image/jpeg,framerate=15/1".to_string()));
+        assert!(gst.contains(&"speed-preset=ultrafast".to_string()));
+        assert!(gst.contains(&"location=/clips/a.mp4.part".to_string()));
+
+        let custom = command_line(
+            &config(&["enc", "-o", "{output}", "-r", "{fps}"]),
+            5,
+            output,
+        );
+        assert_eq!(custom, ["enc", "-o", "/clips/a.mp4.part", "-r", "5"]);
+    }
+
+    #[test]
+    fn test_pending() {
+        let dir = tempfile::tempdir().unwrap();
+        let now = SystemTime::now();
+        let old = now - Duration::from_secs(25 * 3600);
+        for name in [
+            "b.frames",
+            "a.frames",
+            "new.frames",
+            "done.frames",
+            "notes.txt",
+        ] {
+            let file = File::create(dir.path().join(name)).unwrap();
+            if name != "new.frames" {
+                let age = if name == "a.frames" {
+                    Duration::from_secs(60)
+                } else {
+                    Duration::ZERO
+                };
+                file.set_modified(old - age).unwrap();
+            }
+        }
+        File::create(dir.path().join("done.mp4")).unwrap();
+
+        let missing = dir.path().join("missing");
+        let mut transcoder = Transcoder::new(&config(&[]), vec![missing, dir.path().to_path_buf()]);
+        let names = |paths: Vec<PathBuf>| -> Vec<String> {
+            let name = |path: &PathBuf| path.file_name().unwrap().to_string_lossy().into_owned();
+            paths.iter().map(name).collect()
+        };
+        assert_eq!(names(transcoder.pending(now)), ["a.frames", "b.frames"]);
+        transcoder.failed.insert(dir.path().join("a.frames"));
+        assert_eq!(names(transcoder.pending(now)), ["b.frames"]);
+    }
+
+    #[test]
+    fn test_transcode_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("camera1.frames");
+        write_recording(&path, 11);
+        assert_eq!(average_fps(&path).unwrap(), 10);
+        let token = CancellationToken::new();
+
+        let failing = config(&["sh", "-c", "cat > \"$0\"; exit 3", "{output}"]);
+        let err = transcode_file(&failing, &path, &token).unwrap_err();
+        assert!(matches!(err, TranscodeError::Failed(_)), "{}", err);
+        assert_eq!(err.code(), ErrorCode::Device);
+        assert!(path.exists());
+        assert!(!path.with_extension("mp4.part").exists());
+
+        let missing = config(&["/nonexistent/encoder", "{output}"]);
+        let err = transcode_file(&missing, &path, &token).unwrap_err();
+        assert!(matches!(err, TranscodeError::Spawn { .. }), "{}", err);
+
+        // Stands in for an encoder: the output is the JPEGs it was fed
+        let cat = config(&["sh", "-c", "cat > \"$0\"", "{output}"]);
+        let output = transcode_file(&cat, &path, &token).unwrap();
+        assert_eq!(output, dir.path().join("camera1.mp4"));
+        assert_eq!(
+            std::fs::read(&output).unwrap(),
+            (0..11u8).flat_map(|i| [b'a' + i; 3]).collect::<Vec<_>>()
+        );
+        assert!(!path.exists());
+
+        let empty = dir.path().join("empty.frames");
+        FrameWriter::create(&empty).unwrap().finish().unwrap();
+        assert!(matches!(
+            transcode_file(&cat, &empty, &token),
+            Err(TranscodeError::Recording(RecordingError::Empty))
+        ));
+    }
+}
@@ -0,0 +1,188 @@
+//! Shared helpers for integration tests
+
+#![allow(dead_code)]
+
+use std::io;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::UdpSocket;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+
+/// What the simulated network does to each datagram. Probabilities are
+/// 0.0-1.0 and rolled independently per packet from a seeded generator.
+#[derive(Debug, Clone)]
+pub struct Impairments {
+    /// Drop the packet
+    pub loss: f64,
+    /// Deliver the packet twice
+    pub duplicate: f64,
+    /// Hold the packet back by `reorder_delay` so later packets overtake it
+    pub reorder: f64,
+    pub reorder_delay: Duration,
+    /// Extra delay, uniform in 0..jitter. Order is preserved: a packet is
+    /// never delivered before the one received ahead of it.
+    pub jitter: Duration,
+    pub seed: u64,
+}
+
+impl Default for Impairments {
+    fn default() -> Self {
+        Self {
+            loss: 0.0,
+            duplicate: 0.0,
+            reorder: 0.0,
+            reorder_delay: Duration::from_millis(5),
+            jitter: Duration::ZERO,
+            seed: 0x5EED,
+        }
+    }
+}
+
+/// What the simulator did to the packets that went through it
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SimulatorStats {
+    pub received: u64,
+    pub dropped: u64,
+    pub duplicated: u64,
+    pub reordered: u64,
+}
+
+#[derive(Default)]
+struct Counters {
+    received: AtomicU64,
+    dropped: AtomicU64,
+    duplicated: AtomicU64,
+    reordered: AtomicU64,
+}
+
+/// UDP relay between a sender and a receiver that applies [`Impairments`].
+/// Point the sender at [`NetworkSimulator::addr`]; packets are forwarded to
+/// the target given to [`NetworkSimulator::start`].
+pub struct NetworkSimulator {
+    addr: SocketAddr,
+    counters: Arc<Counters>,
+    task: JoinHandle<()>,
+}
+
+impl NetworkSimulator {
+    pub async fn start(target: SocketAddr, impairments: Impairments) -> io::Result<Self> {
+        let ingress = UdpSocket::bind("127.0.0.1:0").await?;
+        let addr = ingress.local_addr()?;
+        let egress = Arc::new(UdpSocket::bind("127.0.0.1:0").await?);
+        egress.connect(target).await?;
+
+        let counters = Arc::new(Counters::default());
+        let task = tokio::spawn(relay(ingress, egress, impairments, Arc::clone(&counters)));
+
+        Ok(Self {
+            addr,
+            counters,
+            task,
+        })
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    pub fn stats(&self) -> SimulatorStats {
+        SimulatorStats {
+            received: self.counters.received.load(Ordering::Relaxed),
+            dropped: self.counters.dropped.load(Ordering::Relaxed),
+            duplicated: self.counters.duplicated.load(Ordering::Relaxed),
+            reordered: self.counters.reordered.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl Drop for NetworkSimulator {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn relay(
+    ingress: UdpSocket,
+    egress: Arc<UdpSocket>,
+    impairments: Impairments,
+    counters: Arc<Counters>,
+) {
+    let mut rng = Rng(impairments.seed | 1);
+
+    // In-order packets go through one queue so jitter never reorders them
+    let (queue_tx, mut queue_rx) = mpsc::unbounded_channel::<(Instant, Vec<u8>)>();
+    let in_order = {
+        let egress = Arc::clone(&egress);
+        tokio::spawn(async move {
+            while let Some((deliver_at, packet)) = queue_rx.recv().await {
+                tokio::time::sleep_until(deliver_at).await;
+                let _ = egress.send(&packet).await;
+            }
+        })
+    };
+
+    let mut last_delivery = Instant::now();
+    let mut buf = vec![0u8; 65536];
+    while let Ok(len) = ingress.recv(&mut buf).await {
+        counters.received.fetch_add(1, Ordering::Relaxed);
+        let packet = buf[..len].to_vec();
+
+        if rng.chance(impairments.loss) {
+            counters.dropped.fetch_add(1, Ordering::Relaxed);
+            continue;
+        }
+        let copies = if rng.chance(impairments.duplicate) {
+            counters.duplicated.fetch_add(1, Ordering::Relaxed);
+            2
+        } else {
+            1
+        };
+
+        let jitter = impairments.jitter.mul_f64(rng.next_f64());
+        let deliver_at = (Instant::now() + jitter).max(last_delivery);
+
+        if rng.chance(impairments.reorder) {
+            counters.reordered.fetch_add(1, Ordering::Relaxed);
+            let egress = Arc::clone(&egress);
+            let deliver_at = deliver_at + impairments.reorder_delay;
+            tokio::spawn(async move {
+                tokio::time::sleep_until(deliver_at).await;
+                for _ in 0..copies {
+                    let _ = egress.send(&packet).await;
+                }
+            });
+            continue;
+        }
+
+        last_delivery = deliver_at;
+        for _ in 0..copies {
+            let _ = queue_tx.send((deliver_at, packet.clone()));
+        }
+    }
+
+    in_order.abort();
+}
+
+/// xorshift64*, so a given seed always impairs the same packets
+struct Rng(u64);
+
+impl Rng {
+    fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    fn chance(&mut self, probability: f64) -> bool {
+        probability > 0.0 && self.next_f64() < probability
+    }
+}
@@ -46,6 +46,7 @@ mod macos_e2e {
             fps: 30,
             quality: 95,
             flip_method: None,
+            ..Default::default()
         };
 
         let mut capture = Capture::new(capture_config).expect("Failed to create capture");
@@ -63,6 +64,7 @@ mod macos_e2e {
             mtu: 1400,
             ssrc: 0xFEEDFACE,
             dscp: 0,
+            ..Default::default()
         };
 
         let mut streamer = Streamer::new(streamer_config)
@@ -0,0 +1,48 @@
+//! Replays the fuzz corpus (fuzz/corpus) through the same code as the fuzz
+//! targets, so inputs that once crashed stay covered without cargo-fuzz
+
+use rust_mjpeg_rtp::receiver::{Depacketizer, ReceiverConfig};
+use rust_mjpeg_rtp::rtp::{parse_jpeg_for_rtp, JpegProbe, RtpPacketizer};
+use std::fs;
+use std::path::PathBuf;
+
+fn corpus(target: &str) -> Vec<(PathBuf, Vec<u8>)> {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("fuzz/corpus")
+        .join(target);
+    let mut inputs: Vec<_> = fs::read_dir(&dir)
+        .unwrap_or_else(|e| panic!("{}: {}", dir.display(), e))
+        .map(|entry| {
+            let path = entry.unwrap().path();
+            let data = fs::read(&path).unwrap();
+            (path, data)
+        })
+        .collect();
+    inputs.sort();
+    assert!(!inputs.is_empty(), "{} is empty", dir.display());
+    inputs
+}
+
+#[test]
+fn test_jpeg_parser_corpus() {
+    for (path, data) in corpus("jpeg_parser") {
+        println!("{}", path.display());
+        let _ = parse_jpeg_for_rtp(&data);
+        let _ = JpegProbe::new(&data);
+        let _ = RtpPacketizer::new(0x1234_5678, 200).packetize_jpeg(&data, 640, 480, 0);
+    }
+}
+
+#[test]
+fn test_depacketizer_corpus() {
+    for (path, data) in corpus("depacketizer") {
+        println!("{}", path.display());
+        let mut depacketizer = Depacketizer::new(ReceiverConfig::default());
+        let mut rest = &data[..];
+        while rest.len() >= 2 {
+            let len = (u16::from_be_bytes([rest[0], rest[1]]) as usize).min(rest.len() - 2);
+            let _ = depacketizer.push(&rest[2..2 + len]);
+            rest = &rest[2 + len..];
+        }
+    }
+}
@@ -50,6 +50,7 @@ async fn test_macos_webcam_capture() {
         fps: 30,
         quality: 85,
         flip_method: None,
+        ..Default::default()
     };
 
     let mut capture = Capture::new(config).expect("Failed to create capture");
@@ -141,6 +142,7 @@ async fn test_macos_mjpeg_rtp_loopback() {
         fps: 30,
         quality: 85,
         flip_method: None,
+        ..Default::default()
     };
 
     let mut capture = Capture::new(capture_config).expect("Failed to create capture");
@@ -157,6 +159,7 @@ async fn test_macos_mjpeg_rtp_loopback() {
         mtu: 1400,
         ssrc: 0xDEADBEEF,
         dscp: 0,
+        ..Default::default()
     };
 
     let mut streamer = Streamer::new(streamer_config)
@@ -276,6 +279,7 @@ async fn test_macos_streaming_statistics() {
         fps: 30,
         quality: 95,
         flip_method: None,
+        ..Default::default()
     };
 
     let mut capture = Capture::new(capture_config).expect("Failed to create capture");
@@ -292,6 +296,7 @@ async fn test_macos_streaming_statistics() {
         mtu: 1400,
         ssrc: 0xCAFEBABE,
         dscp: 0,
+        ..Default::default()
     };
 
     let mut streamer = Streamer::new(streamer_config)
@@ -0,0 +1,223 @@
+//! Streamer → NetworkSimulator → Depacketizer over loopback UDP
+//!
+//! Each test streams the same frames through a different impairment and
+//! checks what the receive side makes of them. Frames carry the frame-info
+//! extension, so whatever else happens, every frame the depacketizer
+//! completes must pass its CRC32 check and match the sent frame byte for byte.
+
+mod common;
+
+use bytes::Bytes;
+use common::{Impairments, NetworkSimulator, SimulatorStats};
+use rust_mjpeg_rtp::pacing::FramePacer;
+use rust_mjpeg_rtp::receiver::{Depacketizer, ReceiverConfig, ReceiverStats};
+use rust_mjpeg_rtp::{Frame, Streamer, StreamerConfig};
+use std::collections::HashSet;
+use std::time::Duration;
+use tokio::net::UdpSocket;
+
+const FRAMES: usize = 30;
+const FPS: u32 = 50;
+const FRAME_INTERVAL: Duration = Duration::from_millis(1000 / FPS as u64);
+const SCAN_SIZE: usize = 6000;
+/// The receiver stops once nothing has arrived for this long
+const IDLE_TIMEOUT: Duration = Duration::from_millis(300);
+const FRAME_INFO_ID: u8 = 1;
+
+/// Baseline 4:2:0 JPEG whose scan data is unique to `index`
+fn test_jpeg(index: usize) -> (Vec<u8>, Vec<u8>) {
+    let scan: Vec<u8> = (0..SCAN_SIZE)
+        .map(|i| ((i * 7 + index * 13) % 251) as u8)
+        .collect();
+
+    let mut jpeg = vec![0xFF, 0xD8];
+    // DQT: two 8-bit tables
+    jpeg.extend([0xFF, 0xDB, 0x00, 0x84, 0x00]);
+    jpeg.extend([16u8; 64]);
+    jpeg.push(0x01);
+    jpeg.extend([17u8; 64]);
+    // SOF0: 640x480, Y 2x2, Cb/Cr 1x1
+    jpeg.extend([0xFF, 0xC0, 0x00, 0x11, 0x08, 0x01, 0xE0, 0x02, 0x80, 0x03]);
+    jpeg.extend([0x01, 0x22, 0x00, 0x02, 0x11, 0x01, 0x03, 0x11, 0x01]);
+    // SOS
+    jpeg.extend([
+        0xFF, 0xDA, 0x00, 0x0C, 0x03, 0x01, 0x00, 0x02, 0x11, 0x03, 0x11, 0x00, 0x3F, 0x00,
+    ]);
+    jpeg.extend(&scan);
+    jpeg.extend([0xFF, 0xD9]);
+    (jpeg, scan)
+}
+
+struct Outcome {
+    /// Indexes of the sent frames that were reassembled
+    completed: Vec<usize>,
+    receiver: ReceiverStats,
+    network: SimulatorStats,
+}
+
+async fn stream_through(impairments: Impairments) -> Outcome {
+    let receiver = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let network = NetworkSimulator::start(receiver.local_addr().unwrap(), impairments)
+        .await
+        .unwrap();
+
+    let mut streamer = Streamer::new(StreamerConfig {
+        dest_port: network.addr().port(),
+        width: 640,
+        height: 480,
+        mtu: 1400,
+        frame_info_id: Some(FRAME_INFO_ID),
+        ..Default::default()
+    })
+    .await
+    .unwrap();
+    streamer.start().await.unwrap();
+
+    let (jpegs, scans): (Vec<_>, Vec<_>) = (0..FRAMES).map(test_jpeg).unzip();
+
+    // Receive while sending, so the socket buffer never overflows
+    let receiving = tokio::spawn(async move {
+        let mut depacketizer = Depacketizer::new(ReceiverConfig {
+            frame_info_id: Some(FRAME_INFO_ID),
+            ..Default::default()
+        });
+        let mut completed = Vec::new();
+        let mut buf = vec![0u8; 2048];
+        while let Ok(Ok(len)) = tokio::time::timeout(IDLE_TIMEOUT, receiver.recv(&mut buf)).await {
+            if let Ok(Some(frame)) = depacketizer.push(&buf[..len]) {
+                let index = frame.frame_id.expect("frame-info extension verified") as usize;
+                assert_eq!(
+                    scans[index][..],
+                    frame.scan_data[..],
+                    "frame {} differs",
+                    index
+                );
+                completed.push(index);
+            }
+        }
+        (completed, depacketizer.get_stats())
+    });
+
+    let mut pacer = FramePacer::new(FPS);
+    for (index, jpeg) in jpegs.into_iter().enumerate() {
+        pacer.tick().await;
+        streamer
+            .send_frame(Frame::new(index as u64, Bytes::from(jpeg)))
+            .await
+            .unwrap();
+    }
+    let (completed, receiver) = receiving.await.unwrap();
+    streamer.stop().await.unwrap();
+
+    let unique: HashSet<_> = completed.iter().collect();
+    assert_eq!(
+        unique.len(),
+        completed.len(),
+        "frame completed twice: {:?}",
+        completed
+    );
+    assert_eq!(receiver.frames_corrupt, 0);
+    assert_eq!(receiver.frames_verified, completed.len() as u64);
+
+    Outcome {
+        completed,
+        receiver,
+        network: network.stats(),
+    }
+}
+
+/// Every frame is either reassembled or counted incomplete, apart from a
+/// last frame whose marker packet never arrived
+fn assert_accounted(outcome: &Outcome) {
+    let accounted = outcome.completed.len() as u64 + outcome.receiver.frames_incomplete;
+    assert!(
+        (FRAMES as u64 - 1..=FRAMES as u64).contains(&accounted),
+        "{} of {} frames accounted for: {:?}",
+        accounted,
+        FRAMES,
+        outcome.receiver
+    );
+}
+
+#[tokio::test]
+async fn test_clean_network_delivers_every_frame() {
+    let outcome = stream_through(Impairments::default()).await;
+
+    assert_eq!(outcome.completed, (0..FRAMES).collect::<Vec<_>>());
+    assert_eq!(outcome.receiver.frames_incomplete, 0);
+}
+
+#[tokio::test]
+async fn test_loss_drops_frames_but_never_corrupts_them() {
+    let outcome = stream_through(Impairments {
+        loss: 0.05,
+        ..Default::default()
+    })
+    .await;
+
+    assert!(outcome.network.dropped > 0);
+    assert!(outcome.completed.len() < FRAMES);
+    assert!(outcome.receiver.frames_incomplete > 0);
+    assert_accounted(&outcome);
+}
+
+#[tokio::test]
+async fn test_duplicates_are_rejected() {
+    let outcome = stream_through(Impairments {
+        duplicate: 0.2,
+        ..Default::default()
+    })
+    .await;
+
+    assert!(outcome.network.duplicated > 0);
+    assert_eq!(outcome.completed.len(), FRAMES);
+    assert_eq!(
+        outcome.receiver.rejected_duplicate,
+        outcome.network.duplicated
+    );
+}
+
+#[tokio::test]
+async fn test_jitter_without_reordering_delivers_every_frame() {
+    let outcome = stream_through(Impairments {
+        jitter: Duration::from_millis(15),
+        ..Default::default()
+    })
+    .await;
+
+    assert_eq!(outcome.completed, (0..FRAMES).collect::<Vec<_>>());
+}
+
+#[tokio::test]
+async fn test_reordering_within_a_frame() {
+    // Held back for less than a frame interval. Fragments overtaken by other
+    // fragments are reassembled; one overtaken by the marker packet arrives
+    // after its frame was given up, and is dropped as late
+    let outcome = stream_through(Impairments {
+        reorder: 0.1,
+        reorder_delay: Duration::from_millis(2),
+        ..Default::default()
+    })
+    .await;
+
+    assert!(outcome.network.reordered > 0);
+    assert!(outcome.completed.len() >= FRAMES / 2);
+    assert!(outcome.receiver.rejected_late <= outcome.network.reordered);
+    assert_accounted(&outcome);
+}
+
+#[tokio::test]
+async fn test_packets_reordered_into_later_frames_are_late() {
+    // Held back past the next frame: the straggler is dropped instead of
+    // discarding the frame being reassembled
+    let outcome = stream_through(Impairments {
+        reorder: 0.05,
+        reorder_delay: FRAME_INTERVAL * 2,
+        ..Default::default()
+    })
+    .await;
+
+    assert!(outcome.receiver.rejected_late > 0);
+    assert_eq!(outcome.receiver.rejected_late, outcome.network.reordered);
+    assert_accounted(&outcome);
+}
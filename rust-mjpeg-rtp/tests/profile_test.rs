@@ -26,6 +26,7 @@ mod profile {
             fps: 30,
             quality: 85,
             flip_method: None,
+            ..Default::default()
         };
 
         let mut capture = Capture::new(capture_config).expect("Failed to create capture");
@@ -43,6 +44,7 @@ mod profile {
             mtu: 1400,
             ssrc: 0xDEADBEEF,
             dscp: 0,
+            ..Default::default()
         };
 
         let mut streamer = Streamer::new(streamer_config)
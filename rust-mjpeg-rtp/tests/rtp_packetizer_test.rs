@@ -13,6 +13,27 @@ fn create_test_jpeg(payload_size: usize) -> Vec<u8> {
     jpeg
 }
 
+/// Helper to create a baseline JPEG with two 64-byte quantization tables,
+/// returning it along with where its scan data starts
+fn create_test_jpeg_with_qtables(scan_size: usize) -> (Vec<u8>, usize) {
+    let mut jpeg = vec![0xFF, 0xD8]; // SOI marker
+    for id in 0..2u8 {
+        jpeg.extend(&[0xFF, 0xDB, 0x00, 0x43, id]); // DQT marker, length, table id
+        jpeg.extend((1..=64).map(|q| q + id));
+    }
+    // SOF0: 640x480, one component
+    jpeg.extend(&[
+        0xFF, 0xC0, 0x00, 0x0B, 0x08, 0x01, 0xE0, 0x02, 0x80, 0x01, 0x01, 0x11, 0x00,
+    ]);
+    // SOS: one component
+    jpeg.extend(&[0xFF, 0xDA, 0x00, 0x08, 0x01, 0x01, 0x00, 0x00, 0x3F, 0x00]);
+    let scan_start = jpeg.len();
+    // Scan data without markers
+    jpeg.extend((0..scan_size).map(|i| (i % 255) as u8));
+    jpeg.extend(&[0xFF, 0xD9]); // EOI marker
+    (jpeg, scan_start)
+}
+
 #[test]
 fn test_new_rtp_packetizer() {
     let p = RtpPacketizer::new(0x12345678, 1400);
@@ -387,3 +408,57 @@ fn test_large_jpeg() {
 
     assert_eq!(total_payload, large_jpeg.len());
 }
+
+#[test]
+fn test_packets_with_qtables_fit_mtu() {
+    // First packet carries a 4-byte q-table header plus both 8-bit tables
+    let qtable_header = 4 + 2 * 64;
+    for mtu in [576, 1200, 1400, 1500] {
+        let p = RtpPacketizer::new(0x12345678, mtu);
+        let max_payload = mtu - RTP_HEADER_SIZE - JPEG_HEADER_SIZE;
+        let first_payload = max_payload - qtable_header;
+        let sizes = [
+            1,
+            first_payload - 1,
+            first_payload,
+            first_payload + 1,
+            max_payload,
+            max_payload + 1,
+            first_payload + max_payload,
+            first_payload + max_payload + 1,
+            50_000,
+        ];
+        for scan_size in sizes {
+            let (jpeg, scan_start) = create_test_jpeg_with_qtables(scan_size);
+            let packets = p.packetize_jpeg(&jpeg, 640, 480, 1000).unwrap();
+
+            let largest = packets.iter().map(|pkt| pkt.len()).max().unwrap();
+            assert!(
+                largest <= mtu,
+                "{} byte packet over the {} MTU for a {} byte scan",
+                largest,
+                mtu,
+                scan_size
+            );
+            let expected = 1 + scan_size
+                .saturating_sub(first_payload)
+                .div_ceil(max_payload);
+            assert_eq!(packets.len(), expected, "scan {} at MTU {}", scan_size, mtu);
+
+            // Fragment offsets line up and the scan data reassembles
+            let mut scan = Vec::new();
+            for (i, pkt) in packets.iter().enumerate() {
+                let offset = u32::from_be_bytes([0, pkt[13], pkt[14], pkt[15]]) as usize;
+                assert_eq!(offset, scan.len());
+                let header = RTP_HEADER_SIZE + JPEG_HEADER_SIZE;
+                let header = if i == 0 {
+                    header + qtable_header
+                } else {
+                    header
+                };
+                scan.extend_from_slice(&pkt[header..]);
+            }
+            assert_eq!(scan, &jpeg[scan_start..scan_start + scan_size]);
+        }
+    }
+}
@@ -0,0 +1,6 @@
+fn main() {
+    // The gRPC service stubs are generated from proto/ only when enabled
+    #[cfg(feature = "grpc")]
+    tonic_build::compile_protos("proto/control.proto")
+        .expect("failed to compile proto/control.proto (is protoc installed?)");
+}
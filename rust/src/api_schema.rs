@@ -0,0 +1,333 @@
+//! Machine-readable description of the web API
+//!
+//! The OpenAPI document and the TypeScript declarations are generated from
+//! the same Rust types the handlers serialize, so they can't drift from what
+//! the server actually sends. Served at `/api/schema` and `/api/schema.d.ts`,
+//! and printed by `--api-schema openapi|typescript` for checking into client
+//! projects.
+
+use schemars::gen::{SchemaGenerator, SchemaSettings};
+use serde_json::{json, Map, Value};
+
+use crate::audit::AuditEntry;
+use crate::control::{ControlRequest, ServerMessage};
+use crate::log_buffer::LogLine;
+use crate::pause::PauseMode;
+use crate::schedule::ScheduleOverride;
+use crate::sensors::runner::SensorHealth;
+use crate::streams::StreamInfo;
+use crate::system_monitor::SystemStats;
+use crate::web_server::{CamerasResponse, EffectiveConfig, OkResponse, SessionList, StreamList};
+
+const SCHEMA_REF_PREFIX: &str = "#/components/schemas/";
+
+/// Types published under components/schemas
+fn generate_schemas() -> Map<String, Value> {
+    let mut gen = SchemaGenerator::new(SchemaSettings::openapi3());
+    gen.subschema_for::<StreamList>();
+    gen.subschema_for::<StreamInfo>();
+    gen.subschema_for::<CamerasResponse>();
+    gen.subschema_for::<SessionList>();
+    gen.subschema_for::<EffectiveConfig>();
+    gen.subschema_for::<OkResponse>();
+    gen.subschema_for::<PauseMode>();
+    gen.subschema_for::<ScheduleOverride>();
+    gen.subschema_for::<SystemStats>();
+    gen.subschema_for::<SensorHealth>();
+    gen.subschema_for::<Vec<LogLine>>();
+    gen.subschema_for::<Vec<AuditEntry>>();
+    gen.subschema_for::<ControlRequest>();
+    gen.subschema_for::<ServerMessage<'static>>();
+    gen.take_definitions()
+        .into_iter()
+        .map(|(name, schema)| (name, serde_json::to_value(schema).unwrap_or(Value::Null)))
+        .collect()
+}
+
+fn schema_ref(name: &str) -> Value {
+    json!({ "$ref": format!("{}{}", SCHEMA_REF_PREFIX, name) })
+}
+
+fn json_body(description: &str, schema: Value) -> Value {
+    json!({
+        "description": description,
+        "content": { "application/json": { "schema": schema } },
+    })
+}
+
+fn stream_name_param() -> Value {
+    json!({ "name": "name", "in": "path", "required": true, "schema": { "type": "string" } })
+}
+
+/// A supervised component's state; the type lives in rust-mjpeg-rtp, which
+/// has no JsonSchema derives
+fn component_status_schema() -> Value {
+    json!({
+        "type": "object",
+        "required": ["name", "state", "dependencies", "restarts"],
+        "properties": {
+            "name": { "type": "string" },
+            "state": {
+                "type": "string",
+                "enum": ["pending", "starting", "running", "restarting", "stopped"],
+            },
+            "dependencies": { "type": "array", "items": { "type": "string" } },
+            "restarts": { "type": "integer" },
+            "last_error": { "type": "string", "nullable": true },
+        },
+    })
+}
+
+/// OpenAPI 3.0 document for the REST API. The control WebSocket has no
+/// OpenAPI representation; its messages are under `x-websockets`.
+pub fn openapi() -> Value {
+    let ok = || json_body("Result of the command", schema_ref("OkResponse"));
+    let pause_params = json!([
+        stream_name_param(),
+        { "name": "mode", "in": "query", "schema": schema_ref("PauseMode") },
+    ]);
+
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "rpi-webrtc-streamer",
+            "version": env!("CARGO_PKG_VERSION"),
+        },
+        "paths": {
+            "/api/streams": { "get": {
+                "summary": "Streams by name, with signaling URLs and live state",
+                "responses": { "200": json_body("Streams", schema_ref("StreamList")) },
+            }},
+            "/api/streams/{name}": { "get": {
+                "summary": "One stream",
+                "parameters": [stream_name_param()],
+                "responses": {
+                    "200": json_body("The stream", schema_ref("StreamInfo")),
+                    "404": { "description": "Unknown stream" },
+                },
+            }},
+            "/api/streams/{name}/pause": { "post": {
+                "summary": "Stop sending a stream's video, keeping viewers connected",
+                "parameters": pause_params,
+                "responses": { "200": ok(), "400": ok() },
+            }},
+            "/api/streams/{name}/resume": { "post": {
+                "summary": "Resume a paused stream",
+                "parameters": [stream_name_param()],
+                "responses": { "200": ok(), "400": ok() },
+            }},
+            "/api/streams/{name}/schedule": { "post": {
+                "summary": "Override a stream's schedule: on, off, or auto to follow it again",
+                "parameters": [
+                    stream_name_param(),
+                    { "name": "mode", "in": "query", "schema": schema_ref("ScheduleOverride") },
+                ],
+                "responses": { "200": ok(), "400": ok() },
+            }},
+            "/api/streams/{name}/pip": { "post": {
+                "summary": "Move, resize, show or hide a stream's picture-in-picture inset; \
+                            fractions of the frame, parameters left out are kept",
+                "parameters": [
+                    stream_name_param(),
+                    { "name": "visible", "in": "query", "schema": { "type": "boolean" } },
+                    { "name": "x", "in": "query", "schema": { "type": "number" } },
+                    { "name": "y", "in": "query", "schema": { "type": "number" } },
+                    { "name": "width", "in": "query", "schema": { "type": "number" } },
+                    { "name": "height", "in": "query", "schema": { "type": "number" } },
+                ],
+                "responses": { "200": ok(), "400": ok() },
+            }},
+            "/api/cameras": { "get": {
+                "summary": "Streams plus ICE servers and control channel label, for the web UI",
+                "responses": { "200": json_body("Cameras", schema_ref("CamerasResponse")) },
+            }},
+            "/api/sessions": { "get": {
+                "summary": "Live WebRTC sessions",
+                "responses": { "200": json_body("Sessions", schema_ref("SessionList")) },
+            }},
+            "/api/sessions/{id}/mute": { "post": {
+                "summary": "Stop sending video to one session (/unmute to undo)",
+                "parameters": [{ "name": "id", "in": "path", "required": true, "schema": { "type": "integer" } }],
+                "responses": { "200": ok(), "400": ok() },
+            }},
+            "/api/sessions/{id}/source": { "post": {
+                "summary": "Show another camera's video in one session from its next keyframe, \
+                            without renegotiating (the session's own camera to switch back)",
+                "parameters": [
+                    { "name": "id", "in": "path", "required": true, "schema": { "type": "integer" } },
+                    { "name": "camera", "in": "query", "required": true, "schema": { "type": "string" } },
+                ],
+                "responses": { "200": ok(), "400": ok() },
+            }},
+            "/api/config": { "get": {
+                "summary": "Configuration in effect, secrets redacted, with its hash",
+                "responses": { "200": json_body("Config", schema_ref("EffectiveConfig")) },
+            }},
+            "/api/stats": { "get": {
+                "summary": "Config hash, latest system sample, active cameras, sensor health \
+                            and the in-process MJPEG-RTP cameras' counters",
+                "responses": { "200": json_body("Stats", json!({
+                    "type": "object",
+                    "properties": {
+                        "config_hash": { "type": "string" },
+                        "system": schema_ref("SystemStats"),
+                        "cameras": { "type": "array", "items": { "type": "string" } },
+                        "sensors": { "type": "array", "items": schema_ref("SensorHealth") },
+                        "mjpeg_rtp": {
+                            "type": "object",
+                            "nullable": true,
+                            "description": "Per-camera streamer stats, null unless run with \
+                                            --mode mjpeg-rtp or both",
+                            "additionalProperties": { "type": "object" },
+                        },
+                    },
+                })) },
+            }},
+            "/api/status": { "get": {
+                "summary": "State of each supervised component (web server, sensors, cameras, \
+                            their outputs) and of the in-process MJPEG-RTP streamer's",
+                "responses": { "200": json_body("Status", json!({
+                    "type": "object",
+                    "properties": {
+                        "components": { "type": "array", "items": component_status_schema() },
+                        "mjpeg_rtp": {
+                            "type": "array",
+                            "nullable": true,
+                            "description": "The MJPEG-RTP streamer's components, null unless \
+                                            run with --mode mjpeg-rtp or both",
+                            "items": component_status_schema(),
+                        },
+                    },
+                })) },
+            }},
+            "/api/logs": { "get": {
+                "summary": "Recent log lines (admin token required)",
+                "parameters": [
+                    { "name": "level", "in": "query", "schema": { "type": "string" } },
+                    { "name": "target", "in": "query", "schema": { "type": "string" } },
+                    { "name": "limit", "in": "query", "schema": { "type": "integer" } },
+                ],
+                "responses": { "200": json_body("Log lines", json!({
+                    "type": "array",
+                    "items": schema_ref("LogLine"),
+                })) },
+            }},
+            "/api/audit": { "get": {
+                "summary": "Control commands from the audit log (admin token required); \
+                            format=jsonl exports them as JSON lines",
+                "parameters": [
+                    { "name": "since", "in": "query", "schema": { "type": "integer" } },
+                    { "name": "limit", "in": "query", "schema": { "type": "integer" } },
+                    { "name": "format", "in": "query", "schema": { "type": "string" } },
+                ],
+                "responses": { "200": json_body("Audit entries", json!({
+                    "type": "array",
+                    "items": schema_ref("AuditEntry"),
+                })) },
+            }},
+        },
+        "components": { "schemas": generate_schemas() },
+        "x-websockets": {
+            "/ws/control": {
+                "client": schema_ref("ControlRequest"),
+                "server": schema_ref("ServerMessage"),
+            },
+        },
+    })
+}
+
+/// TypeScript declarations for every schema in [`openapi`]
+pub fn typescript() -> String {
+    let mut out = String::from(
+        "// Generated by rpi_sensor_streamer --api-schema typescript. Do not edit.\n",
+    );
+    for (name, schema) in generate_schemas() {
+        if let Some(description) = schema.get("description").and_then(Value::as_str) {
+            out.push_str(&format!("\n/** {} */", description.replace("*/", "*\\/")));
+        }
+        out.push_str(&format!("\nexport type {} = {};\n", name, ts_type(&schema)));
+    }
+    out
+}
+
+/// TypeScript type expression for one schema
+fn ts_type(schema: &Value) -> String {
+    let base = ts_base_type(schema);
+    if schema.get("nullable").and_then(Value::as_bool) == Some(true) {
+        format!("{} | null", base)
+    } else {
+        base
+    }
+}
+
+fn ts_base_type(schema: &Value) -> String {
+    if let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+        return reference.trim_start_matches(SCHEMA_REF_PREFIX).to_string();
+    }
+    if let Some(values) = schema.get("enum").and_then(Value::as_array) {
+        return values.iter().map(Value::to_string).collect::<Vec<_>>().join(" | ");
+    }
+
+    // Flattened structs come out as properties next to a oneOf, so the parts
+    // are intersected rather than picked from
+    let mut parts = Vec::new();
+    match schema.get("type").and_then(Value::as_str) {
+        Some("string") => parts.push("string".to_string()),
+        Some("integer") | Some("number") => parts.push("number".to_string()),
+        Some("boolean") => parts.push("boolean".to_string()),
+        Some("array") => parts.push(match schema.get("items") {
+            Some(items) => format!("Array<{}>", ts_type(items)),
+            None => "unknown[]".to_string(),
+        }),
+        Some("object") => parts.push(ts_object(schema)),
+        _ if schema.get("properties").is_some() => parts.push(ts_object(schema)),
+        _ => {}
+    }
+    for key in ["oneOf", "anyOf"] {
+        if let Some(variants) = schema.get(key).and_then(Value::as_array) {
+            let union: Vec<_> = variants.iter().map(ts_type).collect();
+            parts.push(format!("({})", union.join(" | ")));
+        }
+    }
+    if let Some(all) = schema.get("allOf").and_then(Value::as_array) {
+        parts.extend(all.iter().map(|v| format!("({})", ts_type(v))));
+    }
+
+    if parts.is_empty() {
+        "unknown".to_string()
+    } else {
+        parts.join(" & ")
+    }
+}
+
+fn ts_object(schema: &Value) -> String {
+    let required: Vec<&str> = schema
+        .get("required")
+        .and_then(Value::as_array)
+        .map(|names| names.iter().filter_map(Value::as_str).collect())
+        .unwrap_or_default();
+
+    let mut fields: Vec<String> = schema
+        .get("properties")
+        .and_then(Value::as_object)
+        .map(|properties| {
+            properties
+                .iter()
+                .map(|(name, property)| {
+                    let optional = if required.contains(&name.as_str()) { "" } else { "?" };
+                    format!("{}{}: {}", name, optional, ts_type(property))
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    match schema.get("additionalProperties") {
+        Some(values @ Value::Object(_)) => fields.push(format!("[key: string]: {}", ts_type(values))),
+        Some(Value::Bool(true)) if fields.is_empty() => return "Record<string, unknown>".to_string(),
+        _ => {}
+    }
+    if fields.is_empty() {
+        return "{}".to_string();
+    }
+    format!("{{ {} }}", fields.join("; "))
+}
@@ -0,0 +1,172 @@
+//! Audit log of control-plane actions
+//!
+//! Every command accepted on /ws/control, the REST control endpoints or gRPC
+//! is appended to `[audit] path`, one JSON object per line: when, who (the
+//! interface, the peer address and whether the admin token was presented),
+//! the command, whether it succeeded, and the state values it changed. The
+//! file is only ever opened for appending; `GET /api/audit` exports it.
+
+use anyhow::{Context, Result};
+use once_cell::sync::Lazy;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::config::AuditConfig;
+
+static LOG: Lazy<Mutex<Option<AuditLog>>> = Lazy::new(|| Mutex::new(None));
+
+struct AuditLog {
+    path: PathBuf,
+    file: File,
+}
+
+/// Where a command came from
+#[derive(Debug, Clone)]
+pub struct Actor {
+    /// "websocket", "rest" or "grpc"
+    pub interface: &'static str,
+    pub peer: Option<String>,
+    /// The admin token was required and presented
+    pub authenticated: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct AuditEntry {
+    pub ts_ms: u64,
+    pub interface: String,
+    pub peer: Option<String>,
+    pub authenticated: bool,
+    /// The command as sent, e.g. `{"cmd": "set-bitrate", ...}`
+    pub command: Value,
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Values the command changed, before and after
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new: Option<Value>,
+}
+
+/// Opens the audit log for appending, when enabled
+pub fn init(config: &AuditConfig) -> Result<()> {
+    if !config.enabled {
+        return Ok(());
+    }
+    let path = PathBuf::from(&config.path);
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .with_context(|| format!("opening audit log {}", path.display()))?;
+    log::info!("Auditing control commands to {}", path.display());
+    *LOG.lock().unwrap() = Some(AuditLog { path, file });
+    Ok(())
+}
+
+pub fn is_enabled() -> bool {
+    LOG.lock().unwrap().is_some()
+}
+
+/// Appends one command to the log. `before` and `after` are snapshots of
+/// what the command acts on; only the fields that differ are recorded.
+pub fn record(
+    actor: &Actor,
+    command: Value,
+    result: &Result<()>,
+    before: Option<Value>,
+    after: Option<Value>,
+) {
+    let mut log = LOG.lock().unwrap();
+    let Some(log) = log.as_mut() else {
+        return;
+    };
+
+    let (old, new) = match (before, after) {
+        (Some(before), Some(after)) => changes(before, after),
+        (before, after) => (before, after),
+    };
+    let entry = AuditEntry {
+        ts_ms: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0),
+        interface: actor.interface.to_string(),
+        peer: actor.peer.clone(),
+        authenticated: actor.authenticated,
+        command,
+        ok: result.is_ok(),
+        error: result.as_ref().err().map(|e| e.to_string()),
+        old,
+        new,
+    };
+
+    // One write per line, so concurrent appends never interleave
+    let line = match serde_json::to_string(&entry) {
+        Ok(json) => json + "\n",
+        Err(e) => {
+            log::error!("Cannot serialize audit entry: {}", e);
+            return;
+        }
+    };
+    if let Err(e) = log.file.write_all(line.as_bytes()) {
+        log::error!("Failed to write audit log {}: {}", log.path.display(), e);
+    }
+}
+
+/// Reduces two snapshots of an object to the fields that changed
+fn changes(before: Value, after: Value) -> (Option<Value>, Option<Value>) {
+    match (before, after) {
+        (Value::Object(before), Value::Object(mut after)) => {
+            let mut old = Map::new();
+            let mut new = Map::new();
+            for (key, value) in before {
+                let changed = after.remove(&key).unwrap_or(Value::Null);
+                if changed != value {
+                    old.insert(key.clone(), value);
+                    new.insert(key, changed);
+                }
+            }
+            for (key, value) in after {
+                old.insert(key.clone(), Value::Null);
+                new.insert(key, value);
+            }
+            if new.is_empty() {
+                (None, None)
+            } else {
+                (Some(Value::Object(old)), Some(Value::Object(new)))
+            }
+        }
+        (before, after) if before == after => (None, None),
+        (before, after) => (Some(before), Some(after)),
+    }
+}
+
+/// Entries at or after `since_ms`, oldest first, at most `limit` of the newest
+pub fn query(since_ms: u64, limit: usize) -> Result<Vec<AuditEntry>> {
+    let path = match LOG.lock().unwrap().as_ref() {
+        Some(log) => log.path.clone(),
+        None => return Ok(Vec::new()),
+    };
+    let file = File::open(&path).with_context(|| format!("opening {}", path.display()))?;
+
+    let mut entries: Vec<AuditEntry> = BufReader::new(file)
+        .lines()
+        .map_while(|line| line.ok())
+        .filter_map(|line| serde_json::from_str::<AuditEntry>(&line).ok())
+        .filter(|entry| entry.ts_ms >= since_ms)
+        .collect();
+    if entries.len() > limit {
+        entries.drain(..entries.len() - limit);
+    }
+    Ok(entries)
+}
@@ -0,0 +1,164 @@
+//! Lidar-triggered quality boost
+//!
+//! While the configured lidar reports something closer than `below-mm`, the
+//! selected cameras stream at a higher bitrate and/or frame rate, starting
+//! with a fresh keyframe. `hold-ms` after the last close reading they drop
+//! back to what they had before. Useful while a robot docks or approaches
+//! an object and the operator needs detail.
+
+use anyhow::{anyhow, Result};
+use gstreamer as gst;
+use gstreamer::prelude::*;
+use gstreamer_video as gst_video;
+use std::collections::HashMap;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::time::{interval, Duration, Instant};
+
+use crate::config::{BoostSensor, Config, QualityBoostConfig};
+use crate::control;
+use crate::debug;
+use crate::governor;
+use crate::sensors::runner;
+
+/// How often an expired boost is noticed
+const CHECK_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Capsfilters pinning the frame rate, from the camera to the encoder
+const FRAMERATE_FILTERS: &[&str] = &["cfilter", "input_capsfilter", "vp8_caps_filter"];
+
+/// What a boosted camera goes back to
+struct Previous {
+    bitrate: Option<u32>,
+    fps: u32,
+}
+
+fn sensor_name(sensor: BoostSensor) -> &'static str {
+    match sensor {
+        BoostSensor::Tof050c => "TOF050C",
+        BoostSensor::Tof400c => "TOF400C",
+    }
+}
+
+/// Watches the lidar's readings and boosts the cameras while it sees
+/// something close. Runs until cancelled.
+pub async fn run_quality_boost(config: Config) {
+    let boost = config.quality_boost.clone();
+    let sensor = sensor_name(boost.sensor);
+    let hold = Duration::from_millis(boost.hold_ms);
+    let mut readings = runner::subscribe();
+    let mut ticker = interval(CHECK_INTERVAL);
+    let mut boosted: HashMap<String, Previous> = HashMap::new();
+    let mut until = Instant::now();
+
+    log::info!(
+        "Quality boost armed: {} below {} mm for {:?}",
+        sensor,
+        boost.below_mm,
+        hold
+    );
+
+    loop {
+        tokio::select! {
+            reading = readings.recv() => match reading {
+                Ok(reading) if reading.sensor == sensor => {
+                    // Error payloads don't parse and never trigger a boost
+                    let Ok(distance) = reading.payload.parse::<u16>() else {
+                        continue;
+                    };
+                    if distance >= boost.below_mm {
+                        continue;
+                    }
+                    until = Instant::now() + hold;
+                    if boosted.is_empty() {
+                        boosted = start_boost(&config, &boost);
+                        if !boosted.is_empty() {
+                            log::info!("{} reads {} mm, boosting stream quality", sensor, distance);
+                        }
+                    }
+                }
+                Ok(_) | Err(RecvError::Lagged(_)) => {}
+                Err(RecvError::Closed) => break,
+            },
+            _ = ticker.tick() => {
+                if !boosted.is_empty() && Instant::now() >= until {
+                    log::info!("Nothing within {} mm, ending quality boost", boost.below_mm);
+                    end_boost(&config, &boost, std::mem::take(&mut boosted));
+                }
+            }
+        }
+    }
+}
+
+fn start_boost(config: &Config, boost: &QualityBoostConfig) -> HashMap<String, Previous> {
+    let states = control::camera_states(config);
+    let mut boosted = HashMap::new();
+    for (name, camera) in config.streams() {
+        if !boost.cameras.is_empty() && !boost.cameras.contains(&name) {
+            continue;
+        }
+        // Cameras without a running pipeline have nothing to boost
+        if debug::find_pipeline(&name).is_none() {
+            continue;
+        }
+        let bitrate = states.iter().find(|s| s.name == name).and_then(|s| s.bitrate);
+        boosted.insert(
+            name.clone(),
+            Previous {
+                bitrate,
+                fps: camera.fps,
+            },
+        );
+        if let Err(e) = apply(&name, boost.bitrate, boost.fps, boost.keyframe) {
+            log::warn!("Failed to boost {}: {}", name, e);
+        }
+    }
+    boosted
+}
+
+/// Puts back whatever the boost changed
+fn end_boost(config: &Config, boost: &QualityBoostConfig, boosted: HashMap<String, Previous>) {
+    for (name, previous) in boosted {
+        let bitrate = boost
+            .bitrate
+            .map(|_| previous.bitrate.unwrap_or(config.webrtc.bitrate));
+        let fps = boost.fps.map(|_| previous.fps);
+        if let Err(e) = apply(&name, bitrate, fps, false) {
+            log::warn!("Failed to restore {} after the boost: {}", name, e);
+        }
+    }
+}
+
+fn apply(camera: &str, bitrate: Option<u32>, fps: Option<u32>, keyframe: bool) -> Result<()> {
+    let pipeline =
+        debug::find_pipeline(camera).ok_or_else(|| anyhow!("unknown camera '{}'", camera))?;
+    if let Some(bitrate) = bitrate {
+        control::set_bitrate(camera, bitrate)?;
+    }
+    if let Some(fps) = fps {
+        // The fps governor's limit wins over the boost
+        set_framerate(&pipeline, governor::limit(camera, fps));
+    }
+    if keyframe {
+        if let Some(encoder) = pipeline.by_name("encoder") {
+            encoder.send_event(
+                gst_video::UpstreamForceKeyUnitEvent::builder()
+                    .all_headers(true)
+                    .build(),
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Rewrites the frame rate of every capsfilter between camera and encoder;
+/// the pipeline renegotiates on the next buffer
+pub(crate) fn set_framerate(pipeline: &gst::Pipeline, fps: u32) {
+    for name in FRAMERATE_FILTERS {
+        let Some(filter) = pipeline.by_name(name) else {
+            continue;
+        };
+        let mut caps = filter.property::<gst::Caps>("caps");
+        caps.make_mut().set("framerate", gst::Fraction::new(fps as i32, 1));
+        filter.set_property("caps", &caps);
+    }
+}
@@ -0,0 +1,449 @@
+use anyhow::{anyhow, Result};
+use futures_util::{SinkExt, StreamExt};
+use gstreamer as gst;
+use gstreamer::prelude::*;
+use once_cell::sync::Lazy;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use tokio::net::TcpStream;
+use tokio::time::{interval, Duration};
+use tokio_tungstenite::tungstenite::handshake::server::{ErrorResponse, Request, Response};
+use tokio_tungstenite::tungstenite::http::StatusCode;
+use tokio_tungstenite::{accept_hdr_async, tungstenite::Message};
+
+use crate::audit::{self, Actor};
+use crate::config::Config;
+use crate::debug;
+use crate::governor::{self, FpsChange};
+use crate::ndi::{self, NdiStatus};
+use crate::pause::{self, PauseMode};
+use crate::pip::{self, PipStatus, PipUpdate};
+use crate::recording;
+use crate::recording_encryption;
+use crate::rtmp::{self, RtmpStatus};
+use crate::schedule::{self, ScheduleOverride, ScheduleStatus};
+use crate::system_monitor;
+use crate::watchdog::{self, RecoveryStatus};
+use crate::webrtc::switch;
+
+// Flip methods applied at runtime, so state reports reflect them rather than
+// the config file.
+static FLIP_OVERRIDES: Lazy<Mutex<HashMap<String, String>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// videoflip `method` nicks accepted by set-flip.
+const FLIP_METHODS: &[&str] = &[
+    "none",
+    "clockwise",
+    "rotate-180",
+    "counterclockwise",
+    "horizontal-flip",
+    "vertical-flip",
+    "upper-left-diagonal",
+    "upper-right-diagonal",
+    "automatic",
+];
+
+/// How often each connection checks for state changes to push.
+pub(crate) const PUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// `{"id": 1, "cmd": "set-bitrate", "camera": "camera1", "bitrate": 1500000}`
+#[derive(Debug, Deserialize, JsonSchema)]
+pub(crate) struct ControlRequest {
+    #[serde(default)]
+    id: Option<u64>,
+    #[serde(flatten)]
+    command: ControlCommand,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(tag = "cmd", rename_all = "kebab-case")]
+pub(crate) enum ControlCommand {
+    /// Encoder target bitrate in bits per second
+    SetBitrate { camera: String, bitrate: u32 },
+    /// videoflip method, e.g. "rotate-180"
+    SetFlip { camera: String, method: String },
+    StartRecording { camera: String },
+    StopRecording { camera: String },
+    /// Encrypt recording segments closed from now on to these age recipients
+    /// ("age1..." or SSH public keys) instead of the configured ones
+    RotateRecordingKey { recipients: Vec<String> },
+    /// Cycle the camera pipeline through NULL back to its previous state
+    RestartPipeline { camera: String },
+    /// Stop sending media while keeping sessions connected
+    Pause {
+        camera: String,
+        #[serde(default)]
+        mode: PauseMode,
+    },
+    Resume { camera: String },
+    /// Override the camera's streaming schedule ("on", "off", or "auto" to
+    /// follow it again)
+    SetSchedule { camera: String, mode: ScheduleOverride },
+    /// Mute or unmute a single WebRTC session (see GET /api/sessions)
+    SetSessionMuted { session: u64, muted: bool },
+    /// Move, resize, show or hide the camera's picture-in-picture inset;
+    /// positions and sizes are fractions of the frame, unset ones are kept
+    SetPip {
+        camera: String,
+        visible: Option<bool>,
+        x: Option<f32>,
+        y: Option<f32>,
+        width: Option<f32>,
+        height: Option<f32>,
+    },
+    /// Show another camera's video in a WebRTC session, from its next
+    /// keyframe and without renegotiating; its own camera switches back
+    SetSessionSource { session: u64, camera: String },
+    GetState,
+}
+
+#[derive(Debug, Serialize, PartialEq, JsonSchema)]
+pub(crate) struct CameraState {
+    pub name: String,
+    pub pipeline_state: String,
+    pub bitrate: Option<u32>,
+    pub flip: Option<String>,
+    pub recording: bool,
+    pub paused: Option<PauseMode>,
+    /// Streaming schedule state, when the camera has one
+    pub schedule: Option<ScheduleStatus>,
+    /// Watchdog state, when recovery is enabled for the camera
+    pub recovery: Option<RecoveryStatus>,
+    /// RTMP push state, when the camera has an ingest target
+    pub rtmp: Option<RtmpStatus>,
+    /// NDI output state, when the camera is an NDI source
+    pub ndi: Option<NdiStatus>,
+    /// Picture-in-picture state, when another camera is inset into it
+    pub pip: Option<PipStatus>,
+    /// Frame rate the fps governor holds the camera at, while the CPU load
+    /// keeps it below the configured one
+    pub governed_fps: Option<u32>,
+}
+
+/// Messages sent to the client: command responses and unsolicited updates.
+#[derive(Debug, Serialize, JsonSchema)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub(crate) enum ServerMessage<'a> {
+    Response {
+        id: Option<u64>,
+        ok: bool,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+    },
+    State {
+        cameras: &'a [CameraState],
+    },
+    System {
+        system: &'a system_monitor::SystemStats,
+    },
+    /// The fps governor changed a camera's frame rate
+    FpsChange {
+        change: &'a FpsChange,
+    },
+}
+
+/// Compares a presented admin token with the configured one in time that
+/// doesn't depend on where they differ, so timing can't reveal a prefix.
+pub fn token_matches(given: &str, expected: &str) -> bool {
+    given.len() == expected.len()
+        && given
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0u8, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+/// Upgrades a `GET /ws/control` connection and serves it until it closes.
+/// When `web.admin-token` is set, the request must carry it as `?token=`
+/// (browsers can't set headers on WebSocket requests).
+pub async fn handle_control_socket(stream: TcpStream, config: Config) -> Result<()> {
+    let expected_token = config.web.admin_token.clone().filter(|t| !t.is_empty());
+    let actor = Actor {
+        interface: "websocket",
+        peer: stream.peer_addr().ok().map(|addr| addr.to_string()),
+        authenticated: expected_token.is_some(),
+    };
+    let ws = accept_hdr_async(stream, |req: &Request, resp: Response| {
+        let Some(expected) = expected_token.as_deref() else {
+            return Ok(resp);
+        };
+        let token = req
+            .uri()
+            .query()
+            .and_then(|q| q.split('&').find_map(|kv| kv.strip_prefix("token=")));
+        if token.is_some_and(|token| token_matches(token, expected)) {
+            Ok(resp)
+        } else {
+            log::warn!("Rejected unauthorized control socket");
+            let mut err = ErrorResponse::new(Some("admin token required".to_string()));
+            *err.status_mut() = StatusCode::UNAUTHORIZED;
+            Err(err)
+        }
+    })
+    .await?;
+
+    log::info!("Control socket connected");
+    let (mut tx, mut rx) = ws.split();
+    let mut ticker = interval(PUSH_INTERVAL);
+    let mut last_cameras: Vec<CameraState> = Vec::new();
+    let mut last_system_ts = 0;
+    let mut fps_changes = governor::subscribe();
+
+    loop {
+        tokio::select! {
+            msg = rx.next() => {
+                let text = match msg {
+                    Some(Ok(Message::Text(text))) => text,
+                    Some(Ok(Message::Close(_))) | None => break,
+                    Some(Ok(_)) => continue,
+                    Some(Err(e)) => return Err(e.into()),
+                };
+
+                let (id, result) = match serde_json::from_str::<ControlRequest>(&text) {
+                    Ok(req) => {
+                        log::info!("Control command: {:?}", req.command);
+                        (req.id, execute(req.command, &config, &actor).await)
+                    }
+                    Err(e) => (None, Err(anyhow!("invalid command: {}", e))),
+                };
+                let response = ServerMessage::Response {
+                    id,
+                    ok: result.is_ok(),
+                    error: result.err().map(|e| e.to_string()),
+                };
+                tx.send(Message::Text(serde_json::to_string(&response)?.into())).await?;
+
+                // Reflect the effect of the command right away
+                let cameras = camera_states(&config);
+                tx.send(Message::Text(serde_json::to_string(&ServerMessage::State { cameras: &cameras })?.into())).await?;
+                last_cameras = cameras;
+            }
+            Ok(change) = fps_changes.recv() => {
+                tx.send(Message::Text(serde_json::to_string(&ServerMessage::FpsChange { change: &change })?.into())).await?;
+            }
+            _ = ticker.tick() => {
+                let cameras = camera_states(&config);
+                if cameras != last_cameras {
+                    tx.send(Message::Text(serde_json::to_string(&ServerMessage::State { cameras: &cameras })?.into())).await?;
+                    last_cameras = cameras;
+                }
+
+                if let Some(system) = system_monitor::latest() {
+                    if system.ts_ms != last_system_ts {
+                        last_system_ts = system.ts_ms;
+                        tx.send(Message::Text(serde_json::to_string(&ServerMessage::System { system: &system })?.into())).await?;
+                    }
+                }
+            }
+        }
+    }
+
+    log::info!("Control socket disconnected");
+    Ok(())
+}
+
+/// Runs a command and records it in the audit log
+pub(crate) async fn execute(command: ControlCommand, config: &Config, actor: &Actor) -> Result<()> {
+    let subject = match AuditSubject::of(&command) {
+        Some(subject) if audit::is_enabled() => subject,
+        _ => return run_authorized(command, config, actor).await,
+    };
+
+    let before = subject.snapshot(config);
+    let sent = serde_json::to_value(&command).unwrap_or_default();
+    let result = run_authorized(command, config, actor).await;
+    audit::record(actor, sent, &result, before, subject.snapshot(config));
+    result
+}
+
+/// What a command acts on, for the audit log's old and new values
+enum AuditSubject {
+    Camera(String),
+    Session(u64),
+    RecordingKey,
+}
+
+impl AuditSubject {
+    /// None for read-only commands, which aren't audited
+    fn of(command: &ControlCommand) -> Option<Self> {
+        Some(match command {
+            ControlCommand::SetSessionMuted { session, .. }
+            | ControlCommand::SetSessionSource { session, .. } => AuditSubject::Session(*session),
+            ControlCommand::RotateRecordingKey { .. } => AuditSubject::RecordingKey,
+            ControlCommand::SetBitrate { camera, .. }
+            | ControlCommand::SetFlip { camera, .. }
+            | ControlCommand::StartRecording { camera }
+            | ControlCommand::StopRecording { camera }
+            | ControlCommand::RestartPipeline { camera }
+            | ControlCommand::Pause { camera, .. }
+            | ControlCommand::Resume { camera }
+            | ControlCommand::SetSchedule { camera, .. }
+            | ControlCommand::SetPip { camera, .. } => AuditSubject::Camera(camera.clone()),
+            ControlCommand::GetState => return None,
+        })
+    }
+
+    fn snapshot(&self, config: &Config) -> Option<serde_json::Value> {
+        match self {
+            AuditSubject::Camera(camera) => camera_states(config)
+                .into_iter()
+                .find(|state| &state.name == camera)
+                .and_then(|state| serde_json::to_value(state).ok()),
+            AuditSubject::Session(id) => pause::sessions()
+                .into_iter()
+                .find(|session| session.id == *id)
+                .map(|session| serde_json::json!({ "muted": session.muted, "source": session.source })),
+            AuditSubject::RecordingKey => Some(serde_json::json!({
+                "recipients": recording_encryption::recipients(&config.recording),
+            })),
+        }
+    }
+}
+
+/// Refuses the commands that stay closed while `web.admin-token` is unset.
+/// Whoever picks the recording key can read every new segment and lock the
+/// owner out of them, so the LAN at large may not.
+async fn run_authorized(command: ControlCommand, config: &Config, actor: &Actor) -> Result<()> {
+    if matches!(command, ControlCommand::RotateRecordingKey { .. }) && !actor.authenticated {
+        return Err(anyhow!("rotate-recording-key requires web.admin-token to be set"));
+    }
+    run_command(command, config).await
+}
+
+async fn run_command(command: ControlCommand, config: &Config) -> Result<()> {
+    match command {
+        ControlCommand::GetState => Ok(()),
+        ControlCommand::SetBitrate { camera, bitrate } => set_bitrate(&camera, bitrate),
+        ControlCommand::SetFlip { camera, method } => {
+            if !FLIP_METHODS.contains(&method.as_str()) {
+                return Err(anyhow!(
+                    "unknown flip method '{}', expected one of {}",
+                    method,
+                    FLIP_METHODS.join(", ")
+                ));
+            }
+            pipeline_element(&camera, "videoflip")?.set_property_from_str("method", &method);
+            FLIP_OVERRIDES.lock().unwrap().insert(camera, method);
+            Ok(())
+        }
+        ControlCommand::StartRecording { camera } => {
+            schedule::ensure_may_stream(&camera)?;
+            let config = config.clone();
+            tokio::task::spawn_blocking(move || recording::start(&camera, &config).map(|_| ())).await?
+        }
+        ControlCommand::StopRecording { camera } => {
+            // Waits for the muxer to finalize the last segment
+            tokio::task::spawn_blocking(move || recording::stop(&camera)).await?
+        }
+        ControlCommand::RotateRecordingKey { recipients } => {
+            recording_encryption::rotate(&config.recording, recipients)
+        }
+        ControlCommand::Pause { camera, mode } => pause::pause(&camera, mode),
+        ControlCommand::Resume { camera } => {
+            schedule::ensure_may_stream(&camera)?;
+            pause::resume(&camera)
+        }
+        ControlCommand::SetSchedule { camera, mode } => schedule::set_override(&camera, mode),
+        ControlCommand::SetPip { camera, visible, x, y, width, height } => {
+            pip::set_layout(&camera, PipUpdate { visible, x, y, width, height })
+        }
+        ControlCommand::SetSessionMuted { session, muted } => pause::set_session_muted(session, muted),
+        ControlCommand::SetSessionSource { session, camera } => {
+            // Waits for the camera's next keyframe
+            tokio::task::spawn_blocking(move || switch::set_source(session, &camera)).await?
+        }
+        ControlCommand::RestartPipeline { camera } => {
+            if recording::is_recording(&camera) {
+                return Err(anyhow!("{} is recording; stop the recording first", camera));
+            }
+            // State changes block until elements settle; keep them off the runtime
+            tokio::task::spawn_blocking(move || restart_pipeline(&camera)).await?
+        }
+    }
+}
+
+/// Sets the encoder target bitrate, in bits per second
+pub(crate) fn set_bitrate(camera: &str, bitrate: u32) -> Result<()> {
+    let encoder = pipeline_element(camera, "encoder")?;
+    if encoder.find_property("target-bitrate").is_some() {
+        // vp8enc: bits per second
+        encoder.set_property("target-bitrate", bitrate.min(i32::MAX as u32) as i32);
+    } else if encoder.find_property("bitrate").is_some() {
+        // x264enc: kbit per second
+        encoder.set_property("bitrate", bitrate / 1000);
+    } else {
+        return Err(anyhow!("encoder of {} has no bitrate property", camera));
+    }
+    Ok(())
+}
+
+/// Cycles a camera's pipeline through NULL back to its previous state,
+/// re-opening the camera. Blocks until the state changes complete.
+pub(crate) fn restart_pipeline(camera: &str) -> Result<()> {
+    let pipeline =
+        debug::find_pipeline(camera).ok_or_else(|| anyhow!("unknown camera '{}'", camera))?;
+    let (_, previous, _) = pipeline.state(gst::ClockTime::ZERO);
+    pipeline.set_state(gst::State::Null)?;
+    if !matches!(previous, gst::State::Null | gst::State::VoidPending) {
+        pipeline.set_state(previous)?;
+    }
+    log::info!("Restarted pipeline of {} ({:?})", camera, previous);
+    Ok(())
+}
+
+fn pipeline_element(camera: &str, element: &str) -> Result<gst::Element> {
+    debug::find_pipeline(camera)
+        .ok_or_else(|| anyhow!("unknown camera '{}'", camera))?
+        .by_name(element)
+        .ok_or_else(|| anyhow!("{} has no {} element", camera, element))
+}
+
+pub(crate) fn camera_states(config: &Config) -> Vec<CameraState> {
+    let overrides = FLIP_OVERRIDES.lock().unwrap().clone();
+    config
+        .streams()
+        .iter()
+        .map(|(name, cam)| {
+            let pipeline = debug::find_pipeline(name);
+            let pipeline_state = pipeline
+                .as_ref()
+                .map(|p| format!("{:?}", p.current_state()))
+                .unwrap_or_else(|| "Unavailable".to_string());
+            let bitrate = pipeline
+                .as_ref()
+                .and_then(|p| p.by_name("encoder"))
+                .and_then(|enc| {
+                    if enc.find_property("target-bitrate").is_some() {
+                        Some(enc.property::<i32>("target-bitrate").max(0) as u32)
+                    } else if enc.find_property("bitrate").is_some() {
+                        Some(enc.property::<u32>("bitrate") * 1000)
+                    } else {
+                        None
+                    }
+                });
+            // Same default as create_video_flip
+            let flip = overrides.get(name).cloned().or_else(|| {
+                pipeline
+                    .as_ref()
+                    .map(|_| cam.flip_method.clone().unwrap_or_else(|| "rotate-180".to_string()))
+            });
+            CameraState {
+                name: name.to_string(),
+                pipeline_state,
+                bitrate,
+                flip,
+                recording: recording::is_recording(name),
+                paused: pause::pause_mode(name),
+                schedule: schedule::status(name),
+                recovery: watchdog::status(name),
+                rtmp: rtmp::status(name),
+                ndi: ndi::status(name),
+                pip: pip::status(name),
+                governed_fps: governor::governed_fps(name),
+            }
+        })
+        .collect()
+}
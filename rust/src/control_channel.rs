@@ -0,0 +1,107 @@
+use once_cell::sync::OnceCell;
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::thread;
+use std::time::Duration;
+
+use crate::config::ControlChannelConfig;
+
+// Messages from every WebRTC client's control data channel funnel into one
+// forwarder thread, which owns the ZMQ socket (ZMQ sockets are not
+// thread-safe) and the MQTT client.
+static FORWARDER: OnceCell<(SyncSender<Vec<u8>>, usize)> = OnceCell::new();
+
+/// Bounded so a stalled broker can't grow memory; stale input is useless anyway.
+const QUEUE_DEPTH: usize = 256;
+
+/// Hands a data channel message to the forwarder. Drops it if the forwarder
+/// isn't running, the message is too large or the queue is full.
+pub fn forward(message: &[u8]) {
+    let Some((tx, max_bytes)) = FORWARDER.get() else {
+        return;
+    };
+    if message.len() > *max_bytes {
+        log::warn!(
+            "Dropping {} byte control message (limit {})",
+            message.len(),
+            max_bytes
+        );
+        return;
+    }
+    if tx.try_send(message.to_vec()).is_err() {
+        log::warn!("Control forwarder queue full, dropping message");
+    }
+}
+
+/// Starts the forwarder thread if the control channel is enabled.
+pub fn start(config: &ControlChannelConfig) -> anyhow::Result<()> {
+    if !config.enabled {
+        return Ok(());
+    }
+
+    let (tx, rx) = mpsc::sync_channel(QUEUE_DEPTH);
+    if FORWARDER.set((tx, config.max_message_bytes)).is_err() {
+        return Ok(()); // already running
+    }
+
+    let context = zmq::Context::new();
+    let publisher = context.socket(zmq::PUB)?;
+    publisher.bind(&config.zmq_address)?;
+    log::info!(
+        "Control channel '{}' forwarding to {} topic '{}'",
+        config.label,
+        config.zmq_address,
+        config.zmq_topic
+    );
+
+    let mqtt = config.mqtt_broker.as_deref().map(|broker| {
+        log::info!("Control channel also publishing to MQTT {} topic '{}'", broker, config.mqtt_topic);
+        connect_mqtt(broker)
+    });
+
+    let zmq_topic = config.zmq_topic.clone();
+    let mqtt_topic = config.mqtt_topic.clone();
+    thread::Builder::new()
+        .name("control-forwarder".to_string())
+        .spawn(move || run_forwarder(rx, publisher, &zmq_topic, mqtt, &mqtt_topic))?;
+    Ok(())
+}
+
+fn run_forwarder(
+    rx: Receiver<Vec<u8>>,
+    publisher: zmq::Socket,
+    zmq_topic: &str,
+    mqtt: Option<rumqttc::Client>,
+    mqtt_topic: &str,
+) {
+    while let Ok(message) = rx.recv() {
+        if let Err(e) = publisher.send_multipart([zmq_topic.as_bytes(), message.as_slice()], 0) {
+            log::error!("Failed to publish control message on '{}': {}", zmq_topic, e);
+        }
+        if let Some(ref client) = mqtt {
+            if let Err(e) = client.try_publish(mqtt_topic, rumqttc::QoS::AtMostOnce, false, message) {
+                log::warn!("Failed to publish control message to MQTT: {}", e);
+            }
+        }
+    }
+}
+
+/// MQTT client whose event loop runs on its own thread and reconnects on error.
+fn connect_mqtt(broker: &str) -> rumqttc::Client {
+    let (host, port) = match broker.rsplit_once(':') {
+        Some((host, port)) => (host, port.parse().unwrap_or(1883)),
+        None => (broker, 1883),
+    };
+    let mut options = rumqttc::MqttOptions::new("rpi-sensor-streamer-control", host, port);
+    options.set_keep_alive(Duration::from_secs(10));
+
+    let (client, mut connection) = rumqttc::Client::new(options, QUEUE_DEPTH);
+    thread::spawn(move || {
+        for event in connection.iter() {
+            if let Err(e) = event {
+                log::warn!("MQTT connection error: {}", e);
+                thread::sleep(Duration::from_secs(1));
+            }
+        }
+    });
+    client
+}
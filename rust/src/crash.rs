@@ -0,0 +1,98 @@
+use once_cell::sync::{Lazy, OnceCell};
+use std::backtrace::Backtrace;
+use std::fs;
+use std::panic::PanicHookInfo;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::config::Config;
+use crate::debug;
+use crate::log_buffer;
+
+// Everything the panic hook needs is captured up front: once we're panicking,
+// the config may not be reachable and other locks may be poisoned.
+static CRASH_DIR: OnceCell<PathBuf> = OnceCell::new();
+static REDACTED_CONFIG: OnceCell<String> = OnceCell::new();
+static LAST_STATS: Lazy<Mutex<Option<serde_json::Value>>> = Lazy::new(|| Mutex::new(None));
+
+/// Remember the latest stats snapshot for inclusion in a crash bundle.
+pub fn update_stats_snapshot(stats: serde_json::Value) {
+    if let Ok(mut last) = LAST_STATS.lock() {
+        *last = Some(stats);
+    }
+}
+
+/// Install a panic hook that writes a diagnostic bundle to
+/// `<crash-dir>/crash-<unix-ts>/` and then aborts the process.
+///
+/// Tokio would otherwise swallow the panic inside the failing task and leave
+/// a half-working process behind, with no console to report it on.
+pub fn install_panic_hook(config: &Config) {
+    let _ = CRASH_DIR.set(PathBuf::from(&config.diagnostics.crash_dir));
+    let _ = REDACTED_CONFIG.set(
+        toml::to_string_pretty(&config.redacted())
+            .unwrap_or_else(|e| format!("failed to serialize config: {}", e)),
+    );
+
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        match write_crash_bundle(info) {
+            Ok(dir) => eprintln!("Crash bundle written to {}", dir.display()),
+            Err(e) => eprintln!("Failed to write crash bundle: {}", e),
+        }
+        default_hook(info);
+        std::process::abort();
+    }));
+
+    log::info!("Panic hook installed, crash bundles go to {}", config.diagnostics.crash_dir);
+}
+
+fn write_crash_bundle(info: &PanicHookInfo<'_>) -> std::io::Result<PathBuf> {
+    let base = CRASH_DIR
+        .get()
+        .cloned()
+        .unwrap_or_else(|| PathBuf::from("/var/log/rpi-streamer"));
+    let ts = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let dir = base.join(format!("crash-{}", ts));
+    fs::create_dir_all(&dir)?;
+
+    let thread = std::thread::current();
+    let panic_text = format!(
+        "thread '{}' panicked at {}\n\nbacktrace:\n{}\n",
+        thread.name().unwrap_or("<unnamed>"),
+        info,
+        Backtrace::force_capture()
+    );
+    write_file(&dir, "panic.txt", &panic_text);
+
+    if let Some(config) = REDACTED_CONFIG.get() {
+        write_file(&dir, "config.toml", config);
+    }
+
+    // try_lock: the panicking thread may itself hold the stats lock
+    if let Ok(last) = LAST_STATS.try_lock() {
+        if let Some(stats) = last.as_ref() {
+            write_file(&dir, "stats.json", &stats.to_string());
+        }
+    }
+
+    if let Some(logs) = log_buffer::try_dump() {
+        write_file(&dir, "logs.txt", &logs);
+    }
+
+    for (camera, dot) in debug::try_pipeline_dots() {
+        write_file(&dir, &format!("pipeline-{}.dot", camera), &dot);
+    }
+
+    Ok(dir)
+}
+
+fn write_file(dir: &Path, name: &str, contents: &str) {
+    if let Err(e) = fs::write(dir.join(name), contents) {
+        eprintln!("Failed to write {}: {}", name, e);
+    }
+}
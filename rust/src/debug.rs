@@ -0,0 +1,279 @@
+use anyhow::Result;
+use gstreamer as gst;
+use gstreamer::prelude::*;
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::process::Stdio;
+use std::sync::Mutex;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+use crate::config::{CameraConfig, Config, HorizonMode};
+use crate::recording;
+
+// Registry of live camera pipelines so the web server can inspect them
+// without holding a reference to each camera task's state.
+static PIPELINES: Lazy<Mutex<HashMap<String, gst::Pipeline>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+pub fn register_pipeline(camera: &str, pipeline: &gst::Pipeline) {
+    log::debug!("Registering pipeline for {} in debug registry", camera);
+    PIPELINES
+        .lock()
+        .unwrap()
+        .insert(camera.to_string(), pipeline.clone());
+}
+
+pub fn unregister_pipeline(camera: &str) {
+    PIPELINES.lock().unwrap().remove(camera);
+}
+
+pub fn registered_cameras() -> Vec<String> {
+    let mut names: Vec<String> = PIPELINES.lock().unwrap().keys().cloned().collect();
+    names.sort();
+    names
+}
+
+pub fn find_pipeline(camera: &str) -> Option<gst::Pipeline> {
+    PIPELINES.lock().unwrap().get(camera).cloned()
+}
+
+/// DOT graphs of all registered pipelines, without blocking on the registry
+/// lock (safe to call from a panic hook).
+pub fn try_pipeline_dots() -> Vec<(String, String)> {
+    match PIPELINES.try_lock() {
+        Ok(pipelines) => pipelines
+            .iter()
+            .map(|(camera, pipeline)| (camera.clone(), pipeline_dot(pipeline)))
+            .collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct PadReport {
+    pub name: String,
+    pub direction: String,
+    pub linked: bool,
+    pub caps: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ElementReport {
+    pub name: String,
+    pub factory: Option<String>,
+    pub state: String,
+    pub pending_state: String,
+    pub pads: Vec<PadReport>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PipelineReport {
+    pub camera: String,
+    pub state: String,
+    pub elements: Vec<ElementReport>,
+    pub dot: String,
+}
+
+/// Render the pipeline graph as DOT (all details: caps, states, params).
+pub fn pipeline_dot(pipeline: &gst::Pipeline) -> String {
+    pipeline.debug_to_dot_data(gst::DebugGraphDetails::all()).to_string()
+}
+
+/// Render DOT to SVG using the Graphviz `dot` binary, if it is installed.
+/// Awaits `dot` without blocking the runtime, since large graphs take a while.
+pub async fn dot_to_svg(dot: &str) -> Result<String> {
+    let mut child = Command::new("dot")
+        .arg("-Tsvg")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| anyhow::anyhow!("Graphviz 'dot' not available: {}", e))?;
+
+    let mut stdin = child
+        .stdin
+        .take()
+        .ok_or_else(|| anyhow::anyhow!("Failed to open stdin of 'dot'"))?;
+    stdin.write_all(dot.as_bytes()).await?;
+    drop(stdin);
+
+    let output = child.wait_with_output().await?;
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(
+            "'dot' failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+fn element_report(element: &gst::Element) -> ElementReport {
+    let (_, current, pending) = element.state(gst::ClockTime::ZERO);
+
+    let pads = element
+        .pads()
+        .iter()
+        .map(|pad| PadReport {
+            name: pad.name().to_string(),
+            direction: format!("{:?}", pad.direction()),
+            linked: pad.is_linked(),
+            caps: pad.current_caps().map(|c| c.to_string()),
+        })
+        .collect();
+
+    ElementReport {
+        name: element.name().to_string(),
+        factory: element.factory().map(|f| f.name().to_string()),
+        state: format!("{:?}", current),
+        pending_state: format!("{:?}", pending),
+        pads,
+    }
+}
+
+/// Collect element states, negotiated pad caps and the DOT graph for a camera.
+pub fn pipeline_report(camera: &str) -> Option<PipelineReport> {
+    let pipeline = find_pipeline(camera)?;
+
+    let mut elements = Vec::new();
+    for element in pipeline.iterate_recurse().into_iter().flatten() {
+        elements.push(element_report(&element));
+    }
+    elements.sort_by(|a, b| a.name.cmp(&b.name));
+
+    Some(PipelineReport {
+        camera: camera.to_string(),
+        state: format!("{:?}", pipeline.current_state()),
+        elements,
+        dot: pipeline_dot(&pipeline),
+    })
+}
+
+/// A GStreamer element the streamer builds pipelines from
+#[derive(Debug, Clone, Copy)]
+pub struct NeededElement {
+    pub element: &'static str,
+    pub used_for: &'static str,
+    /// Whether the config uses it; without a config, the elements every
+    /// setup uses and both encoders
+    pub needed: bool,
+}
+
+/// Every element the streamer may create, and whether `config` needs it
+pub fn needed_elements(config: Option<&Config>) -> Vec<NeededElement> {
+    let uses = |f: &dyn Fn(&Config) -> bool| config.is_some_and(f);
+    let any_camera = |f: &dyn Fn(&CameraConfig) -> bool| {
+        uses(&|c| c.streams().iter().any(|(_, cam)| f(cam)))
+    };
+    let codec = |name: &str| config.map_or(true, |c| c.video.codec.eq_ignore_ascii_case(name));
+    let (vp8, h264) = (codec("vp8"), codec("h264"));
+    let libcamera = config.map_or(true, |c| c.streams().iter().any(|(_, cam)| cam.rtp_input.is_none()));
+    // Recordings are started on demand, in the container the config names
+    let muxer = config.and_then(|c| recording::muxer_for(&c.video.codec, &c.recording.format).ok());
+    let muxes = |name: &str| muxer.map_or(config.is_none(), |(muxer, _)| muxer == name);
+    let rtp_input = any_camera(&|cam| cam.rtp_input.is_some());
+    let rtmp = uses(&|c| c.rtmp.enabled);
+
+    [
+        ("libcamerasrc", "camera capture", libcamera),
+        ("capsfilter", "camera pipelines", true),
+        ("queue", "camera pipelines", true),
+        ("videoconvert", "camera pipelines", true),
+        ("videoscale", "camera pipelines", true),
+        ("videoflip", "camera pipelines", true),
+        ("tee", "camera pipelines", true),
+        ("fakesink", "camera pipelines", true),
+        ("videobalance", "pausing to black", true),
+        ("webrtcbin", "WebRTC", true),
+        ("nicesrc", "WebRTC ICE", true),
+        ("dtlssrtpenc", "WebRTC DTLS", true),
+        ("srtpenc", "WebRTC SRTP", true),
+        ("rtpbin", "WebRTC RTP sessions", true),
+        ("input-selector", "switching a session's camera", true),
+        ("vp8enc", "VP8 encoding", vp8),
+        ("rtpvp8pay", "VP8 encoding", vp8),
+        ("x264enc", "H.264 encoding", h264),
+        ("h264parse", "H.264 encoding", h264),
+        ("rtph264pay", "H.264 encoding", h264),
+        ("appsrc", "outputs off the camera tees", true),
+        ("appsink", "outputs off the camera tees", true),
+        ("jpegenc", "MJPEG-RTP camera taps", true),
+        ("videorate", "MJPEG-RTP camera taps and inputs", true),
+        ("splitmuxsink", "recording", true),
+        ("filesink", "recording", true),
+        ("mp4mux", "recording to mp4", muxes("mp4mux")),
+        ("matroskamux", "recording to mkv", muxes("matroskamux")),
+        ("flvmux", "RTMP push", rtmp),
+        ("rtmp2sink", "RTMP push", rtmp),
+        ("ndisink", "NDI output", uses(&|c| c.ndi.enabled)),
+        ("udpsrc", "MJPEG-RTP inputs", rtp_input),
+        ("rtpjitterbuffer", "MJPEG-RTP inputs", rtp_input),
+        ("rtpjpegdepay", "MJPEG-RTP inputs", rtp_input),
+        ("jpegdec", "MJPEG-RTP inputs", rtp_input),
+        (
+            "textoverlay",
+            "watermark and horizon overlay",
+            any_camera(&|cam| cam.watermark.enabled || cam.horizon.mode == HorizonMode::Overlay),
+        ),
+        ("rotate", "horizon levelling", any_camera(&|cam| cam.horizon.mode == HorizonMode::Rotate)),
+        ("videocrop", "stabilization", any_camera(&|cam| cam.stabilization.enabled)),
+        ("compositor", "picture-in-picture", any_camera(&|cam| cam.pip.source.is_some())),
+    ]
+    .into_iter()
+    .map(|(element, used_for, needed)| NeededElement { element, used_for, needed })
+    .collect()
+}
+
+#[derive(Debug, Serialize)]
+pub struct ElementAvailability {
+    pub element: &'static str,
+    pub used_for: &'static str,
+    pub needed: bool,
+    pub available: bool,
+    pub plugin: Option<String>,
+    pub plugin_version: Option<String>,
+    /// Distribution package the plugin came from, as it names itself
+    pub package: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GstreamerReport {
+    /// e.g. "GStreamer 1.22.0"
+    pub version: String,
+    pub elements: Vec<ElementAvailability>,
+    /// Elements the config needs that aren't installed
+    pub missing: Vec<&'static str>,
+}
+
+/// GStreamer version and which of the elements the streamer relies on are
+/// installed, from which plugin.
+pub fn gstreamer_report(config: &Config) -> GstreamerReport {
+    let elements: Vec<ElementAvailability> = needed_elements(Some(config))
+        .into_iter()
+        .map(|needed| {
+            let factory = gst::ElementFactory::find(needed.element);
+            let plugin = factory.as_ref().and_then(|f| f.plugin());
+            ElementAvailability {
+                element: needed.element,
+                used_for: needed.used_for,
+                needed: needed.needed,
+                available: factory.is_some(),
+                plugin: plugin.as_ref().map(|p| p.plugin_name().to_string()),
+                plugin_version: plugin.as_ref().map(|p| p.version().to_string()),
+                package: plugin.as_ref().map(|p| p.package().to_string()),
+            }
+        })
+        .collect();
+    let missing = elements
+        .iter()
+        .filter(|e| e.needed && !e.available)
+        .map(|e| e.element)
+        .collect();
+
+    GstreamerReport {
+        version: gst::version_string().to_string(),
+        elements,
+        missing,
+    }
+}
@@ -0,0 +1,227 @@
+use gstreamer as gst;
+use gstreamer::prelude::*;
+use rust_mjpeg_rtp::capture;
+use std::net::{IpAddr, Ipv4Addr, TcpListener, UdpSocket};
+
+use crate::config::{self, Config};
+use crate::debug;
+
+// `--doctor`: checks everything the streamer needs before it starts and
+// prints one line per check. Run it while the streamer is stopped, otherwise
+// its ports and cameras show up as busy.
+
+enum Status {
+    Pass,
+    Warn,
+    Fail,
+}
+
+struct Report {
+    failures: usize,
+}
+
+impl Report {
+    fn line(&mut self, status: Status, what: &str, detail: impl AsRef<str>) {
+        let tag = match status {
+            Status::Pass => "PASS",
+            Status::Warn => "WARN",
+            Status::Fail => {
+                self.failures += 1;
+                "FAIL"
+            }
+        };
+        println!("[{}] {:<32} {}", tag, what, detail.as_ref());
+    }
+
+    fn check(&mut self, what: &str, result: Result<String, String>) {
+        match result {
+            Ok(detail) => self.line(Status::Pass, what, detail),
+            Err(detail) => self.line(Status::Fail, what, detail),
+        }
+    }
+}
+
+/// Runs every check and returns whether all of them passed.
+/// GStreamer must already be initialised.
+pub fn run(web_port: u16, base_port: u16) -> bool {
+    let mut report = Report { failures: 0 };
+
+    println!("rpi_sensor_streamer {} self-test", env!("CARGO_PKG_VERSION"));
+
+    let config = match config::load_config() {
+        Ok(config) => {
+            report.line(Status::Pass, "config.toml", "parsed");
+            Some(config)
+        }
+        Err(e) => {
+            report.line(Status::Fail, "config.toml", e.to_string());
+            None
+        }
+    };
+
+    check_plugins(&mut report, config.as_ref());
+
+    if let Some(ref config) = config {
+        for (name, cam) in config.streams() {
+            match cam.rtp_input {
+                Some(ref input) => report.check(
+                    &format!("{} (MJPEG-RTP on UDP {})", name, input.port),
+                    check_udp_port(&input.address, input.port),
+                ),
+                None => {
+                    report.check(&format!("{} ({})", name, cam.device), check_camera(&cam.device))
+                }
+            }
+        }
+    }
+
+    let mut ports = vec![
+        ("web server", web_port),
+        ("camera 1 signaling", base_port),
+        ("camera 2 signaling", base_port.wrapping_add(1)),
+    ];
+    if let Some(ref config) = config {
+        if let Some(port) = zmq_port(&config.zeromq.data_publisher_address) {
+            ports.push(("ZMQ sensor publisher", port));
+        }
+        if config.control_channel.enabled {
+            if let Some(port) = zmq_port(&config.control_channel.zmq_address) {
+                ports.push(("ZMQ control publisher", port));
+            }
+        }
+    }
+    for (what, port) in ports {
+        report.check(&format!("port {} ({})", port, what), check_port(port));
+    }
+
+    if let Some(ref config) = config {
+        check_i2c(&mut report, config);
+        if config.recording.encryption.enabled {
+            report.check("age (recording encryption)", check_age());
+        }
+    }
+
+    println!();
+    if report.failures == 0 {
+        println!("All checks passed");
+    } else {
+        println!("{} check(s) failed", report.failures);
+    }
+    report.failures == 0
+}
+
+fn check_plugins(report: &mut Report, config: Option<&Config>) {
+    // Elements for features the config leaves off are only reported as a
+    // warning when missing
+    for needed in debug::needed_elements(config) {
+        check_element(report, needed);
+    }
+}
+
+fn check_element(report: &mut Report, needed: debug::NeededElement) {
+    let what = format!("GStreamer element {}", needed.element);
+    match gst::ElementFactory::find(needed.element) {
+        Some(factory) => {
+            let plugin = factory
+                .plugin()
+                .map(|p| p.plugin_name().to_string())
+                .unwrap_or_default();
+            report.line(Status::Pass, &what, format!("plugin {}", plugin));
+        }
+        None if !needed.needed => report.line(
+            Status::Warn,
+            &what,
+            format!("missing (only used for {})", needed.used_for),
+        ),
+        None => report.line(Status::Fail, &what, format!("missing (needed for {})", needed.used_for)),
+    }
+}
+
+// Cameras are libcamera names, so the check opens the camera through
+// libcamerasrc; that also fails if another process holds it, which is
+// then named
+fn check_camera(device: &str) -> Result<String, String> {
+    let src = gst::ElementFactory::make("libcamerasrc")
+        .property("camera-name", device)
+        .build()
+        .map_err(|_| "libcamerasrc unavailable, cannot check".to_string())?;
+
+    let result = src.set_state(gst::State::Ready);
+    let _ = src.set_state(gst::State::Null);
+    result.map(|_| "opened".to_string()).map_err(|_| {
+        // libcamera in this process has the media nodes open too
+        let mut holders = capture::device_holders(device);
+        holders.retain(|holder| !holder.is_self());
+        if holders.is_empty() {
+            "cannot be opened (missing, or in use by another process)".to_string()
+        } else {
+            format!("cannot be opened, {}", capture::describe_holders(&holders))
+        }
+    })
+}
+
+fn check_udp_port(address: &str, port: u16) -> Result<String, String> {
+    // A multicast group is joined rather than bound
+    let address = match address.parse::<IpAddr>() {
+        Ok(ip) if !ip.is_multicast() => ip,
+        _ => Ipv4Addr::UNSPECIFIED.into(),
+    };
+    UdpSocket::bind((address, port))
+        .map(|_| "free".to_string())
+        .map_err(|e| e.to_string())
+}
+
+fn check_port(port: u16) -> Result<String, String> {
+    TcpListener::bind(("0.0.0.0", port))
+        .map(|_| "free".to_string())
+        .map_err(|e| e.to_string())
+}
+
+fn check_age() -> Result<String, String> {
+    let output = std::process::Command::new("age")
+        .arg("--version")
+        .output()
+        .map_err(|e| format!("not found: {}", e))?;
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+// "tcp://host:port" -> port; other transports have no port to check
+fn zmq_port(address: &str) -> Option<u16> {
+    address.strip_prefix("tcp://")?.rsplit(':').next()?.parse().ok()
+}
+
+fn check_i2c(report: &mut Report, config: &Config) {
+    let mut buses = vec![
+        config.lidar_tof400c.i2c_bus,
+        config.lidar_tof050c.i2c_bus,
+        config.imu_1.i2c_bus,
+    ];
+    buses.sort_unstable();
+    buses.dedup();
+
+    for bus in buses {
+        report.check(
+            &format!("I2C bus {}", bus),
+            rppal::i2c::I2c::with_bus(bus)
+                .map(|_| format!("/dev/i2c-{} opened", bus))
+                .map_err(|e| e.to_string()),
+        );
+    }
+
+    // The lidars share 0x29 until their enable pins are sequenced at startup,
+    // so only the IMU can be probed here
+    let imu = &config.imu_1;
+    report.check(
+        &format!("IMU at {:#04x} on bus {}", imu.address, imu.i2c_bus),
+        probe_i2c(imu.i2c_bus, imu.address),
+    );
+}
+
+fn probe_i2c(bus: u8, address: u8) -> Result<String, String> {
+    let mut i2c = rppal::i2c::I2c::with_bus(bus).map_err(|e| e.to_string())?;
+    i2c.set_slave_address(address as u16).map_err(|e| e.to_string())?;
+    let mut byte = [0u8; 1];
+    i2c.read(&mut byte)
+        .map(|_| "responds".to_string())
+        .map_err(|e| format!("no response: {}", e))
+}
@@ -0,0 +1,194 @@
+//! Gyro-based electronic image stabilization
+//!
+//! Built with `--features eis`. A stabilized camera's pipeline crops every
+//! frame by `margin-percent` on each side (`eis_crop`, a videocrop in front
+//! of the scaler). This module integrates the IMU's gyro rates into a yaw
+//! and pitch, low-pass filters them into the path the camera is meant to
+//! follow, and before each frame moves the crop window by the difference.
+//! Shake is cancelled while deliberate pans come through. The correction is
+//! only as fresh as the gyro samples, so poll the IMU fast (e.g.
+//! `interval-ms = 5` under `[imu-1]`).
+
+use anyhow::{anyhow, Result};
+use gstreamer as gst;
+use gstreamer::prelude::*;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::config::{CameraConfig, StabilizationConfig};
+use crate::debug;
+use crate::sensors::icm20948::{Axis, ImuData};
+use crate::sensors::runner;
+
+const IMU_SENSOR: &str = "IMU1";
+
+/// How often a (re)created pipeline is looked for
+const ATTACH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Longest gap between gyro samples that is integrated; longer ones (e.g.
+/// while the IMU re-initializes) would turn one stale rate into a jump
+const MAX_STEP_SECS: f32 = 0.1;
+
+/// Yaw and pitch of the camera and of the smoothed path it should follow
+#[derive(Debug, Default)]
+struct Stabilizer {
+    /// Integrated gyro, degrees
+    angle: [f32; 2],
+    smoothed: [f32; 2],
+    last_sample: Option<Instant>,
+}
+
+impl Stabilizer {
+    /// Integrates one gyro sample (degrees per second)
+    fn update(&mut self, rates: [f32; 2], now: Instant, smoothing_secs: f32) {
+        if let Some(last) = self.last_sample {
+            let dt = now.duration_since(last).as_secs_f32().min(MAX_STEP_SECS);
+            let alpha = dt / (smoothing_secs + dt);
+            for axis in 0..2 {
+                self.angle[axis] += rates[axis] * dt;
+                self.smoothed[axis] += (self.angle[axis] - self.smoothed[axis]) * alpha;
+            }
+        }
+        self.last_sample = Some(now);
+    }
+
+    /// Rotation that undoes the shake, degrees
+    fn correction(&self) -> [f32; 2] {
+        [self.smoothed[0] - self.angle[0], self.smoothed[1] - self.angle[1]]
+    }
+}
+
+/// Turns corrections into videocrop margins for one frame size
+#[derive(Debug, Clone, Copy)]
+struct Window {
+    /// Crop on each side when centered; even, like every crop value, so
+    /// subsampled chroma planes line up
+    margin: [i32; 2],
+    focal_px: f32,
+}
+
+impl Window {
+    fn new(width: u32, height: u32, config: &StabilizationConfig) -> Self {
+        let share = config.margin_percent.clamp(0.0, 45.0) / 100.0;
+        let margin = |size: u32| ((size as f32 * share) as i32) & !1;
+        let half_fov = (config.hfov_deg.clamp(1.0, 179.0) / 2.0).to_radians();
+        Self {
+            margin: [margin(width), margin(height)],
+            focal_px: width as f32 / 2.0 / half_fov.tan(),
+        }
+    }
+
+    /// (left, right, top, bottom) crop for a correction
+    fn crop(&self, correction: [f32; 2]) -> [i32; 4] {
+        let shift = |degrees: f32, margin: i32| {
+            let px = (self.focal_px * degrees.to_radians().tan()).round() as i32;
+            px.clamp(-margin, margin) & !1
+        };
+        let dx = shift(correction[0], self.margin[0]);
+        let dy = shift(correction[1], self.margin[1]);
+        [
+            self.margin[0] + dx,
+            self.margin[0] - dx,
+            self.margin[1] + dy,
+            self.margin[1] - dy,
+        ]
+    }
+}
+
+/// Stabilizes `camera` from the IMU's gyro readings until cancelled. The
+/// camera's pipeline is looked up by name, so it may come and go.
+pub async fn run_stabilizer(camera: String, cam: CameraConfig) {
+    let config = cam.stabilization;
+    let axes = (Axis::parse(&config.yaw_axis), Axis::parse(&config.pitch_axis));
+    let (yaw, pitch) = match axes {
+        (Ok(yaw), Ok(pitch)) => (yaw, pitch),
+        (Err(e), _) | (_, Err(e)) => {
+            log::error!("Stabilization of {} disabled: {}", camera, e);
+            return;
+        }
+    };
+    let smoothing_secs = config.smoothing_ms as f32 / 1000.0;
+    let window = Window::new(cam.width, cam.height, &config);
+    let stabilizer = Arc::new(Mutex::new(Stabilizer::default()));
+
+    let mut readings = runner::subscribe();
+    let mut ticker = tokio::time::interval(ATTACH_INTERVAL);
+    let mut attached: Option<gst::Pipeline> = None;
+    log::info!(
+        "Stabilizing {} with a {}x{} px margin",
+        camera,
+        window.margin[0],
+        window.margin[1]
+    );
+
+    loop {
+        tokio::select! {
+            reading = readings.recv() => match reading {
+                Ok(reading) if reading.sensor == IMU_SENSOR => {
+                    // Error payloads aren't IMU data and are skipped
+                    let Ok(data) = serde_json::from_str::<ImuData>(&reading.payload) else {
+                        continue;
+                    };
+                    let rates = [yaw.of(&data.gyro), pitch.of(&data.gyro)];
+                    stabilizer.lock().unwrap().update(rates, Instant::now(), smoothing_secs);
+                }
+                Ok(_) | Err(RecvError::Lagged(_)) => {}
+                Err(RecvError::Closed) => break,
+            },
+            _ = ticker.tick() => {
+                let Some(pipeline) = debug::find_pipeline(&camera) else {
+                    continue;
+                };
+                if attached.as_ref() == Some(&pipeline) {
+                    continue;
+                }
+                match attach(&pipeline, stabilizer.clone(), window) {
+                    Ok(()) => log::info!("Stabilization attached to {}", camera),
+                    Err(e) => log::warn!("Cannot stabilize {}: {}", camera, e),
+                }
+                attached = Some(pipeline);
+            }
+        }
+    }
+}
+
+/// Moves the pipeline's crop window before every frame
+fn attach(
+    pipeline: &gst::Pipeline,
+    stabilizer: Arc<Mutex<Stabilizer>>,
+    window: Window,
+) -> Result<()> {
+    let crop = pipeline
+        .by_name("eis_crop")
+        .ok_or_else(|| anyhow!("pipeline has no eis_crop element"))?;
+    let pad = crop
+        .static_pad("sink")
+        .ok_or_else(|| anyhow!("eis_crop has no sink pad"))?;
+
+    let initial = window.crop([0.0, 0.0]);
+    set_crop(&crop, initial);
+    // The probe is Fn and may run on any streaming thread
+    let current = Mutex::new(initial);
+    let crop = crop.downgrade();
+    pad.add_probe(gst::PadProbeType::BUFFER, move |_, _| {
+        let next = window.crop(stabilizer.lock().unwrap().correction());
+        let mut current = current.lock().unwrap();
+        // Every change renegotiates videocrop, so skip unchanged windows
+        if next != *current {
+            if let Some(crop) = crop.upgrade() {
+                set_crop(&crop, next);
+            }
+            *current = next;
+        }
+        gst::PadProbeReturn::Ok
+    });
+    Ok(())
+}
+
+fn set_crop(crop: &gst::Element, [left, right, top, bottom]: [i32; 4]) {
+    crop.set_property("left", left);
+    crop.set_property("right", right);
+    crop.set_property("top", top);
+    crop.set_property("bottom", bottom);
+}
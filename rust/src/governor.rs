@@ -0,0 +1,227 @@
+//! CPU-driven frame rate governor
+//!
+//! With `[fps-governor]` enabled, the system monitor's CPU usage is watched.
+//! Once it has stayed above `high-cpu-percent` for `sustain-ms`, the governed
+//! cameras lose `step-fps` frames per second, down to `min-fps`; once it has
+//! stayed below `low-cpu-percent` as long, they get a step back, up to their
+//! configured rate. The rate is changed like the quality boost does it, by
+//! rewriting the capsfilters from camera to encoder, so libcamera captures
+//! with a longer frame duration (an MJPEG-RTP input's videorate drops frames)
+//! instead of frames piling up in the queues. A hot, throttled unit then
+//! sends fewer frames on time rather than every frame late.
+//!
+//! Each change is logged, pushed as an `fps-change` message on `/ws/control`
+//! and shows in the camera's state as `governed_fps`.
+
+use anyhow::{bail, Result};
+use gstreamer as gst;
+use once_cell::sync::Lazy;
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::broadcast;
+use tokio::time::{interval, Duration, Instant};
+
+use crate::boost;
+use crate::config::{Config, FpsGovernorConfig};
+use crate::debug;
+use crate::system_monitor;
+
+/// How often the latest CPU sample is looked at
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+// Frame rate each governed camera is held at, while below its configured
+// one. Shared with the camera state and the quality boost.
+static LIMITS: Lazy<Mutex<HashMap<String, u32>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+static CHANGES: Lazy<broadcast::Sender<FpsChange>> = Lazy::new(|| broadcast::channel(16).0);
+
+/// One step of the governor on one camera
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub(crate) struct FpsChange {
+    pub ts_ms: u64,
+    pub camera: String,
+    pub previous_fps: u32,
+    pub fps: u32,
+    /// The CPU usage that caused it
+    pub cpu_usage_percent: f32,
+}
+
+/// Frame rate changes as they are made
+pub(crate) fn subscribe() -> broadcast::Receiver<FpsChange> {
+    CHANGES.subscribe()
+}
+
+/// Frame rate the governor holds `camera` at, if it has lowered it
+pub(crate) fn governed_fps(camera: &str) -> Option<u32> {
+    LIMITS.lock().unwrap().get(camera).copied()
+}
+
+/// `fps` as far as the governor allows it for `camera`
+pub(crate) fn limit(camera: &str, fps: u32) -> u32 {
+    governed_fps(camera).map_or(fps, |limit| fps.min(limit))
+}
+
+/// Checks `fps-governor`, for config validation
+pub fn validate(config: &Config) -> Result<()> {
+    let governor = &config.fps_governor;
+    if !governor.enabled {
+        return Ok(());
+    }
+    if governor.low_cpu_percent >= governor.high_cpu_percent {
+        bail!("fps-governor.low-cpu-percent must be below high-cpu-percent");
+    }
+    if governor.step_fps == 0 || governor.min_fps == 0 {
+        bail!("fps-governor.step-fps and min-fps must be above 0");
+    }
+    let streams = config.streams();
+    for camera in &governor.cameras {
+        if !streams.iter().any(|(name, _)| name == camera) {
+            bail!("fps-governor.cameras: unknown stream '{}'", camera);
+        }
+    }
+    Ok(())
+}
+
+/// Which way the CPU load pushes the frame rate
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Pressure {
+    High,
+    Low,
+    Neither,
+}
+
+fn pressure(cpu: f32, governor: &FpsGovernorConfig) -> Pressure {
+    if cpu > governor.high_cpu_percent {
+        Pressure::High
+    } else if cpu < governor.low_cpu_percent {
+        Pressure::Low
+    } else {
+        Pressure::Neither
+    }
+}
+
+/// The frame rate one step from `current` under `pressure`, within
+/// min-fps and the camera's configured rate
+fn next_fps(
+    current: u32,
+    configured: u32,
+    pressure: Pressure,
+    governor: &FpsGovernorConfig,
+) -> u32 {
+    let floor = governor.min_fps.min(configured);
+    match pressure {
+        Pressure::High => current.saturating_sub(governor.step_fps).max(floor),
+        Pressure::Low => (current + governor.step_fps).min(configured),
+        Pressure::Neither => current,
+    }
+}
+
+/// A governed camera
+struct Governed {
+    configured: u32,
+    fps: u32,
+    pipeline: Option<gst::Pipeline>,
+    /// `fps` isn't set on the pipeline yet
+    stale: bool,
+}
+
+/// Steps the cameras' frame rate with the CPU load. Runs until cancelled.
+pub async fn run_fps_governor(config: Config) {
+    let governor = config.fps_governor.clone();
+    let sustain = Duration::from_millis(governor.sustain_ms);
+    let mut cameras: HashMap<String, Governed> = config
+        .streams()
+        .into_iter()
+        .filter(|(name, _)| governor.cameras.is_empty() || governor.cameras.contains(name))
+        .map(|(name, cam)| {
+            let governed = Governed {
+                configured: cam.fps,
+                fps: cam.fps,
+                pipeline: None,
+                stale: false,
+            };
+            (name, governed)
+        })
+        .collect();
+    let mut ticker = interval(CHECK_INTERVAL);
+    let mut current = (Pressure::Neither, Instant::now());
+
+    log::info!(
+        "FPS governor armed: down {} fps above {}% CPU, up below {}%, after {:?}",
+        governor.step_fps,
+        governor.high_cpu_percent,
+        governor.low_cpu_percent,
+        sustain
+    );
+
+    loop {
+        ticker.tick().await;
+        let Some(cpu) = system_monitor::latest().and_then(|stats| stats.cpu_usage_percent) else {
+            continue;
+        };
+
+        let now = Instant::now();
+        let pressure = pressure(cpu, &governor);
+        if pressure != current.0 {
+            current = (pressure, now);
+        }
+        // Each step needs the load to hold for another sustain period
+        let step = pressure != Pressure::Neither && now.duration_since(current.1) >= sustain;
+        if step {
+            current.1 = now;
+        }
+
+        for (name, camera) in cameras.iter_mut() {
+            let fps = if step {
+                next_fps(camera.fps, camera.configured, pressure, &governor)
+            } else {
+                camera.fps
+            };
+            if fps != camera.fps {
+                set_limit(name, fps, camera.configured);
+                let change = FpsChange {
+                    ts_ms: SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .unwrap_or_default()
+                        .as_millis() as u64,
+                    camera: name.clone(),
+                    previous_fps: camera.fps,
+                    fps,
+                    cpu_usage_percent: cpu,
+                };
+                if fps < camera.fps {
+                    log::warn!("CPU at {:.0}%, lowering {} to {} fps", cpu, name, fps);
+                } else {
+                    log::info!("CPU at {:.0}%, raising {} to {} fps", cpu, name, fps);
+                }
+                let _ = CHANGES.send(change);
+                camera.fps = fps;
+                camera.stale = true;
+            }
+
+            // A new pipeline starts out at the configured rate, and a camera
+            // without one gets the rate once it has one
+            let pipeline = debug::find_pipeline(name);
+            if pipeline != camera.pipeline {
+                camera.stale |= camera.fps != camera.configured;
+                camera.pipeline = pipeline;
+            }
+            if let (true, Some(pipeline)) = (camera.stale, &camera.pipeline) {
+                boost::set_framerate(pipeline, camera.fps);
+                camera.stale = false;
+            }
+        }
+    }
+}
+
+fn set_limit(camera: &str, fps: u32, configured: u32) {
+    let mut limits = LIMITS.lock().unwrap();
+    if fps < configured {
+        limits.insert(camera.to_string(), fps);
+    } else {
+        limits.remove(camera);
+    }
+}
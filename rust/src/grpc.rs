@@ -0,0 +1,407 @@
+//! Optional gRPC control plane (`--features grpc`)
+//!
+//! Mirrors the REST API and the /ws/control socket for integrators that
+//! already speak gRPC: stream listing, camera commands, a stats stream and an
+//! event subscription. The service is defined in `proto/control.proto` and
+//! listens on its own port (`[grpc] port`). When `web.admin-token` is set,
+//! calls must carry it as `authorization: Bearer <token>` metadata.
+
+use anyhow::Result;
+use std::collections::HashMap;
+use std::pin::Pin;
+use tokio::sync::mpsc;
+use tokio::time::interval;
+use tokio_stream::wrappers::{ReceiverStream, TcpListenerStream};
+use tokio_stream::Stream;
+use tonic::{Request, Response, Status};
+
+use crate::audit::Actor;
+use crate::config::Config;
+use crate::control::{self, ControlCommand, PUSH_INTERVAL};
+use crate::handover;
+use crate::pause::{self, PauseMode};
+use crate::streams::StreamInfo;
+use crate::system_monitor;
+use crate::watchdog::{RecoveryState, RecoveryStatus};
+use crate::web_server;
+
+pub mod proto {
+    tonic::include_proto!("streamer.v1");
+}
+
+use proto::streamer_control_server::{StreamerControl, StreamerControlServer};
+
+/// Messages buffered per streaming call before the sender waits on the client
+const STREAM_BUFFER: usize = 16;
+
+type ResponseStream<T> = Pin<Box<dyn Stream<Item = Result<T, Status>> + Send>>;
+
+struct ControlService {
+    config: Config,
+    pi_ip: String,
+    base_port: u16,
+}
+
+impl ControlService {
+    /// Runs the command built from `request`, on behalf of its peer
+    async fn run<T>(
+        &self,
+        request: Request<T>,
+        command: impl FnOnce(T) -> ControlCommand,
+    ) -> Result<Response<proto::CommandResponse>, Status> {
+        let actor = Actor {
+            interface: "grpc",
+            peer: request.remote_addr().map(|addr| addr.to_string()),
+            authenticated: self.config.web.admin_token.as_deref().is_some_and(|t| !t.is_empty()),
+        };
+        let command = command(request.into_inner());
+        log::info!("gRPC control command: {:?}", command);
+        control::execute(command, &self.config, &actor)
+            .await
+            .map(|()| Response::new(proto::CommandResponse {}))
+            .map_err(|e| Status::invalid_argument(e.to_string()))
+    }
+}
+
+#[tonic::async_trait]
+impl StreamerControl for ControlService {
+    async fn list_streams(
+        &self,
+        _request: Request<proto::ListStreamsRequest>,
+    ) -> Result<Response<proto::ListStreamsResponse>, Status> {
+        let streams = web_server::stream_entries(&self.config, &self.pi_ip, self.base_port)
+            .into_iter()
+            .map(stream_info)
+            .collect();
+        Ok(Response::new(proto::ListStreamsResponse { streams }))
+    }
+
+    async fn get_camera_states(
+        &self,
+        _request: Request<proto::GetCameraStatesRequest>,
+    ) -> Result<Response<proto::CameraStates>, Status> {
+        let cameras = control::camera_states(&self.config).iter().map(camera_state).collect();
+        Ok(Response::new(proto::CameraStates { cameras }))
+    }
+
+    async fn pause(
+        &self,
+        request: Request<proto::PauseRequest>,
+    ) -> Result<Response<proto::CommandResponse>, Status> {
+        self.run(request, |request| {
+            let mode = match request.mode() {
+                proto::PauseMode::Freeze => PauseMode::Freeze,
+                proto::PauseMode::Black | proto::PauseMode::Unspecified => PauseMode::Black,
+            };
+            ControlCommand::Pause { camera: request.stream, mode }
+        })
+        .await
+    }
+
+    async fn resume(
+        &self,
+        request: Request<proto::StreamRequest>,
+    ) -> Result<Response<proto::CommandResponse>, Status> {
+        self.run(request, |r| ControlCommand::Resume { camera: r.stream }).await
+    }
+
+    async fn set_bitrate(
+        &self,
+        request: Request<proto::SetBitrateRequest>,
+    ) -> Result<Response<proto::CommandResponse>, Status> {
+        self.run(request, |r| ControlCommand::SetBitrate { camera: r.stream, bitrate: r.bitrate })
+            .await
+    }
+
+    async fn set_flip(
+        &self,
+        request: Request<proto::SetFlipRequest>,
+    ) -> Result<Response<proto::CommandResponse>, Status> {
+        self.run(request, |r| ControlCommand::SetFlip { camera: r.stream, method: r.method }).await
+    }
+
+    async fn start_recording(
+        &self,
+        request: Request<proto::StreamRequest>,
+    ) -> Result<Response<proto::CommandResponse>, Status> {
+        self.run(request, |r| ControlCommand::StartRecording { camera: r.stream }).await
+    }
+
+    async fn stop_recording(
+        &self,
+        request: Request<proto::StreamRequest>,
+    ) -> Result<Response<proto::CommandResponse>, Status> {
+        self.run(request, |r| ControlCommand::StopRecording { camera: r.stream }).await
+    }
+
+    async fn restart_pipeline(
+        &self,
+        request: Request<proto::StreamRequest>,
+    ) -> Result<Response<proto::CommandResponse>, Status> {
+        self.run(request, |r| ControlCommand::RestartPipeline { camera: r.stream }).await
+    }
+
+    async fn set_session_muted(
+        &self,
+        request: Request<proto::SetSessionMutedRequest>,
+    ) -> Result<Response<proto::CommandResponse>, Status> {
+        self.run(request, |r| ControlCommand::SetSessionMuted {
+            session: r.session,
+            muted: r.muted,
+        })
+        .await
+    }
+
+    async fn set_session_source(
+        &self,
+        request: Request<proto::SetSessionSourceRequest>,
+    ) -> Result<Response<proto::CommandResponse>, Status> {
+        self.run(request, |r| ControlCommand::SetSessionSource {
+            session: r.session,
+            camera: r.stream,
+        })
+        .await
+    }
+
+    type StreamStatsStream = ResponseStream<proto::SystemStats>;
+
+    async fn stream_stats(
+        &self,
+        _request: Request<proto::StreamStatsRequest>,
+    ) -> Result<Response<Self::StreamStatsStream>, Status> {
+        let (tx, rx) = mpsc::channel(STREAM_BUFFER);
+        // Stops once the client goes away
+        tokio::spawn(async move {
+            let mut ticker = interval(PUSH_INTERVAL);
+            let mut last_ts = 0;
+            loop {
+                tokio::select! {
+                    _ = ticker.tick() => {}
+                    _ = tx.closed() => break,
+                }
+                let Some(stats) = system_monitor::latest() else { continue };
+                if stats.ts_ms == last_ts {
+                    continue;
+                }
+                last_ts = stats.ts_ms;
+                if tx.send(Ok(system_stats(stats))).await.is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(Response::new(Box::pin(ReceiverStream::new(rx))))
+    }
+
+    type SubscribeEventsStream = ResponseStream<proto::Event>;
+
+    async fn subscribe_events(
+        &self,
+        _request: Request<proto::SubscribeEventsRequest>,
+    ) -> Result<Response<Self::SubscribeEventsStream>, Status> {
+        let (tx, rx) = mpsc::channel(STREAM_BUFFER);
+        let config = self.config.clone();
+        tokio::spawn(async move {
+            // Same change detection as the control socket's state pushes
+            let mut ticker = interval(PUSH_INTERVAL);
+            let mut cameras = HashMap::new();
+            let mut sessions = HashMap::new();
+            loop {
+                tokio::select! {
+                    _ = ticker.tick() => {}
+                    _ = tx.closed() => break,
+                }
+                let mut events = camera_events(&mut cameras, control::camera_states(&config));
+                events.extend(session_events(&mut sessions, pause::sessions()));
+                for event in events {
+                    if tx.send(Ok(proto::Event { event: Some(event) })).await.is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+        Ok(Response::new(Box::pin(ReceiverStream::new(rx))))
+    }
+}
+
+/// Events for cameras whose state differs from `known`, which is updated
+fn camera_events(
+    known: &mut HashMap<String, control::CameraState>,
+    current: Vec<control::CameraState>,
+) -> Vec<proto::event::Event> {
+    let mut events = Vec::new();
+    for state in current {
+        if known.get(&state.name) != Some(&state) {
+            events.push(proto::event::Event::Camera(camera_state(&state)));
+            known.insert(state.name.clone(), state);
+        }
+    }
+    events
+}
+
+/// Opened, changed and closed sessions relative to `known`, which is updated
+fn session_events(
+    known: &mut HashMap<u64, pause::SessionInfo>,
+    current: Vec<pause::SessionInfo>,
+) -> Vec<proto::event::Event> {
+    use proto::event::Event;
+
+    let mut events = Vec::new();
+    let mut closed: Vec<u64> = known.keys().copied().collect();
+    for session in current {
+        closed.retain(|id| *id != session.id);
+        match known.get(&session.id) {
+            None => events.push(Event::SessionOpened(session_info(&session))),
+            Some(previous) if previous.muted != session.muted || previous.source != session.source => {
+                events.push(Event::SessionChanged(session_info(&session)))
+            }
+            Some(_) => continue,
+        }
+        known.insert(session.id, session);
+    }
+    for id in closed {
+        known.remove(&id);
+        events.push(Event::SessionClosed(id));
+    }
+    events
+}
+
+fn pause_mode(mode: Option<PauseMode>) -> i32 {
+    let mode = match mode {
+        None => proto::PauseMode::Unspecified,
+        Some(PauseMode::Black) => proto::PauseMode::Black,
+        Some(PauseMode::Freeze) => proto::PauseMode::Freeze,
+    };
+    mode as i32
+}
+
+fn stream_info(stream: StreamInfo) -> proto::StreamInfo {
+    proto::StreamInfo {
+        name: stream.name,
+        camera: stream.camera as u32,
+        device: stream.device,
+        width: stream.width,
+        height: stream.height,
+        fps: stream.fps,
+        signaling_port: stream.signaling_port as u32,
+        ws_url: stream.ws_url,
+        active: stream.active,
+        recording: stream.recording,
+        paused: pause_mode(stream.paused),
+    }
+}
+
+fn camera_state(state: &control::CameraState) -> proto::CameraState {
+    proto::CameraState {
+        name: state.name.clone(),
+        pipeline_state: state.pipeline_state.clone(),
+        bitrate: state.bitrate,
+        flip: state.flip.clone(),
+        recording: state.recording,
+        paused: pause_mode(state.paused),
+        recovery: state.recovery.as_ref().map(recovery_status),
+        governed_fps: state.governed_fps,
+    }
+}
+
+fn recovery_status(status: &RecoveryStatus) -> proto::RecoveryStatus {
+    let state = match status.state {
+        RecoveryState::Healthy => proto::RecoveryState::Healthy,
+        RecoveryState::Recovering => proto::RecoveryState::Recovering,
+        RecoveryState::GaveUp => proto::RecoveryState::GaveUp,
+    };
+    proto::RecoveryStatus {
+        state: state as i32,
+        reason: status.reason.clone(),
+        restarts: status.restarts,
+        power_cycles: status.power_cycles,
+        recoveries: status.recoveries,
+    }
+}
+
+fn session_info(session: &pause::SessionInfo) -> proto::SessionInfo {
+    proto::SessionInfo {
+        id: session.id,
+        camera: session.camera.clone(),
+        peer: session.peer.clone(),
+        muted: session.muted,
+        source: session.source.clone(),
+    }
+}
+
+fn system_stats(stats: system_monitor::SystemStats) -> proto::SystemStats {
+    proto::SystemStats {
+        ts_ms: stats.ts_ms,
+        thermal_zones_c: stats.thermal_zones_c.into_iter().collect(),
+        gpu_temp_c: stats.gpu_temp_c,
+        throttling: stats.throttling.map(|t| proto::ThrottleFlags {
+            raw: t.raw,
+            under_voltage: t.under_voltage,
+            freq_capped: t.freq_capped,
+            throttled: t.throttled,
+            soft_temp_limit: t.soft_temp_limit,
+            under_voltage_occurred: t.under_voltage_occurred,
+            freq_capped_occurred: t.freq_capped_occurred,
+            throttled_occurred: t.throttled_occurred,
+            soft_temp_limit_occurred: t.soft_temp_limit_occurred,
+        }),
+        cpu_usage_percent: stats.cpu_usage_percent,
+        load_avg: stats.load_avg.to_vec(),
+        mem_total_kb: stats.mem_total_kb,
+        mem_available_kb: stats.mem_available_kb,
+        network: stats
+            .network
+            .into_iter()
+            .map(|(name, iface)| {
+                let iface = proto::InterfaceStats {
+                    rx_bytes: iface.rx_bytes,
+                    tx_bytes: iface.tx_bytes,
+                    rx_bytes_per_sec: iface.rx_bytes_per_sec,
+                    tx_bytes_per_sec: iface.tx_bytes_per_sec,
+                };
+                (name, iface)
+            })
+            .collect(),
+        disk: stats.disk.map(|d| proto::DiskStats {
+            path: d.path,
+            total_kb: d.total_kb,
+            available_kb: d.available_kb,
+            used_percent: d.used_percent,
+        }),
+    }
+}
+
+/// Serves the control service on `[grpc] port` until the task is cancelled.
+pub async fn run_grpc_server(pi_ip: String, base_port: u16, config: Config) -> Result<()> {
+    let listener = handover::listener(config.grpc.port, &config.handover)?;
+    let expected_token = config.web.admin_token.clone().filter(|t| !t.is_empty());
+    let service = ControlService {
+        config,
+        pi_ip: pi_ip.clone(),
+        base_port,
+    };
+
+    // Same rule as the REST control endpoints: open until a token is set
+    let check_token = move |request: Request<()>| -> Result<Request<()>, Status> {
+        let Some(expected) = expected_token.as_deref() else {
+            return Ok(request);
+        };
+        let token = request
+            .metadata()
+            .get("authorization")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "));
+        if token.is_some_and(|token| control::token_matches(token.trim(), expected)) {
+            Ok(request)
+        } else {
+            log::warn!("Rejected unauthorized gRPC call");
+            Err(Status::unauthenticated("admin token required"))
+        }
+    };
+
+    log::info!("gRPC control service listening on {}:{}", pi_ip, config.grpc.port);
+    tonic::transport::Server::builder()
+        .add_service(StreamerControlServer::with_interceptor(service, check_token))
+        .serve_with_incoming_shutdown(TcpListenerStream::new(listener), handover::draining())
+        .await?;
+    Ok(())
+}
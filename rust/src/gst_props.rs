@@ -0,0 +1,229 @@
+//! Version-tolerant element property setter
+//!
+//! Element properties drift between GStreamer and plugin releases: a
+//! property appears or disappears, an `int` becomes a `uint`, a string
+//! becomes an enum. `has_property(name, Some(type))` only matches the exact
+//! type, so a setting written against one release is silently skipped on
+//! another. [`set`] looks up the property's ParamSpec instead, converts the
+//! value to whatever type the running version declares, and logs when it
+//! can't apply it.
+
+use gstreamer as gst;
+use gstreamer::glib;
+use gstreamer::prelude::*;
+use log::{debug, warn};
+
+/// Sets `name` on `element` if this version has it and the value fits.
+/// Returns whether the property was set.
+pub fn set(element: &impl IsA<gst::Object>, name: &str, value: impl ToValue) -> bool {
+    let element = element.upcast_ref::<gst::Object>();
+    let Some(pspec) = element.find_property(name) else {
+        debug!(
+            "{} ({}): no property '{}' in this GStreamer version, skipped",
+            element.name(),
+            element.type_().name(),
+            name
+        );
+        return false;
+    };
+    let flags = pspec.flags();
+    if !flags.contains(glib::ParamFlags::WRITABLE)
+        || flags.contains(glib::ParamFlags::CONSTRUCT_ONLY)
+    {
+        warn!(
+            "{}: property '{}' is not writable, skipped",
+            element.name(),
+            name
+        );
+        return false;
+    }
+    match coerce(&value.to_value(), &pspec) {
+        Ok(value) => {
+            element.set_property_from_value(name, &value);
+            true
+        }
+        Err(e) => {
+            warn!("{}: property '{}' not set: {}", element.name(), name, e);
+            false
+        }
+    }
+}
+
+/// Converts `value` to the type `pspec` declares, within its range
+fn coerce(value: &glib::Value, pspec: &glib::ParamSpec) -> Result<glib::Value, String> {
+    let target = pspec.value_type();
+    let source = value.type_();
+    if source.is_a(target) {
+        return check_range(value.clone(), pspec);
+    }
+
+    // Strings go through GStreamer's parser, as in gst-launch: enum and
+    // flags nicks, numbers, caps, structures
+    if let Ok(text) = value.get::<&str>() {
+        let parsed = glib::Value::deserialize(text, target)
+            .map_err(|_| format!("'{}' is not a valid {}", text, target.name()))?;
+        return check_range(parsed, pspec);
+    }
+
+    let Some(number) = number(value) else {
+        return Err(format!(
+            "can't convert {} to {}",
+            source.name(),
+            target.name()
+        ));
+    };
+
+    if target.is_a(glib::Type::ENUM) {
+        let class = glib::EnumClass::with_type(target)
+            .ok_or_else(|| format!("{} is not an enum", target.name()))?;
+        return (number.fract() == 0.0)
+            .then(|| class.to_value(number as i32))
+            .flatten()
+            .ok_or_else(|| format!("{} is not a {} value", number, target.name()));
+    }
+
+    let Some((_, _, integral)) = bounds(pspec) else {
+        return Err(format!(
+            "can't convert {} to {}",
+            source.name(),
+            target.name()
+        ));
+    };
+    if integral && number.fract() != 0.0 {
+        return Err(format!("{} is not a whole number", number));
+    }
+    check_range(value.clone(), pspec)?
+        .transform_with_type(target)
+        .map_err(|_| format!("can't convert {} to {}", source.name(), target.name()))
+}
+
+/// Rejects numbers outside the property's range rather than letting GLib
+/// clamp them
+fn check_range(value: glib::Value, pspec: &glib::ParamSpec) -> Result<glib::Value, String> {
+    if let (Some(number), Some((min, max, _))) = (number(&value), bounds(pspec)) {
+        if number < min || number > max {
+            return Err(format!("{} is outside {}..={}", number, min, max));
+        }
+    }
+    Ok(value)
+}
+
+fn number(value: &glib::Value) -> Option<f64> {
+    if let Ok(v) = value.get::<i32>() {
+        Some(v as f64)
+    } else if let Ok(v) = value.get::<u32>() {
+        Some(v as f64)
+    } else if let Ok(v) = value.get::<i64>() {
+        Some(v as f64)
+    } else if let Ok(v) = value.get::<u64>() {
+        Some(v as f64)
+    } else if let Ok(v) = value.get::<f32>() {
+        Some(v as f64)
+    } else {
+        value.get::<f64>().ok()
+    }
+}
+
+/// Range of a numeric property, and whether it takes whole numbers only
+fn bounds(pspec: &glib::ParamSpec) -> Option<(f64, f64, bool)> {
+    if let Some(p) = pspec.downcast_ref::<glib::ParamSpecInt>() {
+        Some((p.minimum() as f64, p.maximum() as f64, true))
+    } else if let Some(p) = pspec.downcast_ref::<glib::ParamSpecUInt>() {
+        Some((p.minimum() as f64, p.maximum() as f64, true))
+    } else if let Some(p) = pspec.downcast_ref::<glib::ParamSpecInt64>() {
+        Some((p.minimum() as f64, p.maximum() as f64, true))
+    } else if let Some(p) = pspec.downcast_ref::<glib::ParamSpecUInt64>() {
+        Some((p.minimum() as f64, p.maximum() as f64, true))
+    } else if let Some(p) = pspec.downcast_ref::<glib::ParamSpecFloat>() {
+        Some((p.minimum() as f64, p.maximum() as f64, false))
+    } else {
+        pspec
+            .downcast_ref::<glib::ParamSpecDouble>()
+            .map(|p| (p.minimum(), p.maximum(), false))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The same setting as different releases declare it
+    fn int(min: i32, max: i32) -> glib::ParamSpec {
+        glib::ParamSpecInt::builder("num-buffers")
+            .minimum(min)
+            .maximum(max)
+            .build()
+    }
+
+    fn uint(max: u32) -> glib::ParamSpec {
+        glib::ParamSpecUInt::builder("num-buffers")
+            .maximum(max)
+            .build()
+    }
+
+    fn state_enum() -> glib::ParamSpec {
+        glib::ParamSpecEnum::builder_with_default("mode", gst::State::Null).build()
+    }
+
+    #[test]
+    fn test_integers_follow_the_declared_type() {
+        gst::init().unwrap();
+        let value = coerce(&3i32.to_value(), &uint(u32::MAX)).unwrap();
+        assert_eq!(value.get::<u32>().unwrap(), 3);
+        let value = coerce(&3u32.to_value(), &int(-1, i32::MAX)).unwrap();
+        assert_eq!(value.get::<i32>().unwrap(), 3);
+        let value = coerce(&(-1i64).to_value(), &int(-1, i32::MAX)).unwrap();
+        assert_eq!(value.get::<i32>().unwrap(), -1);
+
+        assert!(coerce(&(-1i32).to_value(), &uint(u32::MAX)).is_err());
+        assert!(coerce(&11i32.to_value(), &int(0, 10)).is_err());
+        assert!(coerce(&1.5f64.to_value(), &int(0, 10)).is_err());
+        assert!(coerce(&true.to_value(), &int(0, 10)).is_err());
+    }
+
+    #[test]
+    fn test_enums_by_nick_or_number() {
+        gst::init().unwrap();
+        let pspec = state_enum();
+        let value = coerce(&"playing".to_value(), &pspec).unwrap();
+        assert_eq!(value.get::<gst::State>().unwrap(), gst::State::Playing);
+        let value = coerce(&2i32.to_value(), &pspec).unwrap();
+        assert_eq!(value.get::<gst::State>().unwrap(), gst::State::Ready);
+        let value = coerce(&gst::State::Paused.to_value(), &pspec).unwrap();
+        assert_eq!(value.get::<gst::State>().unwrap(), gst::State::Paused);
+
+        assert!(coerce(&"sideways".to_value(), &pspec).is_err());
+        assert!(coerce(&42i32.to_value(), &pspec).is_err());
+    }
+
+    #[test]
+    fn test_strings_are_parsed() {
+        gst::init().unwrap();
+        let value = coerce(&"8".to_value(), &uint(10)).unwrap();
+        assert_eq!(value.get::<u32>().unwrap(), 8);
+        assert!(coerce(&"12".to_value(), &uint(10)).is_err());
+        let pspec = glib::ParamSpecString::builder("name").build();
+        let value = coerce(&"mmap".to_value(), &pspec).unwrap();
+        assert_eq!(value.get::<&str>().unwrap(), "mmap");
+        let pspec = glib::ParamSpecBoolean::builder("drop").build();
+        assert!(coerce(&"maybe".to_value(), &pspec).is_err());
+    }
+
+    #[test]
+    fn test_set_on_element() {
+        gst::init().unwrap();
+        let queue = gst::ElementFactory::make("queue").build().unwrap();
+        assert!(set(&queue, "max-size-buffers", 5i32));
+        assert_eq!(queue.property::<u32>("max-size-buffers"), 5);
+        assert!(set(&queue, "leaky", "downstream"));
+        let leaky = queue.property_value("leaky").serialize().unwrap();
+        assert_eq!(leaky, "downstream");
+        assert!(set(&queue, "leaky", 1i32));
+        let leaky = queue.property_value("leaky").serialize().unwrap();
+        assert_eq!(leaky, "upstream");
+        assert!(!set(&queue, "max-size-buffers", -1i32));
+        assert_eq!(queue.property::<u32>("max-size-buffers"), 5);
+        assert!(!set(&queue, "current-level-buffers", 1u32));
+        assert!(!set(&queue, "no-such-property", true));
+    }
+}
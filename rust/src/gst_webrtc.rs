@@ -1,15 +1,25 @@
 use anyhow::Result;
 use std::sync::Arc;
-use tokio::net::{TcpListener, TcpStream};
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpStream;
 use tokio::sync::Mutex;
 use gstreamer::prelude::*;
 use std::time::Duration;
 
 use crate::config::{CameraConfig, Config};
-use crate::webrtc::{CameraPipeline, WebRTCClient};
+use crate::debug;
+use crate::handover;
+use crate::mjpeg_rtp;
+use crate::ndi;
+use crate::pip;
+use crate::recording;
+use crate::rtmp;
+use crate::streams;
+use crate::webrtc::{switch, CameraPipeline, WebRTCClient};
 
 struct AppState {
     camera_pipeline: CameraPipeline,
+    camera_name: String,
     config: Config,
     client_count: u32, // Track number of connected clients
 }
@@ -45,11 +55,44 @@ async fn monitor_memory_usage(_config: Arc<Config>, _app_state: Arc<Mutex<AppSta
     }
 }
 
-pub async fn run_camera(cfg: Config, cam_cfg: CameraConfig, listen_port: u16) -> Result<()> {
-    log::info!("STARTING run_camera for device {} on port {}", cam_cfg.device, listen_port);
+pub async fn run_camera(cfg: Config, cam_cfg: CameraConfig, camera_name: &str, listen_port: u16) -> Result<()> {
+    log::info!("STARTING run_camera for {} (device {}) on port {}", camera_name, cam_cfg.device, listen_port);
+
+    let addr = format!("0.0.0.0:{}", listen_port);
+    log::info!("🔄 Attempting to bind WebRTC server to {}", addr);
+    
+    // Bound before the claim: while upgrading, the port is shared with the
+    // previous instance, which stops accepting once it drains
+    let listener = match handover::listener(listen_port, &cfg.handover) {
+        Ok(listener) => {
+            log::info!("✅ WebRTC camera server successfully bound to {} (device {})", addr, cam_cfg.device);
+            listener
+        },
+        Err(e) => {
+            log::error!("❌ FAILED to bind WebRTC server to {}: {}", addr, e);
+            return Err(anyhow::anyhow!("Failed to bind to {}: {}", addr, e));
+        }
+    };
+
+    // Waits for a previous instance to hand the camera over when upgrading,
+    // holding on to viewers that arrive meanwhile
+    let mut waiting = Vec::new();
+    let _claim = {
+        let claim = handover::claim(camera_name, &cfg.handover);
+        tokio::pin!(claim);
+        loop {
+            tokio::select! {
+                claim = &mut claim => break claim?,
+                accepted = listener.accept() => match accepted {
+                    Ok(accepted) => waiting.push(accepted),
+                    Err(e) => log::warn!("Failed to accept a viewer for {}: {}", camera_name, e),
+                },
+            }
+        }
+    };
     
     // Add error handling around camera pipeline creation
-    let camera_pipeline = match CameraPipeline::new(cfg.clone(), cam_cfg.clone()) {
+    let camera_pipeline = match CameraPipeline::new(cfg.clone(), cam_cfg.clone(), camera_name) {
         Ok(pipeline) => {
             log::info!("✅ Camera pipeline created successfully for device {}", cam_cfg.device);
             pipeline
@@ -62,8 +105,12 @@ pub async fn run_camera(cfg: Config, cam_cfg: CameraConfig, listen_port: u16) ->
     
     log::info!("Camera pipeline created, waiting for first client to start streaming");
 
+    // Make the pipeline inspectable via /api/debug/pipeline/{camera}
+    debug::register_pipeline(camera_name, &camera_pipeline.pipeline);
+
     let app_state = Arc::new(Mutex::new(AppState {
         camera_pipeline,
+        camera_name: camera_name.to_string(),
         config: cfg.clone(),
         client_count: 0,
     }));
@@ -76,43 +123,108 @@ pub async fn run_camera(cfg: Config, cam_cfg: CameraConfig, listen_port: u16) ->
         monitor_memory_usage(monitor_config, monitor_app_state).await;
     });
 
-    let addr = format!("0.0.0.0:{}", listen_port);
-    log::info!("🔄 Attempting to bind WebRTC server to {}", addr);
-    
-    // Add detailed error handling around TcpListener binding
-    let listener = match TcpListener::bind(&addr).await {
-        Ok(listener) => {
-            log::info!("✅ WebRTC camera server successfully bound to {} (device {})", addr, cam_cfg.device);
-            listener
-        },
-        Err(e) => {
-            log::error!("❌ FAILED to bind WebRTC server to {}: {}", addr, e);
-            return Err(anyhow::anyhow!("Failed to bind to {}: {}", addr, e));
+    log::info!("🎉 WebRTC camera server listening on {} (device {})", addr, cam_cfg.device);
+
+    for (stream, peer) in waiting {
+        serve_viewer(stream, peer, &app_state, &config_arc);
+    }
+
+    // Once draining, new viewers are left to the next instance
+    let handed_over = loop {
+        tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok((stream, peer)) => serve_viewer(stream, peer, &app_state, &config_arc),
+                Err(_) => break false,
+            },
+            _ = handover::draining() => break true,
         }
     };
-    
-    log::info!("🎉 WebRTC camera server listening on {} (device {})", addr, cam_cfg.device);
 
-    while let Ok((stream, peer)) = listener.accept().await {
-        log::info!("Incoming WebRTC connection from {}", peer);
-        let app_state_clone = app_state.clone();
-        let config_clone = config_arc.clone();
-        
-        tokio::spawn(async move {
-            if let Err(e) = handle_client(stream, app_state_clone, config_clone).await {
-                log::error!("WebRTC client error: {}", e);
-            } else {
-                log::info!("WebRTC client disconnected gracefully");
-            }
-        });
+    if handed_over {
+        drop(listener);
+        let mut drain_tick = tokio::time::interval(handover::POLL_INTERVAL);
+        while !is_idle(&*app_state.lock().await) {
+            drain_tick.tick().await;
+        }
+        log::info!("Camera {} is idle, handing it over", camera_name);
+        let state = app_state.lock().await;
+        if let Err(e) = state.camera_pipeline.pipeline.set_state(gstreamer::State::Null) {
+            log::warn!("Failed to stop camera pipeline: {}", e);
+        }
+    } else {
+        log::warn!("WebRTC server loop ended unexpectedly for device {}", cam_cfg.device);
     }
-    
-    log::warn!("WebRTC server loop ended unexpectedly for device {}", cam_cfg.device);
+    debug::unregister_pipeline(camera_name);
     Ok(())
 }
 
-async fn handle_client(stream: TcpStream, app_state: Arc<Mutex<AppState>>, config_arc: Arc<Config>) -> Result<()> {
-    let (pipeline, tee) = {
+/// Serves one viewer's connection on its own task
+fn serve_viewer(
+    stream: TcpStream,
+    peer: std::net::SocketAddr,
+    app_state: &Arc<Mutex<AppState>>,
+    config_arc: &Arc<Config>,
+) {
+    log::info!("Incoming WebRTC connection from {}", peer);
+    let app_state_clone = app_state.clone();
+    let config_clone = config_arc.clone();
+    
+    tokio::spawn(async move {
+        if let Err(e) = handle_client(stream, app_state_clone, config_clone).await {
+            log::error!("WebRTC client error: {}", e);
+        } else {
+            log::info!("WebRTC client disconnected gracefully");
+        }
+    });
+}
+
+/// No viewers, and no recording, RTMP push, NDI source, MJPEG-RTP camera,
+/// other camera's session or inset fed by the camera
+fn is_idle(state: &AppState) -> bool {
+    let camera = state.camera_name.as_str();
+    state.client_count == 0
+        && !recording::is_recording(camera)
+        && !has_outputs(camera)
+}
+
+/// Whether an RTMP push, NDI source, MJPEG-RTP camera, a session switched
+/// over from another camera or another camera's inset is fed by `camera`
+fn has_outputs(camera: &str) -> bool {
+    rtmp::is_pushing(camera)
+        || ndi::is_sending(camera)
+        || mjpeg_rtp::is_tapped(camera)
+        || switch::is_feeding(camera)
+        || pip::is_inset(camera)
+}
+
+/// Path of the upgrade request, peeked so the handshake still sees it
+async fn peek_request_path(stream: &TcpStream) -> Result<Option<String>> {
+    let mut buffer = [0; 512];
+    let peeked = stream.peek(&mut buffer).await?;
+    let request = String::from_utf8_lossy(&buffer[..peeked]);
+    Ok(request
+        .lines()
+        .next()
+        .and_then(|line| line.split_whitespace().nth(1))
+        .map(str::to_string))
+}
+
+async fn handle_client(mut stream: TcpStream, app_state: Arc<Mutex<AppState>>, config_arc: Arc<Config>) -> Result<()> {
+    let peer = stream.peer_addr().map(|a| a.to_string()).unwrap_or_default();
+
+    // Each port serves one stream; a client asking for another one by name
+    // was given a stale URL and should fail loudly, not watch the wrong camera
+    let stream_name = app_state.lock().await.camera_name.clone();
+    if let Some(path) = peek_request_path(&stream).await? {
+        if !streams::signaling_path_matches(&path, &stream_name) {
+            log::warn!("Rejecting signaling request for {} on the {} port", path, stream_name);
+            stream
+                .write_all(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n")
+                .await?;
+            return Ok(());
+        }
+    }
+    let (pipeline, tee, camera_name, latency) = {
         let mut state = app_state.lock().await;
         state.client_count += 1;
         
@@ -132,10 +244,12 @@ async fn handle_client(stream: TcpStream, app_state: Arc<Mutex<AppState>>, confi
         (
             state.camera_pipeline.pipeline.clone(),
             state.camera_pipeline.tee.clone(),
+            state.camera_name.clone(),
+            state.camera_pipeline.latency,
         )
     };
 
-    let client = WebRTCClient::new(&pipeline, &tee, &config_arc)?;
+    let client = WebRTCClient::new(&pipeline, &tee, &config_arc, &camera_name, &peer, latency)?;
     let result = client.handle_connection(stream, config_arc).await;
 
     // Simple cleanup: Decrement client count and manage pipeline state
@@ -143,8 +257,14 @@ async fn handle_client(stream: TcpStream, app_state: Arc<Mutex<AppState>>, confi
         let mut state = app_state.lock().await;
         state.client_count = state.client_count.saturating_sub(1);
         
-        // Stop the pipeline when no clients are connected
-        if state.client_count == 0 {
+        // Stop the pipeline when no clients are connected, unless it is
+        // still feeding a recording or one of the outputs
+        let camera = state.camera_name.as_str();
+        if state.client_count == 0 && recording::is_recording(camera) {
+            log::info!("No clients connected, keeping camera pipeline running for recording");
+        } else if state.client_count == 0 && has_outputs(camera) {
+            log::info!("No clients connected, keeping camera pipeline running for its outputs");
+        } else if state.client_count == 0 {
             log::info!("No clients connected, stopping camera pipeline");
             
             if let Err(e) = state.camera_pipeline.pipeline.set_state(gstreamer::State::Null) {
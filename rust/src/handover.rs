@@ -0,0 +1,185 @@
+//! Zero-downtime upgrades
+//!
+//! A new instance takes over from a running one without cutting off viewers
+//! or recordings. Listeners are the sockets systemd passed in (socket
+//! activation, matched by port) or, with `[handover] enabled`, sockets bound
+//! with SO_REUSEPORT, so both instances can accept on the same ports while
+//! they overlap.
+//!
+//! A camera can only be opened by one process, so each is served under a
+//! lock file in `lock-dir`: the new instance waits for it, and SIGUSR2 makes
+//! the old one drain. The web server, gRPC and cameras stop accepting at
+//! once, leaving new connections to the new instance, which holds on to a
+//! camera's viewers until it gets the lock. Each camera keeps serving until
+//! its last viewer leaves and its recording, RTMP push and NDI source stop,
+//! then closes and releases its lock.
+
+use anyhow::{Context, Result};
+use once_cell::sync::Lazy;
+use socket2::{Domain, Protocol, Socket, Type};
+use std::collections::HashSet;
+use std::fs::{File, OpenOptions, TryLockError};
+use std::net::SocketAddr;
+use std::os::fd::{FromRawFd, RawFd};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::sync::Notify;
+use tokio_util::sync::CancellationToken;
+
+use crate::config::HandoverConfig;
+
+/// First descriptor systemd passes (SD_LISTEN_FDS_START)
+const LISTEN_FDS_START: RawFd = 3;
+
+/// How often a waiting instance retries a camera's lock, and a draining one
+/// checks whether its cameras have gone idle
+pub const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+static INHERITED: Lazy<Mutex<Vec<std::net::TcpListener>>> =
+    Lazy::new(|| Mutex::new(inherited_listeners()));
+static DRAIN: Lazy<CancellationToken> = Lazy::new(CancellationToken::new);
+static SERVING: Lazy<Mutex<HashSet<String>>> = Lazy::new(|| Mutex::new(HashSet::new()));
+static RELEASED: Lazy<Notify> = Lazy::new(Notify::new);
+
+fn inherited_listeners() -> Vec<std::net::TcpListener> {
+    let for_us = std::env::var("LISTEN_PID")
+        .ok()
+        .and_then(|pid| pid.parse::<u32>().ok())
+        == Some(std::process::id());
+    let count = std::env::var("LISTEN_FDS")
+        .ok()
+        .and_then(|n| n.parse::<RawFd>().ok())
+        .unwrap_or(0);
+    if !for_us || count <= 0 {
+        return Vec::new();
+    }
+    // Child processes must not pick them up again
+    for var in ["LISTEN_PID", "LISTEN_FDS", "LISTEN_FDNAMES"] {
+        std::env::remove_var(var);
+    }
+    log::info!("Inherited {} listening socket(s) from systemd", count);
+    (LISTEN_FDS_START..LISTEN_FDS_START + count)
+        // SAFETY: systemd passes these to this process and nothing else owns them
+        .map(|fd| unsafe { std::net::TcpListener::from_raw_fd(fd) })
+        .collect()
+}
+
+/// Listener on all interfaces for `port`: the socket systemd passed in for
+/// it, or a new one that a successor can share when handover is enabled
+pub fn listener(port: u16, config: &HandoverConfig) -> Result<TcpListener> {
+    let inherited = {
+        let mut inherited = INHERITED.lock().unwrap();
+        inherited
+            .iter()
+            .position(|l| l.local_addr().map(|a| a.port()).ok() == Some(port))
+            .map(|index| inherited.swap_remove(index))
+    };
+    let listener = match inherited {
+        Some(listener) => {
+            log::info!("Using the socket passed in for port {}", port);
+            listener
+        }
+        None => bind(port, config.enabled)?,
+    };
+    listener.set_nonblocking(true)?;
+    Ok(TcpListener::from_std(listener)?)
+}
+
+fn bind(port: u16, reuse_port: bool) -> Result<std::net::TcpListener> {
+    let addr = SocketAddr::from(([0, 0, 0, 0], port));
+    let socket = Socket::new(Domain::IPV4, Type::STREAM, Some(Protocol::TCP))?;
+    socket.set_reuse_address(true)?;
+    if reuse_port {
+        socket.set_reuse_port(true)?;
+    }
+    socket
+        .bind(&addr.into())
+        .with_context(|| format!("binding {}", addr))?;
+    socket.listen(1024)?;
+    Ok(socket.into())
+}
+
+/// A camera this instance is serving; dropping it hands the camera over
+pub struct Claim {
+    name: String,
+    _lock: Option<File>,
+}
+
+impl Drop for Claim {
+    fn drop(&mut self) {
+        SERVING.lock().unwrap().remove(&self.name);
+        RELEASED.notify_waiters();
+    }
+}
+
+/// Claims camera `name`, waiting for a previous instance to release it when
+/// handover is enabled
+pub async fn claim(name: &str, config: &HandoverConfig) -> Result<Claim> {
+    let lock = if config.enabled {
+        Some(lock(name, config).await?)
+    } else {
+        None
+    };
+    SERVING.lock().unwrap().insert(name.to_string());
+    Ok(Claim {
+        name: name.to_string(),
+        _lock: lock,
+    })
+}
+
+async fn lock(name: &str, config: &HandoverConfig) -> Result<File> {
+    std::fs::create_dir_all(&config.lock_dir)?;
+    let path = Path::new(&config.lock_dir).join(format!("{}.lock", name));
+    let file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(false)
+        .open(&path)
+        .with_context(|| format!("opening {}", path.display()))?;
+
+    let mut waiting = false;
+    loop {
+        match file.try_lock() {
+            Ok(()) => {
+                if waiting {
+                    log::info!("Took over {} from the previous instance", name);
+                }
+                return Ok(file);
+            }
+            Err(TryLockError::WouldBlock) => {
+                if !waiting {
+                    log::info!("Waiting for the previous instance to hand over {}", name);
+                    waiting = true;
+                }
+                tokio::time::sleep(POLL_INTERVAL).await;
+            }
+            Err(TryLockError::Error(e)) => {
+                return Err(e).with_context(|| format!("locking {}", path.display()));
+            }
+        }
+    }
+}
+
+/// Stops taking new sessions and hands cameras over as they go idle
+pub fn start_drain() {
+    DRAIN.cancel();
+}
+
+/// Resolves once a drain has started
+pub async fn draining() {
+    DRAIN.cancelled().await
+}
+
+/// Resolves once every claimed camera has been released
+pub async fn drained() {
+    loop {
+        // Registered before checking, so a release in between isn't missed
+        let released = RELEASED.notified();
+        if SERVING.lock().unwrap().is_empty() {
+            return;
+        }
+        released.await;
+    }
+}
@@ -0,0 +1,94 @@
+//! Horizon leveling from the IMU's roll
+//!
+//! Cheaper than stabilization: with `horizon.mode` set, a camera's roll is
+//! taken from gravity as the accelerometer sees it and low-pass filtered.
+//! It is then either rotated away (`rotate`, up to `max-angle-deg`) or
+//! printed on the frame (`overlay`). Gravity is only a fair reference while
+//! the platform isn't accelerating hard, which the filter smooths over.
+
+use gstreamer as gst;
+use gstreamer::prelude::*;
+use std::time::Instant;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::config::{HorizonConfig, HorizonMode};
+use crate::debug;
+use crate::sensors::icm20948::{Axis, ImuData};
+use crate::sensors::runner;
+
+const IMU_SENSOR: &str = "IMU1";
+
+/// Smallest change in roll worth touching the pipeline for, in degrees
+const MIN_STEP_DEG: f32 = 0.1;
+
+/// Longest gap between samples the filter takes as a single step
+const MAX_STEP_SECS: f32 = 0.5;
+
+/// Roll in degrees, positive when the camera leans to its right, from the
+/// right and up components of an accelerometer reading
+fn roll_deg(right: f32, up: f32) -> f32 {
+    (-right).atan2(up).to_degrees()
+}
+
+/// Levels `camera` from the IMU's readings until cancelled. The camera's
+/// pipeline is looked up by name, so it may come and go.
+pub async fn run_horizon(camera: String, config: HorizonConfig) {
+    let axes = (Axis::parse(&config.right_axis), Axis::parse(&config.up_axis));
+    let (right, up) = match axes {
+        (Ok(right), Ok(up)) => (right, up),
+        (Err(e), _) | (_, Err(e)) => {
+            log::error!("Horizon leveling of {} disabled: {}", camera, e);
+            return;
+        }
+    };
+    let smoothing_secs = config.smoothing_ms as f32 / 1000.0;
+    let mut readings = runner::subscribe();
+    let mut roll: Option<(f32, Instant)> = None;
+    // Element and roll last applied; a new pipeline starts out level
+    let mut applied: Option<(gst::Element, f32)> = None;
+    log::info!("Leveling the horizon of {} ({:?})", camera, config.mode);
+
+    loop {
+        let reading = match readings.recv().await {
+            Ok(reading) if reading.sensor == IMU_SENSOR => reading,
+            Ok(_) | Err(RecvError::Lagged(_)) => continue,
+            Err(RecvError::Closed) => break,
+        };
+        // Error payloads aren't IMU data and are skipped
+        let Ok(data) = serde_json::from_str::<ImuData>(&reading.payload) else {
+            continue;
+        };
+        let sample = roll_deg(right.of(&data.accel), up.of(&data.accel));
+        let now = Instant::now();
+        let smoothed = match roll {
+            Some((previous, at)) => {
+                let dt = now.duration_since(at).as_secs_f32().min(MAX_STEP_SECS);
+                previous + (sample - previous) * dt / (smoothing_secs + dt)
+            }
+            None => sample,
+        };
+        roll = Some((smoothed, now));
+
+        let Some(element) = debug::find_pipeline(&camera).and_then(|p| p.by_name("horizon")) else {
+            continue;
+        };
+        let unchanged = applied.as_ref().is_some_and(|(last, last_roll)| {
+            *last == element && (smoothed - last_roll).abs() < MIN_STEP_DEG
+        });
+        if !unchanged {
+            apply(&element, &config, smoothed);
+            applied = Some((element, smoothed));
+        }
+    }
+}
+
+fn apply(element: &gst::Element, config: &HorizonConfig, roll: f32) {
+    match config.mode {
+        HorizonMode::Off => {}
+        HorizonMode::Rotate => {
+            let max = config.max_angle_deg.abs();
+            element.set_property("angle", roll.clamp(-max, max).to_radians() as f64);
+        }
+        HorizonMode::Overlay => element.set_property("text", format!("roll {:+.1}°", roll)),
+    }
+}
@@ -0,0 +1,127 @@
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use once_cell::sync::Lazy;
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const DEFAULT_CAPACITY: usize = 5000;
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct LogLine {
+    pub ts_ms: u64,
+    pub level: String,
+    pub target: String,
+    pub message: String,
+}
+
+struct RingBuffer {
+    lines: VecDeque<LogLine>,
+    capacity: usize,
+}
+
+static BUFFER: Lazy<Mutex<RingBuffer>> = Lazy::new(|| {
+    Mutex::new(RingBuffer {
+        lines: VecDeque::with_capacity(DEFAULT_CAPACITY),
+        capacity: DEFAULT_CAPACITY,
+    })
+});
+
+/// Logger that keeps the last N records in memory and forwards everything
+/// to env_logger, so RUST_LOG keeps working as before.
+struct BufferedLogger {
+    inner: env_logger::Logger,
+}
+
+impl Log for BufferedLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.inner.matches(record) {
+            return;
+        }
+        self.inner.log(record);
+
+        let line = LogLine {
+            ts_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis() as u64,
+            level: record.level().to_string(),
+            target: record.target().to_string(),
+            message: record.args().to_string(),
+        };
+
+        if let Ok(mut buf) = BUFFER.lock() {
+            if buf.lines.len() >= buf.capacity {
+                buf.lines.pop_front();
+            }
+            buf.lines.push_back(line);
+        }
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+/// Replaces `env_logger::init()`: same RUST_LOG behaviour plus the ring buffer.
+pub fn init() {
+    let inner = env_logger::Builder::from_default_env().build();
+    let max_level = inner.filter();
+    if log::set_boxed_logger(Box::new(BufferedLogger { inner })).is_ok() {
+        log::set_max_level(max_level);
+    }
+}
+
+pub fn set_capacity(capacity: usize) {
+    let capacity = capacity.max(1);
+    if let Ok(mut buf) = BUFFER.lock() {
+        while buf.lines.len() > capacity {
+            buf.lines.pop_front();
+        }
+        buf.capacity = capacity;
+    }
+}
+
+/// Most recent lines (oldest first) at or above `min_level` whose target
+/// contains `target`, limited to the last `limit` matches.
+pub fn query(min_level: LevelFilter, target: Option<&str>, limit: usize) -> Vec<LogLine> {
+    let buf = match BUFFER.lock() {
+        Ok(buf) => buf,
+        Err(_) => return Vec::new(),
+    };
+
+    let mut lines: Vec<LogLine> = buf
+        .lines
+        .iter()
+        .rev()
+        .filter(|line| {
+            line.level
+                .parse::<Level>()
+                .map(|level| level <= min_level)
+                .unwrap_or(true)
+        })
+        .filter(|line| target.map_or(true, |t| line.target.contains(t)))
+        .take(limit)
+        .cloned()
+        .collect();
+    lines.reverse();
+    lines
+}
+
+/// Snapshot of the buffer as plain text, without blocking (for the panic hook).
+pub fn try_dump() -> Option<String> {
+    let buf = BUFFER.try_lock().ok()?;
+    let mut out = String::new();
+    for line in buf.lines.iter() {
+        out.push_str(&format!(
+            "{} {:<5} {}: {}\n",
+            line.ts_ms, line.level, line.target, line.message
+        ));
+    }
+    Some(out)
+}
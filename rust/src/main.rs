@@ -2,26 +2,63 @@ use anyhow::Result;
 use clap::Parser;
 use gstreamer as gst;
 use log::info;
-use std::thread;
 use std::time::Duration;
 use tokio::time::Duration as TokioDuration;
 
 
+mod api_schema;
+mod audit;
+mod boost;
 mod config;
+mod control;
+mod control_channel;
+mod crash;
+mod debug;
+mod doctor;
+#[cfg(feature = "eis")]
+mod eis;
+#[cfg(feature = "grpc")]
+mod grpc;
+mod horizon;
+mod log_buffer;
+mod mjpeg_rtp;
+mod ndi;
 mod sensors;
+mod system_monitor;
+mod tasks;
+mod gst_props;
 mod gst_webrtc;
+mod governor;
+mod handover;
 mod camera;
+mod pause;
+mod pip;
 mod processing;
+mod recording;
+mod recording_encryption;
+mod rtmp;
+mod schedule;
+mod secrets;
+mod streams;
+mod tee_output;
+mod watchdog;
+mod watermark;
 mod webrtc;
+mod web_assets;
 mod web_server;
 
-use crate::config::load_config;
+use crate::config::{load_config, HorizonMode};
 use crate::sensors::{
     icm20948::Imu,
     lidar::{Lidar, LidarType},
+    runner,
 };
+use crate::tasks::{BoxError, CancellationToken, Supervisor, TaskComponent};
 use crate::web_server::run_web_server;
 
+// How long components get to wind down after a shutdown signal before being aborted
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(5);
+
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct CliArgs {
@@ -36,163 +73,129 @@ struct CliArgs {
     /// IP address of this Pi for the web interface. Default auto-detect.
     #[arg(long)]
     pi_ip: Option<String>,
-}
-
-async fn data_producer_task(config: config::Config) -> Result<()> {
-    // This task is now synchronous and will be run in a blocking thread
-    let task = tokio::task::spawn_blocking(move || -> Result<()> {
-        let context = zmq::Context::new();
-        let publisher = context.socket(zmq::PUB)?;
-
-        // Publisher may fail to bind if port is in use – retry with back-off
-        loop {
-            match publisher.bind(&config.zeromq.data_publisher_address) {
-                Ok(_) => break,
-                Err(e) => {
-                    log::error!(
-                        "Cannot bind ZMQ publisher ({}). Retrying in 1 s…",
-                        e
-                    );
-                    thread::sleep(Duration::from_secs(1));
-                }
-            }
-        }
 
-        // Helper closures -----------------------------------------------------
-        fn publish_kv(publisher: &zmq::Socket, topic: &str, payload: &str) {
-            if let Err(e) = publisher.send_multipart(&[topic.as_bytes(), payload.as_bytes()], 0) {
-                log::error!(
-                    "Failed to publish ZMQ message on topic '{}': {}",
-                    topic,
-                    e
-                );
-            }
-        }
-
-        // -------- GPIO / sensor init, tolerant to failures -------------------
-        let gpio = rppal::gpio::Gpio::new()?;
-        let mut tof050c_enable_pin =
-            gpio.get(config.lidar_tof050c.enable_pin)?.into_output();
-        let mut tof400c_enable_pin =
-            gpio.get(config.lidar_tof400c.enable_pin)?.into_output();
+    /// Check GStreamer plugins, cameras, ports and I2C buses, print a
+    /// pass/fail report and exit (non-zero if anything failed).
+    #[arg(long)]
+    doctor: bool,
 
-        tof050c_enable_pin.set_low();
-        tof400c_enable_pin.set_low();
-        thread::sleep(Duration::from_millis(50));
-        tof400c_enable_pin.set_high();
-        thread::sleep(Duration::from_millis(50));
+    /// Print the web API description ("openapi" or "typescript") and exit.
+    #[arg(long, value_name = "FORMAT")]
+    api_schema: Option<String>,
 
-        // Each sensor is optional – if init fails we keep retrying periodically
-        let mut tof400c: Option<Lidar> = None;
-        let mut tof050c: Option<Lidar> = None;
-        let mut imu1: Option<Imu> = None;
+    /// Which streamers to run: the WebRTC cameras, the MJPEG-RTP streamer
+    /// from the [mjpeg-rtp] config section, or both. Default webrtc.
+    #[arg(long, value_enum, default_value_t = Mode::Webrtc)]
+    mode: Mode,
+}
 
-        const RETRY_DELAY: Duration = Duration::from_secs(2);
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum Mode {
+    Webrtc,
+    MjpegRtp,
+    Both,
+}
 
-        log::info!("Data producer task started – entering main loop");
+impl Mode {
+    fn webrtc(self) -> bool {
+        matches!(self, Mode::Webrtc | Mode::Both)
+    }
 
-        loop {
-            // --- (re)initialize sensors when needed -------------------------
-            if tof400c.is_none() {
-                match Lidar::new(config.lidar_tof400c.i2c_bus, 0x29, LidarType::Tof400c) {
-                    Ok(mut l) => {
-                        if let Some(new_addr) = config.lidar_tof400c.new_i2c_address {
-                            if let Err(e) = l.change_address(new_addr) {
-                                log::error!("Failed to change TOF400C address: {}", e);
-                            }
-                        }
-                        tof400c = Some(l);
-                        log::info!("TOF400C initialised");
-                    }
-                    Err(e) => {
-                        publish_kv(
-                            &publisher,
-                            &config.app.topics.lidar_tof050c,
-                            &format!("ERROR init TOF400C: {}", e),
-                        );
-                        thread::sleep(RETRY_DELAY);
-                    }
-                }
-            }
+    fn mjpeg_rtp(self) -> bool {
+        matches!(self, Mode::MjpegRtp | Mode::Both)
+    }
+}
 
-            if tof050c.is_none() {
-                match Lidar::new(config.lidar_tof050c.i2c_bus, 0x29, LidarType::Tof050c) {
-                    Ok(l) => {
-                        tof050c = Some(l);
-                        log::info!("TOF050C initialised");
-                    }
-                    Err(e) => {
-                        publish_kv(
-                            &publisher,
-                            &config.app.topics.lidar_tof050c,
-                            &format!("ERROR init TOF050C: {}", e),
-                        );
-                        thread::sleep(RETRY_DELAY);
-                    }
-                }
-            }
+async fn data_producer_task(config: config::Config, token: CancellationToken) -> Result<()> {
+    let (publisher, messages) = tokio::sync::mpsc::channel(runner::PUBLISH_QUEUE);
+    let address = config.zeromq.data_publisher_address.clone();
+    let publish_token = token.clone();
+    let publish = tokio::task::spawn_blocking(move || {
+        runner::run_publisher(&address, messages, publish_token)
+    });
 
-            if imu1.is_none() {
-                match Imu::new(config.imu_1.i2c_bus, config.imu_1.address, "IMU1") {
-                    Ok(i) => {
-                        imu1 = Some(i);
-                        log::info!("IMU1 initialised");
-                    }
-                    Err(e) => {
-                        publish_kv(
-                            &publisher,
-                            &config.app.topics.imu_1,
-                            &format!("ERROR init IMU1: {}", e),
-                        );
-                        thread::sleep(RETRY_DELAY);
-                    }
+    // -------- GPIO / sensor init, tolerant to failures -------------------
+    // The pins are held until shutdown so the lidars stay powered as set
+    let gpio = rppal::gpio::Gpio::new()?;
+    let mut tof050c_enable_pin = gpio.get(config.lidar_tof050c.enable_pin)?.into_output();
+    let mut tof400c_enable_pin = gpio.get(config.lidar_tof400c.enable_pin)?.into_output();
+
+    tof050c_enable_pin.set_low();
+    tof400c_enable_pin.set_low();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    tof400c_enable_pin.set_high();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    let default_interval = config.app.data_producer_loop_ms;
+    let timing = |interval_ms: Option<u64>, timeout_ms: u64| runner::Timing {
+        interval: Duration::from_millis(interval_ms.unwrap_or(default_interval).max(1)),
+        timeout: Duration::from_millis(timeout_ms),
+    };
+
+    // Each sensor is optional and polled by its own task, so one that fails
+    // or hangs doesn't hold up the others
+    let mut sensors = tokio::task::JoinSet::new();
+    let tof400c = config.lidar_tof400c.clone();
+    sensors.spawn(runner::run_sensor(
+        "TOF400C",
+        config.app.topics.lidar_tof400c.clone(),
+        timing(tof400c.interval_ms, tof400c.timeout_ms),
+        move || {
+            let mut lidar = Lidar::new(tof400c.i2c_bus, 0x29, LidarType::Tof400c)?;
+            if let Some(new_addr) = tof400c.new_i2c_address {
+                if let Err(e) = lidar.change_address(new_addr) {
+                    log::error!("Failed to change TOF400C address: {}", e);
                 }
             }
-
-            // --- gather sensor data ----------------------------------------
-            if let Some(ref mut lidar) = tof050c {
-                match lidar.read_distance_mm() {
-                    Ok(dist) => publish_kv(
-                        &publisher,
-                        &config.app.topics.lidar_tof050c,
-                        &dist.to_string(),
-                    ),
-                    Err(e) => {
-                        log::warn!("TOF050C read error: {}", e);
-                        publish_kv(
-                            &publisher,
-                            &config.app.topics.lidar_tof050c,
-                            &format!("ERROR: {}", e),
-                        );
-                        tof050c = None; // force re-init
-                    }
-                }
+            Ok(lidar)
+        },
+        publisher.clone(),
+    ));
+    let tof050c = config.lidar_tof050c.clone();
+    sensors.spawn(runner::run_sensor(
+        "TOF050C",
+        config.app.topics.lidar_tof050c.clone(),
+        timing(tof050c.interval_ms, tof050c.timeout_ms),
+        move || Lidar::new(tof050c.i2c_bus, 0x29, LidarType::Tof050c),
+        publisher.clone(),
+    ));
+    let imu = config.imu_1.clone();
+    sensors.spawn(runner::run_sensor(
+        "IMU1",
+        config.app.topics.imu_1.clone(),
+        timing(imu.interval_ms, imu.timeout_ms),
+        move || Imu::new(imu.i2c_bus, imu.address, "IMU1"),
+        publisher.clone(),
+    ));
+
+    // --- system health (temps, throttling) ------------------------
+    let system_topic = config.app.topics.system.clone();
+    sensors.spawn(async move {
+        let mut ticker = tokio::time::interval(Duration::from_millis(default_interval.max(1)));
+        let mut last_system_ts = 0u64;
+        loop {
+            ticker.tick().await;
+            let Some(stats) = system_monitor::latest() else {
+                continue;
+            };
+            if stats.ts_ms == last_system_ts {
+                continue;
             }
-
-            if let Some(ref mut imu) = imu1 {
-                match imu.read_data() {
-                    Ok(data) => {
-                        if let Ok(json) = serde_json::to_string(&data) {
-                            publish_kv(&publisher, &config.app.topics.imu_1, &json);
-                        }
-                    }
-                    Err(e) => {
-                        log::warn!("IMU1 read error: {}", e);
-                        publish_kv(
-                            &publisher,
-                            &config.app.topics.imu_1,
-                            &format!("ERROR: {}", e),
-                        );
-                        imu1 = None; // force re-init
-                    }
+            last_system_ts = stats.ts_ms;
+            if let Ok(json) = serde_json::to_string(&stats) {
+                if publisher.send((system_topic.clone(), json)).await.is_err() {
+                    return;
                 }
             }
-
-            thread::sleep(Duration::from_millis(config.app.data_producer_loop_ms));
         }
     });
 
-    task.await?
+    log::info!("Data producer started – polling sensors");
+    token.cancelled().await;
+
+    // Dropping the sensor tasks closes the channel, which ends the publisher
+    sensors.shutdown().await;
+    publish.await?
 }
 
 fn get_local_ip() -> String {
@@ -212,9 +215,23 @@ fn get_local_ip() -> String {
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    env_logger::init();
+    log_buffer::init();
 
     let args = CliArgs::parse();
+
+    match args.api_schema.as_deref() {
+        Some("openapi") => {
+            println!("{}", serde_json::to_string_pretty(&api_schema::openapi())?);
+            return Ok(());
+        }
+        Some("typescript") => {
+            print!("{}", api_schema::typescript());
+            return Ok(());
+        }
+        Some(other) => anyhow::bail!("unknown API schema format '{}' (use openapi or typescript)", other),
+        None => {}
+    }
+
     log::info!("Starting application with args: {:?}", args);
 
     // MEMORY LEAK DEBUGGING: Set GStreamer debug environment for buffer tracking
@@ -229,62 +246,95 @@ async fn main() -> Result<()> {
     // Initialize GStreamer once globally
     gst::init()?;
 
+    if args.doctor {
+        let passed = doctor::run(args.web_port, args.base_port);
+        std::process::exit(if passed { 0 } else { 1 });
+    }
+
     let config_master = load_config()?;
+    info!("Config loaded, hash {}", config_master.hash());
+    log_buffer::set_capacity(config_master.diagnostics.log_buffer_lines);
+    crash::install_panic_hook(&config_master);
+    audit::init(&config_master.audit)?;
     
     // Determine PI IP address
     let pi_ip = args.pi_ip.unwrap_or_else(get_local_ip);
 
-    // Spawn the data producer as an async task (unaffected by cameras)
-    let producer_config = config_master.clone();
-    let producer_handle = tokio::spawn(async move {
-        if let Err(e) = data_producer_task(producer_config).await {
-            log::error!("Data producer task failed: {}", e);
-        }
-    });
+    // Every long-running task is a component of this supervisor, so a
+    // shutdown signal stops all of them and one that fails is restarted on
+    // its own. They start in the order added: web server, sensors, cameras,
+    // then what works on the cameras' output.
+    let mut supervisor = Supervisor::with_states("main", tasks::components().clone());
 
-    // Spawn the integrated web server
-    let web_pi_ip = pi_ip.clone();
+    // Forward the web UI's control data channel to the robot
+    if let Err(e) = control_channel::start(&config_master.control_channel) {
+        log::error!("Control channel forwarder failed to start: {}", e);
+    }
+
+    // The integrated web server
     let web_config = config_master.clone();
-    let _web_handle = tokio::spawn(async move {
-        if let Err(e) = run_web_server(args.web_port, web_pi_ip, web_config).await {
-            log::error!("Web server failed: {}", e);
+    let web_port = args.web_port;
+    let base_port = args.base_port;
+    let web_pi_ip = pi_ip.clone();
+    supervisor.add(TaskComponent::new("web server", move |token| {
+        let (pi_ip, config) = (web_pi_ip.clone(), web_config.clone());
+        async move {
+            tokio::select! {
+                _ = token.cancelled() => Ok(()),
+                result = run_web_server(web_port, pi_ip, base_port, config) => Ok(result?),
+            }
         }
-    });
-
-    // Spawn WebRTC streamers for each camera on consecutive ports --------
-    let port_cam1 = args.base_port;
-    let port_cam2 = port_cam1 + 1;
-
-    // ---- Cam1 via GStreamer webrtcbin
-    let cfg_cam1 = config_master.clone();
-    log::info!("🚀 Spawning camera 1 task for device {} on port {}", cfg_cam1.camera_1.device, port_cam1);
-    let cfg_cam1_move = cfg_cam1.clone();  // Clone before moving
-    let handle_cam1 = tokio::spawn(async move {
-        match gst_webrtc::run_camera(cfg_cam1_move.clone(), cfg_cam1_move.camera_1.clone(), port_cam1).await {
-            Ok(_) => log::info!("Camera 1 task completed normally"),
-            Err(e) => log::error!("❌ Camera 1 task failed: {}", e),
+    }));
+
+    // The gRPC control service on its own port
+    if config_master.grpc.enabled {
+        #[cfg(feature = "grpc")]
+        {
+            let grpc_pi_ip = pi_ip.clone();
+            let grpc_config = config_master.clone();
+            supervisor.add(TaskComponent::new("grpc server", move |token| {
+                let (pi_ip, config) = (grpc_pi_ip.clone(), grpc_config.clone());
+                async move {
+                    tokio::select! {
+                        _ = token.cancelled() => Ok(()),
+                        result = grpc::run_grpc_server(pi_ip, base_port, config) => Ok(result?),
+                    }
+                }
+            }));
         }
-    });
+        #[cfg(not(feature = "grpc"))]
+        log::warn!("grpc.enabled is set but this build has no gRPC support (build with --features grpc)");
+    }
 
-    // ---- Cam2
-    let mut cfg_cam2 = cfg_cam1.clone();  // Now we can use cfg_cam1 again
-    cfg_cam2.camera_1 = cfg_cam2.camera_2.clone();
-    log::info!("🚀 Spawning camera 2 task for device {} on port {}", cfg_cam2.camera_1.device, port_cam2);
-    let handle_cam2 = tokio::spawn(async move {
-        match gst_webrtc::run_camera(cfg_cam2.clone(), cfg_cam2.camera_1.clone(), port_cam2).await {
-            Ok(_) => log::info!("Camera 2 task completed normally"),
-            Err(e) => log::error!("❌ Camera 2 task failed: {}", e),
-        }
-    });
+    // The system monitor (thermal / throttling / load)
+    let monitor_config = config_master.system_monitor.clone();
+    supervisor.add(TaskComponent::new("system monitor", move |token| {
+        until_cancelled(token, system_monitor::run_system_monitor(monitor_config.clone()))
+    }));
+
+    // The data producer polling the sensors (unaffected by cameras)
+    let producer_config = config_master.clone();
+    supervisor.add(TaskComponent::new("sensors", move |token| {
+        let producer = data_producer_task(producer_config.clone(), token);
+        async move { Ok(producer.await?) }
+    }));
+
+    // The WebRTC cameras and everything that acts on them
+    if args.mode.webrtc() {
+        add_webrtc_components(&mut supervisor, &config_master, args.base_port);
+    }
 
     // ENHANCED MEMORY MONITORING: More aggressive cleanup task
-    let _cleanup_handle = tokio::spawn(async move {
+    supervisor.add(TaskComponent::new("memory monitor", |token| async move {
         let mut interval = tokio::time::interval(TokioDuration::from_secs(120)); // Every 2 minutes
         let mut memory_samples = Vec::new();
         let mut last_rss = 0u32;
         
         loop {
-            interval.tick().await;
+            tokio::select! {
+                _ = token.cancelled() => break,
+                _ = interval.tick() => {}
+            }
             
             // Get detailed memory information
             if let Ok(mem_info) = std::fs::read_to_string("/proc/self/status") {
@@ -305,6 +355,13 @@ async fn main() -> Result<()> {
                     }
                 }
                 
+                crash::update_stats_snapshot(serde_json::json!({
+                    "rss_kb": current_rss,
+                    "memory_samples_mb": memory_samples,
+                    "cameras": debug::registered_cameras(),
+                    "system": system_monitor::latest(),
+                }));
+
                 // Track memory growth trend
                 if current_rss > 0 {
                     let memory_mb = current_rss / 1024;
@@ -355,13 +412,234 @@ async fn main() -> Result<()> {
                 }
             }
         }
-    });
+        Ok(())
+    }));
+
+    supervisor.start().await?;
+
+    // The MJPEG-RTP streamer from the [mjpeg-rtp] section
+    let mut mjpeg_supervisor = None;
+    if args.mode.mjpeg_rtp() {
+        let mjpeg_config = config::load_mjpeg_rtp_config()?;
+        if args.mode.webrtc() {
+            for device in mjpeg_rtp::share_cameras(&config_master, &mjpeg_config) {
+                log::info!("{} is shared: its WebRTC camera captures, MJPEG-RTP takes frames from it", device);
+            }
+        }
+        mjpeg_supervisor = Some(mjpeg_rtp::start(&mjpeg_config).await?);
+    }
 
-    log::info!("All tasks spawned. Application is running.");
+    log::info!("All components started. Application is running.");
 
-    producer_handle.await?;
-    let _ = handle_cam1.await;  // Camera tasks now handle their own errors
-    let _ = handle_cam2.await;  // Camera tasks now handle their own errors
+    tokio::select! {
+        result = wait_for_shutdown_signal() => result?,
+        result = wait_for_drain_signal() => {
+            result?;
+            drain(Duration::from_secs(config_master.handover.drain_timeout_secs)).await?;
+        }
+    }
+    log::info!("Shutting down, waiting up to {:?} for components to stop", SHUTDOWN_GRACE);
+    let aborted = match mjpeg_supervisor {
+        Some(mut mjpeg_supervisor) => {
+            let (main, mjpeg) = tokio::join!(
+                supervisor.shutdown(SHUTDOWN_GRACE),
+                mjpeg_supervisor.shutdown(SHUTDOWN_GRACE)
+            );
+            main + mjpeg
+        }
+        None => supervisor.shutdown(SHUTDOWN_GRACE).await,
+    };
+    if aborted > 0 {
+        log::warn!("{} component(s) still running after {:?}, aborted", aborted, SHUTDOWN_GRACE);
+    }
 
     Ok(())
 }
+
+// Adds the WebRTC streamers for each camera on consecutive ports, and the
+// tasks that adjust or recover them once their camera runs
+fn add_webrtc_components(supervisor: &mut Supervisor, config_master: &config::Config, base_port: u16) {
+    let components = ["camera1", "camera2"];
+    for (index, (component, (stream, cam))) in components.into_iter().zip(config_master.streams()).enumerate() {
+        let port = base_port + index as u16;
+        // Code under run_camera may read the camera from camera_1
+        let mut cfg = config_master.clone();
+        cfg.camera_1 = cam.clone();
+        let cam = cam.clone();
+        log::info!("🚀 Adding {} task '{}' for device {} on port {}", component, stream, cam.device, port);
+        supervisor.add(TaskComponent::new(component, move |token| {
+            let (cfg, cam, stream) = (cfg.clone(), cam.clone(), stream.clone());
+            async move {
+                tokio::select! {
+                    _ = token.cancelled() => Ok(()),
+                    result = gst_webrtc::run_camera(cfg, cam, &stream, port) => Ok(result?),
+                }
+            }
+        }));
+    }
+
+    // Per-camera watchdogs recovering cameras that stop delivering frames
+    for (camera, (name, cam)) in components.into_iter().zip(config_master.streams()) {
+        if !cam.recovery.enabled {
+            continue;
+        }
+        let device = cam.device.clone();
+        let recovery = cam.recovery.clone();
+        supervisor.add(
+            TaskComponent::new(format!("{} watchdog", camera), move |token| {
+                until_cancelled(token, watchdog::run_watchdog(name.clone(), device.clone(), recovery.clone()))
+            })
+            .after(&[camera]),
+        );
+    }
+
+    // Gyro-based stabilization of the cameras that ask for it
+    #[cfg(feature = "eis")]
+    for (camera, (name, cam)) in components.into_iter().zip(config_master.streams()) {
+        if !cam.stabilization.enabled {
+            continue;
+        }
+        let cam = cam.clone();
+        supervisor.add(
+            TaskComponent::new(format!("{} eis", camera), move |token| {
+                until_cancelled(token, eis::run_stabilizer(name.clone(), cam.clone()))
+            })
+            .after(&[camera, "sensors"]),
+        );
+    }
+    #[cfg(not(feature = "eis"))]
+    for (name, cam) in config_master.streams() {
+        if cam.stabilization.enabled {
+            log::warn!(
+                "{}.stabilization is enabled but this build has no EIS (build with --features eis)",
+                name
+            );
+        }
+    }
+
+    // Horizon leveling from the IMU's roll
+    for (camera, (name, cam)) in components.into_iter().zip(config_master.streams()) {
+        if cam.horizon.mode == HorizonMode::Off {
+            continue;
+        }
+        let horizon = cam.horizon.clone();
+        supervisor.add(
+            TaskComponent::new(format!("{} horizon", camera), move |token| {
+                until_cancelled(token, horizon::run_horizon(name.clone(), horizon.clone()))
+            })
+            .after(&[camera, "sensors"]),
+        );
+    }
+
+    // Other cameras' video inset into the cameras that ask for it
+    for (camera, (name, cam)) in components.into_iter().zip(config_master.streams()) {
+        let Some(source) = cam.pip.source.clone() else {
+            continue;
+        };
+        supervisor.add(
+            TaskComponent::new(format!("{} pip", camera), move |token| {
+                until_cancelled(token, pip::run_pip(name.clone(), source.clone()))
+            })
+            .after(&components),
+        );
+    }
+
+    // Raise stream quality while the lidar sees something close
+    if config_master.quality_boost.enabled {
+        let boost_config = config_master.clone();
+        supervisor.add(
+            TaskComponent::new("quality boost", move |token| {
+                until_cancelled(token, boost::run_quality_boost(boost_config.clone()))
+            })
+            .after(&["camera1", "camera2", "sensors"]),
+        );
+    }
+
+    // Lower the frame rate while the CPU can't keep up
+    if config_master.fps_governor.enabled {
+        let governor_config = config_master.clone();
+        supervisor.add(
+            TaskComponent::new("fps governor", move |token| {
+                until_cancelled(token, governor::run_fps_governor(governor_config.clone()))
+            })
+            .after(&["camera1", "camera2", "system monitor"]),
+        );
+    }
+
+    // Streaming windows and quiet hours
+    if config_master.schedule.enabled {
+        let schedule_config = config_master.clone();
+        supervisor.add(
+            TaskComponent::new("schedule", move |token| {
+                until_cancelled(token, schedule::run_schedule(schedule_config.clone()))
+            })
+            .after(&components),
+        );
+    }
+
+    // Pushes to RTMP(S) ingest servers
+    if config_master.rtmp.enabled {
+        let rtmp_config = config_master.clone();
+        supervisor.add(
+            TaskComponent::new("rtmp", move |token| until_cancelled(token, rtmp::run_rtmp(rtmp_config.clone())))
+                .after(&components),
+        );
+    }
+
+    // NDI sources for video mixers on the LAN
+    if config_master.ndi.enabled {
+        let ndi_config = config_master.clone();
+        supervisor.add(
+            TaskComponent::new("ndi", move |token| until_cancelled(token, ndi::run_ndi(ndi_config.clone())))
+                .after(&components),
+        );
+    }
+}
+
+// Runs a task that only ends on its own when it has nothing (more) to do,
+// until `token` is cancelled
+async fn until_cancelled(
+    token: CancellationToken,
+    task: impl std::future::Future<Output = ()>,
+) -> Result<(), BoxError> {
+    tokio::select! {
+        _ = token.cancelled() => {}
+        _ = task => {}
+    }
+    Ok(())
+}
+
+// SIGUSR2 when a new instance is taking over
+async fn wait_for_drain_signal() -> Result<()> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    signal(SignalKind::user_defined2())?.recv().await;
+    Ok(())
+}
+
+// Lets cameras finish their sessions and hand over before shutting down; a
+// second shutdown signal or the timeout cuts the drain short
+async fn drain(timeout: Duration) -> Result<()> {
+    log::info!("Draining for handover, waiting up to {:?} for cameras to go idle", timeout);
+    handover::start_drain();
+    tokio::select! {
+        _ = handover::drained() => log::info!("All cameras handed over"),
+        _ = tokio::time::sleep(timeout) => {
+            log::warn!("Drain timed out, stopping with sessions still open");
+        }
+        result = wait_for_shutdown_signal() => result?,
+    }
+    Ok(())
+}
+
+// Ctrl+C when run by hand, SIGTERM when stopped by systemd
+async fn wait_for_shutdown_signal() -> Result<()> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut sigterm = signal(SignalKind::terminate())?;
+    tokio::select! {
+        result = tokio::signal::ctrl_c() => result?,
+        _ = sigterm.recv() => {}
+    }
+    Ok(())
+}
@@ -0,0 +1,213 @@
+//! The MJPEG-RTP streamer, in this process
+//!
+//! `--mode mjpeg-rtp` or `--mode both` runs rust-mjpeg-rtp's runtime from
+//! the `[mjpeg-rtp]` section of our config file, instead of a second
+//! process configured separately. It keeps its own cameras, relay and
+//! control API; its cameras' counters show up in `/api/stats` and its
+//! components' states in `/api/status`.
+//!
+//! A device can only be opened once, so in `both` mode an MJPEG-RTP camera
+//! on a WebRTC camera's device doesn't open it. It is fed from that
+//! camera's raw_tee (after crop, flip and pause blackout) through a
+//! separate `appsrc ! jpegenc ! appsink` pipeline (see `tee_output`),
+//! scaled to its own size and rate and encoded at the quality its
+//! congestion control asks for. A tap that fails is restarted after
+//! RESTART_DELAY, as long as the MJPEG-RTP camera wants frames.
+
+use anyhow::{anyhow, Result};
+use bytes::Bytes;
+use gstreamer as gst;
+use gstreamer::prelude::*;
+use gstreamer_app as gst_app;
+use once_cell::sync::{Lazy, OnceCell};
+use rust_mjpeg_rtp::api::ApiRegistry;
+use rust_mjpeg_rtp::tap::{DeviceTaps, FrameTap, TapError, TapRequest};
+use rust_mjpeg_rtp::supervisor::{ComponentStatus, Supervisor};
+use rust_mjpeg_rtp::Frame;
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::{mpsc, watch};
+use tokio::time::{sleep, Duration};
+
+use crate::config::Config;
+use crate::tee_output::{self, Tee};
+
+/// Output pipeline; size, rate and quality are set per tap
+const TAP_PIPELINE: &str =
+    "appsrc name=src is-live=true format=time ! videoconvert ! videoscale ! videorate ! \
+     capsfilter name=caps ! jpegenc name=enc ! appsink name=sink sync=false max-buffers=2 drop=true";
+
+const RESTART_DELAY: Duration = Duration::from_secs(5);
+
+// Set once the runtime has started, for the web server
+static REGISTRY: OnceCell<ApiRegistry> = OnceCell::new();
+
+// Devices the MJPEG-RTP cameras take from a WebRTC camera
+static TAPS: Lazy<DeviceTaps> = Lazy::new(DeviceTaps::default);
+
+// WebRTC cameras currently feeding a tap, by stream name
+static TAPPED: Lazy<Mutex<HashSet<String>>> = Lazy::new(|| Mutex::new(HashSet::new()));
+
+/// Starts everything `[mjpeg-rtp]` enables. Shutting the returned
+/// supervisor down stops it.
+pub async fn start(config: &rust_mjpeg_rtp::config::Config) -> Result<Supervisor> {
+    let registry = REGISTRY.get_or_init(ApiRegistry::default);
+    rust_mjpeg_rtp::app::start(config, registry, &TAPS).await
+}
+
+/// Stats of each streaming MJPEG-RTP camera by name, or None when the
+/// streamer isn't running in this process
+pub fn stats() -> Option<serde_json::Value> {
+    let registry = REGISTRY.get()?;
+    serde_json::to_value(registry.stats()).ok()
+}
+
+/// States of the streamer's components, or None when it isn't running in
+/// this process
+pub fn components() -> Option<Vec<ComponentStatus>> {
+    REGISTRY.get().map(|registry| registry.components().snapshot())
+}
+
+/// Whether `camera` feeds an MJPEG-RTP camera, so its pipeline must keep
+/// running without viewers
+pub fn is_tapped(camera: &str) -> bool {
+    TAPPED.lock().unwrap().contains(camera)
+}
+
+/// Has every enabled MJPEG-RTP camera on a WebRTC camera's device take its
+/// frames from that camera; call before [`start`]. Returns the shared
+/// devices.
+pub fn share_cameras(config: &Config, mjpeg: &rust_mjpeg_rtp::config::Config) -> Vec<String> {
+    let section = &mjpeg.mjpeg_rtp;
+    if !section.enabled {
+        return Vec::new();
+    }
+    let mut shared = Vec::new();
+    for camera in [&section.camera1, &section.camera2] {
+        if !camera.enabled || camera.replay.is_some() {
+            continue;
+        }
+        let owner = config
+            .streams()
+            .into_iter()
+            .find(|(_, cam)| cam.rtp_input.is_none() && cam.device == camera.device);
+        if let Some((name, _)) = owner {
+            TAPS.insert(camera.device.clone(), Arc::new(CameraTap { camera: name }));
+            shared.push(camera.device.clone());
+        }
+    }
+    shared
+}
+
+/// JPEG frames from a WebRTC camera's raw_tee
+struct CameraTap {
+    camera: String,
+}
+
+impl FrameTap for CameraTap {
+    fn start(&self, request: TapRequest) -> Result<(), TapError> {
+        tokio::spawn(run_tap(self.camera.clone(), request));
+        Ok(())
+    }
+}
+
+async fn run_tap(camera: String, request: TapRequest) {
+    TAPPED.lock().unwrap().insert(camera.clone());
+    // Ids keep counting across restarts, so the gap shows as dropped frames
+    let next_id = Arc::new(AtomicU64::new(0));
+    loop {
+        log::info!("Feeding MJPEG-RTP from {}", camera);
+        let error = match feed(&camera, &request, &next_id).await {
+            Ok(()) => "output ended".to_string(),
+            Err(e) => e.to_string(),
+        };
+        if request.frames.is_closed() {
+            break;
+        }
+        log::warn!(
+            "MJPEG-RTP tap on {} stopped: {}; restarting in {:?}",
+            camera,
+            error,
+            RESTART_DELAY
+        );
+        tokio::select! {
+            _ = request.frames.closed() => break,
+            _ = sleep(RESTART_DELAY) => {}
+        }
+    }
+    log::info!("Stopped feeding MJPEG-RTP from {}", camera);
+    TAPPED.lock().unwrap().remove(&camera);
+}
+
+/// Runs the tap until it fails or the MJPEG-RTP camera stops
+async fn feed(camera: &str, request: &TapRequest, next_id: &Arc<AtomicU64>) -> Result<()> {
+    let (output, appsrc) = tee_output::output_pipeline(TAP_PIPELINE, Tee::Raw)?;
+    let caps = gst::Caps::builder("video/x-raw")
+        .field("width", request.width as i32)
+        .field("height", request.height as i32)
+        .field("framerate", gst::Fraction::new(request.fps as i32, 1))
+        .build();
+    output
+        .by_name("caps")
+        .ok_or_else(|| anyhow!("tap has no capsfilter"))?
+        .set_property("caps", &caps);
+    let encoder = output
+        .by_name("enc")
+        .ok_or_else(|| anyhow!("tap has no encoder"))?;
+    encoder.set_property("quality", *request.quality.borrow() as i32);
+
+    let sink = output
+        .by_name("sink")
+        .and_then(|sink| sink.downcast::<gst_app::AppSink>().ok())
+        .ok_or_else(|| anyhow!("tap has no appsink"))?;
+    let frames = request.frames.clone();
+    let next_id = next_id.clone();
+    sink.set_callbacks(
+        gst_app::AppSinkCallbacks::builder()
+            .new_sample(move |sink| {
+                let sample = sink.pull_sample().map_err(|_| gst::FlowError::Eos)?;
+                let Some(buffer) = sample.buffer() else {
+                    return Ok(gst::FlowSuccess::Ok);
+                };
+                let map = buffer.map_readable().map_err(|_| gst::FlowError::Error)?;
+                let pts = buffer
+                    .pts()
+                    .map(|pts| std::time::Duration::from_nanos(pts.nseconds()));
+                let id = next_id.fetch_add(1, Ordering::Relaxed);
+                // A full channel drops the frame, leaving a gap in the ids
+                let _ = frames.try_send(Frame::new(id, Bytes::copy_from_slice(&map)).with_pts(pts));
+                Ok(gst::FlowSuccess::Ok)
+            })
+            .build(),
+    );
+
+    let control = tokio::spawn(control(
+        output.clone(),
+        encoder,
+        request.quality.clone(),
+        request.frames.clone(),
+    ));
+    let result = tee_output::run(camera, Tee::Raw, output, appsrc, || {}).await;
+    control.abort();
+    result
+}
+
+/// Applies quality changes, and ends the output once the MJPEG-RTP camera
+/// stops taking frames
+async fn control(
+    output: gst::Pipeline,
+    encoder: gst::Element,
+    mut quality: watch::Receiver<u32>,
+    frames: mpsc::Sender<Frame>,
+) {
+    loop {
+        tokio::select! {
+            _ = frames.closed() => break,
+            Ok(()) = quality.changed() => {
+                encoder.set_property("quality", *quality.borrow_and_update() as i32);
+            }
+        }
+    }
+    output.send_event(gst::event::Eos::new());
+}
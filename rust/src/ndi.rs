@@ -0,0 +1,121 @@
+//! NDI output for video mixers on the LAN
+//!
+//! With `[ndi]` enabled, each selected camera is announced as an NDI source
+//! named after its stream, which mixers list as `HOSTNAME (camera1)`. NDI
+//! carries uncompressed video, so the output is fed from the camera's
+//! raw_tee (after crop, flip and pause blackout) through a separate
+//! `appsrc ! videoconvert ! ndisink` pipeline (see `tee_output`). Needs
+//! `ndisink` from gst-plugin-ndi and the NDI runtime library; a source that
+//! fails is restarted after RESTART_DELAY.
+
+use anyhow::{bail, Result};
+use gstreamer as gst;
+use gstreamer::prelude::*;
+use once_cell::sync::Lazy;
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use tokio::time::{sleep, Duration};
+
+use crate::config::Config;
+use crate::tee_output::{self, Tee};
+
+/// UYVY is NDI's native format; anything else is converted by the SDK
+const OUTPUT_PIPELINE: &str =
+    "appsrc name=src is-live=true format=time ! videoconvert ! video/x-raw,format=UYVY ! \
+     ndisink name=sink";
+
+const RESTART_DELAY: Duration = Duration::from_secs(5);
+
+// Output state of every NDI camera, keyed by stream name
+static SOURCES: Lazy<Mutex<HashMap<String, NdiStatus>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, JsonSchema)]
+pub(crate) struct NdiStatus {
+    /// Announced and sending video
+    pub sending: bool,
+    /// Why the source last stopped
+    pub last_error: Option<String>,
+    /// Restarts since startup
+    pub restarts: u32,
+}
+
+/// NDI state of `camera`, if it is an NDI source
+pub(crate) fn status(camera: &str) -> Option<NdiStatus> {
+    SOURCES.lock().unwrap().get(camera).cloned()
+}
+
+/// Whether `camera` is an NDI source, so its pipeline must keep running
+/// without viewers
+pub fn is_sending(camera: &str) -> bool {
+    SOURCES.lock().unwrap().contains_key(camera)
+}
+
+/// Checks the cameras of an enabled `[ndi]`, for config validation
+pub fn validate(config: &Config) -> Result<()> {
+    if !config.ndi.enabled {
+        return Ok(());
+    }
+    let streams = config.streams();
+    for camera in &config.ndi.cameras {
+        if !streams.iter().any(|(name, _)| name == camera) {
+            bail!("ndi.cameras: unknown stream '{}'", camera);
+        }
+    }
+    Ok(())
+}
+
+/// Sends every selected camera over NDI until cancelled
+pub async fn run_ndi(config: Config) {
+    if gst::ElementFactory::find("ndisink").is_none() {
+        log::error!("[ndi] is enabled but the ndisink element is missing (install gst-plugin-ndi)");
+        return;
+    }
+    let cameras: Vec<String> = config
+        .streams()
+        .into_iter()
+        .map(|(name, _)| name)
+        .filter(|name| config.ndi.cameras.is_empty() || config.ndi.cameras.contains(name))
+        .collect();
+    for camera in &cameras {
+        SOURCES.lock().unwrap().insert(camera.clone(), NdiStatus::default());
+    }
+    futures_util::future::join_all(cameras.iter().map(|camera| run_source(camera))).await;
+}
+
+async fn run_source(camera: &str) {
+    loop {
+        log::info!("Sending {} over NDI", camera);
+        let error = match send(camera).await {
+            Ok(()) => "output ended".to_string(),
+            Err(e) => e.to_string(),
+        };
+        log::warn!("NDI source {} stopped: {}; restarting in {:?}", camera, error, RESTART_DELAY);
+        update(camera, |status| {
+            status.sending = false;
+            status.last_error = Some(error);
+        });
+        sleep(RESTART_DELAY).await;
+        update(camera, |status| status.restarts += 1);
+    }
+}
+
+fn update(camera: &str, f: impl FnOnce(&mut NdiStatus)) {
+    if let Some(status) = SOURCES.lock().unwrap().get_mut(camera) {
+        f(status);
+    }
+}
+
+/// Runs the source until it fails
+async fn send(camera: &str) -> Result<()> {
+    let (output, appsrc) = tee_output::output_pipeline(OUTPUT_PIPELINE, Tee::Raw)?;
+    if let Some(sink) = output.by_name("sink") {
+        sink.set_property("ndi-name", camera);
+    }
+    tee_output::run(camera, Tee::Raw, output, appsrc, || {
+        update(camera, |status| status.sending = true)
+    })
+    .await
+}
@@ -0,0 +1,169 @@
+use anyhow::{anyhow, Result};
+use gstreamer as gst;
+use gstreamer::prelude::*;
+use once_cell::sync::Lazy;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::debug;
+use crate::webrtc::switch;
+
+// Pause state per camera. Pausing never touches the WebRTC sessions, so
+// viewers stay connected and pick up live video again on resume.
+static PAUSED: Lazy<Mutex<HashMap<String, Paused>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+// Live WebRTC sessions by id, for per-session mute.
+static SESSIONS: Lazy<Mutex<HashMap<u64, Session>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+static NEXT_SESSION_ID: AtomicU64 = AtomicU64::new(1);
+
+/// What viewers see while a camera is paused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum PauseMode {
+    /// Frames keep flowing but are blacked out
+    #[default]
+    Black,
+    /// Frames stop; players keep showing the last one
+    Freeze,
+}
+
+impl std::str::FromStr for PauseMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "black" => Ok(PauseMode::Black),
+            "freeze" => Ok(PauseMode::Freeze),
+            other => Err(anyhow!("unknown pause mode '{}', expected black or freeze", other)),
+        }
+    }
+}
+
+struct Paused {
+    mode: PauseMode,
+    freeze_probe: Option<(gst::Pad, gst::PadProbeId)>,
+}
+
+struct Session {
+    camera: String,
+    peer: String,
+    muted: Arc<AtomicBool>,
+}
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct SessionInfo {
+    pub id: u64,
+    pub camera: String,
+    /// Camera whose video the session shows, `camera` unless switched
+    pub source: String,
+    pub peer: String,
+    pub muted: bool,
+}
+
+pub fn pause_mode(camera: &str) -> Option<PauseMode> {
+    PAUSED.lock().unwrap().get(camera).map(|p| p.mode)
+}
+
+/// Stops sending camera media to every viewer (and recording) while keeping
+/// sessions alive. Pausing an already paused camera switches its mode.
+pub fn pause(camera: &str, mode: PauseMode) -> Result<()> {
+    let pipeline = debug::find_pipeline(camera).ok_or_else(|| anyhow!("unknown camera '{}'", camera))?;
+    let mut paused = PAUSED.lock().unwrap();
+    if let Some(previous) = paused.remove(camera) {
+        restore(&pipeline, previous);
+    }
+
+    // privacy_balance sits right before the raw tee, so both modes cover
+    // every branch: viewers, encoder and recording
+    let balance = pipeline
+        .by_name("privacy_balance")
+        .ok_or_else(|| anyhow!("{} has no privacy_balance element", camera))?;
+    let freeze_probe = match mode {
+        PauseMode::Black => {
+            balance.set_property("contrast", &0.0f64);
+            balance.set_property("saturation", &0.0f64);
+            None
+        }
+        PauseMode::Freeze => {
+            let pad = balance.static_pad("src").ok_or_else(|| anyhow!("videobalance has no src pad"))?;
+            let id = pad
+                .add_probe(gst::PadProbeType::BUFFER, |_, _| gst::PadProbeReturn::Drop)
+                .ok_or_else(|| anyhow!("failed to install freeze probe on {}", camera))?;
+            Some((pad, id))
+        }
+    };
+
+    log::info!("Paused {} ({:?})", camera, mode);
+    paused.insert(camera.to_string(), Paused { mode, freeze_probe });
+    Ok(())
+}
+
+pub fn resume(camera: &str) -> Result<()> {
+    let pipeline = debug::find_pipeline(camera).ok_or_else(|| anyhow!("unknown camera '{}'", camera))?;
+    let previous = PAUSED
+        .lock()
+        .unwrap()
+        .remove(camera)
+        .ok_or_else(|| anyhow!("{} is not paused", camera))?;
+    restore(&pipeline, previous);
+    log::info!("Resumed {}", camera);
+    Ok(())
+}
+
+fn restore(pipeline: &gst::Pipeline, paused: Paused) {
+    if let Some(balance) = pipeline.by_name("privacy_balance") {
+        balance.set_property("contrast", &1.0f64);
+        balance.set_property("saturation", &1.0f64);
+    }
+    if let Some((pad, id)) = paused.freeze_probe {
+        pad.remove_probe(id);
+    }
+}
+
+/// Registers a WebRTC session whose frames are dropped while `muted` is set,
+/// and returns its id.
+pub fn register_session(camera: &str, peer: &str, muted: Arc<AtomicBool>) -> u64 {
+    let id = NEXT_SESSION_ID.fetch_add(1, Ordering::Relaxed);
+    SESSIONS.lock().unwrap().insert(
+        id,
+        Session {
+            camera: camera.to_string(),
+            peer: peer.to_string(),
+            muted,
+        },
+    );
+    id
+}
+
+pub fn unregister_session(id: u64) {
+    SESSIONS.lock().unwrap().remove(&id);
+}
+
+pub fn sessions() -> Vec<SessionInfo> {
+    let mut list: Vec<SessionInfo> = SESSIONS
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(id, s)| SessionInfo {
+            id: *id,
+            camera: s.camera.clone(),
+            source: switch::source(*id).unwrap_or_else(|| s.camera.clone()),
+            peer: s.peer.clone(),
+            muted: s.muted.load(Ordering::Relaxed),
+        })
+        .collect();
+    list.sort_by_key(|s| s.id);
+    list
+}
+
+pub fn set_session_muted(id: u64, muted: bool) -> Result<()> {
+    let sessions = SESSIONS.lock().unwrap();
+    let session = sessions.get(&id).ok_or_else(|| anyhow!("unknown session {}", id))?;
+    session.muted.store(muted, Ordering::Relaxed);
+    log::info!("Session {} ({}) {}", id, session.camera, if muted { "muted" } else { "unmuted" });
+    Ok(())
+}
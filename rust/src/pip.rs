@@ -0,0 +1,362 @@
+//! Picture-in-picture of a second camera
+//!
+//! With `pip.source` set, another camera's video (typically a thermal one)
+//! is composited into a camera's frames, so viewers on a thin link get both
+//! in a single encoded stream instead of two. The inset comes from the other
+//! camera's raw_tee through an appsrc (see `tee_output`) into a compositor
+//! ahead of the watermark and the pause blackout, so recordings and outputs
+//! carry it too. Its position, size and visibility are fractions of the
+//! frame, changed at runtime with the `set-pip` control command. The inset
+//! is only fed while the camera is playing, and keeps the other camera's
+//! pipeline running meanwhile; without video from it the frame goes out
+//! without the inset.
+
+use anyhow::{anyhow, bail, Result};
+use gstreamer as gst;
+use gstreamer::prelude::*;
+use gstreamer_app as gst_app;
+use once_cell::sync::Lazy;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use tokio::time::{interval, Duration};
+
+use crate::config::{CameraConfig, Config};
+use crate::debug;
+use crate::gst_props;
+use crate::pause;
+use crate::tee_output::{self, Branch, Tee};
+
+/// How often the feed checks on both cameras' pipelines
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// An inset without video for this long is attached again, e.g. after the
+/// other camera's pipeline was recreated
+const STALL_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Insets by the stream name of the camera they are drawn into. Layout
+// changes outlive the camera's pipeline.
+static INSETS: Lazy<Mutex<HashMap<String, Inset>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+struct Inset {
+    source: String,
+    layout: PipLayout,
+    /// Frame size the fractions are taken of
+    frame: (u32, u32),
+    feeding: bool,
+}
+
+/// Where the inset goes, in fractions of the frame's width and height
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct PipLayout {
+    /// A hidden inset is still fed, so it shows again at once
+    pub visible: bool,
+    /// Left edge
+    pub x: f32,
+    /// Top edge
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Default for PipLayout {
+    /// A quarter-size inset in the top right corner
+    fn default() -> Self {
+        Self {
+            visible: true,
+            x: 0.72,
+            y: 0.03,
+            width: 0.25,
+            height: 0.25,
+        }
+    }
+}
+
+impl PipLayout {
+    fn validate(&self) -> Result<()> {
+        let fractions = [self.x, self.y, self.width, self.height];
+        if fractions.iter().any(|f| !(0.0..=1.0).contains(f)) {
+            bail!("x, y, width and height must be between 0 and 1");
+        }
+        if self.width == 0.0 || self.height == 0.0 {
+            bail!("width and height must be above 0");
+        }
+        if self.x + self.width > 1.0 || self.y + self.height > 1.0 {
+            bail!("the inset must fit in the frame");
+        }
+        Ok(())
+    }
+}
+
+/// Layout fields to change; the others are kept
+#[derive(Debug, Default)]
+pub(crate) struct PipUpdate {
+    pub visible: Option<bool>,
+    pub x: Option<f32>,
+    pub y: Option<f32>,
+    pub width: Option<f32>,
+    pub height: Option<f32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub(crate) struct PipStatus {
+    /// Camera shown in the inset
+    pub source: String,
+    pub layout: PipLayout,
+    /// Whether its video is being composited in
+    pub feeding: bool,
+}
+
+/// Picture-in-picture state of `camera`, if it has an inset
+pub(crate) fn status(camera: &str) -> Option<PipStatus> {
+    INSETS.lock().unwrap().get(camera).map(|inset| PipStatus {
+        source: inset.source.clone(),
+        layout: inset.layout,
+        feeding: inset.feeding,
+    })
+}
+
+/// Whether `camera` is shown in another camera's inset, so its pipeline
+/// must keep running without viewers
+pub fn is_inset(camera: &str) -> bool {
+    INSETS
+        .lock()
+        .unwrap()
+        .values()
+        .any(|inset| inset.feeding && inset.source == camera)
+}
+
+/// Checks every camera's `pip`, for config validation
+pub fn validate(config: &Config) -> Result<()> {
+    let streams = config.streams();
+    for (name, cam) in &streams {
+        let Some(source) = &cam.pip.source else {
+            continue;
+        };
+        if source == name {
+            bail!("{}.pip.source can't be the camera itself", name);
+        }
+        let Some((_, other)) = streams.iter().find(|(other, _)| other == source) else {
+            bail!("{}.pip.source: unknown stream '{}'", name, source);
+        };
+        // Each would end up inside the other's inset, over and over
+        if other.pip.source.as_ref() == Some(name) {
+            bail!("{} and {} can't be inset into each other", name, source);
+        }
+        cam.pip
+            .layout
+            .validate()
+            .map_err(|e| anyhow!("{}.pip: {}", name, e))?;
+    }
+    Ok(())
+}
+
+/// The compositor a camera's frames go through, and the appsrc its inset
+/// enters by
+pub struct Pip {
+    /// Link in the camera's chain as `compositor ! caps`
+    pub compositor: gst::Element,
+    pub caps: gst::Element,
+    appsrc: gst_app::AppSrc,
+}
+
+impl Pip {
+    /// Elements for `stream`'s inset, if its config asks for one
+    pub fn create(cam: &CameraConfig, stream: &str) -> Result<Option<Self>> {
+        let Some(source) = &cam.pip.source else {
+            return Ok(None);
+        };
+        let compositor = gst::ElementFactory::make("compositor")
+            .name("pip")
+            .build()?;
+        compositor.set_property_from_str("background", "black");
+        // Live frames shouldn't wait for an inset that isn't coming
+        gst_props::set(&compositor, "ignore-inactive-pads", true);
+        gst_props::set(&compositor, "start-time-selection", "first");
+
+        // The inset must not grow the frame
+        let caps = gst::ElementFactory::make("capsfilter")
+            .name("pip_caps")
+            .build()?;
+        caps.set_property(
+            "caps",
+            gst::Caps::builder("video/x-raw")
+                .field("width", cam.target_width as i32)
+                .field("height", cam.target_height as i32)
+                .field("framerate", gst::Fraction::new(cam.fps as i32, 1))
+                .build(),
+        );
+
+        let appsrc = gst_app::AppSrc::builder()
+            .name("pip_src")
+            .is_live(true)
+            .format(gst::Format::Time)
+            .build();
+        tee_output::limit_appsrc(&appsrc, Tee::Raw);
+
+        INSETS
+            .lock()
+            .unwrap()
+            .entry(stream.to_string())
+            .or_insert_with(|| Inset {
+                source: source.clone(),
+                layout: cam.pip.layout,
+                frame: (cam.target_width, cam.target_height),
+                feeding: false,
+            });
+        log::info!("Insetting {} into {}", source, stream);
+        Ok(Some(Self {
+            compositor,
+            caps,
+            appsrc,
+        }))
+    }
+
+    /// Adds the inset's appsrc on top of the frame; call once the
+    /// compositor is linked
+    pub fn attach_inset(&self, pipeline: &gst::Pipeline, stream: &str) -> Result<()> {
+        pipeline.add(&self.appsrc)?;
+        let input = self
+            .compositor
+            .request_pad_simple("sink_%u")
+            .ok_or_else(|| anyhow!("Failed to request sink pad from compositor"))?;
+        self.appsrc
+            .static_pad("src")
+            .ok_or_else(|| anyhow!("appsrc has no src pad"))?
+            .link(&input)?;
+        gst_props::set(&input, "zorder", 1u32);
+        if let Some(inset) = INSETS.lock().unwrap().get(stream) {
+            apply(pipeline, inset)?;
+        }
+        Ok(())
+    }
+}
+
+/// Moves, resizes, shows or hides `camera`'s inset
+pub(crate) fn set_layout(camera: &str, update: PipUpdate) -> Result<()> {
+    let mut insets = INSETS.lock().unwrap();
+    let inset = insets
+        .get_mut(camera)
+        .ok_or_else(|| anyhow!("{} has no picture-in-picture", camera))?;
+    let layout = PipLayout {
+        visible: update.visible.unwrap_or(inset.layout.visible),
+        x: update.x.unwrap_or(inset.layout.x),
+        y: update.y.unwrap_or(inset.layout.y),
+        width: update.width.unwrap_or(inset.layout.width),
+        height: update.height.unwrap_or(inset.layout.height),
+    };
+    layout.validate()?;
+    inset.layout = layout;
+    // A camera without a pipeline picks the layout up when it gets one
+    if let Some(pipeline) = debug::find_pipeline(camera) {
+        apply(&pipeline, inset)?;
+    }
+    log::info!("Picture-in-picture of {}: {:?}", camera, layout);
+    Ok(())
+}
+
+/// Sets the inset's compositor pad to its layout
+fn apply(pipeline: &gst::Pipeline, inset: &Inset) -> Result<()> {
+    let pad = pipeline
+        .by_name("pip_src")
+        .and_then(|src| src.static_pad("src"))
+        .and_then(|src| src.peer())
+        .ok_or_else(|| anyhow!("camera pipeline has no picture-in-picture input"))?;
+    let (width, height) = (inset.frame.0 as f32, inset.frame.1 as f32);
+    let layout = &inset.layout;
+    gst_props::set(&pad, "xpos", (layout.x * width).round() as i32);
+    gst_props::set(&pad, "ypos", (layout.y * height).round() as i32);
+    gst_props::set(&pad, "width", (layout.width * width).round() as i32);
+    gst_props::set(&pad, "height", (layout.height * height).round() as i32);
+    gst_props::set(&pad, "alpha", if layout.visible { 1.0f64 } else { 0.0 });
+    Ok(())
+}
+
+fn set_feeding(camera: &str, feeding: bool) {
+    if let Some(inset) = INSETS.lock().unwrap().get_mut(camera) {
+        inset.feeding = feeding;
+    }
+}
+
+/// A branch of the source camera feeding the inset of a camera pipeline
+struct Feed {
+    branch: Branch,
+    target: gst::Pipeline,
+    source: gst::Pipeline,
+}
+
+/// Feeds `camera`'s inset from `source` whenever `camera` is playing, until
+/// cancelled
+pub async fn run_pip(camera: String, source: String) {
+    let mut feed: Option<Feed> = None;
+    let mut last_error: Option<String> = None;
+    let mut ticker = interval(CHECK_INTERVAL);
+    loop {
+        ticker.tick().await;
+        let target =
+            debug::find_pipeline(&camera).filter(|p| p.current_state() == gst::State::Playing);
+        let source_pipeline = debug::find_pipeline(&source);
+
+        if let Some(current) = &feed {
+            // A frozen camera sends nothing on purpose
+            let stalled =
+                current.branch.idle_for() > STALL_TIMEOUT && pause::pause_mode(&source).is_none();
+            let replaced = target.as_ref() != Some(&current.target)
+                || source_pipeline.as_ref() != Some(&current.source);
+            if !stalled && !replaced {
+                continue;
+            }
+            if stalled {
+                log::warn!(
+                    "No video from {} for {:?}, attaching the inset again",
+                    source,
+                    STALL_TIMEOUT
+                );
+            }
+            if let Some(old) = feed.take() {
+                set_feeding(&camera, false);
+                let _ = tokio::task::spawn_blocking(move || old.branch.detach()).await;
+            }
+        }
+
+        let (Some(target), Some(source_pipeline)) = (target, source_pipeline) else {
+            continue;
+        };
+        match attach(&camera, &source, &target, &source_pipeline).await {
+            Ok(branch) => {
+                log::info!("Compositing {} into {}", source, camera);
+                set_feeding(&camera, true);
+                last_error = None;
+                feed = Some(Feed {
+                    branch,
+                    target,
+                    source: source_pipeline,
+                });
+            }
+            Err(e) => {
+                let error = e.to_string();
+                if last_error.as_ref() != Some(&error) {
+                    log::warn!("Can't inset {} into {}: {}", source, camera, error);
+                }
+                last_error = Some(error);
+            }
+        }
+    }
+}
+
+async fn attach(
+    camera: &str,
+    source: &str,
+    target: &gst::Pipeline,
+    source_pipeline: &gst::Pipeline,
+) -> Result<Branch> {
+    let appsrc = target
+        .by_name("pip_src")
+        .and_then(|src| src.downcast::<gst_app::AppSrc>().ok())
+        .ok_or_else(|| anyhow!("{} has no picture-in-picture input", camera))?;
+    let on_sample = tee_output::feed_retimed(source_pipeline, target, &appsrc);
+    let source = source.to_string();
+    tokio::task::spawn_blocking(move || Branch::attach(&source, Tee::Raw, on_sample)).await?
+}
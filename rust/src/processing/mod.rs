@@ -76,13 +76,8 @@ impl VideoProcessor {
         }
     }
 
-    /// Request next frame to be encoded as IDR (keyframe) – ignored if encoder API lacks the call.
+    /// Request next frame to be encoded as IDR (keyframe).
     pub fn force_idr(&mut self) {
-        #[allow(unused)]
-        {
-            // Some versions expose this method; if not, compilation will fail and we'll fall back.
-            #[cfg(any())]
-            self.encoder.force_intra_frame(true).ok();
-        }
+        self.encoder.force_intra_frame();
     }
 }
\ No newline at end of file
@@ -0,0 +1,235 @@
+use anyhow::{anyhow, Result};
+use gstreamer as gst;
+use gstreamer::prelude::*;
+use gstreamer_video as gst_video;
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::config::Config;
+use crate::debug;
+use crate::recording_encryption::Encryptor;
+
+// Recordings in progress, keyed by camera name. Each one is a branch hanging
+// off the camera pipeline's encoded_tee, so the archive holds exactly the
+// bitstream WebRTC viewers receive.
+static RECORDINGS: Lazy<Mutex<HashMap<String, Recording>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// How long stop() waits for the muxer to finish the last segment.
+const FINALIZE_TIMEOUT: Duration = Duration::from_secs(5);
+
+struct Recording {
+    pipeline: gst::Pipeline,
+    tee_pad: gst::Pad,
+    queue: gst::Element,
+    splitmux: gst::Element,
+    stopping: Arc<AtomicBool>,
+    finalized: mpsc::Receiver<()>,
+    location: String,
+    /// With encryption: the worker and the segment being written
+    encryption: Option<(Encryptor, Arc<Mutex<Option<PathBuf>>>)>,
+}
+
+pub fn is_recording(camera: &str) -> bool {
+    RECORDINGS.lock().unwrap().contains_key(camera)
+}
+
+/// Muxer factory and file extension for the configured format.
+pub(crate) fn muxer_for(codec: &str, format: &str) -> Result<(&'static str, &'static str)> {
+    match (format, codec) {
+        ("auto" | "mp4", "h264") => Ok(("mp4mux", "mp4")),
+        ("auto" | "mkv", "vp8") | ("mkv", "h264") => Ok(("matroskamux", "mkv")),
+        ("mp4", "vp8") => Err(anyhow!("VP8 cannot be recorded to mp4, use format = \"mkv\"")),
+        (format, codec) => Err(anyhow!("cannot record {} as '{}'", codec, format)),
+    }
+}
+
+/// Attaches a splitmuxsink branch to the camera's encoded stream and returns
+/// the segment location pattern. The pipeline is started if no viewer has
+/// started it yet.
+pub fn start(camera: &str, config: &Config) -> Result<String> {
+    let mut recordings = RECORDINGS.lock().unwrap();
+    if recordings.contains_key(camera) {
+        return Err(anyhow!("{} is already recording", camera));
+    }
+
+    let pipeline = debug::find_pipeline(camera)
+        .ok_or_else(|| anyhow!("unknown camera '{}'", camera))?;
+    let tee = pipeline
+        .by_name("encoded_tee")
+        .ok_or_else(|| anyhow!("{} has no encoded_tee element", camera))?;
+    let (muxer, ext) = muxer_for(&config.video.codec, &config.recording.format)?;
+
+    std::fs::create_dir_all(&config.recording.dir)?;
+    let ts = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let encrypted = config.recording.encryption.enabled;
+    let location = Path::new(&config.recording.dir)
+        .join(format!("{}-{}-%05d.{}{}", camera, ts, ext, if encrypted { ".age" } else { "" }))
+        .to_string_lossy()
+        .into_owned();
+
+    // Leaky so a slow disk drops recorded frames instead of stalling viewers
+    let queue = gst::ElementFactory::make("queue")
+        .name(format!("recording_queue_{}", ts))
+        .build()?;
+    queue.set_property("max-size-buffers", &0u32);
+    queue.set_property("max-size-bytes", &0u32);
+    queue.set_property("max-size-time", &gst::ClockTime::from_seconds(2));
+    queue.set_property_from_str("leaky", "downstream");
+
+    // Our own filesink, so we can see the final EOS leave the muxer
+    let filesink = gst::ElementFactory::make("filesink").build()?;
+    let splitmux = gst::ElementFactory::make("splitmuxsink")
+        .name(format!("recording_{}", ts))
+        .build()?;
+    if !encrypted {
+        splitmux.set_property("location", &location);
+    }
+    splitmux.set_property("muxer-factory", muxer);
+    splitmux.set_property("sink", &filesink);
+    splitmux.set_property("max-size-time", &(config.recording.segment_secs * 1_000_000_000));
+    splitmux.set_property("max-files", &config.recording.max_files);
+    // Ask the encoder for a keyframe at each boundary instead of waiting
+    // for the next natural one
+    splitmux.set_property("send-keyframe-requests", &true);
+
+    // Segments are written to staging and handed to the encryptor as soon as
+    // splitmuxsink has closed them, i.e. when it names the next one
+    let encryption = if encrypted {
+        let encryptor = Encryptor::start(&config.recording)?;
+        let current = Arc::new(Mutex::new(None::<PathBuf>));
+        let staging = Path::new(&config.recording.encryption.staging_dir)
+            .join(format!("{}-{}", camera, ts));
+        let (current_location, closed) = (current.clone(), encryptor.sender());
+        splitmux.connect("format-location", false, move |args| {
+            let id = args[1].get::<u32>().unwrap_or_default();
+            let next = PathBuf::from(format!("{}-{:05}.{}", staging.display(), id, ext));
+            let name = next.to_string_lossy().into_owned();
+            if let Some(previous) = current_location.lock().unwrap().replace(next) {
+                let _ = closed.send(previous);
+            }
+            Some(name.to_value())
+        });
+        Some((encryptor, current))
+    } else {
+        None
+    };
+
+    let stopping = Arc::new(AtomicBool::new(false));
+    let (finalized_tx, finalized) = mpsc::channel();
+    let filesink_pad = filesink
+        .static_pad("sink")
+        .ok_or_else(|| anyhow!("filesink has no sink pad"))?;
+    let stopping_probe = stopping.clone();
+    // Every segment ends in EOS; only the one after stop() is final
+    filesink_pad.add_probe(gst::PadProbeType::EVENT_DOWNSTREAM, move |_, info| {
+        if let Some(event) = info.event() {
+            if event.type_() == gst::EventType::Eos && stopping_probe.load(Ordering::SeqCst) {
+                let _ = finalized_tx.send(());
+            }
+        }
+        gst::PadProbeReturn::Ok
+    });
+
+    // The branch joins mid-GOP: drop frames up to the next keyframe so the
+    // first segment starts decodable, and ask for that keyframe right away
+    let queue_src = queue
+        .static_pad("src")
+        .ok_or_else(|| anyhow!("recording queue has no src pad"))?;
+    queue_src.add_probe(gst::PadProbeType::BUFFER, |_, info| {
+        match info.buffer() {
+            Some(buffer) if buffer.flags().contains(gst::BufferFlags::DELTA_UNIT) => {
+                gst::PadProbeReturn::Drop
+            }
+            _ => gst::PadProbeReturn::Remove,
+        }
+    });
+
+    pipeline.add_many(&[&queue, &splitmux])?;
+    queue.link(&splitmux)?;
+    let tee_pad = tee
+        .request_pad_simple("src_%u")
+        .ok_or_else(|| anyhow!("Failed to request src pad from encoded_tee"))?;
+    let queue_sink = queue
+        .static_pad("sink")
+        .ok_or_else(|| anyhow!("recording queue has no sink pad"))?;
+    tee_pad.link(&queue_sink)?;
+    splitmux.sync_state_with_parent()?;
+    queue.sync_state_with_parent()?;
+
+    if pipeline.current_state() != gst::State::Playing {
+        log::info!("Starting {} pipeline for recording", camera);
+        pipeline.set_state(gst::State::Playing)?;
+    }
+    queue_sink.push_event(
+        gst_video::UpstreamForceKeyUnitEvent::builder()
+            .all_headers(true)
+            .build(),
+    );
+
+    log::info!("Recording {} to {} ({}, {}s segments)", camera, location, muxer, config.recording.segment_secs);
+    recordings.insert(
+        camera.to_string(),
+        Recording {
+            pipeline,
+            tee_pad,
+            queue,
+            splitmux,
+            stopping,
+            finalized,
+            location: location.clone(),
+            encryption,
+        },
+    );
+    Ok(location)
+}
+
+/// Detaches the recording branch after letting the muxer finalize the last
+/// segment. Blocks for up to FINALIZE_TIMEOUT, then until pending segments
+/// are encrypted.
+pub fn stop(camera: &str) -> Result<()> {
+    let recording = RECORDINGS
+        .lock()
+        .unwrap()
+        .remove(camera)
+        .ok_or_else(|| anyhow!("{} is not recording", camera))?;
+
+    // Unlink from the tee while no buffer is in flight, then push EOS so
+    // splitmuxsink closes the current file properly
+    recording.stopping.store(true, Ordering::SeqCst);
+    recording.tee_pad.add_probe(gst::PadProbeType::IDLE, |pad, _| {
+        if let Some(peer) = pad.peer() {
+            let _ = pad.unlink(&peer);
+            peer.send_event(gst::event::Eos::new());
+        }
+        gst::PadProbeReturn::Remove
+    });
+
+    if recording.finalized.recv_timeout(FINALIZE_TIMEOUT).is_err() {
+        log::warn!("Last segment of {} was not finalized within {:?}", camera, FINALIZE_TIMEOUT);
+    }
+
+    let _ = recording.splitmux.set_state(gst::State::Null);
+    let _ = recording.queue.set_state(gst::State::Null);
+    let _ = recording.pipeline.remove_many(&[&recording.queue, &recording.splitmux]);
+    if let Some(tee) = recording.tee_pad.parent_element() {
+        tee.release_request_pad(&recording.tee_pad);
+    }
+    // The last segment is closed once splitmuxsink is down
+    if let Some((encryptor, current)) = recording.encryption {
+        if let Some(last) = current.lock().unwrap().take() {
+            encryptor.encrypt(last);
+        }
+        encryptor.finish();
+    }
+
+    log::info!("Stopped recording {} ({})", camera, recording.location);
+    Ok(())
+}
@@ -0,0 +1,178 @@
+//! Encryption of recording segments at rest
+//!
+//! With `[recording.encryption]` enabled, splitmuxsink writes each segment to
+//! a staging directory (tmpfs by default) and, once the segment is closed, it
+//! is encrypted with the `age` CLI into `recording.dir` as `<segment>.age` and
+//! the plaintext removed. age encrypts every file under its own random file
+//! key, wrapped to each configured recipient, so the device only ever holds
+//! public keys: a stolen SD card doesn't give up the footage.
+//!
+//! Recipients can be replaced at runtime (`rotate-recording-key`); the new
+//! set applies to segments closed afterwards and is persisted next to the
+//! recordings so it survives a restart. Segments already written stay
+//! readable with the old identities.
+
+use anyhow::{anyhow, bail, Context, Result};
+use once_cell::sync::Lazy;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::{mpsc, Mutex};
+use std::thread::JoinHandle;
+
+use crate::config::RecordingConfig;
+
+/// Recipients set by rotate-recording-key, taking precedence over the file
+/// and the config
+static RECIPIENTS: Lazy<Mutex<Option<Vec<String>>>> = Lazy::new(|| Mutex::new(None));
+
+/// Rotated recipients, one per line, in `recording.dir`
+const RECIPIENTS_FILE: &str = ".age-recipients";
+
+fn validate_recipient(recipient: &str) -> Result<()> {
+    let well_formed = if let Some(key) = recipient.strip_prefix("age1") {
+        !key.is_empty() && key.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit())
+    } else {
+        (recipient.starts_with("ssh-ed25519 ") || recipient.starts_with("ssh-rsa "))
+            && !recipient.contains(['\r', '\n'])
+    };
+    if !well_formed {
+        bail!("'{}' is not an age recipient (age1...) or SSH public key", recipient);
+    }
+    Ok(())
+}
+
+fn validate_recipients(recipients: &[String]) -> Result<()> {
+    if recipients.is_empty() {
+        bail!("recording.encryption needs at least one recipient");
+    }
+    recipients.iter().try_for_each(|r| validate_recipient(r))
+}
+
+pub fn validate(config: &RecordingConfig) -> Result<()> {
+    let encryption = &config.encryption;
+    if !encryption.enabled {
+        return Ok(());
+    }
+    validate_recipients(&encryption.recipients)?;
+    if Path::new(&encryption.staging_dir) == Path::new(&config.dir) {
+        bail!("recording.encryption.staging-dir must differ from recording.dir");
+    }
+    Ok(())
+}
+
+/// Recipients new segments are encrypted to
+pub fn recipients(config: &RecordingConfig) -> Vec<String> {
+    if let Some(ref recipients) = *RECIPIENTS.lock().unwrap() {
+        return recipients.clone();
+    }
+    let path = Path::new(&config.dir).join(RECIPIENTS_FILE);
+    if let Ok(text) = std::fs::read_to_string(&path) {
+        let rotated: Vec<String> = text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(String::from)
+            .collect();
+        match validate_recipients(&rotated) {
+            Ok(()) => return rotated,
+            Err(e) => log::warn!("Ignoring {}: {}", path.display(), e),
+        }
+    }
+    config.encryption.recipients.clone()
+}
+
+/// Replaces the recipients for segments closed from now on
+pub fn rotate(config: &RecordingConfig, recipients: Vec<String>) -> Result<()> {
+    if !config.encryption.enabled {
+        bail!("recording encryption is not enabled");
+    }
+    validate_recipients(&recipients)?;
+
+    std::fs::create_dir_all(&config.dir)?;
+    let path = Path::new(&config.dir).join(RECIPIENTS_FILE);
+    let tmp = path.with_extension("tmp");
+    std::fs::write(&tmp, recipients.join("\n") + "\n")
+        .with_context(|| format!("writing {}", tmp.display()))?;
+    std::fs::rename(&tmp, &path).with_context(|| format!("writing {}", path.display()))?;
+
+    log::info!("Recording key rotated to {} recipient(s)", recipients.len());
+    *RECIPIENTS.lock().unwrap() = Some(recipients);
+    Ok(())
+}
+
+/// Encrypts closed segments of one recording on a worker thread, in order
+pub struct Encryptor {
+    tx: Option<mpsc::Sender<PathBuf>>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl Encryptor {
+    pub fn start(config: &RecordingConfig) -> Result<Self> {
+        std::fs::create_dir_all(&config.encryption.staging_dir)?;
+        std::fs::create_dir_all(&config.dir)?;
+        let config = config.clone();
+        let (tx, rx) = mpsc::channel::<PathBuf>();
+        let worker = std::thread::Builder::new()
+            .name("recording-encrypt".to_string())
+            .spawn(move || {
+                for segment in rx {
+                    if let Err(e) = encrypt_segment(&segment, &config) {
+                        // Leave the plaintext in staging rather than lose it
+                        log::error!("Failed to encrypt {}: {:#}", segment.display(), e);
+                    }
+                }
+            })?;
+        Ok(Self { tx: Some(tx), worker: Some(worker) })
+    }
+
+    /// Queues a closed segment for encryption
+    pub fn encrypt(&self, segment: PathBuf) {
+        if let Some(ref tx) = self.tx {
+            let _ = tx.send(segment);
+        }
+    }
+
+    /// Queue handle for callbacks that outlive a borrow of the encryptor
+    pub fn sender(&self) -> mpsc::Sender<PathBuf> {
+        self.tx.clone().expect("encryptor already finished")
+    }
+
+    /// Waits for every queued segment to be encrypted
+    pub fn finish(mut self) {
+        drop(self.tx.take());
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// `age -e -r R... -o <dir>/<segment>.age <segment>`, then removes the plaintext
+fn encrypt_segment(segment: &Path, config: &RecordingConfig) -> Result<()> {
+    let name = segment
+        .file_name()
+        .ok_or_else(|| anyhow!("segment path has no file name"))?
+        .to_string_lossy();
+    let output = Path::new(&config.dir).join(format!("{}.age", name));
+    let tmp = Path::new(&config.dir).join(format!("{}.age.tmp", name));
+
+    let mut command = Command::new("age");
+    command.arg("--encrypt");
+    for recipient in recipients(config) {
+        command.arg("--recipient").arg(recipient);
+    }
+    let result = command
+        .arg("--output")
+        .arg(&tmp)
+        .arg(segment)
+        .output()
+        .map_err(|e| anyhow!("'age' not available: {}", e))?;
+    if !result.status.success() {
+        let _ = std::fs::remove_file(&tmp);
+        bail!("age failed: {}", String::from_utf8_lossy(&result.stderr).trim());
+    }
+
+    std::fs::rename(&tmp, &output).with_context(|| format!("writing {}", output.display()))?;
+    std::fs::remove_file(segment)?;
+    log::debug!("Encrypted {} to {}", segment.display(), output.display());
+    Ok(())
+}
@@ -0,0 +1,154 @@
+//! Pushing cameras to RTMP(S) ingest servers
+//!
+//! With `[rtmp]` enabled, each camera listed in `targets` is pushed to its
+//! ingest URL (YouTube, Twitch, MediaMTX, ...) for as long as the streamer
+//! runs. The H.264 bitstream viewers receive is taken from the camera's
+//! encoded_tee without re-encoding and handed to a separate
+//! `appsrc ! h264parse ! flvmux ! rtmp2sink` pipeline (see `tee_output`), so
+//! a slow or failing ingest never stalls or errors the camera pipeline. A
+//! push that fails, is closed by the server or stops getting video is torn
+//! down and retried after `reconnect-secs`. Video only; ingests that insist
+//! on audio will reject it.
+
+use anyhow::{anyhow, bail, Result};
+use gstreamer::prelude::*;
+use once_cell::sync::Lazy;
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use tokio::time::{sleep, Duration};
+
+use crate::config::Config;
+use crate::tee_output::{self, Tee};
+
+/// Output pipeline; `location` is set separately so URLs need no quoting
+const OUTPUT_PIPELINE: &str =
+    "appsrc name=src is-live=true format=time ! h264parse ! flvmux streamable=true ! \
+     rtmp2sink name=sink sync=false";
+
+// Push state of every configured camera, keyed by stream name
+static PUSHES: Lazy<Mutex<HashMap<String, RtmpStatus>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum RtmpState {
+    Connecting,
+    Pushing,
+    /// Waiting to reconnect after the push ended
+    Retrying,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub(crate) struct RtmpStatus {
+    pub state: RtmpState,
+    /// Ingest URL without the stream key
+    pub url: String,
+    /// Why the last push ended
+    pub last_error: Option<String>,
+    /// Reconnects since startup
+    pub reconnects: u32,
+}
+
+/// Push state of `camera`, if it has an RTMP target
+pub(crate) fn status(camera: &str) -> Option<RtmpStatus> {
+    PUSHES.lock().unwrap().get(camera).cloned()
+}
+
+/// Whether `camera` feeds an RTMP target, so its pipeline must keep running
+/// without viewers
+pub fn is_pushing(camera: &str) -> bool {
+    PUSHES.lock().unwrap().contains_key(camera)
+}
+
+/// Checks the targets of an enabled `[rtmp]`, for config validation
+pub fn validate(config: &Config) -> Result<()> {
+    let rtmp = &config.rtmp;
+    if !rtmp.enabled {
+        return Ok(());
+    }
+    if rtmp.targets.is_empty() {
+        bail!("rtmp.targets is empty, so nothing would be pushed");
+    }
+    if config.video.codec != "h264" {
+        bail!("rtmp needs video.codec = \"h264\", got '{}'", config.video.codec);
+    }
+    if rtmp.reconnect_secs == 0 {
+        bail!("rtmp.reconnect-secs must be > 0");
+    }
+    let streams = config.streams();
+    for (camera, url) in &rtmp.targets {
+        if !streams.iter().any(|(name, _)| name == camera) {
+            bail!("rtmp.targets: unknown stream '{}'", camera);
+        }
+        if !url.starts_with("rtmp://") && !url.starts_with("rtmps://") {
+            bail!("rtmp.targets.{}: expected an rtmp:// or rtmps:// URL", camera);
+        }
+    }
+    Ok(())
+}
+
+/// The URL with its last path segment (the stream key) masked, for logs and
+/// the API
+fn redact_url(url: &str) -> String {
+    match url.rsplit_once('/') {
+        Some((base, key)) if !base.ends_with('/') && !key.is_empty() => format!("{}/***", base),
+        _ => url.to_string(),
+    }
+}
+
+/// Pushes every configured camera until cancelled
+pub async fn run_rtmp(config: Config) {
+    let reconnect = Duration::from_secs(config.rtmp.reconnect_secs);
+    let pushes = config.rtmp.targets.iter().map(|(camera, url)| {
+        PUSHES.lock().unwrap().insert(
+            camera.clone(),
+            RtmpStatus {
+                state: RtmpState::Connecting,
+                url: redact_url(url),
+                last_error: None,
+                reconnects: 0,
+            },
+        );
+        run_push(camera, url, reconnect)
+    });
+    futures_util::future::join_all(pushes).await;
+}
+
+async fn run_push(camera: &str, url: &str, reconnect: Duration) {
+    loop {
+        update(camera, |status| status.state = RtmpState::Connecting);
+        log::info!("Pushing {} to {}", camera, redact_url(url));
+        let error = match push(camera, url).await {
+            Ok(()) => "push ended".to_string(),
+            Err(e) => e.to_string(),
+        };
+        log::warn!("RTMP push of {} stopped: {}; retrying in {:?}", camera, error, reconnect);
+        update(camera, |status| {
+            status.state = RtmpState::Retrying;
+            status.last_error = Some(error);
+        });
+        sleep(reconnect).await;
+        update(camera, |status| status.reconnects += 1);
+    }
+}
+
+fn update(camera: &str, f: impl FnOnce(&mut RtmpStatus)) {
+    if let Some(status) = PUSHES.lock().unwrap().get_mut(camera) {
+        f(status);
+    }
+}
+
+/// Runs one push until it fails
+async fn push(camera: &str, url: &str) -> Result<()> {
+    let (output, appsrc) = tee_output::output_pipeline(OUTPUT_PIPELINE, Tee::Encoded)?;
+    output
+        .by_name("sink")
+        .ok_or_else(|| anyhow!("RTMP output has no sink"))?
+        .set_property("location", url);
+    tee_output::run(camera, Tee::Encoded, output, appsrc, || {
+        update(camera, |status| status.state = RtmpState::Pushing)
+    })
+    .await
+}
@@ -0,0 +1,430 @@
+//! Scheduled streaming windows and quiet hours
+//!
+//! With `[schedule]` enabled, the selected cameras may only stream and record
+//! during minutes matching one of the `windows`, cron-style expressions in
+//! local time. Outside them the cameras are paused (`quiet-mode`) and any
+//! recording is stopped; manual resumes and recordings are refused. Both are
+//! undone when the next window opens, and `record = true` records throughout
+//! every window. An override per camera (`on`, `off` or back to `auto`) from
+//! the control API wins over the schedule until it is set back.
+
+use anyhow::{anyhow, bail, Result};
+use chrono::{Datelike, Local, NaiveDateTime, Timelike};
+use once_cell::sync::Lazy;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use tokio::sync::Notify;
+use tokio::time::{interval, Duration};
+
+use crate::config::{Config, ScheduleConfig};
+use crate::debug;
+use crate::pause;
+use crate::recording;
+
+/// How often windows are checked and quiet cameras re-checked, e.g. for a
+/// pipeline that was recreated unpaused
+const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+const MONTH_NAMES: &[&str] = &[
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+const WEEKDAY_NAMES: &[&str] = &["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+// Schedule state of every scheduled camera, keyed by stream name
+static CAMERAS: Lazy<Mutex<HashMap<String, CameraSchedule>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+// Wakes the scheduler so overrides apply right away
+static CHANGED: Lazy<Notify> = Lazy::new(Notify::new);
+
+/// Manual override of a camera's schedule
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum ScheduleOverride {
+    /// Follow the schedule
+    #[default]
+    Auto,
+    /// Stream regardless of the schedule
+    On,
+    /// Stay quiet regardless of the schedule
+    Off,
+}
+
+impl std::str::FromStr for ScheduleOverride {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "auto" => Ok(ScheduleOverride::Auto),
+            "on" => Ok(ScheduleOverride::On),
+            "off" => Ok(ScheduleOverride::Off),
+            other => Err(anyhow!("unknown override '{}', expected auto, on or off", other)),
+        }
+    }
+}
+
+/// Schedule state of a camera, as reported to the control API
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, JsonSchema)]
+pub struct ScheduleStatus {
+    /// Whether the camera may stream right now
+    pub streaming: bool,
+    pub mode: ScheduleOverride,
+}
+
+#[derive(Default)]
+struct CameraSchedule {
+    mode: ScheduleOverride,
+    /// Whether the camera may stream, once the scheduler has decided
+    streaming: Option<bool>,
+    /// Paused by the schedule rather than by hand
+    paused: bool,
+    /// Recording to start once the camera may stream
+    record: bool,
+}
+
+/// A parsed window: `minute hour day-of-month month day-of-week`, each a
+/// `*`, value, range or list thereof, optionally with a `/step`. Months and
+/// weekdays also take names (`jan`, `mon-fri`); Sunday is 0 or 7.
+#[derive(Debug, Clone, Copy)]
+pub struct Window {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    /// Day fields given as `*`; like cron, when both are restricted a day
+    /// matching either one matches
+    any_day: bool,
+    any_weekday: bool,
+}
+
+impl Window {
+    pub fn parse(expr: &str) -> Result<Self> {
+        let fields: Vec<&str> = expr.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            bail!("expected 5 fields (minute hour day month weekday), got {}", fields.len());
+        };
+        let mut weekdays = parse_field(weekday, 0, 7, WEEKDAY_NAMES)?;
+        // 7 is Sunday too
+        if weekdays & (1 << 7) != 0 {
+            weekdays = (weekdays | 1) & !(1 << 7);
+        }
+        Ok(Self {
+            minutes: parse_field(minute, 0, 59, &[])?,
+            hours: parse_field(hour, 0, 23, &[])?,
+            days: parse_field(day, 1, 31, &[])?,
+            months: parse_field(month, 1, 12, MONTH_NAMES)?,
+            weekdays,
+            any_day: day.starts_with('*'),
+            any_weekday: weekday.starts_with('*'),
+        })
+    }
+
+    /// Whether the minute of `time` (local) lies in the window
+    pub fn matches(&self, time: &NaiveDateTime) -> bool {
+        let bit = |set: u64, value: u32| set & (1 << value) != 0;
+        let day = bit(self.days, time.day());
+        let weekday = bit(self.weekdays, time.weekday().num_days_from_sunday());
+        let day_matches = match (self.any_day, self.any_weekday) {
+            (true, true) => true,
+            (true, false) => weekday,
+            (false, true) => day,
+            (false, false) => day || weekday,
+        };
+        bit(self.minutes, time.minute())
+            && bit(self.hours, time.hour())
+            && bit(self.months, time.month())
+            && day_matches
+    }
+}
+
+/// Values `field` allows, as a bit set; `names` stand for `min`, `min + 1`, ...
+fn parse_field(field: &str, min: u32, max: u32, names: &[&str]) -> Result<u64> {
+    let value = |s: &str| -> Result<u32> {
+        let value = match names.iter().position(|name| s.eq_ignore_ascii_case(name)) {
+            Some(index) => min + index as u32,
+            None => s.parse().map_err(|_| anyhow!("invalid value '{}' in '{}'", s, field))?,
+        };
+        if !(min..=max).contains(&value) {
+            bail!("{} is out of range {}-{} in '{}'", value, min, max, field);
+        }
+        Ok(value)
+    };
+
+    let mut set = 0u64;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => match step.parse::<u32>() {
+                Ok(step) if step > 0 => (range, step),
+                _ => bail!("invalid step '{}' in '{}'", step, field),
+            },
+            None => (part, 1),
+        };
+        let (start, end) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((start, end)) => (value(start)?, value(end)?),
+            // "5/15" runs from 5 to the end
+            None if step > 1 => (value(range)?, max),
+            None => (value(range)?, value(range)?),
+        };
+        if start > end {
+            bail!("range {}-{} runs backwards in '{}'", start, end, field);
+        }
+        for v in (start..=end).step_by(step as usize) {
+            set |= 1 << v;
+        }
+    }
+    Ok(set)
+}
+
+/// Checks every window of an enabled schedule, for config validation
+pub fn validate(config: &ScheduleConfig) -> Result<()> {
+    if !config.enabled {
+        return Ok(());
+    }
+    if config.windows.is_empty() {
+        bail!("schedule.windows is empty, so the cameras would never stream");
+    }
+    for window in &config.windows {
+        Window::parse(window).map_err(|e| anyhow!("invalid schedule window '{}': {}", window, e))?;
+    }
+    Ok(())
+}
+
+/// Sets the override of a scheduled camera; it applies right away
+pub fn set_override(camera: &str, mode: ScheduleOverride) -> Result<()> {
+    let mut cameras = CAMERAS.lock().unwrap();
+    let state = cameras
+        .get_mut(camera)
+        .ok_or_else(|| anyhow!("{} has no streaming schedule", camera))?;
+    state.mode = mode;
+    log::info!("Schedule of {} overridden: {:?}", camera, mode);
+    CHANGED.notify_one();
+    Ok(())
+}
+
+/// Schedule state of `camera`, if it has a schedule
+pub fn status(camera: &str) -> Option<ScheduleStatus> {
+    CAMERAS.lock().unwrap().get(camera).map(|state| ScheduleStatus {
+        streaming: state.streaming.unwrap_or(true),
+        mode: state.mode,
+    })
+}
+
+/// Fails while `camera` is in its quiet hours, where it may be neither
+/// resumed nor recorded until its schedule is overridden
+pub fn ensure_may_stream(camera: &str) -> Result<()> {
+    let cameras = CAMERAS.lock().unwrap();
+    if cameras.get(camera).is_some_and(|state| state.streaming == Some(false)) {
+        bail!("{} is in its quiet hours; override its schedule first", camera);
+    }
+    Ok(())
+}
+
+/// Applies the schedule to its cameras until cancelled
+pub async fn run_schedule(config: Config) {
+    let schedule = config.schedule.clone();
+    // Checked by validate() at load
+    let windows: Vec<Window> =
+        schedule.windows.iter().filter_map(|w| Window::parse(w).ok()).collect();
+    let cameras: Vec<String> = config
+        .streams()
+        .into_iter()
+        .map(|(name, _)| name)
+        .filter(|name| schedule.cameras.is_empty() || schedule.cameras.contains(name))
+        .collect();
+    for camera in &cameras {
+        CAMERAS.lock().unwrap().insert(camera.clone(), CameraSchedule::default());
+    }
+    log::info!("Streaming schedule for {}: {}", cameras.join(", "), schedule.windows.join(" | "));
+
+    let mut ticker = interval(CHECK_INTERVAL);
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {}
+            _ = CHANGED.notified() => {}
+        }
+        let now = Local::now().naive_local();
+        let in_window = windows.iter().any(|window| window.matches(&now));
+        for camera in &cameras {
+            apply(camera, in_window, &schedule, &config).await;
+        }
+    }
+}
+
+async fn apply(camera: &str, in_window: bool, schedule: &ScheduleConfig, config: &Config) {
+    let (streaming, changed) = {
+        let mut cameras = CAMERAS.lock().unwrap();
+        let Some(state) = cameras.get_mut(camera) else {
+            return;
+        };
+        let streaming = match state.mode {
+            ScheduleOverride::Auto => in_window,
+            ScheduleOverride::On => true,
+            ScheduleOverride::Off => false,
+        };
+        let changed = state.streaming != Some(streaming);
+        if changed {
+            state.streaming = Some(streaming);
+            state.record |= streaming && schedule.record;
+            log::info!(
+                "{} {} ({:?})",
+                camera,
+                if streaming { "may stream" } else { "is in quiet hours" },
+                state.mode
+            );
+        }
+        (streaming, changed)
+    };
+    // Failures are retried every check, but only reported on a change
+    let level = if changed { log::Level::Warn } else { log::Level::Debug };
+    let has_pipeline = debug::find_pipeline(camera).is_some();
+
+    if streaming {
+        let resume = update(camera, |state| std::mem::take(&mut state.paused));
+        if resume && pause::pause_mode(camera).is_some() {
+            if let Err(e) = pause::resume(camera) {
+                log::log!(level, "Failed to resume {} after quiet hours: {}", camera, e);
+            }
+        }
+        let record = update(camera, |state| state.record);
+        if record && has_pipeline && !recording::is_recording(camera) {
+            let (name, config) = (camera.to_string(), config.clone());
+            match tokio::task::spawn_blocking(move || recording::start(&name, &config)).await {
+                Ok(Ok(location)) => {
+                    log::info!("Recording {} to {} for its window", camera, location);
+                    update(camera, |state| state.record = false);
+                }
+                Ok(Err(e)) => log::log!(level, "Failed to start recording {}: {}", camera, e),
+                Err(e) => log::log!(level, "Recording start of {} panicked: {}", camera, e),
+            }
+        }
+        return;
+    }
+
+    if recording::is_recording(camera) {
+        let name = camera.to_string();
+        // Waits for the muxer to finalize the last segment
+        match tokio::task::spawn_blocking(move || recording::stop(&name)).await {
+            Ok(Ok(())) => {
+                log::info!("Stopped recording {} for quiet hours", camera);
+                update(camera, |state| state.record = true);
+            }
+            Ok(Err(e)) => log::log!(level, "Failed to stop recording {}: {}", camera, e),
+            Err(e) => log::log!(level, "Recording stop of {} panicked: {}", camera, e),
+        }
+    }
+    if has_pipeline && pause::pause_mode(camera).is_none() {
+        match pause::pause(camera, schedule.quiet_mode) {
+            Ok(()) => update(camera, |state| state.paused = true),
+            Err(e) => log::log!(level, "Failed to pause {} for quiet hours: {}", camera, e),
+        }
+    }
+}
+
+fn update<T>(camera: &str, f: impl FnOnce(&mut CameraSchedule) -> T) -> T {
+    f(CAMERAS.lock().unwrap().entry(camera.to_string()).or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn bits(values: &[u32]) -> u64 {
+        values.iter().fold(0, |set, v| set | 1 << v)
+    }
+
+    fn at(date: &str, hour: u32, minute: u32) -> NaiveDateTime {
+        NaiveDate::parse_from_str(date, "%Y-%m-%d")
+            .unwrap()
+            .and_hms_opt(hour, minute, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_fields_parse_to_value_sets() {
+        let cases: &[(&str, u32, u32, &[&str], &[u32])] = &[
+            ("*", 0, 5, &[], &[0, 1, 2, 3, 4, 5]),
+            ("7", 0, 59, &[], &[7]),
+            ("1-3,5", 0, 59, &[], &[1, 2, 3, 5]),
+            ("*/15", 0, 59, &[], &[0, 15, 30, 45]),
+            ("5/20", 0, 59, &[], &[5, 25, 45]),
+            ("10-20/5", 0, 59, &[], &[10, 15, 20]),
+            ("mon-fri", 0, 7, WEEKDAY_NAMES, &[1, 2, 3, 4, 5]),
+            ("sat,SUN", 0, 7, WEEKDAY_NAMES, &[6, 0]),
+            ("JAN,jun-aug", 1, 12, MONTH_NAMES, &[1, 6, 7, 8]),
+            ("dec", 1, 12, MONTH_NAMES, &[12]),
+        ];
+        for &(field, min, max, names, expected) in cases {
+            assert_eq!(
+                parse_field(field, min, max, names).unwrap(),
+                bits(expected),
+                "{}",
+                field
+            );
+        }
+    }
+
+    #[test]
+    fn test_sunday_is_0_or_7() {
+        assert_eq!(Window::parse("0 0 * * 7").unwrap().weekdays, bits(&[0]));
+        assert_eq!(Window::parse("0 0 * * 5-7").unwrap().weekdays, bits(&[0, 5, 6]));
+        assert_eq!(Window::parse("0 0 * * 0").unwrap().weekdays, bits(&[0]));
+    }
+
+    #[test]
+    fn test_windows_match_minutes() {
+        // 2026-10-18 and 2026-11-01 are Sundays, 2026-10-19 a Monday,
+        // 2026-10-01 a Thursday
+        let cases = [
+            ("30 9 * * mon-fri", at("2026-10-19", 9, 30), true),
+            ("30 9 * * mon-fri", at("2026-10-19", 9, 31), false),
+            ("30 9 * * mon-fri", at("2026-10-18", 9, 30), false),
+            ("* 22-23,0-5 * * *", at("2026-10-18", 23, 59), true),
+            ("* 22-23,0-5 * * *", at("2026-10-18", 6, 0), false),
+            ("*/10 * * * *", at("2026-10-18", 12, 40), true),
+            ("*/10 * * * *", at("2026-10-18", 12, 41), false),
+            ("* * * jun-aug *", at("2026-07-15", 12, 0), true),
+            ("* * * jun-aug *", at("2026-10-18", 12, 0), false),
+            // Only one day field restricted: that one decides
+            ("0 0 1 * *", at("2026-10-01", 0, 0), true),
+            ("0 0 1 * *", at("2026-10-18", 0, 0), false),
+            ("0 0 * * 7", at("2026-10-18", 0, 0), true),
+            ("0 0 * * sun", at("2026-10-01", 0, 0), false),
+            // Both restricted: either one matches, as in cron
+            ("0 0 1 * sun", at("2026-10-01", 0, 0), true),
+            ("0 0 1 * sun", at("2026-10-18", 0, 0), true),
+            ("0 0 1 * sun", at("2026-11-01", 0, 0), true),
+            ("0 0 1 * sun", at("2026-10-19", 0, 0), false),
+        ];
+        for (expr, time, expected) in cases {
+            let window = Window::parse(expr).unwrap();
+            assert_eq!(window.matches(&time), expected, "{} at {}", expr, time);
+        }
+    }
+
+    #[test]
+    fn test_invalid_windows() {
+        let cases = [
+            ("0 0 * *", "expected 5 fields"),
+            ("0 0 * * * *", "expected 5 fields"),
+            ("0 20-10 * * *", "runs backwards"),
+            ("0 0 * * fri-mon", "runs backwards"),
+            ("*/0 * * * *", "invalid step"),
+            ("*/x * * * *", "invalid step"),
+            ("60 * * * *", "out of range"),
+            ("0 24 * * *", "out of range"),
+            ("0 0 0 * *", "out of range"),
+            ("0 0 * 13 *", "out of range"),
+            ("0 0 * * 8", "out of range"),
+            ("0 0 * * funday", "invalid value"),
+        ];
+        for (expr, error) in cases {
+            let e = Window::parse(expr).unwrap_err().to_string();
+            assert!(e.contains(error), "{}: {}", expr, e);
+        }
+    }
+}
@@ -23,6 +23,35 @@ pub struct ImuData {
     pub gyro: [f32; 3],
 }
 
+/// One of the IMU's axes as configured ("x", "y" or "z", "-" to invert),
+/// for lining its readings up with a camera
+#[derive(Debug, Clone, Copy)]
+pub struct Axis {
+    index: usize,
+    sign: f32,
+}
+
+impl Axis {
+    pub fn parse(axis: &str) -> Result<Self> {
+        let (sign, name) = match axis.strip_prefix('-') {
+            Some(name) => (-1.0, name),
+            None => (1.0, axis),
+        };
+        let index = match name {
+            "x" => 0,
+            "y" => 1,
+            "z" => 2,
+            _ => return Err(anyhow!("unknown IMU axis '{}', expected x, y or z, optionally with '-'", axis)),
+        };
+        Ok(Self { index, sign })
+    }
+
+    /// This axis' component of an accel or gyro reading
+    pub fn of(&self, reading: &[f32; 3]) -> f32 {
+        reading[self.index] * self.sign
+    }
+}
+
 impl Imu {
     pub fn new(i2c_bus: u8, address: u8, _id: &str) -> Result<Self> {
         let mut i2c = I2c::with_bus(i2c_bus)?;
@@ -1,2 +1,3 @@
 pub mod icm20948;
-pub mod lidar; 
\ No newline at end of file
+pub mod lidar;
+pub mod runner;
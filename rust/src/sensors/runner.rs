@@ -0,0 +1,289 @@
+//! Independent per-sensor polling tasks
+//!
+//! Every sensor is polled by its own task at its own rate, so a slow or hung
+//! sensor only stalls itself. I2C access is blocking: each init and read
+//! runs on the blocking pool under a timeout, and a sensor that times out is
+//! abandoned to its thread and initialized afresh. Readings from all tasks
+//! go to a single ZMQ publisher thread, and are also broadcast in-process
+//! (see [`subscribe`]). Each sensor's health is kept for `/api/stats`.
+
+use anyhow::Result;
+use once_cell::sync::Lazy;
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::{broadcast, mpsc};
+use tokio::time::{interval, MissedTickBehavior};
+
+use crate::sensors::icm20948::Imu;
+use crate::sensors::lidar::Lidar;
+use crate::tasks::CancellationToken;
+
+/// Wait before retrying a sensor that failed to initialize
+const RETRY_DELAY: Duration = Duration::from_secs(2);
+
+/// Readings waiting for the publisher; beyond this they are dropped
+pub const PUBLISH_QUEUE: usize = 256;
+
+static HEALTH: Lazy<Mutex<BTreeMap<&'static str, SensorHealth>>> =
+    Lazy::new(|| Mutex::new(BTreeMap::new()));
+
+static READINGS: Lazy<broadcast::Sender<Reading>> = Lazy::new(|| broadcast::channel(64).0);
+
+/// A good reading, as published
+#[derive(Debug, Clone)]
+pub struct Reading {
+    pub sensor: &'static str,
+    pub payload: String,
+}
+
+/// Every good reading from now on, for reacting to sensors in-process
+pub fn subscribe() -> broadcast::Receiver<Reading> {
+    READINGS.subscribe()
+}
+
+/// (topic, payload) pairs for the ZMQ publisher
+pub type Publisher = mpsc::Sender<(String, String)>;
+
+/// A sensor whose readings are published as text
+pub trait Sensor: Send + 'static {
+    fn read(&mut self) -> Result<String>;
+}
+
+impl Sensor for Lidar {
+    fn read(&mut self) -> Result<String> {
+        Ok(self.read_distance_mm()?.to_string())
+    }
+}
+
+impl Sensor for Imu {
+    fn read(&mut self) -> Result<String> {
+        Ok(serde_json::to_string(&self.read_data()?)?)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum SensorState {
+    /// Not initialized yet
+    Initializing,
+    Ok,
+    /// The last init or read failed; it is being re-initialized
+    Error,
+    /// The last init or read didn't finish within the timeout
+    TimedOut,
+}
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct SensorHealth {
+    pub name: String,
+    pub state: SensorState,
+    pub interval_ms: u64,
+    pub reads: u64,
+    pub errors: u64,
+    pub timeouts: u64,
+    /// Unix time of the last good reading
+    pub last_read_ms: Option<u64>,
+    /// How long the last good reading took
+    pub last_latency_ms: Option<f32>,
+    pub last_error: Option<String>,
+}
+
+/// Health of every sensor task, by name
+pub fn health() -> Vec<SensorHealth> {
+    HEALTH.lock().unwrap().values().cloned().collect()
+}
+
+fn update_health(name: &'static str, update: impl FnOnce(&mut SensorHealth)) {
+    if let Some(health) = HEALTH.lock().unwrap().get_mut(name) {
+        update(health);
+    }
+}
+
+/// Polling rate and per-call timeout of one sensor
+#[derive(Debug, Clone, Copy)]
+pub struct Timing {
+    pub interval: Duration,
+    pub timeout: Duration,
+}
+
+enum Failure {
+    Error(anyhow::Error),
+    TimedOut,
+}
+
+/// Runs `f` on the blocking pool, giving up on it after `timeout`
+async fn blocking<T, F>(timeout: Duration, f: F) -> Result<T, Failure>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T> + Send + 'static,
+{
+    match tokio::time::timeout(timeout, tokio::task::spawn_blocking(f)).await {
+        Ok(Ok(Ok(value))) => Ok(value),
+        Ok(Ok(Err(e))) => Err(Failure::Error(e)),
+        Ok(Err(e)) => Err(Failure::Error(e.into())),
+        Err(_) => Err(Failure::TimedOut),
+    }
+}
+
+fn record_failure(name: &'static str, failure: &Failure, during: &str) -> String {
+    let message = match failure {
+        Failure::Error(e) => format!("{} failed: {}", during, e),
+        Failure::TimedOut => format!("{} timed out", during),
+    };
+    update_health(name, |health| {
+        match failure {
+            Failure::Error(_) => {
+                health.state = SensorState::Error;
+                health.errors += 1;
+            }
+            Failure::TimedOut => {
+                health.state = SensorState::TimedOut;
+                health.timeouts += 1;
+            }
+        }
+        health.last_error = Some(message.clone());
+    });
+    message
+}
+
+/// Polls one sensor until the publisher goes away, (re)initializing it with
+/// `init` whenever it has none or the last read failed
+pub async fn run_sensor<S, F>(
+    name: &'static str,
+    topic: String,
+    timing: Timing,
+    init: F,
+    publisher: Publisher,
+) where
+    S: Sensor,
+    F: Fn() -> Result<S> + Send + Sync + 'static,
+{
+    HEALTH.lock().unwrap().insert(
+        name,
+        SensorHealth {
+            name: name.to_string(),
+            state: SensorState::Initializing,
+            interval_ms: timing.interval.as_millis() as u64,
+            reads: 0,
+            errors: 0,
+            timeouts: 0,
+            last_read_ms: None,
+            last_latency_ms: None,
+            last_error: None,
+        },
+    );
+
+    let init = Arc::new(init);
+    let mut sensor: Option<S> = None;
+    let mut ticker = interval(timing.interval);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        ticker.tick().await;
+
+        let current = match sensor.take() {
+            Some(current) => current,
+            None => {
+                let init = init.clone();
+                match blocking(timing.timeout, move || init()).await {
+                    Ok(current) => {
+                        log::info!("{} initialised", name);
+                        current
+                    }
+                    Err(failure) => {
+                        let message = record_failure(name, &failure, "init");
+                        if !publish(&publisher, &topic, format!("ERROR: {}", message)) {
+                            return;
+                        }
+                        tokio::time::sleep(RETRY_DELAY).await;
+                        continue;
+                    }
+                }
+            }
+        };
+
+        let started = Instant::now();
+        let read = blocking(timing.timeout, move || {
+            let mut current = current;
+            let reading = current.read()?;
+            Ok((current, reading))
+        });
+        let payload = match read.await {
+            Ok((current, reading)) => {
+                sensor = Some(current);
+                update_health(name, |health| {
+                    health.state = SensorState::Ok;
+                    health.reads += 1;
+                    health.last_read_ms = Some(unix_ms());
+                    health.last_latency_ms = Some(started.elapsed().as_secs_f32() * 1000.0);
+                });
+                let _ = READINGS.send(Reading {
+                    sensor: name,
+                    payload: reading.clone(),
+                });
+                reading
+            }
+            Err(failure) => {
+                // The sensor is dropped (or left to its hung thread) and
+                // initialized again on the next tick
+                let message = record_failure(name, &failure, "read");
+                log::warn!("{} {}", name, message);
+                format!("ERROR: {}", message)
+            }
+        };
+        if !publish(&publisher, &topic, payload) {
+            return;
+        }
+    }
+}
+
+/// Queues a message without waiting, dropping it while the publisher is
+/// backed up. Returns false once the publisher is gone.
+fn publish(publisher: &Publisher, topic: &str, payload: String) -> bool {
+    match publisher.try_send((topic.to_string(), payload)) {
+        Ok(()) | Err(mpsc::error::TrySendError::Full(_)) => true,
+        Err(mpsc::error::TrySendError::Closed(_)) => false,
+    }
+}
+
+/// Publishes every message from the sensor tasks until they all stop.
+/// Blocks; run it on its own thread.
+pub fn run_publisher(
+    address: &str,
+    mut messages: mpsc::Receiver<(String, String)>,
+    token: CancellationToken,
+) -> Result<()> {
+    let context = zmq::Context::new();
+    let publisher = context.socket(zmq::PUB)?;
+
+    // Publisher may fail to bind if port is in use – retry with back-off
+    loop {
+        if token.is_cancelled() {
+            return Ok(());
+        }
+        match publisher.bind(address) {
+            Ok(_) => break,
+            Err(e) => {
+                log::error!("Cannot bind ZMQ publisher ({}). Retrying in 1 s…", e);
+                std::thread::sleep(Duration::from_secs(1));
+            }
+        }
+    }
+
+    while let Some((topic, payload)) = messages.blocking_recv() {
+        if let Err(e) = publisher.send_multipart(&[topic.as_bytes(), payload.as_bytes()], 0) {
+            log::error!("Failed to publish ZMQ message on topic '{}': {}", topic, e);
+        }
+    }
+    Ok(())
+}
+
+fn unix_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
@@ -37,7 +37,7 @@ use webrtc::rtp_transceiver::rtp_codec::RTPCodecType;
 use crate::config::Config;
 use crate::camera::Camera;
 use crate::processing::VideoProcessor;
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use webrtc::ice::network_type::NetworkType;
 use webrtc::api::interceptor_registry::register_default_interceptors;
 use webrtc::interceptor::registry::Registry;
@@ -72,6 +72,7 @@ async fn handle_websocket_connection(
     config: Config,
     frame_tx: Arc<broadcast::Sender<Bytes>>,
     param_sets: Arc<RwLock<Vec<Bytes>>>,
+    idr_request: Arc<AtomicBool>,
 ) -> Result<()> {
     log::info!("New WebSocket connection from: {}", peer_address);
     let (ws_sender, mut ws_receiver) = ws_stream.split();
@@ -187,6 +188,7 @@ async fn handle_websocket_connection(
     let video_track_clone = Arc::clone(&video_track);
     let frame_tx_clone = Arc::clone(&frame_tx);
     let param_sets_clone = Arc::clone(&param_sets);
+    let keyframe_on_join = config.webrtc.keyframe_on_join;
     let mut maybe_started = false;
 
     peer_connection.on_peer_connection_state_change(Box::new(move |s: RTCPeerConnectionState| {
@@ -244,6 +246,7 @@ async fn handle_websocket_connection(
                     let vt = Arc::clone(&video_track_clone);
                     let ft = Arc::clone(&frame_tx_clone);
                     let ps_sets = Arc::clone(&param_sets_clone);
+                    let idr = Arc::clone(&idr_request);
                     log::info!("starting media loop for peer; receivers={}", ft.receiver_count());
                     tokio::spawn(async move {
                         // Send stored SPS/PPS before regular frames so decoder can start immediately
@@ -260,6 +263,11 @@ async fn handle_websocket_connection(
                         }
 
                         let mut frame_rx = ft.subscribe();
+                        // The cached SPS/PPS only help once an IDR follows;
+                        // have the encoder make the next frame one
+                        if keyframe_on_join {
+                            idr.store(true, Ordering::Relaxed);
+                        }
                         loop {
                             match frame_rx.recv().await {
                                 Ok(encoded) => {
@@ -389,12 +397,15 @@ pub async fn run(config: Config) -> Result<()> {
     let (frame_tx, _rx) = broadcast::channel::<Bytes>(32);
     let frame_tx = Arc::new(frame_tx);
     let param_sets: Arc<RwLock<Vec<Bytes>>> = Arc::new(RwLock::new(Vec::new()));
+    // Set by a peer that just joined; the capture loop forces an IDR
+    let idr_request = Arc::new(AtomicBool::new(false));
 
     // Spawn capture task for this camera
     {
         let camera_conf = config.camera_1.clone();
         let tx_inner = frame_tx.clone();
         let param_sets_clone = param_sets.clone();
+        let idr_request = idr_request.clone();
         tokio::spawn(async move {
             log::info!("Capture loop starts for {}", camera_conf.device);
             let mut camera = match Camera::new(&camera_conf) {
@@ -418,6 +429,9 @@ pub async fn run(config: Config) -> Result<()> {
                 match camera.capture_frame() {
                     Ok((data, _)) => {
                         log::trace!("frame captured ({} bytes)", data.len());
+                        if idr_request.swap(false, Ordering::Relaxed) {
+                            processor.force_idr();
+                        }
                         match processor.encode_i420(data) {
                             Ok(encoded) => {
                                 // Scan for SPS/PPS and remember them for future peers
@@ -471,8 +485,9 @@ pub async fn run(config: Config) -> Result<()> {
                 let config_clone = config.clone();
                 let tx_clone = frame_tx.clone();
                 let param_sets_clone = param_sets.clone();
+                let idr_clone = idr_request.clone();
                 tokio::spawn(async move {
-                    if let Err(e) = handle_websocket_connection(peer_address, ws_stream, config_clone, tx_clone, param_sets_clone).await {
+                    if let Err(e) = handle_websocket_connection(peer_address, ws_stream, config_clone, tx_clone, param_sets_clone, idr_clone).await {
                         log::error!("Error handling WebSocket connection: {}", e);
                     }
                 });
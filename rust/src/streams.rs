@@ -0,0 +1,72 @@
+//! Stream names and the URLs built from them
+//!
+//! Every camera is published under a stream name (`name` in its config
+//! section, "camera1"/"camera2" by default). Signaling, the REST API, the
+//! control socket, pause/mute and recording file names all use it, so
+//! renumbering cameras or swapping their sections doesn't break consumers.
+//!
+//! - signaling: `ws://{host}:{port}/streams/{name}`
+//! - REST: `/api/streams`, `/api/streams/{name}`,
+//!   `/api/streams/{name}/pause|resume`
+
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use crate::pause::PauseMode;
+
+/// Path under which streams are addressed
+pub const PATH_PREFIX: &str = "/streams/";
+
+/// A stream as listed by `GET /api/streams` and `GET /api/cameras`
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct StreamInfo {
+    pub name: String,
+    /// Camera section the stream comes from (1 or 2)
+    pub camera: usize,
+    pub device: String,
+    pub width: u32,
+    pub height: u32,
+    pub fps: u32,
+    pub signaling_port: u16,
+    /// Where to open the signaling WebSocket
+    pub ws_url: String,
+    /// The camera pipeline is up
+    pub active: bool,
+    pub recording: bool,
+    pub paused: Option<PauseMode>,
+}
+
+/// Names end up in URLs and file names, so they are kept to lowercase
+/// ASCII letters, digits, '-' and '_'
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 64
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-' || b == b'_')
+}
+
+/// WebSocket URL of a stream's signaling server
+pub fn signaling_url(host: &str, port: u16, name: &str) -> String {
+    format!("ws://{}:{}{}{}", host, port, PATH_PREFIX, name)
+}
+
+/// Stream name addressed by `path` ("/streams/{name}", query ignored)
+pub fn name_from_path(path: &str) -> Option<&str> {
+    let route = path.split_once('?').map_or(path, |(route, _)| route);
+    route
+        .strip_prefix(PATH_PREFIX)
+        .map(|name| name.trim_end_matches('/'))
+        .filter(|name| !name.is_empty())
+}
+
+/// Whether a signaling request for `path` may reach stream `name`. The
+/// bare "/" and "/ws" paths from before stream names stay accepted, since
+/// each signaling port serves exactly one stream.
+pub fn signaling_path_matches(path: &str, name: &str) -> bool {
+    let route = path.split_once('?').map_or(path, |(route, _)| route);
+    match name_from_path(route) {
+        Some(requested) => requested == name,
+        None => matches!(route.trim_end_matches('/'), "" | "/ws"),
+    }
+}
@@ -0,0 +1,361 @@
+use once_cell::sync::Lazy;
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::process::Command;
+use tokio::time::{interval, Duration, Instant};
+
+use crate::config::SystemMonitorConfig;
+
+// Latest sample, shared with the web server, the sensor bus publisher and
+// the crash bundle without threading handles through every task.
+static LATEST: Lazy<Mutex<Option<SystemStats>>> = Lazy::new(|| Mutex::new(None));
+
+/// Decoded `vcgencmd get_throttled` bitmask.
+#[derive(Debug, Clone, Default, Serialize, PartialEq, JsonSchema)]
+pub struct ThrottleFlags {
+    pub raw: u32,
+    pub under_voltage: bool,
+    pub freq_capped: bool,
+    pub throttled: bool,
+    pub soft_temp_limit: bool,
+    pub under_voltage_occurred: bool,
+    pub freq_capped_occurred: bool,
+    pub throttled_occurred: bool,
+    pub soft_temp_limit_occurred: bool,
+}
+
+impl ThrottleFlags {
+    fn from_raw(raw: u32) -> Self {
+        Self {
+            raw,
+            under_voltage: raw & (1 << 0) != 0,
+            freq_capped: raw & (1 << 1) != 0,
+            throttled: raw & (1 << 2) != 0,
+            soft_temp_limit: raw & (1 << 3) != 0,
+            under_voltage_occurred: raw & (1 << 16) != 0,
+            freq_capped_occurred: raw & (1 << 17) != 0,
+            throttled_occurred: raw & (1 << 18) != 0,
+            soft_temp_limit_occurred: raw & (1 << 19) != 0,
+        }
+    }
+
+    /// True while the SoC is actively slowed down for any reason.
+    pub fn active(&self) -> bool {
+        self.raw & 0xF != 0
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, JsonSchema)]
+pub struct InterfaceStats {
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+    /// Rates over the last sampling period; absent on the first sample
+    pub rx_bytes_per_sec: Option<f64>,
+    pub tx_bytes_per_sec: Option<f64>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, JsonSchema)]
+pub struct DiskStats {
+    pub path: String,
+    pub total_kb: u64,
+    pub available_kb: u64,
+    pub used_percent: f32,
+}
+
+#[derive(Debug, Clone, Default, Serialize, JsonSchema)]
+pub struct SystemStats {
+    pub ts_ms: u64,
+    /// Thermal zone type (e.g. "cpu-thermal") -> degrees Celsius. The ISP has
+    /// no sensor of its own and shares the SoC die reading.
+    pub thermal_zones_c: BTreeMap<String, f32>,
+    pub gpu_temp_c: Option<f32>,
+    pub throttling: Option<ThrottleFlags>,
+    pub cpu_usage_percent: Option<f32>,
+    pub load_avg: [f32; 3],
+    pub mem_total_kb: u64,
+    pub mem_available_kb: u64,
+    /// Per-interface counters from /proc/net/dev (loopback excluded)
+    pub network: BTreeMap<String, InterfaceStats>,
+    /// Free space on the filesystem holding the recording path
+    pub disk: Option<DiskStats>,
+}
+
+/// Most recent system sample, if the monitor has run at least once.
+pub fn latest() -> Option<SystemStats> {
+    LATEST.lock().ok().and_then(|latest| latest.clone())
+}
+
+/// Periodically sample temperatures, throttling, CPU load and memory.
+pub async fn run_system_monitor(config: SystemMonitorConfig) {
+    let mut ticker = interval(Duration::from_millis(config.interval_ms.max(100)));
+    let mut prev_cpu: Option<CpuTimes> = None;
+    let mut prev_net: Option<(Instant, BTreeMap<String, (u64, u64)>)> = None;
+    let mut was_throttled = false;
+
+    log::info!("System monitor started (every {} ms)", config.interval_ms);
+
+    loop {
+        ticker.tick().await;
+
+        let cpu = read_cpu_times();
+        let cpu_usage_percent = match (prev_cpu, cpu) {
+            (Some(prev), Some(now)) => now.usage_since(&prev),
+            _ => None,
+        };
+        prev_cpu = cpu;
+
+        let net_now = (Instant::now(), read_net_dev());
+        let network = interface_stats(prev_net.as_ref(), &net_now);
+        prev_net = Some(net_now);
+
+        let (mem_total_kb, mem_available_kb) = read_meminfo();
+        let stats = SystemStats {
+            ts_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis() as u64,
+            thermal_zones_c: read_thermal_zones(),
+            gpu_temp_c: vcgencmd_gpu_temp().await,
+            throttling: vcgencmd_throttled().await,
+            cpu_usage_percent,
+            load_avg: read_loadavg(),
+            mem_total_kb,
+            mem_available_kb,
+            network,
+            disk: disk_usage(&config.recording_path).await,
+        };
+
+        // Encoder stalls are hard to explain after the fact, so make throttling
+        // transitions visible in the log as they happen.
+        let throttled_now = stats.throttling.as_ref().map_or(false, |t| t.active());
+        if throttled_now && !was_throttled {
+            log::warn!(
+                "🔥 SoC throttling active: {:?}, temps {:?}",
+                stats.throttling,
+                stats.thermal_zones_c
+            );
+        } else if !throttled_now && was_throttled {
+            log::info!("SoC throttling cleared");
+        }
+        was_throttled = throttled_now;
+
+        if let Ok(mut latest) = LATEST.lock() {
+            *latest = Some(stats);
+        }
+    }
+}
+
+fn read_thermal_zones() -> BTreeMap<String, f32> {
+    let mut zones = BTreeMap::new();
+    let entries = match fs::read_dir("/sys/class/thermal") {
+        Ok(entries) => entries,
+        Err(_) => return zones,
+    };
+
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if !name.starts_with("thermal_zone") {
+            continue;
+        }
+        let path = entry.path();
+        let millideg = fs::read_to_string(path.join("temp"))
+            .ok()
+            .and_then(|t| t.trim().parse::<i64>().ok());
+        if let Some(millideg) = millideg {
+            let zone_type = fs::read_to_string(path.join("type"))
+                .map(|t| t.trim().to_string())
+                .unwrap_or(name);
+            zones.insert(zone_type, millideg as f32 / 1000.0);
+        }
+    }
+    zones
+}
+
+async fn vcgencmd(arg: &str) -> Option<String> {
+    let output = Command::new("vcgencmd").arg(arg).output().await.ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// `temp=48.3'C`
+async fn vcgencmd_gpu_temp() -> Option<f32> {
+    let out = vcgencmd("measure_temp").await?;
+    out.strip_prefix("temp=")?
+        .trim_end_matches("'C")
+        .parse()
+        .ok()
+}
+
+/// `throttled=0x50000`
+async fn vcgencmd_throttled() -> Option<ThrottleFlags> {
+    let out = vcgencmd("get_throttled").await?;
+    let hex = out.strip_prefix("throttled=0x")?;
+    u32::from_str_radix(hex, 16).ok().map(ThrottleFlags::from_raw)
+}
+
+#[derive(Debug, Clone, Copy)]
+struct CpuTimes {
+    idle: u64,
+    total: u64,
+}
+
+impl CpuTimes {
+    fn usage_since(&self, prev: &CpuTimes) -> Option<f32> {
+        let total = self.total.checked_sub(prev.total)?;
+        let idle = self.idle.checked_sub(prev.idle)?;
+        if total == 0 {
+            return None;
+        }
+        Some((total - idle) as f32 * 100.0 / total as f32)
+    }
+}
+
+/// Aggregate `cpu` line of /proc/stat.
+fn read_cpu_times() -> Option<CpuTimes> {
+    let stat = fs::read_to_string("/proc/stat").ok()?;
+    let line = stat.lines().find(|l| l.starts_with("cpu "))?;
+    let values: Vec<u64> = line
+        .split_whitespace()
+        .skip(1)
+        .filter_map(|v| v.parse().ok())
+        .collect();
+    if values.len() < 4 {
+        return None;
+    }
+    // idle + iowait
+    let idle = values[3] + values.get(4).copied().unwrap_or(0);
+    Some(CpuTimes {
+        idle,
+        total: values.iter().sum(),
+    })
+}
+
+/// Interface name -> (rx_bytes, tx_bytes) from /proc/net/dev.
+fn read_net_dev() -> BTreeMap<String, (u64, u64)> {
+    let mut counters = BTreeMap::new();
+    let s = match fs::read_to_string("/proc/net/dev") {
+        Ok(s) => s,
+        Err(_) => return counters,
+    };
+
+    // Two header lines, then "iface: rx_bytes rx_packets ... tx_bytes ..."
+    for line in s.lines().skip(2) {
+        let (iface, rest) = match line.split_once(':') {
+            Some(parts) => parts,
+            None => continue,
+        };
+        let iface = iface.trim();
+        if iface == "lo" {
+            continue;
+        }
+        let fields: Vec<u64> = rest
+            .split_whitespace()
+            .filter_map(|v| v.parse().ok())
+            .collect();
+        if fields.len() >= 9 {
+            counters.insert(iface.to_string(), (fields[0], fields[8]));
+        }
+    }
+    counters
+}
+
+fn interface_stats(
+    prev: Option<&(Instant, BTreeMap<String, (u64, u64)>)>,
+    now: &(Instant, BTreeMap<String, (u64, u64)>),
+) -> BTreeMap<String, InterfaceStats> {
+    let (now_at, now_counters) = now;
+    now_counters
+        .iter()
+        .map(|(iface, &(rx, tx))| {
+            let rates = prev.and_then(|(prev_at, prev_counters)| {
+                let &(prev_rx, prev_tx) = prev_counters.get(iface)?;
+                let secs = now_at.duration_since(*prev_at).as_secs_f64();
+                if secs <= 0.0 {
+                    return None;
+                }
+                // Counters reset when an interface goes down and comes back
+                Some((
+                    rx.checked_sub(prev_rx)? as f64 / secs,
+                    tx.checked_sub(prev_tx)? as f64 / secs,
+                ))
+            });
+            let stats = InterfaceStats {
+                rx_bytes: rx,
+                tx_bytes: tx,
+                rx_bytes_per_sec: rates.map(|r| r.0),
+                tx_bytes_per_sec: rates.map(|r| r.1),
+            };
+            (iface.clone(), stats)
+        })
+        .collect()
+}
+
+/// Filesystem usage for `path` via `df -Pk` (POSIX output, 1K blocks).
+/// Falls back to the nearest existing parent so a not-yet-created recording
+/// directory still reports its filesystem.
+async fn disk_usage(path: &str) -> Option<DiskStats> {
+    let mut probe = std::path::Path::new(path);
+    while !probe.exists() {
+        probe = probe.parent()?;
+    }
+
+    let output = Command::new("df").arg("-Pk").arg(probe).output().await.ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    // Filesystem 1024-blocks Used Available Capacity Mounted-on
+    let fields: Vec<&str> = stdout.lines().nth(1)?.split_whitespace().collect();
+    let total_kb: u64 = fields.get(1)?.parse().ok()?;
+    let used_kb: u64 = fields.get(2)?.parse().ok()?;
+    let available_kb: u64 = fields.get(3)?.parse().ok()?;
+    let used_percent = if used_kb + available_kb > 0 {
+        used_kb as f32 * 100.0 / (used_kb + available_kb) as f32
+    } else {
+        0.0
+    };
+
+    Some(DiskStats {
+        path: path.to_string(),
+        total_kb,
+        available_kb,
+        used_percent,
+    })
+}
+
+fn read_loadavg() -> [f32; 3] {
+    let mut load = [0.0; 3];
+    if let Ok(s) = fs::read_to_string("/proc/loadavg") {
+        for (slot, value) in load.iter_mut().zip(s.split_whitespace()) {
+            *slot = value.parse().unwrap_or(0.0);
+        }
+    }
+    load
+}
+
+fn read_meminfo() -> (u64, u64) {
+    let mut total = 0;
+    let mut available = 0;
+    if let Ok(s) = fs::read_to_string("/proc/meminfo") {
+        for line in s.lines() {
+            let value = || {
+                line.split_whitespace()
+                    .nth(1)
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or(0)
+            };
+            if line.starts_with("MemTotal:") {
+                total = value();
+            } else if line.starts_with("MemAvailable:") {
+                available = value();
+            }
+        }
+    }
+    (total, available)
+}
@@ -0,0 +1,14 @@
+use once_cell::sync::Lazy;
+
+pub use rust_mjpeg_rtp::supervisor::{BoxError, ComponentStates, Supervisor, TaskComponent};
+pub use tokio_util::sync::CancellationToken;
+
+// Long-running tasks owned by main are components of one supervisor, which
+// starts them in order, restarts the ones that fail and stops them all on
+// shutdown. Their states are kept here for /api/status.
+static COMPONENTS: Lazy<ComponentStates> = Lazy::new(ComponentStates::default);
+
+/// States of main's components, in start order
+pub fn components() -> &'static ComponentStates {
+    &COMPONENTS
+}
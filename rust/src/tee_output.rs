@@ -0,0 +1,338 @@
+//! Outputs fed from a camera pipeline through a pipeline of their own
+//!
+//! RTMP and NDI hang a `queue ! appsink` branch off one of the camera's tees
+//! and hand its buffers to an `appsrc` at the head of a separate output
+//! pipeline. The output's errors stay on its own bus and a stalled output
+//! only drops frames in the leaky queue, so neither can stall or fail the
+//! camera pipeline and the WebRTC viewers on it. WebRTC sessions switched to
+//! another camera and picture-in-picture insets are fed the same way, into
+//! an appsrc in the other camera's pipeline (see `webrtc::switch`, `pip`).
+
+use anyhow::{anyhow, bail, Result};
+use futures_util::StreamExt;
+use gstreamer as gst;
+use gstreamer::prelude::*;
+use gstreamer::MessageView;
+use gstreamer_app as gst_app;
+use gstreamer_video as gst_video;
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::Instant;
+use tokio::time::{interval, Duration};
+
+use crate::debug;
+use crate::pause;
+
+/// An output without video for this long is restarted, e.g. after the
+/// camera pipeline was recreated
+const STALL_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long detaching waits for the tee to let go of the branch
+const DETACH_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Tee of the camera pipeline an output is fed from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Tee {
+    /// Processed video before the encoder
+    Raw,
+    /// The bitstream WebRTC viewers receive; outputs start on a keyframe
+    Encoded,
+}
+
+impl Tee {
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Tee::Raw => "raw_tee",
+            Tee::Encoded => "encoded_tee",
+        }
+    }
+
+    /// Buffers (0 = unlimited) and time queued on each end before the
+    /// oldest is dropped. Raw frames are megabytes each, so only a couple.
+    fn queue_limits(self) -> (u32, u64) {
+        match self {
+            Tee::Raw => (2, 0),
+            Tee::Encoded => (0, gst::ClockTime::from_seconds(2).nseconds()),
+        }
+    }
+}
+
+/// Parses an output pipeline starting with `appsrc name=src`, to be fed
+/// from `tee`
+pub(crate) fn output_pipeline(
+    description: &str,
+    tee: Tee,
+) -> Result<(gst::Pipeline, gst_app::AppSrc)> {
+    let output = gst::parse::launch(description)?
+        .downcast::<gst::Pipeline>()
+        .map_err(|_| anyhow!("output is not a pipeline"))?;
+    let appsrc = output
+        .by_name("src")
+        .and_then(|src| src.downcast::<gst_app::AppSrc>().ok())
+        .ok_or_else(|| anyhow!("output has no appsrc named src"))?;
+    limit_appsrc(&appsrc, tee);
+    Ok((output, appsrc))
+}
+
+/// Has `appsrc` drop the oldest video rather than buffer without bound
+/// while whatever it feeds is slow
+pub(crate) fn limit_appsrc(appsrc: &gst_app::AppSrc, tee: Tee) {
+    let (max_buffers, max_time) = tee.queue_limits();
+    appsrc.set_property_from_str("leaky-type", "downstream");
+    appsrc.set_property("max-buffers", u64::from(max_buffers));
+    appsrc.set_property("max-time", max_time);
+}
+
+/// Feeds `output` from `camera` until the output fails, ends or gets no
+/// video, then tears both ends down. `on_playing` is called once the output
+/// is running.
+pub(crate) async fn run(
+    camera: &str,
+    tee: Tee,
+    output: gst::Pipeline,
+    appsrc: gst_app::AppSrc,
+    on_playing: impl Fn(),
+) -> Result<()> {
+    let name = camera.to_string();
+    let branch =
+        tokio::task::spawn_blocking(move || Branch::attach(&name, tee, feed_output(tee, appsrc)))
+            .await??;
+    let result = match output.set_state(gst::State::Playing) {
+        Ok(_) => watch(camera, &output, &branch, on_playing).await,
+        Err(e) => Err(anyhow!("cannot start the output: {}", e)),
+    };
+
+    tokio::task::spawn_blocking(move || {
+        branch.detach();
+        let _ = output.set_state(gst::State::Null);
+    })
+    .await?;
+    result
+}
+
+/// Waits for the output to fail, end or run out of video
+async fn watch(
+    camera: &str,
+    output: &gst::Pipeline,
+    branch: &Branch,
+    on_playing: impl Fn(),
+) -> Result<()> {
+    let mut messages = output.bus().ok_or_else(|| anyhow!("output has no bus"))?.stream();
+    let mut stall_check = interval(Duration::from_secs(1));
+    loop {
+        tokio::select! {
+            message = messages.next() => {
+                let Some(message) = message else {
+                    return Ok(());
+                };
+                match message.view() {
+                    MessageView::Error(err) => bail!("{}", err.error()),
+                    MessageView::Eos(_) => bail!("the output ended (e.g. the server closed it)"),
+                    MessageView::StateChanged(sc)
+                        if sc.current() == gst::State::Playing
+                            && sc.src() == Some(output.upcast_ref::<gst::Object>()) =>
+                    {
+                        on_playing();
+                    }
+                    _ => {}
+                }
+            }
+            _ = stall_check.tick() => {
+                let mut last_buffer = branch.last_buffer.lock().unwrap();
+                // A frozen camera sends nothing on purpose
+                if pause::pause_mode(camera).is_some() {
+                    *last_buffer = Instant::now();
+                } else if last_buffer.elapsed() > STALL_TIMEOUT {
+                    bail!("no video from {} for {:?}", camera, STALL_TIMEOUT);
+                }
+            }
+        }
+    }
+}
+
+/// Pushes samples into an output's appsrc, with timestamps restarting
+/// from the first buffer passed on. For encoded video that is a keyframe,
+/// so the output can decode from it.
+fn feed_output(tee: Tee, appsrc: gst_app::AppSrc) -> impl FnMut(gst::Sample) + Send + 'static {
+    let wait_for_keyframe = tee == Tee::Encoded;
+    let mut base: Option<gst::ClockTime> = None;
+    move |sample| {
+        let Some(mut buffer) = sample.buffer_owned() else {
+            return;
+        };
+        let keyframe = !wait_for_keyframe || !buffer.flags().contains(gst::BufferFlags::DELTA_UNIT);
+        let start = match base {
+            Some(start) => start,
+            None if keyframe => *base.insert(buffer.pts().unwrap_or_default()),
+            None => return,
+        };
+        if let Some(caps) = sample.caps_owned() {
+            if appsrc.caps().as_ref() != Some(&caps) {
+                appsrc.set_caps(Some(&caps));
+            }
+        }
+        {
+            let buffer = buffer.make_mut();
+            let pts = buffer.pts().and_then(|pts| pts.checked_sub(start));
+            let dts = buffer.dts().and_then(|dts| dts.checked_sub(start));
+            buffer.set_pts(pts);
+            buffer.set_dts(dts);
+        }
+        // Failures belong to the output; never to the camera
+        let _ = appsrc.push_buffer(buffer);
+    }
+}
+
+/// Pushes `source`'s samples into `appsrc`, moving their timestamps from
+/// its running time to `target`'s. Both run on the system clock, so a
+/// buffer keeps its place in time.
+pub(crate) fn feed_retimed(
+    source: &gst::Pipeline,
+    target: &gst::Pipeline,
+    appsrc: &gst_app::AppSrc,
+) -> impl FnMut(gst::Sample) + Send + 'static {
+    let source = source.downgrade();
+    let target = target.downgrade();
+    let appsrc = appsrc.clone();
+    move |sample| {
+        let (Some(source), Some(target)) = (source.upgrade(), target.upgrade()) else {
+            return;
+        };
+        let Some(mut buffer) = sample.buffer_owned() else {
+            return;
+        };
+        let Some(segment) = sample
+            .segment()
+            .and_then(|segment| segment.downcast_ref::<gst::ClockTime>())
+        else {
+            return;
+        };
+        let source_base = source.base_time().unwrap_or_default();
+        let target_base = target.base_time().unwrap_or_default();
+        let to_target = |ts: Option<gst::ClockTime>| {
+            (segment.to_running_time(ts?)? + source_base).checked_sub(target_base)
+        };
+        if let Some(caps) = sample.caps_owned() {
+            if appsrc.caps().as_ref() != Some(&caps) {
+                appsrc.set_caps(Some(&caps));
+            }
+        }
+        {
+            let buffer = buffer.make_mut();
+            let pts = to_target(buffer.pts());
+            let dts = to_target(buffer.dts());
+            buffer.set_pts(pts);
+            buffer.set_dts(dts);
+        }
+        let _ = appsrc.push_buffer(buffer);
+    }
+}
+
+/// queue ! appsink hanging off a camera's tee, handing its samples on
+pub(crate) struct Branch {
+    pipeline: gst::Pipeline,
+    tee_pad: gst::Pad,
+    queue: gst::Element,
+    appsink: gst::Element,
+    last_buffer: Arc<Mutex<Instant>>,
+}
+
+impl Branch {
+    /// Attaches to the camera's tee, starting its pipeline if no viewer has
+    /// yet. `on_sample` runs on the camera's streaming thread.
+    pub(crate) fn attach(
+        camera: &str,
+        tee: Tee,
+        mut on_sample: impl FnMut(gst::Sample) + Send + 'static,
+    ) -> Result<Self> {
+        let pipeline = debug::find_pipeline(camera)
+            .ok_or_else(|| anyhow!("{} has no running pipeline", camera))?;
+        let tee_element = pipeline
+            .by_name(tee.name())
+            .ok_or_else(|| anyhow!("{} has no {} element", camera, tee.name()))?;
+
+        // Leaky so a stalled output drops frames instead of stalling viewers
+        let queue = gst::ElementFactory::make("queue").build()?;
+        let (max_buffers, max_time) = tee.queue_limits();
+        queue.set_property("max-size-buffers", max_buffers);
+        queue.set_property("max-size-bytes", 0u32);
+        queue.set_property("max-size-time", max_time);
+        queue.set_property_from_str("leaky", "downstream");
+
+        let last_buffer = Arc::new(Mutex::new(Instant::now()));
+        let last_buffer_sink = last_buffer.clone();
+        let appsink = gst_app::AppSink::builder()
+            .sync(false)
+            .callbacks(
+                gst_app::AppSinkCallbacks::builder()
+                    .new_sample(move |sink| {
+                        let sample = sink.pull_sample().map_err(|_| gst::FlowError::Eos)?;
+                        *last_buffer_sink.lock().unwrap() = Instant::now();
+                        on_sample(sample);
+                        Ok(gst::FlowSuccess::Ok)
+                    })
+                    .build(),
+            )
+            .build();
+        let appsink = appsink.upcast::<gst::Element>();
+
+        pipeline.add_many([&queue, &appsink])?;
+        queue.link(&appsink)?;
+        let tee_pad = tee_element
+            .request_pad_simple("src_%u")
+            .ok_or_else(|| anyhow!("Failed to request src pad from {}", tee.name()))?;
+        let queue_sink = queue
+            .static_pad("sink")
+            .ok_or_else(|| anyhow!("output queue has no sink pad"))?;
+        tee_pad.link(&queue_sink)?;
+        appsink.sync_state_with_parent()?;
+        queue.sync_state_with_parent()?;
+
+        if pipeline.current_state() != gst::State::Playing {
+            log::info!("Starting {} pipeline for an output", camera);
+            pipeline.set_state(gst::State::Playing)?;
+        }
+        if tee == Tee::Encoded {
+            queue_sink.push_event(
+                gst_video::UpstreamForceKeyUnitEvent::builder()
+                    .all_headers(true)
+                    .build(),
+            );
+        }
+
+        Ok(Self {
+            pipeline,
+            tee_pad,
+            queue,
+            appsink,
+            last_buffer,
+        })
+    }
+
+    /// How long since the camera last handed on a buffer
+    pub(crate) fn idle_for(&self) -> Duration {
+        self.last_buffer.lock().unwrap().elapsed()
+    }
+
+    /// Unlinks the branch while no buffer is in flight and removes it.
+    /// Blocks for up to DETACH_TIMEOUT.
+    pub(crate) fn detach(self) {
+        let (unlinked_tx, unlinked) = mpsc::channel();
+        self.tee_pad.add_probe(gst::PadProbeType::IDLE, move |pad, _| {
+            if let Some(peer) = pad.peer() {
+                let _ = pad.unlink(&peer);
+            }
+            let _ = unlinked_tx.send(());
+            gst::PadProbeReturn::Remove
+        });
+        if unlinked.recv_timeout(DETACH_TIMEOUT).is_err() {
+            log::warn!("Output branch was not unlinked within {:?}", DETACH_TIMEOUT);
+        }
+
+        let _ = self.appsink.set_state(gst::State::Null);
+        let _ = self.queue.set_state(gst::State::Null);
+        let _ = self.pipeline.remove_many([&self.queue, &self.appsink]);
+        if let Some(tee) = self.tee_pad.parent_element() {
+            tee.release_request_pad(&self.tee_pad);
+        }
+    }
+}
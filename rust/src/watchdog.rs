@@ -0,0 +1,283 @@
+//! Camera watchdog with power-cycle recovery
+//!
+//! Cameras behind flaky USB hubs sometimes drop off the bus, after which
+//! every pipeline restart fails the same way. With `recovery.enabled` a
+//! watchdog per camera notices when a playing pipeline stops delivering
+//! frames (or its /dev node disappears), restarts the pipeline a few times,
+//! then power-cycles the camera's hub port or GPIO power switch before each
+//! further restart, backing off between attempts. After `max-power-cycles`
+//! it gives up until frames come back (e.g. after a manual restart-pipeline).
+//!
+//! The watchdog's state is part of each camera's state, so control clients
+//! and gRPC subscribers get an update whenever it changes.
+
+use anyhow::{anyhow, bail, Result};
+use gstreamer as gst;
+use gstreamer::prelude::*;
+use once_cell::sync::Lazy;
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::Path;
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::config::{PowerControl, RecoveryConfig};
+use crate::control;
+use crate::debug;
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+static STATUS: Lazy<Mutex<HashMap<String, RecoveryStatus>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+#[derive(Debug, Clone, Copy, Default, Serialize, PartialEq, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum RecoveryState {
+    #[default]
+    Healthy,
+    Recovering,
+    /// Out of attempts; waiting for frames to come back on their own
+    GaveUp,
+}
+
+#[derive(Debug, Clone, Default, Serialize, PartialEq, JsonSchema)]
+pub(crate) struct RecoveryStatus {
+    pub state: RecoveryState,
+    /// What went wrong, while not healthy
+    pub reason: Option<String>,
+    /// Pipeline restarts in the current recovery
+    pub restarts: u32,
+    /// Power cycles in the current recovery
+    pub power_cycles: u32,
+    /// Recoveries completed since startup
+    pub recoveries: u64,
+}
+
+/// Watchdog state of `camera`, if it has a watchdog
+pub(crate) fn status(camera: &str) -> Option<RecoveryStatus> {
+    STATUS.lock().unwrap().get(camera).cloned()
+}
+
+fn set_status(camera: &str, status: &RecoveryStatus) {
+    STATUS.lock().unwrap().insert(camera.to_string(), status.clone());
+}
+
+enum Action {
+    Restart,
+    PowerCycle(PowerControl),
+}
+
+/// Next step of a recovery, or None once out of attempts
+fn next_action(status: &RecoveryStatus, config: &RecoveryConfig) -> Option<Action> {
+    if status.restarts < config.reinit_attempts {
+        return Some(Action::Restart);
+    }
+    match config.power {
+        Some(ref power) if status.power_cycles < config.max_power_cycles => {
+            Some(Action::PowerCycle(power.clone()))
+        }
+        _ => None,
+    }
+}
+
+/// Watches `camera` (whose pipeline is looked up by name, so it may come
+/// and go) and recovers it as configured. Runs until cancelled.
+pub async fn run_watchdog(camera: String, device: String, config: RecoveryConfig) {
+    let initial_backoff = Duration::from_millis(config.backoff_initial_ms);
+    let max_backoff = Duration::from_millis(config.backoff_max_ms);
+    let mut backoff = initial_backoff;
+    let mut next_attempt = Instant::now();
+    let mut status = RecoveryStatus::default();
+    set_status(&camera, &status);
+
+    let last_frame = Arc::new(Mutex::new(None));
+    // Frames are only expected after this; moved on when a pipeline is
+    // (re)started so it gets a full stall timeout to deliver
+    let mut grace_start = Instant::now();
+    let mut watched: Option<gst::Pipeline> = None;
+    let mut interval = tokio::time::interval(CHECK_INTERVAL);
+    log::info!("Watchdog started for {}", camera);
+
+    loop {
+        interval.tick().await;
+        let Some(pipeline) = debug::find_pipeline(&camera) else {
+            continue;
+        };
+        if watched.as_ref() != Some(&pipeline) {
+            if let Err(e) = watch_frames(&pipeline, &last_frame) {
+                log::warn!("Watchdog for {} cannot see frames: {}", camera, e);
+            }
+            grace_start = Instant::now();
+            watched = Some(pipeline.clone());
+        }
+
+        let last = *last_frame.lock().unwrap();
+        let fault = match check(&pipeline, &device, last, grace_start, &config) {
+            Health::Waiting => continue,
+            Health::Streaming | Health::Idle if status.state == RecoveryState::Healthy => {
+                backoff = initial_backoff;
+                continue;
+            }
+            Health::Streaming => {
+                log::info!(
+                    "{} recovered after {} restarts and {} power cycles",
+                    camera,
+                    status.restarts,
+                    status.power_cycles
+                );
+                status = RecoveryStatus {
+                    recoveries: status.recoveries + 1,
+                    ..Default::default()
+                };
+                set_status(&camera, &status);
+                continue;
+            }
+            Health::Idle => {
+                // Nobody is watching any more, so there is nothing to judge
+                log::info!("{} stopped streaming, ending its recovery", camera);
+                status = RecoveryStatus {
+                    recoveries: status.recoveries,
+                    ..Default::default()
+                };
+                set_status(&camera, &status);
+                continue;
+            }
+            Health::Failed(fault) => fault,
+        };
+        if status.state == RecoveryState::GaveUp || Instant::now() < next_attempt {
+            continue;
+        }
+
+        if status.state == RecoveryState::Healthy {
+            log::warn!("{}: {}, starting recovery", camera, fault);
+        }
+        status.state = RecoveryState::Recovering;
+        status.reason = Some(fault);
+
+        match next_action(&status, &config) {
+            None => {
+                log::error!(
+                    "{}: giving up after {} restarts and {} power cycles; restart it once fixed",
+                    camera,
+                    status.restarts,
+                    status.power_cycles
+                );
+                status.state = RecoveryState::GaveUp;
+                set_status(&camera, &status);
+                continue;
+            }
+            Some(Action::Restart) => status.restarts += 1,
+            Some(Action::PowerCycle(power)) => {
+                status.power_cycles += 1;
+                set_status(&camera, &status);
+                log::warn!(
+                    "Power-cycling {} ({} of {})",
+                    camera,
+                    status.power_cycles,
+                    config.max_power_cycles
+                );
+                let off = Duration::from_millis(config.power_off_ms);
+                match tokio::task::spawn_blocking(move || power_cycle(&power, off)).await {
+                    Ok(Ok(())) => {}
+                    Ok(Err(e)) => log::error!("Power-cycling {} failed: {}", camera, e),
+                    Err(e) => log::error!("Power-cycling {} failed: {}", camera, e),
+                }
+                tokio::time::sleep(Duration::from_millis(config.settle_ms)).await;
+            }
+        }
+        set_status(&camera, &status);
+
+        let name = camera.clone();
+        match tokio::task::spawn_blocking(move || control::restart_pipeline(&name)).await {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => log::warn!("Watchdog restart of {} failed: {}", camera, e),
+            Err(e) => log::warn!("Watchdog restart of {} failed: {}", camera, e),
+        }
+        grace_start = Instant::now();
+        next_attempt = Instant::now() + backoff;
+        backoff = (backoff * 2).min(max_backoff);
+    }
+}
+
+/// Stamps `last_frame` with every buffer leaving the camera's caps filter
+fn watch_frames(pipeline: &gst::Pipeline, last_frame: &Arc<Mutex<Option<Instant>>>) -> Result<()> {
+    let pad = pipeline
+        .by_name("cfilter")
+        .and_then(|filter| filter.static_pad("src"))
+        .ok_or_else(|| anyhow!("pipeline has no cfilter src pad"))?;
+    let last_frame = last_frame.clone();
+    pad.add_probe(gst::PadProbeType::BUFFER, move |_, _| {
+        *last_frame.lock().unwrap() = Some(Instant::now());
+        gst::PadProbeReturn::Ok
+    });
+    Ok(())
+}
+
+enum Health {
+    /// Frames arrived since `grace_start` and keep arriving
+    Streaming,
+    /// Not playing, e.g. no clients
+    Idle,
+    /// Playing, but still within the stall timeout of `grace_start`
+    Waiting,
+    Failed(String),
+}
+
+fn check(
+    pipeline: &gst::Pipeline,
+    device: &str,
+    last_frame: Option<Instant>,
+    grace_start: Instant,
+    config: &RecoveryConfig,
+) -> Health {
+    // libcamera names ("/base/...") have no device node to check
+    if device.starts_with("/dev/") && !Path::new(device).exists() {
+        return Health::Failed(format!("{} disappeared", device));
+    }
+    if pipeline.current_state() != gst::State::Playing {
+        return Health::Idle;
+    }
+
+    let timeout = Duration::from_millis(config.stall_timeout_ms);
+    let latest = last_frame.map_or(grace_start, |last| last.max(grace_start));
+    if latest.elapsed() > timeout {
+        Health::Failed(format!("no frames for {:.1}s", latest.elapsed().as_secs_f32()))
+    } else if latest > grace_start {
+        Health::Streaming
+    } else {
+        Health::Waiting
+    }
+}
+
+fn power_cycle(power: &PowerControl, off: Duration) -> Result<()> {
+    set_power(power, false)?;
+    std::thread::sleep(off);
+    set_power(power, true)
+}
+
+fn set_power(power: &PowerControl, on: bool) -> Result<()> {
+    let mut command = match power {
+        PowerControl::Uhubctl { location, port } => {
+            let mut command = Command::new("uhubctl");
+            command
+                .args(["-l", location, "-p", &port.to_string()])
+                .args(["-a", if on { "on" } else { "off" }]);
+            command
+        }
+        PowerControl::Gpio { chip, line, active_low } => {
+            let mut command = Command::new("gpioset");
+            command.arg(chip).arg(format!("{}={}", line, (on != *active_low) as u8));
+            command
+        }
+    };
+    let program = command.get_program().to_string_lossy().into_owned();
+    let output = command
+        .output()
+        .map_err(|e| anyhow!("'{}' not available: {}", program, e))?;
+    if !output.status.success() {
+        bail!("{} failed: {}", program, String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(())
+}
@@ -0,0 +1,105 @@
+//! Per-stream watermark
+//!
+//! With `watermark.enabled`, a small translucent text is drawn into the
+//! camera's frames before they are encoded (and before the raw tee, so NDI
+//! carries it too). It names the device and the stream, so footage that
+//! leaks can be traced back to the unit it came from. `{serial}`,
+//! `{hostname}` and `{stream}` in the text are filled in at startup.
+
+use anyhow::{bail, Result};
+use gstreamer as gst;
+use gstreamer::prelude::*;
+
+use crate::config::{Config, WatermarkConfig};
+
+/// Where the serial number of a Raspberry Pi is exposed
+const SERIAL_PATHS: &[&str] = &[
+    "/proc/device-tree/serial-number",
+    "/sys/firmware/devicetree/base/serial-number",
+];
+
+/// `position` values, as textoverlay (valignment, halignment)
+fn alignment(position: &str) -> Option<(&'static str, &'static str)> {
+    match position {
+        "top-left" => Some(("top", "left")),
+        "top-right" => Some(("top", "right")),
+        "bottom-left" => Some(("bottom", "left")),
+        "bottom-right" => Some(("bottom", "right")),
+        _ => None,
+    }
+}
+
+pub fn validate(config: &Config) -> Result<()> {
+    for (name, cam) in config.streams() {
+        let watermark = &cam.watermark;
+        if !watermark.enabled {
+            continue;
+        }
+        if watermark.text.trim().is_empty() {
+            bail!("{}.watermark.text is empty", name);
+        }
+        if alignment(&watermark.position).is_none() {
+            bail!(
+                "{}.watermark.position '{}' must be top-left, top-right, bottom-left or bottom-right",
+                name,
+                watermark.position
+            );
+        }
+        if !(0.0..=1.0).contains(&watermark.opacity) {
+            bail!("{}.watermark.opacity must be between 0 and 1", name);
+        }
+    }
+    Ok(())
+}
+
+/// The board's serial number, falling back to the systemd machine id
+pub fn device_serial() -> String {
+    SERIAL_PATHS
+        .iter()
+        .chain(&["/etc/machine-id"])
+        .filter_map(|path| std::fs::read_to_string(path).ok())
+        .map(|s| s.trim_matches(|c: char| c == '\0' || c.is_whitespace()).to_string())
+        .find(|s| !s.is_empty())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+fn hostname() -> String {
+    std::fs::read_to_string("/proc/sys/kernel/hostname")
+        .map(|s| s.trim().to_string())
+        .unwrap_or_default()
+}
+
+/// `text` with its placeholders filled in for `stream`
+pub fn render(text: &str, stream: &str) -> String {
+    let mut out = text.replace("{stream}", stream);
+    if out.contains("{serial}") {
+        out = out.replace("{serial}", &device_serial());
+    }
+    if out.contains("{hostname}") {
+        out = out.replace("{hostname}", &hostname());
+    }
+    out
+}
+
+/// textoverlay drawing the watermark of `stream`, named "watermark"
+pub fn create_element(config: &WatermarkConfig, stream: &str) -> Result<gst::Element> {
+    let (valign, halign) = alignment(&config.position).unwrap_or(("bottom", "right"));
+    let text = render(&config.text, stream);
+    // White at the configured opacity, without the default outline and shadow
+    // that would make it stand out
+    let alpha = (config.opacity.clamp(0.0, 1.0) * 255.0).round() as u32;
+    let color = (alpha << 24) | 0x00ff_ffff;
+
+    let overlay = gst::ElementFactory::make("textoverlay").name("watermark").build()?;
+    overlay.set_property("text", &text);
+    overlay.set_property_from_str("valignment", valign);
+    overlay.set_property_from_str("halignment", halign);
+    overlay.set_property("font-desc", &config.font_desc);
+    overlay.set_property("color", color);
+    overlay.set_property("draw-outline", false);
+    overlay.set_property("draw-shadow", false);
+    overlay.set_property("shaded-background", false);
+
+    log::info!("Watermarking {} with \"{}\"", stream, text);
+    Ok(overlay)
+}
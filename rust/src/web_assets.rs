@@ -0,0 +1,89 @@
+use once_cell::sync::Lazy;
+use rust_embed::RustEmbed;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+
+// Web UI compiled into the binary (also in debug builds, via the
+// `debug-embed` feature), so the server doesn't depend on the working
+// directory it was started from.
+#[derive(RustEmbed)]
+#[folder = "web/"]
+struct Assets;
+
+// Gzipped bodies, compressed on first request. Assets are immutable for the
+// lifetime of the binary, so entries never need invalidating.
+static GZIPPED: Lazy<Mutex<HashMap<String, Arc<Vec<u8>>>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+pub struct Asset {
+    pub name: String,
+    pub data: Cow<'static, [u8]>,
+    pub content_type: &'static str,
+    /// Quoted strong ETag derived from the content hash
+    pub etag: String,
+}
+
+/// Embedded file for a request path ("/" serves index.html).
+pub fn get(path: &str) -> Option<Asset> {
+    let route = path.split_once('?').map_or(path, |(route, _)| route);
+    let name = match route.trim_start_matches('/') {
+        "" => "index.html",
+        name => name,
+    };
+
+    let file = Assets::get(name)?;
+    let etag = file
+        .metadata
+        .sha256_hash()
+        .iter()
+        .take(16)
+        .map(|b| format!("{:02x}", b))
+        .collect::<String>();
+    Some(Asset {
+        name: name.to_string(),
+        data: file.data,
+        content_type: content_type(name),
+        etag: format!("\"{}\"", etag),
+    })
+}
+
+impl Asset {
+    /// Text assets are worth compressing; images are already compressed.
+    pub fn compressible(&self) -> bool {
+        self.content_type.starts_with("text/")
+            || self.content_type == "application/json"
+            || self.content_type == "image/svg+xml"
+    }
+
+    /// Gzipped body, cached after the first call.
+    pub fn gzipped(&self) -> Option<Arc<Vec<u8>>> {
+        if let Some(cached) = GZIPPED.lock().ok()?.get(&self.name) {
+            return Some(cached.clone());
+        }
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+        encoder.write_all(&self.data).ok()?;
+        let compressed = Arc::new(encoder.finish().ok()?);
+        GZIPPED
+            .lock()
+            .ok()?
+            .insert(self.name.clone(), compressed.clone());
+        Some(compressed)
+    }
+}
+fn content_type(name: &str) -> &'static str {
+    match name.rsplit_once('.').map(|(_, ext)| ext) {
+        Some("html") => "text/html; charset=utf-8",
+        Some("js") => "text/javascript; charset=utf-8",
+        Some("css") => "text/css; charset=utf-8",
+        Some("json") => "application/json",
+        Some("svg") => "image/svg+xml",
+        Some("png") => "image/png",
+        Some("ico") => "image/x-icon",
+        _ => "application/octet-stream",
+    }
+}
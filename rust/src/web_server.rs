@@ -1,19 +1,44 @@
 use anyhow::Result;
-use tokio::net::{TcpListener, TcpStream};
+use schemars::JsonSchema;
+use serde::Serialize;
+use tokio::net::TcpStream;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::fs;
+use crate::api_schema;
+use crate::audit::{self, Actor};
 use crate::config::Config;
+use crate::control::{self, ControlCommand};
+use crate::debug;
+use crate::handover;
+use crate::log_buffer;
+use crate::mjpeg_rtp;
+use crate::pause;
+use crate::recording;
+use crate::sensors::runner;
+use crate::streams::{self, StreamInfo};
+use crate::system_monitor;
+use crate::tasks;
+use crate::web_assets;
 
-pub async fn run_web_server(port: u16, pi_ip: String, config: Config) -> Result<()> {
-    let addr = format!("0.0.0.0:{}", port);
-    let listener = TcpListener::bind(&addr).await?;
+pub async fn run_web_server(port: u16, pi_ip: String, base_port: u16, config: Config) -> Result<()> {
+    let listener = handover::listener(port, &config.handover)?;
     log::info!("Web server listening on http://{}:{}", pi_ip, port);
 
-    while let Ok((stream, _)) = listener.accept().await {
+    loop {
+        // A successor takes new requests once this instance drains
+        let stream = tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok((stream, _)) => stream,
+                Err(_) => break,
+            },
+            _ = handover::draining() => {
+                log::info!("Web server stopped accepting for handover");
+                break;
+            }
+        };
         let pi_ip_clone = pi_ip.clone();
         let config_clone = config.clone();
         tokio::spawn(async move {
-            if let Err(e) = handle_web_request(stream, pi_ip_clone, config_clone).await {
+            if let Err(e) = handle_web_request(stream, pi_ip_clone, port, base_port, config_clone).await {
                 log::error!("Web server error: {}", e);
             }
         });
@@ -21,8 +46,16 @@ pub async fn run_web_server(port: u16, pi_ip: String, config: Config) -> Result<
     Ok(())
 }
 
-async fn handle_web_request(mut stream: TcpStream, pi_ip: String, config: Config) -> Result<()> {
-    let mut buffer = [0; 1024];
+async fn handle_web_request(mut stream: TcpStream, pi_ip: String, web_port: u16, base_port: u16, config: Config) -> Result<()> {
+    // WebSocket upgrades need the untouched request, so peek before reading
+    let mut peek_buffer = [0; 16];
+    let peeked = stream.peek(&mut peek_buffer).await?;
+    if peek_buffer[..peeked].starts_with(b"GET /ws/control") {
+        return control::handle_control_socket(stream, config).await;
+    }
+
+    // Large enough for browser requests carrying cookies and conditional headers
+    let mut buffer = [0; 8192];
     let bytes_read = stream.read(&mut buffer).await?;
     let request = String::from_utf8_lossy(&buffer[..bytes_read]);
     
@@ -30,106 +63,544 @@ async fn handle_web_request(mut stream: TcpStream, pi_ip: String, config: Config
     let first_line = request.lines().next().unwrap_or("invalid request");
     log::info!("Web server request: {}", first_line);
     
+    let path = first_line.split_whitespace().nth(1).unwrap_or("/");
+
     if request.starts_with("GET /api/config") {
         log::info!("Serving config API");
-        let response = create_config_response(&config).await;
+        let command_line = CommandLine { web_port, base_port, pi_ip };
+        let response = create_config_response(&config, command_line);
+        stream.write_all(response.as_bytes()).await?;
+    } else if request.starts_with("GET /api/cameras") {
+        let response = create_cameras_response(&config, &pi_ip, base_port);
+        stream.write_all(response.as_bytes()).await?;
+    } else if request.starts_with("GET /api/schema.d.ts") {
+        let response = create_text_response("200 OK", "application/typescript", &api_schema::typescript());
+        stream.write_all(response.as_bytes()).await?;
+    } else if request.starts_with("GET /api/schema") {
+        let response = create_json_response("200 OK", &api_schema::openapi().to_string());
+        stream.write_all(response.as_bytes()).await?;
+    } else if request.starts_with("GET /api/streams") {
+        let response = create_streams_response(path, &config, &pi_ip, base_port);
+        stream.write_all(response.as_bytes()).await?;
+    } else if request.starts_with("POST /api/cameras/")
+        || request.starts_with("POST /api/streams/")
+        || request.starts_with("POST /api/sessions/")
+    {
+        let response = if !is_control_authorized(&request, &config) {
+            log::warn!("Rejected unauthorized control request: {}", first_line);
+            create_json_response("401 Unauthorized", r#"{"error": "admin token required"}"#)
+        } else {
+            let actor = Actor {
+                interface: "rest",
+                peer: stream.peer_addr().ok().map(|addr| addr.to_string()),
+                authenticated: config.web.admin_token.as_deref().is_some_and(|t| !t.is_empty()),
+            };
+            create_control_response(path, &config, &actor).await
+        };
+        stream.write_all(response.as_bytes()).await?;
+    } else if request.starts_with("GET /api/sessions") {
+        let response = if !is_control_authorized(&request, &config) {
+            create_json_response("401 Unauthorized", r#"{"error": "admin token required"}"#)
+        } else {
+            create_json_response("200 OK", &serde_json::json!(SessionList { sessions: pause::sessions() }).to_string())
+        };
+        stream.write_all(response.as_bytes()).await?;
+    } else if request.starts_with("GET /api/status") {
+        let response = create_status_response();
+        stream.write_all(response.as_bytes()).await?;
+    } else if request.starts_with("GET /api/stats") {
+        let response = create_stats_response(&config);
+        stream.write_all(response.as_bytes()).await?;
+    } else if request.starts_with("GET /api/logs") {
+        let response = if !is_admin_authorized(&request, &config) {
+            log::warn!("Rejected unauthorized logs request");
+            create_json_response("401 Unauthorized", r#"{"error": "admin token required"}"#)
+        } else {
+            create_logs_response(path)
+        };
+        stream.write_all(response.as_bytes()).await?;
+    } else if request.starts_with("GET /api/audit") {
+        let response = if !is_admin_authorized(&request, &config) {
+            log::warn!("Rejected unauthorized audit request");
+            create_json_response("401 Unauthorized", r#"{"error": "admin token required"}"#)
+        } else {
+            create_audit_response(path)
+        };
         stream.write_all(response.as_bytes()).await?;
+    } else if request.starts_with("GET /api/debug/") {
+        let response = if !is_admin_authorized(&request, &config) {
+            log::warn!("Rejected unauthorized debug request: {}", first_line);
+            create_json_response("401 Unauthorized", r#"{"error": "admin token required"}"#)
+        } else {
+            create_debug_response(path, &config).await
+        };
+        stream.write_all(response.as_bytes()).await?;
+    } else if let Some(asset) = web_assets::get(path) {
+        stream.write_all(&create_asset_response(&request, &asset)).await?;
     } else {
-        log::info!("Serving HTML page with PI IP: {}", pi_ip);
-        let response = create_html_response(&pi_ip).await;
+        let response = create_404_response();
         stream.write_all(response.as_bytes()).await?;
     }
     
     Ok(())
 }
 
-async fn create_config_response(config: &Config) -> String {
-    let config_json = format!(
-        r#"{{"codec": "{}", "bitrate": {}, "keyframe_interval": {}}}"#,
-        config.video.codec,
-        config.webrtc.bitrate,
-        config.video.keyframe_interval
-    );
-    
-    format!(
-        "HTTP/1.1 200 OK\r\n\
-         Content-Type: application/json\r\n\
-         Access-Control-Allow-Origin: *\r\n\
-         Content-Length: {}\r\n\
-         \r\n\
-         {}",
-        config_json.len(),
-        config_json
-    )
+/// Looks up a header value (case-insensitive name) in a raw HTTP request.
+fn header_value<'a>(request: &'a str, name: &str) -> Option<&'a str> {
+    request
+        .lines()
+        .skip(1)
+        .take_while(|line| !line.is_empty())
+        .find_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            if key.trim().eq_ignore_ascii_case(name) {
+                Some(value.trim())
+            } else {
+                None
+            }
+        })
 }
 
-async fn create_html_response(pi_ip: &str) -> String {
-    match load_html_template(pi_ip).await {
-        Ok(html) => {
-            format!(
-                "HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\n\r\n{}",
-                html.len(),
-                html
-            )
+fn is_admin_authorized(request: &str, config: &Config) -> bool {
+    match config.web.admin_token.as_deref() {
+        Some(token) if !token.is_empty() => bearer_matches(request, token),
+        _ => false, // Debug endpoints stay closed until a token is configured
+    }
+}
+
+/// Pause/mute endpoints follow /ws/control: open without a token, otherwise
+/// the token is required.
+fn is_control_authorized(request: &str, config: &Config) -> bool {
+    match config.web.admin_token.as_deref() {
+        Some(token) if !token.is_empty() => bearer_matches(request, token),
+        _ => true,
+    }
+}
+
+fn bearer_matches(request: &str, expected: &str) -> bool {
+    header_value(request, "Authorization")
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(|token| control::token_matches(token.trim(), expected))
+        .unwrap_or(false)
+}
+
+/// Returns the value of `key` in the query string of `path`, if present.
+fn query_param<'a>(path: &'a str, key: &str) -> Option<&'a str> {
+    let (_, query) = path.split_once('?')?;
+    query.split('&').find_map(|kv| {
+        let (k, v) = kv.split_once('=')?;
+        if k == key {
+            Some(v)
+        } else {
+            None
         }
+    })
+}
+
+/// Body of `GET /api/streams`
+#[derive(Debug, Serialize, JsonSchema)]
+pub(crate) struct StreamList {
+    pub streams: Vec<StreamInfo>,
+}
+
+/// Body of `GET /api/cameras`, everything the web UI needs to start
+#[derive(Debug, Serialize, JsonSchema)]
+pub(crate) struct CamerasResponse {
+    pub cameras: Vec<StreamInfo>,
+    pub ice_servers: Vec<IceServer>,
+    /// Label of the data channel to open for keyboard/gamepad input
+    pub control_channel: Option<String>,
+}
+
+/// Entry of RTCPeerConnection's `iceServers`
+#[derive(Debug, Serialize, JsonSchema)]
+pub(crate) struct IceServer {
+    pub urls: String,
+}
+
+/// Body of `GET /api/sessions`
+#[derive(Debug, Serialize, JsonSchema)]
+pub(crate) struct SessionList {
+    pub sessions: Vec<pause::SessionInfo>,
+}
+
+/// Body of `GET /api/config`: the settings this unit is actually running
+#[derive(Debug, Serialize, JsonSchema)]
+pub(crate) struct EffectiveConfig {
+    /// config.toml over the built-in defaults, with secrets resolved and
+    /// then masked; keys as in config.toml
+    pub config: serde_json::Value,
+    pub command_line: CommandLine,
+    /// SHA-256 of `config`, also reported by /api/stats as `config_hash`
+    pub hash: String,
+}
+
+/// Settings given as command-line flags (or their defaults)
+#[derive(Debug, Serialize, JsonSchema)]
+pub(crate) struct CommandLine {
+    pub web_port: u16,
+    pub base_port: u16,
+    pub pi_ip: String,
+}
+
+/// Body of `POST` control endpoints
+#[derive(Debug, Serialize, JsonSchema)]
+pub(crate) struct OkResponse {
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// One entry per stream with its signaling endpoint and live state.
+pub(crate) fn stream_entries(config: &Config, pi_ip: &str, base_port: u16) -> Vec<StreamInfo> {
+    let active = debug::registered_cameras();
+    // Cameras get consecutive signaling ports starting at base_port (see main)
+    config
+        .streams()
+        .iter()
+        .enumerate()
+        .map(|(i, (name, cam))| {
+            let port = base_port + i as u16;
+            StreamInfo {
+                name: name.clone(),
+                camera: i + 1,
+                device: cam.device.clone(),
+                width: cam.target_width,
+                height: cam.target_height,
+                fps: cam.fps,
+                signaling_port: port,
+                ws_url: streams::signaling_url(pi_ip, port, name),
+                active: active.iter().any(|c| c == name),
+                recording: recording::is_recording(name),
+                paused: pause::pause_mode(name),
+            }
+        })
+        .collect()
+}
+
+/// GET /api/streams, GET /api/streams/{name}
+fn create_streams_response(path: &str, config: &Config, pi_ip: &str, base_port: u16) -> String {
+    let streams = stream_entries(config, pi_ip, base_port);
+    let route = path.split_once('?').map_or(path, |(route, _)| route);
+    let name = match route.strip_prefix("/api").and_then(streams::name_from_path) {
+        Some(name) => name,
+        None => return create_json_response("200 OK", &serde_json::json!(StreamList { streams }).to_string()),
+    };
+
+    match streams.iter().find(|stream| stream.name == name) {
+        Some(stream) => create_json_response("200 OK", &serde_json::json!(stream).to_string()),
+        None => create_json_response(
+            "404 Not Found",
+            &serde_json::json!({ "error": format!("unknown stream '{}'", name) }).to_string(),
+        ),
+    }
+}
+
+/// Cameras and their signaling endpoints, for the web UI grid.
+fn create_cameras_response(config: &Config, pi_ip: &str, base_port: u16) -> String {
+    let body = CamerasResponse {
+        cameras: stream_entries(config, pi_ip, base_port),
+        ice_servers: vec![IceServer {
+            urls: config.webrtc.stun_server.clone(),
+        }],
+        control_channel: config
+            .control_channel
+            .enabled
+            .then(|| config.control_channel.label.clone()),
+    };
+    create_json_response("200 OK", &serde_json::json!(body).to_string())
+}
+
+fn create_stats_response(config: &Config) -> String {
+    let body = serde_json::json!({
+        "config_hash": config.hash(),
+        "system": system_monitor::latest(),
+        "cameras": debug::registered_cameras(),
+        "sensors": runner::health(),
+        "mjpeg_rtp": mjpeg_rtp::stats(),
+    });
+    create_json_response("200 OK", &body.to_string())
+}
+
+/// GET /api/status: how each supervised component is doing
+fn create_status_response() -> String {
+    let body = serde_json::json!({
+        "components": tasks::components().snapshot(),
+        "mjpeg_rtp": mjpeg_rtp::components(),
+    });
+    create_json_response("200 OK", &body.to_string())
+}
+
+/// GET /api/audit?since=<ts_ms>&limit=1000[&format=jsonl]
+fn create_audit_response(path: &str) -> String {
+    let since = query_param(path, "since").and_then(|s| s.parse().ok()).unwrap_or(0);
+    let limit = query_param(path, "limit")
+        .and_then(|l| l.parse::<usize>().ok())
+        .unwrap_or(usize::MAX);
+
+    let entries = match audit::query(since, limit) {
+        Ok(entries) => entries,
         Err(e) => {
-            log::error!("Failed to load HTML template: {}", e);
-            create_fallback_response(pi_ip)
+            return create_json_response(
+                "500 Internal Server Error",
+                &serde_json::json!({ "error": e.to_string() }).to_string(),
+            )
         }
+    };
+    if query_param(path, "format") == Some("jsonl") {
+        // Same lines as the log file, for archiving
+        let body: String = entries
+            .iter()
+            .filter_map(|entry| serde_json::to_string(entry).ok())
+            .map(|line| line + "\n")
+            .collect();
+        return create_text_response("200 OK", "application/x-ndjson", &body);
+    }
+    match serde_json::to_string(&entries) {
+        Ok(json) => create_json_response("200 OK", &json),
+        Err(e) => create_json_response(
+            "500 Internal Server Error",
+            &serde_json::json!({ "error": e.to_string() }).to_string(),
+        ),
     }
 }
 
-async fn load_html_template(pi_ip: &str) -> Result<String> {
-    log::info!("Loading HTML template from web/viewer.html");
-    let html_content = fs::read_to_string("web/viewer.html").await?;
-    let html_with_ip = html_content.replace("PI_IP_PLACEHOLDER", pi_ip);
-    log::info!("HTML template loaded successfully, replaced IP with: {}", pi_ip);
-    Ok(html_with_ip)
+/// GET /api/logs?level=warn&target=gst&limit=500
+fn create_logs_response(path: &str) -> String {
+    let min_level = query_param(path, "level")
+        .and_then(|l| l.parse::<log::LevelFilter>().ok())
+        .unwrap_or(log::LevelFilter::Trace);
+    let target = query_param(path, "target").filter(|t| !t.is_empty());
+    let limit = query_param(path, "limit")
+        .and_then(|l| l.parse::<usize>().ok())
+        .unwrap_or(1000);
+
+    let lines = log_buffer::query(min_level, target, limit);
+    match serde_json::to_string(&lines) {
+        Ok(json) => create_json_response("200 OK", &json),
+        Err(e) => create_json_response(
+            "500 Internal Server Error",
+            &serde_json::json!({ "error": e.to_string() }).to_string(),
+        ),
+    }
 }
 
-fn create_fallback_response(pi_ip: &str) -> String {
-    let html = format!(r#"<!DOCTYPE html>
-<html>
-<head>
-    <title>RPi Sensor Streamer</title>
-    <meta name="viewport" content="width=device-width, initial-scale=1.0">
-    <style>
-        body {{ 
-            font-family: Arial, sans-serif; 
-            margin: 0; 
-            padding: 20px; 
-            background: #f0f0f0;
-            text-align: center;
-        }}
-        .error {{
-            background: #ffebee;
-            border: 1px solid #f44336;
-            padding: 20px;
-            border-radius: 10px;
-            margin: 20px auto;
-            max-width: 600px;
-        }}
-    </style>
-</head>
-<body>
-    <h1>RPi Sensor Streamer</h1>
-    <div class="error">
-        <h2>Template Loading Error</h2>
-        <p>Could not load web/viewer.html template file.</p>
-        <p>Please ensure the template file exists in the web directory.</p>
-        <hr>
-        <p><strong>Manual Connection:</strong></p>
-        <p>Camera 1: ws://{pi_ip}:5557</p>
-        <p>Camera 2: ws://{pi_ip}:5558</p>
-    </div>
-</body>
-</html>"#, pi_ip = pi_ip);
+async fn create_debug_response(path: &str, config: &Config) -> String {
+    let route = path.split_once('?').map_or(path, |(route, _)| route);
+
+    if route.trim_end_matches('/') == "/api/debug/gstreamer" {
+        return match serde_json::to_string(&debug::gstreamer_report(config)) {
+            Ok(json) => create_json_response("200 OK", &json),
+            Err(e) => create_json_response(
+                "500 Internal Server Error",
+                &serde_json::json!({ "error": e.to_string() }).to_string(),
+            ),
+        };
+    }
+
+    if let Some(camera) = route.strip_prefix("/api/debug/pipeline/") {
+        let camera = camera.trim_end_matches('/');
+        let report = match debug::pipeline_report(camera) {
+            Some(report) => report,
+            None => {
+                let body = serde_json::json!({
+                    "error": format!("unknown camera '{}'", camera),
+                    "cameras": debug::registered_cameras(),
+                });
+                return create_json_response("404 Not Found", &body.to_string());
+            }
+        };
+
+        return match query_param(path, "format") {
+            Some("dot") => create_text_response("200 OK", "text/vnd.graphviz", &report.dot),
+            Some("svg") => match debug::dot_to_svg(&report.dot).await {
+                Ok(svg) => create_text_response("200 OK", "image/svg+xml", &svg),
+                Err(e) => create_json_response(
+                    "501 Not Implemented",
+                    &serde_json::json!({ "error": e.to_string() }).to_string(),
+                ),
+            },
+            _ => match serde_json::to_string(&report) {
+                Ok(json) => create_json_response("200 OK", &json),
+                Err(e) => create_json_response(
+                    "500 Internal Server Error",
+                    &serde_json::json!({ "error": e.to_string() }).to_string(),
+                ),
+            },
+        };
+    }
+
+    create_json_response("404 Not Found", r#"{"error": "unknown debug endpoint"}"#)
+}
+
+/// POST /api/streams/{name}/pause[?mode=black|freeze]
+/// POST /api/streams/{name}/resume
+/// POST /api/streams/{name}/schedule?mode=on|off|auto
+/// POST /api/streams/{name}/pip?visible=&x=&y=&width=&height=
+/// (also under /api/cameras/, which takes the same names)
+/// POST /api/sessions/{id}/mute, POST /api/sessions/{id}/unmute
+/// POST /api/sessions/{id}/source?camera={name}
+/// Run as control commands, so they are audited like /ws/control.
+async fn create_control_response(path: &str, config: &Config, actor: &Actor) -> String {
+    let result = match control_command(path) {
+        Some(Ok(command)) => control::execute(command, config, actor).await,
+        Some(Err(e)) => Err(e),
+        None => return unknown_endpoint(),
+    };
+
+    match result {
+        Ok(()) => create_json_response("200 OK", r#"{"ok": true}"#),
+        Err(e) => create_json_response(
+            "400 Bad Request",
+            &serde_json::json!({ "ok": false, "error": e.to_string() }).to_string(),
+        ),
+    }
+}
+
+/// The command a REST control path stands for, None if it names none
+fn control_command(path: &str) -> Option<Result<ControlCommand>> {
+    let route = path.split_once('?').map_or(path, |(route, _)| route);
+    let camera_route = route
+        .strip_prefix("/api/streams/")
+        .or_else(|| route.strip_prefix("/api/cameras/"));
+    if let Some(rest) = camera_route {
+        let (camera, action) = rest.trim_end_matches('/').split_once('/')?;
+        let camera = camera.to_string();
+        Some(match action {
+            "pause" => query_param(path, "mode")
+                .unwrap_or("black")
+                .parse()
+                .map(|mode| ControlCommand::Pause { camera, mode }),
+            "resume" => Ok(ControlCommand::Resume { camera }),
+            "schedule" => query_param(path, "mode")
+                .unwrap_or("auto")
+                .parse()
+                .map(|mode| ControlCommand::SetSchedule { camera, mode }),
+            "pip" => pip_command(camera, path),
+            _ => return None,
+        })
+    } else if let Some(rest) = route.strip_prefix("/api/sessions/") {
+        let (id, action) = rest.trim_end_matches('/').split_once('/')?;
+        let session = id.parse::<u64>().ok()?;
+        let muted = match action {
+            "mute" => true,
+            "unmute" => false,
+            "source" => {
+                return Some(
+                    query_param(path, "camera")
+                        .map(|camera| ControlCommand::SetSessionSource { session, camera: camera.to_string() })
+                        .ok_or_else(|| anyhow::anyhow!("missing ?camera=")),
+                )
+            }
+            _ => return None,
+        };
+        Some(Ok(ControlCommand::SetSessionMuted { session, muted }))
+    } else {
+        None
+    }
+}
+
+/// set-pip from the query string; parameters left out keep their value
+fn pip_command(camera: String, path: &str) -> Result<ControlCommand> {
+    Ok(ControlCommand::SetPip {
+        camera,
+        visible: parsed_param(path, "visible")?,
+        x: parsed_param(path, "x")?,
+        y: parsed_param(path, "y")?,
+        width: parsed_param(path, "width")?,
+        height: parsed_param(path, "height")?,
+    })
+}
+
+fn parsed_param<T: std::str::FromStr>(path: &str, key: &str) -> Result<Option<T>>
+where
+    T::Err: std::fmt::Display,
+{
+    query_param(path, key)
+        .map(|value| value.parse().map_err(|e| anyhow::anyhow!("invalid {}: {}", key, e)))
+        .transpose()
+}
 
+fn unknown_endpoint() -> String {
+    create_json_response("404 Not Found", r#"{"error": "unknown endpoint"}"#)
+}
+
+fn create_json_response(status: &str, body: &str) -> String {
+    create_text_response(status, "application/json", body)
+}
+
+fn create_text_response(status: &str, content_type: &str, body: &str) -> String {
     format!(
-        "HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\n\r\n{}",
-        html.len(),
-        html
+        "HTTP/1.1 {}\r\n\
+         Content-Type: {}\r\n\
+         Access-Control-Allow-Origin: *\r\n\
+         Content-Length: {}\r\n\
+         \r\n\
+         {}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )
+}
+
+/// GET /api/config
+fn create_config_response(config: &Config, command_line: CommandLine) -> String {
+    let body = EffectiveConfig {
+        config: serde_json::to_value(config.redacted()).unwrap_or_default(),
+        command_line,
+        hash: config.hash(),
+    };
+    create_json_response("200 OK", &serde_json::json!(body).to_string())
+}
+
+/// Serves an embedded asset, honouring If-None-Match and Accept-Encoding.
+fn create_asset_response(request: &str, asset: &web_assets::Asset) -> Vec<u8> {
+    let not_modified = header_value(request, "If-None-Match")
+        .map(|tags| tags.split(',').any(|tag| tag.trim() == asset.etag || tag.trim() == "*"))
+        .unwrap_or(false);
+    if not_modified {
+        return format!(
+            "HTTP/1.1 304 Not Modified\r\n\
+             ETag: {}\r\n\
+             Cache-Control: no-cache\r\n\
+             Vary: Accept-Encoding\r\n\
+             \r\n",
+            asset.etag
+        )
+        .into_bytes();
+    }
+
+    let accepts_gzip = header_value(request, "Accept-Encoding")
+        .map(|v| v.split(',').any(|enc| enc.split(';').next().map(str::trim) == Some("gzip")))
+        .unwrap_or(false);
+    let gzipped = if accepts_gzip && asset.compressible() {
+        asset.gzipped()
+    } else {
+        None
+    };
+    let (body, encoding_header) = match &gzipped {
+        Some(gz) => (gz.as_slice(), "Content-Encoding: gzip\r\n"),
+        None => (&asset.data[..], ""),
+    };
+
+    let mut response = format!(
+        "HTTP/1.1 200 OK\r\n\
+         Content-Type: {}\r\n\
+         Content-Length: {}\r\n\
+         {}\
+         ETag: {}\r\n\
+         Cache-Control: no-cache\r\n\
+         Vary: Accept-Encoding\r\n\
+         \r\n",
+        asset.content_type,
+        body.len(),
+        encoding_header,
+        asset.etag
     )
+    .into_bytes();
+    response.extend_from_slice(body);
+    response
 }
 
 fn create_favicon_response() -> String {
@@ -2,22 +2,32 @@ use anyhow::Result;
 use gstreamer as gst;
 use gstreamer::prelude::*;
 use log::{debug, info, warn};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{mpsc, Arc};
 
 use tokio::net::TcpStream;
 use tokio::sync::Mutex;
 
-use crate::config::Config;
-use crate::webrtc::codec::{create_rtp_payloader, create_rtp_caps, extract_vp8_payload_type, extract_h264_payload_type};
+use crate::config::{Config, LatencyProfile, SdpMungingConfig};
+use crate::control_channel;
+use crate::gst_props;
+use crate::pause;
+use crate::webrtc::latency;
+use crate::webrtc::sdp_munge;
+use crate::webrtc::switch::{self, SourceSwitch};
+use crate::webrtc::codec::{create_h264_rtp_caps, create_rtp_caps, create_rtp_payloader, extract_vp8_payload_type, negotiate_h264};
 
 use futures_util::{SinkExt, StreamExt};
 use tokio_tungstenite::{accept_async, tungstenite::Message};
 use gstreamer_webrtc as gst_webrtc;
 use gstreamer_sdp as gst_sdp;
+use gstreamer_video as gst_video;
 
 pub struct WebRTCClient {
     pub webrtcbin: gst::Element,
     pub queue: gst::Element,
+    // Picks the camera the session shows, see the switch module
+    pub selector: gst::Element,
     pub tee_src_pad: gst::Pad,
     // Store payloader elements for cleanup
     pub payloader_elements: Arc<Mutex<Vec<gst::Element>>>,
@@ -25,6 +35,9 @@ pub struct WebRTCClient {
     pub webrtc_sink_pad: Arc<Mutex<Option<gst::Pad>>>,
     // Store pipeline reference for cleanup
     pub pipeline: gst::Pipeline,
+    // Id in the pause module's session registry, for per-session mute
+    pub session_id: u64,
+    sdp_munging: SdpMungingConfig,
 }
 
 impl WebRTCClient {
@@ -32,6 +45,9 @@ impl WebRTCClient {
         pipeline: &gst::Pipeline,
         tee: &gst::Element,
         config: &Config,
+        camera: &str,
+        peer: &str,
+        latency: LatencyProfile,
     ) -> Result<Self> {
         // Generate unique client ID for element names to avoid conflicts
         let client_id = std::time::SystemTime::now()
@@ -45,38 +61,71 @@ impl WebRTCClient {
         let queue = gst::ElementFactory::make("queue")
             .name(&format!("client_queue_{}", client_id))
             .build()?;
+        let selector = gst::ElementFactory::make("input-selector")
+            .name(&format!("client_selector_{}", client_id))
+            .build()?;
+        // Inactive inputs drop their buffers instead of waiting for the
+        // active one
+        selector.set_property("sync-streams", &false);
 
         // Configure WebRTC
         let stun_uri = normalize_stun_server(&config.webrtc.stun_server);
         webrtcbin.set_property("stun-server", &stun_uri);
         webrtcbin.set_property_from_str("bundle-policy", "max-bundle");
         
-        // CRITICAL FIX: Proper WebRTC latency configuration to fix RTP session timing
-        webrtcbin.set_property("latency", &200u32); // Standard 200ms latency for stable RTP timing
+        // Jitter buffer and queue depth follow the camera's latency profile
+        let tuning = latency::tuning(latency);
+        webrtcbin.set_property("latency", &tuning.webrtcbin_latency_ms);
         
         // ADDITIONAL LATENCY FIXES: Configure WebRTC bin for proper timing
         // Force the webrtcbin to handle latency queries properly
         webrtcbin.set_property("async-handling", &true); // Enable async state changes for proper latency handling
         
         // Set buffering mode for consistent timing
-        if webrtcbin.has_property("buffering-mode", Some(gst::glib::Type::I32)) {
-            webrtcbin.set_property("buffering-mode", &1i32); // Use stream buffering mode
+        gst_props::set(&webrtcbin, "buffering-mode", 1i32); // Use stream buffering mode
+        
+        // NACK/RTX: webrtcbin negotiates the rtx payload types and inserts
+        // rtprtxsend itself once the transceiver has do-nack set (see
+        // handle_offer); here we only bound how much history it keeps
+        let retransmission = config.webrtc.retransmission;
+        gst_props::set(&webrtcbin, "do-retransmission", retransmission);
+        if retransmission {
+            let rtx_time_ms = config.webrtc.rtx_time_ms;
+            webrtcbin.connect("deep-element-added", false, move |values| {
+                let element = values[2].get::<gst::Element>().ok()?;
+                if element.factory().map_or(false, |f| f.name() == "rtprtxsend") {
+                    element.set_property("max-size-time", &rtx_time_ms);
+                    debug!("rtprtxsend configured: max-size-time={}ms", rtx_time_ms);
+                }
+                None
+            });
         }
         
-        // Disable problematic retransmission that can cause buffer accumulation
-        if webrtcbin.has_property("do-retransmission", Some(gst::glib::Type::BOOL)) {
-            webrtcbin.set_property("do-retransmission", &false);
+        // A new viewer can only start decoding at a keyframe: ask for one as
+        // soon as its connection is up instead of waiting for the next
+        // scheduled one
+        if config.webrtc.keyframe_on_join {
+            let pipeline_weak = pipeline.downgrade();
+            webrtcbin.connect_notify(Some("connection-state"), move |webrtcbin, _| {
+                let state = webrtcbin
+                    .property::<gst_webrtc::WebRTCPeerConnectionState>("connection-state");
+                if state != gst_webrtc::WebRTCPeerConnectionState::Connected {
+                    return;
+                }
+                if let Some(pipeline) = pipeline_weak.upgrade() {
+                    request_keyframe(&pipeline);
+                }
+            });
         }
-        
-        // BALANCED queue configuration - not too aggressive
-        queue.set_property("max-size-buffers", &20u32); // Reasonable buffer count
-        queue.set_property("max-size-time", &(gst::ClockTime::from_mseconds(1000))); // 1 second
+
+        queue.set_property("max-size-buffers", &tuning.client_queue.buffers);
+        queue.set_property("max-size-time", &tuning.client_queue.time);
         queue.set_property("max-size-bytes", &(2 * 1024 * 1024u32)); // 2MB reasonable
         queue.set_property_from_str("leaky", "downstream"); // Drop old buffers when full
         queue.set_property("silent", &true); // Reduce logging overhead
 
         // Add elements to pipeline
-        pipeline.add_many(&[&queue, &webrtcbin])?;
+        pipeline.add_many(&[&queue, &selector, &webrtcbin])?;
 
         // Link queue to tee
         let tee_src_pad = tee.request_pad_simple("src_%u")
@@ -85,8 +134,30 @@ impl WebRTCClient {
             .ok_or_else(|| anyhow::anyhow!("Failed to get queue sink pad"))?;
         tee_src_pad.link(&queue_sink_pad)?;
 
+        // The camera's own video is the selector's first input
+        let own_input = selector.request_pad_simple("sink_%u")
+            .ok_or_else(|| anyhow::anyhow!("Failed to request selector pad"))?;
+        queue.static_pad("src")
+            .ok_or_else(|| anyhow::anyhow!("Failed to get queue src pad"))?
+            .link(&own_input)?;
+
+        // A muted session stops getting frames, from whichever camera it
+        // shows, but keeps its connection
+        let muted = Arc::new(AtomicBool::new(false));
+        if let Some(selector_src_pad) = selector.static_pad("src") {
+            let muted = muted.clone();
+            selector_src_pad.add_probe(gst::PadProbeType::BUFFER, move |_, _| {
+                if muted.load(Ordering::Relaxed) {
+                    gst::PadProbeReturn::Drop
+                } else {
+                    gst::PadProbeReturn::Ok
+                }
+            });
+        }
+
         // Sync states
         queue.sync_state_with_parent()?;
+        selector.sync_state_with_parent()?;
         webrtcbin.sync_state_with_parent()?;
 
         // CRITICAL: Force latency recalculation after WebRTC elements are linked
@@ -104,14 +175,19 @@ impl WebRTCClient {
         }
 
         log::debug!("WebRTC client elements created and linked");
+        let session_id = pause::register_session(camera, peer, muted);
+        switch::register(session_id, SourceSwitch::new(pipeline, camera, tee, &selector, &own_input));
 
         Ok(WebRTCClient {
             webrtcbin,
             queue,
+            selector,
             tee_src_pad,
             payloader_elements: Arc::new(Mutex::new(Vec::new())),
             webrtc_sink_pad: Arc::new(Mutex::new(None)),
             pipeline: pipeline.clone(),
+            session_id,
+            sdp_munging: config.sdp_munging.clone(),
         })
     }
 
@@ -153,6 +229,29 @@ impl WebRTCClient {
             }
         });
 
+        // Browser-created data channel carrying keyboard/gamepad input
+        if config.control_channel.enabled {
+            let label = config.control_channel.label.clone();
+            self.webrtcbin.connect("on-data-channel", false, move |values| {
+                let channel = values[1].get::<gst_webrtc::WebRTCDataChannel>().ok()?;
+                if channel.label().as_deref() != Some(label.as_str()) {
+                    return None;
+                }
+                info!("Control data channel '{}' opened", label);
+                channel.connect_on_message_string(|_, msg| {
+                    if let Some(msg) = msg {
+                        control_channel::forward(msg.as_bytes());
+                    }
+                });
+                channel.connect_on_message_data(|_, data| {
+                    if let Some(data) = data {
+                        control_channel::forward(data);
+                    }
+                });
+                None
+            });
+        }
+
         // Wait for offers and send back answers
         while let Some(msg) = ws_receiver.next().await {
             let msg = msg?;
@@ -164,6 +263,8 @@ impl WebRTCClient {
                         self.handle_offer(offer, &config, &ws_sender_arc).await?;
                     } else if let Some(ice) = value.get("iceCandidate") {
                         self.handle_ice_candidate(ice)?;
+                    } else if let Some(camera) = value.get("source").and_then(serde_json::Value::as_str) {
+                        self.handle_source(camera, &ws_sender_arc).await?;
                     }
                 }
             }
@@ -187,10 +288,29 @@ impl WebRTCClient {
         let sdp = offer.get("sdp").and_then(serde_json::Value::as_str).unwrap_or("");
         log::debug!("Processing SDP offer for WebRTC client");
         
-        // Extract payload type based on codec
-        let payload_type = match config.video.codec.as_str() {
-            "vp8" => extract_vp8_payload_type(sdp).unwrap_or(96),
-            "h264" => extract_h264_payload_type(sdp).unwrap_or(96),
+        // Extract payload type based on codec. For H.264 the profile and
+        // packetization mode come from the offer too, so clients that refuse
+        // a hard-coded 42e01f (Safari) get the variant they asked for.
+        let (payload_type, pay_caps) = match config.video.codec.as_str() {
+            "vp8" => {
+                let payload_type = extract_vp8_payload_type(sdp).unwrap_or(96);
+                (payload_type, create_rtp_caps("vp8", payload_type)?)
+            }
+            "h264" => {
+                let (width, height, fps) = self.video_format()?;
+                let params = match negotiate_h264(sdp, width, height, fps) {
+                    Ok(params) => params,
+                    Err(e) => {
+                        warn!("Rejecting WebRTC offer: {}", e);
+                        let msg = serde_json::json!({ "error": e.to_string() });
+                        ws_tx.lock().await.send(Message::Text(msg.to_string().into())).await?;
+                        return Ok(());
+                    }
+                };
+                info!("Using H.264 profile-level-id={} packetization-mode={} from browser offer",
+                      params.profile_level_id, params.packetization_mode);
+                (params.payload_type, create_h264_rtp_caps(&params))
+            }
             codec => {
                 log::error!("Unsupported codec: {}", codec);
                 return Err(anyhow::anyhow!("Unsupported codec: {}", codec));
@@ -212,7 +332,6 @@ impl WebRTCClient {
         let pay_capsfilter = gst::ElementFactory::make("capsfilter")
             .name(&format!("pay_caps_{}", client_id))
             .build()?;
-        let pay_caps = create_rtp_caps(&config.video.codec, payload_type)?;
         pay_capsfilter.set_property("caps", &pay_caps);
         
         // Store elements for cleanup
@@ -224,7 +343,7 @@ impl WebRTCClient {
         
         // Add to pipeline and link
         self.pipeline.add_many(&[&pay, &pay_capsfilter])?;
-        gst::Element::link_many(&[&self.queue, &pay, &pay_capsfilter])?;
+        gst::Element::link_many(&[&self.selector, &pay, &pay_capsfilter])?;
         
         // Link to webrtcbin
         let sink_pad = self.webrtcbin.request_pad_simple("sink_%u")
@@ -232,6 +351,14 @@ impl WebRTCClient {
         let src_pad = pay_capsfilter.static_pad("src")
             .ok_or_else(|| anyhow::anyhow!("Failed to get src pad from capsfilter"))?;
         src_pad.link(&sink_pad)?;
+
+        // Ask webrtcbin to answer with nack feedback and the matching rtx
+        // payload type for every codec the browser offered rtx for
+        if config.webrtc.retransmission {
+            let transceiver = sink_pad.property::<gst_webrtc::WebRTCRTPTransceiver>("transceiver");
+            transceiver.set_property("do-nack", &true);
+            log::debug!("NACK/RTX enabled on WebRTC transceiver");
+        }
         
         // Store sink pad for cleanup
         {
@@ -256,6 +383,41 @@ impl WebRTCClient {
         Ok(())
     }
 
+    /// Width, height and frame rate the camera pipeline is producing
+    fn video_format(&self) -> Result<(u32, u32, u32)> {
+        let caps = self.pipeline.by_name("cfilter")
+            .map(|f| f.property::<gst::Caps>("caps"))
+            .ok_or_else(|| anyhow::anyhow!("Camera pipeline has no capsfilter"))?;
+        let s = caps.structure(0)
+            .ok_or_else(|| anyhow::anyhow!("Camera capsfilter has empty caps"))?;
+        let width = s.get::<i32>("width")?;
+        let height = s.get::<i32>("height")?;
+        let fps = s.get::<gst::Fraction>("framerate")?;
+        let fps = (fps.numer() as f64 / fps.denom().max(1) as f64).ceil();
+        Ok((width as u32, height as u32, fps as u32))
+    }
+
+    /// `{"source": "camera2"}`: show another camera's video without
+    /// renegotiating, from its next keyframe on. Answered with the same
+    /// message once switched, or with `{"error": ...}`.
+    async fn handle_source(
+        &self,
+        camera: &str,
+        ws_tx: &Arc<tokio::sync::Mutex<futures_util::stream::SplitSink<tokio_tungstenite::WebSocketStream<TcpStream>, Message>>>,
+    ) -> Result<()> {
+        let session_id = self.session_id;
+        let target = camera.to_string();
+        let msg = match tokio::task::spawn_blocking(move || switch::set_source(session_id, &target)).await? {
+            Ok(()) => serde_json::json!({ "source": camera }),
+            Err(e) => {
+                warn!("Cannot switch session {} to {}: {}", session_id, camera, e);
+                serde_json::json!({ "error": e.to_string() })
+            }
+        };
+        ws_tx.lock().await.send(Message::Text(msg.to_string().into())).await?;
+        Ok(())
+    }
+
     fn handle_ice_candidate(&self, ice: &serde_json::Value) -> Result<()> {
         let cand = ice.get("candidate").and_then(serde_json::Value::as_str).unwrap_or("").to_string();
         let mline = ice.get("sdpMLineIndex").and_then(serde_json::Value::as_u64).unwrap_or(0) as u32;
@@ -327,6 +489,17 @@ impl WebRTCClient {
         answer_desc: gst_webrtc::WebRTCSessionDescription,
         ws_tx: &Arc<tokio::sync::Mutex<futures_util::stream::SplitSink<tokio_tungstenite::WebSocketStream<TcpStream>, Message>>>,
     ) -> Result<()> {
+        // Apply [sdp-munging] rules before the answer becomes the local
+        // description, so webrtcbin and the client agree on it
+        let answer_desc = match sdp_munge::munge_answer(&answer_desc.sdp().as_text()?, &self.sdp_munging) {
+            Some(munged) => {
+                log::debug!("Munged SDP answer:\n{}", munged);
+                let sdp_msg = gst_sdp::SDPMessage::parse_buffer(munged.as_bytes())?;
+                gst_webrtc::WebRTCSessionDescription::new(gst_webrtc::WebRTCSDPType::Answer, sdp_msg)
+            }
+            None => answer_desc,
+        };
+
         // Set local description
         let (local_tx, local_rx) = mpsc::channel();
         let local_promise = gst::Promise::with_change_func(move |reply| {
@@ -359,11 +532,14 @@ impl WebRTCClient {
     /// Properly cleanup WebRTC resources to prevent memory leaks
     pub fn cleanup(&mut self) {
         info!("Cleaning up WebRTC client resources");
+        pause::unregister_session(self.session_id);
+        switch::unregister(self.session_id);
         
         // SIMPLIFIED CLEANUP: Focus on essential resource release only
         
         // 1. Stop data flow by setting elements to READY state first
         let _ = self.webrtcbin.set_state(gst::State::Ready);
+        let _ = self.selector.set_state(gst::State::Ready);
         let _ = self.queue.set_state(gst::State::Ready);
         
         // 2. Clean up payloader elements (but don't try to unlink during cleanup)
@@ -401,10 +577,11 @@ impl WebRTCClient {
         
         // 6. Set to NULL state for final cleanup
         let _ = self.webrtcbin.set_state(gst::State::Null);
+        let _ = self.selector.set_state(gst::State::Null);
         let _ = self.queue.set_state(gst::State::Null);
         
         // 7. Remove elements from pipeline (this handles the complex unlinking)
-        let _ = self.pipeline.remove_many(&[&self.queue, &self.webrtcbin]);
+        let _ = self.pipeline.remove_many(&[&self.queue, &self.selector, &self.webrtcbin]);
         
         info!("WebRTC client cleanup completed");
     }
@@ -416,9 +593,12 @@ impl WebRTCClient {
 impl Drop for WebRTCClient {
     fn drop(&mut self) {
         log::debug!("WebRTCClient Drop called - performing emergency cleanup");
+        pause::unregister_session(self.session_id);
+        switch::unregister(self.session_id);
         
         // Simple emergency cleanup - don't try complex operations during Drop
         let _ = self.webrtcbin.set_state(gst::State::Null);
+        let _ = self.selector.set_state(gst::State::Null);
         let _ = self.queue.set_state(gst::State::Null);
         
         // Release WebRTC sink pad if still held
@@ -434,7 +614,24 @@ impl Drop for WebRTCClient {
         }
         
         // Remove elements from pipeline (simple removal)
-        let _ = self.pipeline.remove_many(&[&self.queue, &self.webrtcbin]);
+        let _ = self.pipeline.remove_many(&[&self.queue, &self.selector, &self.webrtcbin]);
+    }
+}
+
+/// Asks the camera's encoder for a keyframe with fresh SPS/PPS. The
+/// request goes to the encoder itself, since viewers hang off a tee that
+/// doesn't lead back to it.
+pub(crate) fn request_keyframe(pipeline: &gst::Pipeline) {
+    match pipeline.by_name("encoder") {
+        Some(encoder) => {
+            encoder.send_event(
+                gst_video::UpstreamForceKeyUnitEvent::builder()
+                    .all_headers(true)
+                    .build(),
+            );
+            debug!("Keyframe requested from the camera encoder");
+        }
+        None => warn!("No encoder in the camera pipeline, can't request a keyframe"),
     }
 }
 
@@ -24,24 +24,166 @@ pub fn extract_vp8_payload_type(sdp: &str) -> Option<u32> {
     None
 }
 
-pub fn extract_h264_payload_type(sdp: &str) -> Option<u32> {
+// Our x264enc output is always Constrained Baseline (no CABAC, no B-frames,
+// no 8x8 DCT), which Baseline, Main and High decoders can all consume.
+const H264_DECODABLE_PROFILES: [u8; 3] = [0x42, 0x4d, 0x64];
+
+// (level_idc, max macroblocks per second, max frame size in macroblocks)
+const H264_LEVELS: [(u8, u32, u32); 16] = [
+    (10, 1485, 99),
+    (11, 3000, 396),
+    (12, 6000, 396),
+    (13, 11880, 396),
+    (20, 11880, 396),
+    (21, 19800, 792),
+    (22, 20250, 1620),
+    (30, 40500, 1620),
+    (31, 108000, 3600),
+    (32, 216000, 5120),
+    (40, 245760, 8192),
+    (41, 245760, 8192),
+    (42, 522240, 8704),
+    (50, 589824, 22080),
+    (51, 983040, 36864),
+    (52, 2073600, 36864),
+];
+
+/// H.264 payload chosen from the browser offer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct H264Params {
+    pub payload_type: u32,
+    /// Six hex digits as offered, echoed back so the answer matches exactly
+    pub profile_level_id: String,
+    pub packetization_mode: u8,
+}
+
+impl H264Params {
+    fn profile_idc(&self) -> u8 {
+        u8::from_str_radix(&self.profile_level_id[0..2], 16).unwrap_or(0)
+    }
+
+    fn constrained_baseline(&self) -> bool {
+        let iop = u8::from_str_radix(&self.profile_level_id[2..4], 16).unwrap_or(0);
+        self.profile_idc() == 0x42 && iop & 0x40 != 0
+    }
+
+    fn level_idc(&self) -> u8 {
+        u8::from_str_radix(&self.profile_level_id[4..6], 16).unwrap_or(0)
+    }
+
+    // Lower is better: exact Constrained Baseline first, then the wider
+    // profiles in the order decoders are most likely to handle them
+    fn preference(&self) -> usize {
+        if self.constrained_baseline() {
+            return 0;
+        }
+        1 + H264_DECODABLE_PROFILES
+            .iter()
+            .position(|&p| p == self.profile_idc())
+            .unwrap_or(H264_DECODABLE_PROFILES.len())
+    }
+}
+
+/// Smallest H.264 level_idc able to carry the given resolution and frame rate
+pub fn required_h264_level(width: u32, height: u32, fps: u32) -> Option<u8> {
+    let frame_mbs = width.div_ceil(16) * height.div_ceil(16);
+    let mbs_per_sec = frame_mbs * fps;
+    H264_LEVELS
+        .iter()
+        .find(|(_, max_mbps, max_fs)| frame_mbs <= *max_fs && mbs_per_sec <= *max_mbps)
+        .map(|(level, _, _)| *level)
+}
+
+/// Lists every H.264 payload in an SDP offer with its fmtp parameters.
+/// Payloads without a profile-level-id default to Baseline 1.0 (RFC 6184).
+pub fn parse_h264_offer(sdp: &str) -> Vec<H264Params> {
+    let mut payloads: Vec<H264Params> = sdp
+        .lines()
+        .filter_map(|line| line.strip_prefix("a=rtpmap:"))
+        .filter_map(|rest| {
+            let (pt, encoding) = rest.split_once(' ')?;
+            if !encoding.to_ascii_uppercase().starts_with("H264/") {
+                return None;
+            }
+            Some(H264Params {
+                payload_type: pt.trim().parse().ok()?,
+                profile_level_id: "42000a".to_owned(),
+                packetization_mode: 0,
+            })
+        })
+        .collect();
+
     for line in sdp.lines() {
-        if line.starts_with("a=rtpmap:") {
-            // Example: "a=rtpmap:103 H264/90000"
-            if line.contains("H264/90000") {
-                if let Some(payload_str) = line.strip_prefix("a=rtpmap:") {
-                    if let Some(space_pos) = payload_str.find(' ') {
-                        if let Ok(payload) = payload_str[..space_pos].parse::<u32>() {
-                            log::debug!("Found H.264 payload type {} in SDP", payload);
-                            return Some(payload);
-                        }
-                    }
+        let Some(rest) = line.strip_prefix("a=fmtp:") else { continue };
+        let Some((pt, params)) = rest.split_once(' ') else { continue };
+        let Some(payload) = payloads.iter_mut().find(|p| pt.trim().parse() == Ok(p.payload_type)) else {
+            continue;
+        };
+        for param in params.trim().split(';') {
+            match param.trim().split_once('=') {
+                Some(("profile-level-id", id))
+                    if id.len() == 6 && id.chars().all(|c| c.is_ascii_hexdigit()) =>
+                {
+                    payload.profile_level_id = id.to_ascii_lowercase();
                 }
+                Some(("packetization-mode", mode)) => {
+                    payload.packetization_mode = mode.trim().parse().unwrap_or(0);
+                }
+                _ => {}
             }
         }
     }
-    log::warn!("No H.264 payload type found in SDP, using default 96");
-    None
+
+    payloads
+}
+
+/// Picks the offered H.264 payload that can decode our stream, or explains
+/// why none of them can.
+pub fn negotiate_h264(sdp: &str, width: u32, height: u32, fps: u32) -> Result<H264Params> {
+    let offered = parse_h264_offer(sdp);
+    if offered.is_empty() {
+        return Err(anyhow::anyhow!("Client offer contains no H.264 payload"));
+    }
+
+    // rtph264pay fragments with FU-A, which only exists in packetization-mode=1
+    let mode1: Vec<&H264Params> = offered.iter().filter(|p| p.packetization_mode == 1).collect();
+    if mode1.is_empty() {
+        return Err(anyhow::anyhow!(
+            "Client only offers H.264 packetization-mode=0, but the stream requires packetization-mode=1"
+        ));
+    }
+
+    let decodable: Vec<&H264Params> = mode1
+        .into_iter()
+        .filter(|p| H264_DECODABLE_PROFILES.contains(&p.profile_idc()))
+        .collect();
+    if decodable.is_empty() {
+        let ids: Vec<&str> = offered.iter().map(|p| p.profile_level_id.as_str()).collect();
+        return Err(anyhow::anyhow!(
+            "No offered H.264 profile can decode Constrained Baseline (offered profile-level-id: {})",
+            ids.join(", ")
+        ));
+    }
+
+    // Browsers routinely advertise 3.1 yet decode more, so a level below what
+    // the stream needs is only worth a warning; prefer one that fits if offered
+    let required = required_h264_level(width, height, fps).unwrap_or(u8::MAX);
+    let chosen = decodable
+        .iter()
+        .min_by_key(|p| (p.level_idc() < required, p.preference()))
+        .expect("decodable is non-empty");
+    if chosen.level_idc() < required {
+        log::warn!(
+            "{}x{}@{}fps needs H.264 level {}.{}, but the client offers at most {}.{}; the stream may not decode",
+            width, height, fps, required / 10, required % 10, chosen.level_idc() / 10, chosen.level_idc() % 10
+        );
+    }
+
+    log::debug!(
+        "Negotiated H.264 payload {}: profile-level-id={}, packetization-mode={}",
+        chosen.payload_type, chosen.profile_level_id, chosen.packetization_mode
+    );
+    Ok((*chosen).clone())
 }
 
 pub fn create_rtp_payloader(codec: &str, payload_type: u32, webrtc_cfg: &WebRtcConfig) -> Result<gst::Element> {
@@ -88,6 +230,19 @@ fn create_h264_payloader(payload_type: u32, webrtc_cfg: &WebRtcConfig) -> Result
     Ok(pay)
 }
 
+/// RTP caps for a negotiated H.264 payload, carrying the client's own
+/// profile-level-id so webrtcbin answers with exactly what was offered
+pub fn create_h264_rtp_caps(params: &H264Params) -> gst::Caps {
+    gst::Caps::builder("application/x-rtp")
+        .field("media", "video")
+        .field("encoding-name", "H264")
+        .field("payload", params.payload_type as i32)
+        .field("clock-rate", 90000i32)
+        .field("packetization-mode", params.packetization_mode.to_string())
+        .field("profile-level-id", params.profile_level_id.as_str())
+        .build()
+}
+
 pub fn create_rtp_caps(codec: &str, payload_type: u32) -> Result<gst::Caps> {
     let caps = match codec {
         "vp8" => {
@@ -0,0 +1,67 @@
+//! Latency profiles: how much each stage may buffer
+//!
+//! Queue bounds, the webrtcbin jitter/latency budget and encoder lookahead
+//! all trade delay against resilience. They are set together from the
+//! camera's `latency-profile` so one stage doesn't undo what another saves.
+
+use gstreamer as gst;
+
+use crate::config::LatencyProfile;
+
+/// Bounds for a leaky queue; whichever is hit first drops the oldest buffer
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QueueLimits {
+    pub buffers: u32,
+    pub time: gst::ClockTime,
+}
+
+impl QueueLimits {
+    const fn new(buffers: u32, time_ms: u64) -> Self {
+        Self {
+            buffers,
+            time: gst::ClockTime::from_mseconds(time_ms),
+        }
+    }
+}
+
+/// Every latency knob for one camera
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LatencyTuning {
+    /// Queue right behind the camera source
+    pub source_queue: QueueLimits,
+    /// Queues between the processing steps and in front of the encoder
+    pub processing_queue: QueueLimits,
+    /// Queue feeding each viewer's webrtcbin
+    pub client_queue: QueueLimits,
+    /// webrtcbin's `latency` (its rtpbin jitter buffer), in milliseconds
+    pub webrtcbin_latency_ms: u32,
+    /// Frames the encoder may look ahead for rate control (x264
+    /// `rc-lookahead`, vp8 `lag-in-frames`); 0 keeps it zero-latency
+    pub lookahead_frames: u32,
+}
+
+pub fn tuning(profile: LatencyProfile) -> LatencyTuning {
+    match profile {
+        LatencyProfile::UltraLow => LatencyTuning {
+            source_queue: QueueLimits::new(2, 50),
+            processing_queue: QueueLimits::new(3, 100),
+            client_queue: QueueLimits::new(5, 200),
+            webrtcbin_latency_ms: 50,
+            lookahead_frames: 0,
+        },
+        LatencyProfile::Balanced => LatencyTuning {
+            source_queue: QueueLimits::new(10, 200),
+            processing_queue: QueueLimits::new(20, 500),
+            client_queue: QueueLimits::new(20, 1000),
+            webrtcbin_latency_ms: 200,
+            lookahead_frames: 0,
+        },
+        LatencyProfile::Robust => LatencyTuning {
+            source_queue: QueueLimits::new(20, 500),
+            processing_queue: QueueLimits::new(40, 1000),
+            client_queue: QueueLimits::new(60, 2000),
+            webrtcbin_latency_ms: 500,
+            lookahead_frames: 10,
+        },
+    }
+}
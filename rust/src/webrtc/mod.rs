@@ -1,6 +1,9 @@
 pub mod pipeline;
 pub mod client;
 pub mod codec;
+pub mod latency;
+pub mod sdp_munge;
+pub mod switch;
 
 pub use pipeline::*;
 pub use client::*; 
\ No newline at end of file
@@ -5,7 +5,11 @@ use gstreamer::MessageView;
 use gstreamer::glib::ControlFlow;
 use log::info;
 
-use crate::config::{CameraConfig, Config, VideoConfig};
+use crate::config::{CameraConfig, Config, HorizonMode, LatencyProfile, RtpInputConfig, VideoConfig};
+use crate::gst_props;
+use crate::pip::Pip;
+use crate::webrtc::latency::{self, QueueLimits};
+use crate::watermark;
 
 pub struct CameraPipeline {
     pub pipeline: gst::Pipeline,
@@ -16,100 +20,95 @@ pub struct CameraPipeline {
     pub camera_source: gst::Element,
     // Store processing queues for explicit flushing
     pub processing_queues: Vec<gst::Element>,
+    // Viewers' queues and webrtcbins are tuned to the same profile
+    pub latency: LatencyProfile,
 }
 
 impl CameraPipeline {
-    pub fn new(cfg: Config, cam_cfg: CameraConfig) -> Result<Self> {
+    pub fn new(cfg: Config, cam_cfg: CameraConfig, stream: &str) -> Result<Self> {
         let pipeline = gst::Pipeline::new();
 
-        // Camera source with CRITICAL MEMORY LEAK PROTECTION
-        let camsrc = gst::ElementFactory::make("libcamerasrc").build()?;
-        camsrc.set_property("camera-name", &cam_cfg.device);
-        
-        // CRITICAL MEMORY FIX: Aggressively limit libcamera buffer management
-        // Force minimal buffer pool to prevent accumulation
-        if camsrc.has_property("num-buffers", Some(gst::glib::Type::I32)) {
-            camsrc.set_property("num-buffers", &3i32); // Only 3 buffers in pool
-        }
-        
-        // Set explicit buffer pool configuration
-        if camsrc.has_property("io-mode", Some(gst::glib::Type::STRING)) {
-            camsrc.set_property_from_str("io-mode", "mmap"); // Use memory mapping for efficiency
-        }
-        
-        // CRITICAL: Force buffer dropping when downstream is slow
-        if camsrc.has_property("drop-buffers", Some(gst::glib::Type::BOOL)) {
-            camsrc.set_property("drop-buffers", &true);
-        }
-        
-        // MEMORY LEAK FIX: Set libcamera to immediately drop old frames
-        if camsrc.has_property("max-buffers", Some(gst::glib::Type::U32)) {
-            camsrc.set_property("max-buffers", &3u32); // Maximum 3 buffers
-        }
-        
-        // Set auto exposure/white balance to fixed values to reduce processing overhead
-        if camsrc.has_property("auto-focus-mode", Some(gst::glib::Type::I32)) {
-            camsrc.set_property("auto-focus-mode", &0i32); // Manual focus
-        }
-        
-        // MEMORY OPTIMIZATION: Disable unnecessary camera features
-        if camsrc.has_property("controls", Some(gst::glib::Type::BOXED)) {
-            // Set fixed exposure and gain to reduce internal processing
-            let controls = gst::Structure::builder("controls")
-                .field("AnalogueGain", &2.0f64) // Fixed analog gain
-                .field("ExposureTime", &16000i32) // Fixed exposure time (16ms)
-                .field("AwbEnable", &false) // Disable auto white balance
-                .field("AeEnable", &false) // Disable auto exposure
-                .build();
-            camsrc.set_property("controls", &controls);
-        }
+        let camsrc = match cam_cfg.rtp_input {
+            Some(ref input) => create_rtp_jpeg_source(input, &cam_cfg)?,
+            None => create_libcamera_source(&cam_cfg)?,
+        };
 
-        // Caps filter to force specific format from camera
+        // Caps filter to force specific format from camera. Decoded JPEG
+        // keeps the sender's format; videoconvert takes care of it.
         let capsfilter = gst::ElementFactory::make("capsfilter").name("cfilter").build()?;
-        let caps = gst::Caps::builder("video/x-raw")
-            .field("format", "NV12")  // libcamerasrc native format
+        let mut caps = gst::Caps::builder("video/x-raw");
+        if cam_cfg.rtp_input.is_none() {
+            caps = caps.field("format", "NV12");  // libcamerasrc native format
+        }
+        let caps = caps
             .field("width", cam_cfg.target_width as i32)
             .field("height", cam_cfg.target_height as i32)
             .field("framerate", gst::Fraction::new(cam_cfg.fps as i32, 1))
             .build();
         capsfilter.set_property("caps", &caps);
 
-        // BALANCED MEMORY MANAGEMENT: Reasonable queue settings for good performance
+        // Queue bounds, jitter buffer and encoder lookahead for this camera
+        let tuning = latency::tuning(cam_cfg.latency_profile);
+
         let queue1 = gst::ElementFactory::make("queue").name("queue1").build()?;
-        queue1.set_property("max-size-buffers", &10u32); // Reasonable buffer count
-        queue1.set_property("max-size-time", &(gst::ClockTime::from_mseconds(200))); // 200ms reasonable
+        queue1.set_property("max-size-buffers", &tuning.source_queue.buffers);
+        queue1.set_property("max-size-time", &tuning.source_queue.time);
         queue1.set_property_from_str("leaky", "downstream"); // Drop old buffers when full
         
         // Video processing chain with AGGRESSIVE BUFFER MANAGEMENT
         let videoconvert = gst::ElementFactory::make("videoconvert").build()?;
         let videoscale = gst::ElementFactory::make("videoscale").build()?;
         let videoflip = create_video_flip(&cam_cfg)?;
+        let horizon = create_horizon(&cam_cfg)?;
+        // Drawn before the raw tee, so every output carries it
+        let watermark = if cam_cfg.watermark.enabled {
+            Some(watermark::create_element(&cam_cfg.watermark, stream)?)
+        } else {
+            None
+        };
+
+        // Stabilization window, moved against the camera's shake by the eis
+        // module; videoscale brings the cropped frame back to target size
+        #[cfg(feature = "eis")]
+        let eis_crop = if cam_cfg.stabilization.enabled {
+            Some(gst::ElementFactory::make("videocrop").name("eis_crop").build()?)
+        } else {
+            None
+        };
         
         // CRITICAL MEMORY FIX: Add ultra-aggressive queues between ALL processing elements
         let camera_id = cam_cfg.device.split('/').last().unwrap_or("unknown");
         
         // Queue after capsfilter
         let queue2 = gst::ElementFactory::make("queue").name(&format!("queue2_{}", camera_id)).build()?;
-        configure_ultra_aggressive_queue(&queue2)?;
+        configure_ultra_aggressive_queue(&queue2, tuning.processing_queue)?;
         
         // Queue after videoconvert
         let queue3 = gst::ElementFactory::make("queue").name(&format!("queue3_{}", camera_id)).build()?;
-        configure_ultra_aggressive_queue(&queue3)?;
+        configure_ultra_aggressive_queue(&queue3, tuning.processing_queue)?;
         
         // Queue after videoscale
         let queue4 = gst::ElementFactory::make("queue").name(&format!("queue4_{}", camera_id)).build()?;
-        configure_ultra_aggressive_queue(&queue4)?;
+        configure_ultra_aggressive_queue(&queue4, tuning.processing_queue)?;
+        
+        // Passthrough until a camera pause blacks it out (contrast and
+        // saturation 0 turn every pixel into video black)
+        let privacy_balance = gst::ElementFactory::make("videobalance").name("privacy_balance").build()?;
+
+        // Another camera's video inset into the frame, under the watermark
+        let pip = Pip::create(&cam_cfg, stream)?;
         
         // Store queues for explicit management
         let processing_queues = vec![queue1.clone(), queue2.clone(), queue3.clone(), queue4.clone()];
         
         // Video encoder with enhanced memory management
-        let encoder = create_video_encoder(&cfg.video, &cfg.webrtc)?;
+        let encoder = create_video_encoder(&cfg.video, &cfg.webrtc, tuning.lookahead_frames)?;
         
         // CRITICAL: Remove all other complex encoder settings that caused issues
 
         // Stream distribution with MEMORY MANAGEMENT
-        let tee = gst::ElementFactory::make("tee").name(&format!("tee_{}", camera_id)).build()?;
+        // Named so outputs fed from raw video (NDI) can find it
+        let tee = gst::ElementFactory::make("tee").name("raw_tee").build()?;
         // CRITICAL: Configure tee to immediately drop unlinked buffers
         tee.set_property("allow-not-linked", &true); // Don't block if some pads not linked
         tee.set_property("silent", &true); // Reduce logging overhead
@@ -129,9 +128,29 @@ impl CameraPipeline {
             &queue3,           // Buffer control after scale
             &videoflip,
             &queue4,           // Buffer control before tee
+            &privacy_balance,  // Blacks out frames while the camera is paused
             &tee,              // Tee BEFORE encoder for raw video splitting
         ];
         
+        #[cfg(feature = "eis")]
+        if let Some(ref eis_crop) = eis_crop {
+            let at = elements.iter().position(|e| *e == &videoscale).unwrap_or(elements.len());
+            elements.insert(at, eis_crop);
+        }
+        if let Some(ref horizon) = horizon {
+            let at = elements.iter().position(|e| *e == &videoflip).map_or(elements.len(), |i| i + 1);
+            elements.insert(at, horizon);
+        }
+        if let Some(ref pip) = pip {
+            let at = elements.iter().position(|e| *e == &privacy_balance).unwrap_or(elements.len());
+            elements.insert(at, &pip.caps);
+            elements.insert(at, &pip.compositor);
+        }
+        if let Some(ref watermark) = watermark {
+            let at = elements.iter().position(|e| *e == &privacy_balance).unwrap_or(elements.len());
+            elements.insert(at, watermark);
+        }
+
         // Note: encoder will be connected to tee via separate branch, not in main chain
         elements.push(&fakesink);
         
@@ -140,6 +159,9 @@ impl CameraPipeline {
         
         // Link main pipeline elements (up to tee)
         gst::Element::link_many(&elements[..elements.len()-1])?; // Link everything except fakesink
+        if let Some(ref pip) = pip {
+            pip.attach_inset(&pipeline, stream)?;
+        }
         
         // CRITICAL FIX: Force pipeline latency recalculation to fix RTP session warnings
         // This ensures proper timing distribution to all elements
@@ -159,7 +181,7 @@ impl CameraPipeline {
 
         // Create encoder branch from tee
         let encoder_queue = gst::ElementFactory::make("queue").name("encoder_queue").build()?;
-        configure_ultra_aggressive_queue(&encoder_queue)?;
+        configure_ultra_aggressive_queue(&encoder_queue, tuning.processing_queue)?;
         
         // CRITICAL FIX: Add caps filter to strip colorimetry by forcing specific format
         let input_capsfilter = gst::ElementFactory::make("capsfilter").name("input_capsfilter").build()?;
@@ -203,6 +225,30 @@ impl CameraPipeline {
         // Link encoder branch: queue -> capsfilter -> videoconvert -> vp8_caps_filter -> encoder
         gst::Element::link_many(&[&encoder_queue, &input_capsfilter, &encoder_videoconvert, &vp8_caps_filter, &encoder])?;
         
+        // Encoded output goes to its own tee so the recorder (and anything else
+        // wanting exactly what viewers get) can attach without re-encoding:
+        // encoder -> [h264parse] -> encoded_tee -> fakesink
+        let encoded_tee = gst::ElementFactory::make("tee").name("encoded_tee").build()?;
+        encoded_tee.set_property("allow-not-linked", &true);
+        encoded_tee.set_property("silent", &true);
+        let encoded_fakesink = gst::ElementFactory::make("fakesink").name("encoded_sink").build()?;
+        configure_ultra_aggressive_fakesink(&encoded_fakesink)?;
+        pipeline.add_many(&[&encoded_tee, &encoded_fakesink])?;
+        if cfg.video.codec == "h264" {
+            // Muxers need AVC with codec_data rather than x264's byte-stream
+            let parser = gst::ElementFactory::make("h264parse").name("encoded_parse").build()?;
+            parser.set_property("config-interval", &-1i32);
+            pipeline.add(&parser)?;
+            gst::Element::link_many(&[&encoder, &parser, &encoded_tee])?;
+        } else {
+            encoder.link(&encoded_tee)?;
+        }
+        let encoded_tee_pad = encoded_tee.request_pad_simple("src_%u")
+            .ok_or_else(|| anyhow::anyhow!("Failed to request src pad from encoded_tee"))?;
+        let encoded_sink_pad = encoded_fakesink.static_pad("sink")
+            .ok_or_else(|| anyhow::anyhow!("Failed to get sink pad from encoded fakesink"))?;
+        encoded_tee_pad.link(&encoded_sink_pad)?;
+
         // Connect dummy sink branch: tee -> fakesink to prevent not-linked errors
         let tee_src_pad = tee.request_pad_simple("src_%u")
             .ok_or_else(|| anyhow::anyhow!("Failed to request src pad from tee"))?;
@@ -217,9 +263,7 @@ impl CameraPipeline {
                      cam_cfg.device, cfg.video.codec);
 
         // Force immediate processing for live streams
-        if camsrc.has_property("is-live", Some(gst::glib::Type::BOOL)) {
-            camsrc.set_property("is-live", &true);
-        }
+        gst_props::set(&camsrc, "is-live", true);
 
         Ok(CameraPipeline { 
             pipeline, 
@@ -227,6 +271,7 @@ impl CameraPipeline {
             _bus_watch: bus_watch,
             camera_source: camsrc,
             processing_queues,
+            latency: cam_cfg.latency_profile,
         })
     }
     
@@ -245,16 +290,72 @@ impl CameraPipeline {
         let _ = self.pipeline.send_event(gst::event::FlushStop::builder(true).build());
         
         // Force buffer pool recreation on camera source
-        if self.camera_source.has_property("force-pool-recreation", Some(gst::glib::Type::BOOL)) {
-            self.camera_source.set_property("force-pool-recreation", &true);
-        }
+        gst_props::set(&self.camera_source, "force-pool-recreation", true);
         
         Ok(())
     }
 }
 
+fn create_libcamera_source(cam_cfg: &CameraConfig) -> Result<gst::Element> {
+    // Camera source with CRITICAL MEMORY LEAK PROTECTION
+    let camsrc = gst::ElementFactory::make("libcamerasrc").build()?;
+    camsrc.set_property("camera-name", &cam_cfg.device);
+
+    // CRITICAL MEMORY FIX: Aggressively limit libcamera buffer management
+    // Force minimal buffer pool to prevent accumulation
+    gst_props::set(&camsrc, "num-buffers", 3i32); // Only 3 buffers in pool
+
+    // Set explicit buffer pool configuration
+    gst_props::set(&camsrc, "io-mode", "mmap"); // Use memory mapping for efficiency
+
+    // CRITICAL: Force buffer dropping when downstream is slow
+    gst_props::set(&camsrc, "drop-buffers", true);
+
+    // MEMORY LEAK FIX: Set libcamera to immediately drop old frames
+    gst_props::set(&camsrc, "max-buffers", 3u32); // Maximum 3 buffers
+
+    // Set auto exposure/white balance to fixed values to reduce processing overhead
+    gst_props::set(&camsrc, "auto-focus-mode", 0i32); // Manual focus
+
+    // MEMORY OPTIMIZATION: Disable unnecessary camera features
+    // Set fixed exposure and gain to reduce internal processing
+    let controls = gst::Structure::builder("controls")
+        .field("AnalogueGain", &2.0f64) // Fixed analog gain
+        .field("ExposureTime", &16000i32) // Fixed exposure time (16ms)
+        .field("AwbEnable", &false) // Disable auto white balance
+        .field("AeEnable", &false) // Disable auto exposure
+        .build();
+    gst_props::set(&camsrc, "controls", controls);
+
+    Ok(camsrc)
+}
+
+/// udpsrc ! rtpjitterbuffer ! rtpjpegdepay ! jpegdec ! videoscale ! videorate,
+/// so cfilter can fix size and rate whatever the sender does
+fn create_rtp_jpeg_source(input: &RtpInputConfig, cam_cfg: &CameraConfig) -> Result<gst::Element> {
+    let mut caps =
+        "application/x-rtp,media=video,clock-rate=90000,encoding-name=JPEG,payload=26".to_string();
+    // RFC 2435 can't describe frames over 2040 pixels; senders leave the
+    // size at 0 and the depayloader takes it from here instead
+    if cam_cfg.width > 2040 || cam_cfg.height > 2040 {
+        let dimensions = format!("{},{}", cam_cfg.width, cam_cfg.height);
+        caps.push_str(&format!(",x-dimensions=(string)\\\"{}\\\"", dimensions));
+    }
+    let bin = gst::parse::bin_from_description(
+        &format!(
+            "udpsrc name=rtp_src address={} port={} caps=\"{}\" ! rtpjitterbuffer latency={} ! \
+             rtpjpegdepay ! jpegdec ! videoscale ! videorate",
+            input.address, input.port, caps, input.latency_ms
+        ),
+        true,
+    )?;
+    bin.set_property("name", "rtp_input_bin");
+    log::info!("Receiving MJPEG-RTP on {}:{}", input.address, input.port);
+    Ok(bin.upcast())
+}
+
 fn create_video_flip(cam_cfg: &CameraConfig) -> Result<gst::Element> {
-    let videoflip = gst::ElementFactory::make("videoflip").build()?;
+    let videoflip = gst::ElementFactory::make("videoflip").name("videoflip").build()?;
     
     // Set flip method from config or default to rotate-180
     let flip_method = cam_cfg.flip_method.as_deref().unwrap_or("rotate-180");
@@ -264,16 +365,39 @@ fn create_video_flip(cam_cfg: &CameraConfig) -> Result<gst::Element> {
     Ok(videoflip)
 }
 
-fn create_video_encoder(video_cfg: &VideoConfig, webrtc_cfg: &crate::config::WebRtcConfig) -> Result<gst::Element> {
+/// Element the horizon module levels the frame with, named "horizon"
+fn create_horizon(cam_cfg: &CameraConfig) -> Result<Option<gst::Element>> {
+    match cam_cfg.horizon.mode {
+        HorizonMode::Off => Ok(None),
+        HorizonMode::Rotate => {
+            // rotate only takes packed RGB and AYUV, hence the conversions
+            let bin = gst::parse::bin_from_description(
+                "videoconvert ! rotate name=horizon angle=0 ! videoconvert",
+                true,
+            )?;
+            bin.set_property("name", "horizon_bin");
+            Ok(Some(bin.upcast()))
+        }
+        HorizonMode::Overlay => {
+            let overlay = gst::ElementFactory::make("textoverlay").name("horizon").build()?;
+            overlay.set_property_from_str("valignment", "top");
+            overlay.set_property_from_str("halignment", "center");
+            overlay.set_property("font-desc", "Sans 12");
+            Ok(Some(overlay))
+        }
+    }
+}
+
+fn create_video_encoder(video_cfg: &VideoConfig, webrtc_cfg: &crate::config::WebRtcConfig, lookahead: u32) -> Result<gst::Element> {
     match video_cfg.codec.as_str() {
-        "vp8" => create_vp8_encoder(video_cfg, webrtc_cfg),
-        "h264" => create_h264_encoder(video_cfg, webrtc_cfg),
+        "vp8" => create_vp8_encoder(video_cfg, webrtc_cfg, lookahead),
+        "h264" => create_h264_encoder(video_cfg, webrtc_cfg, lookahead),
         codec => Err(anyhow::anyhow!("Unsupported video codec: {}", codec)),
     }
 }
 
-fn create_vp8_encoder(video_cfg: &VideoConfig, webrtc_cfg: &crate::config::WebRtcConfig) -> Result<gst::Element> {
-    let encoder = gst::ElementFactory::make("vp8enc").build()?;
+fn create_vp8_encoder(video_cfg: &VideoConfig, webrtc_cfg: &crate::config::WebRtcConfig, lookahead: u32) -> Result<gst::Element> {
+    let encoder = gst::ElementFactory::make("vp8enc").name("encoder").build()?;
     
     // Map encoder preset to VP8 deadline/cpu-used settings for optimal performance
     let encoder_preset = video_cfg.encoder_preset.as_str();
@@ -294,7 +418,7 @@ fn create_vp8_encoder(video_cfg: &VideoConfig, webrtc_cfg: &crate::config::WebRt
     
     // Essential settings only - avoid problematic properties
     encoder.set_property("threads", &1i32); // Single thread to reduce memory usage
-    encoder.set_property("lag-in-frames", &0i32); // No lag for realtime encoding
+    encoder.set_property("lag-in-frames", &(lookahead as i32)); // 0 = no lag for realtime encoding
     
     log::info!("VP8 encoder configured: preset={}, bitrate={} bps, keyframe-max-dist=30, SIMPLIFIED", 
                encoder_preset, target_bitrate);
@@ -302,12 +426,14 @@ fn create_vp8_encoder(video_cfg: &VideoConfig, webrtc_cfg: &crate::config::WebRt
     Ok(encoder)
 }
 
-fn create_h264_encoder(video_cfg: &VideoConfig, webrtc_cfg: &crate::config::WebRtcConfig) -> Result<gst::Element> {
-    let encoder = gst::ElementFactory::make("x264enc").build()?;
+fn create_h264_encoder(video_cfg: &VideoConfig, webrtc_cfg: &crate::config::WebRtcConfig, lookahead: u32) -> Result<gst::Element> {
+    let encoder = gst::ElementFactory::make("x264enc").name("encoder").build()?;
     
     // Configure x264 encoder for WebRTC compatibility and low latency
     encoder.set_property_from_str("speed-preset", "ultrafast"); // Fastest encoding
-    encoder.set_property_from_str("tune", "zerolatency"); // Zero latency tuning
+    if lookahead == 0 {
+        encoder.set_property_from_str("tune", "zerolatency"); // Zero latency tuning
+    }
     
     // Configure for Constrained Baseline Profile (required for WebRTC)
     // According to GStreamer docs: "If dct8x8 is enabled, then High profile is used. 
@@ -337,7 +463,7 @@ fn create_h264_encoder(video_cfg: &VideoConfig, webrtc_cfg: &crate::config::WebR
     
     // Additional low-latency settings
     encoder.set_property("ref", &1u32); // Single reference frame for lower latency
-    encoder.set_property("rc-lookahead", &0i32); // Disable lookahead for lower latency
+    encoder.set_property("rc-lookahead", &(lookahead as i32)); // 0 disables lookahead for lower latency
     encoder.set_property("sliced-threads", &false); // Disable sliced threads for lower latency
     encoder.set_property("sync-lookahead", &0i32); // Disable sync lookahead for lower latency
     
@@ -379,11 +505,11 @@ fn setup_bus_monitoring(pipeline: &gst::Pipeline) -> Result<gst::bus::BusWatchGu
 }
 
 // BALANCED MEMORY MANAGEMENT: Configure reasonable queue behavior
-fn configure_ultra_aggressive_queue(queue: &gst::Element) -> Result<()> {
-    // Use more balanced queue settings instead of ultra-aggressive ones
-    queue.set_property("max-size-buffers", &20u32); // Reasonable buffer count
+fn configure_ultra_aggressive_queue(queue: &gst::Element, limits: QueueLimits) -> Result<()> {
+    // Bounds come from the camera's latency profile
+    queue.set_property("max-size-buffers", &limits.buffers);
     queue.set_property("max-size-bytes", &(2048 * 1024u32)); // 2MB reasonable size  
-    queue.set_property("max-size-time", &(gst::ClockTime::from_mseconds(500))); // 500ms reasonable
+    queue.set_property("max-size-time", &limits.time);
     queue.set_property_from_str("leaky", "downstream"); // Drop old buffers when full
     queue.set_property("silent", &true); // Reduce logging overhead
     queue.set_property("flush-on-eos", &true); // Flush buffers on EOS
@@ -400,22 +526,16 @@ fn configure_ultra_aggressive_fakesink(fakesink: &gst::Element) -> Result<()> {
     fakesink.set_property("signal-handoffs", &false); // Don't emit signals
     
     // CRITICAL: Enable immediate buffer dropping
-    if fakesink.has_property("drop", Some(gst::glib::Type::BOOL)) {
-        fakesink.set_property("drop", &true); // Drop all buffers immediately
-    }
-    if fakesink.has_property("can-activate-pull", Some(gst::glib::Type::BOOL)) {
-        fakesink.set_property("can-activate-pull", &false); // Disable pull mode
-    }
-    if fakesink.has_property("dump", Some(gst::glib::Type::BOOL)) {
-        fakesink.set_property("dump", &false); // Don't dump buffer contents
-    }
+    gst_props::set(fakesink, "drop", true); // Drop all buffers immediately
+    gst_props::set(fakesink, "can-activate-pull", false); // Disable pull mode
+    gst_props::set(fakesink, "dump", false); // Don't dump buffer contents
     
     Ok(())
 }
 
 // Rename the old function to avoid conflicts
-fn configure_processing_queue(queue: &gst::Element) -> Result<()> {
-    configure_ultra_aggressive_queue(queue)
+fn configure_processing_queue(queue: &gst::Element, limits: QueueLimits) -> Result<()> {
+    configure_ultra_aggressive_queue(queue, limits)
 }
 
  
\ No newline at end of file
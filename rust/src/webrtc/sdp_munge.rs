@@ -0,0 +1,139 @@
+// Rewrites of the generated SDP answer for clients that reject parts of
+// GStreamer's default output. Operates on the SDP text line by line.
+
+use crate::config::SdpMungingConfig;
+
+/// Applies the configured rules; returns `None` if nothing changed.
+pub fn munge_answer(sdp: &str, rules: &SdpMungingConfig) -> Option<String> {
+    if rules.is_empty() {
+        return None;
+    }
+
+    let mut lines: Vec<String> = sdp.lines().map(str::to_string).collect();
+
+    if !rules.remove_lines.is_empty() {
+        lines.retain(|line| !rules.remove_lines.iter().any(|prefix| line.starts_with(prefix.as_str())));
+    }
+    if rules.strip_rtx {
+        strip_rtx(&mut lines);
+    }
+    if let Some(ref id) = rules.profile_level_id {
+        force_profile_level_id(&mut lines, id);
+    }
+    if !rules.codec_order.is_empty() {
+        reorder_codecs(&mut lines, &rules.codec_order);
+    }
+
+    let mut munged = lines.join("\r\n");
+    munged.push_str("\r\n");
+    if munged == sdp {
+        None
+    } else {
+        Some(munged)
+    }
+}
+
+/// Payload type -> encoding name from `a=rtpmap:<pt> <name>/<rate>` lines.
+fn rtpmap(lines: &[String]) -> Vec<(String, String)> {
+    lines
+        .iter()
+        .filter_map(|line| {
+            let (pt, rest) = line.strip_prefix("a=rtpmap:")?.split_once(' ')?;
+            let name = rest.split('/').next()?;
+            Some((pt.to_string(), name.to_string()))
+        })
+        .collect()
+}
+
+/// Payload type an attribute line refers to (`a=fmtp:97 ...`, `a=rtcp-fb:97 ...`).
+fn attribute_pt(line: &str) -> Option<&str> {
+    let rest = line.strip_prefix("a=")?;
+    let (attr, value) = rest.split_once(':')?;
+    match attr {
+        "rtpmap" | "fmtp" | "rtcp-fb" => value.split_whitespace().next(),
+        _ => None,
+    }
+}
+
+/// Removes rtx payload types from m-lines along with their attributes.
+fn strip_rtx(lines: &mut Vec<String>) {
+    let rtx: Vec<String> = rtpmap(lines)
+        .into_iter()
+        .filter(|(_, name)| name.eq_ignore_ascii_case("rtx"))
+        .map(|(pt, _)| pt)
+        .collect();
+    if rtx.is_empty() {
+        return;
+    }
+
+    lines.retain(|line| attribute_pt(line).map_or(true, |pt| !rtx.iter().any(|r| r == pt)));
+    for line in lines.iter_mut() {
+        if let Some(formats) = m_line_formats(line) {
+            let (head, pts) = formats;
+            let kept: Vec<&str> = pts.into_iter().filter(|pt| !rtx.iter().any(|r| r == pt)).collect();
+            *line = format!("{} {}", head, kept.join(" "));
+        }
+    }
+}
+
+/// Replaces `profile-level-id` in every H.264 fmtp line.
+fn force_profile_level_id(lines: &mut [String], id: &str) {
+    let h264: Vec<String> = rtpmap(lines)
+        .into_iter()
+        .filter(|(_, name)| name.eq_ignore_ascii_case("H264"))
+        .map(|(pt, _)| pt)
+        .collect();
+
+    for line in lines.iter_mut() {
+        let is_h264_fmtp = line.starts_with("a=fmtp:")
+            && attribute_pt(line).map_or(false, |pt| h264.iter().any(|h| h == pt));
+        if !is_h264_fmtp {
+            continue;
+        }
+        let Some((head, params)) = line.split_once(' ') else {
+            continue;
+        };
+        let mut params: Vec<String> = params
+            .split(';')
+            .map(str::trim)
+            .filter(|p| !p.is_empty() && !p.starts_with("profile-level-id="))
+            .map(str::to_string)
+            .collect();
+        params.push(format!("profile-level-id={}", id));
+        *line = format!("{} {}", head, params.join(";"));
+    }
+}
+
+/// Orders each m-line's payload types by encoding name preference; codecs
+/// not listed keep their relative order after the listed ones.
+fn reorder_codecs(lines: &mut [String], order: &[String]) {
+    let names = rtpmap(lines);
+    let rank = |pt: &str| {
+        names
+            .iter()
+            .find(|(p, _)| p == pt)
+            .and_then(|(_, name)| order.iter().position(|o| o.eq_ignore_ascii_case(name)))
+            .unwrap_or(order.len())
+    };
+
+    for line in lines.iter_mut() {
+        if let Some((head, mut pts)) = m_line_formats(line) {
+            pts.sort_by_key(|pt| rank(pt)); // stable: unlisted keep their order
+            *line = format!("{} {}", head, pts.join(" "));
+        }
+    }
+}
+
+/// Splits `m=video 9 UDP/TLS/RTP/SAVPF 96 97` into the first three fields
+/// and the format list. Non-RTP m-lines (e.g. data channels) are skipped.
+fn m_line_formats(line: &str) -> Option<(String, Vec<&str>)> {
+    if !line.starts_with("m=") {
+        return None;
+    }
+    let mut fields = line.split(' ');
+    let head: Vec<&str> = fields.by_ref().take(3).collect();
+    if head.len() < 3 || !head[2].contains("RTP") {
+        return None;
+    }
+    Some((head.join(" "), fields.collect()))
+}
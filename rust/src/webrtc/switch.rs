@@ -0,0 +1,276 @@
+//! Switching a WebRTC session between cameras
+//!
+//! Each session's branch ends in an input-selector fed by its own camera's
+//! tee. Switching it to another camera hangs a `queue ! appsink` branch off
+//! that camera's tee (see `tee_output`) and pushes its buffers, retimed to
+//! this pipeline's clock, into an appsrc on a second selector input. The
+//! selector changes over on the new input's first keyframe, which is asked
+//! for right away, so the viewer's decoder never gets a frame it has no
+//! reference for. The payloader behind the selector keeps its SSRC,
+//! sequence numbers and timestamps, so the browser sees one continuous
+//! track and nothing is renegotiated. All cameras use the configured codec;
+//! a different resolution arrives in-band with the keyframe.
+
+use anyhow::{anyhow, bail, Result};
+use gstreamer as gst;
+use gstreamer::prelude::*;
+use gstreamer_app as gst_app;
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::Duration;
+
+use crate::debug;
+use crate::tee_output::{self, Branch, Tee};
+use crate::webrtc::client::request_keyframe;
+
+/// How long a switch waits for the new camera's keyframe
+const SWITCH_TIMEOUT: Duration = Duration::from_secs(3);
+
+// Switchable sessions by id, as in the pause module's session registry
+static SWITCHES: Lazy<Mutex<HashMap<u64, Arc<SourceSwitch>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Input to make active at its next keyframe
+struct Pending {
+    input: gst::Pad,
+    done: mpsc::Sender<()>,
+}
+
+/// The inputs of one session's selector
+pub struct SourceSwitch {
+    /// The session's own camera
+    camera: String,
+    pipeline: gst::Pipeline,
+    tee: Tee,
+    selector: gst::Element,
+    own_input: gst::Pad,
+    /// Camera the session is showing
+    shown: Mutex<String>,
+    /// Another camera's video, while the session shows it. Held for the
+    /// whole of a switch.
+    feed: Mutex<Option<Feed>>,
+    pending: Arc<Mutex<Option<Pending>>>,
+}
+
+impl SourceSwitch {
+    /// Takes over `selector`, whose first input is fed from `tee` of
+    /// `camera`'s pipeline
+    pub fn new(
+        pipeline: &gst::Pipeline,
+        camera: &str,
+        tee: &gst::Element,
+        selector: &gst::Element,
+        own_input: &gst::Pad,
+    ) -> Self {
+        let tee = if tee.name() == Tee::Encoded.name() {
+            Tee::Encoded
+        } else {
+            Tee::Raw
+        };
+        let pending = Arc::new(Mutex::new(None));
+        switch_on_keyframe(own_input, &pending);
+        Self {
+            camera: camera.to_string(),
+            pipeline: pipeline.clone(),
+            tee,
+            selector: selector.clone(),
+            own_input: own_input.clone(),
+            shown: Mutex::new(camera.to_string()),
+            feed: Mutex::new(None),
+            pending,
+        }
+    }
+
+    /// Camera the session is showing
+    pub fn source(&self) -> String {
+        self.shown.lock().unwrap().clone()
+    }
+
+    /// Shows `camera` from its next keyframe on. Blocks until then, for up
+    /// to SWITCH_TIMEOUT.
+    pub fn switch_to(&self, camera: &str) -> Result<()> {
+        let mut feed = self.feed.lock().unwrap();
+        if *self.shown.lock().unwrap() == camera {
+            return Ok(());
+        }
+
+        let new = if camera == self.camera {
+            self.select(&self.own_input, &self.pipeline)?;
+            None
+        } else {
+            let source = debug::find_pipeline(camera)
+                .ok_or_else(|| anyhow!("unknown camera '{}'", camera))?;
+            let new = Feed::attach(camera, &source, self.tee, &self.pipeline, &self.selector)?;
+            switch_on_keyframe(&new.input, &self.pending);
+            if let Err(e) = self.select(&new.input, &source) {
+                new.remove(&self.pipeline, &self.selector);
+                return Err(e);
+            }
+            Some(new)
+        };
+        *self.shown.lock().unwrap() = camera.to_string();
+        if let Some(old) = std::mem::replace(&mut *feed, new) {
+            old.remove(&self.pipeline, &self.selector);
+        }
+        Ok(())
+    }
+
+    /// Makes `input` active at its next keyframe, asking `source` for one
+    fn select(&self, input: &gst::Pad, source: &gst::Pipeline) -> Result<()> {
+        let (done, switched) = mpsc::channel();
+        *self.pending.lock().unwrap() = Some(Pending {
+            input: input.clone(),
+            done,
+        });
+        request_keyframe(source);
+        if switched.recv_timeout(SWITCH_TIMEOUT).is_ok() {
+            return Ok(());
+        }
+        // It may have switched while we gave up
+        if self.pending.lock().unwrap().take().is_none() {
+            return Ok(());
+        }
+        bail!("no keyframe within {:?}", SWITCH_TIMEOUT)
+    }
+
+    /// Stops feeding from another camera, for a closing session
+    fn close(&self) {
+        if let Some(feed) = self.feed.lock().unwrap().take() {
+            feed.remove(&self.pipeline, &self.selector);
+        }
+        *self.shown.lock().unwrap() = self.camera.clone();
+    }
+}
+
+/// Makes `session` switchable
+pub fn register(session: u64, switch: SourceSwitch) {
+    SWITCHES.lock().unwrap().insert(session, Arc::new(switch));
+}
+
+/// Forgets a closing session, dropping any other camera it was fed from.
+/// Blocks while a switch of it is under way.
+pub fn unregister(session: u64) {
+    let switch = SWITCHES.lock().unwrap().remove(&session);
+    if let Some(switch) = switch {
+        switch.close();
+    }
+}
+
+/// Camera `session` is showing
+pub fn source(session: u64) -> Option<String> {
+    let switch = SWITCHES.lock().unwrap().get(&session).cloned();
+    switch.map(|switch| switch.source())
+}
+
+/// Shows `camera` in `session` from its next keyframe on, or the session's
+/// own camera again. Blocks until it does, for up to SWITCH_TIMEOUT.
+pub fn set_source(session: u64, camera: &str) -> Result<()> {
+    let switch = SWITCHES
+        .lock()
+        .unwrap()
+        .get(&session)
+        .cloned()
+        .ok_or_else(|| anyhow!("unknown session {}", session))?;
+    switch.switch_to(camera)?;
+    log::info!(
+        "Session {} ({}) now shows {}",
+        session,
+        switch.camera,
+        camera
+    );
+    Ok(())
+}
+
+/// Whether a session on another camera is showing `camera`, so its pipeline
+/// must keep running without viewers of its own
+pub fn is_feeding(camera: &str) -> bool {
+    let switches: Vec<_> = SWITCHES.lock().unwrap().values().cloned().collect();
+    switches
+        .iter()
+        .any(|switch| switch.source() == camera && switch.camera != camera)
+}
+
+/// Has the selector switch to `input` on its first keyframe once it is
+/// pending
+fn switch_on_keyframe(input: &gst::Pad, pending: &Arc<Mutex<Option<Pending>>>) {
+    let pending = pending.clone();
+    input.add_probe(gst::PadProbeType::BUFFER, move |pad, info| {
+        let keyframe = info
+            .buffer()
+            .is_some_and(|buffer| !buffer.flags().contains(gst::BufferFlags::DELTA_UNIT));
+        if !keyframe {
+            return gst::PadProbeReturn::Ok;
+        }
+        let mut pending = pending.lock().unwrap();
+        if pending.as_ref().is_some_and(|p| &p.input == pad) {
+            if let Some(selector) = pad.parent_element() {
+                selector.set_property("active-pad", pad);
+            }
+            if let Some(p) = pending.take() {
+                let _ = p.done.send(());
+            }
+        }
+        gst::PadProbeReturn::Ok
+    });
+}
+
+/// Another camera's video entering the session's pipeline
+struct Feed {
+    branch: Branch,
+    appsrc: gst::Element,
+    input: gst::Pad,
+}
+
+impl Feed {
+    fn attach(
+        camera: &str,
+        source: &gst::Pipeline,
+        tee: Tee,
+        pipeline: &gst::Pipeline,
+        selector: &gst::Element,
+    ) -> Result<Self> {
+        let appsrc = gst_app::AppSrc::builder()
+            .is_live(true)
+            .format(gst::Format::Time)
+            .build();
+        tee_output::limit_appsrc(&appsrc, tee);
+        pipeline.add(&appsrc)?;
+        let input = selector
+            .request_pad_simple("sink_%u")
+            .ok_or_else(|| anyhow!("Failed to request sink pad from selector"))?;
+        let appsrc_pad = appsrc
+            .static_pad("src")
+            .ok_or_else(|| anyhow!("appsrc has no src pad"))?;
+        appsrc_pad.link(&input)?;
+        appsrc.sync_state_with_parent()?;
+
+        let branch = match Branch::attach(
+            camera,
+            tee,
+            tee_output::feed_retimed(source, pipeline, &appsrc),
+        ) {
+            Ok(branch) => branch,
+            Err(e) => {
+                let _ = appsrc.set_state(gst::State::Null);
+                selector.release_request_pad(&input);
+                let _ = pipeline.remove(&appsrc);
+                return Err(e);
+            }
+        };
+        Ok(Self {
+            branch,
+            appsrc: appsrc.upcast(),
+            input,
+        })
+    }
+
+    /// Detaches from the other camera and takes the appsrc out. The input
+    /// must not be active any more.
+    fn remove(self, pipeline: &gst::Pipeline, selector: &gst::Element) {
+        self.branch.detach();
+        let _ = self.appsrc.set_state(gst::State::Null);
+        selector.release_request_pad(&self.input);
+        let _ = pipeline.remove(&self.appsrc);
+    }
+}
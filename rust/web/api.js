@@ -0,0 +1,127 @@
+// @ts-check
+// Tiny client for the REST API and the /ws/control socket, used by the grid
+// UI and usable on its own. The types are generated from the server:
+//
+//   rpi_sensor_streamer --api-schema typescript > web/api-types.d.ts
+//   tsc --noEmit --allowJs --checkJs --target es2020 web/api.js
+//
+// A running server also serves them at /api/schema.d.ts, and the OpenAPI
+// document at /api/schema.
+
+/** @typedef {import('./api-types').StreamInfo} StreamInfo */
+/** @typedef {import('./api-types').CamerasResponse} CamerasResponse */
+/** @typedef {import('./api-types').PauseMode} PauseMode */
+/** @typedef {import('./api-types').ScheduleOverride} ScheduleOverride */
+/** @typedef {import('./api-types').OkResponse} OkResponse */
+/** @typedef {import('./api-types').ServerMessage} ServerMessage */
+/** @typedef {import('./api-types').SystemStats} SystemStats */
+
+/**
+ * @param {string} path
+ * @param {RequestInit} [init]
+ */
+async function requestJson(path, init) {
+    const response = await fetch(path, init);
+    if (!response.ok && response.status !== 400) {
+        throw new Error(`${path}: HTTP ${response.status}`);
+    }
+    return response.json();
+}
+
+/** @param {OkResponse} result */
+function checkOk(result) {
+    if (!result.ok) {
+        throw new Error(result.error || 'request failed');
+    }
+}
+
+const api = {
+    /** @returns {Promise<CamerasResponse>} */
+    cameras: () => requestJson('/api/cameras'),
+
+    /** @returns {Promise<StreamInfo[]>} */
+    streams: async () => (await requestJson('/api/streams')).streams,
+
+    /**
+     * @param {string} name
+     * @returns {Promise<StreamInfo>}
+     */
+    stream: (name) => requestJson(`/api/streams/${encodeURIComponent(name)}`),
+
+    /**
+     * @param {string} name
+     * @param {PauseMode} [mode]
+     */
+    pause: async (name, mode = 'black') =>
+        checkOk(await requestJson(`/api/streams/${encodeURIComponent(name)}/pause?mode=${mode}`, { method: 'POST' })),
+
+    /** @param {string} name */
+    resume: async (name) =>
+        checkOk(await requestJson(`/api/streams/${encodeURIComponent(name)}/resume`, { method: 'POST' })),
+
+    /**
+     * @param {string} name
+     * @param {ScheduleOverride} mode
+     */
+    setSchedule: async (name, mode) =>
+        checkOk(await requestJson(`/api/streams/${encodeURIComponent(name)}/schedule?mode=${mode}`, { method: 'POST' })),
+};
+
+// Typed commands to the server over /ws/control. The server answers each
+// command and pushes camera/system state when it changes, so nothing here
+// polls. Pass ?token=... in the page URL when an admin token is configured.
+class ControlSocket {
+    /**
+     * @param {{ onState?: (cameras: any[]) => void, onSystem?: (system: SystemStats) => void }} handlers
+     * @param {number} [reconnectMs]
+     */
+    constructor(handlers, reconnectMs = 3000) {
+        this.handlers = handlers;
+        this.reconnectMs = reconnectMs;
+        this.nextId = 1;
+        /** @type {Map<number, { resolve: () => void, reject: (e: Error) => void }>} */
+        this.pending = new Map();
+        this.connect();
+    }
+
+    connect() {
+        const token = new URLSearchParams(location.search).get('token');
+        const query = token ? `?token=${encodeURIComponent(token)}` : '';
+        const scheme = location.protocol === 'https:' ? 'wss' : 'ws';
+        this.ws = new WebSocket(`${scheme}://${location.host}/ws/control${query}`);
+        this.ws.onmessage = (event) => {
+            /** @type {ServerMessage} */
+            const msg = JSON.parse(event.data);
+            if (msg.type === 'response') {
+                const done = this.pending.get(msg.id);
+                this.pending.delete(msg.id);
+                if (done) {
+                    msg.ok ? done.resolve() : done.reject(new Error(msg.error));
+                }
+            } else if (msg.type === 'state') {
+                this.handlers.onState?.(msg.cameras);
+            } else if (msg.type === 'system') {
+                this.handlers.onSystem?.(msg.system);
+            }
+        };
+        this.ws.onclose = () => {
+            this.pending.forEach((p) => p.reject(new Error('control socket closed')));
+            this.pending.clear();
+            setTimeout(() => this.connect(), this.reconnectMs);
+        };
+    }
+
+    /**
+     * e.g. send('set-flip', { camera: 'camera1', method: 'rotate-180' })
+     * @param {string} cmd
+     * @param {object} [args]
+     * @returns {Promise<void>}
+     */
+    send(cmd, args = {}) {
+        const id = this.nextId++;
+        return new Promise((resolve, reject) => {
+            this.pending.set(id, { resolve, reject });
+            this.ws.send(JSON.stringify({ id, cmd, ...args }));
+        });
+    }
+}
@@ -0,0 +1,362 @@
+// Multi-camera grid: one tile per entry of /api/cameras, each with its own
+// WebRTC session against the camera's signaling server.
+
+const ORDER_KEY = 'camera-order';
+const RECONNECT_MS = 3000;
+const STATS_MS = 1000;
+const INPUT_MS = 50;
+
+const grid = document.getElementById('grid');
+const template = document.getElementById('tile-template');
+
+function savedOrder() {
+    try {
+        return JSON.parse(localStorage.getItem(ORDER_KEY)) || [];
+    } catch (e) {
+        return [];
+    }
+}
+
+function saveOrder() {
+    const names = [...grid.querySelectorAll('.tile')].map((t) => t.dataset.camera);
+    localStorage.setItem(ORDER_KEY, JSON.stringify(names));
+}
+
+function sortCameras(cameras) {
+    const order = savedOrder();
+    const rank = (name) => {
+        const i = order.indexOf(name);
+        return i === -1 ? order.length : i;
+    };
+    return [...cameras].sort((a, b) => rank(a.name) - rank(b.name));
+}
+
+class CameraTile {
+    constructor(camera, iceServers, controlLabel) {
+        this.camera = camera;
+        this.iceServers = iceServers;
+        this.controlLabel = controlLabel;
+        this.controlChannel = null;
+        this.el = template.content.firstElementChild.cloneNode(true);
+        this.el.dataset.camera = camera.name;
+        this.video = this.el.querySelector('video');
+        this.state = this.el.querySelector('.tile-state');
+        this.el.querySelector('.tile-name').textContent = camera.name;
+        this.el.querySelector('[data-action="snapshot"]').onclick = () => this.snapshot();
+        this.el.querySelector('[data-action="fullscreen"]').onclick = () => this.fullscreen();
+        this.lastStats = null;
+    }
+
+    setState(text, cls) {
+        this.state.textContent = text;
+        this.state.className = 'tile-state' + (cls ? ' ' + cls : '');
+    }
+
+    setStat(name, value) {
+        this.el.querySelector(`[data-stat="${name}"]`).textContent = value;
+    }
+
+    connect() {
+        this.setState('connecting');
+        const ws = new WebSocket(this.camera.ws_url);
+        const pc = new RTCPeerConnection({ iceServers: this.iceServers });
+        this.ws = ws;
+        this.pc = pc;
+
+        pc.addTransceiver('video', { direction: 'recvonly' });
+        if (this.controlLabel) {
+            // Input is sent as full state snapshots, so losing one is harmless
+            // and retransmitting a stale one would only add latency
+            this.controlChannel = pc.createDataChannel(this.controlLabel, {
+                ordered: false,
+                maxRetransmits: 0,
+            });
+        }
+        pc.ontrack = (event) => {
+            this.video.srcObject = event.streams[0] || new MediaStream([event.track]);
+        };
+        pc.onicecandidate = (event) => {
+            if (event.candidate && ws.readyState === WebSocket.OPEN) {
+                ws.send(JSON.stringify({
+                    iceCandidate: {
+                        candidate: event.candidate.candidate,
+                        sdpMLineIndex: event.candidate.sdpMLineIndex,
+                    },
+                }));
+            }
+        };
+        pc.onconnectionstatechange = () => {
+            if (pc.connectionState === 'connected') {
+                this.setState('connected', 'connected');
+            } else if (pc.connectionState === 'failed') {
+                ws.close();
+            }
+        };
+
+        ws.onopen = async () => {
+            const offer = await pc.createOffer();
+            await pc.setLocalDescription(offer);
+            ws.send(JSON.stringify({ offer: { type: offer.type, sdp: offer.sdp } }));
+        };
+        ws.onmessage = async (event) => {
+            const msg = JSON.parse(event.data);
+            if (msg.answer) {
+                await pc.setRemoteDescription(msg.answer);
+            } else if (msg.iceCandidate) {
+                await pc.addIceCandidate(msg.iceCandidate);
+            } else if (msg.error) {
+                // The server refused our offer; retrying would only repeat it
+                this.ws = null;
+                ws.close();
+                this.setState(msg.error, 'failed');
+            }
+        };
+        ws.onclose = () => {
+            pc.close();
+            if (this.ws === ws) {
+                this.setState('disconnected', 'failed');
+                setTimeout(() => this.connect(), RECONNECT_MS);
+            }
+        };
+    }
+
+    async updateStats() {
+        if (!this.pc || this.pc.connectionState !== 'connected') {
+            return;
+        }
+        const report = await this.pc.getStats();
+        report.forEach((s) => {
+            if (s.type !== 'inbound-rtp' || s.kind !== 'video') {
+                return;
+            }
+            if (s.frameWidth) {
+                this.setStat('resolution', `${s.frameWidth}×${s.frameHeight}`);
+            }
+            if (s.framesPerSecond !== undefined) {
+                this.setStat('fps', s.framesPerSecond.toFixed(0));
+            }
+            this.setStat('lost', s.packetsLost ?? 0);
+            if (this.lastStats) {
+                const secs = (s.timestamp - this.lastStats.timestamp) / 1000;
+                const bits = (s.bytesReceived - this.lastStats.bytesReceived) * 8;
+                if (secs > 0) {
+                    this.setStat('bitrate', `${(bits / secs / 1e6).toFixed(2)} Mb/s`);
+                }
+            }
+            this.lastStats = s;
+        });
+    }
+
+    snapshot() {
+        if (!this.video.videoWidth) {
+            return;
+        }
+        const canvas = document.createElement('canvas');
+        canvas.width = this.video.videoWidth;
+        canvas.height = this.video.videoHeight;
+        canvas.getContext('2d').drawImage(this.video, 0, 0);
+        canvas.toBlob((blob) => {
+            const link = document.createElement('a');
+            const stamp = new Date().toISOString().replace(/[:.]/g, '-');
+            link.href = URL.createObjectURL(blob);
+            link.download = `${this.camera.name}-${stamp}.jpg`;
+            link.click();
+            URL.revokeObjectURL(link.href);
+        }, 'image/jpeg', 0.95);
+    }
+
+    fullscreen() {
+        if (document.fullscreenElement) {
+            document.exitFullscreen();
+        } else {
+            this.el.requestFullscreen();
+        }
+    }
+}
+
+// Keyboard and gamepad state, sent over the active tile's control data
+// channel as snapshots while anything is pressed (and once on release).
+class InputCapture {
+    constructor(tiles) {
+        this.tiles = tiles;
+        this.active = null;
+        this.keys = new Set();
+        this.seq = 0;
+        this.lastSent = '';
+
+        window.addEventListener('keydown', (event) => {
+            if (!event.target.closest('input, textarea, select')) {
+                this.keys.add(event.code);
+            }
+        });
+        window.addEventListener('keyup', (event) => this.keys.delete(event.code));
+        window.addEventListener('blur', () => this.keys.clear());
+        for (const tile of tiles) {
+            tile.el.addEventListener('pointerdown', () => {
+                this.active = tile;
+            });
+        }
+
+        setInterval(() => this.tick(), INPUT_MS);
+    }
+
+    channel() {
+        const open = (t) => t && t.controlChannel && t.controlChannel.readyState === 'open';
+        if (open(this.active)) {
+            return this.active.controlChannel;
+        }
+        const tile = this.tiles.find(open);
+        return tile ? tile.controlChannel : null;
+    }
+
+    gamepad() {
+        const pad = [...(navigator.getGamepads ? navigator.getGamepads() : [])].find((p) => p);
+        if (!pad) {
+            return null;
+        }
+        return {
+            axes: pad.axes.map((a) => (Math.abs(a) < 0.05 ? 0 : Number(a.toFixed(3)))),
+            buttons: pad.buttons.map((b) => Number(b.value.toFixed(3))),
+        };
+    }
+
+    tick() {
+        const channel = this.channel();
+        if (!channel) {
+            return;
+        }
+        const gamepad = this.gamepad();
+        const state = { keys: [...this.keys].sort(), gamepad };
+        const key = JSON.stringify(state);
+        const idle = !state.keys.length &&
+            (!gamepad || (gamepad.axes.every((a) => a === 0) && gamepad.buttons.every((b) => b === 0)));
+        if (idle && key === this.lastSent) {
+            return;
+        }
+        this.lastSent = key;
+        channel.send(JSON.stringify({ type: 'input', seq: this.seq++, ts: Date.now(), ...state }));
+    }
+}
+
+function enableReordering() {
+    let dragged = null;
+
+    grid.addEventListener('dragstart', (event) => {
+        dragged = event.target.closest('.tile');
+        if (dragged) {
+            dragged.classList.add('dragging');
+            event.dataTransfer.effectAllowed = 'move';
+        }
+    });
+    grid.addEventListener('dragend', () => {
+        if (dragged) {
+            dragged.classList.remove('dragging');
+        }
+        grid.querySelectorAll('.drop-target').forEach((t) => t.classList.remove('drop-target'));
+        dragged = null;
+    });
+    grid.addEventListener('dragover', (event) => {
+        const target = event.target.closest('.tile');
+        if (!dragged || !target || target === dragged) {
+            return;
+        }
+        event.preventDefault();
+        grid.querySelectorAll('.drop-target').forEach((t) => t.classList.remove('drop-target'));
+        target.classList.add('drop-target');
+    });
+    grid.addEventListener('drop', (event) => {
+        const target = event.target.closest('.tile');
+        if (!dragged || !target || target === dragged) {
+            return;
+        }
+        event.preventDefault();
+        const tiles = [...grid.querySelectorAll('.tile')];
+        if (tiles.indexOf(dragged) < tiles.indexOf(target)) {
+            target.after(dragged);
+        } else {
+            target.before(dragged);
+        }
+        saveOrder();
+    });
+}
+
+function renderSystemSummary(sys) {
+    const summary = document.getElementById('system-summary');
+    const temps = Object.values(sys.thermal_zones_c || {});
+    const parts = [];
+    if (temps.length) {
+        parts.push(`${Math.max(...temps).toFixed(1)} °C`);
+    }
+    if (sys.cpu_usage_percent != null) {
+        parts.push(`CPU ${sys.cpu_usage_percent.toFixed(0)}%`);
+    }
+    if (sys.mem_total_kb) {
+        const used = 100 - (sys.mem_available_kb * 100) / sys.mem_total_kb;
+        parts.push(`RAM ${used.toFixed(0)}%`);
+    }
+    summary.textContent = parts.join(' · ');
+    if (sys.throttling && (sys.throttling.raw & 0xf) !== 0) {
+        const warn = document.createElement('span');
+        warn.className = 'warn';
+        warn.textContent = ' · throttled';
+        summary.appendChild(warn);
+    }
+}
+
+async function main() {
+    const { cameras, ice_servers: iceServers, control_channel: controlLabel } = await api.cameras();
+
+    const empty = document.getElementById('grid-empty');
+    if (!cameras.length) {
+        empty.textContent = 'No cameras configured.';
+        return;
+    }
+    empty.remove();
+
+    const tiles = sortCameras(cameras).map((camera) => new CameraTile(camera, iceServers, controlLabel));
+    for (const tile of tiles) {
+        grid.appendChild(tile.el);
+        tile.connect();
+    }
+    enableReordering();
+    if (controlLabel) {
+        new InputCapture(tiles);
+    }
+
+    setInterval(() => tiles.forEach((t) => t.updateStats()), STATS_MS);
+
+    const onState = (states) => {
+        for (const state of states) {
+            const tile = tiles.find((t) => t.camera.name === state.name);
+            if (tile) {
+                tile.el.dataset.pipeline = state.pipeline_state;
+                tile.el.dataset.recording = state.recording;
+                tile.el.dataset.paused = Boolean(state.paused);
+                tile.el.querySelector('[data-action="pause"]').textContent =
+                    state.paused ? 'Resume' : 'Pause';
+                tile.el.querySelector('[data-action="record"]').textContent =
+                    state.recording ? 'Stop' : 'Record';
+                tile.el.dataset.recovery = state.recovery ? state.recovery.state : '';
+                tile.el.title = `pipeline ${state.pipeline_state}` +
+                    (state.bitrate ? `, ${(state.bitrate / 1e6).toFixed(1)} Mb/s target` : '') +
+                    (state.recovery && state.recovery.reason ?
+                        `, ${state.recovery.state}: ${state.recovery.reason}` : '');
+            }
+        }
+    };
+    window.control = new ControlSocket({ onState, onSystem: renderSystemSummary }, RECONNECT_MS);
+    for (const tile of tiles) {
+        tile.el.querySelector('[data-action="record"]').onclick = () => {
+            const cmd = tile.el.dataset.recording === 'true' ? 'stop-recording' : 'start-recording';
+            window.control.send(cmd, { camera: tile.camera.name }).catch((e) => alert(e.message));
+        };
+        tile.el.querySelector('[data-action="pause"]').onclick = () => {
+            const cmd = tile.el.dataset.paused === 'true' ? 'resume' : 'pause';
+            window.control.send(cmd, { camera: tile.camera.name }).catch((e) => alert(e.message));
+        };
+    }
+}
+
+main().catch((e) => {
+    document.getElementById('grid-empty').textContent = `Failed to load cameras: ${e}`;
+});